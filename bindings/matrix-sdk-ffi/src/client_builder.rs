@@ -166,6 +166,7 @@ impl ClientBuilder {
                 backup_download_strategy:
                     matrix_sdk::encryption::BackupDownloadStrategy::AfterDecryptionFailure,
                 auto_enable_backups: false,
+                auto_manage_dehydrated_device: false,
             },
             room_key_recipient_strategy: Default::default(),
             decryption_settings: DecryptionSettings {
@@ -349,6 +350,17 @@ impl ClientBuilder {
         Arc::new(builder)
     }
 
+    /// Automatically rehydrate the dehydrated device once its pickle key is
+    /// imported from secret storage, and rotate it periodically.
+    pub fn auto_manage_dehydrated_device(
+        self: Arc<Self>,
+        auto_manage_dehydrated_device: bool,
+    ) -> Arc<Self> {
+        let mut builder = unwrap_or_clone_arc(self);
+        builder.encryption_settings.auto_manage_dehydrated_device = auto_manage_dehydrated_device;
+        Arc::new(builder)
+    }
+
     /// Set the strategy to be used for picking recipient devices when sending
     /// an encrypted message.
     pub fn room_key_recipient_strategy(self: Arc<Self>, strategy: CollectStrategy) -> Arc<Self> {
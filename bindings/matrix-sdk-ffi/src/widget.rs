@@ -245,6 +245,10 @@ pub fn get_element_call_required_permissions(
         requires_client: true,
         update_delayed_event: true,
         send_delayed_event: true,
+        upload_file: false,
+        download_file: false,
+        user_directory_search: false,
+        turn_servers: false,
     }
 }
 
@@ -310,6 +314,16 @@ pub struct WidgetCapabilities {
     pub update_delayed_event: bool,
     /// This allows the widget to send events with a delay.
     pub send_delayed_event: bool,
+    /// This allows the widget to upload files to the media repository, and to
+    /// get its configuration.
+    pub upload_file: bool,
+    /// This allows the widget to download files from the media repository.
+    pub download_file: bool,
+    /// This allows the widget to search the user directory of the homeserver.
+    pub user_directory_search: bool,
+    /// This allows the widget to get the TURN servers of the homeserver, and
+    /// to be notified when their credentials change.
+    pub turn_servers: bool,
 }
 
 impl From<WidgetCapabilities> for matrix_sdk::widget::Capabilities {
@@ -320,6 +334,10 @@ impl From<WidgetCapabilities> for matrix_sdk::widget::Capabilities {
             requires_client: value.requires_client,
             update_delayed_event: value.update_delayed_event,
             send_delayed_event: value.send_delayed_event,
+            upload_file: value.upload_file,
+            download_file: value.download_file,
+            user_directory_search: value.user_directory_search,
+            turn_servers: value.turn_servers,
         }
     }
 }
@@ -332,6 +350,10 @@ impl From<matrix_sdk::widget::Capabilities> for WidgetCapabilities {
             requires_client: value.requires_client,
             update_delayed_event: value.update_delayed_event,
             send_delayed_event: value.send_delayed_event,
+            upload_file: value.upload_file,
+            download_file: value.download_file,
+            user_directory_search: value.user_directory_search,
+            turn_servers: value.turn_servers,
         }
     }
 }
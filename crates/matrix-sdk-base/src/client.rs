@@ -27,7 +27,8 @@ use futures_util::Stream;
 #[cfg(feature = "e2e-encryption")]
 use matrix_sdk_crypto::{
     CollectStrategy, DecryptionSettings, EncryptionSettings, OlmError, OlmMachine,
-    TrustRequirement, store::DynCryptoStore, types::requests::ToDeviceRequest,
+    RoomKeyRecipientFilter, TrustRequirement, store::DynCryptoStore,
+    types::requests::ToDeviceRequest,
 };
 #[cfg(doc)]
 use ruma::DeviceId;
@@ -116,6 +117,13 @@ pub struct BaseClient {
     #[cfg(feature = "e2e-encryption")]
     pub room_key_recipient_strategy: CollectStrategy,
 
+    /// The policy restricting which devices receive room keys, on top of the
+    /// [`BaseClient::room_key_recipient_strategy`], if any.
+    ///
+    /// It is applied to the `OlmMachine` when it is (re)created.
+    #[cfg(feature = "e2e-encryption")]
+    pub room_key_recipient_filter: Option<Arc<dyn RoomKeyRecipientFilter>>,
+
     /// The settings to use for decrypting events.
     #[cfg(feature = "e2e-encryption")]
     pub decryption_settings: DecryptionSettings,
@@ -198,6 +206,8 @@ impl BaseClient {
             #[cfg(feature = "e2e-encryption")]
             room_key_recipient_strategy: Default::default(),
             #[cfg(feature = "e2e-encryption")]
+            room_key_recipient_filter: None,
+            #[cfg(feature = "e2e-encryption")]
             decryption_settings: DecryptionSettings {
                 sender_device_trust_requirement: TrustRequirement::Untrusted,
             },
@@ -233,6 +243,7 @@ impl BaseClient {
             ignore_user_list_changes: Default::default(),
             room_info_notable_update_sender: self.room_info_notable_update_sender.clone(),
             room_key_recipient_strategy: self.room_key_recipient_strategy.clone(),
+            room_key_recipient_filter: self.room_key_recipient_filter.clone(),
             decryption_settings: self.decryption_settings.clone(),
             handle_verification_events,
             threading_support: self.threading_support,
@@ -392,6 +403,8 @@ impl BaseClient {
         .await
         .map_err(OlmError::from)?;
 
+        olm_machine.set_room_key_recipient_filter(self.room_key_recipient_filter.clone());
+
         *self.olm_machine.write().await = Some(olm_machine);
         Ok(())
     }
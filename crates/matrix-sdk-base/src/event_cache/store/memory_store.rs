@@ -25,10 +25,11 @@ use matrix_sdk_common::{
         RawChunk, Update, relational::RelationalLinkedChunk,
     },
     ring_buffer::RingBuffer,
+    storage_usage::StorageUsage,
     store_locks::memory_store_helper::try_take_leased_lock,
 };
 use ruma::{
-    EventId, MxcUri, OwnedEventId, OwnedMxcUri, RoomId,
+    EventId, MxcUri, OwnedEventId, OwnedMxcUri, OwnedRoomId, RoomId,
     events::relation::RelationType,
     time::{Instant, SystemTime},
 };
@@ -368,6 +369,15 @@ impl EventCacheStore for MemoryStore {
     async fn clean_up_media_cache(&self) -> Result<(), Self::Error> {
         self.media_service.clean_up_media_cache(self).await
     }
+
+    async fn storage_usage(&self, _room_ids: &[OwnedRoomId]) -> Result<StorageUsage, Self::Error> {
+        // The data is kept in memory, there's nothing to measure.
+        Ok(StorageUsage::default())
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 #[cfg_attr(target_family = "wasm", async_trait(?Send))]
@@ -162,6 +162,13 @@ pub enum EventCacheStoreError {
         /// Details why the data contained in the store was invalid.
         details: String,
     },
+
+    /// The database of the store is busy, e.g. because another process is
+    /// using it.
+    ///
+    /// The operation can be retried later.
+    #[error("The event cache store is busy, the operation can be retried later")]
+    Busy,
 }
 
 impl EventCacheStoreError {
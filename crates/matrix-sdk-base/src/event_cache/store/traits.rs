@@ -21,8 +21,9 @@ use matrix_sdk_common::{
         ChunkIdentifier, ChunkIdentifierGenerator, ChunkMetadata, LinkedChunkId, Position,
         RawChunk, Update,
     },
+    storage_usage::StorageUsage,
 };
-use ruma::{EventId, MxcUri, OwnedEventId, RoomId, events::relation::RelationType};
+use ruma::{EventId, MxcUri, OwnedEventId, OwnedRoomId, RoomId, events::relation::RelationType};
 
 use super::{
     EventCacheStoreError,
@@ -292,6 +293,18 @@ pub trait EventCacheStore: AsyncTraitDeps {
     ///
     /// If there is already an ongoing cleanup, this is a noop.
     async fn clean_up_media_cache(&self) -> Result<(), Self::Error>;
+
+    /// Get the storage used by the store.
+    ///
+    /// The usage is only broken down for the given rooms, since the room IDs
+    /// may be hashed in the store, in which case the store can't list the
+    /// rooms it contains data for.
+    async fn storage_usage(&self, room_ids: &[OwnedRoomId]) -> Result<StorageUsage, Self::Error>;
+
+    /// Compact the store, to reclaim unused space and optimize it.
+    ///
+    /// This is a no-op for stores that don't need it.
+    async fn compact(&self) -> Result<(), Self::Error>;
 }
 
 #[repr(transparent)]
@@ -455,6 +468,14 @@ impl<T: EventCacheStore> EventCacheStore for EraseEventCacheStoreError<T> {
     async fn clean_up_media_cache(&self) -> Result<(), Self::Error> {
         self.0.clean_up_media_cache().await.map_err(Into::into)
     }
+
+    async fn storage_usage(&self, room_ids: &[OwnedRoomId]) -> Result<StorageUsage, Self::Error> {
+        self.0.storage_usage(room_ids).await.map_err(Into::into)
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        self.0.compact().await.map_err(Into::into)
+    }
 }
 
 /// A type-erased [`EventCacheStore`].
@@ -81,7 +81,7 @@ pub async fn for_room(
                             &mut context.state_changes,
                             state_store,
                             |room_info| {
-                                room_info.base_info.handle_notable_tags(&event.content.tags);
+                                room_info.base_info.handle_tags(&event.content.tags);
                             },
                         );
                     }
@@ -168,6 +168,9 @@ pub struct BaseRoomInfo {
     /// others, and this field collects them.
     #[serde(skip_serializing_if = "RoomNotableTags::is_empty", default)]
     pub(crate) notable_tags: RoomNotableTags,
+    /// All the tags of this room, with their ordering information.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub(crate) tags: Tags,
     /// The `m.room.pinned_events` of this room.
     pub(crate) pinned_events: Option<RoomPinnedEventsEventContent>,
 }
@@ -388,6 +391,13 @@ impl BaseRoomInfo {
 
         self.notable_tags = notable_tags;
     }
+
+    /// Handle the `m.tag` room account data event for this room, i.e. update
+    /// the cached tags and the notable tags.
+    pub fn handle_tags(&mut self, tags: &Tags) {
+        self.handle_notable_tags(tags);
+        self.tags = tags.clone();
+    }
 }
 
 impl Default for BaseRoomInfo {
@@ -410,6 +420,7 @@ impl Default for BaseRoomInfo {
             is_marked_unread: false,
             is_marked_unread_source: AccountDataSource::Unstable,
             notable_tags: RoomNotableTags::empty(),
+            tags: Tags::new(),
             pinned_events: None,
         }
     }
@@ -502,7 +513,7 @@ impl RoomInfo {
     #[doc(hidden)] // used by store tests, otherwise it would be pub(crate)
     pub fn new(room_id: &RoomId, room_state: RoomState) -> Self {
         Self {
-            data_format_version: 1,
+            data_format_version: 2,
             room_id: room_id.into(),
             room_state,
             notification_counts: Default::default(),
@@ -1118,6 +1129,31 @@ impl RoomInfo {
             migrated = true;
         }
 
+        if self.data_format_version < 2 {
+            info!("Migrating room info to version 2");
+
+            // tags
+            match store.get_room_account_data_event_static::<TagEventContent>(&self.room_id).await {
+                Ok(Some(raw_event)) => match raw_event.deserialize() {
+                    Ok(event) => {
+                        self.base_info.handle_tags(&event.content.tags);
+                    }
+                    Err(error) => {
+                        warn!("Failed to deserialize room tags: {error}");
+                    }
+                },
+                Ok(_) => {
+                    // Nothing to do.
+                }
+                Err(error) => {
+                    warn!("Failed to load room tags: {error}");
+                }
+            }
+
+            self.data_format_version = 2;
+            migrated = true;
+        }
+
         migrated
     }
 }
@@ -1245,8 +1281,10 @@ mod tests {
         test_json::{TAG, sync_events::PINNED_EVENTS},
     };
     use ruma::{
-        assign, events::room::pinned_events::RoomPinnedEventsEventContent, owned_event_id,
-        owned_mxc_uri, owned_user_id, room_id, serde::Raw,
+        assign,
+        events::{room::pinned_events::RoomPinnedEventsEventContent, tag::TagName},
+        owned_event_id, owned_mxc_uri, owned_user_id, room_id,
+        serde::Raw,
     };
     use serde_json::json;
     use similar_asserts::assert_eq;
@@ -1424,15 +1462,17 @@ mod tests {
         // Apply migrations with an empty store.
         assert!(room_info.apply_migrations(store.clone()).await);
 
-        assert_eq!(room_info.data_format_version, 1);
+        assert_eq!(room_info.data_format_version, 2);
         assert!(room_info.base_info.notable_tags.is_empty());
+        assert!(room_info.base_info.tags.is_empty());
         assert!(room_info.base_info.pinned_events.is_none());
 
         // Applying migrations again has no effect.
         assert!(!room_info.apply_migrations(store.clone()).await);
 
-        assert_eq!(room_info.data_format_version, 1);
+        assert_eq!(room_info.data_format_version, 2);
         assert!(room_info.base_info.notable_tags.is_empty());
+        assert!(room_info.base_info.tags.is_empty());
         assert!(room_info.base_info.pinned_events.is_none());
 
         // Add events to the store.
@@ -1452,13 +1492,22 @@ mod tests {
         room_info.data_format_version = 0;
         assert!(room_info.apply_migrations(store.clone()).await);
 
-        assert_eq!(room_info.data_format_version, 1);
+        assert_eq!(room_info.data_format_version, 2);
         assert!(room_info.base_info.notable_tags.contains(RoomNotableTags::FAVOURITE));
+        assert!(room_info.base_info.tags.contains_key(&TagName::Favorite));
         assert!(room_info.base_info.pinned_events.is_some());
 
-        // Creating a new room info initializes it to version 1.
+        // Reset to version 1 and reapply migrations: only the tags are migrated.
+        room_info.base_info.tags.clear();
+        room_info.data_format_version = 1;
+        assert!(room_info.apply_migrations(store.clone()).await);
+
+        assert_eq!(room_info.data_format_version, 2);
+        assert!(room_info.base_info.tags.contains_key(&TagName::Favorite));
+
+        // Creating a new room info initializes it to version 2.
         let new_room_info = RoomInfo::new(room_id!("!new_room:localhost"), RoomState::Joined);
-        assert_eq!(new_room_info.data_format_version, 1);
+        assert_eq!(new_room_info.data_format_version, 2);
     }
 
     #[test]
@@ -13,7 +13,10 @@
 // limitations under the License.
 
 use bitflags::bitflags;
-use ruma::events::{AnyRoomAccountDataEvent, RoomAccountDataEventType, tag::Tags};
+use ruma::events::{
+    AnyRoomAccountDataEvent, RoomAccountDataEventType,
+    tag::{TagName, Tags, UserTagName},
+};
 use serde::{Deserialize, Serialize};
 
 use super::Room;
@@ -48,6 +51,43 @@ impl Room {
     pub fn is_low_priority(&self) -> bool {
         self.inner.read().base_info.notable_tags.contains(RoomNotableTags::LOW_PRIORITY)
     }
+
+    /// Get the tags of this room, as cached in the [`RoomInfo`].
+    ///
+    /// Contrary to [`Room::tags`], this doesn't hit the store, which makes it
+    /// usable in synchronous contexts, e.g. to filter or to sort rooms.
+    ///
+    /// [`RoomInfo`]: crate::RoomInfo
+    pub fn cached_tags(&self) -> Tags {
+        self.inner.read().base_info.tags.clone()
+    }
+
+    /// Call `f` with the tags of this room, as cached in the [`RoomInfo`].
+    ///
+    /// Contrary to [`Room::cached_tags`], the tags aren't cloned, which makes
+    /// it cheap enough to be called for every room when filtering or sorting
+    /// rooms. The [`RoomInfo`] is locked while `f` runs, so `f` must not
+    /// access it.
+    ///
+    /// [`RoomInfo`]: crate::RoomInfo
+    pub fn with_cached_tags<T>(&self, f: impl FnOnce(&Tags) -> T) -> T {
+        f(&self.inner.read().base_info.tags)
+    }
+
+    /// Get the user-defined tags of this room, i.e. the tags in the `u.*`
+    /// namespace.
+    pub fn user_tags(&self) -> Vec<UserTagName> {
+        self.inner
+            .read()
+            .base_info
+            .tags
+            .keys()
+            .filter_map(|tag_name| match tag_name {
+                TagName::User(user_tag_name) => Some(user_tag_name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 bitflags! {
@@ -155,6 +195,8 @@ mod tests {
 
         // The room is now marked as favourite.
         assert!(room.is_favourite());
+        assert!(room.cached_tags().contains_key(&TagName::Favorite));
+        assert!(room.user_tags().is_empty());
 
         // Now, let's remove the tag.
         let tag_raw = Raw::new(&json!({
@@ -313,4 +355,23 @@ mod tests {
         base_room_info.handle_notable_tags(&tags);
         assert!(base_room_info.notable_tags.contains(RoomNotableTags::LOW_PRIORITY).not());
     }
+
+    #[test]
+    fn test_handle_tags() {
+        let mut base_room_info = BaseRoomInfo::default();
+
+        let mut tags = Tags::new();
+        tags.insert(TagName::Favorite, TagInfo::default());
+        tags.insert(TagName::User("u.work".parse().unwrap()), TagInfo::default());
+
+        base_room_info.handle_tags(&tags);
+        assert!(base_room_info.notable_tags.contains(RoomNotableTags::FAVOURITE));
+        assert!(base_room_info.tags.contains_key(&TagName::Favorite));
+        assert!(base_room_info.tags.contains_key(&TagName::User("u.work".parse().unwrap())));
+
+        tags.clear();
+        base_room_info.handle_tags(&tags);
+        assert!(base_room_info.notable_tags.is_empty());
+        assert!(base_room_info.tags.is_empty());
+    }
 }
@@ -0,0 +1,1433 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Copy the content of the stores of a client into other stores, e.g. to move
+//! a user to another storage backend without logging them out.
+//!
+//! The [`StateStore`], the [`EventCacheStore`] and the [`CryptoStore`] are
+//! copied through their trait API, so any implementation of the traits can be
+//! the source or the destination. Once copied, the content of the destination
+//! stores is counted again, and compared to the content of the source stores.
+//!
+//! Since the traits can't list all the data they contain, the data which is
+//! only reachable by a key is looked up from what can be listed, or from the
+//! [`StoreMigrationSettings`]. Notably:
+//!
+//! - the state events and the account data events are looked up for the event
+//!   types of the settings,
+//! - the custom values are looked up for the keys of the settings, since the
+//!   stores can't list them. By default, these are the keys of the custom
+//!   values used by the SDK crates. The items of the notification inbox are
+//!   looked up from its index,
+//! - the media are looked up for the attachments of the events of the event
+//!   cache,
+//! - the thread subscriptions and the composer drafts of threads are looked up
+//!   for the threads of the events of the event cache, and for the threads of
+//!   the settings.
+//!
+//! The counts of the destination stores can only be compared to what was
+//! looked up in the source stores, so they can't tell whether some data which
+//! wasn't looked up is missing. That's why the sync token isn't copied: the
+//! first sync after the migration is an initial sync, which fetches the state
+//! events, the account data, the receipts, the presence and the profiles from
+//! the server again. The copied server data is then only a head start, e.g. to
+//! display the room list before that sync is done. The data which only exists
+//! locally, like the send queue, the composer drafts, the recently visited
+//! rooms or the crypto store, must be looked up entirely. The linked chunks of
+//! threads are not copied, they are loaded again from the server when needed.
+//!
+//! This can also be used to rotate the passphrase of a store, by copying it
+//! into a new store opened with the new passphrase:
+//!
+//! ```no_run
+//! # use matrix_sdk_base::store::{
+//! #     StoreConfig,
+//! #     backend_migration::{StoreMigrationSettings, migrate_stores},
+//! # };
+//! # async {
+//! # let (old_stores, new_stores): (StoreConfig, StoreConfig) = unimplemented!();
+//! # let user_id: ruma::OwnedUserId = unimplemented!();
+//! let settings = StoreMigrationSettings::new(user_id);
+//! let report = migrate_stores(&old_stores, &new_stores, &settings).await?;
+//!
+//! println!("Copied {} rooms", report.state_store.rooms);
+//! # anyhow::Ok(()) };
+//! ```
+//!
+//! [`StateStore`]: super::StateStore
+//! [`EventCacheStore`]: crate::event_cache::store::EventCacheStore
+//! [`CryptoStore`]: matrix_sdk_crypto::store::CryptoStore
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use matrix_sdk_common::{
+    linked_chunk::{ChunkContent, LinkedChunkId, Position, RawChunk, Update},
+    serde_helpers::extract_thread_root,
+    store_locks::LockStoreError,
+};
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_crypto::store::{
+    CryptoStoreError, DynCryptoStore,
+    backend_migration::{CryptoStoreCounts, copy_crypto_store},
+};
+use ruma::{
+    OwnedEventId, OwnedRoomId, OwnedUserId,
+    events::{
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent, GlobalAccountDataEventType,
+        RoomAccountDataEventType, StateEventType, SyncMessageLikeEvent,
+        receipt::{ReceiptEventContent, ReceiptThread, ReceiptType},
+        room::{MediaSource, message::MessageType},
+    },
+    serde::Raw,
+};
+use thiserror::Error;
+use tracing::{info, instrument, warn};
+
+use super::{
+    DependentQueuedRequest, DynStateStore, QueuedRequest, RoomLoadSettings, StateChanges,
+    StateStoreDataKey, StateStoreDataValue, StoreConfig, StoreError, ThreadSubscription,
+};
+use crate::{
+    RoomMemberships,
+    deserialized_responses::{DisplayName, RawAnySyncOrStrippedState},
+    event_cache::{
+        Event, Gap,
+        store::{DynEventCacheStore, EventCacheStoreError, media::IgnoreMediaRetentionPolicy},
+    },
+    media::{MediaFormat, MediaRequestParameters},
+};
+
+/// The keys of the custom values that the SDK crates store in the state store.
+const SDK_STATE_STORE_CUSTOM_VALUE_KEYS: &[&[u8]] = &[
+    // The pending jobs of `matrix_sdk::encryption::history_sharing`.
+    b"history_sharing_jobs",
+    // The remembered decisions of `matrix_sdk::widget::CapabilityApprovals`.
+    b"widget_capability_approvals",
+    // The index of `matrix_sdk_ui::notification_inbox::NotificationInbox`.
+    b"notification_inbox.index",
+];
+
+/// The custom values of the state store which list the keys of other custom
+/// values, with the prefix of these keys.
+///
+/// The value of such an index is a JSON array of strings, each string being
+/// the suffix of the key of another custom value.
+const STATE_STORE_CUSTOM_VALUE_INDEXES: &[(&[u8], &str)] =
+    &[(b"notification_inbox.index", "notification_inbox.item.")];
+
+/// The keys of the custom values that the SDK crates store in the crypto
+/// store, in addition to the ones of the `OlmMachine` which are always copied.
+#[cfg(feature = "e2e-encryption")]
+const SDK_CRYPTO_STORE_CUSTOM_VALUE_KEYS: &[&str] = &[
+    // The hash of the OAuth 2.0 session of `matrix_sdk::authentication::oauth`.
+    "oidc_session_hash",
+];
+
+/// An error which occurred while migrating stores.
+#[derive(Debug, Error)]
+pub enum StoreMigrationError {
+    /// An error occurred in a state store.
+    #[error(transparent)]
+    StateStore(#[from] StoreError),
+
+    /// An error occurred in an event cache store.
+    #[error(transparent)]
+    EventCacheStore(#[from] EventCacheStoreError),
+
+    /// An event cache store couldn't be locked.
+    #[error(transparent)]
+    EventCacheStoreLock(#[from] LockStoreError),
+
+    /// An error occurred in a crypto store.
+    #[cfg(feature = "e2e-encryption")]
+    #[error(transparent)]
+    CryptoStore(#[from] CryptoStoreError),
+
+    /// The content of a copied store doesn't match the content of the
+    /// original store.
+    #[error("the copy of the {store} doesn't match the original: {original} != {copy}")]
+    CountMismatch {
+        /// The kind of store.
+        store: &'static str,
+
+        /// The counts of the original store.
+        original: String,
+
+        /// The counts of the copied store.
+        copy: String,
+    },
+}
+
+/// The settings of a store migration.
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Clone, Debug)]
+pub struct StoreMigrationSettings {
+    /// The user of the stores, to copy the data stored for this user, e.g.
+    /// their recently visited rooms.
+    pub user_id: OwnedUserId,
+
+    /// The names of the sync filters to copy.
+    pub filter_names: Vec<String>,
+
+    /// The types of the state events to copy.
+    pub state_event_types: Vec<StateEventType>,
+
+    /// The types of the global account data events to copy.
+    pub global_account_data_types: Vec<GlobalAccountDataEventType>,
+
+    /// The types of the room account data events to copy.
+    pub room_account_data_types: Vec<RoomAccountDataEventType>,
+
+    /// The threads to copy the subscriptions and the composer drafts of, in
+    /// addition to the threads of the events of the event cache, by room.
+    pub thread_roots: BTreeMap<OwnedRoomId, BTreeSet<OwnedEventId>>,
+
+    /// The keys of the custom values of the state store to copy.
+    ///
+    /// Defaults to the keys of the custom values used by the SDK crates. If
+    /// one of them is the index of the notification inbox, the items of the
+    /// inbox are copied too.
+    pub state_store_custom_value_keys: Vec<Vec<u8>>,
+
+    /// The keys of the custom values of the crypto store to copy.
+    ///
+    /// Defaults to the keys of the custom values used by the SDK crates. The
+    /// custom values of the `OlmMachine`, like the room key recipient
+    /// strategies of the rooms, are always copied.
+    #[cfg(feature = "e2e-encryption")]
+    pub crypto_store_custom_value_keys: Vec<String>,
+}
+
+impl StoreMigrationSettings {
+    /// Create the settings to migrate the stores of the given user.
+    ///
+    /// The state events and the account data events which are used to display
+    /// the rooms are copied, the other ones are fetched again by the first sync
+    /// after the migration.
+    pub fn new(user_id: OwnedUserId) -> Self {
+        Self {
+            user_id,
+            filter_names: Vec::new(),
+            state_event_types: vec![
+                StateEventType::RoomCreate,
+                StateEventType::RoomMember,
+                StateEventType::RoomName,
+                StateEventType::RoomTopic,
+                StateEventType::RoomAvatar,
+                StateEventType::RoomCanonicalAlias,
+                StateEventType::RoomJoinRules,
+                StateEventType::RoomGuestAccess,
+                StateEventType::RoomHistoryVisibility,
+                StateEventType::RoomPowerLevels,
+                StateEventType::RoomEncryption,
+                StateEventType::RoomTombstone,
+                StateEventType::RoomPinnedEvents,
+                StateEventType::RoomServerAcl,
+                StateEventType::RoomThirdPartyInvite,
+                StateEventType::SpaceChild,
+                StateEventType::SpaceParent,
+                StateEventType::MemberHints,
+                StateEventType::CallMember,
+            ],
+            global_account_data_types: vec![
+                GlobalAccountDataEventType::Direct,
+                GlobalAccountDataEventType::IgnoredUserList,
+                GlobalAccountDataEventType::PushRules,
+                GlobalAccountDataEventType::SecretStorageDefaultKey,
+                GlobalAccountDataEventType::MediaPreviewConfig,
+            ],
+            room_account_data_types: vec![
+                RoomAccountDataEventType::Tag,
+                RoomAccountDataEventType::FullyRead,
+                RoomAccountDataEventType::MarkedUnread,
+            ],
+            thread_roots: BTreeMap::new(),
+            state_store_custom_value_keys: SDK_STATE_STORE_CUSTOM_VALUE_KEYS
+                .iter()
+                .map(|key| key.to_vec())
+                .collect(),
+            #[cfg(feature = "e2e-encryption")]
+            crypto_store_custom_value_keys: SDK_CRYPTO_STORE_CUSTOM_VALUE_KEYS
+                .iter()
+                .map(|key| key.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// The number of items of each kind in a [`StateStore`], as far as they can be
+/// listed through the trait API.
+///
+/// [`StateStore`]: super::StateStore
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateStoreCounts {
+    /// The number of key-value entries, e.g. the composer drafts.
+    pub kv_data: usize,
+
+    /// The number of rooms.
+    pub rooms: usize,
+
+    /// The number of state events.
+    pub state_events: usize,
+
+    /// The number of stripped state events, for invited rooms.
+    pub stripped_state_events: usize,
+
+    /// The number of room member profiles.
+    pub profiles: usize,
+
+    /// The number of room member display names.
+    pub display_names: usize,
+
+    /// The number of global account data events.
+    pub global_account_data: usize,
+
+    /// The number of room account data events.
+    pub room_account_data: usize,
+
+    /// The number of presence events.
+    pub presence: usize,
+
+    /// The number of read receipts.
+    pub receipts: usize,
+
+    /// The number of requests in the send queues.
+    pub send_queue_requests: usize,
+
+    /// The number of dependent requests in the send queues.
+    pub dependent_send_queue_requests: usize,
+
+    /// The number of thread subscriptions.
+    pub thread_subscriptions: usize,
+
+    /// The number of custom values.
+    pub custom_values: usize,
+}
+
+/// The number of items of each kind in an [`EventCacheStore`], as far as they
+/// can be listed through the trait API.
+///
+/// [`EventCacheStore`]: crate::event_cache::store::EventCacheStore
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventCacheStoreCounts {
+    /// The number of rooms with a linked chunk.
+    pub rooms: usize,
+
+    /// The number of chunks.
+    pub chunks: usize,
+
+    /// The number of gaps.
+    pub gaps: usize,
+
+    /// The number of events.
+    pub events: usize,
+
+    /// The number of cached media.
+    pub media: usize,
+}
+
+/// The result of [`migrate_stores()`].
+#[derive(Clone, Debug)]
+pub struct StoreMigrationReport {
+    /// The number of copied items of the state store.
+    pub state_store: StateStoreCounts,
+
+    /// The number of copied items of the event cache store.
+    pub event_cache_store: EventCacheStoreCounts,
+
+    /// The number of copied items of the crypto store.
+    #[cfg(feature = "e2e-encryption")]
+    pub crypto_store: CryptoStoreCounts,
+}
+
+/// Copy the content of all the `source` stores into the `destination` stores,
+/// and verify the copy.
+///
+/// The destination stores should be empty, otherwise their content is merged
+/// with the copied one. No client should use any of the stores during the
+/// migration.
+///
+/// The sync token is not copied, so the next sync of the client is an initial
+/// sync.
+#[instrument(skip_all)]
+pub async fn migrate_stores(
+    source: &StoreConfig,
+    destination: &StoreConfig,
+    settings: &StoreMigrationSettings,
+) -> Result<StoreMigrationReport, StoreMigrationError> {
+    let room_ids: Vec<_> = source
+        .state_store
+        .get_room_infos(&RoomLoadSettings::All)
+        .await?
+        .into_iter()
+        .map(|room_info| room_info.room_id().to_owned())
+        .collect();
+
+    // The event cache store is copied first, to find the threads to copy the
+    // subscriptions and the drafts of.
+    let (event_cache_store, thread_roots) = {
+        let source = source.event_cache_store.lock().await?;
+        let destination = destination.event_cache_store.lock().await?;
+        copy_event_cache_store(&source, &destination, &room_ids).await?
+    };
+
+    let mut settings = settings.clone();
+    for (room_id, roots) in thread_roots {
+        settings.thread_roots.entry(room_id).or_default().extend(roots);
+    }
+
+    let state_store =
+        migrate_state_store(&*source.state_store, &*destination.state_store, &settings).await?;
+
+    #[cfg(feature = "e2e-encryption")]
+    let crypto_store = migrate_crypto_store(
+        &*source.crypto_store,
+        &*destination.crypto_store,
+        &room_ids,
+        &settings.crypto_store_custom_value_keys,
+    )
+    .await?;
+
+    Ok(StoreMigrationReport {
+        state_store,
+        event_cache_store,
+        #[cfg(feature = "e2e-encryption")]
+        crypto_store,
+    })
+}
+
+/// Copy the content of the `source` state store into the `destination` state
+/// store, and verify the copy.
+///
+/// The sync token is not copied, so the next sync of the client is an initial
+/// sync.
+pub async fn migrate_state_store(
+    source: &DynStateStore,
+    destination: &DynStateStore,
+    settings: &StoreMigrationSettings,
+) -> Result<StateStoreCounts, StoreMigrationError> {
+    let snapshot = StateStoreSnapshot::load(source, settings).await?;
+    let counts = snapshot.counts();
+
+    snapshot.save(destination).await?;
+
+    let copied = StateStoreSnapshot::load(destination, settings).await?.counts();
+    check_counts("state store", &counts, &copied)?;
+
+    info!(?counts, "Copied the state store");
+
+    Ok(counts)
+}
+
+/// Copy the linked chunks of the given rooms, and the media of their events,
+/// from the `source` event cache store into the `destination` event cache
+/// store, and verify the copy.
+pub async fn migrate_event_cache_store(
+    source: &DynEventCacheStore,
+    destination: &DynEventCacheStore,
+    room_ids: &[OwnedRoomId],
+) -> Result<EventCacheStoreCounts, StoreMigrationError> {
+    Ok(copy_event_cache_store(source, destination, room_ids).await?.0)
+}
+
+/// Same as [`migrate_event_cache_store()`], but also returns the roots of the
+/// threads of the copied events, by room.
+async fn copy_event_cache_store(
+    source: &DynEventCacheStore,
+    destination: &DynEventCacheStore,
+    room_ids: &[OwnedRoomId],
+) -> Result<
+    (EventCacheStoreCounts, BTreeMap<OwnedRoomId, BTreeSet<OwnedEventId>>),
+    StoreMigrationError,
+> {
+    let snapshot = EventCacheStoreSnapshot::load(source, room_ids).await?;
+    let counts = snapshot.counts();
+    let thread_roots = snapshot.thread_roots();
+
+    snapshot.save(destination).await?;
+
+    let copied = EventCacheStoreSnapshot::load(destination, room_ids).await?.counts();
+    check_counts("event cache store", &counts, &copied)?;
+
+    info!(?counts, "Copied the event cache store");
+
+    Ok((counts, thread_roots))
+}
+
+/// Copy the content of the `source` crypto store into the `destination`
+/// crypto store, and verify the copy.
+///
+/// See [`copy_crypto_store()`] for the meaning of the arguments.
+#[cfg(feature = "e2e-encryption")]
+pub async fn migrate_crypto_store(
+    source: &DynCryptoStore,
+    destination: &DynCryptoStore,
+    room_ids: &[OwnedRoomId],
+    custom_value_keys: &[String],
+) -> Result<CryptoStoreCounts, StoreMigrationError> {
+    let counts = copy_crypto_store(source, destination, room_ids, custom_value_keys).await?;
+
+    let copied = CryptoStoreCounts::collect(destination, room_ids, custom_value_keys).await?;
+    check_counts("crypto store", &counts, &copied)?;
+
+    Ok(counts)
+}
+
+fn check_counts<T: std::fmt::Debug + PartialEq>(
+    store: &'static str,
+    original: &T,
+    copy: &T,
+) -> Result<(), StoreMigrationError> {
+    if original == copy {
+        Ok(())
+    } else {
+        Err(StoreMigrationError::CountMismatch {
+            store,
+            original: format!("{original:?}"),
+            copy: format!("{copy:?}"),
+        })
+    }
+}
+
+/// An owned [`StateStoreDataKey`].
+///
+/// The sync token is deliberately missing, see the [module-level
+/// documentation](self).
+#[derive(Clone, Debug)]
+enum KvDataKey {
+    ServerInfo,
+    Filter(String),
+    UserAvatarUrl(OwnedUserId),
+    RecentlyVisitedRooms(OwnedUserId),
+    UtdHookManagerData,
+    ComposerDraft(OwnedRoomId, Option<OwnedEventId>),
+    SeenKnockRequests(OwnedRoomId),
+}
+
+impl KvDataKey {
+    fn as_key(&self) -> StateStoreDataKey<'_> {
+        match self {
+            Self::ServerInfo => StateStoreDataKey::ServerInfo,
+            Self::Filter(name) => StateStoreDataKey::Filter(name),
+            Self::UserAvatarUrl(user_id) => StateStoreDataKey::UserAvatarUrl(user_id),
+            Self::RecentlyVisitedRooms(user_id) => StateStoreDataKey::RecentlyVisitedRooms(user_id),
+            Self::UtdHookManagerData => StateStoreDataKey::UtdHookManagerData,
+            Self::ComposerDraft(room_id, thread_root) => {
+                StateStoreDataKey::ComposerDraft(room_id, thread_root.as_deref())
+            }
+            Self::SeenKnockRequests(room_id) => StateStoreDataKey::SeenKnockRequests(room_id),
+        }
+    }
+}
+
+/// All the data of a [`StateStore`] which can be listed through the trait
+/// API, or looked up from the [`StoreMigrationSettings`].
+///
+/// [`StateStore`]: super::StateStore
+#[derive(Default)]
+struct StateStoreSnapshot {
+    kv_data: Vec<(KvDataKey, StateStoreDataValue)>,
+    changes: StateChanges,
+    send_queue_requests: BTreeMap<OwnedRoomId, Vec<QueuedRequest>>,
+    dependent_send_queue_requests: BTreeMap<OwnedRoomId, Vec<DependentQueuedRequest>>,
+    thread_subscriptions: Vec<(OwnedRoomId, OwnedEventId, ThreadSubscription)>,
+    custom_values: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl StateStoreSnapshot {
+    async fn load(
+        store: &DynStateStore,
+        settings: &StoreMigrationSettings,
+    ) -> Result<Self, StoreError> {
+        let mut snapshot = Self::default();
+        let changes = &mut snapshot.changes;
+
+        let room_infos = store.get_room_infos(&RoomLoadSettings::All).await?;
+        let room_ids: Vec<_> =
+            room_infos.iter().map(|room_info| room_info.room_id().to_owned()).collect();
+
+        let mut kv_keys = vec![
+            KvDataKey::ServerInfo,
+            KvDataKey::UtdHookManagerData,
+            KvDataKey::UserAvatarUrl(settings.user_id.clone()),
+            KvDataKey::RecentlyVisitedRooms(settings.user_id.clone()),
+        ];
+        kv_keys.extend(settings.filter_names.iter().cloned().map(KvDataKey::Filter));
+        for room_id in &room_ids {
+            kv_keys.push(KvDataKey::ComposerDraft(room_id.clone(), None));
+            kv_keys.push(KvDataKey::SeenKnockRequests(room_id.clone()));
+        }
+        for (room_id, thread_roots) in &settings.thread_roots {
+            for thread_root in thread_roots {
+                kv_keys.push(KvDataKey::ComposerDraft(room_id.clone(), Some(thread_root.clone())));
+
+                if let Some(subscription) =
+                    store.load_thread_subscription(room_id, thread_root).await?
+                {
+                    snapshot.thread_subscriptions.push((
+                        room_id.clone(),
+                        thread_root.clone(),
+                        subscription,
+                    ));
+                }
+            }
+        }
+
+        for key in kv_keys {
+            if let Some(value) = store.get_kv_data(key.as_key()).await? {
+                snapshot.kv_data.push((key, value));
+            }
+        }
+
+        for event_type in &settings.global_account_data_types {
+            if let Some(event) = store.get_account_data_event(event_type.clone()).await? {
+                changes.account_data.insert(event_type.clone(), event);
+            }
+        }
+
+        let mut user_ids = BTreeSet::from([settings.user_id.clone()]);
+
+        for room_id in &room_ids {
+            for event_type in &settings.state_event_types {
+                for event in store.get_state_events(room_id, event_type.clone()).await? {
+                    match event {
+                        RawAnySyncOrStrippedState::Sync(event) => {
+                            let state_key = state_key(&event);
+                            changes
+                                .state
+                                .entry(room_id.clone())
+                                .or_default()
+                                .entry(event_type.clone())
+                                .or_default()
+                                .insert(state_key, event);
+                        }
+                        RawAnySyncOrStrippedState::Stripped(event) => {
+                            let state_key = state_key(&event);
+                            changes
+                                .stripped_state
+                                .entry(room_id.clone())
+                                .or_default()
+                                .entry(event_type.clone())
+                                .or_default()
+                                .insert(state_key, event);
+                        }
+                    }
+                }
+            }
+
+            for event_type in &settings.room_account_data_types {
+                if let Some(event) =
+                    store.get_room_account_data_event(room_id, event_type.clone()).await?
+                {
+                    changes
+                        .room_account_data
+                        .entry(room_id.clone())
+                        .or_default()
+                        .insert(event_type.clone(), event);
+                }
+            }
+
+            let members = store.get_user_ids(room_id, RoomMemberships::empty()).await?;
+
+            let mut display_names = Vec::new();
+            for (user_id, profile) in store.get_profiles(room_id, &members).await? {
+                if let Some(display_name) =
+                    profile.as_original().and_then(|event| event.content.displayname.as_deref())
+                {
+                    display_names.push(DisplayName::new(display_name));
+                }
+
+                changes
+                    .profiles
+                    .entry(room_id.clone())
+                    .or_default()
+                    .insert(user_id.to_owned(), profile);
+            }
+
+            let ambiguity_map = store.get_users_with_display_names(room_id, &display_names).await?;
+            if !ambiguity_map.is_empty() {
+                changes.ambiguity_maps.insert(
+                    room_id.clone(),
+                    ambiguity_map
+                        .into_iter()
+                        .map(|(display_name, user_ids)| (display_name.clone(), user_ids))
+                        .collect(),
+                );
+            }
+
+            let mut receipts = ReceiptEventContent(BTreeMap::new());
+            for user_id in &members {
+                for receipt_type in [ReceiptType::Read, ReceiptType::ReadPrivate] {
+                    for thread in [ReceiptThread::Unthreaded, ReceiptThread::Main] {
+                        if let Some((event_id, receipt)) = store
+                            .get_user_room_receipt_event(
+                                room_id,
+                                receipt_type.clone(),
+                                thread,
+                                user_id,
+                            )
+                            .await?
+                        {
+                            receipts
+                                .0
+                                .entry(event_id)
+                                .or_default()
+                                .entry(receipt_type.clone())
+                                .or_default()
+                                .insert(user_id.clone(), receipt);
+                        }
+                    }
+                }
+            }
+            if !receipts.0.is_empty() {
+                changes.receipts.insert(room_id.clone(), receipts);
+            }
+
+            user_ids.extend(members);
+        }
+
+        let user_ids: Vec<_> = user_ids.into_iter().collect();
+        for event in store.get_presence_events(&user_ids).await? {
+            if let Ok(Some(user_id)) = event.get_field::<OwnedUserId>("sender") {
+                changes.presence.insert(user_id, event);
+            }
+        }
+
+        changes.room_infos = room_infos
+            .into_iter()
+            .map(|room_info| (room_info.room_id().to_owned(), room_info))
+            .collect();
+
+        // The send queue may contain requests for rooms which aren't known yet.
+        let send_queue_room_ids: BTreeSet<_> =
+            store.load_rooms_with_unsent_requests().await?.into_iter().chain(room_ids).collect();
+
+        for room_id in send_queue_room_ids {
+            let requests = store.load_send_queue_requests(&room_id).await?;
+            if !requests.is_empty() {
+                snapshot.send_queue_requests.insert(room_id.clone(), requests);
+            }
+
+            let dependent_requests = store.load_dependent_queued_requests(&room_id).await?;
+            if !dependent_requests.is_empty() {
+                snapshot.dependent_send_queue_requests.insert(room_id, dependent_requests);
+            }
+        }
+
+        let mut custom_value_keys = settings.state_store_custom_value_keys.clone();
+        let mut index = 0;
+
+        // The keys listed by an index are appended while iterating.
+        while let Some(key) = custom_value_keys.get(index).cloned() {
+            index += 1;
+
+            let Some(value) = store.get_custom_value(&key).await? else {
+                continue;
+            };
+
+            if let Some((_, prefix)) = STATE_STORE_CUSTOM_VALUE_INDEXES
+                .iter()
+                .find(|(index_key, _)| *index_key == key.as_slice())
+            {
+                match serde_json::from_slice::<Vec<String>>(&value) {
+                    Ok(suffixes) => custom_value_keys.extend(
+                        suffixes.into_iter().map(|suffix| format!("{prefix}{suffix}").into_bytes()),
+                    ),
+                    Err(error) => warn!(
+                        key = %String::from_utf8_lossy(&key),
+                        "Failed to read the keys listed by a custom value: {error}"
+                    ),
+                }
+            }
+
+            snapshot.custom_values.insert(key, value);
+        }
+
+        Ok(snapshot)
+    }
+
+    fn counts(&self) -> StateStoreCounts {
+        fn count_events<K, V, W>(map: &BTreeMap<K, BTreeMap<V, BTreeMap<String, W>>>) -> usize {
+            map.values().flat_map(BTreeMap::values).map(BTreeMap::len).sum()
+        }
+
+        let changes = &self.changes;
+
+        StateStoreCounts {
+            kv_data: self.kv_data.len(),
+            rooms: changes.room_infos.len(),
+            state_events: count_events(&changes.state),
+            stripped_state_events: count_events(&changes.stripped_state),
+            profiles: changes.profiles.values().map(BTreeMap::len).sum(),
+            display_names: changes.ambiguity_maps.values().map(|map| map.len()).sum(),
+            global_account_data: changes.account_data.len(),
+            room_account_data: changes.room_account_data.values().map(BTreeMap::len).sum(),
+            presence: changes.presence.len(),
+            receipts: changes
+                .receipts
+                .values()
+                .flat_map(|receipts| receipts.0.values())
+                .flat_map(|receipts| receipts.values())
+                .map(BTreeMap::len)
+                .sum(),
+            send_queue_requests: self.send_queue_requests.values().map(Vec::len).sum(),
+            dependent_send_queue_requests: self
+                .dependent_send_queue_requests
+                .values()
+                .map(Vec::len)
+                .sum(),
+            thread_subscriptions: self.thread_subscriptions.len(),
+            custom_values: self.custom_values.len(),
+        }
+    }
+
+    async fn save(self, store: &DynStateStore) -> Result<(), StoreError> {
+        store.save_changes(&self.changes).await?;
+
+        for (key, value) in self.kv_data {
+            store.set_kv_data(key.as_key(), value).await?;
+        }
+
+        for (room_id, requests) in self.send_queue_requests {
+            for request in requests {
+                store
+                    .save_send_queue_request(
+                        &room_id,
+                        request.transaction_id.clone(),
+                        request.created_at,
+                        request.kind,
+                        request.priority,
+                    )
+                    .await?;
+
+                if let Some(error) = request.error {
+                    store
+                        .update_send_queue_request_status(
+                            &room_id,
+                            &request.transaction_id,
+                            Some(error),
+                        )
+                        .await?;
+                }
+            }
+        }
+
+        for (room_id, dependent_requests) in self.dependent_send_queue_requests {
+            let mut sent_parents = BTreeMap::new();
+
+            for request in dependent_requests {
+                store
+                    .save_dependent_queued_request(
+                        &room_id,
+                        &request.parent_transaction_id,
+                        request.own_transaction_id,
+                        request.created_at,
+                        request.kind,
+                    )
+                    .await?;
+
+                if let Some(parent_key) = request.parent_key {
+                    sent_parents.insert(request.parent_transaction_id, parent_key);
+                }
+            }
+
+            // All the dependent requests of a sent parent are marked as ready at once.
+            for (parent_transaction_id, parent_key) in sent_parents {
+                store
+                    .mark_dependent_queued_requests_as_ready(
+                        &room_id,
+                        &parent_transaction_id,
+                        parent_key,
+                    )
+                    .await?;
+            }
+        }
+
+        for (room_id, thread_root, subscription) in self.thread_subscriptions {
+            store.upsert_thread_subscription(&room_id, &thread_root, subscription).await?;
+        }
+
+        for (key, value) in self.custom_values {
+            store.set_custom_value_no_read(&key, value).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the state key of a raw state event.
+fn state_key<T>(event: &Raw<T>) -> String {
+    event.get_field::<String>("state_key").ok().flatten().unwrap_or_default()
+}
+
+/// All the data of an [`EventCacheStore`] which can be listed through the
+/// trait API.
+///
+/// [`EventCacheStore`]: crate::event_cache::store::EventCacheStore
+#[derive(Default)]
+struct EventCacheStoreSnapshot {
+    linked_chunks: Vec<(OwnedRoomId, Vec<RawChunk<Event, Gap>>)>,
+    media: Vec<(MediaRequestParameters, Vec<u8>)>,
+}
+
+impl EventCacheStoreSnapshot {
+    async fn load(
+        store: &DynEventCacheStore,
+        room_ids: &[OwnedRoomId],
+    ) -> Result<Self, EventCacheStoreError> {
+        let mut snapshot = Self::default();
+        let mut media_uris = BTreeSet::new();
+
+        for room_id in room_ids {
+            let linked_chunk_id = LinkedChunkId::Room(room_id);
+
+            // Walk the linked chunk backwards, from its last chunk.
+            let mut chunks = Vec::new();
+            let (mut chunk, _) = store.load_last_chunk(linked_chunk_id).await?;
+
+            while let Some(current) = chunk {
+                chunk = if current.previous.is_some() {
+                    store.load_previous_chunk(linked_chunk_id, current.identifier).await?
+                } else {
+                    None
+                };
+                chunks.push(current);
+            }
+
+            if chunks.is_empty() {
+                continue;
+            }
+
+            chunks.reverse();
+
+            for chunk in &chunks {
+                let ChunkContent::Items(events) = &chunk.content else {
+                    continue;
+                };
+
+                for source in events.iter().flat_map(media_sources) {
+                    let uri = match &source {
+                        MediaSource::Plain(uri) => uri.to_string(),
+                        MediaSource::Encrypted(file) => file.url.to_string(),
+                    };
+
+                    if !media_uris.insert(uri) {
+                        continue;
+                    }
+
+                    let request = MediaRequestParameters { source, format: MediaFormat::File };
+                    if let Some(content) = store.get_media_content(&request).await? {
+                        snapshot.media.push((request, content));
+                    }
+                }
+            }
+
+            snapshot.linked_chunks.push((room_id.clone(), chunks));
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Get the roots of the threads of the events of the linked chunks, by
+    /// room: the thread roots which have a thread summary, and the roots of the
+    /// threads the events belong to.
+    fn thread_roots(&self) -> BTreeMap<OwnedRoomId, BTreeSet<OwnedEventId>> {
+        let mut thread_roots = BTreeMap::new();
+
+        for (room_id, chunks) in &self.linked_chunks {
+            let roots: BTreeSet<_> = chunks
+                .iter()
+                .filter_map(|chunk| match &chunk.content {
+                    ChunkContent::Items(events) => Some(events),
+                    ChunkContent::Gap(_) => None,
+                })
+                .flatten()
+                .flat_map(|event| {
+                    let own_thread = event.thread_summary.summary().and_then(|_| event.event_id());
+                    own_thread.into_iter().chain(extract_thread_root(event.raw()))
+                })
+                .collect();
+
+            if !roots.is_empty() {
+                thread_roots.insert(room_id.clone(), roots);
+            }
+        }
+
+        thread_roots
+    }
+
+    fn counts(&self) -> EventCacheStoreCounts {
+        let chunks = self.linked_chunks.iter().flat_map(|(_, chunks)| chunks);
+
+        EventCacheStoreCounts {
+            rooms: self.linked_chunks.len(),
+            chunks: chunks.clone().count(),
+            gaps: chunks
+                .clone()
+                .filter(|chunk| matches!(chunk.content, ChunkContent::Gap(_)))
+                .count(),
+            events: chunks
+                .map(|chunk| match &chunk.content {
+                    ChunkContent::Items(events) => events.len(),
+                    ChunkContent::Gap(_) => 0,
+                })
+                .sum(),
+            media: self.media.len(),
+        }
+    }
+
+    async fn save(self, store: &DynEventCacheStore) -> Result<(), EventCacheStoreError> {
+        for (room_id, chunks) in self.linked_chunks {
+            let mut updates = Vec::new();
+
+            // The chunks are inserted in order, so each of them is linked to the previous
+            // one when it's inserted.
+            for chunk in chunks {
+                match chunk.content {
+                    ChunkContent::Gap(gap) => updates.push(Update::NewGapChunk {
+                        previous: chunk.previous,
+                        new: chunk.identifier,
+                        next: None,
+                        gap,
+                    }),
+                    ChunkContent::Items(events) => {
+                        updates.push(Update::NewItemsChunk {
+                            previous: chunk.previous,
+                            new: chunk.identifier,
+                            next: None,
+                        });
+
+                        if !events.is_empty() {
+                            updates.push(Update::PushItems {
+                                at: Position::new(chunk.identifier, 0),
+                                items: events,
+                            });
+                        }
+                    }
+                }
+            }
+
+            store.handle_linked_chunk_updates(LinkedChunkId::Room(&room_id), updates).await?;
+        }
+
+        for (request, content) in self.media {
+            store.add_media_content(&request, content, IgnoreMediaRetentionPolicy::No).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the sources of the media attached to an event, if any.
+fn media_sources(event: &Event) -> Vec<MediaSource> {
+    let Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+        SyncMessageLikeEvent::Original(event),
+    ))) = event.raw().deserialize()
+    else {
+        return Vec::new();
+    };
+
+    let (source, thumbnail_source) = match event.content.msgtype {
+        MessageType::Image(content) => {
+            (content.source, content.info.and_then(|info| info.thumbnail_source))
+        }
+        MessageType::Video(content) => {
+            (content.source, content.info.and_then(|info| info.thumbnail_source))
+        }
+        MessageType::File(content) => {
+            (content.source, content.info.and_then(|info| info.thumbnail_source))
+        }
+        MessageType::Audio(content) => (content.source, None),
+        _ => return Vec::new(),
+    };
+
+    std::iter::once(source).chain(thumbnail_source).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_let;
+    use matrix_sdk_common::linked_chunk::{ChunkIdentifier, LinkedChunkId, Position, Update};
+    use matrix_sdk_test::{async_test, event_factory::EventFactory};
+    use ruma::{
+        MilliSecondsSinceUnixEpoch, OwnedRoomId, RoomId, TransactionId, event_id,
+        events::{AnyMessageLikeEventContent, room::message::RoomMessageEventContent},
+        mxc_uri, owned_room_id, room_id, user_id,
+    };
+
+    use super::{
+        StateStoreCounts, StoreMigrationError, StoreMigrationSettings, check_counts, migrate_stores,
+    };
+    use crate::{
+        RoomInfo, RoomState, StateChanges, StateStoreDataKey, StateStoreDataValue,
+        event_cache::{Event, Gap},
+        store::{
+            ComposerDraft, ComposerDraftType, QueuedRequestKind, RoomLoadSettings,
+            SerializableEventContent, StoreConfig, ThreadSubscription,
+        },
+    };
+
+    /// Save a linked chunk with a gap and the given events in the event cache
+    /// store.
+    async fn save_linked_chunk(store: &StoreConfig, room_id: &RoomId, events: Vec<Event>) {
+        store
+            .event_cache_store
+            .lock()
+            .await
+            .unwrap()
+            .handle_linked_chunk_updates(
+                LinkedChunkId::Room(room_id),
+                vec![
+                    Update::NewGapChunk {
+                        previous: None,
+                        new: ChunkIdentifier::new(0),
+                        next: None,
+                        gap: Gap { prev_token: "prev".to_owned() },
+                    },
+                    Update::NewItemsChunk {
+                        previous: Some(ChunkIdentifier::new(0)),
+                        new: ChunkIdentifier::new(1),
+                        next: None,
+                    },
+                    Update::PushItems {
+                        at: Position::new(ChunkIdentifier::new(1), 0),
+                        items: events,
+                    },
+                ],
+            )
+            .await
+            .unwrap();
+    }
+
+    fn draft(text: &str) -> StateStoreDataValue {
+        StateStoreDataValue::ComposerDraft(ComposerDraft {
+            plain_text: text.to_owned(),
+            html_text: None,
+            draft_type: ComposerDraftType::NewMessage,
+        })
+    }
+
+    #[async_test]
+    async fn test_migrate_stores() {
+        let room_id = room_id!("!room:localhost");
+        let user_id = user_id!("@alice:localhost");
+
+        let source = StoreConfig::new("source".to_owned());
+        let destination = StoreConfig::new("destination".to_owned());
+
+        // Fill the source stores.
+        let mut changes = StateChanges::new("sync_token".to_owned());
+        changes.add_room(RoomInfo::new(room_id, RoomState::Joined));
+        source.state_store.save_changes(&changes).await.unwrap();
+        source.state_store.set_custom_value_no_read(b"custom", b"value".to_vec()).await.unwrap();
+
+        let content = SerializableEventContent::new(&AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("Hello"),
+        ))
+        .unwrap();
+        source
+            .state_store
+            .save_send_queue_request(
+                room_id,
+                TransactionId::new(),
+                MilliSecondsSinceUnixEpoch::now(),
+                QueuedRequestKind::Event { content },
+                0,
+            )
+            .await
+            .unwrap();
+
+        let event = EventFactory::new()
+            .text_msg("Hi")
+            .sender(user_id)
+            .room(room_id)
+            .event_id(event_id!("$ev"))
+            .into_event();
+        save_linked_chunk(&source, room_id, vec![event]).await;
+
+        let settings = StoreMigrationSettings {
+            state_store_custom_value_keys: vec![b"custom".to_vec()],
+            ..StoreMigrationSettings::new(user_id.to_owned())
+        };
+
+        let report = migrate_stores(&source, &destination, &settings).await.unwrap();
+
+        assert_eq!(report.state_store.rooms, 1);
+        assert_eq!(report.state_store.send_queue_requests, 1);
+        assert_eq!(report.state_store.custom_values, 1);
+        assert_eq!(report.event_cache_store.rooms, 1);
+        assert_eq!(report.event_cache_store.chunks, 2);
+        assert_eq!(report.event_cache_store.gaps, 1);
+        assert_eq!(report.event_cache_store.events, 1);
+
+        // The destination stores contain the copied data.
+        let requests = destination.state_store.load_send_queue_requests(room_id).await.unwrap();
+        assert_eq!(requests.len(), 1);
+
+        let custom_value = destination.state_store.get_custom_value(b"custom").await.unwrap();
+        assert_eq!(custom_value.as_deref(), Some(&b"value"[..]));
+
+        let event_cache_store = destination.event_cache_store.lock().await.unwrap();
+        let event = event_cache_store.find_event(room_id, event_id!("$ev")).await.unwrap();
+        assert!(event.is_some());
+    }
+
+    #[async_test]
+    async fn test_migrate_stores_copies_the_custom_values_of_the_sdk() {
+        let room_id = room_id!("!room:localhost");
+        let user_id = user_id!("@alice:localhost");
+
+        let source = StoreConfig::new("source".to_owned());
+        let destination = StoreConfig::new("destination".to_owned());
+
+        let mut changes = StateChanges::new("sync_token".to_owned());
+        changes.add_room(RoomInfo::new(room_id, RoomState::Joined));
+        source.state_store.save_changes(&changes).await.unwrap();
+
+        let state_store_values: [(&[u8], &[u8]); 4] = [
+            (b"history_sharing_jobs", b"[]"),
+            (b"widget_capability_approvals", b"[]"),
+            (b"notification_inbox.index", br#"["$a"]"#),
+            // Only listed by the index of the notification inbox.
+            (b"notification_inbox.item.$a", b"{}"),
+        ];
+        for (key, value) in state_store_values {
+            source.state_store.set_custom_value_no_read(key, value.to_vec()).await.unwrap();
+        }
+
+        #[cfg(feature = "e2e-encryption")]
+        let crypto_store_values = [
+            ("generation-counter".to_owned(), b"1".to_vec()),
+            ("only_allow_trusted_devices".to_owned(), b"true".to_vec()),
+            (format!("room_key_recipient_strategy.{room_id}"), b"{}".to_vec()),
+            ("oidc_session_hash".to_owned(), b"hash".to_vec()),
+        ];
+        #[cfg(feature = "e2e-encryption")]
+        for (key, value) in &crypto_store_values {
+            source.crypto_store.set_custom_value(key, value.clone()).await.unwrap();
+        }
+
+        // The default settings copy all of them.
+        let settings = StoreMigrationSettings::new(user_id.to_owned());
+        let report = migrate_stores(&source, &destination, &settings).await.unwrap();
+
+        assert_eq!(report.state_store.custom_values, state_store_values.len());
+        for (key, value) in state_store_values {
+            let copied = destination.state_store.get_custom_value(key).await.unwrap();
+            assert_eq!(copied.as_deref(), Some(value));
+        }
+
+        #[cfg(feature = "e2e-encryption")]
+        {
+            assert_eq!(report.crypto_store.custom_values, crypto_store_values.len());
+            for (key, value) in crypto_store_values {
+                let copied = destination.crypto_store.get_custom_value(&key).await.unwrap();
+                assert_eq!(copied, Some(value));
+            }
+        }
+    }
+
+    #[async_test]
+    async fn test_migrate_stores_does_not_copy_the_sync_token() {
+        let room_id = room_id!("!room:localhost");
+        let user_id = user_id!("@alice:localhost");
+
+        let source = StoreConfig::new("source".to_owned());
+        let destination = StoreConfig::new("destination".to_owned());
+
+        let mut changes = StateChanges::new("sync_token".to_owned());
+        changes.add_room(RoomInfo::new(room_id, RoomState::Joined));
+        source.state_store.save_changes(&changes).await.unwrap();
+        source
+            .state_store
+            .set_kv_data(
+                StateStoreDataKey::SyncToken,
+                StateStoreDataValue::SyncToken("sync_token".to_owned()),
+            )
+            .await
+            .unwrap();
+
+        let report =
+            migrate_stores(&source, &destination, &StoreMigrationSettings::new(user_id.to_owned()))
+                .await
+                .unwrap();
+        assert_eq!(report.state_store.rooms, 1);
+        assert_eq!(report.state_store.kv_data, 0);
+
+        // The next sync is an initial sync, which fetches what wasn't copied.
+        let sync_token =
+            destination.state_store.get_kv_data(StateStoreDataKey::SyncToken).await.unwrap();
+        assert!(sync_token.is_none());
+        let room_infos =
+            destination.state_store.get_room_infos(&RoomLoadSettings::All).await.unwrap();
+        assert_eq!(room_infos.len(), 1);
+    }
+
+    #[async_test]
+    async fn test_migrate_stores_copies_the_local_data_of_the_user() {
+        let room_id = room_id!("!room:localhost");
+        let user_id = user_id!("@alice:localhost");
+
+        let source = StoreConfig::new("source".to_owned());
+        let destination = StoreConfig::new("destination".to_owned());
+
+        let mut changes = StateChanges::default();
+        changes.add_room(RoomInfo::new(room_id, RoomState::Joined));
+        source.state_store.save_changes(&changes).await.unwrap();
+
+        let recently_visited: Vec<OwnedRoomId> = vec![owned_room_id!("!visited:localhost")];
+        source
+            .state_store
+            .set_kv_data(
+                StateStoreDataKey::RecentlyVisitedRooms(user_id),
+                StateStoreDataValue::RecentlyVisitedRooms(recently_visited.clone()),
+            )
+            .await
+            .unwrap();
+        source
+            .state_store
+            .set_kv_data(
+                StateStoreDataKey::UserAvatarUrl(user_id),
+                StateStoreDataValue::UserAvatarUrl(mxc_uri!("mxc://localhost/avatar").to_owned()),
+            )
+            .await
+            .unwrap();
+        source
+            .state_store
+            .set_kv_data(StateStoreDataKey::ComposerDraft(room_id, None), draft("room draft"))
+            .await
+            .unwrap();
+
+        let report =
+            migrate_stores(&source, &destination, &StoreMigrationSettings::new(user_id.to_owned()))
+                .await
+                .unwrap();
+        assert_eq!(report.state_store.kv_data, 3);
+
+        let value = destination
+            .state_store
+            .get_kv_data(StateStoreDataKey::RecentlyVisitedRooms(user_id))
+            .await
+            .unwrap();
+        assert_eq!(
+            value.and_then(|value| value.into_recently_visited_rooms()),
+            Some(recently_visited)
+        );
+
+        let value = destination
+            .state_store
+            .get_kv_data(StateStoreDataKey::UserAvatarUrl(user_id))
+            .await
+            .unwrap();
+        assert_eq!(
+            value.and_then(|value| value.into_user_avatar_url()).as_deref(),
+            Some(mxc_uri!("mxc://localhost/avatar"))
+        );
+
+        let value = destination
+            .state_store
+            .get_kv_data(StateStoreDataKey::ComposerDraft(room_id, None))
+            .await
+            .unwrap();
+        assert_let!(Some(draft) = value.and_then(|value| value.into_composer_draft()));
+        assert_eq!(draft.plain_text, "room draft");
+    }
+
+    #[async_test]
+    async fn test_migrate_stores_copies_the_threads_of_the_event_cache() {
+        let room_id = room_id!("!room:localhost");
+        let user_id = user_id!("@alice:localhost");
+        let thread_root = event_id!("$root");
+        let other_thread_root = event_id!("$other_root");
+
+        let source = StoreConfig::new("source".to_owned());
+        let destination = StoreConfig::new("destination".to_owned());
+
+        let mut changes = StateChanges::default();
+        changes.add_room(RoomInfo::new(room_id, RoomState::Joined));
+        source.state_store.save_changes(&changes).await.unwrap();
+
+        // The event cache only contains a reply in the thread, not its root.
+        let reply = EventFactory::new()
+            .text_msg("In the thread")
+            .sender(user_id)
+            .room(room_id)
+            .event_id(event_id!("$reply"))
+            .in_thread(thread_root, thread_root)
+            .into_event();
+        save_linked_chunk(&source, room_id, vec![reply]).await;
+
+        let subscription = ThreadSubscription { automatic: false };
+        source
+            .state_store
+            .upsert_thread_subscription(room_id, thread_root, subscription)
+            .await
+            .unwrap();
+        source
+            .state_store
+            .set_kv_data(
+                StateStoreDataKey::ComposerDraft(room_id, Some(thread_root)),
+                draft("thread draft"),
+            )
+            .await
+            .unwrap();
+
+        // The thread of this subscription isn't in the event cache, it is given in the
+        // settings.
+        source
+            .state_store
+            .upsert_thread_subscription(room_id, other_thread_root, subscription)
+            .await
+            .unwrap();
+
+        let mut settings = StoreMigrationSettings::new(user_id.to_owned());
+        settings
+            .thread_roots
+            .entry(room_id.to_owned())
+            .or_default()
+            .insert(other_thread_root.to_owned());
+
+        let report = migrate_stores(&source, &destination, &settings).await.unwrap();
+        assert_eq!(report.state_store.thread_subscriptions, 2);
+
+        for root in [thread_root, other_thread_root] {
+            let copied =
+                destination.state_store.load_thread_subscription(room_id, root).await.unwrap();
+            assert_eq!(copied, Some(subscription));
+        }
+
+        let value = destination
+            .state_store
+            .get_kv_data(StateStoreDataKey::ComposerDraft(room_id, Some(thread_root)))
+            .await
+            .unwrap();
+        assert_let!(Some(draft) = value.and_then(|value| value.into_composer_draft()));
+        assert_eq!(draft.plain_text, "thread draft");
+    }
+
+    #[test]
+    fn test_check_counts_mismatch() {
+        let original = StateStoreCounts { rooms: 2, ..Default::default() };
+        let copy = StateStoreCounts { rooms: 1, ..Default::default() };
+
+        check_counts("state store", &original, &original).unwrap();
+
+        assert_let!(
+            Err(StoreMigrationError::CountMismatch { store, .. }) =
+                check_counts("state store", &original, &copy)
+        );
+        assert_eq!(store, "state store");
+    }
+}
@@ -19,7 +19,9 @@ use std::{
 
 use async_trait::async_trait;
 use growable_bloom_filter::GrowableBloom;
-use matrix_sdk_common::{ROOM_VERSION_FALLBACK, ROOM_VERSION_RULES_FALLBACK};
+use matrix_sdk_common::{
+    ROOM_VERSION_FALLBACK, ROOM_VERSION_RULES_FALLBACK, storage_usage::StorageUsage,
+};
 use ruma::{
     CanonicalJsonObject, EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedMxcUri,
     OwnedRoomId, OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UserId,
@@ -1002,6 +1004,15 @@ impl StateStore for MemoryStore {
 
         Ok(())
     }
+
+    async fn storage_usage(&self, _room_ids: &[OwnedRoomId]) -> Result<StorageUsage> {
+        // The data is kept in memory, there's nothing to measure.
+        Ok(StorageUsage::default())
+    }
+
+    async fn compact(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[cfg(test)]
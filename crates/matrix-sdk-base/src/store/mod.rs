@@ -75,6 +75,7 @@ use crate::{
 };
 
 pub(crate) mod ambiguity_map;
+pub mod backend_migration;
 mod memory_store;
 pub mod migration_helpers;
 mod send_queue;
@@ -88,7 +89,8 @@ pub use self::{
     send_queue::{
         ChildTransactionId, DependentQueuedRequest, DependentQueuedRequestKind,
         FinishUploadThumbnailInfo, QueueWedgeError, QueuedRequest, QueuedRequestKind,
-        SentMediaInfo, SentRequestKey, SerializableEventContent,
+        ResumableUploadEncryption, ResumableUploadState, SentMediaInfo, SentRequestKey,
+        SerializableEventContent,
     },
     traits::{
         ComposerDraft, ComposerDraftType, DynStateStore, IntoStateStore, ServerInfo, StateStore,
@@ -148,6 +150,13 @@ pub enum StoreError {
         /// Details about which data is invalid, and how.
         details: String,
     },
+
+    /// The database of the store is busy, e.g. because another process is
+    /// using it.
+    ///
+    /// The operation can be retried later.
+    #[error("The store is busy, the operation can be retried later")]
+    Busy,
 }
 
 impl StoreError {
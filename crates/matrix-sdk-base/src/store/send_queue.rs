@@ -14,17 +14,17 @@
 
 //! All data types related to the send queue.
 
-use std::{collections::BTreeMap, fmt, ops::Deref};
+use std::{collections::BTreeMap, fmt, ops::Deref, path::PathBuf};
 
 use as_variant::as_variant;
 use ruma::{
-    MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedEventId, OwnedTransactionId, OwnedUserId,
-    TransactionId, UInt,
+    MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedEventId, OwnedMxcUri, OwnedTransactionId,
+    OwnedUserId, TransactionId, UInt,
     events::{
         AnyMessageLikeEventContent, MessageLikeEventContent as _, RawExt as _,
-        room::{MediaSource, message::RoomMessageEventContent},
+        room::{JsonWebKey, MediaSource, message::RoomMessageEventContent},
     },
-    serde::Raw,
+    serde::{Base64, Raw},
 };
 use serde::{Deserialize, Serialize};
 
@@ -92,8 +92,9 @@ pub enum QueuedRequestKind {
 
     /// Content to upload on the media server.
     ///
-    /// The bytes must be stored in the media cache, and are identified by the
-    /// cache key.
+    /// The bytes must either be stored in the media cache, and are identified
+    /// by the cache key, or live in the local file pointed at by
+    /// `local_file`.
     MediaUpload {
         /// Content type of the media to be uploaded.
         ///
@@ -105,6 +106,19 @@ pub enum QueuedRequestKind {
         /// store.
         cache_key: MediaRequestParameters,
 
+        /// The path to a local file holding the media's bytes, if they haven't
+        /// been copied into the event cache store.
+        ///
+        /// When set, the file is streamed from disk during the upload, and the
+        /// cache key only serves as an identifier for the local echo.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        local_file: Option<PathBuf>,
+
+        /// The state of the upload, if it's uploaded in several chunks that
+        /// can be resumed after an interruption.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        resumable_upload: Option<ResumableUploadState>,
+
         /// An optional media source for a thumbnail already uploaded.
         thumbnail_source: Option<MediaSource>,
 
@@ -119,6 +133,59 @@ pub enum QueuedRequestKind {
     },
 }
 
+/// The state of a media upload that is sent in several chunks, persisted so
+/// the upload can be resumed after an interruption, including a restart of
+/// the app.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResumableUploadState {
+    /// The MXC URI preallocated for the media, to which the chunks are
+    /// uploaded.
+    pub mxc_uri: OwnedMxcUri,
+
+    /// The number of bytes that have been acknowledged by the server so far,
+    /// i.e. the offset of the next chunk to upload.
+    pub offset: u64,
+
+    /// The total length of the media when the upload started.
+    ///
+    /// If the length of the media has changed when the upload is resumed, it
+    /// must be started over.
+    pub total_length: u64,
+
+    /// The SHA-256 hash of the `offset` bytes that have been uploaded so far,
+    /// or `None` if nothing has been uploaded yet.
+    ///
+    /// If the media doesn't produce the same bytes when the upload is resumed,
+    /// it must be started over.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploaded_hash: Option<Base64>,
+
+    /// The key used to encrypt the media, if it's uploaded encrypted.
+    ///
+    /// The media must be encrypted with the same key when the upload is
+    /// resumed, so the remaining chunks match those already uploaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<ResumableUploadEncryption>,
+}
+
+/// The key used to encrypt a media uploaded in several chunks.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ResumableUploadEncryption {
+    /// The web key used to encrypt the media.
+    pub key: JsonWebKey,
+
+    /// The initialization vector used to encrypt the media.
+    pub iv: Base64,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for ResumableUploadEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't include the key in the debug display.
+        f.debug_struct("ResumableUploadEncryption").finish_non_exhaustive()
+    }
+}
+
 impl From<SerializableEventContent> for QueuedRequestKind {
     fn from(content: SerializableEventContent) -> Self {
         Self::Event { content }
@@ -193,8 +260,8 @@ pub enum QueueWedgeError {
     #[error("Own verification is required")]
     CrossVerificationRequired,
 
-    /// Media content was cached in the media store, but has disappeared before
-    /// we could upload it.
+    /// Media content was cached in the media store, or referenced as a local
+    /// file, but has disappeared before we could upload it.
     #[error("Media content disappeared")]
     MissingMediaContent,
 
@@ -241,6 +308,11 @@ pub enum DependentQueuedRequestKind {
         /// Media request necessary to retrieve the file or thumbnail itself.
         cache_key: MediaRequestParameters,
 
+        /// The path to a local file holding the media's bytes, if they haven't
+        /// been copied into the event cache store.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        local_file: Option<PathBuf>,
+
         /// To which media transaction id does this upload relate to?
         related_to: OwnedTransactionId,
 
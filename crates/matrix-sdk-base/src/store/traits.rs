@@ -22,7 +22,7 @@ use std::{
 use as_variant::as_variant;
 use async_trait::async_trait;
 use growable_bloom_filter::GrowableBloom;
-use matrix_sdk_common::AsyncTraitDeps;
+use matrix_sdk_common::{AsyncTraitDeps, storage_usage::StorageUsage};
 use ruma::{
     EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedMxcUri, OwnedRoomId,
     OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UserId,
@@ -505,6 +505,18 @@ pub trait StateStore: AsyncTraitDeps {
         room: &RoomId,
         thread_id: &EventId,
     ) -> Result<Option<ThreadSubscription>, Self::Error>;
+
+    /// Get the storage used by the store.
+    ///
+    /// The usage is only broken down for the given rooms, since the room IDs
+    /// may be hashed in the store, in which case the store can't list the
+    /// rooms it contains data for.
+    async fn storage_usage(&self, room_ids: &[OwnedRoomId]) -> Result<StorageUsage, Self::Error>;
+
+    /// Compact the store, to reclaim unused space and optimize it.
+    ///
+    /// This is a no-op for stores that don't need it.
+    async fn compact(&self) -> Result<(), Self::Error>;
 }
 
 #[repr(transparent)]
@@ -824,6 +836,14 @@ impl<T: StateStore> StateStore for EraseStateStoreError<T> {
     ) -> Result<(), Self::Error> {
         self.0.remove_thread_subscription(room, thread_id).await.map_err(Into::into)
     }
+
+    async fn storage_usage(&self, room_ids: &[OwnedRoomId]) -> Result<StorageUsage, Self::Error> {
+        self.0.storage_usage(room_ids).await.map_err(Into::into)
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        self.0.compact().await.map_err(Into::into)
+    }
 }
 
 /// Convenience functionality for state stores.
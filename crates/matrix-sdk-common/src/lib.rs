@@ -33,6 +33,7 @@ pub mod locks;
 pub mod ring_buffer;
 pub mod serde_helpers;
 pub mod sleep;
+pub mod storage_usage;
 pub mod store_locks;
 pub mod stream;
 pub mod timeout;
@@ -0,0 +1,152 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types to report how much storage the stores use.
+
+use std::collections::BTreeMap;
+
+use ruma::{OwnedRoomId, RoomId};
+
+/// A category of data, used to break down the [`StorageUsage`] of a store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum StorageCategory {
+    /// Room events, e.g. the events of the event cache.
+    Events,
+    /// Media content.
+    Media,
+    /// Room state events and room information.
+    State,
+    /// Room members, their profiles and their display names.
+    Members,
+    /// Read receipts.
+    Receipts,
+    /// Global and room account data.
+    AccountData,
+    /// Requests waiting to be sent by the send queue.
+    SendQueue,
+    /// End-to-end encryption sessions: Olm sessions, and inbound and outbound
+    /// group sessions.
+    Sessions,
+    /// Devices and identities of the users.
+    Devices,
+    /// Search indexes.
+    SearchIndex,
+    /// Everything else, e.g. key-value data.
+    Other,
+}
+
+/// The storage used by a store.
+///
+/// The per-category and per-room sizes are the sizes of the stored values,
+/// after serialization and encryption. They don't include the overhead of the
+/// storage backend, like keys, indexes, or unused space, which is only
+/// accounted for in [`StorageUsage::total_bytes`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StorageUsage {
+    /// The total number of bytes used by the store, or `None` if the store
+    /// can't measure it, e.g. because it's in memory.
+    pub total_bytes: Option<u64>,
+
+    /// The number of bytes that compacting the store would reclaim, or `None`
+    /// if the store can't measure it.
+    pub reclaimable_bytes: Option<u64>,
+
+    /// The number of bytes used by each category of data.
+    pub categories: BTreeMap<StorageCategory, u64>,
+
+    /// The number of bytes used by the data of each room.
+    ///
+    /// Only the rooms that were asked for are reported, since some stores
+    /// can't list the rooms they contain data for.
+    pub rooms: BTreeMap<OwnedRoomId, u64>,
+}
+
+impl StorageUsage {
+    /// Add the given number of bytes to the given category.
+    pub fn add_to_category(&mut self, category: StorageCategory, bytes: u64) {
+        if bytes > 0 {
+            *self.categories.entry(category).or_default() += bytes;
+        }
+    }
+
+    /// Add the given number of bytes to the given room.
+    pub fn add_to_room(&mut self, room_id: &RoomId, bytes: u64) {
+        if bytes > 0 {
+            *self.rooms.entry(room_id.to_owned()).or_default() += bytes;
+        }
+    }
+
+    /// Get the number of bytes used by the given category.
+    pub fn category(&self, category: StorageCategory) -> u64 {
+        self.categories.get(&category).copied().unwrap_or_default()
+    }
+
+    /// Get the number of bytes used by the data of the given room.
+    pub fn room(&self, room_id: &RoomId) -> u64 {
+        self.rooms.get(room_id).copied().unwrap_or_default()
+    }
+
+    /// Merge the usage of another store into this one.
+    ///
+    /// The totals are only known if they are known for both stores.
+    pub fn merge(&mut self, other: StorageUsage) {
+        self.total_bytes = self.total_bytes.zip(other.total_bytes).map(|(a, b)| a + b);
+        self.reclaimable_bytes =
+            self.reclaimable_bytes.zip(other.reclaimable_bytes).map(|(a, b)| a + b);
+
+        for (category, bytes) in other.categories {
+            self.add_to_category(category, bytes);
+        }
+
+        for (room_id, bytes) in other.rooms {
+            self.add_to_room(&room_id, bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::room_id;
+
+    use super::{StorageCategory, StorageUsage};
+
+    #[test]
+    fn test_merge() {
+        let room_id = room_id!("!room:localhost");
+
+        let mut usage = StorageUsage { total_bytes: Some(100), ..Default::default() };
+        usage.add_to_category(StorageCategory::Events, 10);
+        usage.add_to_room(room_id, 10);
+
+        let mut other = StorageUsage {
+            total_bytes: Some(50),
+            reclaimable_bytes: Some(5),
+            ..Default::default()
+        };
+        other.add_to_category(StorageCategory::Events, 5);
+        other.add_to_category(StorageCategory::Media, 20);
+        other.add_to_room(room_id, 5);
+
+        usage.merge(other);
+
+        assert_eq!(usage.total_bytes, Some(150));
+        // The first store doesn't know its reclaimable bytes.
+        assert_eq!(usage.reclaimable_bytes, None);
+        assert_eq!(usage.category(StorageCategory::Events), 15);
+        assert_eq!(usage.category(StorageCategory::Media), 20);
+        assert_eq!(usage.category(StorageCategory::Sessions), 0);
+        assert_eq!(usage.room(room_id), 15);
+    }
+}
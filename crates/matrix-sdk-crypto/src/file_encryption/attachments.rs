@@ -261,6 +261,51 @@ impl<'a, R: Read + ?Sized + 'a> AttachmentEncryptor<'a, R> {
         }
     }
 
+    /// Wrap the given reader, encrypting it with the key and initialization
+    /// vector of the given [`MediaEncryptionInfo`] instead of fresh ones.
+    ///
+    /// Encrypting the same data again with the info returned by a previous
+    /// call to [`finish()`](#method.finish) produces the same encrypted data,
+    /// which allows to resume an interrupted upload of it. The hashes of
+    /// `info` are ignored, they are computed again from the encrypted data.
+    ///
+    /// **Never** encrypt different data with the same key and initialization
+    /// vector, it would break the confidentiality of both.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The `Reader` that should be wrapped and encrypted.
+    ///
+    /// * `info` - The encryption info holding the key and initialization vector
+    ///   to use.
+    pub fn with_encryption_info(
+        reader: &'a mut R,
+        info: &MediaEncryptionInfo,
+    ) -> Result<Self, DecryptorError> {
+        if info.version != VERSION {
+            return Err(DecryptorError::UnknownVersion);
+        }
+
+        let key = info.key.k.as_bytes();
+        let iv = info.iv.as_bytes();
+
+        if key.len() != KEY_SIZE || iv.len() != IV_SIZE {
+            return Err(DecryptorError::KeyNonceLength);
+        }
+
+        let aes = Aes256Ctr::new(GenericArray::from_slice(key), GenericArray::from_slice(iv));
+
+        Ok(AttachmentEncryptor {
+            finished: false,
+            inner: reader,
+            iv: info.iv.clone(),
+            web_key: info.key.clone(),
+            hashes: BTreeMap::new(),
+            aes,
+            sha: Sha256::default(),
+        })
+    }
+
     /// Consume the encryptor and get the encryption key.
     pub fn finish(mut self) -> MediaEncryptionInfo {
         let hash = self.sha.finalize();
@@ -330,6 +375,29 @@ mod tests {
         serde_json::from_value(info).unwrap()
     }
 
+    #[test]
+    fn encrypt_again_with_encryption_info() {
+        let data = "Hello world".to_owned();
+
+        let mut cursor = Cursor::new(data.clone());
+        let mut encryptor = AttachmentEncryptor::new(&mut cursor);
+        let mut encrypted = Vec::new();
+        encryptor.read_to_end(&mut encrypted).unwrap();
+        let info = encryptor.finish();
+
+        // Encrypting the same data with the same key gives the same result.
+        let mut cursor = Cursor::new(data);
+        let mut encryptor = AttachmentEncryptor::with_encryption_info(&mut cursor, &info).unwrap();
+        let mut encrypted_again = Vec::new();
+        encryptor.read_to_end(&mut encrypted_again).unwrap();
+        let info_again = encryptor.finish();
+
+        assert_eq!(encrypted, encrypted_again);
+        assert_eq!(info.key.k, info_again.key.k);
+        assert_eq!(info.iv, info_again.iv);
+        assert_eq!(info.hashes, info_again.hashes);
+    }
+
     #[test]
     fn encrypt_decrypt_cycle() {
         let data = "Hello world".to_owned();
@@ -114,7 +114,7 @@ use crate::{
     utilities::timestamp_to_iso8601,
     verification::{Verification, VerificationMachine, VerificationRequest},
     CollectStrategy, CryptoStoreError, DecryptionSettings, DeviceData, LocalTrust,
-    RoomEventDecryptionResult, SignatureError, TrustRequirement,
+    RoomEventDecryptionResult, RoomKeyRecipientFilter, SignatureError, TrustRequirement,
 };
 
 /// State machine implementation of the Olm/Megolm encryption protocol used for
@@ -166,8 +166,9 @@ impl std::fmt::Debug for OlmMachine {
 }
 
 impl OlmMachine {
-    const CURRENT_GENERATION_STORE_KEY: &'static str = "generation-counter";
-    const HAS_MIGRATED_VERIFICATION_LATCH: &'static str = "HAS_MIGRATED_VERIFICATION_LATCH";
+    pub(crate) const CURRENT_GENERATION_STORE_KEY: &'static str = "generation-counter";
+    pub(crate) const HAS_MIGRATED_VERIFICATION_LATCH: &'static str =
+        "HAS_MIGRATED_VERIFICATION_LATCH";
 
     /// Create a new memory based OlmMachine.
     ///
@@ -2768,27 +2769,38 @@ impl OlmMachine {
         // scope.
         let mut gen_guard = generation.lock().await;
 
+        // A read-only store never modifies the data the other processes may have
+        // cached, so it doesn't need to signal them, and it couldn't anyway.
+        let read_only = self.inner.store.is_read_only();
+
         let prev_generation =
             self.inner.store.get_custom_value(Self::CURRENT_GENERATION_STORE_KEY).await?;
 
         let gen = match prev_generation {
             Some(val) => {
+                let prev_gen = u64::from_le_bytes(val.try_into().map_err(|_| {
+                    CryptoStoreError::InvalidLockGeneration("invalid format".to_owned())
+                })?);
+
                 // There was a value in the store. We need to signal that we're a different
                 // process, so we don't just reuse the value but increment it.
-                u64::from_le_bytes(val.try_into().map_err(|_| {
-                    CryptoStoreError::InvalidLockGeneration("invalid format".to_owned())
-                })?)
-                .wrapping_add(1)
+                if read_only {
+                    prev_gen
+                } else {
+                    prev_gen.wrapping_add(1)
+                }
             }
             None => 0,
         };
 
         tracing::debug!("Initialising crypto store generation at {}", gen);
 
-        self.inner
-            .store
-            .set_custom_value(Self::CURRENT_GENERATION_STORE_KEY, gen.to_le_bytes().to_vec())
-            .await?;
+        if !read_only {
+            self.inner
+                .store
+                .set_custom_value(Self::CURRENT_GENERATION_STORE_KEY, gen.to_le_bytes().to_vec())
+                .await?;
+        }
 
         *gen_guard = Some(gen);
 
@@ -2825,19 +2837,27 @@ impl OlmMachine {
     ) -> StoreResult<(bool, u64)> {
         let mut gen_guard = generation.lock().await;
 
+        let read_only = self.inner.store.is_read_only();
+
         // The database value must be there:
         // - either we could initialize beforehand, thus write into the database,
         // - or we couldn't, and then another process was holding onto the database's
         //   lock, thus
         // has written a generation counter in there.
-        let actual_gen = self
-            .inner
-            .store
-            .get_custom_value(Self::CURRENT_GENERATION_STORE_KEY)
-            .await?
-            .ok_or_else(|| {
-                CryptoStoreError::InvalidLockGeneration("counter missing in store".to_owned())
-            })?;
+        //
+        // Except if the store is read-only and no other process initialized it.
+        let actual_gen =
+            self.inner.store.get_custom_value(Self::CURRENT_GENERATION_STORE_KEY).await?;
+
+        let actual_gen = match actual_gen {
+            Some(actual_gen) => actual_gen,
+            None if read_only => return Ok((false, gen_guard.unwrap_or_default())),
+            None => {
+                return Err(CryptoStoreError::InvalidLockGeneration(
+                    "counter missing in store".to_owned(),
+                ));
+            }
+        };
 
         let actual_gen =
             u64::from_le_bytes(actual_gen.try_into().map_err(|_| {
@@ -2849,9 +2869,20 @@ impl OlmMachine {
                 if actual_gen == *expected_gen {
                     return Ok((false, actual_gen));
                 }
+
+                // A read-only store only needs to catch up with the other processes.
+                if read_only {
+                    *gen_guard = Some(actual_gen);
+                    return Ok((true, actual_gen));
+                }
                 // Increment the biggest, and store it everywhere.
                 actual_gen.max(*expected_gen).wrapping_add(1)
             }
+            None if read_only => {
+                // Some other process hold onto the lock when initializing, so we must reload.
+                *gen_guard = Some(actual_gen);
+                return Ok((true, actual_gen));
+            }
             None => {
                 // Some other process hold onto the lock when initializing, so we must reload.
                 // Increment database value, and store it everywhere.
@@ -2966,6 +2997,45 @@ impl OlmMachine {
         Ok(())
     }
 
+    /// Override the strategy used to collect the devices which receive the
+    /// room key of the given room.
+    ///
+    /// The strategy is persisted separately from the [`RoomSettings`] of the
+    /// room, so it can be set before the encryption settings of the room are
+    /// known. It takes precedence over the one passed to
+    /// [`OlmMachine::share_room_key`] in the [`EncryptionSettings`]. Pass
+    /// `None` to remove the override.
+    ///
+    /// If the new strategy excludes devices which the current outbound session
+    /// has already been shared with, the session will be rotated the next time
+    /// the room key is shared.
+    pub async fn set_room_key_recipient_strategy(
+        &self,
+        room_id: &RoomId,
+        strategy: Option<CollectStrategy>,
+    ) -> StoreResult<()> {
+        self.inner.store.set_room_key_recipient_strategy(room_id, strategy.as_ref()).await
+    }
+
+    /// Get the strategy overriding the one passed to
+    /// [`OlmMachine::share_room_key`] for the given room, set with
+    /// [`OlmMachine::set_room_key_recipient_strategy`], if any.
+    pub async fn room_key_recipient_strategy(
+        &self,
+        room_id: &RoomId,
+    ) -> StoreResult<Option<CollectStrategy>> {
+        self.inner.store.get_room_key_recipient_strategy(room_id).await
+    }
+
+    /// Set a policy restricting which devices receive the room keys, on top of
+    /// the [`CollectStrategy`] of each room.
+    ///
+    /// The policy isn't persisted, and must be set again every time an
+    /// `OlmMachine` is created. Pass `None` to remove it.
+    pub fn set_room_key_recipient_filter(&self, filter: Option<Arc<dyn RoomKeyRecipientFilter>>) {
+        self.inner.group_session_manager.set_recipient_filter(filter);
+    }
+
     /// Returns whether this `OlmMachine` is the same another one.
     ///
     /// Useful for testing purposes only.
@@ -5,8 +5,8 @@ use matrix_sdk_test::async_test;
 use ruma::room_id;
 
 use crate::{
-    machine::tests, store::types::RoomSettings, types::EventEncryptionAlgorithm, OlmMachine,
-    SetRoomSettingsError,
+    machine::tests, store::types::RoomSettings, types::EventEncryptionAlgorithm, CollectStrategy,
+    OlmMachine, SetRoomSettingsError,
 };
 
 #[async_test]
@@ -101,3 +101,66 @@ async fn test_set_room_settings_accepts_noop_changes() {
         .await
         .unwrap();
 }
+
+#[async_test]
+async fn test_set_room_key_recipient_strategy() {
+    let machine = OlmMachine::new(tests::user_id(), tests::alice_device_id()).await;
+    let room_id = room_id!("!test:localhost");
+
+    let settings =
+        RoomSettings { session_rotation_period_messages: Some(100), ..Default::default() };
+    machine.set_room_settings(room_id, &settings).await.unwrap();
+
+    machine
+        .set_room_key_recipient_strategy(room_id, Some(CollectStrategy::IdentityBasedStrategy))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        machine.room_key_recipient_strategy(room_id).await.unwrap(),
+        Some(CollectStrategy::IdentityBasedStrategy)
+    );
+    // The room settings are untouched.
+    assert_eq!(machine.room_settings(room_id).await.unwrap(), Some(settings.clone()));
+
+    // Setting the same encryption settings again isn't a downgrade, and doesn't
+    // reset the strategy.
+    machine.set_room_settings(room_id, &settings).await.unwrap();
+    assert_eq!(
+        machine.room_key_recipient_strategy(room_id).await.unwrap(),
+        Some(CollectStrategy::IdentityBasedStrategy)
+    );
+
+    machine.set_room_key_recipient_strategy(room_id, None).await.unwrap();
+    assert_eq!(machine.room_key_recipient_strategy(room_id).await.unwrap(), None);
+}
+
+#[async_test]
+async fn test_set_room_key_recipient_strategy_before_room_settings() {
+    let machine = OlmMachine::new(tests::user_id(), tests::alice_device_id()).await;
+    let room_id = room_id!("!test:localhost");
+
+    // Set the strategy of a room whose encryption settings aren't known yet.
+    machine
+        .set_room_key_recipient_strategy(room_id, Some(CollectStrategy::IdentityBasedStrategy))
+        .await
+        .unwrap();
+
+    // No room settings have been created for it.
+    assert!(machine.room_settings(room_id).await.unwrap().is_none());
+
+    // The actual encryption settings of the room, e.g. from its `m.room.encryption`
+    // state event, can then be set, they aren't considered a downgrade.
+    let settings = RoomSettings {
+        session_rotation_period: Some(Duration::from_secs(3600)),
+        session_rotation_period_messages: Some(50),
+        ..Default::default()
+    };
+    machine.set_room_settings(room_id, &settings).await.unwrap();
+
+    assert_eq!(machine.room_settings(room_id).await.unwrap(), Some(settings));
+    assert_eq!(
+        machine.room_key_recipient_strategy(room_id).await.unwrap(),
+        Some(CollectStrategy::IdentityBasedStrategy)
+    );
+}
@@ -39,10 +39,10 @@ use ruma::{
 use serde::Serialize;
 #[cfg(feature = "experimental-send-custom-to-device")]
 pub(crate) use share_strategy::split_devices_for_share_strategy;
-pub use share_strategy::CollectStrategy;
 pub(crate) use share_strategy::{
     withheld_code_for_device_for_share_strategy, CollectRecipientsResult,
 };
+pub use share_strategy::{CollectStrategy, RoomKeyRecipientFilter};
 use tracing::{debug, error, info, instrument, trace, warn, Instrument};
 
 use crate::{
@@ -148,13 +148,26 @@ pub(crate) struct GroupSessionManager {
     store: Store,
     /// The currently active outbound group sessions.
     sessions: GroupSessionCache,
+    /// The user-supplied policy restricting which devices receive room keys,
+    /// if any.
+    recipient_filter: Arc<StdRwLock<Option<Arc<dyn RoomKeyRecipientFilter>>>>,
 }
 
 impl GroupSessionManager {
     const MAX_TO_DEVICE_MESSAGES: usize = 250;
 
     pub fn new(store: Store) -> Self {
-        Self { store: store.clone(), sessions: GroupSessionCache::new(store) }
+        Self {
+            store: store.clone(),
+            sessions: GroupSessionCache::new(store),
+            recipient_filter: Default::default(),
+        }
+    }
+
+    /// Set the user-supplied policy restricting which devices receive room
+    /// keys.
+    pub fn set_recipient_filter(&self, filter: Option<Arc<dyn RoomKeyRecipientFilter>>) {
+        *self.recipient_filter.write() = filter;
     }
 
     pub async fn invalidate_group_session(&self, room_id: &RoomId) -> StoreResult<bool> {
@@ -391,7 +404,18 @@ impl GroupSessionManager {
         settings: &EncryptionSettings,
         outbound: &OutboundGroupSession,
     ) -> OlmResult<CollectRecipientsResult> {
-        share_strategy::collect_session_recipients(&self.store, users, settings, outbound).await
+        let mut result =
+            share_strategy::collect_session_recipients(&self.store, users, settings, outbound)
+                .await?;
+
+        let filter = self.recipient_filter.read().clone();
+
+        if let Some(filter) = filter {
+            share_strategy::apply_recipient_filter(&self.store, &*filter, outbound, &mut result)
+                .await?;
+        }
+
+        Ok(result)
     }
 
     async fn encrypt_request(
@@ -688,9 +712,15 @@ impl GroupSessionManager {
         let account = self.store.static_account();
         let device = self.store.get_device(account.user_id(), account.device_id()).await?;
 
-        let encryption_settings = encryption_settings.into();
+        let mut encryption_settings = encryption_settings.into();
         let mut changes = Changes::default();
 
+        // The recipient collection strategy may be overridden for this room.
+        if let Some(sharing_strategy) = self.store.get_room_key_recipient_strategy(room_id).await? {
+            trace!(?sharing_strategy, "Using the recipient collection strategy of the room");
+            encryption_settings.sharing_strategy = sharing_strategy;
+        }
+
         // Try to get an existing session or create a new one.
         let (outbound, inbound) = self
             .get_or_create_outbound_session(
@@ -15,11 +15,13 @@
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
     default::Default,
+    fmt::Debug,
+    mem,
 };
 
 use itertools::{Either, Itertools};
 use matrix_sdk_common::deserialized_responses::WithheldCode;
-use ruma::{DeviceId, OwnedDeviceId, OwnedUserId, UserId};
+use ruma::{DeviceId, OwnedDeviceId, OwnedUserId, RoomId, UserId};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, instrument, trace};
 
@@ -35,7 +37,7 @@ use crate::{Device, UserIdentity};
 
 /// Strategy to collect the devices that should receive room keys for the
 /// current discussion.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
 #[serde(from = "CollectStrategyDeserializationHelper")]
 pub enum CollectStrategy {
@@ -81,6 +83,37 @@ impl CollectStrategy {
     }
 }
 
+/// A user-supplied policy restricting which devices receive room keys.
+///
+/// The policy is applied on top of the [`CollectStrategy`] of the room: it can
+/// only exclude devices which the strategy would otherwise share the room key
+/// with. Excluded devices are sent a withheld notice with the returned code,
+/// like the devices excluded by the built-in strategies.
+///
+/// This can be used to enforce an organisation policy, e.g. to only share room
+/// keys with devices cross-signed by identities on a given server, or to
+/// exclude devices which haven't been seen for a long time.
+pub trait RoomKeyRecipientFilter: Debug + Send + Sync {
+    /// Decide whether the room key of the given room may be shared with the
+    /// given device.
+    ///
+    /// Returns `None` if the room key may be shared with the device, or the
+    /// [`WithheldCode`] to send to the device otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room the room key is used in.
+    /// * `device` - The candidate recipient device.
+    /// * `owner_identity` - The cryptographic identity of the owner of the
+    ///   device, if known.
+    fn withheld_code(
+        &self,
+        room_id: &RoomId,
+        device: &DeviceData,
+        owner_identity: Option<&UserIdentityData>,
+    ) -> Option<WithheldCode>;
+}
+
 /// Deserialization helper for [`CollectStrategy`].
 #[derive(Deserialize)]
 enum CollectStrategyDeserializationHelper {
@@ -190,6 +223,43 @@ pub(crate) async fn collect_session_recipients(
     Ok(result)
 }
 
+/// Apply a [`RoomKeyRecipientFilter`] to the result of
+/// [`collect_session_recipients`], moving the devices it excludes to the
+/// withheld devices.
+///
+/// If the session has already been shared with an excluded device, the
+/// session is marked as needing to be rotated.
+pub(crate) async fn apply_recipient_filter(
+    store: &Store,
+    filter: &dyn RoomKeyRecipientFilter,
+    outbound: &OutboundGroupSession,
+    result: &mut CollectRecipientsResult,
+) -> OlmResult<()> {
+    let CollectRecipientsResult { should_rotate, devices, withheld_devices } = result;
+
+    for (user_id, user_devices) in devices.iter_mut() {
+        let owner_identity = store.get_user_identity(user_id).await?;
+        let mut excluded_any = false;
+
+        for device in mem::take(user_devices) {
+            match filter.withheld_code(outbound.room_id(), &device, owner_identity.as_ref()) {
+                Some(code) => {
+                    trace!(?user_id, device_id = ?device.device_id(), ?code, "Device excluded by the recipient filter");
+                    excluded_any = true;
+                    withheld_devices.push((device, code));
+                }
+                None => user_devices.push(device),
+            }
+        }
+
+        if excluded_any && !*should_rotate {
+            *should_rotate = is_session_overshared_for_user(outbound, user_id, user_devices);
+        }
+    }
+
+    Ok(())
+}
+
 /// Given a list of users and a [`CollectStrategy`], return the list of devices
 /// that cryptographic keys should be shared with, or that withheld notices
 /// should be sent to.
@@ -1043,7 +1113,7 @@ mod tests {
     use ruma::{
         device_id,
         events::{dummy::ToDeviceDummyEventContent, room::history_visibility::HistoryVisibility},
-        room_id, DeviceId, TransactionId, UserId,
+        room_id, DeviceId, RoomId, TransactionId, UserId,
     };
     use serde_json::json;
 
@@ -1054,14 +1124,16 @@ mod tests {
         olm::{OutboundGroupSession, ShareInfo},
         session_manager::{
             group_sessions::share_strategy::{
-                collect_session_recipients, withheld_code_for_device_for_share_strategy,
+                apply_recipient_filter, collect_session_recipients,
+                withheld_code_for_device_for_share_strategy,
             },
-            CollectStrategy,
+            CollectStrategy, RoomKeyRecipientFilter,
         },
         store::caches::SequenceNumber,
         testing::simulate_key_query_response_for_verification,
         types::requests::ToDeviceRequest,
         CrossSigningKeyExport, DeviceData, EncryptionSettings, LocalTrust, OlmError, OlmMachine,
+        UserIdentityData,
     };
 
     /// Returns an `OlmMachine` set up for the test user in
@@ -1273,6 +1345,56 @@ mod tests {
         );
     }
 
+    #[async_test]
+    async fn test_recipient_filter_excludes_devices() {
+        /// Only share room keys with the devices of Dan.
+        #[derive(Debug)]
+        struct OnlyDan;
+
+        impl RoomKeyRecipientFilter for OnlyDan {
+            fn withheld_code(
+                &self,
+                _room_id: &RoomId,
+                device: &DeviceData,
+                _owner_identity: Option<&UserIdentityData>,
+            ) -> Option<WithheldCode> {
+                (device.user_id() != KeyDistributionTestData::dan_id())
+                    .then_some(WithheldCode::Unauthorised)
+            }
+        }
+
+        let machine = test_machine().await;
+        import_known_users_to_test_machine(&machine).await;
+
+        let encryption_settings = all_devices_strategy_settings();
+        let group_session = create_test_outbound_group_session(&machine, &encryption_settings);
+
+        let mut share_result = collect_session_recipients(
+            machine.store(),
+            vec![KeyDistributionTestData::dan_id(), KeyDistributionTestData::good_id()].into_iter(),
+            &encryption_settings,
+            &group_session,
+        )
+        .await
+        .unwrap();
+
+        apply_recipient_filter(machine.store(), &OnlyDan, &group_session, &mut share_result)
+            .await
+            .unwrap();
+
+        // The session hasn't been shared yet, there is no need to rotate it.
+        assert!(!share_result.should_rotate);
+
+        assert_eq!(share_result.devices.get(KeyDistributionTestData::dan_id()).unwrap().len(), 2);
+        assert!(share_result.devices.get(KeyDistributionTestData::good_id()).unwrap().is_empty());
+
+        assert_eq!(share_result.withheld_devices.len(), 2);
+        assert!(share_result.withheld_devices.iter().all(|(device, code)| {
+            device.user_id() == KeyDistributionTestData::good_id()
+                && *code == WithheldCode::Unauthorised
+        }));
+    }
+
     #[async_test]
     async fn test_share_with_only_trusted_strategy() {
         let machine = test_machine().await;
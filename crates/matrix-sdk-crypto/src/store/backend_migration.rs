@@ -0,0 +1,578 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Copy the content of a [`CryptoStore`] into another one, e.g. to move a
+//! user to another storage backend without logging them out.
+//!
+//! Everything is copied through the trait API, so any implementation of the
+//! trait can be the source or the destination. Since the trait can't list all
+//! the data it contains, the data which is only reachable by a key is looked
+//! up from what can be listed:
+//!
+//! - the devices and identities are looked up for the tracked users and our own
+//!   user,
+//! - the outbound group sessions, the room settings and the room key recipient
+//!   strategies are looked up for the rooms of the inbound group sessions, and
+//!   for the given rooms,
+//! - the custom values are looked up for the given keys, since the stores can't
+//!   list them, in addition to the ones used by the [`OlmMachine`] itself.
+//!
+//! Everything else, including the Olm sessions with all the devices, the
+//! withheld room key information, the received room key bundles, the secret
+//! requests and the Olm message hashes, is listed and copied.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use ruma::{OwnedRoomId, OwnedUserId};
+use tracing::{info, instrument};
+
+use super::{
+    integrity::INBOX_SECRET_NAMES,
+    types::{
+        BackupKeys, Changes, DehydratedDeviceKey, DeviceChanges, IdentityChanges, PendingChanges,
+        RoomSettings, StoredRoomKeyBundleData, TrackedUser,
+    },
+    CryptoStore, DynCryptoStore, Result, Store,
+};
+use crate::{
+    gossiping::{GossipRequest, GossippedSecret},
+    identities::{DeviceData, UserIdentityData},
+    olm::{
+        Account, InboundGroupSession, OlmMessageHash, OutboundGroupSession,
+        PrivateCrossSigningIdentity, Session,
+    },
+    types::events::room_key_withheld::{
+        MegolmV1AesSha2WithheldContent, RoomKeyWithheldContent, RoomKeyWithheldEvent,
+    },
+    OlmMachine,
+};
+
+/// The keys of the custom values used by the [`OlmMachine`], which are always
+/// copied.
+const OLM_MACHINE_CUSTOM_VALUE_KEYS: &[&str] = &[
+    OlmMachine::CURRENT_GENERATION_STORE_KEY,
+    OlmMachine::HAS_MIGRATED_VERIFICATION_LATCH,
+    Store::ONLY_ALLOW_TRUSTED_DEVICES_KEY,
+];
+
+/// The number of items of each kind in a [`CryptoStore`], as far as they can
+/// be listed through the trait API.
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CryptoStoreCounts {
+    /// Whether the store contains an account.
+    pub has_account: bool,
+
+    /// Whether the store contains a private cross-signing identity.
+    pub has_private_identity: bool,
+
+    /// Whether the store contains a backup decryption key.
+    pub has_backup_decryption_key: bool,
+
+    /// The number of tracked users.
+    pub tracked_users: usize,
+
+    /// The number of devices of the tracked users.
+    pub devices: usize,
+
+    /// The number of identities of the tracked users.
+    pub user_identities: usize,
+
+    /// The number of Olm sessions.
+    pub olm_sessions: usize,
+
+    /// The number of inbound group sessions.
+    pub inbound_group_sessions: usize,
+
+    /// The number of inbound group sessions which are backed up.
+    pub backed_up_inbound_group_sessions: usize,
+
+    /// The number of outbound group sessions.
+    pub outbound_group_sessions: usize,
+
+    /// The number of rooms with settings.
+    pub room_settings: usize,
+
+    /// The number of secrets in the secrets inbox.
+    pub secrets: usize,
+
+    /// The number of outgoing secret requests, sent or not.
+    pub secret_requests: usize,
+
+    /// The number of withheld room key information.
+    pub withheld_info: usize,
+
+    /// The number of received room key bundles.
+    pub room_key_bundles: usize,
+
+    /// The number of Olm message hashes.
+    pub olm_message_hashes: usize,
+
+    /// The number of custom values.
+    pub custom_values: usize,
+}
+
+impl CryptoStoreCounts {
+    /// Count the items of each kind in the given store.
+    ///
+    /// The `room_ids` and the `custom_value_keys` must be the same as the ones
+    /// given to [`copy_crypto_store()`].
+    pub async fn collect(
+        store: &DynCryptoStore,
+        room_ids: &[OwnedRoomId],
+        custom_value_keys: &[String],
+    ) -> Result<Self> {
+        Ok(CryptoStoreSnapshot::load(store, room_ids, custom_value_keys).await?.counts())
+    }
+}
+
+/// Copy the content of the `source` store into the `destination` store.
+///
+/// The destination store should be empty, otherwise its content is merged
+/// with the copied one. No [`OlmMachine`] should use any of the stores during
+/// the copy.
+///
+/// The `room_ids` are the rooms to look up outbound group sessions and room
+/// settings for, in addition to the rooms of the inbound group sessions,
+/// typically all the rooms of the state store. The `custom_value_keys` are the
+/// keys of the custom values to copy, in addition to the ones used by the
+/// [`OlmMachine`].
+///
+/// Returns the number of copied items of each kind, which can be compared to
+/// the [`CryptoStoreCounts`] of the destination store to verify the copy.
+#[instrument(skip_all)]
+pub async fn copy_crypto_store(
+    source: &DynCryptoStore,
+    destination: &DynCryptoStore,
+    room_ids: &[OwnedRoomId],
+    custom_value_keys: &[String],
+) -> Result<CryptoStoreCounts> {
+    let snapshot = CryptoStoreSnapshot::load(source, room_ids, custom_value_keys).await?;
+    let counts = snapshot.counts();
+
+    snapshot.save(destination).await?;
+
+    info!(?counts, "Copied the crypto store");
+
+    Ok(counts)
+}
+
+/// All the data of a [`CryptoStore`] which can be listed through the trait
+/// API.
+#[derive(Default)]
+struct CryptoStoreSnapshot {
+    account: Option<Account>,
+    private_identity: Option<PrivateCrossSigningIdentity>,
+    backup_keys: BackupKeys,
+    dehydrated_device_pickle_key: Option<DehydratedDeviceKey>,
+    next_batch_token: Option<String>,
+    tracked_users: Vec<TrackedUser>,
+    devices: Vec<DeviceData>,
+    user_identities: Vec<UserIdentityData>,
+    olm_sessions: Vec<Session>,
+    inbound_group_sessions: Vec<InboundGroupSession>,
+    outbound_group_sessions: Vec<OutboundGroupSession>,
+    room_settings: HashMap<OwnedRoomId, RoomSettings>,
+    secrets: Vec<GossippedSecret>,
+    secret_requests: Vec<GossipRequest>,
+    withheld_info: Vec<RoomKeyWithheldEvent>,
+    room_key_bundles: Vec<StoredRoomKeyBundleData>,
+    olm_message_hashes: Vec<OlmMessageHash>,
+    custom_values: BTreeMap<String, Vec<u8>>,
+}
+
+impl CryptoStoreSnapshot {
+    async fn load(
+        store: &DynCryptoStore,
+        room_ids: &[OwnedRoomId],
+        custom_value_keys: &[String],
+    ) -> Result<Self> {
+        let mut snapshot = Self {
+            account: store.load_account().await?,
+            private_identity: store.load_identity().await?,
+            backup_keys: store.load_backup_keys().await?,
+            dehydrated_device_pickle_key: store.load_dehydrated_device_pickle_key().await?,
+            next_batch_token: store.next_batch_token().await?,
+            tracked_users: store.load_tracked_users().await?,
+            inbound_group_sessions: store.get_inbound_group_sessions().await?,
+            secret_requests: store.get_all_outgoing_secret_requests().await?,
+            withheld_info: store.get_all_withheld_info().await?,
+            room_key_bundles: store.get_all_received_room_key_bundle_data().await?,
+            olm_message_hashes: store.get_olm_message_hashes().await?,
+            ..Default::default()
+        };
+
+        // The Olm sessions can only be restored with our own device keys.
+        if snapshot.account.is_some() {
+            snapshot.olm_sessions = store.get_all_sessions().await?;
+        }
+
+        // Our own user may not be tracked yet.
+        let mut user_ids: BTreeSet<OwnedUserId> =
+            snapshot.tracked_users.iter().map(|user| user.user_id.clone()).collect();
+        if let Some(account) = &snapshot.account {
+            user_ids.insert(account.user_id().to_owned());
+        }
+
+        for user_id in &user_ids {
+            snapshot.devices.extend(store.get_user_devices(user_id).await?.into_values());
+
+            if let Some(identity) = store.get_user_identity(user_id).await? {
+                snapshot.user_identities.push(identity);
+            }
+        }
+
+        let room_ids: BTreeSet<_> = snapshot
+            .inbound_group_sessions
+            .iter()
+            .map(|session| session.room_id().to_owned())
+            .chain(room_ids.iter().cloned())
+            .collect();
+
+        for room_id in room_ids {
+            if let Some(session) = store.get_outbound_group_session(&room_id).await? {
+                snapshot.outbound_group_sessions.push(session);
+            }
+
+            // The recipient strategy of a room is stored as a custom value, separately
+            // from the room settings.
+            let strategy_key = Store::room_key_recipient_strategy_key(&room_id);
+            if let Some(value) = store.get_custom_value(&strategy_key).await? {
+                snapshot.custom_values.insert(strategy_key, value);
+            }
+
+            if let Some(settings) = store.get_room_settings(&room_id).await? {
+                snapshot.room_settings.insert(room_id, settings);
+            }
+        }
+
+        for secret_name in INBOX_SECRET_NAMES {
+            snapshot.secrets.extend(store.get_secrets_from_inbox(&secret_name).await?);
+        }
+
+        let custom_value_keys = OLM_MACHINE_CUSTOM_VALUE_KEYS
+            .iter()
+            .map(|key| key.to_string())
+            .chain(custom_value_keys.iter().cloned());
+
+        for key in custom_value_keys {
+            if let Some(value) = store.get_custom_value(&key).await? {
+                snapshot.custom_values.insert(key, value);
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    fn counts(&self) -> CryptoStoreCounts {
+        CryptoStoreCounts {
+            has_account: self.account.is_some(),
+            has_private_identity: self.private_identity.is_some(),
+            has_backup_decryption_key: self.backup_keys.decryption_key.is_some(),
+            tracked_users: self.tracked_users.len(),
+            devices: self.devices.len(),
+            user_identities: self.user_identities.len(),
+            olm_sessions: self.olm_sessions.len(),
+            inbound_group_sessions: self.inbound_group_sessions.len(),
+            backed_up_inbound_group_sessions: self
+                .inbound_group_sessions
+                .iter()
+                .filter(|session| session.backed_up())
+                .count(),
+            outbound_group_sessions: self.outbound_group_sessions.len(),
+            room_settings: self.room_settings.len(),
+            secrets: self.secrets.len(),
+            secret_requests: self.secret_requests.len(),
+            withheld_info: self.withheld_info.len(),
+            room_key_bundles: self.room_key_bundles.len(),
+            olm_message_hashes: self.olm_message_hashes.len(),
+            custom_values: self.custom_values.len(),
+        }
+    }
+
+    async fn save(self, store: &DynCryptoStore) -> Result<()> {
+        if let Some(account) = self.account {
+            store.save_pending_changes(PendingChanges { account: Some(account) }).await?;
+        }
+
+        let backed_up: Vec<_> = self
+            .inbound_group_sessions
+            .iter()
+            .filter(|session| session.backed_up())
+            .map(|session| (session.room_id().to_owned(), session.session_id().to_owned()))
+            .collect();
+
+        let changes = Changes {
+            private_identity: self.private_identity,
+            backup_version: self.backup_keys.backup_version.clone(),
+            backup_decryption_key: self.backup_keys.decryption_key,
+            dehydrated_device_pickle_key: self.dehydrated_device_pickle_key,
+            sessions: self.olm_sessions,
+            inbound_group_sessions: self.inbound_group_sessions,
+            outbound_group_sessions: self.outbound_group_sessions,
+            message_hashes: self.olm_message_hashes,
+            key_requests: self.secret_requests,
+            withheld_session_info: withheld_session_info(self.withheld_info),
+            received_room_key_bundles: self.room_key_bundles,
+            identities: IdentityChanges { new: self.user_identities, ..Default::default() },
+            devices: DeviceChanges { new: self.devices, ..Default::default() },
+            room_settings: self.room_settings,
+            secrets: self.secrets,
+            next_batch_token: self.next_batch_token,
+            ..Default::default()
+        };
+
+        store.save_changes(changes).await?;
+
+        // Some stores track the backup state separately from the sessions.
+        if let Some(backup_version) = &self.backup_keys.backup_version {
+            if !backed_up.is_empty() {
+                let room_and_session_ids: Vec<_> = backed_up
+                    .iter()
+                    .map(|(room_id, session_id)| (&**room_id, session_id.as_str()))
+                    .collect();
+
+                store
+                    .mark_inbound_group_sessions_as_backed_up(backup_version, &room_and_session_ids)
+                    .await?;
+            }
+        }
+
+        let tracked_users: Vec<_> =
+            self.tracked_users.iter().map(|user| (&*user.user_id, user.dirty)).collect();
+        store.save_tracked_users(&tracked_users).await?;
+
+        for (key, value) in self.custom_values {
+            store.set_custom_value(&key, value).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Group the withheld room key information by room and session, like
+/// [`Changes::withheld_session_info`] expects it.
+///
+/// Information without a room or a session can't be saved, so it is dropped
+/// here and shows up in the counts of the destination store.
+fn withheld_session_info(
+    events: Vec<RoomKeyWithheldEvent>,
+) -> BTreeMap<OwnedRoomId, BTreeMap<String, RoomKeyWithheldEvent>> {
+    let mut info: BTreeMap<OwnedRoomId, BTreeMap<String, RoomKeyWithheldEvent>> = BTreeMap::new();
+
+    for event in events {
+        if let RoomKeyWithheldContent::MegolmV1AesSha2(
+            MegolmV1AesSha2WithheldContent::BlackListed(content)
+            | MegolmV1AesSha2WithheldContent::Unverified(content)
+            | MegolmV1AesSha2WithheldContent::Unauthorised(content)
+            | MegolmV1AesSha2WithheldContent::Unavailable(content),
+        ) = &event.content
+        {
+            let (room_id, session_id) = (content.room_id.clone(), content.session_id.clone());
+            info.entry(room_id).or_default().insert(session_id, event);
+        }
+    }
+
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use matrix_sdk_test::async_test;
+    use ruma::{device_id, events::room::EncryptedFileInit, room_id, user_id, TransactionId};
+
+    use super::{copy_crypto_store, CryptoStoreCounts};
+    use crate::{
+        gossiping::{GossipRequest, SecretInfo},
+        olm::{Account, OlmMessageHash, SenderData},
+        store::{
+            types::{Changes, DeviceChanges, PendingChanges, StoredRoomKeyBundleData},
+            CryptoStore, IntoCryptoStore, MemoryStore, Store,
+        },
+        types::events::{
+            room_key_bundle::RoomKeyBundleContent,
+            room_key_request::MegolmV1AesSha2Content,
+            room_key_withheld::{
+                CommonWithheldCodeContent, MegolmV1AesSha2WithheldContent, RoomKeyWithheldContent,
+            },
+            ToDeviceEvent,
+        },
+        DeviceData,
+    };
+
+    #[async_test]
+    async fn test_copy_crypto_store() {
+        let room_id = room_id!("!room:localhost");
+        let alice = Account::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let mut bob = Account::with_device_id(user_id!("@bob:localhost"), device_id!("BOB"));
+
+        // An Olm session with a user we don't track.
+        bob.generate_one_time_keys(1);
+        let session = alice.create_outbound_session_helper(
+            Default::default(),
+            bob.identity_keys().curve25519,
+            *bob.one_time_keys().values().next().unwrap(),
+            false,
+            alice.device_keys(),
+        );
+
+        let sender_key = bob.identity_keys().curve25519;
+        let withheld_content =
+            RoomKeyWithheldContent::MegolmV1AesSha2(MegolmV1AesSha2WithheldContent::Unavailable(
+                CommonWithheldCodeContent::new(
+                    room_id.to_owned(),
+                    "withheld_session".to_owned(),
+                    sender_key,
+                    bob.device_id().to_owned(),
+                )
+                .into(),
+            ));
+
+        let bundle = StoredRoomKeyBundleData {
+            sender_user: bob.user_id().to_owned(),
+            sender_key,
+            sender_data: SenderData::unknown(),
+            bundle_data: RoomKeyBundleContent {
+                room_id: room_id.to_owned(),
+                file: EncryptedFileInit {
+                    url: "mxc://localhost/bundle".into(),
+                    key: ruma::events::room::JsonWebKeyInit {
+                        kty: "oct".to_owned(),
+                        key_ops: vec!["encrypt".to_owned(), "decrypt".to_owned()],
+                        alg: "A256CTR".to_owned(),
+                        k: ruma::serde::Base64::new(vec![0u8; 0]),
+                        ext: true,
+                    }
+                    .into(),
+                    iv: ruma::serde::Base64::new(vec![0u8; 0]),
+                    hashes: Default::default(),
+                    v: "v2".to_owned(),
+                }
+                .into(),
+            },
+        };
+
+        // A request which was already sent out.
+        let request_id = TransactionId::new();
+        let request = GossipRequest {
+            request_recipient: alice.user_id().to_owned(),
+            request_id: request_id.clone(),
+            info: SecretInfo::from(MegolmV1AesSha2Content {
+                room_id: room_id.to_owned(),
+                sender_key: Some(sender_key),
+                session_id: "requested_session".to_owned(),
+            }),
+            sent_out: true,
+        };
+
+        let source = MemoryStore::new().into_crypto_store();
+        source
+            .save_pending_changes(PendingChanges { account: Some(alice.deep_clone()) })
+            .await
+            .unwrap();
+        source
+            .save_changes(Changes {
+                devices: DeviceChanges {
+                    new: vec![DeviceData::from_account(&alice)],
+                    ..Default::default()
+                },
+                sessions: vec![session.clone()],
+                withheld_session_info: BTreeMap::from([(
+                    room_id.to_owned(),
+                    BTreeMap::from([(
+                        "withheld_session".to_owned(),
+                        ToDeviceEvent::new(bob.user_id().to_owned(), withheld_content),
+                    )]),
+                )]),
+                received_room_key_bundles: vec![bundle],
+                key_requests: vec![request.clone()],
+                message_hashes: vec![OlmMessageHash {
+                    sender_key: sender_key.to_base64(),
+                    hash: "hash".to_owned(),
+                }],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let destination = MemoryStore::new().into_crypto_store();
+        let counts = copy_crypto_store(&source, &destination, &[], &[]).await.unwrap();
+
+        assert!(counts.has_account);
+        assert_eq!(counts.olm_sessions, 1);
+        assert_eq!(counts.withheld_info, 1);
+        assert_eq!(counts.room_key_bundles, 1);
+        assert_eq!(counts.secret_requests, 1);
+        assert_eq!(counts.olm_message_hashes, 1);
+        assert_eq!(CryptoStoreCounts::collect(&destination, &[], &[]).await.unwrap(), counts);
+
+        // The copied data can be found in the destination store.
+        let sessions = destination.get_sessions(&sender_key.to_base64()).await.unwrap().unwrap();
+        assert_eq!(sessions, vec![session]);
+
+        let withheld = destination.get_withheld_info(room_id, "withheld_session").await.unwrap();
+        assert!(withheld.is_some());
+
+        let bundle = destination
+            .get_received_room_key_bundle_data(room_id, bob.user_id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(bundle.bundle_data.file.url.as_str(), "mxc://localhost/bundle");
+
+        let copied_request = destination.get_outgoing_secret_requests(&request_id).await.unwrap();
+        assert_eq!(copied_request, Some(request));
+
+        let hash = OlmMessageHash { sender_key: sender_key.to_base64(), hash: "hash".to_owned() };
+        assert!(destination.is_message_known(&hash).await.unwrap());
+    }
+
+    #[async_test]
+    async fn test_copy_room_key_recipient_strategies() {
+        let room_id = room_id!("!room:localhost");
+        let key = Store::room_key_recipient_strategy_key(room_id);
+
+        let source = MemoryStore::new().into_crypto_store();
+        source.set_custom_value(&key, b"strategy".to_vec()).await.unwrap();
+
+        // The strategies are looked up for the given rooms, without listing their keys.
+        let destination = MemoryStore::new().into_crypto_store();
+        let counts =
+            copy_crypto_store(&source, &destination, &[room_id.to_owned()], &[]).await.unwrap();
+
+        assert_eq!(counts.custom_values, 1);
+        assert_eq!(
+            CryptoStoreCounts::collect(&destination, &[room_id.to_owned()], &[]).await.unwrap(),
+            counts
+        );
+        assert_eq!(
+            destination.get_custom_value(&key).await.unwrap().as_deref(),
+            Some(&b"strategy"[..])
+        );
+    }
+
+    #[async_test]
+    async fn test_copy_empty_crypto_store() {
+        let source = MemoryStore::new().into_crypto_store();
+        let destination = MemoryStore::new().into_crypto_store();
+
+        let counts = copy_crypto_store(&source, &destination, &[], &[]).await.unwrap();
+
+        assert_eq!(counts, CryptoStoreCounts::default());
+        assert!(destination.load_account().await.unwrap().is_none());
+    }
+}
@@ -82,6 +82,13 @@ pub enum CryptoStoreError {
     /// An error due to an invalid generation in a cross-process locking scheme.
     #[error("invalid lock generation: {0}")]
     InvalidLockGeneration(String),
+
+    /// The database of the store is busy, e.g. because another process is
+    /// using it.
+    ///
+    /// The operation can be retried later.
+    #[error("the store is busy, the operation can be retried later")]
+    Busy,
 }
 
 impl CryptoStoreError {
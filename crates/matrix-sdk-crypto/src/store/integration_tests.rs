@@ -239,6 +239,9 @@ macro_rules! cryptostore_integration_tests {
                 let loaded_session = sessions.get(0).cloned().expect("We should find the session in the store.");
 
                 assert_eq!(&session, &loaded_session, "The loaded session should be the same one we put into the store.");
+
+                let all_sessions = store.get_all_sessions().await.expect("Can't list sessions");
+                assert_eq!(all_sessions, vec![session], "The session should be listed with all the sessions.");
             }
 
             #[async_test]
@@ -935,8 +938,14 @@ macro_rules! cryptostore_integration_tests {
                 changes.message_hashes.push(hash.clone());
 
                 assert!(!store.is_message_known(&hash).await.unwrap());
+                assert!(store.get_olm_message_hashes().await.unwrap().is_empty());
                 store.save_changes(changes).await.unwrap();
                 assert!(store.is_message_known(&hash).await.unwrap());
+
+                let hashes = store.get_olm_message_hashes().await.unwrap();
+                assert_eq!(hashes.len(), 1);
+                assert_eq!(hashes[0].sender_key, hash.sender_key);
+                assert_eq!(hashes[0].hash, hash.hash);
             }
 
             #[async_test]
@@ -989,7 +998,8 @@ macro_rules! cryptostore_integration_tests {
 
                 assert!(store.get_unsent_secret_requests().await.unwrap().is_empty());
                 let stored_request = store.get_outgoing_secret_requests(&id).await.unwrap();
-                assert_eq!(Some(request), stored_request);
+                assert_eq!(Some(request.clone()), stored_request);
+                assert_eq!(store.get_all_outgoing_secret_requests().await.unwrap(), vec![request]);
 
                 store.delete_outgoing_secret_requests(&id).await.unwrap();
 
@@ -1154,6 +1164,9 @@ macro_rules! cryptostore_integration_tests {
                     store.get_withheld_info(other_room_id, session_id_2).await.unwrap();
 
                 assert!(is_withheld.is_none());
+
+                let all_withheld = store.get_all_withheld_info().await.unwrap();
+                assert_eq!(all_withheld.len(), 2);
             }
 
             #[async_test]
@@ -1282,6 +1295,75 @@ macro_rules! cryptostore_integration_tests {
                 assert_eq!(None, loaded_2);
             }
 
+            #[async_test]
+            async fn test_remove_unreadable_sessions_keeps_readable_ones() {
+                let store = get_store("remove_unreadable_sessions_keeps_readable_ones", None, true).await;
+                let (account, session) = get_account_and_session().await;
+                let sender_key = session.sender_key.to_base64();
+                let (_, inbound) = account.create_group_session_pair_with_defaults(room_id!("!test:localhost")).await;
+
+                store.save_pending_changes(PendingChanges { account: Some(account.deep_clone()) }).await.unwrap();
+                store
+                    .save_changes(Changes {
+                        devices: DeviceChanges {
+                            new: vec![DeviceData::from_account(&account)],
+                            ..Default::default()
+                        },
+                        sessions: vec![session],
+                        inbound_group_sessions: vec![inbound],
+                        ..Default::default()
+                    })
+                    .await
+                    .unwrap();
+
+                for dry_run in [true, false] {
+                    assert_eq!(store.remove_unreadable_inbound_group_sessions(dry_run).await.unwrap(), 0);
+                    assert_eq!(store.remove_unreadable_sessions(&sender_key, dry_run).await.unwrap(), 0);
+                }
+
+                assert_eq!(store.get_inbound_group_sessions().await.unwrap().len(), 1);
+                assert_eq!(store.get_sessions(&sender_key).await.unwrap().unwrap().len(), 1);
+            }
+
+            #[async_test]
+            async fn test_integrity_check_and_repair() {
+                use $crate::store::integrity::{CryptoStoreVerifier, IntegrityIssue, IntegritySeverity};
+
+                let store = std::sync::Arc::new(get_store("integrity_check_and_repair", None, true).await);
+                let account = get_account();
+                store.save_pending_changes(PendingChanges { account: Some(account.deep_clone()) }).await.unwrap();
+
+                let verifier = CryptoStoreVerifier::new(store.clone());
+
+                // The device of the account isn't saved, and Bob is tracked without any device
+                // nor being marked as dirty.
+                store.save_tracked_users(&[(bob_id(), false)]).await.unwrap();
+
+                let report = verifier.check().await.unwrap();
+                assert!(!report.is_healthy());
+                assert_eq!(report.max_severity(), Some(IntegritySeverity::Warning));
+                assert!(report.issues.contains(&IntegrityIssue::MissingOwnDevice));
+                assert!(report.issues.contains(&IntegrityIssue::TrackedUserWithoutDevices {
+                    user_id: bob_id().to_owned()
+                }));
+
+                let repair_report = verifier.repair(&report).await.unwrap();
+                assert!(repair_report.unrepaired.is_empty());
+                assert_eq!(repair_report.users_to_query, [bob_id().to_owned()]);
+
+                let own_device =
+                    store.get_device(account.user_id(), account.device_id()).await.unwrap();
+                assert!(own_device.is_some(), "The own device should have been rebuilt");
+
+                // Bob is now waiting to be queried again, which is expected.
+                let report = verifier.check().await.unwrap();
+                assert!(report.is_healthy());
+                assert_eq!(
+                    report.issues,
+                    [IntegrityIssue::OutdatedTrackedUser { user_id: bob_id().to_owned() }]
+                );
+            }
+
             #[async_test]
             async fn test_received_room_key_bundle() {
                 let store = get_store("received_room_key_bundle", None, true).await;
@@ -1331,6 +1413,17 @@ macro_rules! cryptostore_integration_tests {
                     test_room, user_id!("@alice:example.com")
                 ).await.unwrap().expect("Did not get any bundle data");
                 assert_eq!(bundle.bundle_data.file.url.to_string(), "alice2");
+
+                // The bundle of each sender is listed once.
+                let mut urls: Vec<_> = store
+                    .get_all_received_room_key_bundle_data()
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|bundle| bundle.bundle_data.file.url.to_string())
+                    .collect();
+                urls.sort();
+                assert_eq!(urls, ["alice2", "bob1"]);
             }
 
             fn session_info(session: &InboundGroupSession) -> (&RoomId, &str) {
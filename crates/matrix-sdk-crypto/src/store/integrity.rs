@@ -0,0 +1,824 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Integrity check and repair of a [`CryptoStore`].
+//!
+//! The [`CryptoStoreVerifier`] walks every table of a [`CryptoStore`] through
+//! the trait API, so it works with any implementation of the trait. It
+//! reports the inconsistencies it finds as [`IntegrityIssue`]s, and can repair
+//! the recoverable ones.
+//!
+//! The verifier must run while no [`OlmMachine`] uses the store, since the
+//! [`OlmMachine`] caches some of the data in memory, e.g. the tracked users.
+//!
+//! ```no_run
+//! # use matrix_sdk_crypto::store::{integrity::CryptoStoreVerifier, MemoryStore};
+//! # async {
+//! let verifier = CryptoStoreVerifier::new(MemoryStore::new());
+//!
+//! let report = verifier.check().await?;
+//!
+//! for issue in &report.issues {
+//!     println!("{:?}: {issue}", issue.severity());
+//! }
+//!
+//! // Look at what would be repaired first, without modifying the store.
+//! let repair_report = verifier.clone().dry_run(true).repair(&report).await?;
+//! println!("{} issues would be repaired", repair_report.repaired.len());
+//!
+//! let repair_report = verifier.repair(&report).await?;
+//! # anyhow::Ok(()) };
+//! ```
+//!
+//! [`OlmMachine`]: crate::OlmMachine
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    sync::Arc,
+};
+
+use ruma::{events::secret::request::SecretName, OwnedDeviceId, OwnedRoomId, OwnedUserId};
+use tracing::{info, instrument, warn};
+use vodozemac::Curve25519PublicKey;
+
+use super::{
+    types::{Changes, DeviceChanges, RoomKeyCounts},
+    CryptoStore, DynCryptoStore, IntoCryptoStore, Result,
+};
+use crate::{identities::DeviceData, olm::InboundGroupSession};
+
+/// The secrets which may be waiting in the secrets inbox.
+pub(super) const INBOX_SECRET_NAMES: [SecretName; 4] = [
+    SecretName::CrossSigningMasterKey,
+    SecretName::CrossSigningUserSigningKey,
+    SecretName::CrossSigningSelfSigningKey,
+    SecretName::RecoveryKey,
+];
+
+/// The severity of an [`IntegrityIssue`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IntegritySeverity {
+    /// The store is consistent, but the data deserves attention.
+    Info,
+
+    /// The store is inconsistent, but it can still be used.
+    Warning,
+
+    /// Some data is unusable, e.g. because it can't be decrypted.
+    Error,
+
+    /// The store is unusable, it must be wiped.
+    Critical,
+}
+
+/// An inconsistency found in a [`CryptoStore`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IntegrityIssue {
+    /// The store contains no account.
+    MissingAccount,
+
+    /// The account can't be loaded, e.g. because its pickle can't be
+    /// decrypted.
+    UnreadableAccount {
+        /// The error which occurred when loading the account.
+        error: String,
+    },
+
+    /// The private cross-signing identity can't be loaded.
+    UnreadableIdentity {
+        /// The error which occurred when loading the identity.
+        error: String,
+    },
+
+    /// The device of the account isn't in the devices table.
+    MissingOwnDevice,
+
+    /// The keys of our own device in the devices table don't match the keys of
+    /// the account.
+    OwnDeviceKeysMismatch,
+
+    /// The inbound group sessions can't be loaded.
+    UnreadableInboundGroupSessions {
+        /// The error which occurred when loading the sessions.
+        error: String,
+    },
+
+    /// The number of inbound group sessions reported by the store doesn't
+    /// match the number of sessions it returns.
+    InboundGroupSessionCountMismatch {
+        /// The number of sessions reported by the store.
+        counted: usize,
+
+        /// The number of sessions returned by the store.
+        loaded: usize,
+    },
+
+    /// The backup state of the inbound group sessions doesn't match the
+    /// backup configuration, e.g. sessions are marked as backed up while no
+    /// backup is configured.
+    BackupStateOutOfSync {
+        /// The number of sessions marked as backed up.
+        backed_up: usize,
+
+        /// The total number of sessions.
+        total: usize,
+    },
+
+    /// The backup keys can't be loaded.
+    UnreadableBackupKeys {
+        /// The error which occurred when loading the backup keys.
+        error: String,
+    },
+
+    /// The outbound group session of a room has no matching inbound group
+    /// session, so the recipients of the messages encrypted with it can
+    /// decrypt them, but not us.
+    OrphanedOutboundGroupSession {
+        /// The room of the session.
+        room_id: OwnedRoomId,
+
+        /// The ID of the session.
+        session_id: String,
+    },
+
+    /// The outbound group session of a room can't be loaded.
+    UnreadableOutboundGroupSession {
+        /// The room of the session.
+        room_id: OwnedRoomId,
+
+        /// The error which occurred when loading the session.
+        error: String,
+    },
+
+    /// The list of tracked users can't be loaded.
+    UnreadableTrackedUsers {
+        /// The error which occurred when loading the tracked users.
+        error: String,
+    },
+
+    /// The devices of a tracked user are considered outdated, and will be
+    /// queried again.
+    ///
+    /// This is the normal state of a user whose devices have changed, but
+    /// users staying in that state are never queried again, e.g. because of
+    /// a bug.
+    OutdatedTrackedUser {
+        /// The ID of the user.
+        user_id: OwnedUserId,
+    },
+
+    /// A tracked user has no devices and isn't considered outdated, so their
+    /// devices will never be queried.
+    TrackedUserWithoutDevices {
+        /// The ID of the user.
+        user_id: OwnedUserId,
+    },
+
+    /// The devices of a user can't be loaded.
+    UnreadableDevices {
+        /// The ID of the user.
+        user_id: OwnedUserId,
+
+        /// The error which occurred when loading the devices.
+        error: String,
+    },
+
+    /// The identity of a user can't be loaded.
+    UnreadableUserIdentity {
+        /// The ID of the user.
+        user_id: OwnedUserId,
+
+        /// The error which occurred when loading the identity.
+        error: String,
+    },
+
+    /// The Olm sessions with a device can't be loaded.
+    UnreadableOlmSessions {
+        /// The ID of the owner of the device.
+        user_id: OwnedUserId,
+
+        /// The ID of the device.
+        device_id: OwnedDeviceId,
+
+        /// The Curve25519 key of the device.
+        sender_key: Curve25519PublicKey,
+
+        /// The error which occurred when loading the sessions.
+        error: String,
+    },
+
+    /// The pickle key of the dehydrated device can't be loaded.
+    UnreadableDehydratedDevicePickleKey {
+        /// The error which occurred when loading the pickle key.
+        error: String,
+    },
+
+    /// The secrets received for a secret name can't be loaded.
+    UnreadableSecretsInbox {
+        /// The name of the secret.
+        secret_name: SecretName,
+
+        /// The error which occurred when loading the secrets.
+        error: String,
+    },
+
+    /// The outgoing secret requests can't be loaded.
+    UnreadableSecretRequests {
+        /// The error which occurred when loading the requests.
+        error: String,
+    },
+}
+
+impl IntegrityIssue {
+    /// Get the severity of this issue.
+    pub fn severity(&self) -> IntegritySeverity {
+        match self {
+            Self::MissingAccount | Self::UnreadableAccount { .. } | Self::OwnDeviceKeysMismatch => {
+                IntegritySeverity::Critical
+            }
+
+            Self::UnreadableIdentity { .. }
+            | Self::UnreadableInboundGroupSessions { .. }
+            | Self::UnreadableBackupKeys { .. }
+            | Self::UnreadableOutboundGroupSession { .. }
+            | Self::UnreadableTrackedUsers { .. }
+            | Self::UnreadableDevices { .. }
+            | Self::UnreadableUserIdentity { .. }
+            | Self::UnreadableOlmSessions { .. }
+            | Self::UnreadableDehydratedDevicePickleKey { .. }
+            | Self::UnreadableSecretsInbox { .. }
+            | Self::UnreadableSecretRequests { .. } => IntegritySeverity::Error,
+
+            Self::MissingOwnDevice
+            | Self::InboundGroupSessionCountMismatch { .. }
+            | Self::BackupStateOutOfSync { .. }
+            | Self::OrphanedOutboundGroupSession { .. }
+            | Self::TrackedUserWithoutDevices { .. } => IntegritySeverity::Warning,
+
+            Self::OutdatedTrackedUser { .. } => IntegritySeverity::Info,
+        }
+    }
+
+    /// Whether [`CryptoStoreVerifier::repair()`] can repair this issue.
+    pub fn is_repairable(&self) -> bool {
+        match self {
+            // The own device is rebuilt from the account.
+            Self::MissingOwnDevice
+            // The sessions are marked to be backed up again.
+            | Self::BackupStateOutOfSync { .. }
+            // The session is invalidated, so a new one is created for the next message.
+            | Self::OrphanedOutboundGroupSession { .. }
+            // The users are marked to be queried again, which replaces the devices and
+            // identities.
+            | Self::OutdatedTrackedUser { .. }
+            | Self::TrackedUserWithoutDevices { .. }
+            | Self::UnreadableDevices { .. }
+            | Self::UnreadableUserIdentity { .. }
+            // The undecryptable data is dropped.
+            | Self::UnreadableInboundGroupSessions { .. }
+            | Self::UnreadableOlmSessions { .. }
+            | Self::UnreadableDehydratedDevicePickleKey { .. }
+            | Self::UnreadableSecretsInbox { .. } => true,
+
+            Self::MissingAccount
+            | Self::UnreadableAccount { .. }
+            | Self::UnreadableIdentity { .. }
+            | Self::OwnDeviceKeysMismatch
+            | Self::InboundGroupSessionCountMismatch { .. }
+            | Self::UnreadableBackupKeys { .. }
+            | Self::UnreadableOutboundGroupSession { .. }
+            | Self::UnreadableTrackedUsers { .. }
+            | Self::UnreadableSecretRequests { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingAccount => write!(f, "The store contains no account"),
+            Self::UnreadableAccount { error } => write!(f, "The account can't be loaded: {error}"),
+            Self::UnreadableIdentity { error } => {
+                write!(f, "The private cross-signing identity can't be loaded: {error}")
+            }
+            Self::MissingOwnDevice => write!(f, "The device of the account is missing"),
+            Self::OwnDeviceKeysMismatch => {
+                write!(f, "The keys of our own device don't match the keys of the account")
+            }
+            Self::UnreadableInboundGroupSessions { error } => {
+                write!(f, "The inbound group sessions can't be loaded: {error}")
+            }
+            Self::InboundGroupSessionCountMismatch { counted, loaded } => write!(
+                f,
+                "The store counts {counted} inbound group sessions, but returns {loaded} sessions"
+            ),
+            Self::BackupStateOutOfSync { backed_up, total } => write!(
+                f,
+                "The backup state of the inbound group sessions is out of sync with the backup \
+                 configuration ({backed_up} of {total} sessions are marked as backed up)"
+            ),
+            Self::UnreadableBackupKeys { error } => {
+                write!(f, "The backup keys can't be loaded: {error}")
+            }
+            Self::OrphanedOutboundGroupSession { room_id, session_id } => write!(
+                f,
+                "The outbound group session {session_id} of {room_id} has no matching inbound \
+                 group session"
+            ),
+            Self::UnreadableOutboundGroupSession { room_id, error } => {
+                write!(f, "The outbound group session of {room_id} can't be loaded: {error}")
+            }
+            Self::UnreadableTrackedUsers { error } => {
+                write!(f, "The tracked users can't be loaded: {error}")
+            }
+            Self::OutdatedTrackedUser { user_id } => {
+                write!(f, "The devices of {user_id} are marked as outdated")
+            }
+            Self::TrackedUserWithoutDevices { user_id } => {
+                write!(f, "{user_id} is tracked, but has no devices and isn't marked as outdated")
+            }
+            Self::UnreadableDevices { user_id, error } => {
+                write!(f, "The devices of {user_id} can't be loaded: {error}")
+            }
+            Self::UnreadableUserIdentity { user_id, error } => {
+                write!(f, "The identity of {user_id} can't be loaded: {error}")
+            }
+            Self::UnreadableOlmSessions { user_id, device_id, error, .. } => write!(
+                f,
+                "The Olm sessions with the device {device_id} of {user_id} can't be loaded: \
+                 {error}"
+            ),
+            Self::UnreadableDehydratedDevicePickleKey { error } => {
+                write!(f, "The dehydrated device pickle key can't be loaded: {error}")
+            }
+            Self::UnreadableSecretsInbox { secret_name, error } => {
+                write!(f, "The received {secret_name} secrets can't be loaded: {error}")
+            }
+            Self::UnreadableSecretRequests { error } => {
+                write!(f, "The outgoing secret requests can't be loaded: {error}")
+            }
+        }
+    }
+}
+
+/// The result of [`CryptoStoreVerifier::check()`].
+#[derive(Clone, Debug, Default)]
+pub struct IntegrityReport {
+    /// The issues found in the store.
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Whether no issue, other than informational ones, has been found.
+    pub fn is_healthy(&self) -> bool {
+        self.max_severity().is_none_or(|severity| severity == IntegritySeverity::Info)
+    }
+
+    /// Get the severity of the most severe issue, if any.
+    pub fn max_severity(&self) -> Option<IntegritySeverity> {
+        self.issues.iter().map(IntegrityIssue::severity).max()
+    }
+}
+
+/// The result of [`CryptoStoreVerifier::repair()`].
+#[derive(Clone, Debug, Default)]
+pub struct RepairReport {
+    /// The issues which have been repaired.
+    pub repaired: Vec<IntegrityIssue>,
+
+    /// The issues which couldn't be repaired.
+    pub unrepaired: Vec<IntegrityIssue>,
+
+    /// The users which have been marked to be queried again.
+    ///
+    /// The [`OlmMachine`] queries them once it's created with the repaired
+    /// store. They can also be queried explicitly with
+    /// [`OlmMachine::query_keys_for_users()`].
+    ///
+    /// [`OlmMachine`]: crate::OlmMachine
+    /// [`OlmMachine::query_keys_for_users()`]: crate::OlmMachine::query_keys_for_users
+    pub users_to_query: Vec<OwnedUserId>,
+
+    /// The number of inbound group sessions which have been removed because
+    /// they can't be loaded.
+    pub removed_inbound_group_sessions: usize,
+
+    /// The number of Olm sessions which have been removed because they can't
+    /// be loaded.
+    pub removed_olm_sessions: usize,
+
+    /// Whether this is the report of a dry run, i.e. the store hasn't been
+    /// modified and this report describes what would have been repaired.
+    pub dry_run: bool,
+}
+
+/// A tool to check the integrity of a [`CryptoStore`], and to repair it.
+///
+/// See the [module-level documentation](self) for more details.
+#[derive(Clone, Debug)]
+pub struct CryptoStoreVerifier {
+    store: Arc<DynCryptoStore>,
+    dry_run: bool,
+}
+
+impl CryptoStoreVerifier {
+    /// Create a new verifier for the given store.
+    pub fn new(store: impl IntoCryptoStore) -> Self {
+        Self { store: store.into_crypto_store(), dry_run: false }
+    }
+
+    /// Whether [`CryptoStoreVerifier::repair()`] should only report what it
+    /// would repair, without modifying the store.
+    ///
+    /// Disabled by default.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Check the integrity of the store.
+    ///
+    /// The data which can't be loaded is reported as an issue, an error is
+    /// only returned if the store can't be accessed at all.
+    #[instrument(skip_all)]
+    pub async fn check(&self) -> Result<IntegrityReport> {
+        let mut issues = Vec::new();
+
+        self.check_account(&mut issues).await;
+
+        if let Err(error) = self.store.load_identity().await {
+            issues.push(IntegrityIssue::UnreadableIdentity { error: error.to_string() });
+        }
+
+        self.check_room_keys(&mut issues).await?;
+        self.check_tracked_users(&mut issues).await;
+
+        if let Err(error) = self.store.load_dehydrated_device_pickle_key().await {
+            issues.push(IntegrityIssue::UnreadableDehydratedDevicePickleKey {
+                error: error.to_string(),
+            });
+        }
+
+        for secret_name in INBOX_SECRET_NAMES {
+            if let Err(error) = self.store.get_secrets_from_inbox(&secret_name).await {
+                issues.push(IntegrityIssue::UnreadableSecretsInbox {
+                    secret_name,
+                    error: error.to_string(),
+                });
+            }
+        }
+
+        if let Err(error) = self.store.get_unsent_secret_requests().await {
+            issues.push(IntegrityIssue::UnreadableSecretRequests { error: error.to_string() });
+        }
+
+        if issues.is_empty() {
+            info!("The crypto store is consistent");
+        } else {
+            warn!(num_issues = issues.len(), "Found inconsistencies in the crypto store");
+        }
+
+        Ok(IntegrityReport { issues })
+    }
+
+    /// Repair the repairable issues of the given report.
+    ///
+    /// The report should be fresh, i.e. the store shouldn't have been modified
+    /// since it was checked.
+    ///
+    /// The data which can't be decrypted is removed, e.g. the inbound group
+    /// sessions or the Olm sessions. Use [`CryptoStoreVerifier::dry_run()`]
+    /// to know what would be repaired or removed first.
+    #[instrument(skip_all, fields(dry_run = self.dry_run))]
+    pub async fn repair(&self, report: &IntegrityReport) -> Result<RepairReport> {
+        let dry_run = self.dry_run;
+        let mut repair_report = RepairReport { dry_run, ..Default::default() };
+        let mut users_to_query = BTreeSet::new();
+        let mut reset_backup_state = false;
+
+        for issue in &report.issues {
+            let repaired = match issue {
+                IntegrityIssue::MissingOwnDevice => self.repair_own_device().await?,
+
+                IntegrityIssue::BackupStateOutOfSync { .. } => {
+                    // All the inbound group sessions are reset at once.
+                    if !reset_backup_state {
+                        if !dry_run {
+                            self.store.reset_backup_state().await?;
+                        }
+                        reset_backup_state = true;
+                    }
+                    true
+                }
+
+                IntegrityIssue::UnreadableInboundGroupSessions { .. } => {
+                    repair_report.removed_inbound_group_sessions +=
+                        self.store.remove_unreadable_inbound_group_sessions(dry_run).await?;
+                    true
+                }
+
+                IntegrityIssue::UnreadableOlmSessions { sender_key, .. } => {
+                    repair_report.removed_olm_sessions += self
+                        .store
+                        .remove_unreadable_sessions(&sender_key.to_base64(), dry_run)
+                        .await?;
+                    true
+                }
+
+                IntegrityIssue::OrphanedOutboundGroupSession { .. } if dry_run => true,
+
+                IntegrityIssue::OrphanedOutboundGroupSession { room_id, session_id } => {
+                    match self.store.get_outbound_group_session(room_id).await? {
+                        Some(session) if session.session_id() == session_id => {
+                            session.invalidate_session();
+                            self.store
+                                .save_changes(Changes {
+                                    outbound_group_sessions: vec![session],
+                                    ..Default::default()
+                                })
+                                .await?;
+                        }
+                        // The session has been replaced in the meantime.
+                        _ => {}
+                    }
+                    true
+                }
+
+                IntegrityIssue::OutdatedTrackedUser { user_id }
+                | IntegrityIssue::TrackedUserWithoutDevices { user_id }
+                | IntegrityIssue::UnreadableDevices { user_id, .. }
+                | IntegrityIssue::UnreadableUserIdentity { user_id, .. } => {
+                    users_to_query.insert(user_id.clone());
+                    true
+                }
+
+                IntegrityIssue::UnreadableDehydratedDevicePickleKey { .. } => {
+                    if !dry_run {
+                        self.store.delete_dehydrated_device_pickle_key().await?;
+                    }
+                    true
+                }
+
+                IntegrityIssue::UnreadableSecretsInbox { secret_name, .. } => {
+                    if !dry_run {
+                        self.store.delete_secrets_from_inbox(secret_name).await?;
+                    }
+                    true
+                }
+
+                _ => false,
+            };
+
+            if repaired {
+                repair_report.repaired.push(issue.clone());
+            } else {
+                repair_report.unrepaired.push(issue.clone());
+            }
+        }
+
+        if !users_to_query.is_empty() && !dry_run {
+            let users: Vec<_> = users_to_query.iter().map(|user_id| (&**user_id, true)).collect();
+            self.store.save_tracked_users(&users).await?;
+        }
+
+        repair_report.users_to_query = users_to_query.into_iter().collect();
+
+        info!(
+            num_repaired = repair_report.repaired.len(),
+            num_unrepaired = repair_report.unrepaired.len(),
+            removed_inbound_group_sessions = repair_report.removed_inbound_group_sessions,
+            removed_olm_sessions = repair_report.removed_olm_sessions,
+            "Repaired the crypto store"
+        );
+
+        Ok(repair_report)
+    }
+
+    async fn check_account(&self, issues: &mut Vec<IntegrityIssue>) {
+        let account = match self.store.load_account().await {
+            Ok(Some(account)) => account,
+            Ok(None) => {
+                issues.push(IntegrityIssue::MissingAccount);
+                return;
+            }
+            Err(error) => {
+                issues.push(IntegrityIssue::UnreadableAccount { error: error.to_string() });
+                return;
+            }
+        };
+
+        match self.store.get_device(account.user_id(), account.device_id()).await {
+            Ok(Some(device)) => {
+                let identity_keys = account.identity_keys();
+
+                if device.curve25519_key() != Some(identity_keys.curve25519)
+                    || device.ed25519_key() != Some(identity_keys.ed25519)
+                {
+                    issues.push(IntegrityIssue::OwnDeviceKeysMismatch);
+                }
+            }
+            Ok(None) => issues.push(IntegrityIssue::MissingOwnDevice),
+            Err(error) => issues.push(IntegrityIssue::UnreadableDevices {
+                user_id: account.user_id().to_owned(),
+                error: error.to_string(),
+            }),
+        }
+    }
+
+    async fn check_room_keys(&self, issues: &mut Vec<IntegrityIssue>) -> Result<()> {
+        let sessions = match self.store.get_inbound_group_sessions().await {
+            Ok(sessions) => sessions,
+            Err(error) => {
+                issues.push(IntegrityIssue::UnreadableInboundGroupSessions {
+                    error: error.to_string(),
+                });
+                return Ok(());
+            }
+        };
+
+        let backup_version = match self.store.load_backup_keys().await {
+            Ok(backup_keys) => Some(backup_keys.backup_version),
+            Err(error) => {
+                issues.push(IntegrityIssue::UnreadableBackupKeys { error: error.to_string() });
+                None
+            }
+        };
+
+        let counts = self
+            .store
+            .inbound_group_session_counts(backup_version.as_ref().and_then(|v| v.as_deref()))
+            .await?;
+
+        if counts.total != sessions.len() {
+            issues.push(IntegrityIssue::InboundGroupSessionCountMismatch {
+                counted: counts.total,
+                loaded: sessions.len(),
+            });
+        }
+
+        if let Some(backup_version) = backup_version {
+            self.check_backup_state(backup_version.as_deref(), &sessions, counts, issues).await?;
+        }
+
+        // The outbound group sessions can only be looked up by room, so look at the
+        // rooms we have room keys for.
+        let room_ids: BTreeSet<_> = sessions.iter().map(|s| s.room_id().to_owned()).collect();
+
+        for room_id in room_ids {
+            let outbound = match self.store.get_outbound_group_session(&room_id).await {
+                Ok(Some(outbound)) => outbound,
+                Ok(None) => continue,
+                Err(error) => {
+                    issues.push(IntegrityIssue::UnreadableOutboundGroupSession {
+                        room_id,
+                        error: error.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            let has_inbound = sessions
+                .iter()
+                .any(|s| *s.room_id() == *room_id && s.session_id() == outbound.session_id());
+
+            if !has_inbound && !outbound.invalidated() {
+                issues.push(IntegrityIssue::OrphanedOutboundGroupSession {
+                    room_id,
+                    session_id: outbound.session_id().to_owned(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn check_backup_state(
+        &self,
+        backup_version: Option<&str>,
+        sessions: &[InboundGroupSession],
+        counts: RoomKeyCounts,
+        issues: &mut Vec<IntegrityIssue>,
+    ) -> Result<()> {
+        let out_of_sync = match backup_version {
+            // No backup is configured, so no session can be backed up.
+            None => sessions.iter().any(InboundGroupSession::backed_up),
+
+            // Some sessions aren't backed up, yet none of them is waiting to be backed up.
+            Some(backup_version) => {
+                counts.backed_up < counts.total
+                    && self
+                        .store
+                        .inbound_group_sessions_for_backup(backup_version, 1)
+                        .await?
+                        .is_empty()
+            }
+        };
+
+        if out_of_sync {
+            let backed_up = match backup_version {
+                None => sessions.iter().filter(|s| s.backed_up()).count(),
+                Some(_) => counts.backed_up,
+            };
+
+            issues.push(IntegrityIssue::BackupStateOutOfSync { backed_up, total: sessions.len() });
+        }
+
+        Ok(())
+    }
+
+    async fn check_tracked_users(&self, issues: &mut Vec<IntegrityIssue>) {
+        let tracked_users = match self.store.load_tracked_users().await {
+            Ok(tracked_users) => tracked_users,
+            Err(error) => {
+                issues.push(IntegrityIssue::UnreadableTrackedUsers { error: error.to_string() });
+                return;
+            }
+        };
+
+        for tracked_user in tracked_users {
+            let user_id = tracked_user.user_id;
+
+            if let Err(error) = self.store.get_user_identity(&user_id).await {
+                issues.push(IntegrityIssue::UnreadableUserIdentity {
+                    user_id: user_id.clone(),
+                    error: error.to_string(),
+                });
+            }
+
+            let devices = match self.store.get_user_devices(&user_id).await {
+                Ok(devices) => devices,
+                Err(error) => {
+                    issues.push(IntegrityIssue::UnreadableDevices {
+                        user_id,
+                        error: error.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            if tracked_user.dirty {
+                issues.push(IntegrityIssue::OutdatedTrackedUser { user_id: user_id.clone() });
+            } else if devices.is_empty() {
+                issues.push(IntegrityIssue::TrackedUserWithoutDevices { user_id: user_id.clone() });
+            }
+
+            self.check_olm_sessions(&user_id, devices, issues).await;
+        }
+    }
+
+    async fn check_olm_sessions(
+        &self,
+        user_id: &OwnedUserId,
+        devices: HashMap<OwnedDeviceId, DeviceData>,
+        issues: &mut Vec<IntegrityIssue>,
+    ) {
+        for (device_id, device) in devices {
+            let Some(sender_key) = device.curve25519_key() else {
+                continue;
+            };
+
+            if let Err(error) = self.store.get_sessions(&sender_key.to_base64()).await {
+                issues.push(IntegrityIssue::UnreadableOlmSessions {
+                    user_id: user_id.clone(),
+                    device_id,
+                    sender_key,
+                    error: error.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Rebuild our own device from the account.
+    async fn repair_own_device(&self) -> Result<bool> {
+        let Some(account) = self.store.load_account().await? else {
+            return Ok(false);
+        };
+
+        if self.dry_run {
+            return Ok(true);
+        }
+
+        let device = DeviceData::from_account(&account);
+
+        self.store
+            .save_changes(Changes {
+                devices: DeviceChanges { new: vec![device], ..Default::default() },
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(true)
+    }
+}
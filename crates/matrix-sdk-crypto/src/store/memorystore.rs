@@ -20,7 +20,8 @@ use std::{
 
 use async_trait::async_trait;
 use matrix_sdk_common::{
-    locks::RwLock as StdRwLock, store_locks::memory_store_helper::try_take_leased_lock,
+    locks::RwLock as StdRwLock, storage_usage::StorageUsage,
+    store_locks::memory_store_helper::try_take_leased_lock,
 };
 use ruma::{
     events::secret::request::SecretName, time::Instant, DeviceId, OwnedDeviceId, OwnedRoomId,
@@ -42,8 +43,8 @@ use crate::{
     gossiping::{GossipRequest, GossippedSecret, SecretInfo},
     identities::{DeviceData, UserIdentityData},
     olm::{
-        OutboundGroupSession, PickledAccount, PickledInboundGroupSession, PickledSession,
-        PrivateCrossSigningIdentity, SenderDataType, StaticAccountData,
+        OlmMessageHash, OutboundGroupSession, PickledAccount, PickledInboundGroupSession,
+        PickledSession, PrivateCrossSigningIdentity, SenderDataType, StaticAccountData,
     },
     types::events::room_key_withheld::RoomKeyWithheldEvent,
 };
@@ -397,6 +398,23 @@ impl CryptoStore for MemoryStore {
         }
     }
 
+    async fn get_all_sessions(&self) -> Result<Vec<Session>> {
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+
+        Ok(self
+            .sessions
+            .read()
+            .values()
+            .flat_map(BTreeMap::values)
+            .map(|serialized_pickle| {
+                let pickle: PickledSession = serde_json::from_str(serialized_pickle.as_str())
+                    .expect("Pickle pickle deserialization should work");
+                Session::from_pickle(device_keys.clone(), pickle)
+                    .expect("Expect from pickle to always work")
+            })
+            .collect())
+    }
+
     async fn get_inbound_group_session(
         &self,
         room_id: &RoomId,
@@ -428,6 +446,10 @@ impl CryptoStore for MemoryStore {
             .and_then(|e| Some(e.get(session_id)?.to_owned())))
     }
 
+    async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>> {
+        Ok(self.direct_withheld_info.read().values().flat_map(HashMap::values).cloned().collect())
+    }
+
     async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>> {
         let inbounds = self
             .inbound_group_sessions
@@ -465,6 +487,16 @@ impl CryptoStore for MemoryStore {
         Ok(RoomKeyCounts { total, backed_up })
     }
 
+    async fn remove_unreadable_inbound_group_sessions(&self, _dry_run: bool) -> Result<usize> {
+        // The sessions are pickled by this store, so they can always be loaded.
+        Ok(0)
+    }
+
+    async fn remove_unreadable_sessions(&self, _sender_key: &str, _dry_run: bool) -> Result<usize> {
+        // The sessions are pickled by this store, so they can always be loaded.
+        Ok(0)
+    }
+
     async fn get_inbound_group_sessions_for_device_batch(
         &self,
         sender_key: Curve25519PublicKey,
@@ -649,6 +681,20 @@ impl CryptoStore for MemoryStore {
             .contains(&message_hash.hash))
     }
 
+    async fn get_olm_message_hashes(&self) -> Result<Vec<OlmMessageHash>> {
+        Ok(self
+            .olm_hashes
+            .read()
+            .iter()
+            .flat_map(|(sender_key, hashes)| {
+                hashes.iter().map(|hash| OlmMessageHash {
+                    sender_key: sender_key.clone(),
+                    hash: hash.clone(),
+                })
+            })
+            .collect())
+    }
+
     async fn get_outgoing_secret_requests(
         &self,
         request_id: &TransactionId,
@@ -679,6 +725,10 @@ impl CryptoStore for MemoryStore {
             .collect())
     }
 
+    async fn get_all_outgoing_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        Ok(self.outgoing_key_requests.read().values().cloned().collect())
+    }
+
     async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
         let req = self.outgoing_key_requests.write().remove(request_id);
         if let Some(i) = req {
@@ -718,6 +768,10 @@ impl CryptoStore for MemoryStore {
         Ok(result)
     }
 
+    async fn get_all_received_room_key_bundle_data(&self) -> Result<Vec<StoredRoomKeyBundleData>> {
+        Ok(self.room_key_bundles.read().values().flat_map(HashMap::values).cloned().collect())
+    }
+
     async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
         Ok(self.custom_values.read().get(key).cloned())
     }
@@ -740,6 +794,15 @@ impl CryptoStore for MemoryStore {
     ) -> Result<bool> {
         Ok(try_take_leased_lock(&mut self.leases.write(), lease_duration_ms, key, holder))
     }
+
+    async fn storage_usage(&self, _room_ids: &[OwnedRoomId]) -> Result<StorageUsage> {
+        // The data is kept in memory, there's nothing to measure.
+        Ok(StorageUsage::default())
+    }
+
+    async fn compact(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -1238,8 +1301,10 @@ mod integration_tests {
     };
 
     use async_trait::async_trait;
+    use matrix_sdk_common::storage_usage::StorageUsage;
     use ruma::{
-        events::secret::request::SecretName, DeviceId, OwnedDeviceId, RoomId, TransactionId, UserId,
+        events::secret::request::SecretName, DeviceId, OwnedDeviceId, OwnedRoomId, RoomId,
+        TransactionId, UserId,
     };
     use vodozemac::Curve25519PublicKey;
 
@@ -1341,6 +1406,10 @@ mod integration_tests {
             self.0.get_sessions(sender_key).await
         }
 
+        async fn get_all_sessions(&self) -> Result<Vec<Session>, Self::Error> {
+            self.0.get_all_sessions().await
+        }
+
         async fn get_inbound_group_session(
             &self,
             room_id: &RoomId,
@@ -1357,6 +1426,10 @@ mod integration_tests {
             self.0.get_withheld_info(room_id, session_id).await
         }
 
+        async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>, Self::Error> {
+            self.0.get_all_withheld_info().await
+        }
+
         async fn get_inbound_group_sessions(
             &self,
         ) -> Result<Vec<InboundGroupSession>, Self::Error> {
@@ -1370,6 +1443,21 @@ mod integration_tests {
             self.0.inbound_group_session_counts(backup_version).await
         }
 
+        async fn remove_unreadable_inbound_group_sessions(
+            &self,
+            dry_run: bool,
+        ) -> Result<usize, Self::Error> {
+            self.0.remove_unreadable_inbound_group_sessions(dry_run).await
+        }
+
+        async fn remove_unreadable_sessions(
+            &self,
+            sender_key: &str,
+            dry_run: bool,
+        ) -> Result<usize, Self::Error> {
+            self.0.remove_unreadable_sessions(sender_key, dry_run).await
+        }
+
         async fn get_inbound_group_sessions_for_device_batch(
             &self,
             sender_key: Curve25519PublicKey,
@@ -1471,6 +1559,10 @@ mod integration_tests {
             self.0.is_message_known(message_hash).await
         }
 
+        async fn get_olm_message_hashes(&self) -> Result<Vec<OlmMessageHash>, Self::Error> {
+            self.0.get_olm_message_hashes().await
+        }
+
         async fn get_outgoing_secret_requests(
             &self,
             request_id: &TransactionId,
@@ -1489,6 +1581,12 @@ mod integration_tests {
             self.0.get_unsent_secret_requests().await
         }
 
+        async fn get_all_outgoing_secret_requests(
+            &self,
+        ) -> Result<Vec<GossipRequest>, Self::Error> {
+            self.0.get_all_outgoing_secret_requests().await
+        }
+
         async fn delete_outgoing_secret_requests(
             &self,
             request_id: &TransactionId,
@@ -1525,6 +1623,12 @@ mod integration_tests {
             self.0.get_received_room_key_bundle_data(room_id, user_id).await
         }
 
+        async fn get_all_received_room_key_bundle_data(
+            &self,
+        ) -> Result<Vec<StoredRoomKeyBundleData>, Self::Error> {
+            self.0.get_all_received_room_key_bundle_data().await
+        }
+
         async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Error> {
             self.0.get_custom_value(key).await
         }
@@ -1549,6 +1653,17 @@ mod integration_tests {
         async fn next_batch_token(&self) -> Result<Option<String>, Self::Error> {
             self.0.next_batch_token().await
         }
+
+        async fn storage_usage(
+            &self,
+            room_ids: &[OwnedRoomId],
+        ) -> Result<StorageUsage, Self::Error> {
+            self.0.storage_usage(room_ids).await
+        }
+
+        async fn compact(&self) -> Result<(), Self::Error> {
+            self.0.compact().await
+        }
     }
 
     cryptostore_integration_tests!();
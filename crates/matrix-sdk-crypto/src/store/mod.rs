@@ -81,12 +81,14 @@ use crate::{
         SecretsBundle,
     },
     verification::VerificationMachine,
-    CrossSigningStatus, OwnUserIdentityData, RoomKeyImportResult,
+    CollectStrategy, CrossSigningStatus, OwnUserIdentityData, RoomKeyImportResult,
 };
 
+pub mod backend_migration;
 pub mod caches;
 mod crypto_store_wrapper;
 mod error;
+pub mod integrity;
 mod memorystore;
 mod traits;
 pub mod types;
@@ -1072,10 +1074,14 @@ impl Store {
         Ok(())
     }
 
+    /// The key of the custom value storing the global flag to only encrypt
+    /// messages for trusted devices.
+    pub(crate) const ONLY_ALLOW_TRUSTED_DEVICES_KEY: &'static str = "only_allow_trusted_devices";
+
     /// Check whether there is a global flag to only encrypt messages for
     /// trusted devices or for everyone.
     pub async fn get_only_allow_trusted_devices(&self) -> Result<bool> {
-        let value = self.get_value("only_allow_trusted_devices").await?.unwrap_or_default();
+        let value = self.get_value(Self::ONLY_ALLOW_TRUSTED_DEVICES_KEY).await?.unwrap_or_default();
         Ok(value)
     }
 
@@ -1085,7 +1091,43 @@ impl Store {
         &self,
         block_untrusted_devices: bool,
     ) -> Result<()> {
-        self.set_value("only_allow_trusted_devices", &block_untrusted_devices).await
+        self.set_value(Self::ONLY_ALLOW_TRUSTED_DEVICES_KEY, &block_untrusted_devices).await
+    }
+
+    /// The key of the custom value storing the recipient collection strategy
+    /// of a room.
+    pub(crate) fn room_key_recipient_strategy_key(room_id: &RoomId) -> String {
+        format!("room_key_recipient_strategy.{room_id}")
+    }
+
+    /// Get the strategy overriding the one of the [`EncryptionSettings`] to
+    /// collect the devices which receive the room key of the given room, if
+    /// any.
+    ///
+    /// [`EncryptionSettings`]: crate::olm::EncryptionSettings
+    pub async fn get_room_key_recipient_strategy(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<CollectStrategy>> {
+        self.get_value(&Self::room_key_recipient_strategy_key(room_id)).await
+    }
+
+    /// Set the strategy overriding the one of the [`EncryptionSettings`] to
+    /// collect the devices which receive the room key of the given room, or
+    /// remove it with `None`.
+    ///
+    /// [`EncryptionSettings`]: crate::olm::EncryptionSettings
+    pub async fn set_room_key_recipient_strategy(
+        &self,
+        room_id: &RoomId,
+        strategy: Option<&CollectStrategy>,
+    ) -> Result<()> {
+        let key = Self::room_key_recipient_strategy_key(room_id);
+
+        match strategy {
+            Some(strategy) => self.set_value(&key, strategy).await,
+            None => Ok(self.remove_custom_value(&key).await?),
+        }
     }
 
     /// Get custom stored value associated with a key
@@ -15,9 +15,10 @@
 use std::{collections::HashMap, fmt, sync::Arc};
 
 use async_trait::async_trait;
-use matrix_sdk_common::AsyncTraitDeps;
+use matrix_sdk_common::{storage_usage::StorageUsage, AsyncTraitDeps};
 use ruma::{
-    events::secret::request::SecretName, DeviceId, OwnedDeviceId, RoomId, TransactionId, UserId,
+    events::secret::request::SecretName, DeviceId, OwnedDeviceId, OwnedRoomId, RoomId,
+    TransactionId, UserId,
 };
 use vodozemac::Curve25519PublicKey;
 
@@ -93,6 +94,10 @@ pub trait CryptoStore: AsyncTraitDeps {
     /// * `sender_key` - The sender key that was used to establish the sessions.
     async fn get_sessions(&self, sender_key: &str) -> Result<Option<Vec<Session>>, Self::Error>;
 
+    /// Get all the Olm sessions we have stored, regardless of the device they
+    /// were established with.
+    async fn get_all_sessions(&self) -> Result<Vec<Session>, Self::Error>;
+
     /// Get the inbound group session from our store.
     ///
     /// # Arguments
@@ -118,6 +123,9 @@ pub trait CryptoStore: AsyncTraitDeps {
         session_id: &str,
     ) -> Result<Option<RoomKeyWithheldEvent>, Self::Error>;
 
+    /// Get all the withheld info we have stored.
+    async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>, Self::Error>;
+
     /// Get all the inbound group sessions we have stored.
     async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>, Self::Error>;
 
@@ -128,6 +136,35 @@ pub trait CryptoStore: AsyncTraitDeps {
         backup_version: Option<&str>,
     ) -> Result<RoomKeyCounts, Self::Error>;
 
+    /// Remove the inbound group sessions which can't be loaded, e.g. because
+    /// they can't be decrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `dry_run` - If `true`, the sessions are only counted, not removed.
+    ///
+    /// Returns the number of sessions which can't be loaded.
+    async fn remove_unreadable_inbound_group_sessions(
+        &self,
+        dry_run: bool,
+    ) -> Result<usize, Self::Error>;
+
+    /// Remove the Olm sessions established with the given sender key which
+    /// can't be loaded, e.g. because they can't be decrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `sender_key` - The sender key that was used to establish the sessions.
+    ///
+    /// * `dry_run` - If `true`, the sessions are only counted, not removed.
+    ///
+    /// Returns the number of sessions which can't be loaded.
+    async fn remove_unreadable_sessions(
+        &self,
+        sender_key: &str,
+        dry_run: bool,
+    ) -> Result<usize, Self::Error>;
+
     /// Get a batch of inbound group sessions for the device with the supplied
     /// curve key, whose sender data is of the supplied type.
     ///
@@ -266,6 +303,12 @@ pub trait CryptoStore: AsyncTraitDeps {
     /// Check if a hash for an Olm message stored in the database.
     async fn is_message_known(&self, message_hash: &OlmMessageHash) -> Result<bool, Self::Error>;
 
+    /// Get all the hashes of the Olm messages we have stored.
+    ///
+    /// Stores which only kept a hashed key for the message hashes in older
+    /// versions can't return those.
+    async fn get_olm_message_hashes(&self) -> Result<Vec<OlmMessageHash>, Self::Error>;
+
     /// Get an outgoing secret request that we created that matches the given
     /// request id.
     ///
@@ -292,6 +335,10 @@ pub trait CryptoStore: AsyncTraitDeps {
     /// Get all outgoing secret requests that we have in the store.
     async fn get_unsent_secret_requests(&self) -> Result<Vec<GossipRequest>, Self::Error>;
 
+    /// Get all the outgoing secret requests that we have in the store, whether
+    /// they have been sent or not.
+    async fn get_all_outgoing_secret_requests(&self) -> Result<Vec<GossipRequest>, Self::Error>;
+
     /// Delete an outgoing key request that we created that matches the given
     /// request id.
     ///
@@ -334,6 +381,11 @@ pub trait CryptoStore: AsyncTraitDeps {
         user_id: &UserId,
     ) -> Result<Option<StoredRoomKeyBundleData>, Self::Error>;
 
+    /// Get the details about all the room key bundle data we have received.
+    async fn get_all_received_room_key_bundle_data(
+        &self,
+    ) -> Result<Vec<StoredRoomKeyBundleData>, Self::Error>;
+
     /// Get arbitrary data from the store
     ///
     /// # Arguments
@@ -377,6 +429,25 @@ pub trait CryptoStore: AsyncTraitDeps {
 
     /// Load the next-batch token for a to-device query, if any.
     async fn next_batch_token(&self) -> Result<Option<String>, Self::Error>;
+
+    /// Get the storage used by the store.
+    ///
+    /// The usage is only broken down for the given rooms, since the room IDs
+    /// may be hashed in the store, in which case the store can't list the
+    /// rooms it contains data for.
+    async fn storage_usage(&self, room_ids: &[OwnedRoomId]) -> Result<StorageUsage, Self::Error>;
+
+    /// Compact the store, to reclaim unused space and optimize it.
+    ///
+    /// This is a no-op for stores that don't need it.
+    async fn compact(&self) -> Result<(), Self::Error>;
+
+    /// Whether the store was opened in read-only mode, in which case the
+    /// methods that would modify it fail, except
+    /// [`CryptoStore::try_take_leased_lock`].
+    fn is_read_only(&self) -> bool {
+        false
+    }
 }
 
 #[repr(transparent)]
@@ -422,6 +493,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.get_sessions(sender_key).await.map_err(Into::into)
     }
 
+    async fn get_all_sessions(&self) -> Result<Vec<Session>> {
+        self.0.get_all_sessions().await.map_err(Into::into)
+    }
+
     async fn get_inbound_group_session(
         &self,
         room_id: &RoomId,
@@ -458,6 +533,14 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
     ) -> Result<RoomKeyCounts> {
         self.0.inbound_group_session_counts(backup_version).await.map_err(Into::into)
     }
+    async fn remove_unreadable_inbound_group_sessions(&self, dry_run: bool) -> Result<usize> {
+        self.0.remove_unreadable_inbound_group_sessions(dry_run).await.map_err(Into::into)
+    }
+
+    async fn remove_unreadable_sessions(&self, sender_key: &str, dry_run: bool) -> Result<usize> {
+        self.0.remove_unreadable_sessions(sender_key, dry_run).await.map_err(Into::into)
+    }
+
     async fn inbound_group_sessions_for_backup(
         &self,
         backup_version: &str,
@@ -535,6 +618,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.is_message_known(message_hash).await.map_err(Into::into)
     }
 
+    async fn get_olm_message_hashes(&self) -> Result<Vec<OlmMessageHash>> {
+        self.0.get_olm_message_hashes().await.map_err(Into::into)
+    }
+
     async fn get_outgoing_secret_requests(
         &self,
         request_id: &TransactionId,
@@ -553,6 +640,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.get_unsent_secret_requests().await.map_err(Into::into)
     }
 
+    async fn get_all_outgoing_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        self.0.get_all_outgoing_secret_requests().await.map_err(Into::into)
+    }
+
     async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
         self.0.delete_outgoing_secret_requests(request_id).await.map_err(Into::into)
     }
@@ -576,6 +667,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.get_withheld_info(room_id, session_id).await.map_err(Into::into)
     }
 
+    async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>> {
+        self.0.get_all_withheld_info().await.map_err(Into::into)
+    }
+
     async fn get_room_settings(&self, room_id: &RoomId) -> Result<Option<RoomSettings>> {
         self.0.get_room_settings(room_id).await.map_err(Into::into)
     }
@@ -588,6 +683,10 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.get_received_room_key_bundle_data(room_id, user_id).await.map_err(Into::into)
     }
 
+    async fn get_all_received_room_key_bundle_data(&self) -> Result<Vec<StoredRoomKeyBundleData>> {
+        self.0.get_all_received_room_key_bundle_data().await.map_err(Into::into)
+    }
+
     async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Error> {
         self.0.get_custom_value(key).await.map_err(Into::into)
     }
@@ -612,6 +711,18 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
     async fn next_batch_token(&self) -> Result<Option<String>, Self::Error> {
         self.0.next_batch_token().await.map_err(Into::into)
     }
+
+    async fn storage_usage(&self, room_ids: &[OwnedRoomId]) -> Result<StorageUsage, Self::Error> {
+        self.0.storage_usage(room_ids).await.map_err(Into::into)
+    }
+
+    async fn compact(&self) -> Result<(), Self::Error> {
+        self.0.compact().await.map_err(Into::into)
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.0.is_read_only()
+    }
 }
 
 /// A type-erased [`CryptoStore`].
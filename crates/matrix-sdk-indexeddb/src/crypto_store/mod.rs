@@ -22,6 +22,7 @@ use gloo_utils::format::JsValueSerdeExt;
 use hkdf::Hkdf;
 use indexed_db_futures::prelude::*;
 use js_sys::Array;
+use matrix_sdk_common::storage_usage::StorageUsage;
 use matrix_sdk_crypto::{
     olm::{
         Curve25519PublicKey, InboundGroupSession, OlmMessageHash, OutboundGroupSession,
@@ -42,7 +43,7 @@ use matrix_sdk_crypto::{
 use matrix_sdk_store_encryption::StoreCipher;
 use ruma::{
     events::secret::request::SecretName, DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId,
-    RoomId, TransactionId, UserId,
+    OwnedRoomId, RoomId, TransactionId, UserId,
 };
 use sha2::Sha256;
 use tokio::sync::Mutex;
@@ -322,15 +323,48 @@ impl IndexeddbCryptoStore {
     ///   key which is used to encrypt the store. Must be the same each time the
     ///   store is opened.
     pub async fn open_with_passphrase(prefix: &str, passphrase: &str) -> Result<Self> {
+        Self::open_with_passphrase_inner(prefix, passphrase, false).await
+    }
+
+    /// Open an `IndexeddbCryptoStore` with given name and passphrase, like
+    /// [`IndexeddbCryptoStore::open_with_passphrase`], and re-wrap the
+    /// encryption cipher using Argon2id if it was exported with PBKDF2.
+    ///
+    /// Once upgraded, the store can't be opened anymore by the versions of this
+    /// crate that don't support Argon2id.
+    pub async fn open_with_passphrase_and_upgrade_kdf(
+        prefix: &str,
+        passphrase: &str,
+    ) -> Result<Self> {
+        Self::open_with_passphrase_inner(prefix, passphrase, true).await
+    }
+
+    async fn open_with_passphrase_inner(
+        prefix: &str,
+        passphrase: &str,
+        upgrade_passphrase_kdf: bool,
+    ) -> Result<Self> {
         let db = open_meta_db(prefix).await?;
         let store_cipher = load_store_cipher(&db).await?;
 
         let store_cipher = match store_cipher {
-            Some(cipher) => {
+            Some(cipher) if !upgrade_passphrase_kdf => {
                 debug!("IndexedDbCryptoStore: decrypting store cipher");
                 StoreCipher::import(passphrase, &cipher)
                     .map_err(|_| CryptoStoreError::UnpicklingError)?
             }
+            Some(cipher) => {
+                debug!("IndexedDbCryptoStore: decrypting store cipher");
+                let (cipher, new_export) = StoreCipher::import_and_upgrade(passphrase, &cipher)
+                    .map_err(|_| CryptoStoreError::UnpicklingError)?;
+
+                if let Some(export) = new_export {
+                    debug!("IndexedDbCryptoStore: re-wrapping store cipher");
+                    save_store_cipher(&db, &export).await?;
+                }
+
+                cipher
+            }
             None => {
                 debug!("IndexedDbCryptoStore: encrypting new store cipher");
                 let cipher = StoreCipher::new().map_err(CryptoStoreError::backend)?;
@@ -610,9 +644,11 @@ impl IndexeddbCryptoStore {
         if !olm_hashes.is_empty() {
             let mut hashes = indexeddb_changes.get(keys::OLM_HASHES);
             for hash in olm_hashes {
+                // The key might be hashed, so store the hash as the value too, to be able
+                // to list the hashes.
                 hashes.put(
                     self.serializer.encode_key(keys::OLM_HASHES, (&hash.sender_key, &hash.hash)),
-                    JsValue::TRUE,
+                    self.serializer.serialize_value(&hash)?,
                 );
             }
         }
@@ -945,6 +981,23 @@ impl_crypto_store! {
         }
     }
 
+    async fn get_all_sessions(&self) -> Result<Vec<Session>> {
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+
+        self.inner
+            .transaction_on_one_with_mode(keys::SESSION, IdbTransactionMode::Readonly)?
+            .object_store(keys::SESSION)?
+            .get_all()?
+            .await?
+            .iter()
+            .map(|value| {
+                let pickle = self.serializer.deserialize_value(value)?;
+                Session::from_pickle(device_keys.clone(), pickle)
+                    .map_err(|_| IndexeddbCryptoStoreError::CryptoStoreError(CryptoStoreError::AccountUnset))
+            })
+            .collect()
+    }
+
     async fn get_inbound_group_session(
         &self,
         room_id: &RoomId,
@@ -1045,6 +1098,77 @@ impl_crypto_store! {
         Ok(RoomKeyCounts { total: all, backed_up: all - not_backed_up })
     }
 
+    async fn remove_unreadable_inbound_group_sessions(&self, dry_run: bool) -> Result<usize> {
+        let mode = if dry_run { IdbTransactionMode::Readonly } else { IdbTransactionMode::Readwrite };
+        let tx = self.inner.transaction_on_one_with_mode(keys::INBOUND_GROUP_SESSIONS_V3, mode)?;
+
+        let mut count = 0;
+
+        if let Some(cursor) = tx.object_store(keys::INBOUND_GROUP_SESSIONS_V3)?.open_cursor()?.await? {
+            loop {
+                if self.deserialize_inbound_group_session(cursor.value()).is_err() {
+                    count += 1;
+
+                    if !dry_run {
+                        cursor.delete()?;
+                    }
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+
+        tx.await.into_result()?;
+
+        if count > 0 {
+            warn!(count, dry_run, "Found unreadable inbound group sessions");
+        }
+
+        Ok(count)
+    }
+
+    async fn remove_unreadable_sessions(&self, sender_key: &str, dry_run: bool) -> Result<usize> {
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+
+        let range = self.serializer.encode_to_range(keys::SESSION, sender_key)?;
+        let mode = if dry_run { IdbTransactionMode::Readonly } else { IdbTransactionMode::Readwrite };
+        let tx = self.inner.transaction_on_one_with_mode(keys::SESSION, mode)?;
+
+        let mut count = 0;
+
+        if let Some(cursor) = tx.object_store(keys::SESSION)?.open_cursor_with_range(&range)?.await? {
+            loop {
+                let is_readable = self
+                    .serializer
+                    .deserialize_value(cursor.value())
+                    .ok()
+                    .is_some_and(|pickle| Session::from_pickle(device_keys.clone(), pickle).is_ok());
+
+                if !is_readable {
+                    count += 1;
+
+                    if !dry_run {
+                        cursor.delete()?;
+                    }
+                }
+
+                if !cursor.continue_cursor()?.await? {
+                    break;
+                }
+            }
+        }
+
+        tx.await.into_result()?;
+
+        if count > 0 {
+            warn!(count, dry_run, sender_key, "Found unreadable Olm sessions");
+        }
+
+        Ok(count)
+    }
+
     async fn inbound_group_sessions_for_backup(
         &self,
         _backup_version: &str,
@@ -1225,6 +1349,20 @@ impl_crypto_store! {
             .is_some())
     }
 
+    async fn get_olm_message_hashes(&self) -> Result<Vec<OlmMessageHash>> {
+        self.inner
+            .transaction_on_one_with_mode(keys::OLM_HASHES, IdbTransactionMode::Readonly)?
+            .object_store(keys::OLM_HASHES)?
+            .get_all()?
+            .await?
+            .iter()
+            // Older versions only stored the key of the hash, with a `true` value, those
+            // can't be listed.
+            .filter(|value| value.as_bool().is_none())
+            .map(|value| self.serializer.deserialize_value(value).map_err(Into::into))
+            .collect()
+    }
+
     async fn get_secrets_from_inbox(
         &self,
         secret_name: &SecretName,
@@ -1303,6 +1441,17 @@ impl_crypto_store! {
         Ok(results)
     }
 
+    async fn get_all_outgoing_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        self.inner
+            .transaction_on_one_with_mode(keys::GOSSIP_REQUESTS, IdbTransactionMode::Readonly)?
+            .object_store(keys::GOSSIP_REQUESTS)?
+            .get_all()?
+            .await?
+            .iter()
+            .map(|val| self.deserialize_gossip_request(val))
+            .collect()
+    }
+
     async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
         let jskey = self.serializer.encode_key(keys::GOSSIP_REQUESTS, request_id);
         let tx = self.inner.transaction_on_one_with_mode(keys::GOSSIP_REQUESTS, IdbTransactionMode::Readwrite)?;
@@ -1380,6 +1529,17 @@ impl_crypto_store! {
         }
     }
 
+    async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>> {
+        self.inner
+            .transaction_on_one_with_mode(keys::DIRECT_WITHHELD_INFO, IdbTransactionMode::Readonly)?
+            .object_store(keys::DIRECT_WITHHELD_INFO)?
+            .get_all()?
+            .await?
+            .iter()
+            .map(|v| self.serializer.deserialize_value(v).map_err(Into::into))
+            .collect()
+    }
+
     async fn get_room_settings(&self, room_id: &RoomId) -> Result<Option<RoomSettings>> {
         let key = self.serializer.encode_key(keys::ROOM_SETTINGS, room_id);
         self
@@ -1406,6 +1566,17 @@ impl_crypto_store! {
         Ok(result)
     }
 
+    async fn get_all_received_room_key_bundle_data(&self) -> Result<Vec<StoredRoomKeyBundleData>> {
+        self.inner
+            .transaction_on_one_with_mode(keys::RECEIVED_ROOM_KEY_BUNDLES, IdbTransactionMode::Readonly)?
+            .object_store(keys::RECEIVED_ROOM_KEY_BUNDLES)?
+            .get_all()?
+            .await?
+            .iter()
+            .map(|v| self.serializer.deserialize_value(v).map_err(Into::into))
+            .collect()
+    }
+
     async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
         self
             .inner
@@ -1477,6 +1648,17 @@ impl_crypto_store! {
             }
         }
     }
+
+    async fn storage_usage(&self, _room_ids: &[OwnedRoomId]) -> Result<StorageUsage> {
+        // IndexedDB only exposes an estimate of the storage used by the whole
+        // origin, not by a single database.
+        Ok(StorageUsage::default())
+    }
+
+    async fn compact(&self) -> Result<()> {
+        // The browser takes care of compacting IndexedDB.
+        Ok(())
+    }
 }
 
 impl Drop for IndexeddbCryptoStore {
@@ -30,11 +30,12 @@ use matrix_sdk_base::{
         RawChunk, Update,
     },
     media::MediaRequestParameters,
+    storage_usage::StorageUsage,
     timer,
 };
 use ruma::{
     events::relation::RelationType, EventId, MilliSecondsSinceUnixEpoch, MxcUri, OwnedEventId,
-    RoomId,
+    OwnedRoomId, RoomId,
 };
 use tracing::{error, instrument, trace};
 use web_sys::IdbTransactionMode;
@@ -649,6 +650,22 @@ impl_event_cache_store! {
             .await
             .map_err(IndexeddbEventCacheStoreError::MemoryStore)
     }
+
+    #[instrument(skip_all)]
+    async fn storage_usage(
+        &self,
+        _room_ids: &[OwnedRoomId],
+    ) -> Result<StorageUsage, IndexeddbEventCacheStoreError> {
+        // IndexedDB only exposes an estimate of the storage used by the whole
+        // origin, not by a single database.
+        Ok(StorageUsage::default())
+    }
+
+    #[instrument(skip_all)]
+    async fn compact(&self) -> Result<(), IndexeddbEventCacheStoreError> {
+        // The browser takes care of compacting IndexedDB.
+        Ok(())
+    }
 }
 
 #[cfg(test)]
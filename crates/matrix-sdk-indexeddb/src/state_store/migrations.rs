@@ -82,6 +82,7 @@ mod old_keys {
 pub async fn upgrade_meta_db(
     meta_name: &str,
     passphrase: Option<&str>,
+    upgrade_passphrase_kdf: bool,
 ) -> Result<(IdbDatabase, Option<Arc<StoreCipher>>)> {
     // Meta database.
     let mut db_req: OpenDbRequest = IdbDatabase::open_u32(meta_name, CURRENT_META_DB_VERSION)?;
@@ -113,7 +114,22 @@ pub async fn upgrade_meta_db(
             .map(|v| v.into_serde())
             .transpose()?
         {
-            StoreCipher::import(passphrase, &inner)?
+            if !upgrade_passphrase_kdf {
+                StoreCipher::import(passphrase, &inner)?
+            } else {
+                let (cipher, new_export) = StoreCipher::import_and_upgrade(passphrase, &inner)?;
+
+                // Re-wrap the store cipher if it was exported with an outdated key
+                // derivation function.
+                if let Some(export) = new_export {
+                    ob.put_key_val(
+                        &JsValue::from_str(keys::STORE_KEY),
+                        &JsValue::from_serde(&StoreKeyWrapper(export))?,
+                    )?;
+                }
+
+                cipher
+            }
         } else {
             let cipher = StoreCipher::new()?;
             #[cfg(not(test))]
@@ -24,6 +24,7 @@ use growable_bloom_filter::GrowableBloom;
 use indexed_db_futures::prelude::*;
 use matrix_sdk_base::{
     deserialized_responses::{DisplayName, RawAnySyncOrStrippedState},
+    storage_usage::StorageUsage,
     store::{
         ChildTransactionId, ComposerDraft, DependentQueuedRequest, DependentQueuedRequestKind,
         QueuedRequest, QueuedRequestKind, RoomLoadSettings, SentRequestKey,
@@ -216,6 +217,7 @@ pub struct IndexeddbStateStoreBuilder {
     name: Option<String>,
     passphrase: Option<String>,
     migration_conflict_strategy: MigrationConflictStrategy,
+    upgrade_passphrase_kdf: bool,
 }
 
 impl IndexeddbStateStoreBuilder {
@@ -224,6 +226,7 @@ impl IndexeddbStateStoreBuilder {
             name: None,
             passphrase: None,
             migration_conflict_strategy: MigrationConflictStrategy::BackupAndDrop,
+            upgrade_passphrase_kdf: false,
         }
     }
 
@@ -249,13 +252,25 @@ impl IndexeddbStateStoreBuilder {
         self
     }
 
+    /// Whether the store cipher should be re-wrapped using Argon2id when the
+    /// store is opened, if it was exported with PBKDF2.
+    ///
+    /// Once upgraded, the store can't be opened anymore by the versions of this
+    /// crate that don't support Argon2id. Defaults to `false`.
+    pub fn upgrade_passphrase_kdf(mut self, value: bool) -> Self {
+        self.upgrade_passphrase_kdf = value;
+        self
+    }
+
     pub async fn build(self) -> Result<IndexeddbStateStore> {
         let migration_strategy = self.migration_conflict_strategy.clone();
         let name = self.name.unwrap_or_else(|| "state".to_owned());
 
         let meta_name = format!("{name}::{}", keys::INTERNAL_STATE);
 
-        let (meta, store_cipher) = upgrade_meta_db(&meta_name, self.passphrase.as_deref()).await?;
+        let (meta, store_cipher) =
+            upgrade_meta_db(&meta_name, self.passphrase.as_deref(), self.upgrade_passphrase_kdf)
+                .await?;
         let inner =
             upgrade_inner_db(&name, store_cipher.as_deref(), migration_strategy, &meta).await?;
 
@@ -1854,6 +1869,17 @@ impl_state_store!({
 
         Ok(())
     }
+
+    async fn storage_usage(&self, _room_ids: &[OwnedRoomId]) -> Result<StorageUsage> {
+        // IndexedDB only exposes an estimate of the storage used by the whole
+        // origin, not by a single database.
+        Ok(StorageUsage::default())
+    }
+
+    async fn compact(&self) -> Result<()> {
+        // The browser takes care of compacting IndexedDB.
+        Ok(())
+    }
 });
 
 /// A room member.
@@ -21,6 +21,7 @@ use std::{
 
 use async_trait::async_trait;
 use deadpool_sqlite::{Object as SqliteAsyncConn, Pool as SqlitePool, Runtime};
+use matrix_sdk_common::storage_usage::{StorageCategory, StorageUsage};
 use matrix_sdk_crypto::{
     olm::{
         InboundGroupSession, OutboundGroupSession, PickledInboundGroupSession,
@@ -39,7 +40,7 @@ use matrix_sdk_crypto::{
 use matrix_sdk_store_encryption::StoreCipher;
 use ruma::{
     events::secret::request::SecretName, DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId,
-    RoomId, TransactionId, UserId,
+    OwnedRoomId, RoomId, TransactionId, UserId,
 };
 use rusqlite::{named_params, params_from_iter, OptionalExtension};
 use tokio::{fs, sync::Mutex};
@@ -49,8 +50,9 @@ use vodozemac::Curve25519PublicKey;
 use crate::{
     error::{Error, Result},
     utils::{
-        repeat_vars, EncryptableStore, Key, SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt,
-        SqliteKeyValueStoreConnExt,
+        create_lease_pool, database_path, repeat_vars, storage_usage, EncryptableStore, Key,
+        SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt, SqliteKeyValueStoreConnExt,
+        StorageUsageTable,
     },
     OpenStoreError, SqliteStoreConfig,
 };
@@ -67,6 +69,13 @@ pub struct SqliteCryptoStore {
     // DB values cached in memory
     static_account: Arc<RwLock<Option<StaticAccountData>>>,
     save_changes_lock: Arc<Mutex<()>>,
+
+    /// Whether the database was opened in read-only mode.
+    read_only: bool,
+
+    /// The pool of the read-write connection used to take the leased locks,
+    /// if the database was opened in read-only mode.
+    lease_pool: Option<SqlitePool>,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -94,18 +103,37 @@ impl SqliteCryptoStore {
 
     /// Open the SQLite-based crypto store with the config open config.
     pub async fn open_with_config(config: SqliteStoreConfig) -> Result<Self, OpenStoreError> {
-        let SqliteStoreConfig { path, passphrase, pool_config, runtime_config } = config;
-
-        fs::create_dir_all(&path).await.map_err(OpenStoreError::CreateDir)?;
+        let SqliteStoreConfig {
+            path,
+            passphrase,
+            pool_config,
+            mut runtime_config,
+            read_only,
+            upgrade_passphrase_kdf,
+        } = config;
+
+        if read_only {
+            // The database can't be modified, so it can't be optimized.
+            runtime_config.optimize = false;
+        } else {
+            fs::create_dir_all(&path).await.map_err(OpenStoreError::CreateDir)?;
+        }
 
-        let mut config = deadpool_sqlite::Config::new(path.join(DATABASE_NAME));
+        let mut config =
+            deadpool_sqlite::Config::new(database_path(&path, DATABASE_NAME, read_only));
         config.pool = Some(pool_config);
 
         let pool = config.create_pool(Runtime::Tokio1)?;
 
-        let this = Self::open_with_pool(pool, passphrase.as_deref()).await?;
+        let mut this =
+            Self::open_with_pool(pool, passphrase.as_deref(), read_only, upgrade_passphrase_kdf)
+                .await?;
         this.pool.get().await?.apply_runtime_config(runtime_config).await?;
 
+        if read_only {
+            this.lease_pool = Some(create_lease_pool(&path, DATABASE_NAME)?);
+        }
+
         Ok(this)
     }
 
@@ -114,16 +142,35 @@ impl SqliteCryptoStore {
     async fn open_with_pool(
         pool: SqlitePool,
         passphrase: Option<&str>,
+        read_only: bool,
+        upgrade_passphrase_kdf: bool,
     ) -> Result<Self, OpenStoreError> {
         let conn = pool.get().await?;
 
         let version = conn.db_version().await?;
         debug!("Opened sqlite store with version {}", version);
-        run_migrations(&conn, version).await?;
 
-        let store_cipher = match passphrase {
-            Some(p) => Some(Arc::new(conn.get_or_create_store_cipher(p).await?)),
-            None => None,
+        let store_cipher = if read_only {
+            if version < DATABASE_VERSION {
+                return Err(OpenStoreError::ReadOnlyMigration {
+                    version,
+                    expected: DATABASE_VERSION,
+                });
+            }
+
+            match passphrase {
+                Some(p) => Some(Arc::new(conn.get_store_cipher(p).await?)),
+                None => None,
+            }
+        } else {
+            run_migrations(&conn, version).await?;
+
+            match passphrase {
+                Some(p) => Some(Arc::new(
+                    conn.get_or_create_store_cipher(p, upgrade_passphrase_kdf).await?,
+                )),
+                None => None,
+            }
         };
 
         Ok(SqliteCryptoStore {
@@ -131,6 +178,8 @@ impl SqliteCryptoStore {
             pool,
             static_account: Arc::new(RwLock::new(None)),
             save_changes_lock: Default::default(),
+            read_only,
+            lease_pool: None,
         })
     }
 
@@ -165,6 +214,24 @@ impl SqliteCryptoStore {
     async fn acquire(&self) -> Result<SqliteAsyncConn> {
         Ok(self.pool.get().await?)
     }
+
+    /// Acquire the connection used to take the leased locks, which can modify
+    /// the database even if it was opened in read-only mode.
+    async fn acquire_lease_connection(&self) -> Result<SqliteAsyncConn> {
+        match &self.lease_pool {
+            Some(lease_pool) => Ok(lease_pool.get().await?),
+            None => self.acquire().await,
+        }
+    }
+
+    /// Return an error if the database was opened in read-only mode.
+    fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            Err(Error::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
 }
 
 const DATABASE_VERSION: u8 = 11;
@@ -172,6 +239,101 @@ const DATABASE_VERSION: u8 = 11;
 /// key for the dehydrated device pickle key in the key/value table.
 const DEHYDRATED_DEVICE_PICKLE_KEY: &str = "dehydrated_device_pickle_key";
 
+/// The tables measured by [`CryptoStore::storage_usage`].
+const STORAGE_USAGE_TABLES: &[StorageUsageTable] = &[
+    StorageUsageTable {
+        name: "kv",
+        value_column: "value",
+        room_id_column: None,
+        room_id_key_table: "kv",
+        category: StorageCategory::Other,
+    },
+    StorageUsageTable {
+        name: "session",
+        value_column: "data",
+        room_id_column: None,
+        room_id_key_table: "session",
+        category: StorageCategory::Sessions,
+    },
+    StorageUsageTable {
+        name: "inbound_group_session",
+        value_column: "data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: "inbound_group_session",
+        category: StorageCategory::Sessions,
+    },
+    StorageUsageTable {
+        name: "outbound_group_session",
+        value_column: "data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: "outbound_group_session",
+        category: StorageCategory::Sessions,
+    },
+    StorageUsageTable {
+        name: "device",
+        value_column: "data",
+        room_id_column: None,
+        room_id_key_table: "device",
+        category: StorageCategory::Devices,
+    },
+    StorageUsageTable {
+        name: "identity",
+        value_column: "data",
+        room_id_column: None,
+        room_id_key_table: "identity",
+        category: StorageCategory::Devices,
+    },
+    StorageUsageTable {
+        name: "tracked_user",
+        value_column: "data",
+        room_id_column: None,
+        room_id_key_table: "tracked_user",
+        category: StorageCategory::Devices,
+    },
+    StorageUsageTable {
+        name: "olm_hash",
+        value_column: "data",
+        room_id_column: None,
+        room_id_key_table: "olm_hash",
+        category: StorageCategory::Other,
+    },
+    StorageUsageTable {
+        name: "key_requests",
+        value_column: "data",
+        room_id_column: None,
+        room_id_key_table: "key_requests",
+        category: StorageCategory::Other,
+    },
+    StorageUsageTable {
+        name: "room_settings",
+        value_column: "data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: "room_settings",
+        category: StorageCategory::Other,
+    },
+    StorageUsageTable {
+        name: "direct_withheld_info",
+        value_column: "data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: "direct_withheld_info",
+        category: StorageCategory::Other,
+    },
+    StorageUsageTable {
+        name: "secrets",
+        value_column: "data",
+        room_id_column: None,
+        room_id_key_table: "secrets",
+        category: StorageCategory::Other,
+    },
+    StorageUsageTable {
+        name: "received_room_key_bundle",
+        value_column: "bundle_data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: "received_room_key_bundle",
+        category: StorageCategory::Other,
+    },
+];
+
 /// Run migrations for the given version of the database.
 async fn run_migrations(conn: &SqliteAsyncConn, version: u8) -> Result<()> {
     if version == 0 {
@@ -492,6 +654,39 @@ trait SqliteObjectCryptoStoreExt: SqliteAsyncConnExt {
             .await?)
     }
 
+    async fn get_all_sessions(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT data FROM session", |mut stmt| {
+                stmt.query(())?.mapped(|row| row.get(0)).collect()
+            })
+            .await?)
+    }
+
+    async fn get_sessions_with_ids_for_sender_key(
+        &self,
+        sender_key: Key,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        Ok(self
+            .prepare("SELECT session_id, data FROM session WHERE sender_key = ?", |mut stmt| {
+                stmt.query((sender_key,))?.mapped(|row| Ok((row.get(0)?, row.get(1)?))).collect()
+            })
+            .await?)
+    }
+
+    async fn delete_sessions(&self, session_ids: Vec<Key>) -> Result<()> {
+        self.chunk_large_query_over(session_ids, None, |txn, session_ids| {
+            // Safety: placeholders is not generated using any user input except the number
+            // of session IDs, so it is safe from injection.
+            let sql_params = repeat_vars(session_ids.len());
+            let query = format!("DELETE FROM session WHERE session_id IN ({sql_params})");
+            txn.prepare(&query)?.execute(params_from_iter(session_ids.iter()))?;
+            Ok(Vec::<()>::new())
+        })
+        .await?;
+
+        Ok(())
+    }
+
     async fn get_inbound_group_session(
         &self,
         session_id: Key,
@@ -514,6 +709,29 @@ trait SqliteObjectCryptoStoreExt: SqliteAsyncConnExt {
             .await?)
     }
 
+    async fn get_inbound_group_sessions_with_ids(&self) -> Result<Vec<(Vec<u8>, Vec<u8>, bool)>> {
+        Ok(self
+            .prepare("SELECT session_id, data, backed_up FROM inbound_group_session", |mut stmt| {
+                stmt.query(())?.mapped(|row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).collect()
+            })
+            .await?)
+    }
+
+    async fn delete_inbound_group_sessions(&self, session_ids: Vec<Key>) -> Result<()> {
+        self.chunk_large_query_over(session_ids, None, |txn, session_ids| {
+            // Safety: placeholders is not generated using any user input except the number
+            // of session IDs, so it is safe from injection.
+            let sql_params = repeat_vars(session_ids.len());
+            let query =
+                format!("DELETE FROM inbound_group_session WHERE session_id IN ({sql_params})");
+            txn.prepare(&query)?.execute(params_from_iter(session_ids.iter()))?;
+            Ok(Vec::<()>::new())
+        })
+        .await?;
+
+        Ok(())
+    }
+
     async fn get_inbound_group_session_counts(
         &self,
         _backup_version: Option<&str>,
@@ -650,6 +868,14 @@ trait SqliteObjectCryptoStoreExt: SqliteAsyncConnExt {
             > 0)
     }
 
+    async fn get_olm_hashes(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT data FROM olm_hash", |mut stmt| {
+                stmt.query(())?.mapped(|row| row.get(0)).collect()
+            })
+            .await?)
+    }
+
     async fn get_tracked_users(&self) -> Result<Vec<Vec<u8>>> {
         Ok(self
             .prepare("SELECT data FROM tracked_user", |mut stmt| {
@@ -738,6 +964,14 @@ trait SqliteObjectCryptoStoreExt: SqliteAsyncConnExt {
             .optional()?)
     }
 
+    async fn get_all_direct_withheld_info(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT data FROM direct_withheld_info", |mut stmt| {
+                stmt.query(())?.mapped(|row| row.get(0)).collect()
+            })
+            .await?)
+    }
+
     async fn get_room_settings(&self, room_id: Key) -> Result<Option<Vec<u8>>> {
         Ok(self
             .query_row("SELECT data FROM room_settings WHERE room_id = ?", (room_id,), |row| {
@@ -761,6 +995,14 @@ trait SqliteObjectCryptoStoreExt: SqliteAsyncConnExt {
             .await
             .optional()?)
     }
+
+    async fn get_all_received_room_key_bundles(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .prepare("SELECT bundle_data FROM received_room_key_bundle", |mut stmt| {
+                stmt.query(())?.mapped(|row| row.get(0)).collect()
+            })
+            .await?)
+    }
 }
 
 #[async_trait]
@@ -796,6 +1038,8 @@ impl CryptoStore for SqliteCryptoStore {
     }
 
     async fn save_pending_changes(&self, changes: PendingChanges) -> Result<()> {
+        self.ensure_writable()?;
+
         // Serialize calls to `save_pending_changes`; there are multiple await points
         // below, and we're pickling data as we go, so we don't want to
         // invalidate data we've previously read and overwrite it in the store.
@@ -826,6 +1070,8 @@ impl CryptoStore for SqliteCryptoStore {
     }
 
     async fn save_changes(&self, changes: Changes) -> Result<()> {
+        self.ensure_writable()?;
+
         // Serialize calls to `save_changes`; there are multiple await points below, and
         // we're pickling data as we go, so we don't want to invalidate data
         // we've previously read and overwrite it in the store.
@@ -984,6 +1230,8 @@ impl CryptoStore for SqliteCryptoStore {
         sessions: Vec<InboundGroupSession>,
         backed_up_to_version: Option<&str>,
     ) -> matrix_sdk_crypto::store::Result<(), Self::Error> {
+        self.ensure_writable()?;
+
         // Sanity-check that the data in the sessions corresponds to backed_up_version
         sessions.iter().for_each(|s| {
             let backed_up = s.backed_up();
@@ -1023,6 +1271,21 @@ impl CryptoStore for SqliteCryptoStore {
         }
     }
 
+    async fn get_all_sessions(&self) -> Result<Vec<Session>> {
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+
+        self.acquire()
+            .await?
+            .get_all_sessions()
+            .await?
+            .into_iter()
+            .map(|bytes| {
+                let pickle = self.deserialize_value(&bytes)?;
+                Session::from_pickle(device_keys.clone(), pickle).map_err(|_| Error::AccountUnset)
+            })
+            .collect()
+    }
+
     #[instrument(skip(self))]
     async fn get_inbound_group_session(
         &self,
@@ -1057,6 +1320,71 @@ impl CryptoStore for SqliteCryptoStore {
             .collect()
     }
 
+    async fn remove_unreadable_inbound_group_sessions(&self, dry_run: bool) -> Result<usize> {
+        if !dry_run {
+            self.ensure_writable()?;
+        }
+
+        let conn = self.acquire().await?;
+
+        let unreadable: Vec<_> = conn
+            .get_inbound_group_sessions_with_ids()
+            .await?
+            .into_iter()
+            .filter(|(_, value, backed_up)| {
+                self.deserialize_and_unpickle_inbound_group_session(value.clone(), *backed_up)
+                    .is_err()
+            })
+            .map(|(session_id, _, _)| Key::Plain(session_id))
+            .collect();
+
+        let count = unreadable.len();
+
+        if count > 0 {
+            warn!(count, dry_run, "Found unreadable inbound group sessions");
+
+            if !dry_run {
+                conn.delete_inbound_group_sessions(unreadable).await?;
+            }
+        }
+
+        Ok(count)
+    }
+
+    async fn remove_unreadable_sessions(&self, sender_key: &str, dry_run: bool) -> Result<usize> {
+        if !dry_run {
+            self.ensure_writable()?;
+        }
+
+        let device_keys = self.get_own_device().await?.as_device_keys().clone();
+        let conn = self.acquire().await?;
+
+        let unreadable: Vec<_> = conn
+            .get_sessions_with_ids_for_sender_key(self.encode_key("session", sender_key.as_bytes()))
+            .await?
+            .into_iter()
+            .filter(|(_, value)| {
+                self.deserialize_value(value)
+                    .ok()
+                    .and_then(|pickle| Session::from_pickle(device_keys.clone(), pickle).ok())
+                    .is_none()
+            })
+            .map(|(session_id, _)| Key::Plain(session_id))
+            .collect();
+
+        let count = unreadable.len();
+
+        if count > 0 {
+            warn!(count, dry_run, sender_key, "Found unreadable Olm sessions");
+
+            if !dry_run {
+                conn.delete_sessions(unreadable).await?;
+            }
+        }
+
+        Ok(count)
+    }
+
     async fn get_inbound_group_sessions_for_device_batch(
         &self,
         sender_key: Curve25519PublicKey,
@@ -1110,6 +1438,8 @@ impl CryptoStore for SqliteCryptoStore {
         _backup_version: &str,
         session_ids: &[(&RoomId, &str)],
     ) -> Result<()> {
+        self.ensure_writable()?;
+
         Ok(self
             .acquire()
             .await?
@@ -1123,6 +1453,8 @@ impl CryptoStore for SqliteCryptoStore {
     }
 
     async fn reset_backup_state(&self) -> Result<()> {
+        self.ensure_writable()?;
+
         Ok(self.acquire().await?.reset_inbound_group_session_backup_state().await?)
     }
 
@@ -1154,6 +1486,8 @@ impl CryptoStore for SqliteCryptoStore {
     }
 
     async fn delete_dehydrated_device_pickle_key(&self) -> Result<(), Self::Error> {
+        self.ensure_writable()?;
+
         let conn = self.acquire().await?;
         conn.clear_kv(DEHYDRATED_DEVICE_PICKLE_KEY).await?;
 
@@ -1192,6 +1526,8 @@ impl CryptoStore for SqliteCryptoStore {
     }
 
     async fn save_tracked_users(&self, tracked_users: &[(&UserId, bool)]) -> Result<()> {
+        self.ensure_writable()?;
+
         let users: Vec<(Key, Vec<u8>)> = tracked_users
             .iter()
             .map(|(u, d)| {
@@ -1266,6 +1602,16 @@ impl CryptoStore for SqliteCryptoStore {
         Ok(self.acquire().await?.has_olm_hash(value).await?)
     }
 
+    async fn get_olm_message_hashes(&self) -> Result<Vec<matrix_sdk_crypto::olm::OlmMessageHash>> {
+        self.acquire()
+            .await?
+            .get_olm_hashes()
+            .await?
+            .iter()
+            .map(|value| Ok(rmp_serde::from_slice(value)?))
+            .collect()
+    }
+
     async fn get_outgoing_secret_requests(
         &self,
         request_id: &TransactionId,
@@ -1307,7 +1653,19 @@ impl CryptoStore for SqliteCryptoStore {
             .collect()
     }
 
+    async fn get_all_outgoing_secret_requests(&self) -> Result<Vec<GossipRequest>> {
+        self.acquire()
+            .await?
+            .get_outgoing_secret_requests()
+            .await?
+            .iter()
+            .map(|(value, sent_out)| self.deserialize_key_request(value, *sent_out))
+            .collect()
+    }
+
     async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
+        self.ensure_writable()?;
+
         let request_id = self.encode_key("key_requests", request_id.as_bytes());
         Ok(self.acquire().await?.delete_key_request(request_id).await?)
     }
@@ -1328,6 +1686,8 @@ impl CryptoStore for SqliteCryptoStore {
     }
 
     async fn delete_secrets_from_inbox(&self, secret_name: &SecretName) -> Result<()> {
+        self.ensure_writable()?;
+
         let secret_name = self.encode_key("secrets", secret_name.to_string());
         self.acquire().await?.delete_secrets_from_inbox(secret_name).await
     }
@@ -1351,6 +1711,16 @@ impl CryptoStore for SqliteCryptoStore {
             .transpose()
     }
 
+    async fn get_all_withheld_info(&self) -> Result<Vec<RoomKeyWithheldEvent>> {
+        self.acquire()
+            .await?
+            .get_all_direct_withheld_info()
+            .await?
+            .iter()
+            .map(|value| self.deserialize_json(value))
+            .collect()
+    }
+
     async fn get_room_settings(&self, room_id: &RoomId) -> Result<Option<RoomSettings>> {
         let room_id = self.encode_key("room_settings", room_id.as_bytes());
         let Some(value) = self.acquire().await?.get_room_settings(room_id).await? else {
@@ -1377,6 +1747,16 @@ impl CryptoStore for SqliteCryptoStore {
             .transpose()
     }
 
+    async fn get_all_received_room_key_bundle_data(&self) -> Result<Vec<StoredRoomKeyBundleData>> {
+        self.acquire()
+            .await?
+            .get_all_received_room_key_bundles()
+            .await?
+            .iter()
+            .map(|value| self.deserialize_value(value))
+            .collect()
+    }
+
     async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
         let Some(serialized) = self.acquire().await?.get_kv(key).await? else {
             return Ok(None);
@@ -1392,6 +1772,8 @@ impl CryptoStore for SqliteCryptoStore {
     }
 
     async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.ensure_writable()?;
+
         let serialized = if let Some(cipher) = &self.store_cipher {
             let encrypted = cipher.encrypt_value_data(value)?;
             rmp_serde::to_vec_named(&encrypted)?
@@ -1404,6 +1786,8 @@ impl CryptoStore for SqliteCryptoStore {
     }
 
     async fn remove_custom_value(&self, key: &str) -> Result<()> {
+        self.ensure_writable()?;
+
         let key = key.to_owned();
         self.acquire()
             .await?
@@ -1426,7 +1810,7 @@ impl CryptoStore for SqliteCryptoStore {
         let expiration_ts = now_ts + lease_duration_ms as u64;
 
         let num_touched = self
-            .acquire()
+            .acquire_lease_connection()
             .await?
             .with_transaction(move |txn| {
                 txn.execute(
@@ -1455,16 +1839,36 @@ impl CryptoStore for SqliteCryptoStore {
             Ok(None)
         }
     }
+
+    async fn storage_usage(&self, room_ids: &[OwnedRoomId]) -> Result<StorageUsage> {
+        let conn = self.acquire().await?;
+        storage_usage(self, &conn, STORAGE_USAGE_TABLES, room_ids).await
+    }
+
+    async fn compact(&self) -> Result<()> {
+        self.ensure_writable()?;
+        self.acquire().await?.compact().await
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::Path;
+    use std::{path::Path, sync::Arc};
 
-    use matrix_sdk_common::deserialized_responses::WithheldCode;
+    use matrix_sdk_common::{deserialized_responses::WithheldCode, storage_usage::StorageCategory};
     use matrix_sdk_crypto::{
-        cryptostore_integration_tests, cryptostore_integration_tests_time, olm::SenderDataType,
-        store::CryptoStore,
+        cryptostore_integration_tests, cryptostore_integration_tests_time,
+        olm::{Account, SenderData, SenderDataType},
+        store::{
+            integrity::{CryptoStoreVerifier, IntegrityIssue, IntegritySeverity},
+            types::{Changes, DeviceChanges, PendingChanges},
+            CryptoStore,
+        },
+        DeviceData,
     };
     use matrix_sdk_test::async_test;
     use once_cell::sync::Lazy;
@@ -1474,7 +1878,10 @@ mod tests {
     use tokio::fs;
 
     use super::SqliteCryptoStore;
-    use crate::SqliteStoreConfig;
+    use crate::{
+        utils::{EncryptableStore, SqliteAsyncConnExt},
+        SqliteStoreConfig,
+    };
 
     static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
 
@@ -1520,6 +1927,162 @@ mod tests {
         assert_eq!(store.pool.status().max_size, 42);
     }
 
+    #[async_test]
+    async fn test_storage_usage() {
+        let store = get_store("storage_usage", Some("default_test_password"), true).await;
+
+        let room_id = room_id!("!room:localhost");
+        let other_room_id = room_id!("!other_room:localhost");
+        let room_ids = [room_id.to_owned(), other_room_id.to_owned()];
+
+        let account = Account::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let (_, session) = account
+            .create_group_session_pair(room_id, Default::default(), SenderData::unknown())
+            .await
+            .unwrap();
+
+        store.save_pending_changes(PendingChanges { account: Some(account) }).await.unwrap();
+        store
+            .save_changes(Changes { inbound_group_sessions: vec![session], ..Default::default() })
+            .await
+            .unwrap();
+
+        let usage = store.storage_usage(&room_ids).await.unwrap();
+        assert!(usage.total_bytes.unwrap() > 0);
+        assert!(usage.reclaimable_bytes.is_some());
+
+        // The room key is the only data of the room, even though the room IDs are
+        // hashed.
+        let room_bytes = usage.room(room_id);
+        assert!(room_bytes > 0);
+        assert!(usage.category(StorageCategory::Sessions) >= room_bytes);
+        assert_eq!(usage.room(other_room_id), 0);
+
+        // The account is stored with the other key-value data.
+        assert!(usage.category(StorageCategory::Other) > 0);
+    }
+
+    #[async_test]
+    async fn test_read_only_leased_lock() {
+        let path = TMP_DIR.path().join("test_read_only_leased_lock");
+        let store = SqliteCryptoStore::open(&path, None).await.unwrap();
+
+        let read_only_store =
+            SqliteCryptoStore::open_with_config(SqliteStoreConfig::new(&path).read_only(true))
+                .await
+                .unwrap();
+        assert!(read_only_store.is_read_only());
+        assert!(!store.is_read_only());
+
+        // The data can't be modified.
+        read_only_store.set_custom_value("key", b"value".to_vec()).await.unwrap_err();
+
+        // But the leased locks can be taken, and are shared with the read-write store.
+        assert!(read_only_store.try_take_leased_lock(30_000, "key", "reader").await.unwrap());
+        assert!(!store.try_take_leased_lock(30_000, "key", "writer").await.unwrap());
+        assert!(read_only_store.try_take_leased_lock(30_000, "key", "reader").await.unwrap());
+    }
+
+    #[async_test]
+    async fn test_remove_unreadable_sessions() {
+        let store = Arc::new(get_store("remove_unreadable_sessions", None, true).await);
+
+        let alice = Account::with_device_id(user_id!("@alice:localhost"), device_id!("ALICE"));
+        let mut bob = Account::with_device_id(user_id!("@bob:localhost"), device_id!("BOB"));
+
+        bob.generate_one_time_keys(1);
+        let one_time_key = *bob.one_time_keys().values().next().unwrap();
+        let olm_session = alice.create_outbound_session_helper(
+            Default::default(),
+            bob.identity_keys().curve25519,
+            one_time_key,
+            false,
+            alice.device_keys(),
+        );
+        let sender_key = olm_session.sender_key.to_base64();
+
+        let room_id = room_id!("!test:localhost");
+        let (_, readable) = alice
+            .create_group_session_pair(room_id, Default::default(), SenderData::unknown())
+            .await
+            .unwrap();
+        let (_, unreadable) = alice
+            .create_group_session_pair(room_id, Default::default(), SenderData::unknown())
+            .await
+            .unwrap();
+
+        store
+            .save_pending_changes(PendingChanges { account: Some(alice.deep_clone()) })
+            .await
+            .unwrap();
+        store
+            .save_changes(Changes {
+                devices: DeviceChanges {
+                    new: vec![DeviceData::from_account(&alice), DeviceData::from_account(&bob)],
+                    ..Default::default()
+                },
+                sessions: vec![olm_session],
+                inbound_group_sessions: vec![readable.clone(), unreadable.clone()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        store.save_tracked_users(&[(bob.user_id(), false)]).await.unwrap();
+
+        // Corrupt one of the inbound group sessions, and the Olm session.
+        let conn = store.acquire().await.unwrap();
+        conn.execute(
+            "UPDATE inbound_group_session SET data = ?1 WHERE session_id = ?2",
+            (
+                b"corrupted".to_vec(),
+                store.encode_key("inbound_group_session", unreadable.session_id()),
+            ),
+        )
+        .await
+        .unwrap();
+        conn.execute("UPDATE session SET data = ?1", (b"corrupted".to_vec(),)).await.unwrap();
+
+        let verifier = CryptoStoreVerifier::new(store.clone());
+        let report = verifier.check().await.unwrap();
+
+        assert_eq!(report.max_severity(), Some(IntegritySeverity::Error));
+        assert!(report
+            .issues
+            .iter()
+            .any(|issue| matches!(issue, IntegrityIssue::UnreadableInboundGroupSessions { .. })));
+        assert!(report.issues.iter().any(|issue| matches!(
+            issue,
+            IntegrityIssue::UnreadableOlmSessions { sender_key: key, .. }
+                if key.to_base64() == sender_key
+        )));
+        assert!(report.issues.iter().all(IntegrityIssue::is_repairable));
+
+        // A dry run counts the unreadable sessions, without removing them.
+        let repair_report = verifier.clone().dry_run(true).repair(&report).await.unwrap();
+
+        assert!(repair_report.dry_run);
+        assert!(repair_report.unrepaired.is_empty());
+        assert_eq!(repair_report.removed_inbound_group_sessions, 1);
+        assert_eq!(repair_report.removed_olm_sessions, 1);
+        assert_eq!(store.inbound_group_session_counts(None).await.unwrap().total, 2);
+        store.get_sessions(&sender_key).await.unwrap_err();
+
+        // A real run removes the unreadable sessions only.
+        let repair_report = verifier.repair(&report).await.unwrap();
+
+        assert!(!repair_report.dry_run);
+        assert!(repair_report.unrepaired.is_empty());
+        assert_eq!(repair_report.removed_inbound_group_sessions, 1);
+        assert_eq!(repair_report.removed_olm_sessions, 1);
+
+        let sessions = store.get_inbound_group_sessions().await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].session_id(), readable.session_id());
+        assert!(store.get_sessions(&sender_key).await.unwrap().is_none());
+
+        assert!(verifier.check().await.unwrap().is_healthy());
+    }
+
     /// Test that we didn't regress in our storage layer by loading data from a
     /// pre-filled database, or in other words use a test vector for this.
     #[async_test]
@@ -65,6 +65,26 @@ pub enum OpenStoreError {
     /// Failed to save the store cipher to the DB.
     #[error("Failed to save the store cipher to the DB: {0}")]
     SaveCipher(#[source] rusqlite::Error),
+
+    /// The database must be migrated, which is not possible in read-only
+    /// mode.
+    #[error(
+        "The database must be migrated from version {version} to version {expected}, which is \
+         not possible in read-only mode"
+    )]
+    ReadOnlyMigration {
+        /// The current version of the database.
+        version: u8,
+        /// The version expected by the store.
+        expected: u8,
+    },
+
+    /// A passphrase was given but the database doesn't contain a store cipher,
+    /// which can't be created in read-only mode.
+    #[error(
+        "The database doesn't contain a store cipher, which can't be created in read-only mode"
+    )]
+    MissingCipher,
 }
 
 #[derive(Debug, Error)]
@@ -107,6 +127,9 @@ pub enum Error {
 
     #[error("The store contains invalid data: {details}")]
     InvalidData { details: String },
+
+    #[error("The store was opened in read-only mode")]
+    ReadOnly,
 }
 
 macro_rules! impl_from {
@@ -135,6 +158,21 @@ impl From<rusqlite::Error> for Error {
     }
 }
 
+impl Error {
+    /// Whether the database was busy, i.e. locked by another connection, e.g.
+    /// from another process.
+    fn is_busy(&self) -> bool {
+        matches!(
+            self,
+            Error::Sqlite(rusqlite::Error::SqliteFailure(ffi_error, _))
+                if matches!(
+                    ffi_error.code,
+                    rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+                )
+        )
+    }
+}
+
 impl_from!(PoolError => Error::Pool);
 impl_from!(rmp_serde::encode::Error => Error::Encode);
 impl_from!(rmp_serde::decode::Error => Error::Decode);
@@ -143,6 +181,10 @@ impl_from!(matrix_sdk_store_encryption::Error => Error::Encryption);
 #[cfg(feature = "crypto-store")]
 impl From<Error> for CryptoStoreError {
     fn from(e: Error) -> Self {
+        if e.is_busy() {
+            return CryptoStoreError::Busy;
+        }
+
         CryptoStoreError::backend(e)
     }
 }
@@ -154,6 +196,7 @@ impl From<Error> for StateStoreError {
             Error::Json(e) => StateStoreError::Json(e),
             Error::Encryption(e) => StateStoreError::Encryption(e),
             Error::Redaction(e) => StateStoreError::Redaction(e),
+            e if e.is_busy() => StateStoreError::Busy,
             e => StateStoreError::backend(e),
         }
     }
@@ -164,6 +207,7 @@ impl From<Error> for EventCacheStoreError {
     fn from(e: Error) -> Self {
         match e {
             Error::Encryption(e) => EventCacheStoreError::Encryption(e),
+            e if e.is_busy() => EventCacheStoreError::Busy,
             e => EventCacheStoreError::backend(e),
         }
     }
@@ -36,12 +36,13 @@ use matrix_sdk_base::{
         Position, RawChunk, Update,
     },
     media::{MediaRequestParameters, UniqueKey},
+    storage_usage::{StorageCategory, StorageUsage},
     timer,
 };
 use matrix_sdk_store_encryption::StoreCipher;
 use ruma::{
     events::relation::RelationType, time::SystemTime, EventId, MilliSecondsSinceUnixEpoch, MxcUri,
-    OwnedEventId, RoomId,
+    OwnedEventId, OwnedRoomId, RoomId,
 };
 use rusqlite::{params_from_iter, OptionalExtension, ToSql, Transaction, TransactionBehavior};
 use tokio::{
@@ -53,8 +54,9 @@ use tracing::{debug, error, instrument, trace};
 use crate::{
     error::{Error, Result},
     utils::{
-        repeat_vars, time_to_timestamp, EncryptableStore, Key, SqliteAsyncConnExt,
-        SqliteKeyValueStoreAsyncConnExt, SqliteKeyValueStoreConnExt, SqliteTransactionExt,
+        create_lease_pool, database_path, repeat_vars, storage_usage, time_to_timestamp,
+        EncryptableStore, Key, SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt,
+        SqliteKeyValueStoreConnExt, SqliteTransactionExt, StorageUsageTable,
     },
     OpenStoreError, SqliteStoreConfig,
 };
@@ -86,6 +88,41 @@ const CHUNK_TYPE_EVENT_TYPE_STRING: &str = "E";
 /// database.
 const CHUNK_TYPE_GAP_TYPE_STRING: &str = "G";
 
+/// The tables measured by [`EventCacheStore::storage_usage`].
+///
+/// The room IDs are encoded like the identifiers of the linked chunks of the
+/// rooms.
+const STORAGE_USAGE_TABLES: &[StorageUsageTable] = &[
+    StorageUsageTable {
+        name: "events",
+        value_column: "content",
+        room_id_column: Some("room_id"),
+        room_id_key_table: keys::LINKED_CHUNKS,
+        category: StorageCategory::Events,
+    },
+    StorageUsageTable {
+        name: "gap_chunks",
+        value_column: "prev_token",
+        room_id_column: Some("linked_chunk_id"),
+        room_id_key_table: keys::LINKED_CHUNKS,
+        category: StorageCategory::Events,
+    },
+    StorageUsageTable {
+        name: keys::MEDIA,
+        value_column: "data",
+        room_id_column: None,
+        room_id_key_table: keys::MEDIA,
+        category: StorageCategory::Media,
+    },
+    StorageUsageTable {
+        name: "kv",
+        value_column: "value",
+        room_id_column: None,
+        room_id_key_table: "kv",
+        category: StorageCategory::Other,
+    },
+];
+
 /// An SQLite-based event cache store.
 #[derive(Clone)]
 pub struct SqliteEventCacheStore {
@@ -101,6 +138,14 @@ pub struct SqliteEventCacheStore {
     write_connection: Arc<Mutex<SqliteAsyncConn>>,
 
     media_service: MediaService,
+
+    /// Whether the database was opened in read-only mode, in which case
+    /// [`Self::write`] always fails.
+    read_only: bool,
+
+    /// The pool of the read-write connection used to take the leased locks,
+    /// if the database was opened in read-only mode.
+    lease_pool: Option<SqlitePool>,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -133,17 +178,38 @@ impl SqliteEventCacheStore {
 
         let _timer = timer!("open_with_config");
 
-        let SqliteStoreConfig { path, passphrase, pool_config, runtime_config } = config;
-
-        fs::create_dir_all(&path).await.map_err(OpenStoreError::CreateDir)?;
+        let SqliteStoreConfig {
+            path,
+            passphrase,
+            pool_config,
+            mut runtime_config,
+            read_only,
+            upgrade_passphrase_kdf,
+        } = config;
+
+        if read_only {
+            // The database can't be modified, so it can't be optimized.
+            runtime_config.optimize = false;
+        } else {
+            fs::create_dir_all(&path).await.map_err(OpenStoreError::CreateDir)?;
+        }
 
-        let mut config = deadpool_sqlite::Config::new(path.join(DATABASE_NAME));
+        let mut config =
+            deadpool_sqlite::Config::new(database_path(&path, DATABASE_NAME, read_only));
         config.pool = Some(pool_config);
 
         let pool = config.create_pool(Runtime::Tokio1)?;
 
-        let this = Self::open_with_pool(pool, passphrase.as_deref()).await?;
-        this.write().await?.apply_runtime_config(runtime_config).await?;
+        let mut this =
+            Self::open_with_pool(pool, passphrase.as_deref(), read_only, upgrade_passphrase_kdf)
+                .await?;
+
+        if read_only {
+            this.read().await?.apply_runtime_config(runtime_config).await?;
+            this.lease_pool = Some(create_lease_pool(&path, DATABASE_NAME)?);
+        } else {
+            this.write().await?.apply_runtime_config(runtime_config).await?;
+        }
 
         Ok(this)
     }
@@ -153,15 +219,34 @@ impl SqliteEventCacheStore {
     async fn open_with_pool(
         pool: SqlitePool,
         passphrase: Option<&str>,
+        read_only: bool,
+        upgrade_passphrase_kdf: bool,
     ) -> Result<Self, OpenStoreError> {
         let conn = pool.get().await?;
 
         let version = conn.db_version().await?;
-        run_migrations(&conn, version).await?;
 
-        let store_cipher = match passphrase {
-            Some(p) => Some(Arc::new(conn.get_or_create_store_cipher(p).await?)),
-            None => None,
+        let store_cipher = if read_only {
+            if version < DATABASE_VERSION {
+                return Err(OpenStoreError::ReadOnlyMigration {
+                    version,
+                    expected: DATABASE_VERSION,
+                });
+            }
+
+            match passphrase {
+                Some(p) => Some(Arc::new(conn.get_store_cipher(p).await?)),
+                None => None,
+            }
+        } else {
+            run_migrations(&conn, version).await?;
+
+            match passphrase {
+                Some(p) => Some(Arc::new(
+                    conn.get_or_create_store_cipher(p, upgrade_passphrase_kdf).await?,
+                )),
+                None => None,
+            }
         };
 
         let media_service = MediaService::new();
@@ -175,6 +260,8 @@ impl SqliteEventCacheStore {
             // Use `conn` as our selected write connections.
             write_connection: Arc::new(Mutex::new(conn)),
             media_service,
+            read_only,
+            lease_pool: None,
         })
     }
 
@@ -198,6 +285,10 @@ impl SqliteEventCacheStore {
     // Acquire a connection for executing write operations.
     #[instrument(skip_all)]
     async fn write(&self) -> Result<OwnedMutexGuard<SqliteAsyncConn>> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
         trace!("Taking a `write` connection");
         let _timer = timer!("connection");
 
@@ -473,23 +564,26 @@ impl EventCacheStore for SqliteEventCacheStore {
         let now: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
         let expiration = now + lease_duration_ms as u64;
 
-        let num_touched = self
-            .write()
-            .await?
-            .with_transaction(move |txn| {
-                txn.execute(
-                    "INSERT INTO lease_locks (key, holder, expiration)
-                    VALUES (?1, ?2, ?3)
-                    ON CONFLICT (key)
-                    DO
-                        UPDATE SET holder = ?2, expiration = ?3
-                        WHERE holder = ?2
-                        OR expiration < ?4
-                ",
-                    (key, holder, expiration, now),
-                )
-            })
-            .await?;
+        let take_lock = move |txn: &Transaction<'_>| {
+            txn.execute(
+                "INSERT INTO lease_locks (key, holder, expiration)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (key)
+                DO
+                    UPDATE SET holder = ?2, expiration = ?3
+                    WHERE holder = ?2
+                    OR expiration < ?4
+            ",
+                (key, holder, expiration, now),
+            )
+        };
+
+        // The leased locks are taken even if the database was opened in read-only
+        // mode, with a dedicated read-write connection.
+        let num_touched = match &self.lease_pool {
+            Some(lease_pool) => lease_pool.get().await?.with_transaction(take_lock).await?,
+            None => self.write().await?.with_transaction(take_lock).await?,
+        };
 
         Ok(num_touched == 1)
     }
@@ -1417,6 +1511,21 @@ impl EventCacheStore for SqliteEventCacheStore {
 
         self.media_service.clean_up_media_cache(self).await
     }
+
+    #[instrument(skip_all)]
+    async fn storage_usage(&self, room_ids: &[OwnedRoomId]) -> Result<StorageUsage, Self::Error> {
+        let _timer = timer!("method");
+
+        let conn = self.read().await?;
+        storage_usage(self, &conn, STORAGE_USAGE_TABLES, room_ids).await
+    }
+
+    #[instrument(skip_all)]
+    async fn compact(&self) -> Result<(), Self::Error> {
+        let _timer = timer!("method");
+
+        self.write().await?.compact().await
+    }
 }
 
 #[cfg_attr(target_family = "wasm", async_trait(?Send))]
@@ -1497,6 +1606,22 @@ impl EventCacheStoreMedia for SqliteEventCacheStore {
         let format = self.encode_key(keys::MEDIA, request.format.unique_key());
         let timestamp = time_to_timestamp(current_time);
 
+        if self.read_only {
+            // The last access can't be updated.
+            let data = self
+                .read()
+                .await?
+                .query_row(
+                    "SELECT data FROM media WHERE uri = ? AND format = ?",
+                    (uri, format),
+                    |row| row.get::<_, Vec<u8>>(0),
+                )
+                .await
+                .optional()?;
+
+            return data.map(|v| self.decode_value(&v).map(Into::into)).transpose();
+        }
+
         let conn = self.write().await?;
         let data = conn
             .with_transaction::<_, rusqlite::Error, _>(move |txn| {
@@ -1528,6 +1653,20 @@ impl EventCacheStoreMedia for SqliteEventCacheStore {
         let uri = self.encode_key(keys::MEDIA, uri);
         let timestamp = time_to_timestamp(current_time);
 
+        if self.read_only {
+            // The last access can't be updated.
+            let data = self
+                .read()
+                .await?
+                .query_row("SELECT data FROM media WHERE uri = ?", (uri,), |row| {
+                    row.get::<_, Vec<u8>>(0)
+                })
+                .await
+                .optional()?;
+
+            return data.map(|v| self.decode_value(&v).map(Into::into)).transpose();
+        }
+
         let conn = self.write().await?;
         let data = conn
             .with_transaction::<_, rusqlite::Error, _>(move |txn| {
@@ -1874,6 +2013,7 @@ mod tests {
         event_cache_store_media_integration_tests,
         linked_chunk::{ChunkContent, ChunkIdentifier, LinkedChunkId, Position, Update},
         media::{MediaFormat, MediaRequestParameters, MediaThumbnailSettings},
+        storage_usage::StorageCategory,
     };
     use matrix_sdk_test::{async_test, DEFAULT_TEST_ROOM_ID};
     use once_cell::sync::Lazy;
@@ -1882,6 +2022,7 @@ mod tests {
 
     use super::SqliteEventCacheStore;
     use crate::{
+        error::Error,
         event_cache_store::keys,
         utils::{EncryptableStore as _, SqliteAsyncConnExt},
         SqliteStoreConfig,
@@ -1996,6 +2137,109 @@ mod tests {
         assert_eq!(contents[1], thumbnail_content, "thumbnail is not second-to-last access");
     }
 
+    #[async_test]
+    async fn test_storage_usage() {
+        let store = get_event_cache_store().await.expect("creating cache store failed");
+
+        let room_id = room_id!("!room:localhost");
+        let other_room_id = room_id!("!other_room:localhost");
+        let room_ids = [room_id.to_owned(), other_room_id.to_owned()];
+
+        store
+            .handle_linked_chunk_updates(
+                LinkedChunkId::Room(room_id),
+                vec![
+                    Update::NewItemsChunk {
+                        previous: None,
+                        new: ChunkIdentifier::new(42),
+                        next: None,
+                    },
+                    Update::PushItems {
+                        at: Position::new(ChunkIdentifier::new(42), 0),
+                        items: vec![
+                            make_test_event(room_id, "hello"),
+                            make_test_event(room_id, "world"),
+                        ],
+                    },
+                ],
+            )
+            .await
+            .unwrap();
+
+        let request = MediaRequestParameters {
+            source: MediaSource::Plain(mxc_uri!("mxc://localhost/media").to_owned()),
+            format: MediaFormat::File,
+        };
+        store
+            .add_media_content(&request, b"hello world".to_vec(), IgnoreMediaRetentionPolicy::No)
+            .await
+            .unwrap();
+
+        let usage = store.storage_usage(&room_ids).await.unwrap();
+        assert!(usage.total_bytes.unwrap() > 0);
+        assert!(usage.reclaimable_bytes.is_some());
+
+        // The events are the only data of the room.
+        let room_bytes = usage.room(room_id);
+        assert!(room_bytes > 0);
+        assert_eq!(usage.category(StorageCategory::Events), room_bytes);
+        assert_eq!(usage.room(other_room_id), 0);
+
+        // The media isn't attributed to any room.
+        assert!(usage.category(StorageCategory::Media) > 0);
+
+        // Once the events are removed and the store is compacted, there is no
+        // space left to reclaim.
+        store.clear_all_linked_chunks().await.unwrap();
+        store.compact().await.unwrap();
+
+        let usage = store.storage_usage(&room_ids).await.unwrap();
+        assert_eq!(usage.room(room_id), 0);
+        assert_eq!(usage.category(StorageCategory::Events), 0);
+        assert_eq!(usage.reclaimable_bytes, Some(0));
+    }
+
+    #[async_test]
+    async fn test_read_only() {
+        let tmpdir_path = new_event_cache_store_workspace();
+        let event_cache_store = SqliteEventCacheStore::open(&tmpdir_path, None).await.unwrap();
+
+        let uri = mxc_uri!("mxc://localhost/media");
+        let request = MediaRequestParameters {
+            source: MediaSource::Plain(uri.to_owned()),
+            format: MediaFormat::File,
+        };
+        let content: Vec<u8> = "hello world".into();
+
+        event_cache_store
+            .add_media_content(&request, content.clone(), IgnoreMediaRetentionPolicy::No)
+            .await
+            .unwrap();
+
+        let read_only_store = SqliteEventCacheStore::open_with_config(
+            SqliteStoreConfig::new(&tmpdir_path).read_only(true),
+        )
+        .await
+        .unwrap();
+
+        // The media can be read, without updating its last access.
+        assert_eq!(
+            read_only_store.get_media_content(&request).await.unwrap(),
+            Some(content.clone())
+        );
+        assert_eq!(read_only_store.get_media_content_for_uri(uri).await.unwrap(), Some(content));
+
+        // But nothing can be modified.
+        assert_matches!(read_only_store.remove_media_content(&request).await, Err(Error::ReadOnly));
+        assert_matches!(read_only_store.clear_all_linked_chunks().await, Err(Error::ReadOnly));
+        assert!(read_only_store.get_media_content(&request).await.unwrap().is_some());
+
+        // Except the leased locks, which are shared with the other processes.
+        assert!(read_only_store.try_take_leased_lock(30_000, "key", "reader").await.unwrap());
+        assert!(!event_cache_store.try_take_leased_lock(30_000, "key", "writer").await.unwrap());
+        assert!(read_only_store.try_take_leased_lock(30_000, "key", "reader").await.unwrap());
+    }
+
     #[async_test]
     async fn test_linked_chunk_new_items_chunk() {
         let store = get_event_cache_store().await.expect("creating cache store failed");
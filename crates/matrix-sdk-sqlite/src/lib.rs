@@ -54,6 +54,10 @@ pub struct SqliteStoreConfig {
     pool_config: PoolConfig,
     /// The runtime configuration to apply when opening an SQLite connection.
     runtime_config: RuntimeConfig,
+    /// Whether the database is opened in read-only mode.
+    read_only: bool,
+    /// Whether the store cipher export is upgraded to use Argon2id.
+    upgrade_passphrase_kdf: bool,
 }
 
 impl fmt::Debug for SqliteStoreConfig {
@@ -63,6 +67,8 @@ impl fmt::Debug for SqliteStoreConfig {
             .field("path", &self.path)
             .field("pool_config", &self.pool_config)
             .field("runtime_config", &self.runtime_config)
+            .field("read_only", &self.read_only)
+            .field("upgrade_passphrase_kdf", &self.upgrade_passphrase_kdf)
             .finish_non_exhaustive()
     }
 }
@@ -85,6 +91,8 @@ impl SqliteStoreConfig {
             passphrase: None,
             pool_config: PoolConfig::new(max(POOL_MINIMUM_SIZE, num_cpus::get_physical() * 4)),
             runtime_config: RuntimeConfig::default(),
+            read_only: false,
+            upgrade_passphrase_kdf: false,
         }
     }
 
@@ -184,6 +192,50 @@ impl SqliteStoreConfig {
         self.runtime_config.journal_size_limit = limit;
         self
     }
+
+    /// Open the database in read-only mode.
+    ///
+    /// This is useful for processes which only need to read the data, while
+    /// another process owns the store, e.g. a notification extension. It
+    /// avoids contention on the database locks with the other process.
+    ///
+    /// In read-only mode:
+    ///
+    /// * The database is opened with [`SQLITE_OPEN_READONLY`], so it must
+    ///   already exist,
+    /// * No migrations are run: opening the store fails with
+    ///   [`OpenStoreError::ReadOnlyMigration`] if the database must be
+    ///   migrated, and with [`OpenStoreError::MissingCipher`] if a passphrase
+    ///   is given but no store cipher exists in the database,
+    /// * The database is not optimized, regardless of
+    ///   [`SqliteStoreConfig::optimize`],
+    /// * All the methods of the stores that would modify the database return an
+    ///   error, except the ones taking the cross-process locks, which use a
+    ///   dedicated read-write connection since the locks are shared with the
+    ///   other processes.
+    ///
+    /// The default value is `false`.
+    ///
+    /// [`SQLITE_OPEN_READONLY`]: https://www.sqlite.org/c3ref/open.html
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Upgrade the key derivation function of the store cipher export to
+    /// Argon2id when the store is opened, if the export used PBKDF2.
+    ///
+    /// Once upgraded, the database can't be opened anymore by the versions of
+    /// this crate that don't support Argon2id, so this should only be enabled
+    /// once going back to such a version isn't a concern.
+    ///
+    /// This has no effect if no passphrase is given, or in read-only mode.
+    ///
+    /// The default value is `false`.
+    pub fn upgrade_passphrase_kdf(mut self, upgrade: bool) -> Self {
+        self.upgrade_passphrase_kdf = upgrade;
+        self
+    }
 }
 
 /// This type represents values to set at runtime when a database is opened.
@@ -235,6 +287,7 @@ mod tests {
         assert!(store_config.runtime_config.optimize);
         assert_eq!(store_config.runtime_config.cache_size, 2_000_000);
         assert_eq!(store_config.runtime_config.journal_size_limit, 10_000_000);
+        assert!(store_config.read_only.not());
     }
 
     #[test]
@@ -254,7 +307,8 @@ mod tests {
             .pool_max_size(42)
             .optimize(false)
             .cache_size(43)
-            .journal_size_limit(44);
+            .journal_size_limit(44)
+            .read_only(true);
 
         assert_eq!(store_config.path, PathBuf::from("foo"));
         assert_eq!(store_config.passphrase, Some("bar".to_owned()));
@@ -262,6 +316,7 @@ mod tests {
         assert!(store_config.runtime_config.optimize.not());
         assert_eq!(store_config.runtime_config.cache_size, 43);
         assert_eq!(store_config.runtime_config.journal_size_limit, 44);
+        assert!(store_config.read_only);
     }
 
     #[test]
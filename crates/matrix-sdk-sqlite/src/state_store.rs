@@ -10,6 +10,7 @@ use async_trait::async_trait;
 use deadpool_sqlite::{Object as SqliteAsyncConn, Pool as SqlitePool, Runtime};
 use matrix_sdk_base::{
     deserialized_responses::{DisplayName, RawAnySyncOrStrippedState, SyncOrStrippedState},
+    storage_usage::{StorageCategory, StorageUsage},
     store::{
         migration_helpers::RoomInfoV1, ChildTransactionId, DependentQueuedRequest,
         DependentQueuedRequestKind, QueueWedgeError, QueuedRequest, QueuedRequestKind,
@@ -43,8 +44,8 @@ use tracing::{debug, warn};
 use crate::{
     error::{Error, Result},
     utils::{
-        repeat_vars, EncryptableStore, Key, SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt,
-        SqliteKeyValueStoreConnExt,
+        database_path, repeat_vars, storage_usage, EncryptableStore, Key, SqliteAsyncConnExt,
+        SqliteKeyValueStoreAsyncConnExt, SqliteKeyValueStoreConnExt, StorageUsageTable,
     },
     OpenStoreError, SqliteStoreConfig,
 };
@@ -75,11 +76,94 @@ pub const DATABASE_NAME: &str = "matrix-sdk-state.sqlite3";
 /// the [`SqliteStateStore::run_migrations`] function.
 const DATABASE_VERSION: u8 = 13;
 
+/// The tables measured by [`StateStore::storage_usage`].
+const STORAGE_USAGE_TABLES: &[StorageUsageTable] = &[
+    StorageUsageTable {
+        name: keys::KV_BLOB,
+        value_column: "value",
+        room_id_column: None,
+        room_id_key_table: keys::KV_BLOB,
+        category: StorageCategory::Other,
+    },
+    StorageUsageTable {
+        name: keys::ROOM_INFO,
+        value_column: "data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: keys::ROOM_INFO,
+        category: StorageCategory::State,
+    },
+    StorageUsageTable {
+        name: keys::STATE_EVENT,
+        value_column: "data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: keys::STATE_EVENT,
+        category: StorageCategory::State,
+    },
+    StorageUsageTable {
+        name: keys::GLOBAL_ACCOUNT_DATA,
+        value_column: "data",
+        room_id_column: None,
+        room_id_key_table: keys::GLOBAL_ACCOUNT_DATA,
+        category: StorageCategory::AccountData,
+    },
+    StorageUsageTable {
+        name: keys::ROOM_ACCOUNT_DATA,
+        value_column: "data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: keys::ROOM_ACCOUNT_DATA,
+        category: StorageCategory::AccountData,
+    },
+    StorageUsageTable {
+        name: keys::MEMBER,
+        value_column: "data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: keys::MEMBER,
+        category: StorageCategory::Members,
+    },
+    StorageUsageTable {
+        name: keys::PROFILE,
+        value_column: "data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: keys::PROFILE,
+        category: StorageCategory::Members,
+    },
+    StorageUsageTable {
+        name: keys::DISPLAY_NAME,
+        value_column: "data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: keys::DISPLAY_NAME,
+        category: StorageCategory::Members,
+    },
+    StorageUsageTable {
+        name: keys::RECEIPT,
+        value_column: "data",
+        room_id_column: Some("room_id"),
+        room_id_key_table: keys::RECEIPT,
+        category: StorageCategory::Receipts,
+    },
+    StorageUsageTable {
+        name: keys::SEND_QUEUE,
+        value_column: "content",
+        room_id_column: Some("room_id"),
+        room_id_key_table: keys::SEND_QUEUE,
+        category: StorageCategory::SendQueue,
+    },
+    StorageUsageTable {
+        name: keys::DEPENDENTS_SEND_QUEUE,
+        value_column: "content",
+        room_id_column: Some("room_id"),
+        room_id_key_table: keys::DEPENDENTS_SEND_QUEUE,
+        category: StorageCategory::SendQueue,
+    },
+];
+
 /// An SQLite-based state store.
 #[derive(Clone)]
 pub struct SqliteStateStore {
     store_cipher: Option<Arc<StoreCipher>>,
     pool: SqlitePool,
+    /// Whether the database was opened in read-only mode.
+    read_only: bool,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -101,16 +185,35 @@ impl SqliteStateStore {
 
     /// Open the SQLite-based state store with the config open config.
     pub async fn open_with_config(config: SqliteStoreConfig) -> Result<Self, OpenStoreError> {
-        let SqliteStoreConfig { path, passphrase, pool_config, runtime_config } = config;
-
-        fs::create_dir_all(&path).await.map_err(OpenStoreError::CreateDir)?;
+        let SqliteStoreConfig {
+            path,
+            passphrase,
+            pool_config,
+            mut runtime_config,
+            read_only,
+            upgrade_passphrase_kdf,
+        } = config;
+
+        if read_only {
+            // The database can't be modified, so it can't be optimized.
+            runtime_config.optimize = false;
+        } else {
+            fs::create_dir_all(&path).await.map_err(OpenStoreError::CreateDir)?;
+        }
 
-        let mut config = deadpool_sqlite::Config::new(path.join(DATABASE_NAME));
+        let mut config =
+            deadpool_sqlite::Config::new(database_path(&path, DATABASE_NAME, read_only));
         config.pool = Some(pool_config);
 
         let pool = config.create_pool(Runtime::Tokio1)?;
 
-        let this = Self::open_with_pool(pool, passphrase.as_deref()).await?;
+        let this = Self::open_with_pool_inner(
+            pool,
+            passphrase.as_deref(),
+            read_only,
+            upgrade_passphrase_kdf,
+        )
+        .await?;
         this.pool.get().await?.apply_runtime_config(runtime_config).await?;
 
         Ok(this)
@@ -121,21 +224,48 @@ impl SqliteStateStore {
     pub async fn open_with_pool(
         pool: SqlitePool,
         passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        Self::open_with_pool_inner(pool, passphrase, false, false).await
+    }
+
+    async fn open_with_pool_inner(
+        pool: SqlitePool,
+        passphrase: Option<&str>,
+        read_only: bool,
+        upgrade_passphrase_kdf: bool,
     ) -> Result<Self, OpenStoreError> {
         let conn = pool.get().await?;
 
         let mut version = conn.db_version().await?;
 
+        if read_only {
+            if version < DATABASE_VERSION {
+                return Err(OpenStoreError::ReadOnlyMigration {
+                    version,
+                    expected: DATABASE_VERSION,
+                });
+            }
+
+            let store_cipher = match passphrase {
+                Some(p) => Some(Arc::new(conn.get_store_cipher(p).await?)),
+                None => None,
+            };
+
+            return Ok(Self { store_cipher, pool, read_only });
+        }
+
         if version == 0 {
             init(&conn).await?;
             version = 1;
         }
 
         let store_cipher = match passphrase {
-            Some(p) => Some(Arc::new(conn.get_or_create_store_cipher(p).await?)),
+            Some(p) => {
+                Some(Arc::new(conn.get_or_create_store_cipher(p, upgrade_passphrase_kdf).await?))
+            }
             None => None,
         };
-        let this = Self { store_cipher, pool };
+        let this = Self { store_cipher, pool, read_only };
         this.run_migrations(&conn, version, None).await?;
 
         Ok(this)
@@ -419,6 +549,15 @@ impl SqliteStateStore {
         Ok(self.pool.get().await?)
     }
 
+    /// Return an error if the database was opened in read-only mode.
+    fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            Err(Error::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
     fn remove_maybe_stripped_room_data(
         &self,
         txn: &Transaction<'_>,
@@ -1028,6 +1167,8 @@ impl StateStore for SqliteStateStore {
         key: StateStoreDataKey<'_>,
         value: StateStoreDataValue,
     ) -> Result<()> {
+        self.ensure_writable()?;
+
         let serialized_value = match key {
             StateStoreDataKey::SyncToken => self.serialize_value(
                 &value.into_sync_token().expect("Session data not a sync token"),
@@ -1064,10 +1205,14 @@ impl StateStore for SqliteStateStore {
     }
 
     async fn remove_kv_data(&self, key: StateStoreDataKey<'_>) -> Result<()> {
+        self.ensure_writable()?;
+
         self.acquire().await?.delete_kv_blob(self.encode_state_store_data_key(key)).await
     }
 
     async fn save_changes(&self, changes: &StateChanges) -> Result<()> {
+        self.ensure_writable()?;
+
         let changes = changes.to_owned();
         let this = self.clone();
         self.acquire()
@@ -1707,6 +1852,8 @@ impl StateStore for SqliteStateStore {
     }
 
     async fn set_custom_value_no_read(&self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.ensure_writable()?;
+
         let conn = self.acquire().await?;
         let key = self.encode_custom_key(key);
         conn.set_kv_blob(key, value).await?;
@@ -1714,6 +1861,8 @@ impl StateStore for SqliteStateStore {
     }
 
     async fn set_custom_value(&self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        self.ensure_writable()?;
+
         let conn = self.acquire().await?;
         let key = self.encode_custom_key(key);
         let previous = conn.get_kv_blob(key.clone()).await?;
@@ -1722,6 +1871,8 @@ impl StateStore for SqliteStateStore {
     }
 
     async fn remove_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.ensure_writable()?;
+
         let conn = self.acquire().await?;
         let key = self.encode_custom_key(key);
         let previous = conn.get_kv_blob(key.clone()).await?;
@@ -1732,6 +1883,8 @@ impl StateStore for SqliteStateStore {
     }
 
     async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
+        self.ensure_writable()?;
+
         let this = self.clone();
         let room_id = room_id.to_owned();
 
@@ -1788,6 +1941,8 @@ impl StateStore for SqliteStateStore {
         content: QueuedRequestKind,
         priority: usize,
     ) -> Result<(), Self::Error> {
+        self.ensure_writable()?;
+
         let room_id_key = self.encode_key(keys::SEND_QUEUE, room_id);
         let room_id_value = self.serialize_value(&room_id.to_owned())?;
 
@@ -1813,6 +1968,8 @@ impl StateStore for SqliteStateStore {
         transaction_id: &TransactionId,
         content: QueuedRequestKind,
     ) -> Result<bool, Self::Error> {
+        self.ensure_writable()?;
+
         let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
 
         let content = self.serialize_json(&content)?;
@@ -1835,6 +1992,8 @@ impl StateStore for SqliteStateStore {
         room_id: &RoomId,
         transaction_id: &TransactionId,
     ) -> Result<bool, Self::Error> {
+        self.ensure_writable()?;
+
         let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
 
         // See comment in `save_send_queue_event`.
@@ -1900,6 +2059,8 @@ impl StateStore for SqliteStateStore {
         transaction_id: &TransactionId,
         error: Option<QueueWedgeError>,
     ) -> Result<(), Self::Error> {
+        self.ensure_writable()?;
+
         let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
 
         // See comment in `save_send_queue_event`.
@@ -1948,6 +2109,8 @@ impl StateStore for SqliteStateStore {
         created_at: MilliSecondsSinceUnixEpoch,
         content: DependentQueuedRequestKind,
     ) -> Result<()> {
+        self.ensure_writable()?;
+
         let room_id = self.encode_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
         let content = self.serialize_json(&content)?;
 
@@ -1982,6 +2145,8 @@ impl StateStore for SqliteStateStore {
         own_transaction_id: &ChildTransactionId,
         new_content: DependentQueuedRequestKind,
     ) -> Result<bool> {
+        self.ensure_writable()?;
+
         let room_id = self.encode_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
         let content = self.serialize_json(&new_content)?;
 
@@ -2015,6 +2180,8 @@ impl StateStore for SqliteStateStore {
         parent_txn_id: &TransactionId,
         parent_key: SentRequestKey,
     ) -> Result<usize> {
+        self.ensure_writable()?;
+
         let room_id = self.encode_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
         let parent_key = self.serialize_value(&parent_key)?;
 
@@ -2037,6 +2204,8 @@ impl StateStore for SqliteStateStore {
         room_id: &RoomId,
         txn_id: &ChildTransactionId,
     ) -> Result<bool> {
+        self.ensure_writable()?;
+
         let room_id = self.encode_key(keys::DEPENDENTS_SEND_QUEUE, room_id);
 
         // See comment in `save_send_queue_event`.
@@ -2100,6 +2269,8 @@ impl StateStore for SqliteStateStore {
         thread_id: &EventId,
         subscription: ThreadSubscription,
     ) -> Result<(), Self::Error> {
+        self.ensure_writable()?;
+
         let room_id = self.encode_key(keys::THREAD_SUBSCRIPTIONS, room_id);
         let thread_id = self.encode_key(keys::THREAD_SUBSCRIPTIONS, thread_id);
         let status = subscription.as_str();
@@ -2148,6 +2319,8 @@ impl StateStore for SqliteStateStore {
         room_id: &RoomId,
         thread_id: &EventId,
     ) -> Result<(), Self::Error> {
+        self.ensure_writable()?;
+
         let room_id = self.encode_key(keys::THREAD_SUBSCRIPTIONS, room_id);
         let thread_id = self.encode_key(keys::THREAD_SUBSCRIPTIONS, thread_id);
 
@@ -2161,6 +2334,16 @@ impl StateStore for SqliteStateStore {
 
         Ok(())
     }
+
+    async fn storage_usage(&self, room_ids: &[OwnedRoomId]) -> Result<StorageUsage> {
+        let conn = self.acquire().await?;
+        storage_usage(self, &conn, STORAGE_USAGE_TABLES, room_ids).await
+    }
+
+    async fn compact(&self) -> Result<()> {
+        self.ensure_writable()?;
+        self.acquire().await?.compact().await
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -2202,13 +2385,21 @@ mod encrypted_tests {
         sync::atomic::{AtomicU32, Ordering::SeqCst},
     };
 
-    use matrix_sdk_base::{statestore_integration_tests, StateStore, StoreError};
+    use assert_matches::assert_matches;
+    use matrix_sdk_base::{
+        statestore_integration_tests, storage_usage::StorageCategory, RoomInfo, RoomState,
+        StateChanges, StateStore, StateStoreDataKey, StateStoreDataValue, StoreError,
+    };
     use matrix_sdk_test::async_test;
     use once_cell::sync::Lazy;
+    use ruma::room_id;
     use tempfile::{tempdir, TempDir};
 
-    use super::SqliteStateStore;
-    use crate::{utils::SqliteAsyncConnExt, SqliteStoreConfig};
+    use super::{SqliteStateStore, DATABASE_NAME};
+    use crate::{
+        utils::{SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt},
+        OpenStoreError, SqliteStoreConfig,
+    };
 
     static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
     static NUM: AtomicU32 = AtomicU32::new(0);
@@ -2273,6 +2464,147 @@ mod encrypted_tests {
         assert_eq!(journal_size_limit, 1500);
     }
 
+    #[async_test]
+    async fn test_read_only() {
+        let tmpdir_path = new_state_store_workspace();
+        let passphrase = Some("default_test_password");
+
+        // A database that doesn't exist can't be opened in read-only mode.
+        let read_only_config =
+            SqliteStoreConfig::new(&tmpdir_path).passphrase(passphrase).read_only(true);
+        SqliteStateStore::open_with_config(read_only_config.clone()).await.unwrap_err();
+
+        let store = SqliteStateStore::open(&tmpdir_path, passphrase).await.unwrap();
+        store
+            .set_kv_data(
+                StateStoreDataKey::SyncToken,
+                StateStoreDataValue::SyncToken("s0".to_owned()),
+            )
+            .await
+            .unwrap();
+
+        let read_only_store =
+            SqliteStateStore::open_with_config(read_only_config.clone()).await.unwrap();
+
+        // The data can be read.
+        let sync_token = read_only_store.get_kv_data(StateStoreDataKey::SyncToken).await.unwrap();
+        assert_matches!(sync_token, Some(StateStoreDataValue::SyncToken(token)) if token == "s0");
+
+        // The data can't be modified.
+        let error = read_only_store.remove_kv_data(StateStoreDataKey::SyncToken).await.unwrap_err();
+        assert_eq!(error.to_string(), "The store was opened in read-only mode");
+        read_only_store.compact().await.unwrap_err();
+
+        // The read-write store still works alongside the read-only one.
+        store.remove_kv_data(StateStoreDataKey::SyncToken).await.unwrap();
+        assert!(read_only_store.get_kv_data(StateStoreDataKey::SyncToken).await.unwrap().is_none());
+
+        // The store cipher must already exist.
+        let tmpdir_path = new_state_store_workspace();
+        let _store = SqliteStateStore::open(&tmpdir_path, None).await.unwrap();
+
+        let result = SqliteStateStore::open_with_config(
+            SqliteStoreConfig::new(&tmpdir_path).passphrase(passphrase).read_only(true),
+        )
+        .await;
+        assert_matches!(result, Err(OpenStoreError::MissingCipher));
+    }
+
+    #[async_test]
+    async fn test_upgrade_passphrase_kdf() {
+        let tmpdir_path = new_state_store_workspace();
+        let passphrase = Some("default_test_password");
+
+        let store = SqliteStateStore::open(&tmpdir_path, passphrase).await.unwrap();
+        let export = store.pool.get().await.unwrap().get_kv("cipher").await.unwrap().unwrap();
+        drop(store);
+
+        // The store cipher export isn't upgraded by default.
+        let store = SqliteStateStore::open(&tmpdir_path, passphrase).await.unwrap();
+        let same_export = store.pool.get().await.unwrap().get_kv("cipher").await.unwrap();
+        assert_eq!(same_export, Some(export.clone()));
+        drop(store);
+
+        // It is upgraded when asked to.
+        let store = SqliteStateStore::open_with_config(
+            SqliteStoreConfig::new(&tmpdir_path)
+                .passphrase(passphrase)
+                .upgrade_passphrase_kdf(true),
+        )
+        .await
+        .unwrap();
+        let new_export = store.pool.get().await.unwrap().get_kv("cipher").await.unwrap().unwrap();
+        assert_ne!(new_export, export);
+        drop(store);
+
+        // And the store can still be opened with the upgraded export.
+        SqliteStateStore::open(&tmpdir_path, passphrase).await.unwrap();
+    }
+
+    #[async_test]
+    async fn test_storage_usage() {
+        let tmpdir_path = new_state_store_workspace();
+        let store =
+            SqliteStateStore::open(&tmpdir_path, Some("default_test_password")).await.unwrap();
+
+        let room_id = room_id!("!room:localhost");
+        let other_room_id = room_id!("!other_room:localhost");
+        let room_ids = [room_id.to_owned(), other_room_id.to_owned()];
+
+        let mut changes = StateChanges::default();
+        changes.add_room(RoomInfo::new(room_id, RoomState::Joined));
+        store.save_changes(&changes).await.unwrap();
+        store
+            .set_kv_data(
+                StateStoreDataKey::SyncToken,
+                StateStoreDataValue::SyncToken("s0".to_owned()),
+            )
+            .await
+            .unwrap();
+
+        let usage = store.storage_usage(&room_ids).await.unwrap();
+        assert!(usage.total_bytes.unwrap() > 0);
+        assert!(usage.reclaimable_bytes.is_some());
+
+        // The room info is the only data of the room.
+        let room_bytes = usage.room(room_id);
+        assert!(room_bytes > 0);
+        assert_eq!(usage.category(StorageCategory::State), room_bytes);
+        assert_eq!(usage.room(other_room_id), 0);
+
+        // The sync token is stored with the other key-value data.
+        assert!(usage.category(StorageCategory::Other) > 0);
+
+        // Once the room is removed and the store is compacted, there is no
+        // space left to reclaim.
+        store.remove_room(room_id).await.unwrap();
+        store.compact().await.unwrap();
+
+        let usage = store.storage_usage(&room_ids).await.unwrap();
+        assert_eq!(usage.room(room_id), 0);
+        assert_eq!(usage.category(StorageCategory::State), 0);
+        assert_eq!(usage.reclaimable_bytes, Some(0));
+    }
+
+    #[async_test]
+    async fn test_compact_busy_database() {
+        let tmpdir_path = new_state_store_workspace();
+        let store =
+            SqliteStateStore::open(&tmpdir_path, Some("default_test_password")).await.unwrap();
+
+        // Another connection, e.g. from another process, is writing to the database.
+        let other_conn = rusqlite::Connection::open(tmpdir_path.join(DATABASE_NAME)).unwrap();
+        other_conn.execute_batch("BEGIN IMMEDIATE;").unwrap();
+
+        // The store can't be compacted, but it can be retried.
+        let error = store.compact().await.unwrap_err();
+        assert_matches!(StoreError::from(error), StoreError::Busy);
+
+        // Once the other connection is done, the store can be compacted.
+        other_conn.execute_batch("COMMIT;").unwrap();
+        store.compact().await.unwrap();
+    }
+
     statestore_integration_tests!();
 }
 
@@ -2287,6 +2619,7 @@ mod migration_tests {
     };
 
     use as_variant::as_variant;
+    use assert_matches::assert_matches;
     use deadpool_sqlite::Runtime;
     use matrix_sdk_base::{
         media::{MediaFormat, MediaRequestParameters},
@@ -2313,11 +2646,11 @@ mod migration_tests {
     use tempfile::{tempdir, TempDir};
     use tokio::fs;
 
-    use super::{init, keys, SqliteStateStore, DATABASE_NAME};
+    use super::{init, keys, SqliteStateStore, DATABASE_NAME, DATABASE_VERSION};
     use crate::{
         error::{Error, Result},
         utils::{EncryptableStore as _, SqliteAsyncConnExt, SqliteKeyValueStoreAsyncConnExt},
-        OpenStoreError,
+        OpenStoreError, SqliteStoreConfig,
     };
 
     static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
@@ -2340,13 +2673,38 @@ mod migration_tests {
 
         init(&conn).await?;
 
-        let store_cipher = Some(Arc::new(conn.get_or_create_store_cipher(SECRET).await.unwrap()));
-        let this = SqliteStateStore { store_cipher, pool };
+        let store_cipher =
+            Some(Arc::new(conn.get_or_create_store_cipher(SECRET, false).await.unwrap()));
+        let this = SqliteStateStore { store_cipher, pool, read_only: false };
         this.run_migrations(&conn, 1, Some(version)).await?;
 
         Ok(this)
     }
 
+    #[async_test]
+    pub async fn test_read_only_refuses_outdated_database() {
+        let path = new_path();
+        let store = create_fake_db(&path, 3).await.unwrap();
+
+        let result = SqliteStateStore::open_with_config(
+            SqliteStoreConfig::new(&path).passphrase(Some(SECRET)).read_only(true),
+        )
+        .await;
+        assert_matches!(
+            result,
+            Err(OpenStoreError::ReadOnlyMigration { version: 3, expected: DATABASE_VERSION })
+        );
+
+        // The database can still be migrated when opened in read-write mode.
+        drop(store);
+        let _store = SqliteStateStore::open(&path, Some(SECRET)).await.unwrap();
+        SqliteStateStore::open_with_config(
+            SqliteStoreConfig::new(&path).passphrase(Some(SECRET)).read_only(true),
+        )
+        .await
+        .unwrap();
+    }
+
     fn room_info_v1_json(
         room_id: &RoomId,
         state: RoomState,
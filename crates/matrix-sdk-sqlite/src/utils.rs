@@ -18,11 +18,13 @@ use std::{
     cmp::min,
     iter,
     ops::Deref,
+    path::{Component, Path, PathBuf},
 };
 
 use async_trait::async_trait;
-use deadpool_sqlite::Object as SqliteAsyncConn;
+use deadpool_sqlite::{Object as SqliteAsyncConn, Pool as SqlitePool, PoolConfig, Runtime};
 use itertools::Itertools;
+use matrix_sdk_common::storage_usage::{StorageCategory, StorageUsage};
 use matrix_sdk_store_encryption::StoreCipher;
 use ruma::{serde::Raw, time::SystemTime, OwnedEventId, OwnedRoomId};
 use rusqlite::{limits::Limit, OptionalExtension, Params, Row, Statement, Transaction};
@@ -199,6 +201,31 @@ pub(crate) trait SqliteAsyncConnExt {
 
         Ok(())
     }
+
+    /// Compact the database: defragment it, free space on the filesystem and
+    /// update the statistics of the query planner.
+    ///
+    /// Unlike [`Self::vacuum()`], this always returns the errors, e.g. when the
+    /// database is busy because another process is using it.
+    async fn compact(&self) -> Result<()> {
+        self.execute_batch("VACUUM").await?;
+
+        // `VACUUM` writes the whole database to the WAL file, truncate it to
+        // actually free the space. The first column of the result is set if
+        // another connection prevented the checkpoint from completing.
+        let busy: i64 =
+            self.query_row("PRAGMA wal_checkpoint(TRUNCATE);", (), |row| row.get(0)).await?;
+        if busy != 0 {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+                Some("the WAL file couldn't be truncated".to_owned()),
+            )
+            .into());
+        }
+
+        self.optimize().await?;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -454,15 +481,45 @@ pub(crate) trait SqliteKeyValueStoreAsyncConnExt: SqliteAsyncConnExt {
         }
     }
 
+    /// Get the [`StoreCipher`] of the database, without creating or upgrading
+    /// it.
+    ///
+    /// This is meant to be used when the database is opened in read-only mode.
+    async fn get_store_cipher(&self, passphrase: &str) -> Result<StoreCipher, OpenStoreError> {
+        let encrypted = self
+            .get_kv("cipher")
+            .await
+            .map_err(OpenStoreError::LoadCipher)?
+            .ok_or(OpenStoreError::MissingCipher)?;
+
+        Ok(StoreCipher::import(passphrase, &encrypted)?)
+    }
+
     /// Get the [`StoreCipher`] of the database or create it.
+    ///
+    /// If `upgrade_passphrase_kdf` is `true`, the store cipher is re-wrapped
+    /// using Argon2id if it was exported with PBKDF2.
     async fn get_or_create_store_cipher(
         &self,
         passphrase: &str,
+        upgrade_passphrase_kdf: bool,
     ) -> Result<StoreCipher, OpenStoreError> {
         let encrypted_cipher = self.get_kv("cipher").await.map_err(OpenStoreError::LoadCipher)?;
 
         let cipher = if let Some(encrypted) = encrypted_cipher {
-            StoreCipher::import(passphrase, &encrypted)?
+            if !upgrade_passphrase_kdf {
+                StoreCipher::import(passphrase, &encrypted)?
+            } else {
+                let (cipher, new_export) = StoreCipher::import_and_upgrade(passphrase, &encrypted)?;
+
+                // Re-wrap the store cipher if it was exported with an outdated key
+                // derivation function.
+                if let Some(export) = new_export {
+                    self.set_kv("cipher", export).await.map_err(OpenStoreError::SaveCipher)?;
+                }
+
+                cipher
+            }
         } else {
             let cipher = StoreCipher::new()?;
             #[cfg(not(test))]
@@ -505,6 +562,166 @@ impl SqliteKeyValueStoreAsyncConnExt for SqliteAsyncConn {
     }
 }
 
+/// Get the path used to open the database with the given file name, in the
+/// given directory.
+///
+/// In read-only mode, this is an [URI] with the `mode=ro` query parameter,
+/// which is equivalent to opening the database with `SQLITE_OPEN_READONLY`.
+///
+/// [URI]: https://www.sqlite.org/uri.html
+pub(crate) fn database_path(directory: &Path, file_name: &str, read_only: bool) -> PathBuf {
+    let path = directory.join(file_name);
+
+    if !read_only {
+        return path;
+    }
+
+    let mut uri_path = String::new();
+
+    for component in path.components() {
+        match component {
+            // A Windows prefix, like a drive letter. The separators are
+            // normalized, like in the rest of the path.
+            Component::Prefix(prefix) => {
+                uri_path.push_str(&prefix.as_os_str().to_string_lossy().replace('\\', "/"));
+            }
+            Component::RootDir => uri_path.push('/'),
+            component => {
+                if !uri_path.is_empty() && !uri_path.ends_with('/') {
+                    uri_path.push('/');
+                }
+                percent_encode_uri_path_segment(
+                    &component.as_os_str().to_string_lossy(),
+                    &mut uri_path,
+                );
+            }
+        }
+    }
+
+    // A path with a root must be preceded by an empty authority, and must start
+    // with a `/`, even if it starts with a Windows drive letter.
+    let uri = if path.has_root() {
+        let separator = if uri_path.starts_with('/') { "" } else { "/" };
+        format!("file://{separator}{uri_path}?mode=ro")
+    } else {
+        format!("file:{uri_path}?mode=ro")
+    };
+
+    uri.into()
+}
+
+/// Percent-encode the given segment of a path, to use it in an [URI].
+///
+/// Only the unreserved characters of [RFC 3986] are kept as-is.
+///
+/// [URI]: https://www.sqlite.org/uri.html
+/// [RFC 3986]: https://www.rfc-editor.org/rfc/rfc3986#section-2.3
+fn percent_encode_uri_path_segment(segment: &str, output: &mut String) {
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                output.push(byte.into())
+            }
+            byte => output.push_str(&format!("%{byte:02X}")),
+        }
+    }
+}
+
+/// Create the pool of the connection used to take the leased locks of a store
+/// opened in read-only mode.
+///
+/// The leased locks are shared with the other processes using the database, so
+/// they must be taken even if the rest of the database is only read. This is
+/// the only connection of such a store that is opened in read-write mode.
+pub(crate) fn create_lease_pool(
+    directory: &Path,
+    file_name: &str,
+) -> Result<SqlitePool, OpenStoreError> {
+    let mut config = deadpool_sqlite::Config::new(database_path(directory, file_name, false));
+    config.pool = Some(PoolConfig::new(1));
+
+    Ok(config.create_pool(Runtime::Tokio1)?)
+}
+
+/// A table measured by [`storage_usage()`].
+pub(crate) struct StorageUsageTable {
+    /// The name of the table.
+    pub name: &'static str,
+    /// The column containing the values.
+    pub value_column: &'static str,
+    /// The column containing the encoded room IDs, if the values belong to
+    /// rooms.
+    pub room_id_column: Option<&'static str>,
+    /// The table name used to encode the room IDs.
+    pub room_id_key_table: &'static str,
+    /// The category of the values.
+    pub category: StorageCategory,
+}
+
+/// Measure the storage used by the database of the given store.
+///
+/// The size of the values of each table is added to its category and, if the
+/// values belong to rooms, to the given rooms.
+pub(crate) async fn storage_usage(
+    store: &impl EncryptableStore,
+    conn: &SqliteAsyncConn,
+    tables: &'static [StorageUsageTable],
+    room_ids: &[OwnedRoomId],
+) -> Result<StorageUsage> {
+    // The room IDs may be hashed differently for each table.
+    let encoded_room_ids: Vec<Vec<(OwnedRoomId, Key)>> = tables
+        .iter()
+        .map(|table| {
+            if table.room_id_column.is_none() {
+                return Vec::new();
+            }
+
+            room_ids
+                .iter()
+                .map(|room_id| {
+                    (room_id.clone(), store.encode_key(table.room_id_key_table, room_id.as_str()))
+                })
+                .collect()
+        })
+        .collect();
+
+    conn.interact(move |conn| -> Result<StorageUsage> {
+        let (page_count, freelist_count, page_size) = conn.query_row(
+            "SELECT * FROM pragma_page_count(), pragma_freelist_count(), pragma_page_size()",
+            (),
+            |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?, row.get::<_, u64>(2)?)),
+        )?;
+
+        let mut usage = StorageUsage {
+            total_bytes: Some(page_count * page_size),
+            reclaimable_bytes: Some(freelist_count * page_size),
+            ..Default::default()
+        };
+
+        for (table, room_ids) in tables.iter().zip(encoded_room_ids) {
+            let StorageUsageTable { name, value_column, room_id_column, category, .. } = table;
+            let sum = format!(r#"SELECT COALESCE(SUM(LENGTH("{value_column}")), 0) FROM "{name}""#);
+
+            let bytes = conn.query_row(&sum, (), |row| row.get::<_, u64>(0))?;
+            usage.add_to_category(*category, bytes);
+
+            if let Some(room_id_column) = room_id_column {
+                let mut statement =
+                    conn.prepare(&format!(r#"{sum} WHERE "{room_id_column}" = ?"#))?;
+
+                for (room_id, key) in room_ids {
+                    let bytes = statement.query_row((key,), |row| row.get::<_, u64>(0))?;
+                    usage.add_to_room(&room_id, bytes);
+                }
+            }
+        }
+
+        Ok(usage)
+    })
+    .await
+    .unwrap()
+}
+
 /// Repeat `?` n times, where n is defined by `count`. `?` are comma-separated.
 pub(crate) fn repeat_vars(count: usize) -> impl fmt::Display {
     assert_ne!(count, 0, "Can't generate zero repeated vars");
@@ -641,4 +858,39 @@ mod unit_tests {
         // Fallback value on overflow.
         assert_eq!(time_to_timestamp(SystemTime::UNIX_EPOCH - Duration::from_secs(60)), 0);
     }
+
+    #[test]
+    fn test_database_path() {
+        let directory = Path::new("/data/store#1?");
+
+        assert_eq!(
+            database_path(directory, "db.sqlite3", false),
+            PathBuf::from("/data/store#1?/db.sqlite3")
+        );
+        assert_eq!(
+            database_path(directory, "db.sqlite3", true),
+            PathBuf::from("file:///data/store%231%3F/db.sqlite3?mode=ro")
+        );
+
+        // Spaces, percent signs and non-ASCII characters are encoded too.
+        assert_eq!(
+            database_path(Path::new("/my data/100%/été"), "db.sqlite3", true),
+            PathBuf::from("file:///my%20data/100%25/%C3%A9t%C3%A9/db.sqlite3?mode=ro")
+        );
+
+        // Relative paths stay relative.
+        assert_eq!(
+            database_path(Path::new("data"), "db.sqlite3", true),
+            PathBuf::from("file:data/db.sqlite3?mode=ro")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_database_path_windows() {
+        assert_eq!(
+            database_path(Path::new(r"C:\Users\me\store"), "db.sqlite3", true),
+            PathBuf::from("file:///C:/Users/me/store/db.sqlite3?mode=ro")
+        );
+    }
 }
@@ -18,6 +18,7 @@
 
 use std::ops::DerefMut;
 
+use argon2::{Algorithm, Argon2, Params as Argon2Params, Version as Argon2Version};
 use base64::{
     Engine, alphabet,
     engine::{GeneralPurpose, general_purpose},
@@ -36,8 +37,21 @@ use zeroize::{Zeroize, ZeroizeOnDrop};
 
 const VERSION: u8 = 1;
 const KDF_SALT_SIZE: usize = 32;
-const XNONCE_SIZE: usize = 24;
 const KDF_ROUNDS: u32 = 200_000;
+/// The version of the exports using PBKDF2 or a key, which can be read by all
+/// the versions of this crate.
+const LEGACY_EXPORT_VERSION: u8 = 1;
+/// The version of the exports using Argon2id.
+const EXPORT_VERSION: u8 = 2;
+/// The maximum memory cost, in KiB, of the Argon2id key derivation: 1 GiB.
+const MAX_ARGON2ID_MEMORY_COST: u32 = 1024 * 1024;
+/// The maximum time cost, i.e. number of iterations, of the Argon2id key
+/// derivation.
+const MAX_ARGON2ID_TIME_COST: u32 = 16;
+/// The maximum degree of parallelism, i.e. number of lanes, of the Argon2id key
+/// derivation.
+const MAX_ARGON2ID_PARALLELISM: u32 = 16;
+const XNONCE_SIZE: usize = 24;
 
 const BASE64: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, general_purpose::NO_PAD);
 
@@ -74,6 +88,35 @@ pub enum Error {
     #[error("The ciphertext had an invalid length, expected `{0}`, got `{1}`")]
     Length(usize, usize),
 
+    /// The parameters of the key derivation function are invalid.
+    #[error("The parameters of the key derivation function are invalid: `{0}`")]
+    KdfParams(#[from] KdfParamsError),
+
+    /// The memory cost of the key derivation function is too high.
+    #[error(
+        "The memory cost of the key derivation function is too high, expected at most `{0}` KiB, \
+         got `{1}` KiB"
+    )]
+    KdfMemoryCost(u32, u32),
+
+    /// The time cost of the key derivation function is too high.
+    #[error(
+        "The time cost of the key derivation function is too high, expected at most `{0}` \
+         iterations, got `{1}` iterations"
+    )]
+    KdfTimeCost(u32, u32),
+
+    /// The degree of parallelism of the key derivation function is too high.
+    #[error(
+        "The degree of parallelism of the key derivation function is too high, expected at most \
+         `{0}` lanes, got `{1}` lanes"
+    )]
+    KdfParallelism(u32, u32),
+
+    /// Unsupported store cipher export version.
+    #[error("Unsupported store cipher export version, expected at most `{0}`, got `{1}`")]
+    ExportVersion(u8, u8),
+
     /// Failed to import a store cipher, the export used a passphrase while
     /// we are trying to import it using a key or vice-versa.
     #[error(
@@ -83,6 +126,35 @@ pub enum Error {
     KdfMismatch,
 }
 
+/// Error returned when the parameters of the Argon2id key derivation function
+/// are invalid.
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub struct KdfParamsError(argon2::Error);
+
+/// The parameters of the Argon2id key derivation function used to expand a
+/// passphrase into the key that encrypts a [`StoreCipher`] export.
+///
+/// The default values follow the OWASP recommendations for Argon2id: 19 MiB of
+/// memory, 2 iterations and 1 degree of parallelism.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Argon2idParams {
+    /// The memory cost, in KiB. Must be at least `8 * parallelism`, and at
+    /// most 1 GiB.
+    pub memory_cost: u32,
+    /// The time cost, i.e. the number of iterations. Must be at most 16.
+    pub time_cost: u32,
+    /// The degree of parallelism, i.e. the number of lanes. Must be at most
+    /// 16.
+    pub parallelism: u32,
+}
+
+impl Default for Argon2idParams {
+    fn default() -> Self {
+        Self { memory_cost: 19 * 1024, time_cost: 2, parallelism: 1 }
+    }
+}
+
 /// An encryption key that can be used to encrypt data for key/value stores.
 ///
 /// # Examples
@@ -126,6 +198,10 @@ impl StoreCipher {
     /// The `StoreCipher` can later on be restored using
     /// [`StoreCipher::import`].
     ///
+    /// The passphrase is expanded into a key using PBKDF2, so the export can be
+    /// imported by all the versions of this crate. Use
+    /// [`StoreCipher::export_with_argon2id`] to use Argon2id instead.
+    ///
     /// # Arguments
     ///
     /// * `passphrase` - The passphrase that should be used to encrypt the store
@@ -150,6 +226,61 @@ impl StoreCipher {
         self.export_kdf(passphrase, KDF_ROUNDS)
     }
 
+    /// Encrypt the store cipher using the given passphrase, expanded with
+    /// Argon2id using the given parameters, and export it.
+    ///
+    /// The parameters are recorded in the export, so the `StoreCipher` can
+    /// later on be restored using [`StoreCipher::import`].
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - The passphrase that should be used to encrypt the store
+    ///   cipher.
+    ///
+    /// * `params` - The parameters of the Argon2id key derivation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let example = || {
+    /// use matrix_sdk_store_encryption::{Argon2idParams, StoreCipher};
+    ///
+    /// let store_cipher = StoreCipher::new()?;
+    ///
+    /// // Use 64 MiB of memory and 3 iterations.
+    /// let params =
+    ///     Argon2idParams { memory_cost: 64 * 1024, time_cost: 3, parallelism: 1 };
+    /// let export =
+    ///     store_cipher.export_with_argon2id("secret-passphrase", params)?;
+    ///
+    /// // Save the export in your key/value store.
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn export_with_argon2id(
+        &self,
+        passphrase: &str,
+        params: Argon2idParams,
+    ) -> Result<Vec<u8>, Error> {
+        let mut rng = thread_rng();
+
+        let mut salt = [0u8; KDF_SALT_SIZE];
+        salt.try_fill(&mut rng)?;
+
+        let key = StoreCipher::expand_key_argon2id(passphrase, &salt, params)?;
+
+        let store_cipher = self.export_helper(
+            &key,
+            KdfInfo::Argon2idToChaCha20Poly1305 {
+                memory_cost: params.memory_cost,
+                time_cost: params.time_cost,
+                parallelism: params.parallelism,
+                kdf_salt: salt,
+            },
+        )?;
+
+        Ok(rmp_serde::to_vec_named(&store_cipher).expect("Can't serialize the store cipher"))
+    }
+
     /// Encrypt the store cipher using the given key and export it.
     ///
     /// This method can be used to persist the `StoreCipher` in an unencrypted
@@ -202,7 +333,15 @@ impl StoreCipher {
 
         keys.zeroize();
 
+        // Only the Argon2id exports need the new version, this way the other
+        // exports can still be imported by the older versions of this crate.
+        let version = match kdf_info {
+            KdfInfo::Argon2idToChaCha20Poly1305 { .. } => EXPORT_VERSION,
+            KdfInfo::None | KdfInfo::Pbkdf2ToChaCha20Poly1305 { .. } => LEGACY_EXPORT_VERSION,
+        };
+
         Ok(EncryptedStoreCipher {
+            version,
             kdf_info,
             ciphertext_info: CipherTextInfo::ChaCha20Poly1305 { nonce, ciphertext },
         })
@@ -219,7 +358,7 @@ impl StoreCipher {
         let mut salt = [0u8; KDF_SALT_SIZE];
         salt.try_fill(&mut rng)?;
 
-        let key = StoreCipher::expand_key(passphrase, &salt, kdf_rounds);
+        let key = StoreCipher::expand_key_pbkdf2(passphrase, &salt, kdf_rounds);
 
         let store_cipher = self.export_helper(
             &key,
@@ -285,19 +424,96 @@ impl StoreCipher {
     /// # anyhow::Ok(()) };
     /// ```
     pub fn import(passphrase: &str, encrypted: &[u8]) -> Result<Self, Error> {
+        let encrypted = Self::deserialize_passphrase_export(encrypted)?;
+        Self::import_passphrase_helper(passphrase, encrypted)
+    }
+
+    /// Restore a store cipher from an export encrypted with a passphrase, and
+    /// re-export it if the export uses an outdated key derivation function.
+    ///
+    /// Exports whose passphrase was expanded with PBKDF2 are re-exported using
+    /// Argon2id with the default [`Argon2idParams`]. The new export, if any,
+    /// should replace the old one in the key/value store, this way existing
+    /// stores are upgraded the next time they are opened.
+    ///
+    /// Note that the new export can't be imported by the versions of this crate
+    /// that don't support Argon2id, so the upgrade should only be done once
+    /// going back to such a version isn't a concern.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - The passphrase that was used to encrypt the store
+    ///   cipher.
+    ///
+    /// * `encrypted` - The exported and encrypted version of the store cipher.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # let example = |export: Vec<u8>| {
+    /// use matrix_sdk_store_encryption::StoreCipher;
+    ///
+    /// let (store_cipher, new_export) =
+    ///     StoreCipher::import_and_upgrade("secret-passphrase", &export)?;
+    ///
+    /// if let Some(new_export) = new_export {
+    ///     // Replace the old export in your key/value store.
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn import_and_upgrade(
+        passphrase: &str,
+        encrypted: &[u8],
+    ) -> Result<(Self, Option<Vec<u8>>), Error> {
+        let encrypted = Self::deserialize_passphrase_export(encrypted)?;
+        let needs_upgrade = encrypted.kdf_info.needs_upgrade();
+
+        let cipher = Self::import_passphrase_helper(passphrase, encrypted)?;
+
+        let new_export = if needs_upgrade {
+            Some(cipher.export_with_argon2id(passphrase, Argon2idParams::default())?)
+        } else {
+            None
+        };
+
+        Ok((cipher, new_export))
+    }
+
+    fn deserialize_passphrase_export(encrypted: &[u8]) -> Result<EncryptedStoreCipher, Error> {
         // Our old export format used serde_json for the serialization format. Let's
         // first try the new format and if that fails, try the old one.
-        let encrypted: EncryptedStoreCipher =
+        let deserialized: EncryptedStoreCipher =
             if let Ok(deserialized) = rmp_serde::from_slice(encrypted) {
                 deserialized
             } else {
                 serde_json::from_slice(encrypted)?
             };
 
+        if deserialized.version > EXPORT_VERSION {
+            return Err(Error::ExportVersion(EXPORT_VERSION, deserialized.version));
+        }
+
+        Ok(deserialized)
+    }
+
+    fn import_passphrase_helper(
+        passphrase: &str,
+        encrypted: EncryptedStoreCipher,
+    ) -> Result<Self, Error> {
         let key = match encrypted.kdf_info {
             KdfInfo::Pbkdf2ToChaCha20Poly1305 { rounds, kdf_salt } => {
-                Self::expand_key(passphrase, &kdf_salt, rounds)
+                Self::expand_key_pbkdf2(passphrase, &kdf_salt, rounds)
             }
+            KdfInfo::Argon2idToChaCha20Poly1305 {
+                memory_cost,
+                time_cost,
+                parallelism,
+                kdf_salt,
+            } => Self::expand_key_argon2id(
+                passphrase,
+                &kdf_salt,
+                Argon2idParams { memory_cost, time_cost, parallelism },
+            )?,
             KdfInfo::None => {
                 return Err(Error::KdfMismatch);
             }
@@ -338,7 +554,11 @@ impl StoreCipher {
     pub fn import_with_key(key: &[u8; 32], encrypted: &[u8]) -> Result<Self, Error> {
         let encrypted: EncryptedStoreCipher = rmp_serde::from_slice(encrypted)?;
 
-        if let KdfInfo::Pbkdf2ToChaCha20Poly1305 { .. } = encrypted.kdf_info {
+        if encrypted.version > EXPORT_VERSION {
+            return Err(Error::ExportVersion(EXPORT_VERSION, encrypted.version));
+        }
+
+        if encrypted.kdf_info != KdfInfo::None {
             return Err(Error::KdfMismatch);
         }
 
@@ -609,8 +829,8 @@ impl StoreCipher {
         Ok(cipher.decrypt(nonce, value.ciphertext.as_ref())?)
     }
 
-    /// Expand the given passphrase into a KEY_SIZE long key.
-    fn expand_key(passphrase: &str, salt: &[u8], rounds: u32) -> Box<[u8; 32]> {
+    /// Expand the given passphrase into a KEY_SIZE long key using PBKDF2.
+    fn expand_key_pbkdf2(passphrase: &str, salt: &[u8], rounds: u32) -> Box<[u8; 32]> {
         let mut key = Box::new([0u8; 32]);
         pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, rounds, key.deref_mut()).expect(
             "We should be able to expand a passphrase of any length due to \
@@ -619,6 +839,37 @@ impl StoreCipher {
 
         key
     }
+
+    /// Expand the given passphrase into a KEY_SIZE long key using Argon2id.
+    fn expand_key_argon2id(
+        passphrase: &str,
+        salt: &[u8],
+        params: Argon2idParams,
+    ) -> Result<Box<[u8; 32]>, Error> {
+        // The parameters may come from an export, don't let it make us allocate an
+        // arbitrary amount of memory, or spend an arbitrary amount of time.
+        if params.memory_cost > MAX_ARGON2ID_MEMORY_COST {
+            return Err(Error::KdfMemoryCost(MAX_ARGON2ID_MEMORY_COST, params.memory_cost));
+        }
+        if params.time_cost > MAX_ARGON2ID_TIME_COST {
+            return Err(Error::KdfTimeCost(MAX_ARGON2ID_TIME_COST, params.time_cost));
+        }
+        if params.parallelism > MAX_ARGON2ID_PARALLELISM {
+            return Err(Error::KdfParallelism(MAX_ARGON2ID_PARALLELISM, params.parallelism));
+        }
+
+        let params =
+            Argon2Params::new(params.memory_cost, params.time_cost, params.parallelism, Some(32))
+                .map_err(KdfParamsError)?;
+        let argon2 = Argon2::new(Algorithm::Argon2id, Argon2Version::V0x13, params);
+
+        let mut key = Box::new([0u8; 32]);
+        argon2
+            .hash_password_into(passphrase.as_bytes(), salt, key.deref_mut())
+            .map_err(KdfParamsError)?;
+
+        Ok(key)
+    }
 }
 
 #[derive(ZeroizeOnDrop)]
@@ -785,6 +1036,27 @@ enum KdfInfo {
         /// key.
         kdf_salt: [u8; KDF_SALT_SIZE],
     },
+    /// The Argon2id to Chacha key derivation variant.
+    Argon2idToChaCha20Poly1305 {
+        /// The memory cost, in KiB, that was used when deriving the store key.
+        memory_cost: u32,
+        /// The time cost that was used when deriving the store key.
+        time_cost: u32,
+        /// The degree of parallelism that was used when deriving the store
+        /// key.
+        parallelism: u32,
+        /// The salt that was used when the passphrase was expanded into a store
+        /// key.
+        kdf_salt: [u8; KDF_SALT_SIZE],
+    },
+}
+
+impl KdfInfo {
+    /// Whether an export using this key derivation method should be
+    /// re-exported using the current default one.
+    fn needs_upgrade(&self) -> bool {
+        matches!(self, KdfInfo::Pbkdf2ToChaCha20Poly1305 { .. })
+    }
 }
 
 /// Version specific info for encryption method that is used to encrypt our
@@ -804,6 +1076,10 @@ enum CipherTextInfo {
 /// database.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 struct EncryptedStoreCipher {
+    /// The version of the export, missing from the exports created before
+    /// Argon2id was supported.
+    #[serde(default = "legacy_export_version")]
+    pub version: u8,
     /// Info about the key derivation method that was used to expand the
     /// passphrase into an encryption key.
     pub kdf_info: KdfInfo,
@@ -812,11 +1088,20 @@ struct EncryptedStoreCipher {
     pub ciphertext_info: CipherTextInfo,
 }
 
+fn legacy_export_version() -> u8 {
+    LEGACY_EXPORT_VERSION
+}
+
 #[cfg(test)]
 mod tests {
+    use assert_matches::assert_matches;
     use serde_json::{Value, json};
 
-    use super::{Error, StoreCipher};
+    use super::{
+        Argon2idParams, EXPORT_VERSION, EncryptedStoreCipher, Error, KdfInfo,
+        LEGACY_EXPORT_VERSION, MAX_ARGON2ID_MEMORY_COST, MAX_ARGON2ID_PARALLELISM,
+        MAX_ARGON2ID_TIME_COST, StoreCipher,
+    };
     use crate::{EncryptedValue, EncryptedValueBase64, EncryptedValueBase64DecodeError};
 
     #[test]
@@ -905,6 +1190,202 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_exporting_store_cipher_with_argon2id() -> Result<(), Error> {
+        let passphrase = "it's a secret to everybody";
+        let store_cipher = StoreCipher::new()?;
+
+        let params = Argon2idParams { memory_cost: 64, time_cost: 2, parallelism: 2 };
+        let export = store_cipher.export_with_argon2id(passphrase, params)?;
+
+        let encrypted: EncryptedStoreCipher = rmp_serde::from_slice(&export)?;
+        assert_eq!(encrypted.version, EXPORT_VERSION);
+        assert_matches!(
+            encrypted.kdf_info,
+            KdfInfo::Argon2idToChaCha20Poly1305 {
+                memory_cost: 64,
+                time_cost: 2,
+                parallelism: 2,
+                ..
+            }
+        );
+
+        let decrypted = StoreCipher::import(passphrase, &export)?;
+        assert_eq!(store_cipher.inner.encryption_key, decrypted.inner.encryption_key);
+        assert_eq!(store_cipher.inner.mac_key_seed, decrypted.inner.mac_key_seed);
+
+        // A wrong passphrase can't decrypt the export.
+        assert!(StoreCipher::import("wrong passphrase", &export).is_err());
+
+        // Nor can a key.
+        match StoreCipher::import_with_key(&[0u8; 32], &export) {
+            Err(Error::KdfMismatch) => {}
+            _ => panic!("Invalid error when importing an Argon2id store cipher with a key"),
+        }
+
+        // Invalid parameters are refused.
+        let params = Argon2idParams { memory_cost: 8, time_cost: 1, parallelism: 2 };
+        match store_cipher.export_with_argon2id(passphrase, params) {
+            Err(Error::KdfParams(_)) => {}
+            _ => panic!("Invalid error when exporting with invalid Argon2id parameters"),
+        }
+
+        // So is a memory cost that is too high.
+        let params = Argon2idParams {
+            memory_cost: MAX_ARGON2ID_MEMORY_COST + 1,
+            time_cost: 1,
+            parallelism: 1,
+        };
+        match store_cipher.export_with_argon2id(passphrase, params) {
+            Err(Error::KdfMemoryCost(MAX_ARGON2ID_MEMORY_COST, _)) => {}
+            _ => panic!("Invalid error when exporting with a too high memory cost"),
+        }
+
+        // And a time cost that is too high.
+        let params = Argon2idParams {
+            memory_cost: 8,
+            time_cost: MAX_ARGON2ID_TIME_COST + 1,
+            parallelism: 1,
+        };
+        match store_cipher.export_with_argon2id(passphrase, params) {
+            Err(Error::KdfTimeCost(MAX_ARGON2ID_TIME_COST, _)) => {}
+            _ => panic!("Invalid error when exporting with a too high time cost"),
+        }
+
+        // And a degree of parallelism that is too high.
+        let params = Argon2idParams {
+            memory_cost: 8 * (MAX_ARGON2ID_PARALLELISM + 1),
+            time_cost: 1,
+            parallelism: MAX_ARGON2ID_PARALLELISM + 1,
+        };
+        match store_cipher.export_with_argon2id(passphrase, params) {
+            Err(Error::KdfParallelism(MAX_ARGON2ID_PARALLELISM, _)) => {}
+            _ => panic!("Invalid error when exporting with a too high degree of parallelism"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_export_uses_pbkdf2() -> Result<(), Error> {
+        let passphrase = "it's a secret to everybody";
+        let store_cipher = StoreCipher::new()?;
+
+        let export = store_cipher._insecure_export_fast_for_testing(passphrase)?;
+
+        // The export has no reason to be upgraded, so older versions can import it.
+        let encrypted: EncryptedStoreCipher = rmp_serde::from_slice(&export)?;
+        assert_eq!(encrypted.version, LEGACY_EXPORT_VERSION);
+        assert_matches!(encrypted.kdf_info, KdfInfo::Pbkdf2ToChaCha20Poly1305 { .. });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_importing_export_with_unknown_version() -> Result<(), Error> {
+        let passphrase = "it's a secret to everybody";
+        let store_cipher = StoreCipher::new()?;
+
+        let export = store_cipher._insecure_export_fast_for_testing(passphrase)?;
+        let mut encrypted: EncryptedStoreCipher = rmp_serde::from_slice(&export)?;
+        encrypted.version = EXPORT_VERSION + 1;
+        let export = rmp_serde::to_vec_named(&encrypted)?;
+
+        match StoreCipher::import(passphrase, &export) {
+            Err(Error::ExportVersion(EXPORT_VERSION, version)) => {
+                assert_eq!(version, EXPORT_VERSION + 1)
+            }
+            _ => panic!("Invalid error when importing an export with an unknown version"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_importing_export_with_too_high_memory_cost() -> Result<(), Error> {
+        let passphrase = "it's a secret to everybody";
+        let store_cipher = StoreCipher::new()?;
+
+        let params = Argon2idParams { memory_cost: 8, time_cost: 1, parallelism: 1 };
+        let export = store_cipher.export_with_argon2id(passphrase, params)?;
+
+        let mut encrypted: EncryptedStoreCipher = rmp_serde::from_slice(&export)?;
+        let KdfInfo::Argon2idToChaCha20Poly1305 { memory_cost, .. } = &mut encrypted.kdf_info
+        else {
+            panic!("The export should use Argon2id");
+        };
+        *memory_cost = u32::MAX;
+        let export = rmp_serde::to_vec_named(&encrypted)?;
+
+        // The key isn't derived with the memory cost of the export.
+        match StoreCipher::import(passphrase, &export) {
+            Err(Error::KdfMemoryCost(MAX_ARGON2ID_MEMORY_COST, u32::MAX)) => {}
+            _ => panic!("Invalid error when importing an export with a too high memory cost"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_importing_export_with_too_high_time_cost_or_parallelism() -> Result<(), Error> {
+        let passphrase = "it's a secret to everybody";
+        let store_cipher = StoreCipher::new()?;
+
+        let params = Argon2idParams { memory_cost: 8, time_cost: 1, parallelism: 1 };
+        let export = store_cipher.export_with_argon2id(passphrase, params)?;
+
+        let mut tampered: EncryptedStoreCipher = rmp_serde::from_slice(&export)?;
+        let KdfInfo::Argon2idToChaCha20Poly1305 { time_cost, .. } = &mut tampered.kdf_info else {
+            panic!("The export should use Argon2id");
+        };
+        *time_cost = u32::MAX;
+        let tampered_export = rmp_serde::to_vec_named(&tampered)?;
+
+        // The key isn't derived with the time cost of the export.
+        match StoreCipher::import(passphrase, &tampered_export) {
+            Err(Error::KdfTimeCost(MAX_ARGON2ID_TIME_COST, u32::MAX)) => {}
+            _ => panic!("Invalid error when importing an export with a too high time cost"),
+        }
+
+        let mut tampered: EncryptedStoreCipher = rmp_serde::from_slice(&export)?;
+        let KdfInfo::Argon2idToChaCha20Poly1305 { parallelism, .. } = &mut tampered.kdf_info else {
+            panic!("The export should use Argon2id");
+        };
+        *parallelism = u32::MAX;
+        let tampered_export = rmp_serde::to_vec_named(&tampered)?;
+
+        // Nor with its degree of parallelism.
+        match StoreCipher::import(passphrase, &tampered_export) {
+            Err(Error::KdfParallelism(MAX_ARGON2ID_PARALLELISM, u32::MAX)) => {}
+            _ => panic!("Invalid error when importing an export with a too high parallelism"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrading_pbkdf2_export() -> Result<(), Error> {
+        let passphrase = "it's a secret to everybody";
+        let store_cipher = StoreCipher::new()?;
+
+        let old_export = store_cipher.export_kdf(passphrase, 1000)?;
+
+        let (imported, new_export) = StoreCipher::import_and_upgrade(passphrase, &old_export)?;
+        assert_eq!(store_cipher.inner.encryption_key, imported.inner.encryption_key);
+
+        let new_export = new_export.expect("A PBKDF2 export should be upgraded");
+        let encrypted: EncryptedStoreCipher = rmp_serde::from_slice(&new_export)?;
+        assert_eq!(encrypted.version, EXPORT_VERSION);
+        assert_matches!(encrypted.kdf_info, KdfInfo::Argon2idToChaCha20Poly1305 { .. });
+
+        let (imported, newer_export) = StoreCipher::import_and_upgrade(passphrase, &new_export)?;
+        assert_eq!(store_cipher.inner.encryption_key, imported.inner.encryption_key);
+        assert_eq!(store_cipher.inner.mac_key_seed, imported.inner.mac_key_seed);
+        assert!(newer_export.is_none(), "An Argon2id export doesn't need to be upgraded");
+
+        Ok(())
+    }
+
     #[test]
     fn test_importing_invalid_store_cipher_does_not_panic() {
         // This used to panic, we're testing that we're getting a real error.
@@ -20,6 +20,7 @@ use ruma::html::HtmlSanitizerMode;
 
 pub mod encryption_sync_service;
 pub mod notification_client;
+pub mod notification_inbox;
 pub mod room_list_service;
 pub mod sync_service;
 pub mod timeline;
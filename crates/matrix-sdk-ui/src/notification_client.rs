@@ -20,7 +20,8 @@ use std::{
 
 use futures_util::{StreamExt as _, pin_mut};
 use matrix_sdk::{
-    Client, ClientBuildError, SlidingSyncList, SlidingSyncMode, room::Room, sleep::sleep,
+    Client, ClientBuildError, SlidingSyncList, SlidingSyncMode,
+    notification_settings::DoNotDisturbEventContent, room::Room, sleep::sleep,
 };
 use matrix_sdk_base::{RoomState, StoreError, deserialized_responses::TimelineEvent};
 use ruma::{
@@ -606,13 +607,28 @@ impl NotificationClient {
         get_notifications_result.remove(event_id).unwrap_or(Ok(NotificationStatus::EventNotFound))
     }
 
+    /// Load the "do not disturb" settings of the user.
+    ///
+    /// If they can't be loaded, the default, disabled, settings are returned,
+    /// so that no notification is lost.
+    async fn do_not_disturb_settings(&self) -> DoNotDisturbEventContent {
+        match self.client.account().account_data::<DoNotDisturbEventContent>().await {
+            Ok(settings) => settings.and_then(|raw| raw.deserialize().ok()).unwrap_or_default(),
+            Err(error) => {
+                warn!("Couldn't load the do not disturb settings, ignoring them: {error}");
+                DoNotDisturbEventContent::default()
+            }
+        }
+    }
+
     /// Given a (decrypted or not) event, figure out whether it should be
     /// filtered out for other client-side reasons (such as the sender being
-    /// ignored, for instance), and returns the corresponding
-    /// [`NotificationStatus`].
+    /// ignored, or the "do not disturb" mode being active, for instance), and
+    /// returns the corresponding [`NotificationStatus`].
     async fn compute_status(
         &self,
         room: &Room,
+        do_not_disturb: &DoNotDisturbEventContent,
         push_actions: Option<&[Action]>,
         raw_event: RawNotificationEvent,
         state_events: Vec<Raw<AnyStateEvent>>,
@@ -628,10 +644,22 @@ impl NotificationClient {
             NotificationItem::new(room, raw_event, push_actions, state_events).await?;
 
         if self.client.is_user_ignored(notification_item.event.sender()).await {
-            Ok(NotificationStatus::EventFilteredOut)
-        } else {
-            Ok(NotificationStatus::Event(Box::new(notification_item)))
+            return Ok(NotificationStatus::EventFilteredOut);
         }
+
+        // Respect the "do not disturb" settings of the user.
+        if !do_not_disturb
+            .should_show_notification(
+                room,
+                notification_item.event.sender(),
+                push_actions.unwrap_or_default(),
+            )
+            .await
+        {
+            return Ok(NotificationStatus::EventFilteredOut);
+        }
+
+        Ok(NotificationStatus::Event(Box::new(notification_item)))
     }
 
     /// Get a list of full notifications, given a room id and event ids.
@@ -645,6 +673,7 @@ impl NotificationClient {
         let raw_events = self.try_sliding_sync(requests).await?;
 
         let mut batch_result = BatchNotificationFetchingResult::new();
+        let do_not_disturb = self.do_not_disturb_settings().await;
 
         for (event_id, (room_id, raw_event)) in raw_events.into_iter() {
             // At this point it should have been added by the sync, if it's not, give up.
@@ -702,8 +731,15 @@ impl NotificationClient {
                 }
             };
 
-            let notification_status_result =
-                self.compute_status(&room, push_actions.as_deref(), raw_event, Vec::new()).await;
+            let notification_status_result = self
+                .compute_status(
+                    &room,
+                    &do_not_disturb,
+                    push_actions.as_deref(),
+                    raw_event,
+                    Vec::new(),
+                )
+                .await;
 
             batch_result.insert(event_id, notification_status_result);
         }
@@ -746,8 +782,11 @@ impl NotificationClient {
 
         let push_actions = timeline_event.push_actions().map(ToOwned::to_owned);
 
+        let do_not_disturb = self.do_not_disturb_settings().await;
+
         self.compute_status(
             &room,
+            &do_not_disturb,
             push_actions.as_deref(),
             RawNotificationEvent::Timeline(timeline_event.into_raw()),
             state_events,
@@ -0,0 +1,1011 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A persistent inbox of the notifications received across all rooms.
+//!
+//! The [`NotificationInbox`] listens to the room updates received by the
+//! [`Client`], and records every event whose push actions notify (see
+//! [`Room::event_push_actions`]). The recorded items are persisted in the state
+//! store, so that they survive restarts, and their read status is kept in sync
+//! with the read receipts of the current user.
+//!
+//! This is useful to build “activity” or “mentions” panels, without having to
+//! re-scan every room.
+
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use eyeball::SharedObservable;
+use eyeball_im::{ObservableVector, Vector, VectorDiff};
+use eyeball_im_util::vector::VectorObserverExt;
+use futures_util::Stream;
+use matrix_sdk::{
+    Client, Room,
+    deserialized_responses::TimelineEvent,
+    executor::{JoinHandle, spawn},
+    sync::RoomUpdates,
+};
+use matrix_sdk_base::StoreError;
+use ruma::{
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId,
+    events::{
+        AnyStrippedStateEvent, AnySyncTimelineEvent,
+        receipt::{ReceiptThread, ReceiptType},
+    },
+    serde::Raw,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, error, trace, warn};
+
+/// The key used to persist the list of the items of the inbox in the state
+/// store, as a custom value.
+///
+/// Each item is persisted separately, see [`item_store_key`].
+const NOTIFICATION_INBOX_STORE_KEY: &[u8] = b"notification_inbox.index";
+
+/// The number of latest timeline events of each room whose position is
+/// remembered, to locate the events targeted by the read receipts.
+const NUM_RECENT_EVENTS_PER_ROOM: usize = 100;
+
+/// The key used to persist an item of the inbox in the state store, as a
+/// custom value.
+fn item_store_key(event_id: &EventId) -> Vec<u8> {
+    format!("notification_inbox.item.{event_id}").into_bytes()
+}
+
+/// The default maximum number of items kept in the inbox.
+pub const DEFAULT_MAX_ITEMS: usize = 1000;
+
+/// An item of the [`NotificationInbox`], i.e. an event that has notified the
+/// user.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NotificationInboxItem {
+    /// The room in which the event has been received.
+    pub room_id: OwnedRoomId,
+
+    /// The ID of the event.
+    pub event_id: OwnedEventId,
+
+    /// The sender of the event.
+    pub sender: OwnedUserId,
+
+    /// The `origin_server_ts` of the event.
+    pub timestamp: MilliSecondsSinceUnixEpoch,
+
+    /// Whether the push actions of the event contain a highlight, i.e. whether
+    /// the event is a mention.
+    pub is_highlight: bool,
+
+    /// Whether the event has been read by the current user.
+    pub is_read: bool,
+
+    /// The `body` of the event content, if any.
+    pub body: Option<String>,
+
+    /// The root of the thread the event is in, if any.
+    pub thread_root: Option<OwnedEventId>,
+
+    /// Whether the item is an invite to the room.
+    ///
+    /// In this case, [`NotificationInboxItem::event`] is the stripped
+    /// `m.room.member` event of the invite, and the item is marked as read
+    /// once the invite is accepted or rejected.
+    pub is_invite: bool,
+
+    /// The event itself, decrypted if it was possible.
+    pub event: Raw<AnySyncTimelineEvent>,
+
+    /// The position of the event in the order in which the events were
+    /// received, used to compare it with the events targeted by the read
+    /// receipts.
+    position: u64,
+}
+
+/// A filter applied on the items of the [`NotificationInbox`].
+///
+/// All the criteria must match for an item to be kept. The default filter
+/// keeps all the items.
+#[derive(Clone, Debug, Default)]
+pub struct NotificationInboxFilter {
+    /// Keep the unread items only.
+    pub unread_only: bool,
+
+    /// Keep the mentions (i.e. highlights) only.
+    pub mentions_only: bool,
+
+    /// Keep the items whose body contains at least one of these keywords,
+    /// case-insensitively. An empty list disables this criterion.
+    pub keywords: Vec<String>,
+
+    /// Keep the items of this room only.
+    pub room_id: Option<OwnedRoomId>,
+}
+
+impl NotificationInboxFilter {
+    /// Check whether an item matches this filter.
+    pub fn matches(&self, item: &NotificationInboxItem) -> bool {
+        if self.unread_only && item.is_read {
+            return false;
+        }
+
+        if self.mentions_only && !item.is_highlight {
+            return false;
+        }
+
+        if let Some(room_id) = &self.room_id {
+            if *room_id != item.room_id {
+                return false;
+            }
+        }
+
+        if !self.keywords.is_empty() {
+            let Some(body) = item.body.as_ref().map(|body| body.to_lowercase()) else {
+                return false;
+            };
+
+            if !self.keywords.iter().any(|keyword| body.contains(&keyword.to_lowercase())) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Controller for the paginated entries of a [`NotificationInbox`], see
+/// [`NotificationInbox::entries`].
+#[derive(Debug)]
+pub struct NotificationInboxPaginator {
+    page_size: usize,
+    limit: SharedObservable<usize>,
+}
+
+impl NotificationInboxPaginator {
+    /// Extend the entries with one more page.
+    pub fn add_one_page(&self) {
+        self.limit.update(|limit| *limit += self.page_size);
+    }
+
+    /// Reset the entries to the first page.
+    pub fn reset_to_one_page(&self) {
+        self.limit.set_if_not_eq(self.page_size);
+    }
+}
+
+/// A persistent, observable inbox of the notifications received across all the
+/// rooms.
+///
+/// Items are sorted from the most recent to the oldest one. The inbox keeps at
+/// most a fixed number of items (see [`DEFAULT_MAX_ITEMS`]), the oldest items
+/// being dropped first.
+#[derive(Debug)]
+pub struct NotificationInbox {
+    inner: Arc<NotificationInboxInner>,
+
+    /// The task listening to the room updates.
+    task: JoinHandle<()>,
+}
+
+impl Drop for NotificationInbox {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl NotificationInbox {
+    /// Create a new [`NotificationInbox`] keeping at most `max_items` items.
+    ///
+    /// The items recorded by a previous inbox are loaded from the state
+    /// store. The inbox then starts listening to the room updates received by
+    /// the client, i.e. it must be created before the sync is started to not
+    /// miss any notification.
+    pub async fn new(client: Client, max_items: usize) -> Result<Self, StoreError> {
+        let store = client.state_store();
+
+        let event_ids = match store.get_custom_value(NOTIFICATION_INBOX_STORE_KEY).await? {
+            Some(bytes) => match serde_json::from_slice::<Vec<OwnedEventId>>(&bytes) {
+                Ok(event_ids) => event_ids,
+                Err(error) => {
+                    warn!(
+                        "Failed to deserialize the notification inbox, starting from scratch: {error}"
+                    );
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+
+        let mut items = Vec::with_capacity(event_ids.len());
+        let mut changes = PendingChanges::default();
+
+        for event_id in event_ids {
+            if items.len() >= max_items {
+                changes.removed.insert(event_id);
+                changes.index = true;
+                continue;
+            }
+
+            let item = store
+                .get_custom_value(&item_store_key(&event_id))
+                .await?
+                .and_then(|bytes| serde_json::from_slice::<NotificationInboxItem>(&bytes).ok());
+
+            match item {
+                Some(item) => items.push(item),
+                None => {
+                    warn!(?event_id, "Failed to load a notification inbox item, dropping it");
+                    changes.removed.insert(event_id);
+                    changes.index = true;
+                }
+            }
+        }
+
+        let next_position = items.iter().map(|item| item.position + 1).max().unwrap_or_default();
+
+        let mut observable_items = ObservableVector::with_capacity(items.len());
+        observable_items.append(items.into());
+
+        let inner = Arc::new(NotificationInboxInner {
+            client: client.clone(),
+            items: Mutex::new(observable_items),
+            max_items,
+            positions: Mutex::new(EventPositions { next: next_position, ..Default::default() }),
+            changes: Mutex::new(changes),
+            persist_lock: Default::default(),
+        });
+
+        let task = spawn({
+            let inner = inner.clone();
+            let mut room_updates = client.subscribe_to_all_room_updates();
+
+            async move {
+                loop {
+                    match room_updates.recv().await {
+                        Ok(updates) => inner.handle_room_updates(updates).await,
+
+                        Err(RecvError::Lagged(num_skipped)) => {
+                            warn!(
+                                num_skipped,
+                                "Lagged behind room updates, some notifications may be missing"
+                            );
+                        }
+
+                        Err(RecvError::Closed) => {
+                            debug!("Room updates channel closed, stopping the notification inbox");
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Self { inner, task })
+    }
+
+    /// Get a copy of all the items, from the most recent to the oldest one.
+    pub fn items(&self) -> Vector<NotificationInboxItem> {
+        self.inner.items.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Get the number of unread items, optionally only counting the mentions.
+    pub fn num_unread(&self, mentions_only: bool) -> usize {
+        self.inner
+            .items
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|item| !item.is_read && (!mentions_only || item.is_highlight))
+            .count()
+    }
+
+    /// Subscribe to the items matching `filter`, one page of `page_size` items
+    /// at a time.
+    ///
+    /// Returns the initial items, a stream of updates, and a
+    /// [`NotificationInboxPaginator`] to load more pages.
+    pub fn entries(
+        &self,
+        filter: NotificationInboxFilter,
+        page_size: usize,
+    ) -> (
+        Vector<NotificationInboxItem>,
+        impl Stream<Item = Vec<VectorDiff<NotificationInboxItem>>> + use<>,
+        NotificationInboxPaginator,
+    ) {
+        let limit = SharedObservable::new(page_size);
+        let paginator = NotificationInboxPaginator { page_size, limit: limit.clone() };
+
+        let (values, stream) =
+            self.inner.items.lock().unwrap().subscribe().into_values_and_batched_stream();
+
+        let (values, stream) = (values, stream)
+            .filter(move |item| filter.matches(item))
+            .dynamic_head_with_initial_value(page_size, limit.subscribe());
+
+        (values, stream, paginator)
+    }
+
+    /// Mark an item as read, without sending any read receipt.
+    pub async fn mark_as_read(&self, event_id: &EventId) -> Result<(), StoreError> {
+        self.inner.mark_as_read_where(|item| item.event_id == event_id);
+        self.inner.persist().await
+    }
+
+    /// Mark all the items as read, without sending any read receipt.
+    pub async fn mark_all_as_read(&self) -> Result<(), StoreError> {
+        self.inner.mark_as_read_where(|_| true);
+        self.inner.persist().await
+    }
+
+    /// Remove all the items, and from the store too.
+    pub async fn clear(&self) -> Result<(), StoreError> {
+        {
+            let mut items = self.inner.items.lock().unwrap();
+            let mut changes = self.inner.changes.lock().unwrap();
+
+            for item in items.iter() {
+                changes.removed.insert(item.event_id.clone());
+            }
+            changes.updated.clear();
+            changes.index = true;
+
+            items.clear();
+        }
+
+        self.inner.persist().await
+    }
+}
+
+/// The positions of the events in the order in which they were received.
+#[derive(Debug, Default)]
+struct EventPositions {
+    /// The position of the next received event.
+    next: u64,
+
+    /// The positions of the latest timeline events of each room, including
+    /// the ones that didn't notify.
+    recent: HashMap<OwnedRoomId, VecDeque<(OwnedEventId, u64)>>,
+}
+
+/// The changes of the items that weren't persisted yet.
+#[derive(Debug, Default)]
+struct PendingChanges {
+    /// The items that were added or modified.
+    updated: BTreeSet<OwnedEventId>,
+
+    /// The items that were removed.
+    removed: BTreeSet<OwnedEventId>,
+
+    /// Whether the list of items changed.
+    index: bool,
+}
+
+impl PendingChanges {
+    fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.removed.is_empty() && !self.index
+    }
+
+    /// Merge older changes that couldn't be persisted into these ones.
+    fn merge_older(&mut self, older: PendingChanges) {
+        for event_id in older.updated {
+            if !self.removed.contains(&event_id) {
+                self.updated.insert(event_id);
+            }
+        }
+        for event_id in older.removed {
+            if !self.updated.contains(&event_id) {
+                self.removed.insert(event_id);
+            }
+        }
+        self.index |= older.index;
+    }
+}
+
+/// The position of the event targeted by a read receipt.
+#[derive(Debug)]
+struct ReceiptPosition {
+    thread: ReceiptThread,
+    position: u64,
+}
+
+#[derive(Debug)]
+struct NotificationInboxInner {
+    client: Client,
+    items: Mutex<ObservableVector<NotificationInboxItem>>,
+    max_items: usize,
+    positions: Mutex<EventPositions>,
+    changes: Mutex<PendingChanges>,
+
+    /// Lock held while persisting the changes, so that they are written in
+    /// order.
+    persist_lock: tokio::sync::Mutex<()>,
+}
+
+impl NotificationInboxInner {
+    async fn handle_room_updates(&self, updates: RoomUpdates) {
+        for (room_id, update) in &updates.joined {
+            let Some(room) = self.client.get_room(room_id) else {
+                warn!(?room_id, "Room not found, skipping its notifications");
+                continue;
+            };
+
+            for event in &update.timeline.events {
+                let position = self.next_position(room_id, event.event_id());
+
+                if let Some(item) = Self::item_for_event(&room, event, position).await {
+                    self.insert(item);
+                }
+            }
+
+            // The invite, if any, was accepted.
+            self.mark_as_read_where(|item| item.is_invite && item.room_id == *room_id);
+
+            self.refresh_read_status(&room).await;
+        }
+
+        for room_id in updates.left.keys() {
+            // The invite, if any, was rejected.
+            self.mark_as_read_where(|item| item.is_invite && item.room_id == *room_id);
+        }
+
+        for (room_id, update) in &updates.invited {
+            let Some(room) = self.client.get_room(room_id) else {
+                warn!(?room_id, "Room not found, skipping its invite");
+                continue;
+            };
+
+            if let Some(item) = self.item_for_invite(&room, &update.invite_state.events).await {
+                self.insert(item);
+            }
+        }
+
+        if let Err(error) = self.persist().await {
+            error!("Failed to persist the notification inbox: {error}");
+        }
+    }
+
+    /// Get the position of a newly received event, and remember it if it has
+    /// an ID.
+    fn next_position(&self, room_id: &RoomId, event_id: Option<OwnedEventId>) -> u64 {
+        let mut positions = self.positions.lock().unwrap();
+
+        let position = positions.next;
+        positions.next += 1;
+
+        if let Some(event_id) = event_id {
+            let recent = positions.recent.entry(room_id.to_owned()).or_default();
+            recent.push_back((event_id, position));
+
+            if recent.len() > NUM_RECENT_EVENTS_PER_ROOM {
+                recent.pop_front();
+            }
+        }
+
+        position
+    }
+
+    /// Get the position of an event of a room, if it is known.
+    fn position_of(&self, room_id: &RoomId, event_id: &EventId) -> Option<u64> {
+        let recent_position =
+            self.positions.lock().unwrap().recent.get(room_id).and_then(|recent| {
+                recent.iter().rev().find(|(id, _)| id == event_id).map(|(_, position)| *position)
+            });
+
+        recent_position.or_else(|| {
+            self.items
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|item| item.room_id == room_id && item.event_id == event_id)
+                .map(|item| item.position)
+        })
+    }
+
+    /// Build an item for an event, if its push actions notify.
+    async fn item_for_event(
+        room: &Room,
+        event: &TimelineEvent,
+        position: u64,
+    ) -> Option<NotificationInboxItem> {
+        let actions = match event.push_actions() {
+            Some(actions) => actions.to_owned(),
+            None => match room.event_push_actions(event.raw()).await {
+                Ok(Some(actions)) => actions,
+                Ok(None) => return None,
+                Err(error) => {
+                    warn!("Failed to compute the push actions of an event: {error}");
+                    return None;
+                }
+            },
+        };
+
+        if !actions.iter().any(|action| action.should_notify()) {
+            return None;
+        }
+
+        #[derive(Deserialize)]
+        struct RelatesTo {
+            rel_type: Option<String>,
+            event_id: Option<OwnedEventId>,
+        }
+
+        #[derive(Deserialize)]
+        struct Content {
+            body: Option<String>,
+            #[serde(rename = "m.relates_to")]
+            relates_to: Option<RelatesTo>,
+        }
+
+        let raw = event.raw();
+        let event_id = event.event_id()?;
+        let sender = raw.get_field::<OwnedUserId>("sender").ok().flatten()?;
+        let timestamp =
+            raw.get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts").ok().flatten()?;
+
+        if sender == room.own_user_id() {
+            return None;
+        }
+
+        trace!(room_id = ?room.room_id(), ?event_id, "Recording a notification");
+
+        let content = raw.get_field::<Content>("content").ok().flatten();
+        let (body, relates_to) =
+            content.map(|content| (content.body, content.relates_to)).unwrap_or_default();
+        let thread_root = relates_to
+            .filter(|relates_to| relates_to.rel_type.as_deref() == Some("m.thread"))
+            .and_then(|relates_to| relates_to.event_id);
+
+        Some(NotificationInboxItem {
+            room_id: room.room_id().to_owned(),
+            event_id,
+            sender,
+            timestamp,
+            is_highlight: actions.iter().any(|action| action.is_highlight()),
+            is_read: false,
+            body,
+            thread_root,
+            is_invite: false,
+            event: raw.clone(),
+            position,
+        })
+    }
+
+    /// Build an item for the invite of the current user to a room, if its
+    /// push actions notify.
+    async fn item_for_invite(
+        &self,
+        room: &Room,
+        invite_state: &[Raw<AnyStrippedStateEvent>],
+    ) -> Option<NotificationInboxItem> {
+        let own_user_id = room.own_user_id();
+
+        let raw = invite_state.iter().rev().find(|raw| {
+            raw.get_field::<String>("type").ok().flatten().as_deref() == Some("m.room.member")
+                && raw.get_field::<OwnedUserId>("state_key").ok().flatten().as_deref()
+                    == Some(own_user_id)
+        })?;
+
+        let Some(event_id) = raw.get_field::<OwnedEventId>("event_id").ok().flatten() else {
+            warn!(room_id = ?room.room_id(), "The invite has no event ID, it can't be recorded");
+            return None;
+        };
+
+        let actions = match room.event_push_actions(raw).await {
+            Ok(Some(actions)) => actions,
+            Ok(None) => return None,
+            Err(error) => {
+                warn!("Failed to compute the push actions of an invite: {error}");
+                return None;
+            }
+        };
+
+        if !actions.iter().any(|action| action.should_notify()) {
+            return None;
+        }
+
+        let sender = raw.get_field::<OwnedUserId>("sender").ok().flatten()?;
+        let timestamp = raw
+            .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+            .ok()
+            .flatten()
+            .unwrap_or_else(MilliSecondsSinceUnixEpoch::now);
+
+        trace!(room_id = ?room.room_id(), ?event_id, "Recording an invite");
+
+        Some(NotificationInboxItem {
+            room_id: room.room_id().to_owned(),
+            position: self.next_position(room.room_id(), Some(event_id.clone())),
+            event_id,
+            sender,
+            timestamp,
+            is_highlight: actions.iter().any(|action| action.is_highlight()),
+            is_read: false,
+            body: None,
+            thread_root: None,
+            is_invite: true,
+            event: raw.clone().cast_unchecked(),
+        })
+    }
+
+    /// Insert an item at the right position, from the most recent to the oldest
+    /// one, and drop the oldest items if needed.
+    fn insert(&self, item: NotificationInboxItem) {
+        let mut items = self.items.lock().unwrap();
+
+        if items.iter().any(|other| other.event_id == item.event_id) {
+            return;
+        }
+
+        let index =
+            items.iter().position(|other| other.timestamp < item.timestamp).unwrap_or(items.len());
+
+        if index >= self.max_items {
+            return;
+        }
+
+        let mut changes = self.changes.lock().unwrap();
+        changes.removed.remove(&item.event_id);
+        changes.updated.insert(item.event_id.clone());
+        changes.index = true;
+
+        items.insert(index, item);
+
+        while items.len() > self.max_items {
+            if let Some(item) = items.pop_back() {
+                changes.updated.remove(&item.event_id);
+                changes.removed.insert(item.event_id);
+            }
+        }
+    }
+
+    /// Mark the items of a room as read, based on the room's unread counts and
+    /// on the read receipts of the current user.
+    async fn refresh_read_status(&self, room: &Room) {
+        let room_id = room.room_id();
+
+        // The threads of the unread items, `None` being the main timeline.
+        let unread_threads = self
+            .items
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|item| item.room_id == room_id && !item.is_read && !item.is_invite)
+            .map(|item| item.thread_root.clone())
+            .collect::<BTreeSet<_>>();
+
+        if unread_threads.is_empty() {
+            return;
+        }
+
+        let all_read = room.num_unread_notifications() == 0
+            && room.unread_notification_counts().notification_count == 0;
+
+        let mut receipts = Vec::new();
+
+        if !all_read {
+            let threads = [ReceiptThread::Unthreaded, ReceiptThread::Main]
+                .into_iter()
+                .chain(unread_threads.into_iter().flatten().map(ReceiptThread::Thread));
+
+            for thread in threads {
+                for receipt_type in [ReceiptType::Read, ReceiptType::ReadPrivate] {
+                    match room
+                        .load_user_receipt(receipt_type, thread.clone(), room.own_user_id())
+                        .await
+                    {
+                        Ok(Some((event_id, _))) => {
+                            // Receipts on events whose position is unknown can't be compared
+                            // with the items.
+                            if let Some(position) = self.position_of(room_id, &event_id) {
+                                receipts.push(ReceiptPosition { thread: thread.clone(), position });
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(error) => warn!("Failed to load the read receipt: {error}"),
+                    }
+                }
+            }
+        }
+
+        self.mark_as_read_where(|item| {
+            item.room_id == room_id
+                && !item.is_invite
+                && (all_read || receipts.iter().any(|receipt| is_read_by(item, receipt)))
+        });
+    }
+
+    fn mark_as_read_where(&self, predicate: impl Fn(&NotificationInboxItem) -> bool) {
+        let mut items = self.items.lock().unwrap();
+
+        let indices = items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| !item.is_read && predicate(item))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        if indices.is_empty() {
+            return;
+        }
+
+        let mut changes = self.changes.lock().unwrap();
+
+        for index in indices {
+            let mut item = items[index].clone();
+            item.is_read = true;
+            changes.updated.insert(item.event_id.clone());
+            items.set(index, item);
+        }
+    }
+
+    /// Save the items that changed in the state store.
+    async fn persist(&self) -> Result<(), StoreError> {
+        let _guard = self.persist_lock.lock().await;
+
+        let (changes, updated_items, index) = {
+            let items = self.items.lock().unwrap();
+            let changes = mem::take(&mut *self.changes.lock().unwrap());
+
+            if changes.is_empty() {
+                return Ok(());
+            }
+
+            let updated_items = items
+                .iter()
+                .filter(|item| changes.updated.contains(&item.event_id))
+                .cloned()
+                .collect::<Vec<_>>();
+            let index = changes
+                .index
+                .then(|| items.iter().map(|item| item.event_id.clone()).collect::<Vec<_>>());
+
+            (changes, updated_items, index)
+        };
+
+        let result = async {
+            let store = self.client.state_store();
+
+            for item in &updated_items {
+                store
+                    .set_custom_value(&item_store_key(&item.event_id), serde_json::to_vec(item)?)
+                    .await?;
+            }
+
+            for event_id in &changes.removed {
+                store.remove_custom_value(&item_store_key(event_id)).await?;
+            }
+
+            if let Some(index) = &index {
+                store
+                    .set_custom_value(NOTIFICATION_INBOX_STORE_KEY, serde_json::to_vec(index)?)
+                    .await?;
+            }
+
+            Ok::<_, StoreError>(())
+        }
+        .await;
+
+        if result.is_err() {
+            // Try again next time.
+            self.changes.lock().unwrap().merge_older(changes);
+        }
+
+        result
+    }
+}
+
+/// Whether an item is read, according to a read receipt of the current user.
+///
+/// Read receipts are cumulative: a receipt on an event acknowledges all the
+/// events received before it in the same thread, or in all the threads for an
+/// unthreaded receipt.
+fn is_read_by(item: &NotificationInboxItem, receipt: &ReceiptPosition) -> bool {
+    let applies_to_item = match &receipt.thread {
+        ReceiptThread::Unthreaded => true,
+        ReceiptThread::Main => item.thread_root.is_none(),
+        ReceiptThread::Thread(thread_root) => item.thread_root.as_ref() == Some(thread_root),
+        _ => false,
+    };
+
+    applies_to_item && receipt.position >= item.position
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::test_utils::logged_in_client;
+    use matrix_sdk_test::async_test;
+    use ruma::{event_id, owned_event_id, owned_room_id, owned_user_id, serde::Raw};
+    use serde_json::json;
+
+    use super::*;
+
+    fn item(event_id: &str, ts: u32, is_highlight: bool, body: &str) -> NotificationInboxItem {
+        NotificationInboxItem {
+            room_id: owned_room_id!("!r:b.c"),
+            event_id: event_id.try_into().unwrap(),
+            sender: owned_user_id!("@bob:b.c"),
+            timestamp: MilliSecondsSinceUnixEpoch(ts.into()),
+            is_highlight,
+            is_read: false,
+            body: Some(body.to_owned()),
+            thread_root: None,
+            is_invite: false,
+            event: Raw::new(&json!({})).unwrap().cast_unchecked(),
+            position: ts.into(),
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut mention = item("$a", 1, true, "Hello, Alice!");
+        let notification = item("$b", 2, false, "Deploy is done");
+
+        assert!(NotificationInboxFilter::default().matches(&mention));
+        assert!(NotificationInboxFilter::default().matches(&notification));
+
+        let mentions_only = NotificationInboxFilter { mentions_only: true, ..Default::default() };
+        assert!(mentions_only.matches(&mention));
+        assert!(!mentions_only.matches(&notification));
+
+        let keywords =
+            NotificationInboxFilter { keywords: vec!["DEPLOY".to_owned()], ..Default::default() };
+        assert!(!keywords.matches(&mention));
+        assert!(keywords.matches(&notification));
+
+        let other_room = NotificationInboxFilter {
+            room_id: Some(owned_room_id!("!o:b.c")),
+            ..Default::default()
+        };
+        assert!(!other_room.matches(&mention));
+        let same_room = NotificationInboxFilter {
+            room_id: Some(owned_room_id!("!r:b.c")),
+            ..Default::default()
+        };
+        assert!(same_room.matches(&mention));
+
+        let unread_only = NotificationInboxFilter { unread_only: true, ..Default::default() };
+        assert!(unread_only.matches(&mention));
+        mention.is_read = true;
+        assert!(!unread_only.matches(&mention));
+    }
+
+    #[test]
+    fn test_is_read_by() {
+        let item = item("$a", 10, false, "");
+
+        // A receipt on the item itself.
+        let receipt = ReceiptPosition { thread: ReceiptThread::Unthreaded, position: 10 };
+        assert!(is_read_by(&item, &receipt));
+
+        // A receipt on an event received before the item.
+        let receipt = ReceiptPosition { thread: ReceiptThread::Unthreaded, position: 5 };
+        assert!(!is_read_by(&item, &receipt));
+
+        // A receipt on an event received after the item.
+        let receipt = ReceiptPosition { thread: ReceiptThread::Main, position: 15 };
+        assert!(is_read_by(&item, &receipt));
+
+        // A receipt in a thread doesn't apply to the main timeline.
+        let receipt = ReceiptPosition {
+            thread: ReceiptThread::Thread(owned_event_id!("$root")),
+            position: 15,
+        };
+        assert!(!is_read_by(&item, &receipt));
+    }
+
+    #[test]
+    fn test_is_read_by_in_thread() {
+        let mut item = item("$a", 10, false, "");
+        item.thread_root = Some(owned_event_id!("$root"));
+
+        // A receipt on the main timeline doesn't apply to the thread.
+        let receipt = ReceiptPosition { thread: ReceiptThread::Main, position: 15 };
+        assert!(!is_read_by(&item, &receipt));
+
+        // A receipt in another thread doesn't apply either.
+        let receipt = ReceiptPosition {
+            thread: ReceiptThread::Thread(owned_event_id!("$other")),
+            position: 15,
+        };
+        assert!(!is_read_by(&item, &receipt));
+
+        // A receipt in the same thread or an unthreaded receipt do.
+        let receipt = ReceiptPosition {
+            thread: ReceiptThread::Thread(owned_event_id!("$root")),
+            position: 15,
+        };
+        assert!(is_read_by(&item, &receipt));
+        let receipt = ReceiptPosition { thread: ReceiptThread::Unthreaded, position: 15 };
+        assert!(is_read_by(&item, &receipt));
+    }
+
+    #[async_test]
+    async fn test_receipt_position_ignores_timestamps() {
+        let client = logged_in_client(None).await;
+        let inbox = NotificationInbox::new(client, 10).await.unwrap();
+        let room_id = owned_room_id!("!r:b.c");
+
+        // The item has a timestamp in the future, as if the clock of its sender was
+        // skewed, but it was received before the event targeted by the receipt.
+        let mut item = item("$a", 1000, false, "a");
+        item.position = inbox.inner.next_position(&room_id, Some(owned_event_id!("$a")));
+        inbox.inner.insert(item);
+        inbox.inner.next_position(&room_id, Some(owned_event_id!("$later")));
+
+        let receipt_position = inbox.inner.position_of(&room_id, event_id!("$later")).unwrap();
+        let item = &inbox.items()[0];
+        assert!(is_read_by(
+            item,
+            &ReceiptPosition { thread: ReceiptThread::Unthreaded, position: receipt_position }
+        ));
+
+        // Unknown events can't be located.
+        assert!(inbox.inner.position_of(&room_id, event_id!("$unknown")).is_none());
+    }
+
+    #[async_test]
+    async fn test_insert_and_persist() {
+        let client = logged_in_client(None).await;
+
+        {
+            let inbox = NotificationInbox::new(client.clone(), 2).await.unwrap();
+
+            inbox.inner.insert(item("$a", 1, false, "a"));
+            inbox.inner.persist().await.unwrap();
+            inbox.inner.insert(item("$c", 3, false, "c"));
+            inbox.inner.insert(item("$b", 2, false, "b"));
+            // Duplicates are ignored.
+            inbox.inner.insert(item("$b", 2, false, "b"));
+
+            // Items are sorted from the most recent to the oldest, and the oldest one has
+            // been dropped.
+            let items = inbox.items();
+            assert_eq!(items.len(), 2);
+            assert_eq!(items[0].event_id, event_id!("$c"));
+            assert_eq!(items[1].event_id, event_id!("$b"));
+            assert_eq!(inbox.num_unread(false), 2);
+
+            inbox.mark_as_read(event_id!("$c")).await.unwrap();
+            assert_eq!(inbox.num_unread(false), 1);
+
+            // Nothing is left to persist.
+            assert!(inbox.inner.changes.lock().unwrap().is_empty());
+        }
+
+        // The dropped item was removed from the store.
+        let store = client.state_store();
+        assert!(store.get_custom_value(&item_store_key(event_id!("$a"))).await.unwrap().is_none());
+        assert!(store.get_custom_value(&item_store_key(event_id!("$b"))).await.unwrap().is_some());
+
+        // The items are reloaded from the store.
+        let inbox = NotificationInbox::new(client, 2).await.unwrap();
+        let items = inbox.items();
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_read);
+        assert!(!items[1].is_read);
+
+        // New events are received after the reloaded ones.
+        let position = inbox.inner.next_position(&owned_room_id!("!r:b.c"), None);
+        assert!(position > items[0].position);
+
+        let (values, _stream, _paginator) =
+            inbox.entries(NotificationInboxFilter { unread_only: true, ..Default::default() }, 10);
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].event_id, event_id!("$b"));
+    }
+}
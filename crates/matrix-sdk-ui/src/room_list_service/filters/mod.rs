@@ -65,7 +65,9 @@ mod none;
 mod normalized_match_room_name;
 mod not;
 mod space;
+mod tag;
 mod unread;
+mod untagged;
 
 pub use all::new_filter as new_filter_all;
 pub use any::new_filter as new_filter_any;
@@ -80,7 +82,7 @@ pub use low_priority::new_filter as new_filter_low_priority;
 use matrix_sdk::Client;
 use matrix_sdk::Room;
 #[cfg(test)]
-use matrix_sdk_test::{JoinedRoomBuilder, SyncResponseBuilder};
+use matrix_sdk_test::{JoinedRoomBuilder, RoomAccountDataTestEvent, SyncResponseBuilder};
 pub use non_left::new_filter as new_filter_non_left;
 pub use none::new_filter as new_filter_none;
 pub use normalized_match_room_name::new_filter as new_filter_normalized_match_room_name;
@@ -88,8 +90,10 @@ pub use not::new_filter as new_filter_not;
 #[cfg(test)]
 use ruma::RoomId;
 pub use space::new_filter as new_filter_space;
+pub use tag::new_filter as new_filter_tag;
 use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
 pub use unread::new_filter as new_filter_unread;
+pub use untagged::new_filter as new_filter_untagged;
 #[cfg(test)]
 use wiremock::{
     Mock, MockServer, ResponseTemplate,
@@ -142,6 +146,38 @@ pub(super) async fn new_rooms<const N: usize>(
     room_ids.map(|room_id| client.get_room(room_id).unwrap())
 }
 
+/// Like [`new_rooms`], but each room is tagged with the given `m.tag` tags.
+#[cfg(test)]
+pub(super) async fn new_rooms_with_tags<const N: usize>(
+    rooms: [(&RoomId, serde_json::Value); N],
+    client: &Client,
+    server: &MockServer,
+) -> [Room; N] {
+    let mut response_builder = SyncResponseBuilder::default();
+
+    for (room_id, tags) in &rooms {
+        response_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_account_data(
+            RoomAccountDataTestEvent::Custom(serde_json::json!({
+                "type": "m.tag",
+                "content": { "tags": tags },
+            })),
+        ));
+    }
+
+    let json_response = response_builder.build_json_sync_response();
+
+    let _scope = Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/sync"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json_response))
+        .mount_as_scoped(server)
+        .await;
+
+    let _response = client.sync_once(Default::default()).await.unwrap();
+
+    rooms.map(|(room_id, _)| client.get_room(room_id).unwrap())
+}
+
 #[cfg(test)]
 mod tests {
     use super::normalize_string;
@@ -0,0 +1,92 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ruma::events::tag::TagName;
+
+use super::{super::Room, Filter};
+
+struct TagRoomMatcher<F>
+where
+    F: Fn(&Room) -> bool,
+{
+    has_tag: F,
+}
+
+impl<F> TagRoomMatcher<F>
+where
+    F: Fn(&Room) -> bool,
+{
+    fn matches(&self, room: &Room) -> bool {
+        (self.has_tag)(room)
+    }
+}
+
+/// Create a new filter that will filter out rooms that are not tagged with
+/// `tag_name` (see [`matrix_sdk_base::Room::with_cached_tags`]).
+///
+/// This is typically used with user-defined tags (`u.*`), see
+/// [`super::super::RoomListService::user_tags`] to list them.
+pub fn new_filter(tag_name: TagName) -> impl Filter {
+    let matcher = TagRoomMatcher {
+        has_tag: move |room| room.with_cached_tags(|tags| tags.contains_key(&tag_name)),
+    };
+
+    move |room| -> bool { matcher.matches(room) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use matrix_sdk::test_utils::logged_in_client_with_server;
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+    use serde_json::json;
+
+    use super::{
+        super::{new_rooms, new_rooms_with_tags},
+        *,
+    };
+
+    #[async_test]
+    async fn test_has_tag() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room] = new_rooms_with_tags(
+            [(room_id!("!a:b.c"), json!({ "u.work": {}, "m.favourite": { "order": 0.5 } }))],
+            &client,
+            &server,
+        )
+        .await;
+
+        assert!(new_filter(TagName::User("u.work".parse().unwrap()))(&room));
+        assert!(new_filter(TagName::Favorite)(&room));
+    }
+
+    #[async_test]
+    async fn test_has_not_tag() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [tagged_room] =
+            new_rooms_with_tags([(room_id!("!a:b.c"), json!({ "u.home": {} }))], &client, &server)
+                .await;
+        let [untagged_room] = new_rooms([room_id!("!d:e.f")], &client, &server).await;
+
+        let filter = new_filter(TagName::User("u.work".parse().unwrap()));
+
+        // A room with other tags doesn't match.
+        assert!(filter(&tagged_room).not());
+
+        // Neither does a room without any tag.
+        assert!(filter(&untagged_room).not());
+    }
+}
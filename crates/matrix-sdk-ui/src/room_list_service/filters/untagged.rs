@@ -0,0 +1,109 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ruma::events::tag::TagName;
+
+use super::{super::Room, Filter};
+
+struct UntaggedRoomMatcher<F>
+where
+    F: Fn(&Room) -> bool,
+{
+    has_user_tags: F,
+}
+
+impl<F> UntaggedRoomMatcher<F>
+where
+    F: Fn(&Room) -> bool,
+{
+    fn matches(&self, room: &Room) -> bool {
+        !(self.has_user_tags)(room)
+    }
+}
+
+/// Create a new filter that will filter out rooms that have at least one
+/// user-defined tag, i.e. a tag in the `u.*` namespace (see
+/// [`matrix_sdk_base::Room::with_cached_tags`]).
+///
+/// Other tags, like `m.favourite` or `m.lowpriority`, are ignored by this
+/// filter.
+pub fn new_filter() -> impl Filter {
+    let matcher = UntaggedRoomMatcher {
+        has_user_tags: move |room| {
+            room.with_cached_tags(|tags| {
+                tags.keys().any(|tag_name| matches!(tag_name, TagName::User(_)))
+            })
+        },
+    };
+
+    move |room| -> bool { matcher.matches(room) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use matrix_sdk::test_utils::logged_in_client_with_server;
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+    use serde_json::json;
+
+    use super::{
+        super::{new_rooms, new_rooms_with_tags},
+        *,
+    };
+
+    #[async_test]
+    async fn test_is_untagged() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room] = new_rooms([room_id!("!a:b.c")], &client, &server).await;
+
+        let matcher = UntaggedRoomMatcher { has_user_tags: |_| false };
+
+        assert!(matcher.matches(&room));
+
+        // A room without any tag is untagged.
+        assert!(new_filter()(&room));
+    }
+
+    #[async_test]
+    async fn test_is_not_untagged() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room] = new_rooms([room_id!("!a:b.c")], &client, &server).await;
+
+        let matcher = UntaggedRoomMatcher { has_user_tags: |_| true };
+
+        assert!(matcher.matches(&room).not());
+    }
+
+    #[async_test]
+    async fn test_only_user_tags_count() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [user_tagged_room, favourite_room] = new_rooms_with_tags(
+            [
+                (room_id!("!a:b.c"), json!({ "u.work": {} })),
+                (room_id!("!d:e.f"), json!({ "m.favourite": {} })),
+            ],
+            &client,
+            &server,
+        )
+        .await;
+
+        let filter = new_filter();
+
+        assert!(filter(&user_tagged_room).not());
+        // Tags outside of the `u.*` namespace are ignored.
+        assert!(filter(&favourite_room));
+    }
+}
@@ -56,19 +56,21 @@ mod room_list;
 pub mod sorters;
 mod state;
 
-use std::{sync::Arc, time::Duration};
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
 
 use async_stream::stream;
 use eyeball::Subscriber;
 use futures_util::{Stream, StreamExt, pin_mut};
 use matrix_sdk::{
-    Client, Error as SlidingSyncError, Room, SlidingSync, SlidingSyncList, SlidingSyncMode,
-    event_cache::EventCacheError, timeout::timeout,
+    Client, Error as SlidingSyncError, Room, RoomState, SlidingSync, SlidingSyncList,
+    SlidingSyncMode, event_cache::EventCacheError, timeout::timeout,
 };
 pub use room_list::*;
 use ruma::{
-    OwnedRoomId, RoomId, UInt, api::client::sync::sync_events::v5 as http, assign,
-    events::StateEventType,
+    OwnedRoomId, RoomId, UInt,
+    api::client::sync::sync_events::v5 as http,
+    assign,
+    events::{StateEventType, tag::UserTagName},
 };
 pub use state::*;
 use thiserror::Error;
@@ -399,6 +401,22 @@ impl RoomListService {
         self.client.get_room(room_id).ok_or_else(|| Error::RoomNotFound(room_id.to_owned()))
     }
 
+    /// Get all the user-defined tags (i.e. in the `u.*` namespace) that are in
+    /// use across the non-left rooms, sorted by name and without duplicates.
+    ///
+    /// It is useful to build a list of “folders”, that can be combined with
+    /// [`filters::new_filter_tag`] and [`sorters::new_sorter_tag_order`].
+    pub fn user_tags(&self) -> Vec<UserTagName> {
+        self.client
+            .rooms()
+            .into_iter()
+            .filter(|room| room.state() != RoomState::Left)
+            .flat_map(|room| room.user_tags())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
     /// Subscribe to rooms.
     ///
     /// It means that all events from these rooms will be received every time,
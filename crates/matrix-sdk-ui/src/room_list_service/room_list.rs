@@ -12,7 +12,7 @@
 // See the License for that specific language governing permissions and
 // limitations under the License.
 
-use std::{future::ready, sync::Arc};
+use std::{cmp::Reverse, future::ready, sync::Arc};
 
 use async_cell::sync::AsyncCell;
 use async_rx::StreamExt as _;
@@ -24,6 +24,7 @@ use futures_util::{Stream, StreamExt as _, pin_mut, stream};
 use matrix_sdk::{
     Client, SlidingSync, SlidingSyncList,
     executor::{JoinHandle, spawn},
+    media::MediaScheduler,
 };
 use matrix_sdk_base::RoomInfoNotableUpdate;
 use tokio::{
@@ -179,6 +180,41 @@ impl RoomList {
 
         (stream, dynamic_entries_controller)
     }
+
+    /// Prefetch the thumbnails of the latest events of the joined rooms, in
+    /// the background.
+    ///
+    /// At most `limit` rooms are considered, starting with the most recently
+    /// active ones. The downloads go through the given [`MediaScheduler`], so
+    /// they respect the media previews policy of the user, and have the
+    /// lowest priority. Unless the scheduler allows a single download at a
+    /// time, they never take the last free download slot, so the media that
+    /// is displayed doesn't wait for them; they still count towards the
+    /// scheduler's bandwidth limit, though.
+    ///
+    /// Returns the number of thumbnails that were queued.
+    pub async fn prefetch_latest_event_thumbnails(
+        &self,
+        scheduler: &MediaScheduler,
+        limit: usize,
+    ) -> usize {
+        let mut rooms = self.client.joined_rooms();
+        rooms.sort_by_key(|room| Reverse(room.recency_stamp()));
+
+        let mut queued = 0;
+
+        for room in rooms.into_iter().take(limit) {
+            match scheduler.prefetch_latest_event_thumbnail(&room).await {
+                Ok(true) => queued += 1,
+                Ok(false) => {}
+                Err(err) => {
+                    error!(room_id = ?room.room_id(), "Failed to prefetch the latest event thumbnail: {err}");
+                }
+            }
+        }
+
+        queued
+    }
 }
 
 /// This function remembers the current state of the unfiltered room list, so it
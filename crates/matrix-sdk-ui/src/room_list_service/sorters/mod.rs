@@ -16,13 +16,17 @@
 
 mod lexicographic;
 mod name;
+mod priority;
 mod recency;
+mod tag_order;
 
 use std::cmp::Ordering;
 
 pub use lexicographic::new_sorter as new_sorter_lexicographic;
 pub use name::new_sorter as new_sorter_name;
+pub use priority::{PrioritySorterConfig, new_sorter as new_sorter_priority};
 pub use recency::new_sorter as new_sorter_recency;
+pub use tag_order::new_sorter as new_sorter_tag_order;
 
 use super::Room;
 
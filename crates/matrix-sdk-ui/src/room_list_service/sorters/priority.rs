@@ -0,0 +1,292 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use ruma::OwnedRoomId;
+
+use super::{Room, Sorter};
+
+/// Configure which signals the [`new_sorter`] sorter uses to rank rooms.
+///
+/// The signals are evaluated in the order of the fields of this struct: a room
+/// with mentions always comes before a room without mentions, whatever the
+/// other signals are. When all the enabled signals are equal, rooms are sorted
+/// by recency.
+#[derive(Clone, Debug)]
+pub struct PrioritySorterConfig {
+    /// Rank rooms with unread mentions (or highlights) first.
+    pub mentions: bool,
+
+    /// Then, rank rooms with unread notifications first.
+    pub notifications: bool,
+
+    /// Then, rank rooms that are manually marked as unread first.
+    pub marked_unread: bool,
+
+    /// Then, rank rooms that are marked as favourite first.
+    pub favourite: bool,
+
+    /// Then, rank rooms that are marked as low priority last.
+    pub low_priority: bool,
+
+    /// Then, rank the rooms that have been visited recently first, the most
+    /// recently visited room coming first.
+    ///
+    /// This list is typically the result of
+    /// [`matrix_sdk::Account::get_recently_visited_rooms`]. It is empty by
+    /// default, i.e. no room is boosted.
+    pub recently_visited_rooms: Vec<OwnedRoomId>,
+}
+
+impl Default for PrioritySorterConfig {
+    fn default() -> Self {
+        Self {
+            mentions: true,
+            notifications: true,
+            marked_unread: true,
+            favourite: true,
+            low_priority: true,
+            recently_visited_rooms: Vec::new(),
+        }
+    }
+}
+
+/// The signals used to rank a single room.
+#[derive(Clone, Debug, Default)]
+struct RoomPriority {
+    has_mentions: bool,
+    has_notifications: bool,
+    is_marked_unread: bool,
+    is_favourite: bool,
+    is_low_priority: bool,
+    recently_visited_position: Option<usize>,
+    recency_stamp: Option<u64>,
+}
+
+struct PriorityMatcher<F>
+where
+    F: Fn(&Room) -> RoomPriority,
+{
+    config: PrioritySorterConfig,
+    priority: F,
+}
+
+impl<F> PriorityMatcher<F>
+where
+    F: Fn(&Room) -> RoomPriority,
+{
+    fn matches(&self, left: &Room, right: &Room) -> Ordering {
+        if left.room_id() == right.room_id() {
+            // `left` and `right` are the same room, which means one of them has received
+            // an update. See the recency sorter to learn more about why
+            // `Ordering::Greater` is returned here.
+            return Ordering::Greater;
+        }
+
+        let left = (self.priority)(left);
+        let right = (self.priority)(right);
+
+        // For each boolean signal, `true` must come first, hence the `reverse()`.
+        let flag = |enabled: bool, left: bool, right: bool| {
+            if enabled { left.cmp(&right).reverse() } else { Ordering::Equal }
+        };
+
+        flag(self.config.mentions, left.has_mentions, right.has_mentions)
+            .then_with(|| {
+                flag(self.config.notifications, left.has_notifications, right.has_notifications)
+            })
+            .then_with(|| {
+                flag(self.config.marked_unread, left.is_marked_unread, right.is_marked_unread)
+            })
+            .then_with(|| flag(self.config.favourite, left.is_favourite, right.is_favourite))
+            // Low priority rooms must come last, so no `reverse()` here.
+            .then_with(|| {
+                if self.config.low_priority {
+                    left.is_low_priority.cmp(&right.is_low_priority)
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .then_with(|| match (left.recently_visited_position, right.recently_visited_position) {
+                (Some(left), Some(right)) => left.cmp(&right),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            })
+            .then_with(|| match (left.recency_stamp, right.recency_stamp) {
+                (Some(left), Some(right)) => left.cmp(&right).reverse(),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            })
+    }
+}
+
+/// Create a new sorter that will sort two [`Room`] by priority, also known as
+/// “importance”.
+///
+/// Rooms are ranked, in this order, by: unread mentions, unread notifications,
+/// marked as unread, favourite, low priority, recently visited, and finally by
+/// recency (see [`super::new_sorter_recency`]). Each signal, except recency,
+/// can be disabled through [`PrioritySorterConfig`].
+///
+/// Unread counts are taken from both the server-side counts
+/// ([`matrix_sdk_base::Room::unread_notification_counts`]) and the client-side
+/// counts ([`matrix_sdk_base::Room::read_receipts`]).
+pub fn new_sorter(config: PrioritySorterConfig) -> impl Sorter {
+    let recently_visited_rooms = config.recently_visited_rooms.clone();
+
+    let matcher = PriorityMatcher {
+        config,
+        priority: move |room| {
+            let read_receipts = room.read_receipts();
+            let counts = room.unread_notification_counts();
+
+            RoomPriority {
+                has_mentions: read_receipts.num_mentions > 0 || counts.highlight_count > 0,
+                has_notifications: read_receipts.num_notifications > 0
+                    || counts.notification_count > 0,
+                is_marked_unread: room.is_marked_unread(),
+                is_favourite: room.is_favourite(),
+                is_low_priority: room.is_low_priority(),
+                recently_visited_position: recently_visited_rooms
+                    .iter()
+                    .position(|room_id| room_id == room.room_id()),
+                recency_stamp: room.recency_stamp(),
+            }
+        },
+    };
+
+    move |left, right| -> Ordering { matcher.matches(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::test_utils::logged_in_client_with_server;
+    use matrix_sdk_test::async_test;
+    use ruma::{RoomId, room_id};
+
+    use super::{super::super::filters::new_rooms, *};
+
+    fn matcher(
+        config: PrioritySorterConfig,
+        left: RoomPriority,
+        right: RoomPriority,
+        left_room_id: &RoomId,
+    ) -> PriorityMatcher<impl Fn(&Room) -> RoomPriority> {
+        let left_room_id = left_room_id.to_owned();
+
+        PriorityMatcher {
+            config,
+            priority: move |room| {
+                if room.room_id() == left_room_id { left.clone() } else { right.clone() }
+            },
+        }
+    }
+
+    #[async_test]
+    async fn test_mentions_come_first() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server).await;
+
+        // `room_a` has a mention, `room_b` is a favourite with notifications.
+        let matcher = matcher(
+            PrioritySorterConfig::default(),
+            RoomPriority { has_mentions: true, ..Default::default() },
+            RoomPriority { has_notifications: true, is_favourite: true, ..Default::default() },
+            room_a.room_id(),
+        );
+
+        assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Less);
+        assert_eq!(matcher.matches(&room_b, &room_a), Ordering::Greater);
+    }
+
+    #[async_test]
+    async fn test_disabled_signals_are_ignored() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server).await;
+
+        // `room_a` has a mention, but mentions are ignored; `room_b` is more recent.
+        let matcher = matcher(
+            PrioritySorterConfig { mentions: false, ..Default::default() },
+            RoomPriority { has_mentions: true, recency_stamp: Some(1), ..Default::default() },
+            RoomPriority { recency_stamp: Some(2), ..Default::default() },
+            room_a.room_id(),
+        );
+
+        assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Greater);
+    }
+
+    #[async_test]
+    async fn test_low_priority_comes_last() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server).await;
+
+        // `room_a` is low priority but more recent than `room_b`.
+        let matcher = matcher(
+            PrioritySorterConfig::default(),
+            RoomPriority { is_low_priority: true, recency_stamp: Some(2), ..Default::default() },
+            RoomPriority { recency_stamp: Some(1), ..Default::default() },
+            room_a.room_id(),
+        );
+
+        assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Greater);
+    }
+
+    #[async_test]
+    async fn test_recently_visited_rooms_are_boosted() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server).await;
+
+        // `room_a` is older, but has been visited more recently than `room_b`.
+        let matcher = matcher(
+            PrioritySorterConfig::default(),
+            RoomPriority {
+                recently_visited_position: Some(0),
+                recency_stamp: Some(1),
+                ..Default::default()
+            },
+            RoomPriority {
+                recently_visited_position: Some(1),
+                recency_stamp: Some(2),
+                ..Default::default()
+            },
+            room_a.room_id(),
+        );
+
+        assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Less);
+    }
+
+    #[async_test]
+    async fn test_fallback_on_recency() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server).await;
+
+        let matcher = matcher(
+            PrioritySorterConfig::default(),
+            RoomPriority { is_favourite: true, recency_stamp: Some(2), ..Default::default() },
+            RoomPriority { is_favourite: true, recency_stamp: Some(1), ..Default::default() },
+            room_a.room_id(),
+        );
+
+        assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Less);
+        assert_eq!(matcher.matches(&room_a, &room_a), Ordering::Greater);
+    }
+}
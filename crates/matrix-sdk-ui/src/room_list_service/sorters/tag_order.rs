@@ -0,0 +1,212 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use ruma::events::tag::TagName;
+
+use super::{Room, Sorter};
+
+/// The position of a room for a given tag.
+#[derive(Clone, Copy, Debug)]
+enum TagPosition {
+    /// The room has the tag, with an `order`.
+    Ordered(f64),
+
+    /// The room has the tag, but without an `order`.
+    Unordered,
+
+    /// The room doesn't have the tag.
+    Untagged,
+}
+
+struct TagOrderMatcher<F>
+where
+    F: Fn(&Room, &Room) -> (TagPosition, TagPosition),
+{
+    positions: F,
+}
+
+impl<F> TagOrderMatcher<F>
+where
+    F: Fn(&Room, &Room) -> (TagPosition, TagPosition),
+{
+    fn matches(&self, left: &Room, right: &Room) -> Ordering {
+        match (self.positions)(left, right) {
+            (TagPosition::Ordered(left_order), TagPosition::Ordered(right_order)) => {
+                left_order.total_cmp(&right_order)
+            }
+
+            (TagPosition::Ordered(_), _) => Ordering::Less,
+            (_, TagPosition::Ordered(_)) => Ordering::Greater,
+
+            (TagPosition::Unordered, TagPosition::Untagged) => Ordering::Less,
+            (TagPosition::Untagged, TagPosition::Unordered) => Ordering::Greater,
+
+            (TagPosition::Unordered, TagPosition::Unordered)
+            | (TagPosition::Untagged, TagPosition::Untagged) => Ordering::Equal,
+        }
+    }
+}
+
+/// Create a new sorter that will sort two [`Room`] by the `order` of a tag,
+/// see [`ruma::events::tag::TagInfo::order`].
+///
+/// Rooms with the lowest `order` come first. As recommended by the
+/// specification, rooms that have the tag without an `order` come after the
+/// rooms with an `order`. Rooms that don't have the tag come last.
+///
+/// Rooms with the same position are equal, so this sorter is expected to be
+/// combined with other sorters, e.g. with [`super::new_sorter_lexicographic`]
+/// and [`super::new_sorter_recency`].
+pub fn new_sorter(tag_name: TagName) -> impl Sorter {
+    let position = move |room: &Room| {
+        room.with_cached_tags(|tags| match tags.get(&tag_name) {
+            Some(tag_info) => match tag_info.order {
+                Some(order) => TagPosition::Ordered(order),
+                None => TagPosition::Unordered,
+            },
+            None => TagPosition::Untagged,
+        })
+    };
+
+    let matcher =
+        TagOrderMatcher { positions: move |left, right| (position(left), position(right)) };
+
+    move |left, right| -> Ordering { matcher.matches(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk::test_utils::logged_in_client_with_server;
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+    use serde_json::json;
+
+    use super::{
+        super::super::filters::{new_rooms, new_rooms_with_tags},
+        *,
+    };
+
+    #[async_test]
+    async fn test_with_two_orders() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server).await;
+
+        // `room_a` has a greater order than `room_b`.
+        {
+            let matcher = TagOrderMatcher {
+                positions: |_left, _right| (TagPosition::Ordered(0.5), TagPosition::Ordered(0.2)),
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Greater);
+        }
+
+        // `room_a` has a lower order than `room_b`.
+        {
+            let matcher = TagOrderMatcher {
+                positions: |_left, _right| (TagPosition::Ordered(0.2), TagPosition::Ordered(0.5)),
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Less);
+        }
+
+        // `room_a` and `room_b` have the same order.
+        {
+            let matcher = TagOrderMatcher {
+                positions: |_left, _right| (TagPosition::Ordered(0.2), TagPosition::Ordered(0.2)),
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Equal);
+        }
+    }
+
+    #[async_test]
+    async fn test_with_one_order() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server).await;
+
+        // `room_a` has an order, `room_b` has the tag without an order.
+        {
+            let matcher = TagOrderMatcher {
+                positions: |_left, _right| (TagPosition::Ordered(0.9), TagPosition::Unordered),
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Less);
+        }
+
+        // `room_a` doesn't have the tag, `room_b` has an order.
+        {
+            let matcher = TagOrderMatcher {
+                positions: |_left, _right| (TagPosition::Untagged, TagPosition::Ordered(0.9)),
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Greater);
+        }
+    }
+
+    #[async_test]
+    async fn test_with_zero_order() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room_a, room_b] =
+            new_rooms([room_id!("!a:b.c"), room_id!("!d:e.f")], &client, &server).await;
+
+        // `room_a` has the tag without an order, `room_b` doesn't have the tag.
+        {
+            let matcher = TagOrderMatcher {
+                positions: |_left, _right| (TagPosition::Unordered, TagPosition::Untagged),
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Less);
+        }
+
+        // None of the rooms have the tag.
+        {
+            let matcher = TagOrderMatcher {
+                positions: |_left, _right| (TagPosition::Untagged, TagPosition::Untagged),
+            };
+
+            assert_eq!(matcher.matches(&room_a, &room_b), Ordering::Equal);
+
+            let sorter = new_sorter(TagName::Favorite);
+
+            assert_eq!(sorter(&room_a, &room_b), Ordering::Equal);
+        }
+    }
+
+    #[async_test]
+    async fn test_with_tagged_rooms() {
+        let (client, server) = logged_in_client_with_server().await;
+        let [room_a, room_b, room_c] = new_rooms_with_tags(
+            [
+                (room_id!("!a:b.c"), json!({ "u.work": {} })),
+                (room_id!("!d:e.f"), json!({ "u.work": { "order": 0.8 } })),
+                (room_id!("!g:h.i"), json!({ "u.work": { "order": 0.2 }, "u.home": {} })),
+            ],
+            &client,
+            &server,
+        )
+        .await;
+        let [room_d] = new_rooms([room_id!("!j:k.l")], &client, &server).await;
+
+        let sorter = new_sorter(TagName::User("u.work".parse().unwrap()));
+
+        assert_eq!(sorter(&room_c, &room_b), Ordering::Less);
+        assert_eq!(sorter(&room_b, &room_a), Ordering::Less);
+        assert_eq!(sorter(&room_a, &room_d), Ordering::Less);
+        assert_eq!(sorter(&room_d, &room_c), Ordering::Greater);
+    }
+}
@@ -27,6 +27,7 @@ use growable_bloom_filter::{GrowableBloom, GrowableBloomBuilder};
 use matrix_sdk::{
     Client,
     crypto::types::events::UtdCause,
+    encryption::utd_remediation::UtdRemediationOutcome,
     executor::{JoinHandle, spawn},
     sleep::sleep,
 };
@@ -54,6 +55,7 @@ pub trait UnableToDecryptHook: std::fmt::Debug + SendOutsideWasm + SyncOutsideWa
 
 /// Information about an event we were unable to decrypt (UTD).
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct UnableToDecryptInfo {
     /// The identifier of the event that couldn't get decrypted.
     pub event_id: OwnedEventId,
@@ -83,6 +85,12 @@ pub struct UnableToDecryptInfo {
     /// Our local user's own homeserver, or `None` if the client is not logged
     /// in.
     pub own_homeserver: Option<OwnedServerName>,
+
+    /// The outcome of the automatic remediation of this UTD, if it has been
+    /// remediated by the time it's reported.
+    ///
+    /// See [`matrix_sdk::encryption::utd_remediation`].
+    pub remediation: Option<UtdRemediationOutcome>,
 }
 
 /// Data about a UTD event which we are waiting to report to the parent hook.
@@ -279,6 +287,7 @@ impl UtdHookManager {
             user_trusts_own_identity,
             own_homeserver,
             sender_homeserver,
+            remediation: None,
         };
 
         let Some(max_delay) = self.max_delay else {
@@ -363,12 +372,13 @@ impl UtdHookManager {
     /// Must be called with the lock held on [`UtdHookManager::reported_utds`],
     /// and takes a `MutexGuard` to enforce that.
     async fn report_utd(
-        info: UnableToDecryptInfo,
+        mut info: UnableToDecryptInfo,
         parent_hook: &Arc<dyn UnableToDecryptHook>,
         client: &Client,
         reported_utds_lock: &mut MutexGuard<'_, GrowableBloom>,
     ) {
         let event_id = info.event_id.clone();
+        info.remediation = client.encryption().utd_remediation().outcome(&event_id);
         parent_hook.on_utd(info);
         reported_utds_lock.insert(event_id);
         if let Err(e) = client
@@ -14,6 +14,8 @@
 
 //! Types and traits for attachments.
 
+#[cfg(not(target_family = "wasm"))]
+use std::path::Path;
 use std::time::Duration;
 
 use ruma::{
@@ -27,9 +29,15 @@ use ruma::{
     },
     OwnedTransactionId, UInt,
 };
+use tracing::debug;
 
+pub use self::voice::{VoiceMetadata, VoiceMetadataError};
+#[cfg(not(target_family = "wasm"))]
+use crate::media::UploadStream;
 use crate::room::reply::Reply;
 
+mod voice;
+
 /// Base metadata about an image.
 #[derive(Debug, Clone, Default)]
 pub struct BaseImageInfo {
@@ -88,6 +96,12 @@ pub enum AttachmentInfo {
     /// The metadata of a file.
     File(BaseFileInfo),
     /// The metadata of a voice message
+    ///
+    /// When the content is sent from memory, or from a file that isn't bigger
+    /// than 32 MiB, a missing duration or waveform is computed from it if its
+    /// format is supported by [`VoiceMetadata::extract()`]. The waveform of
+    /// Opus audio is only an estimate, see
+    /// [`VoiceMetadata::waveform_is_estimated`].
     Voice {
         /// The audio info
         audio_info: BaseAudioInfo,
@@ -96,6 +110,78 @@ pub enum AttachmentInfo {
     },
 }
 
+impl AttachmentInfo {
+    /// The maximum size of a file that is read to compute the metadata of a
+    /// voice message, in bytes.
+    #[cfg(not(target_family = "wasm"))]
+    const MAX_VOICE_FILE_SIZE: u64 = 32 * 1024 * 1024;
+
+    /// Whether this is the info of a voice message which is missing its
+    /// duration or its waveform.
+    fn is_missing_voice_metadata(&self) -> bool {
+        matches!(
+            self,
+            AttachmentInfo::Voice { audio_info, waveform }
+                if audio_info.duration.is_none() || waveform.is_none()
+        )
+    }
+
+    /// Compute the duration and the waveform of a voice message from its
+    /// content, if they're missing and its format is supported.
+    pub(crate) fn fill_voice_metadata(&mut self, content_type: &mime::Mime, data: &[u8]) {
+        if !self.is_missing_voice_metadata() {
+            return;
+        }
+
+        let AttachmentInfo::Voice { audio_info, waveform } = self else {
+            return;
+        };
+
+        // Let `VoiceMetadata::extract()` tell which formats it supports, since it also
+        // accepts non-audio types, like `application/ogg`.
+        match VoiceMetadata::extract(content_type, data, VoiceMetadata::DEFAULT_WAVEFORM_RESOLUTION)
+        {
+            Ok(metadata) => {
+                audio_info.duration.get_or_insert(metadata.duration);
+                waveform.get_or_insert(metadata.waveform);
+            }
+            Err(err) => {
+                debug!("couldn't compute the metadata of the voice message: {err}");
+            }
+        }
+    }
+
+    /// Compute the duration and the waveform of a voice message from the
+    /// content of the file at the given path, if they're missing and its
+    /// format is supported.
+    ///
+    /// The file is only read if it isn't bigger than
+    /// [`Self::MAX_VOICE_FILE_SIZE`].
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) async fn fill_voice_metadata_from_file(
+        &mut self,
+        content_type: &mime::Mime,
+        path: &Path,
+        file_size: u64,
+    ) {
+        if !self.is_missing_voice_metadata() {
+            return;
+        }
+
+        if file_size > Self::MAX_VOICE_FILE_SIZE {
+            debug!(file_size, "the voice message is too big to compute its metadata");
+            return;
+        }
+
+        match tokio::fs::read(path).await {
+            Ok(data) => self.fill_voice_metadata(content_type, &data),
+            Err(err) => {
+                debug!("couldn't read the voice message to compute its metadata: {err}");
+            }
+        }
+    }
+}
+
 impl From<AttachmentInfo> for ImageInfo {
     fn from(info: AttachmentInfo) -> Self {
         match info {
@@ -181,6 +267,17 @@ impl Thumbnail {
     }
 }
 
+/// Where the content of an attachment comes from.
+#[derive(Debug)]
+pub(crate) enum AttachmentSource {
+    /// The content is entirely held in memory.
+    Data(Vec<u8>),
+
+    /// The content is read while it's being uploaded.
+    #[cfg(not(target_family = "wasm"))]
+    Stream(UploadStream),
+}
+
 /// Configuration for sending an attachment.
 #[derive(Debug, Default)]
 pub struct AttachmentConfig {
@@ -0,0 +1,650 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Extraction of the duration and waveform of voice messages.
+
+use std::{mem, time::Duration};
+
+use mime::Mime;
+
+/// The maximum amplitude in a waveform, as defined by [MSC3246].
+///
+/// [MSC3246]: https://github.com/matrix-org/matrix-spec-proposals/pull/3246
+const MAX_AMPLITUDE: u16 = 1024;
+
+/// The rate of the granule positions of an Ogg Opus stream, in samples per
+/// second, whatever the sample rate of the original audio.
+const OPUS_GRANULE_RATE: u64 = 48_000;
+
+/// An error that happened when extracting the metadata of a voice message.
+#[derive(Debug, thiserror::Error)]
+pub enum VoiceMetadataError {
+    /// The format of the audio content isn't supported.
+    #[error("unsupported audio format: {0}")]
+    UnsupportedFormat(String),
+
+    /// The audio content is malformed.
+    #[error("malformed audio content: {0}")]
+    Malformed(&'static str),
+}
+
+/// The duration and waveform of a voice message, computed from its content.
+///
+/// The supported formats are Ogg Opus (`audio/ogg`, `audio/opus`) and PCM or
+/// floating-point WAV (`audio/wav`, `audio/x-wav`, …).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoiceMetadata {
+    /// The duration of the voice message.
+    pub duration: Duration,
+
+    /// The waveform of the voice message, as defined by [MSC3246]: the
+    /// amplitudes of the audio over time, between 0 and 1024, normalized so
+    /// that the loudest part of the message is at 1024.
+    ///
+    /// See [`Self::waveform_is_estimated`] for its accuracy.
+    ///
+    /// [MSC3246]: https://github.com/matrix-org/matrix-spec-proposals/pull/3246
+    pub waveform: Vec<u16>,
+
+    /// Whether the waveform is only an estimate, rather than computed from the
+    /// decoded audio samples.
+    ///
+    /// This is the case for Opus audio, which isn't decoded: its waveform is
+    /// estimated from the bitrate of its packets.
+    pub waveform_is_estimated: bool,
+}
+
+impl VoiceMetadata {
+    /// The number of amplitudes in the waveform computed when sending a voice
+    /// message without one.
+    pub const DEFAULT_WAVEFORM_RESOLUTION: usize = 100;
+
+    /// Compute the duration and waveform of a voice message from its content.
+    ///
+    /// The audio is split into `resolution` spans of equal duration, and the
+    /// waveform holds one amplitude per span.
+    ///
+    /// Opus audio isn't decoded, so its waveform is only an estimate: since
+    /// it's encoded with a variable bitrate that goes up with the loudness of
+    /// the speech, the amplitudes are estimated from the bitrate of its
+    /// packets. Silence and speech are told apart, but the amplitudes don't
+    /// match the actual loudness of the audio.
+    pub fn extract(
+        content_type: &Mime,
+        data: &[u8],
+        resolution: usize,
+    ) -> Result<Self, VoiceMetadataError> {
+        match (content_type.type_(), content_type.subtype().as_str()) {
+            (mime::AUDIO, "ogg" | "opus") | (mime::APPLICATION, "ogg") => {
+                estimate_ogg_opus(data, resolution)
+            }
+            (mime::AUDIO, "wav" | "wave" | "x-wav" | "vnd.wave") => extract_wav(data, resolution),
+            _ => Err(VoiceMetadataError::UnsupportedFormat(content_type.essence_str().to_owned())),
+        }
+    }
+}
+
+/// Averages values spread over the duration of an audio content into a fixed
+/// number of buckets.
+struct Envelope {
+    sums: Vec<f64>,
+    counts: Vec<u64>,
+}
+
+impl Envelope {
+    fn new(resolution: usize) -> Self {
+        Self { sums: vec![0.0; resolution], counts: vec![0; resolution] }
+    }
+
+    /// Add a value measured at `position`, out of `length`, in any unit.
+    fn add(&mut self, position: u64, length: u64, value: f64) {
+        if self.sums.is_empty() || length == 0 {
+            return;
+        }
+
+        let index = (u128::from(position) * self.sums.len() as u128 / u128::from(length)) as usize;
+        let index = index.min(self.sums.len() - 1);
+
+        self.sums[index] += value;
+        self.counts[index] += 1;
+    }
+
+    /// Normalize the averaged values into a waveform.
+    ///
+    /// If `subtract_floor` is true, the lowest value is mapped to a zero
+    /// amplitude, instead of the zero value.
+    fn into_waveform(self, subtract_floor: bool) -> Vec<u16> {
+        // Buckets without any value, which happens when there are more buckets than
+        // values, repeat the previous one.
+        let mut previous = 0.0;
+        let values = self
+            .sums
+            .iter()
+            .zip(&self.counts)
+            .map(|(sum, count)| {
+                if *count > 0 {
+                    previous = sum / *count as f64;
+                }
+                previous
+            })
+            .collect::<Vec<_>>();
+
+        let floor =
+            if subtract_floor { values.iter().copied().fold(f64::INFINITY, f64::min) } else { 0.0 };
+        let max = values.iter().copied().fold(0.0, f64::max);
+
+        if max <= floor {
+            return vec![0; values.len()];
+        }
+
+        values
+            .into_iter()
+            .map(|value| {
+                ((value - floor) / (max - floor) * f64::from(MAX_AMPLITUDE)).round() as u16
+            })
+            .collect()
+    }
+}
+
+/// Extract the packets of the first logical stream of an Ogg container, along
+/// with the last granule position of that stream, if any.
+fn parse_ogg(mut data: &[u8]) -> Result<(Vec<Vec<u8>>, Option<u64>), VoiceMetadataError> {
+    const HEADER_LENGTH: usize = 27;
+
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    let mut stream_serial = None;
+    let mut last_granule_position = None;
+
+    while !data.is_empty() {
+        if data.len() < HEADER_LENGTH || !data.starts_with(b"OggS") {
+            return Err(VoiceMetadataError::Malformed("invalid Ogg page header"));
+        }
+
+        let granule_position = u64::from_le_bytes(data[6..14].try_into().unwrap());
+        let serial = u32::from_le_bytes(data[14..18].try_into().unwrap());
+        let segment_count = usize::from(data[26]);
+
+        let Some(lacing_values) = data.get(HEADER_LENGTH..HEADER_LENGTH + segment_count) else {
+            return Err(VoiceMetadataError::Malformed("truncated Ogg page header"));
+        };
+        let body_start = HEADER_LENGTH + segment_count;
+        let body_length = lacing_values.iter().map(|value| usize::from(*value)).sum::<usize>();
+        let Some(mut body) = data.get(body_start..body_start + body_length) else {
+            return Err(VoiceMetadataError::Malformed("truncated Ogg page"));
+        };
+
+        data = &data[body_start + body_length..];
+
+        // Only consider the first logical stream, in case several are multiplexed.
+        if *stream_serial.get_or_insert(serial) != serial {
+            continue;
+        }
+
+        // A packet spans over segments of 255 bytes, and ends with a shorter one. It
+        // might continue on the next page.
+        for lacing_value in lacing_values {
+            let (segment, rest) = body.split_at(usize::from(*lacing_value));
+            packet.extend_from_slice(segment);
+            body = rest;
+
+            if *lacing_value < 255 {
+                packets.push(mem::take(&mut packet));
+            }
+        }
+
+        // A granule position with all bits set means that no packet ends on this page.
+        if granule_position != u64::MAX {
+            last_granule_position = Some(granule_position);
+        }
+    }
+
+    Ok((packets, last_granule_position))
+}
+
+/// The number of samples, at 48 kHz, encoded in an Opus packet, according to
+/// its table-of-contents byte ([RFC 6716, section 3.1]).
+///
+/// [RFC 6716, section 3.1]: https://datatracker.ietf.org/doc/html/rfc6716#section-3.1
+fn opus_packet_samples(packet: &[u8]) -> u64 {
+    let Some(toc) = packet.first() else {
+        return 0;
+    };
+
+    let config = usize::from(toc >> 3);
+    let frame_samples = match config {
+        // SILK-only: 10, 20, 40 or 60 ms.
+        0..=11 => [480, 960, 1920, 2880][config % 4],
+        // Hybrid: 10 or 20 ms.
+        12..=15 => [480, 960][config % 2],
+        // CELT-only: 2.5, 5, 10 or 20 ms.
+        _ => [120, 240, 480, 960][config % 4],
+    };
+
+    let frame_count = match toc & 0b11 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => packet.get(1).map_or(0, |count| u64::from(count & 0b0011_1111)),
+    };
+
+    frame_samples * frame_count
+}
+
+/// The duration of `samples` samples at the given sample rate.
+fn duration_from_samples(samples: u64, rate: u64) -> Duration {
+    // Split the computation to avoid overflowing with very long content.
+    Duration::from_secs(samples / rate)
+        + Duration::from_nanos(samples % rate * 1_000_000_000 / rate)
+}
+
+/// Compute the duration of an Ogg Opus content, and estimate its waveform from
+/// the bitrate of its packets.
+fn estimate_ogg_opus(data: &[u8], resolution: usize) -> Result<VoiceMetadata, VoiceMetadataError> {
+    let (packets, last_granule_position) = parse_ogg(data)?;
+    let mut packets = packets.into_iter();
+
+    let identification_header = packets
+        .next()
+        .filter(|packet| packet.starts_with(b"OpusHead") && packet.len() >= 19)
+        .ok_or(VoiceMetadataError::Malformed("missing Opus identification header"))?;
+    let pre_skip =
+        u64::from(u16::from_le_bytes([identification_header[10], identification_header[11]]));
+
+    packets
+        .next()
+        .filter(|packet| packet.starts_with(b"OpusTags"))
+        .ok_or(VoiceMetadataError::Malformed("missing Opus comment header"))?;
+
+    let audio_packets = packets
+        .filter(|packet| !packet.is_empty())
+        .map(|packet| (opus_packet_samples(&packet), packet.len()))
+        .collect::<Vec<_>>();
+    let total_samples = audio_packets.iter().map(|(samples, _)| samples).sum::<u64>();
+
+    // The granule position of the last page tells exactly where the audio ends,
+    // the last packet being possibly padded.
+    let duration_samples = last_granule_position.unwrap_or(total_samples).saturating_sub(pre_skip);
+    let duration = duration_from_samples(duration_samples, OPUS_GRANULE_RATE);
+
+    let mut envelope = Envelope::new(resolution);
+    let mut position = 0;
+
+    for (samples, length) in audio_packets {
+        if samples > 0 {
+            // The number of bytes per sample, i.e. the bitrate of the packet.
+            envelope.add(position, total_samples, length as f64 / samples as f64);
+        }
+        position += samples;
+    }
+
+    Ok(VoiceMetadata {
+        duration,
+        waveform: envelope.into_waveform(true),
+        waveform_is_estimated: true,
+    })
+}
+
+/// The format of the samples of a WAV content.
+struct WavFormat {
+    is_float: bool,
+    channels: usize,
+    sample_rate: u32,
+    bytes_per_sample: usize,
+}
+
+impl WavFormat {
+    fn parse(chunk: &[u8]) -> Result<Self, VoiceMetadataError> {
+        if chunk.len() < 16 {
+            return Err(VoiceMetadataError::Malformed("truncated WAV format chunk"));
+        }
+
+        let mut format_tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+        let channels = usize::from(u16::from_le_bytes([chunk[2], chunk[3]]));
+        let sample_rate = u32::from_le_bytes(chunk[4..8].try_into().unwrap());
+        let bits_per_sample = u16::from_le_bytes([chunk[14], chunk[15]]);
+
+        // The extensible format holds the actual format in the first bytes of its
+        // sub-format GUID.
+        if format_tag == 0xFFFE {
+            let Some(sub_format) = chunk.get(24..26) else {
+                return Err(VoiceMetadataError::Malformed("truncated WAV format chunk"));
+            };
+            format_tag = u16::from_le_bytes([sub_format[0], sub_format[1]]);
+        }
+
+        let is_float = match (format_tag, bits_per_sample) {
+            (1, 8 | 16 | 24 | 32) => false,
+            (3, 32) => true,
+            _ => {
+                return Err(VoiceMetadataError::UnsupportedFormat(format!(
+                    "WAV with format {format_tag} and {bits_per_sample} bits per sample"
+                )));
+            }
+        };
+
+        if channels == 0 || sample_rate == 0 {
+            return Err(VoiceMetadataError::Malformed("invalid WAV format chunk"));
+        }
+
+        Ok(Self {
+            is_float,
+            channels,
+            sample_rate,
+            bytes_per_sample: usize::from(bits_per_sample / 8),
+        })
+    }
+
+    /// The absolute amplitude of a sample, between 0 and 1.
+    fn amplitude(&self, sample: &[u8]) -> f64 {
+        if self.is_float {
+            return f64::from(f32::from_le_bytes(sample.try_into().unwrap()).abs()).min(1.0);
+        }
+
+        match sample {
+            // 8-bit samples are unsigned.
+            [sample] => (f64::from(*sample) - 128.0).abs() / 128.0,
+            [low, high] => f64::from(i16::from_le_bytes([*low, *high])).abs() / 32_768.0,
+            [low, middle, high] => {
+                f64::from(i32::from_le_bytes([0, *low, *middle, *high]) >> 8).abs() / 8_388_608.0
+            }
+            _ => f64::from(i32::from_le_bytes(sample.try_into().unwrap())).abs() / 2_147_483_648.0,
+        }
+    }
+}
+
+fn extract_wav(data: &[u8], resolution: usize) -> Result<VoiceMetadata, VoiceMetadataError> {
+    if data.len() < 12 || !data.starts_with(b"RIFF") || &data[8..12] != b"WAVE" {
+        return Err(VoiceMetadataError::Malformed("missing RIFF WAVE header"));
+    }
+
+    let mut chunks = &data[12..];
+    let mut format = None;
+    let mut samples = None;
+
+    while chunks.len() >= 8 {
+        let id = &chunks[..4];
+        let length = u32::from_le_bytes(chunks[4..8].try_into().unwrap()) as usize;
+
+        // The end of the chunk can overflow on 32-bit targets, with a bogus length.
+        let end = length.checked_add(8);
+
+        // Recorders that stream their output can't know the length of the data in
+        // advance, so be lenient with a chunk that goes past the end of the content.
+        let body = end.and_then(|end| chunks.get(8..end)).unwrap_or(&chunks[8..]);
+
+        match id {
+            b"fmt " => format = Some(WavFormat::parse(body)?),
+            b"data" => samples = Some(body),
+            _ => {}
+        }
+
+        // Chunks are padded to an even length. A chunk that goes past the end of the
+        // content is the last one.
+        let next = end.and_then(|end| end.checked_add(length & 1));
+        match next.and_then(|next| chunks.get(next..)) {
+            Some(rest) => chunks = rest,
+            None => break,
+        }
+    }
+
+    let format = format.ok_or(VoiceMetadataError::Malformed("missing WAV format chunk"))?;
+    let samples = samples.ok_or(VoiceMetadataError::Malformed("missing WAV data chunk"))?;
+
+    let frame_length = format.channels * format.bytes_per_sample;
+    let frame_count = (samples.len() / frame_length) as u64;
+    let duration = duration_from_samples(frame_count, u64::from(format.sample_rate));
+
+    let mut envelope = Envelope::new(resolution);
+
+    for (index, frame) in samples.chunks_exact(frame_length).enumerate() {
+        let amplitude = frame
+            .chunks_exact(format.bytes_per_sample)
+            .map(|sample| format.amplitude(sample))
+            .sum::<f64>()
+            / format.channels as f64;
+
+        envelope.add(index as u64, frame_count, amplitude);
+    }
+
+    Ok(VoiceMetadata {
+        duration,
+        waveform: envelope.into_waveform(false),
+        waveform_is_estimated: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use assert_matches2::{assert_let, assert_matches};
+
+    use super::{VoiceMetadata, VoiceMetadataError};
+    use crate::attachment::AttachmentInfo;
+
+    /// Build an Ogg page holding the given packets, which must fit in a single
+    /// page.
+    fn ogg_page(packets: &[Vec<u8>], granule_position: u64, sequence: u32) -> Vec<u8> {
+        let mut lacing_values = Vec::new();
+        for packet in packets {
+            lacing_values.extend(std::iter::repeat_n(255, packet.len() / 255));
+            lacing_values.push((packet.len() % 255) as u8);
+        }
+
+        ogg_raw_page(&lacing_values, &packets.concat(), granule_position, sequence)
+    }
+
+    /// Build an Ogg page with the given segment lengths and body.
+    fn ogg_raw_page(
+        lacing_values: &[u8],
+        body: &[u8],
+        granule_position: u64,
+        sequence: u32,
+    ) -> Vec<u8> {
+        let mut page = b"OggS".to_vec();
+        page.push(0); // Version.
+        page.push(if sequence == 0 { 0x02 } else { 0 }); // Header type.
+        page.extend_from_slice(&granule_position.to_le_bytes());
+        page.extend_from_slice(&1234u32.to_le_bytes()); // Stream serial number.
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]); // Checksum, which isn't checked.
+        page.push(lacing_values.len() as u8);
+        page.extend_from_slice(lacing_values);
+        page.extend_from_slice(body);
+        page
+    }
+
+    /// Build an Ogg Opus content with one 20 ms packet per given packet size.
+    fn ogg_opus(packet_sizes: &[usize], pre_skip: u16) -> Vec<u8> {
+        let mut identification_header = b"OpusHead".to_vec();
+        identification_header.push(1); // Version.
+        identification_header.push(1); // Channel count.
+        identification_header.extend_from_slice(&pre_skip.to_le_bytes());
+        identification_header.extend_from_slice(&48_000u32.to_le_bytes());
+        identification_header.extend_from_slice(&[0, 0, 0]); // Gain and mapping family.
+
+        let mut data = ogg_page(&[identification_header], 0, 0);
+        data.extend(ogg_page(&[b"OpusTagsfake".to_vec()], 0, 1));
+
+        // CELT-only, 20 ms, a single frame.
+        let toc = 31 << 3;
+        let packets = packet_sizes
+            .iter()
+            .map(|size| {
+                let mut packet = vec![0; *size];
+                packet[0] = toc;
+                packet
+            })
+            .collect::<Vec<_>>();
+
+        for (sequence, chunk) in packets.chunks(10).enumerate() {
+            let end = ((sequence * 10 + chunk.len()) * 960) as u64 + u64::from(pre_skip);
+            data.extend(ogg_page(chunk, end, sequence as u32 + 2));
+        }
+
+        data
+    }
+
+    /// Build a 16-bit mono WAV content.
+    fn wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+        let data_length = (samples.len() * 2) as u32;
+
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(36 + data_length).to_le_bytes());
+        data.extend_from_slice(b"WAVEfmt ");
+        data.extend_from_slice(&16u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes()); // PCM.
+        data.extend_from_slice(&1u16.to_le_bytes()); // Mono.
+        data.extend_from_slice(&sample_rate.to_le_bytes());
+        data.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // Byte rate.
+        data.extend_from_slice(&2u16.to_le_bytes()); // Block align.
+        data.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample.
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&data_length.to_le_bytes());
+        for sample in samples {
+            data.extend_from_slice(&sample.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_ogg_opus_metadata() {
+        // One second of silence, then one second of speech.
+        let mut packet_sizes = vec![3; 50];
+        packet_sizes.extend([120; 50]);
+        let data = ogg_opus(&packet_sizes, 312);
+
+        let metadata = VoiceMetadata::extract(&"audio/ogg".parse().unwrap(), &data, 10).unwrap();
+
+        assert_eq!(metadata.duration, Duration::from_secs(2));
+        assert_eq!(metadata.waveform, [0, 0, 0, 0, 0, 1024, 1024, 1024, 1024, 1024]);
+        assert!(metadata.waveform_is_estimated);
+    }
+
+    #[test]
+    fn test_ogg_opus_metadata_with_more_buckets_than_packets() {
+        let data = ogg_opus(&[3, 120], 0);
+
+        let metadata = VoiceMetadata::extract(&"audio/opus".parse().unwrap(), &data, 4).unwrap();
+
+        assert_eq!(metadata.duration, Duration::from_millis(40));
+        assert_eq!(metadata.waveform, [0, 0, 1024, 1024]);
+    }
+
+    #[test]
+    fn test_ogg_packet_spanning_pages() {
+        let mut data = ogg_opus(&[], 0);
+
+        // A 300-byte packet split over two pages: the first one ends with a full
+        // segment, so the packet continues on the next one.
+        let mut packet = vec![0; 300];
+        packet[0] = 31 << 3;
+        data.extend(ogg_raw_page(&[255], &packet[..255], u64::MAX, 2));
+        data.extend(ogg_raw_page(&[45], &packet[255..], 960, 3));
+
+        let metadata = VoiceMetadata::extract(&"audio/ogg".parse().unwrap(), &data, 1).unwrap();
+
+        assert_eq!(metadata.duration, Duration::from_millis(20));
+        assert_eq!(metadata.waveform, [0]);
+    }
+
+    #[test]
+    fn test_wav_metadata() {
+        // Half a second of silence, then half a second at half the full scale.
+        let mut samples = vec![0; 4000];
+        samples.extend((0..4000).map(|index| if index % 2 == 0 { 16_384 } else { -16_384 }));
+        let data = wav(&samples, 8000);
+
+        let metadata = VoiceMetadata::extract(&"audio/wav".parse().unwrap(), &data, 4).unwrap();
+
+        assert_eq!(metadata.duration, Duration::from_secs(1));
+        assert_eq!(metadata.waveform, [0, 0, 1024, 1024]);
+        assert!(!metadata.waveform_is_estimated);
+    }
+
+    #[test]
+    fn test_silent_wav_metadata() {
+        let data = wav(&[0; 800], 8000);
+
+        let metadata = VoiceMetadata::extract(&"audio/x-wav".parse().unwrap(), &data, 4).unwrap();
+
+        assert_eq!(metadata.duration, Duration::from_millis(100));
+        assert_eq!(metadata.waveform, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_wav_with_bogus_chunk_length() {
+        // A recorder streaming its output wrote the maximum length for the data
+        // chunk.
+        let mut data = wav(&[0; 800], 8000);
+        let length_offset = data.len() - 1600 - 4;
+        data[length_offset..length_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let metadata = VoiceMetadata::extract(&"audio/wav".parse().unwrap(), &data, 4).unwrap();
+        assert_eq!(metadata.duration, Duration::from_millis(100));
+
+        // An unknown chunk with the maximum length hides the following chunks.
+        let mut data = wav(&[0; 800], 8000);
+        data.splice(12..12, b"junk".iter().chain(&u32::MAX.to_le_bytes()).copied());
+
+        assert_matches!(
+            VoiceMetadata::extract(&"audio/wav".parse().unwrap(), &data, 4),
+            Err(VoiceMetadataError::Malformed(_))
+        );
+    }
+
+    #[test]
+    fn test_duration_of_very_long_content() {
+        // A granule position this high would overflow when converted to
+        // microseconds naively.
+        let mut data = ogg_opus(&[], 0);
+        let mut packet = vec![0; 3];
+        packet[0] = 31 << 3;
+        data.extend(ogg_page(&[packet], u64::MAX - 1, 2));
+
+        let metadata = VoiceMetadata::extract(&"audio/ogg".parse().unwrap(), &data, 1).unwrap();
+
+        assert_eq!(metadata.duration.as_secs(), (u64::MAX - 1) / 48_000);
+    }
+
+    #[test]
+    fn test_fill_voice_metadata_of_application_ogg() {
+        let data = ogg_opus(&[3, 120], 0);
+        let mut info = AttachmentInfo::Voice { audio_info: Default::default(), waveform: None };
+
+        info.fill_voice_metadata(&"application/ogg".parse().unwrap(), &data);
+
+        assert_let!(AttachmentInfo::Voice { audio_info, waveform: Some(waveform) } = info);
+        assert_eq!(audio_info.duration, Some(Duration::from_millis(40)));
+        assert_eq!(waveform.len(), VoiceMetadata::DEFAULT_WAVEFORM_RESOLUTION);
+    }
+
+    #[test]
+    fn test_unsupported_or_malformed_content() {
+        assert_let!(
+            Err(VoiceMetadataError::UnsupportedFormat(format)) =
+                VoiceMetadata::extract(&"audio/mpeg".parse().unwrap(), b"ID3", 10)
+        );
+        assert_eq!(format, "audio/mpeg");
+
+        assert_matches!(
+            VoiceMetadata::extract(&"audio/ogg".parse().unwrap(), b"not an ogg file", 10),
+            Err(VoiceMetadataError::Malformed(_))
+        );
+        assert_matches!(
+            VoiceMetadata::extract(&"audio/wav".parse().unwrap(), b"RIFF\0\0\0\0WAVE", 10),
+            Err(VoiceMetadataError::Malformed(_))
+        );
+    }
+}
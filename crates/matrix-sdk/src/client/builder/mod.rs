@@ -43,7 +43,7 @@ use crate::client::search::SearchIndex;
 #[cfg(feature = "experimental-search")]
 use crate::client::search::SearchIndexStoreKind;
 #[cfg(feature = "e2e-encryption")]
-use crate::crypto::{CollectStrategy, TrustRequirement};
+use crate::crypto::{CollectStrategy, RoomKeyRecipientFilter, TrustRequirement};
 #[cfg(feature = "e2e-encryption")]
 use crate::encryption::EncryptionSettings;
 #[cfg(not(target_family = "wasm"))]
@@ -59,7 +59,7 @@ use crate::{
     http_client::HttpClient,
     send_queue::SendQueueData,
     sliding_sync::VersionBuilder as SlidingSyncVersionBuilder,
-    HttpError, IdParseError,
+    HttpError, HttpMiddleware, IdParseError,
 };
 
 /// Builder that allows creating and configuring various parts of a [`Client`].
@@ -106,6 +106,7 @@ pub struct ClientBuilder {
     homeserver_cfg: Option<HomeserverConfig>,
     sliding_sync_version_builder: SlidingSyncVersionBuilder,
     http_cfg: Option<HttpConfig>,
+    http_middlewares: Vec<Arc<dyn HttpMiddleware>>,
     store_config: BuilderStoreConfig,
     request_config: RequestConfig,
     respect_login_well_known: bool,
@@ -117,6 +118,8 @@ pub struct ClientBuilder {
     #[cfg(feature = "e2e-encryption")]
     room_key_recipient_strategy: CollectStrategy,
     #[cfg(feature = "e2e-encryption")]
+    room_key_recipient_filter: Option<Arc<dyn RoomKeyRecipientFilter>>,
+    #[cfg(feature = "e2e-encryption")]
     decryption_settings: DecryptionSettings,
     #[cfg(feature = "e2e-encryption")]
     enable_share_history_on_invite: bool,
@@ -134,6 +137,7 @@ impl ClientBuilder {
             homeserver_cfg: None,
             sliding_sync_version_builder: SlidingSyncVersionBuilder::Native,
             http_cfg: None,
+            http_middlewares: Vec::new(),
             store_config: BuilderStoreConfig::Custom(StoreConfig::new(
                 Self::DEFAULT_CROSS_PROCESS_STORE_LOCKS_HOLDER_NAME.to_owned(),
             )),
@@ -147,6 +151,8 @@ impl ClientBuilder {
             #[cfg(feature = "e2e-encryption")]
             room_key_recipient_strategy: Default::default(),
             #[cfg(feature = "e2e-encryption")]
+            room_key_recipient_filter: None,
+            #[cfg(feature = "e2e-encryption")]
             decryption_settings: DecryptionSettings {
                 sender_device_trust_requirement: TrustRequirement::Untrusted,
             },
@@ -396,6 +402,43 @@ impl ClientBuilder {
         self
     }
 
+    /// Add a middleware called around every request sent to the homeserver.
+    ///
+    /// It can be used to add headers to the requests, to record metrics about
+    /// them, or to answer them without sending them. Middlewares are called in
+    /// the order in which they were added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// use matrix_sdk::{bytes::Bytes, Client, HttpMiddleware, HttpRequestInfo};
+    ///
+    /// #[derive(Debug)]
+    /// struct ProxyAuth;
+    ///
+    /// impl HttpMiddleware for ProxyAuth {
+    ///     fn before_request(
+    ///         &self,
+    ///         _info: &HttpRequestInfo,
+    ///         request: &mut http::Request<Bytes>,
+    ///     ) -> Option<http::Response<Bytes>> {
+    ///         request.headers_mut().insert(
+    ///             "x-proxy-auth",
+    ///             http::HeaderValue::from_static("secret"),
+    ///         );
+    ///         None
+    ///     }
+    /// }
+    ///
+    /// let client_builder =
+    ///     Client::builder().add_http_middleware(Arc::new(ProxyAuth));
+    /// ```
+    pub fn add_http_middleware(mut self, middleware: Arc<dyn HttpMiddleware>) -> Self {
+        self.http_middlewares.push(middleware);
+        self
+    }
+
     /// Specify the Matrix versions supported by the homeserver manually, rather
     /// than `build()` doing it using a `get_supported_versions` request.
     ///
@@ -459,6 +502,23 @@ impl ClientBuilder {
         self
     }
 
+    /// Set a policy restricting which devices receive room keys, on top of
+    /// the room key recipient strategy.
+    ///
+    /// The strategy can be overridden per room with
+    /// [`Room::set_room_key_recipient_strategy()`], while the filter applies
+    /// to all the rooms.
+    ///
+    /// [`Room::set_room_key_recipient_strategy()`]: crate::Room::set_room_key_recipient_strategy
+    #[cfg(feature = "e2e-encryption")]
+    pub fn with_room_key_recipient_filter(
+        mut self,
+        filter: Arc<dyn RoomKeyRecipientFilter>,
+    ) -> Self {
+        self.room_key_recipient_filter = Some(filter);
+        self
+    }
+
     /// Set the trust requirement to be used when decrypting events.
     #[cfg(feature = "e2e-encryption")]
     pub fn with_decryption_settings(mut self, decryption_settings: DecryptionSettings) -> Self {
@@ -550,13 +610,15 @@ impl ClientBuilder {
             #[cfg(feature = "e2e-encryption")]
             {
                 client.room_key_recipient_strategy = self.room_key_recipient_strategy;
+                client.room_key_recipient_filter = self.room_key_recipient_filter;
                 client.decryption_settings = self.decryption_settings;
             }
 
             client
         };
 
-        let http_client = HttpClient::new(inner_http_client.clone(), self.request_config);
+        let http_client = HttpClient::new(inner_http_client.clone(), self.request_config)
+            .with_middlewares(self.http_middlewares);
 
         #[allow(unused_variables)]
         let HomeserverDiscoveryResult { server, homeserver, supported_versions, well_known } =
@@ -14,17 +14,27 @@
 
 #![deny(unreachable_pub)]
 
+#[cfg(not(target_family = "wasm"))]
+use std::path::PathBuf;
 use std::{fmt::Debug, future::IntoFuture};
 
+#[cfg(not(target_family = "wasm"))]
+use bytes::Bytes;
 use eyeball::{SharedObservable, Subscriber};
+#[cfg(not(target_family = "wasm"))]
+use futures_util::stream::BoxStream;
 use js_int::UInt;
 use matrix_sdk_common::{boxed_into_future, SendOutsideWasm, SyncOutsideWasm};
+#[cfg(not(target_family = "wasm"))]
+use mime::Mime;
 use oauth2::{basic::BasicErrorResponseType, RequestTokenError};
 use ruma::api::{
     client::{error::ErrorKind, media},
     error::FromHttpResponseError,
     OutgoingRequest,
 };
+#[cfg(not(target_family = "wasm"))]
+use ruma::assign;
 use tracing::{error, trace};
 
 use super::super::Client;
@@ -35,6 +45,8 @@ use crate::{
     media::MediaError,
     Error, RefreshTokenError, TransmissionProgress,
 };
+#[cfg(not(target_family = "wasm"))]
+use crate::{media::UploadStream, Media};
 
 /// `IntoFuture` returned by [`Client::send`].
 #[allow(missing_debug_implementations)]
@@ -96,56 +108,7 @@ where
             {
                 trace!("Token refresh: Unknown token error received.");
 
-                // If automatic token refresh isn't supported, there is nothing more to do.
-                if !client.inner.auth_ctx.handle_refresh_tokens {
-                    trace!("Token refresh: Automatic refresh disabled.");
-                    client.broadcast_unknown_token(soft_logout);
-                    return res;
-                }
-
-                // Try to refresh the token and retry the request.
-                if let Err(refresh_error) = client.refresh_access_token().await {
-                    match &refresh_error {
-                        RefreshTokenError::RefreshTokenRequired => {
-                            trace!("Token refresh: The session doesn't have a refresh token.");
-                            // Refreshing access tokens is not supported by this `Session`, ignore.
-                            client.broadcast_unknown_token(soft_logout);
-                        }
-
-                        RefreshTokenError::OAuth(oauth_error) => {
-                            match &**oauth_error {
-                                OAuthError::RefreshToken(RequestTokenError::ServerResponse(
-                                    error_response,
-                                )) if *error_response.error()
-                                    == BasicErrorResponseType::InvalidGrant =>
-                                {
-                                    error!(
-                                        "Token refresh: OAuth 2.0 refresh_token rejected \
-                                         with invalid grant"
-                                    );
-                                    // The refresh was denied, signal to sign out the user.
-                                    client.broadcast_unknown_token(soft_logout);
-                                }
-                                _ => {
-                                    trace!(
-                                        "Token refresh: OAuth 2.0 refresh encountered a problem."
-                                    );
-                                    // The refresh failed for other reasons, no
-                                    // need to sign out.
-                                }
-                            }
-                            return Err(HttpError::RefreshToken(refresh_error));
-                        }
-
-                        _ => {
-                            trace!("Token refresh: Token refresh failed.");
-                            // This isn't necessarily correct, but matches the behaviour when
-                            // implementing OAuth 2.0.
-                            client.broadcast_unknown_token(soft_logout);
-                            return Err(HttpError::RefreshToken(refresh_error));
-                        }
-                    }
-                } else {
+                if refresh_access_token_after_unknown_token(&client, *soft_logout).await? {
                     trace!("Token refresh: Refresh succeeded, retrying request.");
                     return Box::pin(client.send_inner(request, config, send_progress)).await;
                 }
@@ -156,6 +119,63 @@ where
     }
 }
 
+/// Try to refresh the access token after an `M_UNKNOWN_TOKEN` error.
+///
+/// Returns `Ok(true)` if the token was refreshed and the request can be
+/// retried, `Ok(false)` if the token can't be refreshed and the original error
+/// should be returned.
+async fn refresh_access_token_after_unknown_token(
+    client: &Client,
+    soft_logout: bool,
+) -> HttpResult<bool> {
+    // If automatic token refresh isn't supported, there is nothing more to do.
+    if !client.inner.auth_ctx.handle_refresh_tokens {
+        trace!("Token refresh: Automatic refresh disabled.");
+        client.broadcast_unknown_token(&soft_logout);
+        return Ok(false);
+    }
+
+    // Try to refresh the token.
+    let Err(refresh_error) = client.refresh_access_token().await else {
+        return Ok(true);
+    };
+
+    match &refresh_error {
+        RefreshTokenError::RefreshTokenRequired => {
+            trace!("Token refresh: The session doesn't have a refresh token.");
+            // Refreshing access tokens is not supported by this `Session`, ignore.
+            client.broadcast_unknown_token(&soft_logout);
+            Ok(false)
+        }
+
+        RefreshTokenError::OAuth(oauth_error) => {
+            match &**oauth_error {
+                OAuthError::RefreshToken(RequestTokenError::ServerResponse(error_response))
+                    if *error_response.error() == BasicErrorResponseType::InvalidGrant =>
+                {
+                    error!("Token refresh: OAuth 2.0 refresh_token rejected with invalid grant");
+                    // The refresh was denied, signal to sign out the user.
+                    client.broadcast_unknown_token(&soft_logout);
+                }
+                _ => {
+                    trace!("Token refresh: OAuth 2.0 refresh encountered a problem.");
+                    // The refresh failed for other reasons, no need to sign
+                    // out.
+                }
+            }
+            Err(HttpError::RefreshToken(refresh_error))
+        }
+
+        _ => {
+            trace!("Token refresh: Token refresh failed.");
+            // This isn't necessarily correct, but matches the behaviour when
+            // implementing OAuth 2.0.
+            client.broadcast_unknown_token(&soft_logout);
+            Err(HttpError::RefreshToken(refresh_error))
+        }
+    }
+}
+
 /// `IntoFuture` used to send media upload requests. It wraps another
 /// [`SendRequest`], checking its size will be accepted by the homeserver before
 /// uploading.
@@ -213,3 +233,150 @@ impl IntoFuture for SendMediaUploadRequest {
         })
     }
 }
+
+/// `IntoFuture` used to upload a media whose content is streamed while it's
+/// sent, checking its size will be accepted by the homeserver before
+/// uploading.
+#[cfg(not(target_family = "wasm"))]
+#[allow(missing_debug_implementations)]
+pub struct SendMediaUploadStream {
+    client: Client,
+    content_type: Mime,
+    body: BoxStream<'static, std::io::Result<Bytes>>,
+    length: u64,
+    request_config: Option<RequestConfig>,
+    send_progress: SharedObservable<TransmissionProgress>,
+    reopen_path: Option<PathBuf>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl SendMediaUploadStream {
+    pub(crate) fn new(
+        client: Client,
+        content_type: Mime,
+        body: BoxStream<'static, std::io::Result<Bytes>>,
+        length: u64,
+        request_config: Option<RequestConfig>,
+    ) -> Self {
+        Self {
+            client,
+            content_type,
+            body,
+            length,
+            request_config,
+            send_progress: Default::default(),
+            reopen_path: None,
+        }
+    }
+
+    /// Set the path of the file the body is read from, to read it again if the
+    /// upload needs to be retried after refreshing the access token.
+    pub(crate) fn with_reopen_path(mut self, path: Option<PathBuf>) -> Self {
+        self.reopen_path = path;
+        self
+    }
+
+    /// Replace the default `SharedObservable` used for tracking upload
+    /// progress.
+    ///
+    /// Note that any subscribers obtained from
+    /// [`subscribe_to_send_progress`][Self::subscribe_to_send_progress]
+    /// will be invalidated by this.
+    pub fn with_send_progress_observable(
+        mut self,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Self {
+        self.send_progress = send_progress;
+        self
+    }
+
+    /// Get a subscriber to observe the progress of sending the request
+    /// body.
+    pub fn subscribe_to_send_progress(&self) -> Subscriber<TransmissionProgress> {
+        self.send_progress.subscribe()
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl IntoFuture for SendMediaUploadStream {
+    type Output = Result<media::create_content::v3::Response, Error>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self {
+            client,
+            content_type,
+            mut body,
+            length,
+            request_config,
+            send_progress,
+            reopen_path,
+        } = self;
+
+        Box::pin(async move {
+            let max_upload_size = client.load_or_fetch_max_upload_size().await?;
+            let request_length = UInt::new_wrapping(length);
+            if request_length > max_upload_size {
+                return Err(Error::Media(MediaError::MediaTooLargeToUpload {
+                    max: max_upload_size,
+                    current: request_length,
+                }));
+            }
+
+            let request_config = request_config.unwrap_or_else(|| {
+                client.request_config().timeout(Media::reasonable_upload_timeout_for_length(length))
+            });
+
+            let mut retried = false;
+
+            loop {
+                // The file is left empty, the actual content comes from the body stream.
+                let request = assign!(media::create_content::v3::Request::new(Vec::new()), {
+                    content_type: Some(content_type.essence_str().to_owned()),
+                });
+
+                let res = client
+                    .send_streaming_inner(
+                        request,
+                        body,
+                        length,
+                        Default::default(),
+                        Some(request_config),
+                        send_progress.clone(),
+                    )
+                    .await;
+
+                // An `M_UNKNOWN_TOKEN` error can potentially be fixed with a token refresh,
+                // if the body can be read again from the start.
+                if let Err(Some(ErrorKind::UnknownToken { soft_logout })) =
+                    res.as_ref().map_err(HttpError::client_api_error_kind)
+                {
+                    trace!("Token refresh: Unknown token error received during streaming upload.");
+
+                    if !retried
+                        && refresh_access_token_after_unknown_token(&client, *soft_logout).await?
+                    {
+                        if let Some(path) = &reopen_path {
+                            trace!("Token refresh: Refresh succeeded, retrying upload.");
+
+                            let stream = UploadStream::from_path(path).await?;
+                            if stream.len() != length {
+                                return Err(std::io::Error::new(
+                                    std::io::ErrorKind::InvalidData,
+                                    "the file was modified before the upload could be retried",
+                                )
+                                .into());
+                            }
+
+                            body = stream.into_body();
+                            retried = true;
+                            continue;
+                        }
+                    }
+                }
+
+                return res.map_err(Into::into);
+            }
+        })
+    }
+}
@@ -37,7 +37,7 @@ use matrix_sdk_base::{
     BaseClient, RoomInfoNotableUpdate, RoomState, RoomStateFilter, SendOutsideWasm, SessionMeta,
     StateStoreDataKey, StateStoreDataValue, SyncOutsideWasm, ThreadingSupport,
 };
-use matrix_sdk_common::ttl_cache::TtlCache;
+use matrix_sdk_common::{storage_usage::StorageUsage, ttl_cache::TtlCache};
 #[cfg(feature = "e2e-encryption")]
 use ruma::events::{room::encryption::RoomEncryptionEventContent, InitialStateEvent};
 use ruma::{
@@ -212,7 +212,7 @@ pub(crate) struct ClientLocks {
     /// Handler making sure we only have one group session sharing request in
     /// flight per room.
     #[cfg(feature = "e2e-encryption")]
-    pub(crate) group_session_deduplicated_handler: DeduplicatingHandler<OwnedRoomId>,
+    pub(crate) group_session_deduplicated_handler: DeduplicatingHandler<OwnedRoomId, bool>,
 
     /// Lock making sure we're only doing one key claim request at a time.
     #[cfg(feature = "e2e-encryption")]
@@ -726,6 +726,62 @@ impl Client {
         self.base_client().event_cache_store()
     }
 
+    /// Get the storage used by the stores of the client.
+    ///
+    /// This includes the state store, the event cache store and, if they are
+    /// enabled, the crypto store and the search index. The usage is broken
+    /// down for the rooms known by the client.
+    ///
+    /// The event cache store is measured while holding its cross-process
+    /// lock.
+    pub async fn storage_usage(&self) -> Result<StorageUsage> {
+        let room_ids: Vec<_> = self.rooms().iter().map(|room| room.room_id().to_owned()).collect();
+
+        let mut usage = self.state_store().storage_usage(&room_ids).await?;
+        usage.merge(self.event_cache_store().lock().await?.storage_usage(&room_ids).await?);
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Some(olm_machine) = self.olm_machine().await.as_ref() {
+            usage.merge(olm_machine.store().storage_usage(&room_ids).await?);
+        }
+
+        #[cfg(feature = "experimental-search")]
+        if let Some(search_index_usage) = self.search_index().storage_usage(&room_ids).await? {
+            usage.merge(search_index_usage);
+        }
+
+        Ok(usage)
+    }
+
+    /// Compact the stores of the client, to reclaim unused space and optimize
+    /// them.
+    ///
+    /// The cross-process locks of the crypto store and of the event cache
+    /// store are taken before compacting any store, and held until all of
+    /// them are compacted, so this can be called while another process uses
+    /// the same stores.
+    ///
+    /// The state store isn't protected by a cross-process lock, and another
+    /// process may still read from the stores, e.g. an app extension. In that
+    /// case the compaction fails with an error for which
+    /// [`Error::is_store_busy()`] returns `true`, and it can be retried
+    /// later.
+    pub async fn compact_stores(&self) -> Result<()> {
+        #[cfg(feature = "e2e-encryption")]
+        let _crypto_store_guard = self.encryption().spin_lock_store(None).await?;
+        let event_cache_store = self.event_cache_store().lock().await?;
+
+        self.state_store().compact().await?;
+        event_cache_store.compact().await?;
+
+        #[cfg(feature = "e2e-encryption")]
+        if let Some(olm_machine) = self.olm_machine().await.as_ref() {
+            olm_machine.store().compact().await?;
+        }
+
+        Ok(())
+    }
+
     /// Access the native Matrix authentication API with this client.
     pub fn matrix_auth(&self) -> MatrixAuth {
         MatrixAuth::new(self.clone())
@@ -1862,6 +1918,79 @@ impl Client {
             .await
     }
 
+    /// Send a request whose body is streamed from `body`, rather than from the
+    /// serialized request.
+    ///
+    /// Contrary to [`Self::send`], the request isn't retried, and the access
+    /// token isn't refreshed if it has expired, since the body can't be
+    /// replayed. Callers that can produce the body again, like
+    /// [`SendMediaUploadStream`](futures::SendMediaUploadStream), handle
+    /// refreshing the token and retrying themselves.
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) async fn send_streaming_inner<Request>(
+        &self,
+        request: Request,
+        body: futures_util::stream::BoxStream<'static, std::io::Result<bytes::Bytes>>,
+        content_length: u64,
+        headers: http::HeaderMap,
+        config: Option<RequestConfig>,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> HttpResult<Request::IncomingResponse>
+    where
+        Request: OutgoingRequest + Debug,
+        HttpError: From<FromHttpResponseError<Request::EndpointError>>,
+    {
+        let homeserver = self.homeserver().to_string();
+        let access_token = self.access_token();
+
+        self.inner
+            .http_client
+            .send_streaming(
+                request,
+                body,
+                content_length,
+                headers,
+                config,
+                homeserver,
+                access_token.as_deref(),
+                &self.supported_versions().await?,
+                send_progress,
+            )
+            .await
+    }
+
+    /// Send a media download request, starting at the given `offset`, and
+    /// return the response body as a stream.
+    ///
+    /// Like [`Self::send_streaming_inner`], the request isn't retried, and the
+    /// access token isn't refreshed if it has expired.
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) async fn download_streaming_inner<Request>(
+        &self,
+        request: Request,
+        offset: u64,
+        config: Option<RequestConfig>,
+    ) -> HttpResult<crate::http_client::StreamingDownload>
+    where
+        Request: OutgoingRequest + Debug,
+        HttpError: From<FromHttpResponseError<Request::EndpointError>>,
+    {
+        let homeserver = self.homeserver().to_string();
+        let access_token = self.access_token();
+
+        self.inner
+            .http_client
+            .download_streaming(
+                request,
+                offset,
+                config,
+                homeserver,
+                access_token.as_deref(),
+                &self.supported_versions().await?,
+            )
+            .await
+    }
+
     fn broadcast_unknown_token(&self, soft_logout: &bool) {
         _ = self
             .inner
@@ -3085,6 +3214,20 @@ pub(crate) mod tests {
         assert_eq!(content.ignored_users.len(), 1);
     }
 
+    #[async_test]
+    async fn test_storage_usage_of_memory_stores() {
+        let client = MockClientBuilder::new(None).build().await;
+
+        // The memory stores can't be measured.
+        let usage = client.storage_usage().await.unwrap();
+        assert_eq!(usage.total_bytes, None);
+        assert_eq!(usage.reclaimable_bytes, None);
+        assert!(usage.categories.is_empty());
+
+        // Compacting them is a no-op.
+        client.compact_stores().await.unwrap();
+    }
+
     #[async_test]
     async fn test_successful_discovery() {
         // Imagine this is `matrix.org`.
@@ -12,8 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::hash_map::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::hash_map::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
+use matrix_sdk_common::storage_usage::{StorageCategory, StorageUsage};
 use matrix_sdk_search::{error::IndexError, index::RoomIndex};
 use ruma::{events::AnySyncMessageLikeEvent, OwnedEventId, OwnedRoomId, RoomId};
 use tokio::sync::{Mutex, MutexGuard};
@@ -53,6 +59,67 @@ impl SearchIndex {
             search_index_store_kind: &self.search_index_store_kind,
         }
     }
+
+    /// Get the storage used by the indexes on the filesystem, or `None` if
+    /// they are kept in memory.
+    ///
+    /// The filesystem is walked on a blocking task.
+    pub(crate) async fn storage_usage(
+        &self,
+        room_ids: &[OwnedRoomId],
+    ) -> io::Result<Option<StorageUsage>> {
+        let SearchIndexStoreKind::Directory(path) = &self.search_index_store_kind else {
+            return Ok(None);
+        };
+
+        let path = path.clone();
+        let room_ids = room_ids.to_vec();
+
+        tokio::task::spawn_blocking(move || directory_storage_usage(&path, &room_ids).map(Some))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+/// Get the storage used by the indexes in the given directory.
+fn directory_storage_usage(path: &Path, room_ids: &[OwnedRoomId]) -> io::Result<StorageUsage> {
+    let total_bytes = directory_size(path)?;
+
+    let mut usage = StorageUsage {
+        total_bytes: Some(total_bytes),
+        // Tantivy deletes the files it doesn't use anymore by itself.
+        reclaimable_bytes: Some(0),
+        ..Default::default()
+    };
+    usage.add_to_category(StorageCategory::SearchIndex, total_bytes);
+
+    for room_id in room_ids {
+        usage.add_to_room(room_id, directory_size(&path.join(room_id.as_str()))?);
+    }
+
+    Ok(usage)
+}
+
+/// Get the size of the files in the given directory, recursively.
+///
+/// Returns 0 if the directory doesn't exist.
+fn directory_size(path: &Path) -> io::Result<u64> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error),
+    };
+
+    let mut size = 0;
+
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        size += if metadata.is_dir() { directory_size(&entry.path())? } else { metadata.len() };
+    }
+
+    Ok(size)
 }
 
 pub(crate) struct SearchIndexGuard<'a> {
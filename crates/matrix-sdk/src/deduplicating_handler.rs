@@ -26,18 +26,18 @@ use crate::{Error, Result};
 
 /// State machine for the state of a query deduplicated by the
 /// [`DeduplicatingHandler`].
-enum QueryState {
+enum QueryState<Value> {
     /// The query hasn't completed. This doesn't mean it hasn't *started* yet,
     /// but rather that it couldn't get to completion: some intermediate
     /// steps might have run.
     Cancelled,
-    /// The query has completed with an `Ok` result.
-    Success,
+    /// The query has completed with an `Ok` result, holding this value.
+    Success(Value),
     /// The query has completed with an `Err` result.
     Failure,
 }
 
-type DeduplicatedRequestMap<Key> = Mutex<BTreeMap<Key, Arc<Mutex<QueryState>>>>;
+type DeduplicatedRequestMap<Key, Value> = Mutex<BTreeMap<Key, Arc<Mutex<QueryState<Value>>>>>;
 
 /// Handler that properly deduplicates function calls given a key uniquely
 /// identifying the call kind, and will properly report error upwards in case
@@ -45,18 +45,21 @@ type DeduplicatedRequestMap<Key> = Mutex<BTreeMap<Key, Arc<Mutex<QueryState>>>>;
 ///
 /// This is handy for deduplicating per-room requests, but can also be used in
 /// other contexts.
-pub(crate) struct DeduplicatingHandler<Key> {
+///
+/// The value returned by the call is forwarded to the concurrent callers, so it
+/// must be cheap to clone.
+pub(crate) struct DeduplicatingHandler<Key, Value = ()> {
     /// Map of outstanding function calls, grouped by key.
-    inflight: DeduplicatedRequestMap<Key>,
+    inflight: DeduplicatedRequestMap<Key, Value>,
 }
 
-impl<Key> Default for DeduplicatingHandler<Key> {
+impl<Key, Value> Default for DeduplicatingHandler<Key, Value> {
     fn default() -> Self {
         Self { inflight: Default::default() }
     }
 }
 
-impl<Key: Clone + Ord + std::hash::Hash> DeduplicatingHandler<Key> {
+impl<Key: Clone + Ord + std::hash::Hash, Value: Clone> DeduplicatingHandler<Key, Value> {
     /// Runs the given code if and only if there wasn't a similar query running
     /// for the same key.
     ///
@@ -66,11 +69,11 @@ impl<Key: Clone + Ord + std::hash::Hash> DeduplicatingHandler<Key> {
     /// idempotent.
     ///
     /// See also [`DeduplicatingHandler`] for more details.
-    pub async fn run<'a, F: Future<Output = Result<()>> + SendOutsideWasm + 'a>(
+    pub async fn run<'a, F: Future<Output = Result<Value>> + SendOutsideWasm + 'a>(
         &self,
         key: Key,
         code: F,
-    ) -> Result<()> {
+    ) -> Result<Value> {
         let mut map = self.inflight.lock().await;
 
         if let Some(request_mutex) = map.get(&key).cloned() {
@@ -79,10 +82,10 @@ impl<Key: Clone + Ord + std::hash::Hash> DeduplicatingHandler<Key> {
 
             let mut request_guard = request_mutex.lock().await;
 
-            return match *request_guard {
-                QueryState::Success => {
+            return match &*request_guard {
+                QueryState::Success(value) => {
                     // The query completed with a success: forward this success.
-                    Ok(())
+                    Ok(value.clone())
                 }
 
                 QueryState::Failure => {
@@ -116,20 +119,20 @@ impl<Key: Clone + Ord + std::hash::Hash> DeduplicatingHandler<Key> {
         self.run_code(key, code, &mut request_guard).await
     }
 
-    async fn run_code<'a, F: Future<Output = Result<()>> + SendOutsideWasm + 'a>(
+    async fn run_code<'a, F: Future<Output = Result<Value>> + SendOutsideWasm + 'a>(
         &self,
         key: Key,
         code: F,
-        result: &mut QueryState,
-    ) -> Result<()> {
+        result: &mut QueryState<Value>,
+    ) -> Result<Value> {
         match code.await {
-            Ok(()) => {
+            Ok(value) => {
                 // Mark the request as completed.
-                *result = QueryState::Success;
+                *result = QueryState::Success(value.clone());
 
                 self.inflight.lock().await.remove(&key);
 
-                Ok(())
+                Ok(value)
             }
 
             Err(err) => {
@@ -181,6 +184,35 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    async fn test_deduplicating_handler_forwards_value() -> anyhow::Result<()> {
+        let num_calls = Arc::new(Mutex::new(0));
+
+        let inner = || {
+            let num_calls_cloned = num_calls.clone();
+            async move {
+                yield_now().await;
+                let mut num_calls = num_calls_cloned.lock().await;
+                *num_calls += 1;
+                let value = *num_calls;
+                drop(num_calls);
+                yield_now().await;
+                Ok(value)
+            }
+        };
+
+        let handler = DeduplicatingHandler::default();
+
+        let (first, second) = join!(handler.run(0, inner()), handler.run(0, inner()));
+
+        // The concurrent caller gets the value of the call that ran.
+        assert_eq!(first?, 1);
+        assert_eq!(second?, 1);
+        assert_eq!(*num_calls.lock().await, 1);
+
+        Ok(())
+    }
+
     #[async_test]
     async fn test_deduplicating_handler_different_keys() -> anyhow::Result<()> {
         let num_calls = Arc::new(Mutex::new(0));
@@ -0,0 +1,148 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Named futures for the dehydrated device support.
+
+use std::future::IntoFuture;
+
+use futures_core::Stream;
+use matrix_sdk_common::boxed_into_future;
+use ruma::api::client::dehydrated_device::get_events;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tracing::{info, Instrument, Span};
+
+use super::{DehydratedDevice, DehydratedDeviceError, RehydrationProgress, Result};
+use crate::{utils::ChannelObservable, Error};
+
+/// Named future for the [`DehydratedDevice::rehydrate()`] method.
+#[derive(Debug)]
+pub struct Rehydrate<'a> {
+    pub(super) dehydrated_device: &'a DehydratedDevice,
+    pub(super) progress: ChannelObservable<RehydrationProgress>,
+    pub(super) rotate: bool,
+    tracing_span: Span,
+}
+
+impl<'a> Rehydrate<'a> {
+    pub(super) fn new(dehydrated_device: &'a DehydratedDevice) -> Self {
+        Self {
+            dehydrated_device,
+            progress: Default::default(),
+            rotate: true,
+            tracing_span: Span::current(),
+        }
+    }
+
+    /// Subscribe to updates to the rehydration progress.
+    pub fn subscribe_to_progress(
+        &self,
+    ) -> impl Stream<Item = Result<RehydrationProgress, BroadcastStreamRecvError>> {
+        self.progress.subscribe()
+    }
+
+    /// Don't replace the rehydrated device with a new dehydrated device once
+    /// all its to-device events have been received.
+    ///
+    /// By default, a new dehydrated device is created and uploaded, as a
+    /// rehydrated device must not be reused.
+    pub fn without_rotation(mut self) -> Self {
+        self.rotate = false;
+
+        self
+    }
+}
+
+impl<'a> IntoFuture for Rehydrate<'a> {
+    type Output = Result<usize>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { dehydrated_device, progress, rotate, tracing_span } = self;
+
+        let future = async move {
+            let client = &dehydrated_device.client;
+
+            let olm_machine = client.olm_machine().await;
+            let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+            let dehydrated_devices = olm_machine.dehydrated_devices();
+
+            let pickle_key = dehydrated_devices
+                .get_dehydrated_device_pickle_key()
+                .await?
+                .ok_or(DehydratedDeviceError::MissingPickleKey)?;
+
+            let Some(response) = dehydrated_device.fetch().await? else {
+                info!("There is no dehydrated device on the server, nothing to rehydrate");
+                progress.set(RehydrationProgress::Done { room_keys: 0 });
+                return Ok(0);
+            };
+
+            progress.set(RehydrationProgress::Rehydrating);
+
+            let device_id = response.device_id;
+            let rehydrated =
+                dehydrated_devices.rehydrate(&pickle_key, &device_id, response.device_data).await?;
+
+            let mut next_batch = None;
+            let mut events = 0;
+            let mut room_keys = 0;
+
+            // Page through the to-device events of the dehydrated device, until the server
+            // returns an empty page.
+            loop {
+                let mut request = get_events::unstable::Request::new(device_id.clone());
+                request.next_batch = next_batch.take();
+
+                let response = client.send(request).await?;
+
+                if response.events.is_empty() {
+                    break;
+                }
+
+                events += response.events.len();
+                room_keys += rehydrated
+                    .receive_events(response.events, client.decryption_settings())
+                    .await
+                    .map_err(Error::from)?
+                    .len();
+
+                progress.set(RehydrationProgress::ReceivingEvents {
+                    device_id: device_id.clone(),
+                    events,
+                    room_keys,
+                });
+
+                match response.next_batch {
+                    Some(token) => next_batch = Some(token),
+                    None => break,
+                }
+            }
+
+            info!(%device_id, events, room_keys, "Done receiving the events of the dehydrated device");
+
+            if rotate {
+                progress.set(RehydrationProgress::Rotating);
+                // All the events were just received, so upload the new dehydrated device
+                // directly.
+                dehydrated_device.upload(&pickle_key).await?;
+            }
+
+            progress.set(RehydrationProgress::Done { room_keys });
+
+            Ok(room_keys)
+        };
+
+        Box::pin(future.instrument(tracing_span))
+    }
+}
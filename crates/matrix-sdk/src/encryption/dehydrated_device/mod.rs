@@ -0,0 +1,328 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Dehydrated device support, as defined in [MSC3814].
+//!
+//! A dehydrated device is a virtual device, living on the homeserver, which
+//! receives the room keys sent to the user while none of their real devices
+//! are online. Its private keys are encrypted with a pickle key, which is
+//! stored in secret storage, so that a new device of the user can download
+//! it, decrypt it, and import the room keys it received. This process is
+//! called rehydration.
+//!
+//! A dehydrated device is enabled once, typically together with
+//! [recovery](crate::encryption::recovery):
+//!
+//! ```no_run
+//! # use matrix_sdk::Client;
+//! # use url::Url;
+//! # async {
+//! # let homeserver = Url::parse("http://example.com")?;
+//! # let client = Client::new(homeserver).await?;
+//! let secret_store = client
+//!     .encryption()
+//!     .secret_storage()
+//!     .open_secret_store("my recovery key")
+//!     .await?;
+//!
+//! client.encryption().dehydrated_device().enable(&secret_store).await?;
+//! # anyhow::Ok(()) };
+//! ```
+//!
+//! After logging in on a new device, and once the pickle key has been imported
+//! from secret storage, e.g. with [`SecretStore::import_secrets()`], the room
+//! keys received by the dehydrated device can be imported:
+//!
+//! ```no_run
+//! # use matrix_sdk::Client;
+//! # use futures_util::StreamExt;
+//! # use url::Url;
+//! # async {
+//! # let homeserver = Url::parse("http://example.com")?;
+//! # let client = Client::new(homeserver).await?;
+//! let dehydrated_device = client.encryption().dehydrated_device();
+//! let rehydrate = dehydrated_device.rehydrate();
+//! let mut progress = rehydrate.subscribe_to_progress();
+//!
+//! tokio::spawn(async move {
+//!     while let Some(update) = progress.next().await {
+//!         println!("Rehydration progress: {update:?}");
+//!     }
+//! });
+//!
+//! let room_keys = rehydrate.await?;
+//! println!("Imported {room_keys} room keys from the dehydrated device");
+//! # anyhow::Ok(()) };
+//! ```
+//!
+//! Alternatively, with [`EncryptionSettings::auto_manage_dehydrated_device`]
+//! the dehydrated device is rehydrated in the background as soon as its pickle
+//! key is imported, and rotated periodically while the [`Client`] is alive.
+//!
+//! [`EncryptionSettings::auto_manage_dehydrated_device`]: crate::encryption::EncryptionSettings::auto_manage_dehydrated_device
+//! [MSC3814]: https://github.com/matrix-org/matrix-spec-proposals/pull/3814
+
+use std::time::Duration;
+
+use matrix_sdk_base::crypto::store::types::DehydratedDeviceKey;
+use ruma::{
+    api::client::{
+        dehydrated_device::{delete_dehydrated_device, get_dehydrated_device},
+        error::ErrorKind,
+    },
+    events::secret::request::SecretName,
+    OwnedDeviceId,
+};
+use tracing::{debug, info, instrument, warn};
+use vodozemac::base64_decode;
+
+pub mod futures;
+mod types;
+
+use self::futures::Rehydrate;
+pub use self::types::{DehydratedDeviceError, RehydrationProgress, Result};
+use crate::{
+    client::WeakClient,
+    encryption::{secret_storage::SecretStore, tasks::DehydratedDeviceRotationTask},
+    Client, Error,
+};
+
+/// The dehydrated device manager for the [`Client`].
+#[derive(Debug, Clone)]
+pub struct DehydratedDevice {
+    pub(super) client: Client,
+}
+
+impl DehydratedDevice {
+    /// The name of the secret, in secret storage, holding the pickle key of
+    /// the dehydrated device.
+    pub const SECRET_NAME: &str = "org.matrix.msc3814";
+
+    /// The display name of the dehydrated devices created by the SDK.
+    pub const DISPLAY_NAME: &str = "Dehydrated device";
+
+    /// The default period after which the dehydrated device is rotated, see
+    /// [`DehydratedDevice::start_periodic_rotation()`].
+    pub const DEFAULT_ROTATION_PERIOD: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+    /// Is the dehydrated device enabled, i.e. is its pickle key available
+    /// locally?
+    pub async fn is_enabled(&self) -> Result<bool> {
+        Ok(self.pickle_key().await?.is_some())
+    }
+
+    /// Enable the dehydrated device.
+    ///
+    /// The pickle key is taken from secret storage, or a new one is created and
+    /// stored there if it doesn't exist yet. A new dehydrated device is then
+    /// created and uploaded to the homeserver, replacing any existing one.
+    ///
+    /// Returns the ID of the new dehydrated device.
+    #[instrument(skip_all)]
+    pub async fn enable(&self, secret_store: &SecretStore) -> Result<OwnedDeviceId> {
+        let pickle_key = match self.pickle_key_from_secret_store(secret_store).await? {
+            Some(pickle_key) => pickle_key,
+            None => {
+                info!("Creating a new dehydrated device pickle key");
+
+                let pickle_key = DehydratedDeviceKey::new()
+                    .map_err(|error| DehydratedDeviceError::PickleKeyGeneration(error.into()))?;
+                secret_store.put_secret(Self::SECRET_NAME, &pickle_key.to_base64()).await?;
+
+                pickle_key
+            }
+        };
+
+        self.save_pickle_key(&pickle_key).await?;
+        self.upload(&pickle_key).await
+    }
+
+    /// Disable the dehydrated device.
+    ///
+    /// The dehydrated device is deleted from the homeserver, and its pickle key
+    /// is removed from the local store. The pickle key is left in secret
+    /// storage.
+    #[instrument(skip_all)]
+    pub async fn disable(&self) -> Result<()> {
+        self.stop_periodic_rotation();
+
+        let request = delete_dehydrated_device::unstable::Request::new();
+
+        match self.client.send(request).await {
+            Ok(_) => {}
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::NotFound) => {}
+            Err(error) => return Err(error.into()),
+        }
+
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        olm_machine.dehydrated_devices().delete_dehydrated_device_pickle_key().await?;
+
+        Ok(())
+    }
+
+    /// Import the pickle key of the dehydrated device from secret storage,
+    /// so the dehydrated device can be rehydrated and rotated.
+    ///
+    /// This is done automatically by [`SecretStore::import_secrets()`].
+    ///
+    /// Returns `true` if a pickle key has been found in secret storage.
+    pub async fn import_pickle_key(&self, secret_store: &SecretStore) -> Result<bool> {
+        match self.pickle_key_from_secret_store(secret_store).await? {
+            Some(pickle_key) => {
+                self.save_pickle_key(&pickle_key).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Replace the dehydrated device with a new one.
+    ///
+    /// Dehydrated devices should be rotated regularly, so that they don't run
+    /// out of one-time keys, and don't accumulate too many to-device events.
+    ///
+    /// The to-device events still waiting on the previous dehydrated device
+    /// are received first, and the room keys they contain are imported, so
+    /// that they aren't lost. If the previous dehydrated device can't be
+    /// rehydrated, e.g. because it was encrypted with another pickle key, it is
+    /// replaced anyway.
+    ///
+    /// Returns the ID of the new dehydrated device.
+    #[instrument(skip_all)]
+    pub async fn rotate(&self) -> Result<OwnedDeviceId> {
+        let pickle_key = self.pickle_key().await?.ok_or(DehydratedDeviceError::MissingPickleKey)?;
+
+        match self.rehydrate().without_rotation().await {
+            Ok(room_keys) => {
+                debug!(room_keys, "Received the events of the previous dehydrated device");
+            }
+            Err(DehydratedDeviceError::Dehydration(error)) => {
+                warn!("Couldn't rehydrate the previous dehydrated device, replacing it: {error}");
+            }
+            Err(error) => return Err(error),
+        }
+
+        self.upload(&pickle_key).await
+    }
+
+    /// Rehydrate the dehydrated device, and import the room keys it received.
+    ///
+    /// The to-device events of the dehydrated device are downloaded page by
+    /// page and decrypted, the room keys they contain are imported in the
+    /// store of the current device. Once done, the dehydrated device is
+    /// rotated, unless [`Rehydrate::without_rotation()`] is used.
+    ///
+    /// The pickle key must be available locally, see
+    /// [`DehydratedDevice::import_pickle_key()`].
+    ///
+    /// The future resolves to the number of imported room keys. Use
+    /// [`Rehydrate::subscribe_to_progress()`] to follow the progress.
+    pub fn rehydrate(&self) -> Rehydrate<'_> {
+        Rehydrate::new(self)
+    }
+
+    /// Rotate the dehydrated device every `period`, in the background, as long
+    /// as the [`Client`] is alive.
+    ///
+    /// This replaces any previously started periodic rotation.
+    pub fn start_periodic_rotation(&self, period: Duration) {
+        self.spawn_rotation_task(period, period);
+    }
+
+    /// Rehydrate the dehydrated device right away, in the background, and then
+    /// rotate it every [`DehydratedDevice::DEFAULT_ROTATION_PERIOD`].
+    ///
+    /// Used when [`EncryptionSettings::auto_manage_dehydrated_device`] is set
+    /// and the pickle key has just been imported from secret storage.
+    ///
+    /// [`EncryptionSettings::auto_manage_dehydrated_device`]: crate::encryption::EncryptionSettings::auto_manage_dehydrated_device
+    pub(crate) fn start_rehydration_and_rotation(&self) {
+        self.spawn_rotation_task(Duration::ZERO, Self::DEFAULT_ROTATION_PERIOD);
+    }
+
+    fn spawn_rotation_task(&self, initial_delay: Duration, period: Duration) {
+        let task = DehydratedDeviceRotationTask::new(
+            WeakClient::from_client(&self.client),
+            initial_delay,
+            period,
+        );
+        self.client.inner.e2ee.tasks.lock().rotate_dehydrated_device = Some(task);
+    }
+
+    /// Stop the periodic rotation started with
+    /// [`DehydratedDevice::start_periodic_rotation()`].
+    pub fn stop_periodic_rotation(&self) {
+        self.client.inner.e2ee.tasks.lock().rotate_dehydrated_device = None;
+    }
+
+    /// Fetch the dehydrated device from the homeserver, if there is one.
+    pub(super) async fn fetch(&self) -> Result<Option<get_dehydrated_device::unstable::Response>> {
+        let request = get_dehydrated_device::unstable::Request::new();
+
+        match self.client.send(request).await {
+            Ok(response) => Ok(Some(response)),
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::NotFound) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Create a new dehydrated device and upload it to the homeserver.
+    async fn upload(&self, pickle_key: &DehydratedDeviceKey) -> Result<OwnedDeviceId> {
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let device = olm_machine.dehydrated_devices().create().await?;
+        let request = device.keys_for_upload(Self::DISPLAY_NAME.to_owned(), pickle_key).await?;
+        let device_id = request.device_id.clone();
+
+        self.client.send(request).await?;
+
+        info!(%device_id, "Uploaded a new dehydrated device");
+
+        Ok(device_id)
+    }
+
+    async fn pickle_key(&self) -> Result<Option<DehydratedDeviceKey>> {
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm_machine.dehydrated_devices().get_dehydrated_device_pickle_key().await?)
+    }
+
+    async fn save_pickle_key(&self, pickle_key: &DehydratedDeviceKey) -> Result<()> {
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm_machine.dehydrated_devices().save_dehydrated_device_pickle_key(pickle_key).await?)
+    }
+
+    async fn pickle_key_from_secret_store(
+        &self,
+        secret_store: &SecretStore,
+    ) -> Result<Option<DehydratedDeviceKey>> {
+        let Some(secret) = secret_store.get_secret(SecretName::from(Self::SECRET_NAME)).await?
+        else {
+            return Ok(None);
+        };
+
+        let bytes =
+            base64_decode(secret.trim()).map_err(|_| DehydratedDeviceError::InvalidPickleKey)?;
+
+        DehydratedDeviceKey::from_slice(&bytes)
+            .map(Some)
+            .map_err(|_| DehydratedDeviceError::InvalidPickleKey)
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk_base::crypto::dehydrated_devices::DehydrationError;
+use ruma::OwnedDeviceId;
+use thiserror::Error;
+
+#[cfg(doc)]
+use crate::encryption::dehydrated_device::DehydratedDevice;
+
+/// Result type alias for the [`DehydratedDevice`] subsystem.
+pub type Result<A, E = DehydratedDeviceError> = std::result::Result<A, E>;
+
+/// Error type for the [`DehydratedDevice`] subsystem.
+#[derive(Debug, Error)]
+pub enum DehydratedDeviceError {
+    /// The key used to encrypt the dehydrated device is not available locally,
+    /// the device needs to be enabled, or the key needs to be imported from
+    /// secret storage first.
+    #[error("The dehydrated device pickle key is missing")]
+    MissingPickleKey,
+
+    /// The key used to encrypt the dehydrated device, found in secret storage,
+    /// isn't valid.
+    #[error("The dehydrated device pickle key found in secret storage is invalid")]
+    InvalidPickleKey,
+
+    /// A new key to encrypt the dehydrated device couldn't be generated.
+    #[error("Couldn't generate a new dehydrated device pickle key: {0}")]
+    PickleKeyGeneration(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// The dehydrated device couldn't be created or rehydrated.
+    #[error(transparent)]
+    Dehydration(#[from] DehydrationError),
+
+    /// A typical SDK error.
+    #[error(transparent)]
+    Sdk(#[from] crate::Error),
+}
+
+impl From<crate::HttpError> for DehydratedDeviceError {
+    fn from(error: crate::HttpError) -> Self {
+        Self::Sdk(error.into())
+    }
+}
+
+/// Enum describing the states the [`DehydratedDevice::rehydrate()`] method
+/// can be in.
+#[derive(Debug, Default, Clone)]
+pub enum RehydrationProgress {
+    /// The client is just starting the process of rehydration, this is the
+    /// initial state.
+    #[default]
+    Starting,
+
+    /// The client is downloading and decrypting the dehydrated device.
+    Rehydrating,
+
+    /// The client is downloading the to-device events the dehydrated device
+    /// has received. This state is emitted once per page of events.
+    ReceivingEvents {
+        /// The ID of the rehydrated device.
+        device_id: OwnedDeviceId,
+        /// The number of to-device events received so far.
+        events: usize,
+        /// The number of room keys imported so far.
+        room_keys: usize,
+    },
+
+    /// The client is replacing the rehydrated device with a new dehydrated
+    /// device.
+    Rotating,
+
+    /// The rehydration is done.
+    Done {
+        /// The number of room keys imported from the dehydrated device.
+        room_keys: usize,
+    },
+}
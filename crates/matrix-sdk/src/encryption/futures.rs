@@ -17,12 +17,31 @@
 
 #![deny(unreachable_pub)]
 
+#[cfg(not(target_family = "wasm"))]
+use std::io;
 use std::{future::IntoFuture, io::Read};
 
+#[cfg(not(target_family = "wasm"))]
+use bytes::Bytes;
 use eyeball::{SharedObservable, Subscriber};
+#[cfg(not(target_family = "wasm"))]
+use futures_util::StreamExt;
+#[cfg(not(target_family = "wasm"))]
+use matrix_sdk_base::crypto::{AttachmentEncryptor, MediaEncryptionInfo};
 use matrix_sdk_common::boxed_into_future;
 use ruma::events::room::{EncryptedFile, EncryptedFileInit};
+#[cfg(not(target_family = "wasm"))]
+use tokio::sync::mpsc;
+#[cfg(not(target_family = "wasm"))]
+use tokio_stream::wrappers::ReceiverStream;
+#[cfg(not(target_family = "wasm"))]
+use tokio_util::io::SyncIoBridge;
 
+#[cfg(not(target_family = "wasm"))]
+use crate::{
+    client::futures::SendMediaUploadStream,
+    media::{UploadStream, UPLOAD_STREAM_CHUNK_SIZE},
+};
 use crate::{config::RequestConfig, Client, Media, Result, TransmissionProgress};
 
 /// Future returned by [`Client::upload_encrypted_file`].
@@ -116,3 +135,130 @@ where
         })
     }
 }
+
+/// Future returned by [`Client::upload_encrypted_stream`].
+#[cfg(not(target_family = "wasm"))]
+#[allow(missing_debug_implementations)]
+pub struct UploadEncryptedStream {
+    client: Client,
+    stream: UploadStream,
+    send_progress: SharedObservable<TransmissionProgress>,
+    request_config: Option<RequestConfig>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl UploadEncryptedStream {
+    pub(crate) fn new(client: &Client, stream: UploadStream) -> Self {
+        Self {
+            client: client.clone(),
+            stream,
+            send_progress: Default::default(),
+            request_config: None,
+        }
+    }
+
+    /// Replace the default `SharedObservable` used for tracking upload
+    /// progress.
+    ///
+    /// Note that any subscribers obtained from
+    /// [`subscribe_to_send_progress`][Self::subscribe_to_send_progress]
+    /// will be invalidated by this.
+    pub fn with_send_progress_observable(
+        mut self,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Self {
+        self.send_progress = send_progress;
+        self
+    }
+
+    /// Replace the default request config used for the upload request.
+    ///
+    /// The timeout value will be overridden with a reasonable default, based on
+    /// the size of the encrypted payload.
+    pub fn with_request_config(mut self, request_config: RequestConfig) -> Self {
+        self.request_config = Some(request_config);
+        self
+    }
+
+    /// Get a subscriber to observe the progress of sending the request
+    /// body.
+    pub fn subscribe_to_send_progress(&self) -> Subscriber<TransmissionProgress> {
+        self.send_progress.subscribe()
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl IntoFuture for UploadEncryptedStream {
+    type Output = Result<EncryptedFile>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { client, stream, send_progress, request_config } = self;
+        Box::pin(async move {
+            // AES-CTR doesn't change the length of the payload.
+            let length = stream.len();
+
+            // The encryptor only works over a blocking reader, so run it on a dedicated
+            // thread, and forward the encrypted chunks to the request body as they come.
+            let (sender, receiver) = mpsc::channel(4);
+            let reader = SyncIoBridge::new(stream.into_reader());
+            let encryption = tokio::task::spawn_blocking(move || encrypt_into(reader, sender));
+
+            let request_config = request_config
+                .map(|config| config.timeout(Media::reasonable_upload_timeout_for_length(length)));
+
+            let response = SendMediaUploadStream::new(
+                client,
+                mime::APPLICATION_OCTET_STREAM,
+                ReceiverStream::new(receiver).boxed(),
+                length,
+                request_config,
+            )
+            .with_send_progress_observable(send_progress)
+            .await?;
+
+            let keys = encryption.await.map_err(io::Error::other)??;
+
+            let file: EncryptedFile = EncryptedFileInit {
+                url: response.content_uri,
+                key: keys.key,
+                iv: keys.iv,
+                hashes: keys.hashes,
+                v: keys.version,
+            }
+            .into();
+
+            Ok(file)
+        })
+    }
+}
+
+/// Encrypt everything that can be read from `reader`, sending the encrypted
+/// chunks to `sender`, and return the encryption info once done.
+#[cfg(not(target_family = "wasm"))]
+fn encrypt_into(
+    mut reader: impl Read,
+    sender: mpsc::Sender<io::Result<Bytes>>,
+) -> io::Result<MediaEncryptionInfo> {
+    let mut encryptor = AttachmentEncryptor::new(&mut reader);
+    let mut buf = vec![0; UPLOAD_STREAM_CHUNK_SIZE];
+
+    loop {
+        let read = match encryptor.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                // Make the upload fail too.
+                let _ = sender.blocking_send(Err(io::Error::new(err.kind(), err.to_string())));
+                return Err(err);
+            }
+        };
+
+        if sender.blocking_send(Ok(Bytes::copy_from_slice(&buf[..read]))).is_err() {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "the upload has been aborted"));
+        }
+    }
+
+    Ok(encryptor.finish())
+}
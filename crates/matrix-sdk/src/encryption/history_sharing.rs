@@ -0,0 +1,780 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sharing of the room history on invite, as per [MSC4268].
+//!
+//! When [`ClientBuilder::with_enable_share_history_on_invite()`] is enabled,
+//! inviting a user to an encrypted room with [`Room::invite_user_by_id()`]
+//! creates a history sharing job: a bundle of the room keys of the room is
+//! built, uploaded as an encrypted file, and sent to the devices of the
+//! invitee.
+//!
+//! The jobs are persisted in the state store. A job whose attempt fails, e.g.
+//! because of a flaky network, is retried in the background with an
+//! exponential backoff, including after a restart of the application. The
+//! jobs, and their outcome, can be observed with
+//! [`HistorySharing::subscribe()`], so that the inviter can see which invitees
+//! received the room history.
+//!
+//! [MSC4268]: https://github.com/matrix-org/matrix-spec-proposals/pull/4268
+//! [`ClientBuilder::with_enable_share_history_on_invite()`]: crate::ClientBuilder::with_enable_share_history_on_invite
+//! [`Room::invite_user_by_id()`]: crate::Room::invite_user_by_id
+
+use std::time::Duration;
+
+use eyeball::{SharedObservable, Subscriber};
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedUserId, RoomId, UInt, UserId};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{info, instrument, warn};
+
+use crate::{
+    room::shared_room_history::{self, ShareRoomHistoryOutcome},
+    Client, Result,
+};
+
+/// The key under which the history sharing jobs are persisted in the state
+/// store.
+const HISTORY_SHARING_JOBS_STORE_KEY: &[u8] = b"history_sharing_jobs";
+
+/// The status of a [`HistorySharingJob`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum HistorySharingStatus {
+    /// The job is waiting for its next attempt.
+    Pending {
+        /// When the next attempt is due, or `None` if it's due immediately.
+        next_attempt_at: Option<MilliSecondsSinceUnixEpoch>,
+
+        /// The error of the previous attempt, if any.
+        last_error: Option<String>,
+    },
+
+    /// The bundle of room keys is being built.
+    BuildingBundle,
+
+    /// The bundle of room keys is being uploaded.
+    UploadingBundle,
+
+    /// The bundle of room keys is being sent to the devices of the invitee.
+    SendingBundle,
+
+    /// The bundle of room keys has been sent to the invitee.
+    Sent {
+        /// When the bundle has been sent.
+        at: MilliSecondsSinceUnixEpoch,
+
+        /// The number of room keys in the bundle.
+        room_keys: usize,
+    },
+
+    /// There were no room keys to share with the invitee.
+    NothingToShare,
+
+    /// The job has been given up, after too many failed attempts, or because
+    /// it can't succeed.
+    Failed {
+        /// The error of the last attempt.
+        error: String,
+    },
+}
+
+impl HistorySharingStatus {
+    /// Whether the job is finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Sent { .. } | Self::NothingToShare | Self::Failed { .. })
+    }
+
+    /// Whether an attempt of the job is in progress.
+    pub fn is_in_progress(&self) -> bool {
+        matches!(self, Self::BuildingBundle | Self::UploadingBundle | Self::SendingBundle)
+    }
+}
+
+/// A job sharing the history of a room with a user invited to it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistorySharingJob {
+    /// The room whose history is shared.
+    pub room_id: OwnedRoomId,
+
+    /// The invited user, with whom the history is shared.
+    pub user_id: OwnedUserId,
+
+    /// When the job has been created.
+    pub created_at: MilliSecondsSinceUnixEpoch,
+
+    /// The number of attempts made so far.
+    pub attempts: u32,
+
+    /// The status of the job.
+    pub status: HistorySharingStatus,
+}
+
+impl HistorySharingJob {
+    fn new(room_id: OwnedRoomId, user_id: OwnedUserId) -> Self {
+        Self {
+            room_id,
+            user_id,
+            created_at: MilliSecondsSinceUnixEpoch::now(),
+            attempts: 0,
+            status: HistorySharingStatus::Pending { next_attempt_at: None, last_error: None },
+        }
+    }
+
+    fn is(&self, room_id: &RoomId, user_id: &UserId) -> bool {
+        self.room_id == room_id && self.user_id == user_id
+    }
+
+    /// Whether this job is finished and older than
+    /// [`HistorySharing::FINISHED_JOBS_RETENTION`], so it can be pruned.
+    fn is_expired(&self, now: MilliSecondsSinceUnixEpoch) -> bool {
+        let age = Duration::from_millis(now.0.saturating_sub(self.created_at.0).into());
+        self.status.is_finished() && age > HistorySharing::FINISHED_JOBS_RETENTION
+    }
+
+    /// The delay until the next attempt of this job, if it's pending.
+    fn delay_until_next_attempt(&self, now: MilliSecondsSinceUnixEpoch) -> Option<Duration> {
+        match &self.status {
+            HistorySharingStatus::Pending { next_attempt_at: Some(at), .. } => {
+                Some(Duration::from_millis(at.0.saturating_sub(now.0).into()))
+            }
+            HistorySharingStatus::Pending { next_attempt_at: None, .. } => Some(Duration::ZERO),
+            _ => None,
+        }
+    }
+}
+
+/// The state of the history sharing jobs, shared by all the rooms of a client.
+#[derive(Default)]
+pub(crate) struct HistorySharingState {
+    /// All the jobs, loaded lazily from the state store.
+    jobs: SharedObservable<Vec<HistorySharingJob>>,
+
+    /// Whether the jobs have been loaded from the state store.
+    loaded: OnceCell<()>,
+
+    /// A lock to run a single job at a time, so that a job is never run twice
+    /// concurrently.
+    run_lock: Mutex<()>,
+
+    /// A lock held from the change of the jobs until they are persisted, so
+    /// that concurrent changes are persisted in the order they are made.
+    persist_lock: Mutex<()>,
+}
+
+/// The history sharing manager for the [`Client`].
+#[derive(Debug, Clone)]
+pub struct HistorySharing {
+    pub(super) client: Client,
+}
+
+impl HistorySharing {
+    /// The maximum number of attempts of a job, before it's given up.
+    pub const MAX_ATTEMPTS: u32 = 10;
+
+    /// The delay before the first retry of a failed attempt. It's doubled for
+    /// every subsequent attempt, up to [`HistorySharing::MAX_RETRY_DELAY`].
+    pub const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+    /// The maximum delay between two attempts of a job.
+    pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+    /// How long a finished job is kept after its creation. Older finished jobs
+    /// are pruned when the jobs are loaded, and when a new job is created.
+    pub const FINISHED_JOBS_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+    /// Get all the history sharing jobs, including the finished ones.
+    pub async fn jobs(&self) -> Result<Vec<HistorySharingJob>> {
+        self.load().await?;
+        Ok(self.state().jobs.get())
+    }
+
+    /// Get the history sharing jobs of the given room, i.e. the users invited
+    /// to it with whom the room history is, or has been, shared.
+    pub async fn jobs_for_room(&self, room_id: &RoomId) -> Result<Vec<HistorySharingJob>> {
+        Ok(self.jobs().await?.into_iter().filter(|job| job.room_id == room_id).collect())
+    }
+
+    /// Subscribe to the updates of the history sharing jobs.
+    ///
+    /// The subscriber yields the current list of jobs first, then the full
+    /// list of jobs every time a job is created, progresses, or is removed.
+    pub async fn subscribe(&self) -> Result<Subscriber<Vec<HistorySharingJob>>> {
+        self.load().await?;
+        Ok(self.state().jobs.subscribe_reset())
+    }
+
+    /// Retry a job immediately.
+    ///
+    /// This is useful for a job that has been given up, or to skip the
+    /// backoff delay of a pending job. Does nothing if the job doesn't exist,
+    /// or if it's finished successfully.
+    pub async fn retry(&self, room_id: &RoomId, user_id: &UserId) -> Result<()> {
+        let updated = self
+            .update(room_id, user_id, |job| match &job.status {
+                HistorySharingStatus::Failed { error } => {
+                    job.attempts = 0;
+                    job.status = HistorySharingStatus::Pending {
+                        next_attempt_at: None,
+                        last_error: Some(error.clone()),
+                    };
+                }
+                HistorySharingStatus::Pending { last_error, .. } => {
+                    job.status = HistorySharingStatus::Pending {
+                        next_attempt_at: None,
+                        last_error: last_error.clone(),
+                    };
+                }
+                _ => {}
+            })
+            .await?;
+
+        if updated {
+            self.wake_up_task();
+        }
+
+        Ok(())
+    }
+
+    /// Remove a job, whatever its status.
+    ///
+    /// An attempt in progress isn't interrupted, but the job won't be retried.
+    pub async fn remove(&self, room_id: &RoomId, user_id: &UserId) -> Result<()> {
+        self.modify(|jobs| {
+            let len = jobs.len();
+            jobs.retain(|job| !job.is(room_id, user_id));
+            jobs.len() != len
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove all the finished jobs.
+    pub async fn clear_finished(&self) -> Result<()> {
+        self.modify(|jobs| {
+            let len = jobs.len();
+            jobs.retain(|job| !job.status.is_finished());
+            jobs.len() != len
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Create a job to share the history of the given room with the given
+    /// user, replacing any previous job for the same room and user.
+    pub(crate) async fn enqueue(&self, room_id: &RoomId, user_id: &UserId) -> Result<()> {
+        let now = MilliSecondsSinceUnixEpoch::now();
+
+        self.modify(|jobs| {
+            jobs.retain(|job| !job.is(room_id, user_id) && !job.is_expired(now));
+            jobs.push(HistorySharingJob::new(room_id.to_owned(), user_id.to_owned()));
+            true
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Run all the pending jobs that are due.
+    ///
+    /// Returns the delay until the next pending job is due, if any.
+    pub(crate) async fn run_due_jobs(&self) -> Result<Option<Duration>> {
+        self.load().await?;
+
+        let now = MilliSecondsSinceUnixEpoch::now();
+        let due_jobs = self
+            .state()
+            .jobs
+            .get()
+            .into_iter()
+            .filter(|job| job.delay_until_next_attempt(now) == Some(Duration::ZERO));
+
+        for job in due_jobs {
+            self.run_job(&job.room_id, &job.user_id).await?;
+        }
+
+        let now = MilliSecondsSinceUnixEpoch::now();
+
+        Ok(self.state().jobs.get().iter().filter_map(|job| job.delay_until_next_attempt(now)).min())
+    }
+
+    /// Run an attempt of the job for the given room and user, if it's
+    /// pending, regardless of its backoff delay.
+    ///
+    /// The failure of the attempt is recorded in the job, and isn't returned:
+    /// an error is returned only if the job couldn't be persisted.
+    #[instrument(skip(self))]
+    pub(crate) async fn run_job(&self, room_id: &RoomId, user_id: &UserId) -> Result<()> {
+        self.load().await?;
+
+        let state = self.state();
+        let _guard = state.run_lock.lock().await;
+
+        let Some(job) = state.jobs.get().into_iter().find(|job| job.is(room_id, user_id)) else {
+            return Ok(());
+        };
+
+        if !matches!(job.status, HistorySharingStatus::Pending { .. }) {
+            return Ok(());
+        }
+
+        let attempts = job.attempts + 1;
+
+        let status = match self.client.get_room(room_id) {
+            Some(room) => {
+                self.set_status(room_id, user_id, HistorySharingStatus::BuildingBundle);
+
+                let result =
+                    shared_room_history::share_room_history(&room, user_id.to_owned(), |status| {
+                        self.set_status(room_id, user_id, status)
+                    })
+                    .await;
+
+                match result {
+                    Ok(ShareRoomHistoryOutcome::Sent { room_keys }) => {
+                        info!(room_keys, "The room history has been shared");
+                        HistorySharingStatus::Sent {
+                            at: MilliSecondsSinceUnixEpoch::now(),
+                            room_keys,
+                        }
+                    }
+                    Ok(ShareRoomHistoryOutcome::NothingToShare) => {
+                        HistorySharingStatus::NothingToShare
+                    }
+                    Ok(ShareRoomHistoryOutcome::CrossSigningNotSetUp) => {
+                        Self::retry_status(attempts, "cross-signing is not set up".to_owned())
+                    }
+                    Err(error) => {
+                        warn!(attempts, "Couldn't share the room history: {error}");
+                        Self::retry_status(attempts, error.to_string())
+                    }
+                }
+            }
+            None => HistorySharingStatus::Failed { error: "the room is unknown".to_owned() },
+        };
+
+        self.update(room_id, user_id, |job| {
+            job.attempts = attempts;
+            job.status = status;
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// The status of a job after a failed attempt.
+    fn retry_status(attempts: u32, error: String) -> HistorySharingStatus {
+        if attempts >= Self::MAX_ATTEMPTS {
+            return HistorySharingStatus::Failed { error };
+        }
+
+        let delay = Self::INITIAL_RETRY_DELAY
+            .saturating_mul(2u32.saturating_pow(attempts - 1))
+            .min(Self::MAX_RETRY_DELAY);
+        let delay = UInt::new_saturating(delay.as_millis().try_into().unwrap_or(u64::MAX));
+        let next_attempt_at =
+            MilliSecondsSinceUnixEpoch(MilliSecondsSinceUnixEpoch::now().0.saturating_add(delay));
+
+        HistorySharingStatus::Pending {
+            next_attempt_at: Some(next_attempt_at),
+            last_error: Some(error),
+        }
+    }
+
+    /// Wake up the background task running the jobs, so it takes a change to
+    /// the jobs into account.
+    pub(crate) fn wake_up_task(&self) {
+        if let Some(task) = &self.client.inner.e2ee.tasks.lock().share_room_history {
+            task.wake_up();
+        }
+    }
+
+    fn state(&self) -> &HistorySharingState {
+        &self.client.inner.e2ee.history_sharing
+    }
+
+    /// Load the jobs from the state store, if not done yet.
+    ///
+    /// If the persisted jobs can't be deserialized, an error is returned and
+    /// the jobs stay unloaded, so that they are never overwritten.
+    async fn load(&self) -> Result<()> {
+        self.state().loaded.get_or_try_init(|| self.load_from_store()).await?;
+        Ok(())
+    }
+
+    async fn load_from_store(&self) -> Result<()> {
+        let Some(bytes) =
+            self.client.state_store().get_custom_value(HISTORY_SHARING_JOBS_STORE_KEY).await?
+        else {
+            return Ok(());
+        };
+
+        let mut jobs: Vec<HistorySharingJob> = serde_json::from_slice(&bytes)?;
+
+        let now = MilliSecondsSinceUnixEpoch::now();
+        jobs.retain(|job| !job.is_expired(now));
+
+        // An attempt that was in progress has been interrupted, e.g. by a restart.
+        for job in &mut jobs {
+            if job.status.is_in_progress() {
+                job.status =
+                    HistorySharingStatus::Pending { next_attempt_at: None, last_error: None };
+            }
+        }
+
+        self.state().jobs.set(jobs);
+
+        Ok(())
+    }
+
+    /// Change the jobs with `f`, and persist them if `f` returns `true`.
+    ///
+    /// The jobs are changed in place, and the persist lock is held until they
+    /// are saved, so that a concurrent change, e.g. from the background task,
+    /// is never lost.
+    ///
+    /// Returns the value returned by `f`.
+    async fn modify(&self, f: impl FnOnce(&mut Vec<HistorySharingJob>) -> bool) -> Result<bool> {
+        self.load().await?;
+
+        let state = self.state();
+        let _guard = state.persist_lock.lock().await;
+
+        let mut changed = false;
+        state.jobs.update_if(|jobs| {
+            changed = f(jobs);
+            changed
+        });
+
+        if changed {
+            let bytes = serde_json::to_vec(&*state.jobs.read())?;
+            self.client
+                .state_store()
+                .set_custom_value(HISTORY_SHARING_JOBS_STORE_KEY, bytes)
+                .await?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Update the job for the given room and user, and persist the jobs.
+    ///
+    /// Returns `false` if there is no such job.
+    async fn update(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        f: impl FnOnce(&mut HistorySharingJob),
+    ) -> Result<bool> {
+        self.modify(|jobs| match jobs.iter_mut().find(|job| job.is(room_id, user_id)) {
+            Some(job) => {
+                f(job);
+                true
+            }
+            None => false,
+        })
+        .await
+    }
+
+    /// Update the status of a job in memory only, to report the progress of an
+    /// attempt.
+    fn set_status(&self, room_id: &RoomId, user_id: &UserId, status: HistorySharingStatus) {
+        self.state().jobs.update_if(|jobs| {
+            match jobs.iter_mut().find(|job| job.is(room_id, user_id)) {
+                Some(job) => {
+                    job.status = status;
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use assert_matches2::assert_let;
+    use futures_util::future::join_all;
+    use matrix_sdk_test::async_test;
+    use ruma::{
+        owned_room_id, owned_user_id, room_id, user_id, MilliSecondsSinceUnixEpoch, UInt, UserId,
+    };
+
+    use super::{
+        HistorySharing, HistorySharingJob, HistorySharingStatus, HISTORY_SHARING_JOBS_STORE_KEY,
+    };
+    use crate::{test_utils::mocks::MatrixMockServer, Client};
+
+    /// Get the jobs persisted in the state store of the client.
+    async fn persisted_jobs(client: &Client) -> Vec<HistorySharingJob> {
+        let bytes = client
+            .state_store()
+            .get_custom_value(HISTORY_SHARING_JOBS_STORE_KEY)
+            .await
+            .unwrap()
+            .expect("The jobs should have been persisted");
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_retry_status_backoff() {
+        let now = MilliSecondsSinceUnixEpoch::now();
+
+        assert_let!(
+            HistorySharingStatus::Pending { next_attempt_at: Some(at), last_error: Some(error) } =
+                HistorySharing::retry_status(1, "oops".to_owned())
+        );
+        assert_eq!(error, "oops");
+        let delay = Duration::from_millis(at.0.saturating_sub(now.0).into());
+        assert!(delay >= HistorySharing::INITIAL_RETRY_DELAY);
+        assert!(delay < HistorySharing::INITIAL_RETRY_DELAY * 2);
+
+        // The delay is capped.
+        assert_let!(
+            HistorySharingStatus::Pending { next_attempt_at: Some(at), .. } =
+                HistorySharing::retry_status(HistorySharing::MAX_ATTEMPTS - 1, "oops".to_owned())
+        );
+        let delay = Duration::from_millis(at.0.saturating_sub(now.0).into());
+        assert!(delay <= HistorySharing::MAX_RETRY_DELAY + Duration::from_secs(1));
+
+        // The job is given up after too many attempts.
+        assert_let!(
+            HistorySharingStatus::Failed { error } =
+                HistorySharing::retry_status(HistorySharing::MAX_ATTEMPTS, "oops".to_owned())
+        );
+        assert_eq!(error, "oops");
+    }
+
+    #[test]
+    fn test_delay_until_next_attempt() {
+        let now = MilliSecondsSinceUnixEpoch(UInt::new(10_000).unwrap());
+        let mut job =
+            HistorySharingJob::new(owned_room_id!("!a:b.c"), owned_user_id!("@alice:b.c"));
+
+        assert_eq!(job.delay_until_next_attempt(now), Some(Duration::ZERO));
+
+        job.status = HistorySharingStatus::Pending {
+            next_attempt_at: Some(MilliSecondsSinceUnixEpoch(UInt::new(12_000).unwrap())),
+            last_error: None,
+        };
+        assert_eq!(job.delay_until_next_attempt(now), Some(Duration::from_secs(2)));
+
+        // An overdue job is due immediately.
+        job.status = HistorySharingStatus::Pending {
+            next_attempt_at: Some(MilliSecondsSinceUnixEpoch(UInt::new(5_000).unwrap())),
+            last_error: None,
+        };
+        assert_eq!(job.delay_until_next_attempt(now), Some(Duration::ZERO));
+
+        job.status = HistorySharingStatus::NothingToShare;
+        assert_eq!(job.delay_until_next_attempt(now), None);
+    }
+
+    #[async_test]
+    async fn test_concurrent_changes_are_persisted() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let history_sharing = client.encryption().history_sharing();
+        let room_id = room_id!("!a:b.c");
+
+        let user_ids: Vec<_> =
+            (0..20).map(|i| UserId::parse(format!("@user{i}:b.c")).unwrap()).collect();
+
+        join_all(user_ids.iter().map(|user_id| history_sharing.enqueue(room_id, user_id)))
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // No job was lost, neither in memory nor in the store.
+        assert_eq!(history_sharing.jobs().await.unwrap().len(), user_ids.len());
+        assert_eq!(persisted_jobs(&client).await.len(), user_ids.len());
+
+        join_all(
+            user_ids.iter().step_by(2).map(|user_id| history_sharing.remove(room_id, user_id)),
+        )
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+        assert_eq!(history_sharing.jobs().await.unwrap().len(), user_ids.len() / 2);
+        assert_eq!(persisted_jobs(&client).await, history_sharing.jobs().await.unwrap());
+    }
+
+    #[async_test]
+    async fn test_jobs_are_loaded_from_the_store() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = owned_room_id!("!a:b.c");
+
+        let mut in_progress = HistorySharingJob::new(room_id.clone(), owned_user_id!("@a:b.c"));
+        in_progress.attempts = 1;
+        in_progress.status = HistorySharingStatus::UploadingBundle;
+        let mut failed = HistorySharingJob::new(room_id, owned_user_id!("@b:b.c"));
+        failed.attempts = HistorySharing::MAX_ATTEMPTS;
+        failed.status = HistorySharingStatus::Failed { error: "oops".to_owned() };
+
+        client
+            .state_store()
+            .set_custom_value(
+                HISTORY_SHARING_JOBS_STORE_KEY,
+                serde_json::to_vec(&[&in_progress, &failed]).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let jobs = client.encryption().history_sharing().jobs().await.unwrap();
+        assert_eq!(jobs.len(), 2);
+
+        // The interrupted attempt is pending again.
+        assert_eq!(jobs[0].attempts, 1);
+        assert_eq!(
+            jobs[0].status,
+            HistorySharingStatus::Pending { next_attempt_at: None, last_error: None }
+        );
+        assert_eq!(jobs[1], failed);
+    }
+
+    #[async_test]
+    async fn test_expired_jobs_are_pruned() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let history_sharing = client.encryption().history_sharing();
+        let room_id = owned_room_id!("!a:b.c");
+
+        // Twice the retention period ago.
+        let long_ago = MilliSecondsSinceUnixEpoch(
+            MilliSecondsSinceUnixEpoch::now().0 - UInt::new(14 * 24 * 60 * 60 * 1000).unwrap(),
+        );
+
+        let mut expired = HistorySharingJob::new(room_id.clone(), owned_user_id!("@a:b.c"));
+        expired.created_at = long_ago;
+        expired.status = HistorySharingStatus::NothingToShare;
+        let mut old_pending = HistorySharingJob::new(room_id.clone(), owned_user_id!("@b:b.c"));
+        old_pending.created_at = long_ago;
+        let recent = HistorySharingJob {
+            status: HistorySharingStatus::NothingToShare,
+            ..HistorySharingJob::new(room_id.clone(), owned_user_id!("@c:b.c"))
+        };
+
+        client
+            .state_store()
+            .set_custom_value(
+                HISTORY_SHARING_JOBS_STORE_KEY,
+                serde_json::to_vec(&[&expired, &old_pending, &recent]).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        // The old finished job is pruned, but not the old pending one.
+        let jobs = history_sharing.jobs().await.unwrap();
+        assert_eq!(jobs, vec![old_pending.clone(), recent.clone()]);
+
+        // The pruned jobs are persisted with the next change.
+        history_sharing.enqueue(&room_id, user_id!("@d:b.c")).await.unwrap();
+        let persisted = persisted_jobs(&client).await;
+        assert_eq!(persisted.len(), 3);
+        assert_eq!(persisted[..2], [old_pending, recent]);
+    }
+
+    #[async_test]
+    async fn test_undeserializable_jobs_are_not_overwritten() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let history_sharing = client.encryption().history_sharing();
+
+        client
+            .state_store()
+            .set_custom_value(HISTORY_SHARING_JOBS_STORE_KEY, b"not json".to_vec())
+            .await
+            .unwrap();
+
+        history_sharing.jobs().await.unwrap_err();
+        history_sharing.enqueue(room_id!("!a:b.c"), user_id!("@a:b.c")).await.unwrap_err();
+
+        // The persisted jobs are left untouched.
+        let bytes = client
+            .state_store()
+            .get_custom_value(HISTORY_SHARING_JOBS_STORE_KEY)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(bytes, b"not json");
+    }
+
+    #[async_test]
+    async fn test_retry() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let history_sharing = client.encryption().history_sharing();
+        let room_id = room_id!("!unknown:b.c");
+        let user_id = user_id!("@a:b.c");
+
+        history_sharing.enqueue(room_id, user_id).await.unwrap();
+
+        // The room is unknown, so the job can't succeed.
+        history_sharing.run_job(room_id, user_id).await.unwrap();
+
+        let job = history_sharing.jobs().await.unwrap().remove(0);
+        assert_eq!(job.attempts, 1);
+        assert_let!(HistorySharingStatus::Failed { error } = &job.status);
+        assert_eq!(persisted_jobs(&client).await, vec![job.clone()]);
+
+        // Retrying a failed job resets its attempts, and makes it due immediately.
+        history_sharing.retry(room_id, user_id).await.unwrap();
+
+        let job = history_sharing.jobs().await.unwrap().remove(0);
+        assert_eq!(job.attempts, 0);
+        assert_eq!(
+            job.status,
+            HistorySharingStatus::Pending {
+                next_attempt_at: None,
+                last_error: Some(error.clone())
+            }
+        );
+        assert_eq!(
+            job.delay_until_next_attempt(MilliSecondsSinceUnixEpoch::now()),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(persisted_jobs(&client).await, vec![job]);
+
+        // A pending job with a backoff delay is due immediately after a retry.
+        history_sharing
+            .update(room_id, user_id, |job| {
+                job.status = HistorySharing::retry_status(3, "oops".to_owned());
+            })
+            .await
+            .unwrap();
+        history_sharing.retry(room_id, user_id).await.unwrap();
+
+        let job = history_sharing.jobs().await.unwrap().remove(0);
+        assert_eq!(job.attempts, 0);
+        assert_eq!(
+            job.status,
+            HistorySharingStatus::Pending {
+                next_attempt_at: None,
+                last_error: Some("oops".to_owned())
+            }
+        );
+
+        // Retrying a job that doesn't exist does nothing.
+        history_sharing.retry(room_id, user_id!("@b:b.c")).await.unwrap();
+        assert_eq!(history_sharing.jobs().await.unwrap().len(), 1);
+    }
+}
@@ -20,6 +20,7 @@
 use std::ops::Deref;
 use std::{
     collections::{BTreeMap, HashSet},
+    future::IntoFuture,
     io::{Cursor, Read, Write},
     iter,
     path::PathBuf,
@@ -72,15 +73,23 @@ use tracing::{debug, error, instrument, trace, warn};
 use url::Url;
 use vodozemac::Curve25519PublicKey;
 
+#[cfg(not(target_family = "wasm"))]
+use self::futures::UploadEncryptedStream;
 use self::{
     backups::{types::BackupClientState, Backups},
+    dehydrated_device::DehydratedDevice,
     futures::UploadEncryptedFile,
+    history_sharing::{HistorySharing, HistorySharingState},
     identities::{Device, DeviceUpdates, IdentityUpdates, UserDevices, UserIdentity},
     recovery::{Recovery, RecoveryState},
+    room_key_preshare::{RoomKeyPreshareSettings, RoomKeyPreshareState, RoomKeyPreshareStats},
     secret_storage::SecretStorage,
-    tasks::{BackupDownloadTask, BackupUploadingTask, ClientTasks},
+    tasks::{BackupDownloadTask, BackupUploadingTask, ClientTasks, HistorySharingTask},
+    utd_remediation::{UtdRemediation, UtdRemediationState},
     verification::{SasVerification, Verification, VerificationRequest},
 };
+#[cfg(not(target_family = "wasm"))]
+use crate::media::UploadStream;
 use crate::{
     attachment::Thumbnail,
     client::{ClientInner, WeakClient},
@@ -90,11 +99,15 @@ use crate::{
 };
 
 pub mod backups;
+pub mod dehydrated_device;
 pub mod futures;
+pub mod history_sharing;
 pub mod identities;
 pub mod recovery;
+pub mod room_key_preshare;
 pub mod secret_storage;
 pub(crate) mod tasks;
+pub mod utd_remediation;
 pub mod verification;
 
 pub use matrix_sdk_base::crypto::{
@@ -125,6 +138,16 @@ pub(crate) struct EncryptionData {
 
     /// All state related to secret storage recovery.
     pub recovery_state: SharedObservable<RecoveryState>,
+
+    /// All state related to the pre-sharing of room keys.
+    pub room_key_preshare: RoomKeyPreshareState,
+
+    /// All state related to the sharing of the room history on invite.
+    pub history_sharing: HistorySharingState,
+
+    /// All state related to the automatic remediation of unable-to-decrypt
+    /// events.
+    pub utd_remediation: UtdRemediationState,
 }
 
 impl EncryptionData {
@@ -135,6 +158,9 @@ impl EncryptionData {
             tasks: StdMutex::new(Default::default()),
             backup_state: Default::default(),
             recovery_state: Default::default(),
+            room_key_preshare: Default::default(),
+            history_sharing: Default::default(),
+            utd_remediation: Default::default(),
         }
     }
 
@@ -184,6 +210,18 @@ pub struct EncryptionSettings {
 
     /// Automatically create a backup version if no backup exists.
     pub auto_enable_backups: bool,
+
+    /// Automatically rehydrate and rotate the [dehydrated device], if one is
+    /// enabled for this user.
+    ///
+    /// The dehydrated device is rehydrated as soon as its pickle key is
+    /// imported from secret storage, typically after logging in on a new
+    /// device, and then rotated every
+    /// [`DehydratedDevice::DEFAULT_ROTATION_PERIOD`]. When restoring a session
+    /// which already has the pickle key, only the periodic rotation is started.
+    ///
+    /// [dehydrated device]: crate::encryption::dehydrated_device
+    pub auto_manage_dehydrated_device: bool,
 }
 
 /// Settings for end-to-end encryption features.
@@ -458,6 +496,49 @@ impl Client {
         UploadEncryptedFile::new(self, reader)
     }
 
+    /// Construct a [`EncryptedFile`][ruma::events::room::EncryptedFile] by
+    /// encrypting and uploading the content of an [`UploadStream`].
+    ///
+    /// Contrary to [`Client::upload_encrypted_file()`], the content is
+    /// encrypted and uploaded chunk by chunk, so neither the content nor its
+    /// encrypted copy is ever entirely held in memory.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, media::UploadStream};
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://example.com")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let stream = UploadStream::from_path("/home/example/my-cat.mp4").await?;
+    /// let encrypted_file = client.upload_encrypted_stream(stream).await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[cfg(not(target_family = "wasm"))]
+    pub fn upload_encrypted_stream(&self, stream: UploadStream) -> UploadEncryptedStream {
+        UploadEncryptedStream::new(self, stream)
+    }
+
+    /// Encrypt and upload the file streamed from `stream` and the thumbnail,
+    /// and return the source information.
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) async fn upload_encrypted_stream_and_thumbnail(
+        &self,
+        stream: UploadStream,
+        thumbnail: Option<Thumbnail>,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Result<(MediaSource, Option<(MediaSource, Box<ThumbnailInfo>)>)> {
+        let upload_thumbnail = self.upload_encrypted_thumbnail(thumbnail, send_progress.clone());
+
+        let upload_attachment =
+            self.upload_encrypted_stream(stream).with_send_progress_observable(send_progress);
+
+        let (thumbnail, file) = try_join(upload_thumbnail, upload_attachment.into_future()).await?;
+
+        Ok((MediaSource::Encrypted(Box::new(file)), thumbnail))
+    }
+
     /// Encrypt and upload the file and thumbnails, and return the source
     /// information.
     pub(crate) async fn upload_encrypted_media_and_thumbnail(
@@ -1554,6 +1635,45 @@ impl Encryption {
         Recovery { client: self.client.to_owned() }
     }
 
+    /// Get the dehydrated device manager of the client.
+    pub fn dehydrated_device(&self) -> DehydratedDevice {
+        DehydratedDevice { client: self.client.to_owned() }
+    }
+
+    /// Get the history sharing manager of the client.
+    ///
+    /// See [`history_sharing`] for more details.
+    pub fn history_sharing(&self) -> HistorySharing {
+        HistorySharing { client: self.client.to_owned() }
+    }
+
+    /// Get the manager of the automatic remediation of unable-to-decrypt
+    /// events.
+    ///
+    /// See [`utd_remediation`] for more details.
+    pub fn utd_remediation(&self) -> UtdRemediation {
+        UtdRemediation { client: self.client.to_owned() }
+    }
+
+    /// Get the settings for the pre-sharing of room keys.
+    ///
+    /// See [`Room::preshare_room_key()`] for more details.
+    pub fn room_key_preshare_settings(&self) -> RoomKeyPreshareSettings {
+        self.client.inner.e2ee.room_key_preshare.settings()
+    }
+
+    /// Set the settings for the pre-sharing of room keys, e.g. to pre-share
+    /// room keys automatically when the user starts typing.
+    pub fn set_room_key_preshare_settings(&self, settings: RoomKeyPreshareSettings) {
+        self.client.inner.e2ee.room_key_preshare.set_settings(settings);
+    }
+
+    /// Get statistics about the pre-sharing of room keys, notably how often it
+    /// avoided sharing the room key when sending an event.
+    pub fn room_key_preshare_stats(&self) -> RoomKeyPreshareStats {
+        self.client.inner.e2ee.room_key_preshare.stats()
+    }
+
     /// Enables the crypto-store cross-process lock.
     ///
     /// This may be required if there are multiple processes that may do writes
@@ -1713,11 +1833,15 @@ impl Encryption {
         // It's fine to be async here as we're only getting the lock protecting the
         // `OlmMachine`. Since the lock shouldn't be that contested right after logging
         // in we won't delay the login or restoration of the Client.
-        let bundle_receiver_task = if self.client.inner.enable_share_history_on_invite {
-            Some(BundleReceiverTask::new(&self.client).await)
-        } else {
-            None
-        };
+        let (bundle_receiver_task, history_sharing_task) =
+            if self.client.inner.enable_share_history_on_invite {
+                (
+                    Some(BundleReceiverTask::new(&self.client).await),
+                    Some(HistorySharingTask::new(WeakClient::from_client(&self.client))),
+                )
+            } else {
+                (None, None)
+            };
 
         let mut tasks = self.client.inner.e2ee.tasks.lock();
 
@@ -1740,9 +1864,21 @@ impl Encryption {
             if let Err(e) = this.recovery().setup().await {
                 error!("Couldn't setup and resume recovery {e:?}");
             }
+
+            if this.settings().auto_manage_dehydrated_device {
+                let dehydrated_device = this.dehydrated_device();
+
+                match dehydrated_device.is_enabled().await {
+                    Ok(true) => dehydrated_device
+                        .start_periodic_rotation(DehydratedDevice::DEFAULT_ROTATION_PERIOD),
+                    Ok(false) => {}
+                    Err(e) => error!("Couldn't check if the dehydrated device is enabled {e:?}"),
+                }
+            }
         }));
 
         tasks.receive_historic_room_key_bundles = bundle_receiver_task;
+        tasks.share_room_history = history_sharing_task;
     }
 
     /// Waits for end-to-end encryption initialization tasks to finish, if any
@@ -2236,6 +2372,75 @@ mod tests {
         assert!(after_taking_lock_first_time.same_as(&after_taking_lock_second_time));
     }
 
+    #[cfg(feature = "sqlite")]
+    #[async_test]
+    async fn test_cross_process_store_lock_with_read_only_store() {
+        use matrix_sdk_base::store::RoomLoadSettings;
+        use matrix_sdk_sqlite::SqliteStoreConfig;
+
+        let sqlite_path = std::env::temp_dir().join("cross_process_store_lock_read_only.db");
+        let session = mock_matrix_session();
+
+        // The store is created by a client opening it in read-write mode.
+        let client = Client::builder()
+            .homeserver_url("http://localhost:1234")
+            .request_config(RequestConfig::new().disable_retry())
+            .sqlite_store(&sqlite_path, None)
+            .build()
+            .await
+            .unwrap();
+        client
+            .matrix_auth()
+            .restore_session(session.clone(), RoomLoadSettings::default())
+            .await
+            .unwrap();
+        client.encryption().enable_cross_process_store_lock("client".to_owned()).await.unwrap();
+
+        // Let the lock taken while enabling it be released.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Another client can use it in read-only mode.
+        let read_only_client = Client::builder()
+            .homeserver_url("http://localhost:1234")
+            .request_config(RequestConfig::new().disable_retry())
+            .sqlite_store_with_config_and_cache_path(
+                SqliteStoreConfig::new(&sqlite_path).read_only(true),
+                None::<&std::path::Path>,
+            )
+            .build()
+            .await
+            .unwrap();
+        read_only_client
+            .matrix_auth()
+            .restore_session(session, RoomLoadSettings::default())
+            .await
+            .unwrap();
+        read_only_client
+            .encryption()
+            .enable_cross_process_store_lock("read_only_client".to_owned())
+            .await
+            .unwrap();
+
+        // The read-only client can take the lock, which excludes the other client.
+        let acquired = read_only_client.encryption().try_lock_store_once().await.unwrap();
+        assert!(acquired.is_some());
+        assert!(client.encryption().try_lock_store_once().await.unwrap().is_none());
+
+        drop(acquired);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Once released, the other client can take the lock, and keeps its olm machine
+        // since the read-only client didn't modify the store.
+        let initial_olm_machine = client.olm_machine().await.clone().unwrap();
+        let acquired = client.encryption().try_lock_store_once().await.unwrap();
+        assert!(acquired.is_some());
+        let olm_machine = client.olm_machine().await.clone().unwrap();
+        assert!(initial_olm_machine.same_as(&olm_machine));
+
+        // The read-only client can't take it anymore.
+        assert!(read_only_client.encryption().try_lock_store_once().await.unwrap().is_none());
+    }
+
     #[async_test]
     async fn test_update_verification_state_is_updated_before_any_requests_happen() {
         // Given a client and a server
@@ -0,0 +1,350 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pre-sharing of room keys.
+//!
+//! Before an event can be sent in an encrypted room, the room key must be
+//! shared with all the devices of the room members, which may require to
+//! claim one-time keys and to send many to-device messages. In large rooms,
+//! after a membership change, this can take seconds.
+//!
+//! Pre-sharing the room key, e.g. when the user starts typing, moves this work
+//! out of the send path. See [`Room::preshare_room_key()`] and
+//! [`Room::schedule_room_key_preshare()`].
+//!
+//! [`Room::preshare_room_key()`]: crate::Room::preshare_room_key
+//! [`Room::schedule_room_key_preshare()`]: crate::Room::schedule_room_key_preshare
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use matrix_sdk_common::{
+    executor::{spawn, AbortOnDrop},
+    locks::{Mutex as StdMutex, RwLock as StdRwLock},
+};
+use ruma::{OwnedRoomId, RoomId};
+use tracing::{debug, warn};
+
+use crate::{Error, Room};
+
+/// Settings for the pre-sharing of room keys.
+#[derive(Clone, Copy, Debug)]
+pub struct RoomKeyPreshareSettings {
+    /// Automatically pre-share the room key of a room when the user starts
+    /// typing in it, i.e. when [`Room::typing_notice()`] is called with
+    /// `true`, or when a composer draft is saved with
+    /// [`Room::save_composer_draft()`].
+    ///
+    /// Disabled by default.
+    ///
+    /// [`Room::typing_notice()`]: crate::Room::typing_notice
+    /// [`Room::save_composer_draft()`]: crate::Room::save_composer_draft
+    pub automatic: bool,
+
+    /// How long to wait after the last trigger before pre-sharing the room
+    /// key, so that a burst of keystrokes triggers a single pre-share.
+    pub debounce: Duration,
+}
+
+impl Default for RoomKeyPreshareSettings {
+    fn default() -> Self {
+        Self { automatic: false, debounce: Duration::from_millis(500) }
+    }
+}
+
+/// Statistics about the pre-sharing of room keys, since the [`Client`] has
+/// been created.
+///
+/// [`Client`]: crate::Client
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RoomKeyPreshareStats {
+    /// The number of successful pre-shares.
+    pub preshares: u64,
+
+    /// The number of failed pre-shares.
+    pub failed_preshares: u64,
+
+    /// The number of pre-shares which were skipped because the room key was
+    /// already being shared at the same time, e.g. to send an event, and
+    /// this concurrent sharing failed.
+    pub skipped_preshares: u64,
+
+    /// The number of events sent after a pre-share, for which the room key
+    /// didn't have to be shared anymore, i.e. for which the pre-share avoided
+    /// the sharing latency.
+    pub sends_with_preshared_key: u64,
+
+    /// The number of events sent after a pre-share, for which the room key
+    /// still had to be shared, e.g. because the room members changed in the
+    /// meantime.
+    pub sends_with_stale_preshare: u64,
+
+    /// The number of events sent without any prior pre-share.
+    pub sends_without_preshare: u64,
+}
+
+/// The state of the pre-sharing of room keys, shared by all the rooms of a
+/// client.
+#[derive(Default)]
+pub(crate) struct RoomKeyPreshareState {
+    settings: StdRwLock<RoomKeyPreshareSettings>,
+    stats: StdMutex<RoomKeyPreshareStats>,
+
+    /// The rooms whose room key has been pre-shared, and for which no event
+    /// has been sent since.
+    preshared_rooms: StdMutex<BTreeSet<OwnedRoomId>>,
+
+    /// The pending debounced pre-shares, removed when they are done.
+    pending: StdMutex<BTreeMap<OwnedRoomId, PendingPreshare>>,
+
+    /// The ID of the next pending pre-share.
+    next_pending_id: AtomicU64,
+}
+
+/// A pending debounced pre-share.
+struct PendingPreshare {
+    /// The ID of this pre-share, to know whether it has been replaced.
+    id: u64,
+
+    /// The task running the pre-share, aborted when dropped.
+    _task: AbortOnDrop<()>,
+}
+
+/// The outcome of a pre-share of a room key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PreshareOutcome {
+    /// The room key has been shared, or didn't need to be.
+    Shared,
+
+    /// The pre-share was skipped, because it was deduplicated with a
+    /// concurrent sharing of the room key which failed.
+    Skipped,
+
+    /// The pre-share failed.
+    Failed,
+}
+
+impl PreshareOutcome {
+    pub fn from_result<T>(result: &Result<T, Error>) -> Self {
+        match result {
+            Ok(_) => Self::Shared,
+            Err(Error::ConcurrentRequestFailed) => Self::Skipped,
+            Err(_) => Self::Failed,
+        }
+    }
+}
+
+impl RoomKeyPreshareState {
+    pub fn settings(&self) -> RoomKeyPreshareSettings {
+        *self.settings.read()
+    }
+
+    pub fn set_settings(&self, settings: RoomKeyPreshareSettings) {
+        *self.settings.write() = settings;
+    }
+
+    pub fn stats(&self) -> RoomKeyPreshareStats {
+        *self.stats.lock()
+    }
+
+    /// Schedule a pre-share of the room key of `room`, after the debounce
+    /// delay, replacing any pending one.
+    pub fn schedule(&self, room: &Room) {
+        let debounce = self.settings().debounce;
+        let room = room.clone();
+        let room_id = room.room_id().to_owned();
+        let id = self.next_pending_id.fetch_add(1, Ordering::Relaxed);
+
+        // Hold the lock until the task is inserted, so it can't try to remove itself
+        // before.
+        let mut pending = self.pending.lock();
+
+        let task = spawn(async move {
+            crate::sleep::sleep(debounce).await;
+
+            if let Err(error) = room.preshare_room_key().await {
+                warn!(room_id = ?room.room_id(), "Couldn't pre-share the room key: {error}");
+            }
+
+            room.client.inner.e2ee.room_key_preshare.remove_pending(room.room_id(), id);
+        });
+
+        // Replacing the previous task aborts it.
+        pending.insert(room_id, PendingPreshare { id, _task: AbortOnDrop::new(task) });
+    }
+
+    /// Cancel the pending pre-share of the room key of a room, if any.
+    pub fn cancel(&self, room_id: &RoomId) {
+        self.pending.lock().remove(room_id);
+    }
+
+    /// Remove the pending pre-share with the given ID, once it is done, unless
+    /// it has been replaced by a newer one.
+    fn remove_pending(&self, room_id: &RoomId, id: u64) {
+        let mut pending = self.pending.lock();
+
+        if pending.get(room_id).is_some_and(|preshare| preshare.id == id) {
+            pending.remove(room_id);
+        }
+    }
+
+    /// Whether a pre-share of the room key of a room is pending.
+    #[cfg(test)]
+    fn is_pending(&self, room_id: &RoomId) -> bool {
+        self.pending.lock().contains_key(room_id)
+    }
+
+    /// Record the outcome of a pre-share.
+    pub fn record_preshare(&self, room_id: &RoomId, outcome: PreshareOutcome) {
+        let mut stats = self.stats.lock();
+
+        match outcome {
+            PreshareOutcome::Shared => {
+                stats.preshares += 1;
+                self.preshared_rooms.lock().insert(room_id.to_owned());
+            }
+            PreshareOutcome::Skipped => stats.skipped_preshares += 1,
+            PreshareOutcome::Failed => stats.failed_preshares += 1,
+        }
+    }
+
+    /// Record that an event has been sent in a room, and whether the room key
+    /// had to be shared before sending it.
+    pub fn record_send(&self, room_id: &RoomId, room_key_shared_on_send: bool) {
+        let mut stats = self.stats.lock();
+
+        if self.preshared_rooms.lock().remove(room_id) {
+            if room_key_shared_on_send {
+                debug!(?room_id, "The pre-shared room key had to be shared again on send");
+                stats.sends_with_stale_preshare += 1;
+            } else {
+                stats.sends_with_preshared_key += 1;
+            }
+        } else {
+            stats.sends_without_preshare += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+
+    use super::{
+        PreshareOutcome, RoomKeyPreshareSettings, RoomKeyPreshareState, RoomKeyPreshareStats,
+    };
+    use crate::{sleep::sleep, test_utils::mocks::MatrixMockServer};
+
+    /// The number of pre-shares which have been attempted.
+    fn attempts(stats: RoomKeyPreshareStats) -> u64 {
+        stats.preshares + stats.failed_preshares + stats.skipped_preshares
+    }
+
+    #[test]
+    fn test_stats() {
+        let state = RoomKeyPreshareState::default();
+        let room_id = room_id!("!a:b.c");
+        let other_room_id = room_id!("!d:e.f");
+
+        state.record_preshare(room_id, PreshareOutcome::Shared);
+        state.record_preshare(other_room_id, PreshareOutcome::Failed);
+        state.record_preshare(other_room_id, PreshareOutcome::Skipped);
+
+        // The first send after a pre-share benefits from it, the next ones don't count
+        // as pre-shared.
+        state.record_send(room_id, false);
+        state.record_send(room_id, false);
+        state.record_send(other_room_id, true);
+
+        state.record_preshare(room_id, PreshareOutcome::Shared);
+        state.record_send(room_id, true);
+
+        assert_eq!(
+            state.stats(),
+            RoomKeyPreshareStats {
+                preshares: 2,
+                failed_preshares: 1,
+                skipped_preshares: 1,
+                sends_with_preshared_key: 1,
+                sends_with_stale_preshare: 1,
+                sends_without_preshare: 2,
+            }
+        );
+    }
+
+    #[async_test]
+    async fn test_schedule_debounces() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!a:b.c");
+
+        server.mock_room_state_encryption().encrypted().mount().await;
+        let room = server.sync_joined_room(&client, room_id).await;
+
+        client.encryption().set_room_key_preshare_settings(RoomKeyPreshareSettings {
+            automatic: false,
+            debounce: Duration::from_millis(100),
+        });
+        let state = &client.inner.e2ee.room_key_preshare;
+
+        // A burst of triggers keeps delaying the pre-share.
+        for _ in 0..5 {
+            room.schedule_room_key_preshare();
+            sleep(Duration::from_millis(20)).await;
+        }
+
+        assert!(state.is_pending(room_id));
+        assert_eq!(attempts(state.stats()), 0);
+
+        sleep(Duration::from_millis(500)).await;
+
+        // A single pre-share happened, and it isn't pending anymore. It failed because
+        // the members of the room can't be fetched, but that doesn't matter here.
+        assert_eq!(attempts(state.stats()), 1);
+        assert!(!state.is_pending(room_id));
+    }
+
+    #[async_test]
+    async fn test_cancel_scheduled_preshare() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!a:b.c");
+
+        server.mock_room_state_encryption().encrypted().mount().await;
+        let room = server.sync_joined_room(&client, room_id).await;
+
+        client.encryption().set_room_key_preshare_settings(RoomKeyPreshareSettings {
+            automatic: false,
+            debounce: Duration::from_millis(100),
+        });
+        let state = &client.inner.e2ee.room_key_preshare;
+
+        room.schedule_room_key_preshare();
+        assert!(state.is_pending(room_id));
+
+        room.cancel_room_key_preshare();
+        assert!(!state.is_pending(room_id));
+
+        sleep(Duration::from_millis(300)).await;
+
+        // The pre-share never happened.
+        assert_eq!(attempts(state.stats()), 0);
+    }
+}
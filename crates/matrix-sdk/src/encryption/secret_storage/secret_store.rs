@@ -324,6 +324,8 @@ impl SecretStore {
     /// - `m.cross_signing.self_signing`: The self-signing cross-signing key.
     /// - `m.cross_signing.user_signing`: The user-signing cross-signing key.
     /// - `m.megolm_backup.v1`: The backup recovery key.
+    /// - `org.matrix.msc3814`: The dehydrated device pickle key, see
+    ///   [`DehydratedDevice`](crate::encryption::dehydrated_device::DehydratedDevice).
     ///
     /// If the `m.cross_signing.self_signing` key is successfully imported, it
     /// is used to sign our own [`Device`], marking it as verified. This step is
@@ -417,6 +419,19 @@ impl SecretStore {
 
         self.maybe_enable_backups().await?;
 
+        // Import the pickle key of the dehydrated device, if any, so the dehydrated
+        // device can be rehydrated and rotated.
+        let encryption = self.client.encryption();
+        let dehydrated_device = encryption.dehydrated_device();
+
+        match dehydrated_device.import_pickle_key(self).await {
+            Ok(true) if encryption.settings().auto_manage_dehydrated_device => {
+                dehydrated_device.start_rehydration_and_rotation();
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Couldn't import the dehydrated device pickle key: {e:?}"),
+        }
+
         Ok(())
     }
 
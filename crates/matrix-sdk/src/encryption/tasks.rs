@@ -30,7 +30,7 @@ use tracing::{debug, info, instrument, trace, warn};
 
 use crate::{
     client::WeakClient,
-    encryption::backups::UploadState,
+    encryption::{backups::UploadState, history_sharing::HistorySharing},
     executor::{spawn, JoinHandle},
     room::shared_room_history,
     Client, Room,
@@ -46,6 +46,8 @@ pub(crate) struct ClientTasks {
     pub(crate) update_recovery_state_after_backup: Option<JoinHandle<()>>,
     pub(crate) receive_historic_room_key_bundles: Option<BundleReceiverTask>,
     pub(crate) setup_e2ee: Option<JoinHandle<()>>,
+    pub(crate) rotate_dehydrated_device: Option<DehydratedDeviceRotationTask>,
+    pub(crate) share_room_history: Option<HistorySharingTask>,
 }
 
 pub(crate) struct BackupUploadingTask {
@@ -123,6 +125,122 @@ impl RoomKeyDownloadRequest {
 
 pub type RoomKeyInfo = (OwnedRoomId, String);
 
+/// A task which periodically replaces the dehydrated device with a new one.
+pub(crate) struct DehydratedDeviceRotationTask {
+    #[allow(dead_code)]
+    join_handle: JoinHandle<()>,
+}
+
+impl Drop for DehydratedDeviceRotationTask {
+    fn drop(&mut self) {
+        #[cfg(not(target_family = "wasm"))]
+        self.join_handle.abort();
+    }
+}
+
+impl DehydratedDeviceRotationTask {
+    /// Rotate the dehydrated device after `initial_delay`, and then every
+    /// `period`.
+    ///
+    /// Rotating the dehydrated device also imports the room keys it received,
+    /// so an `initial_delay` of zero rehydrates it right away.
+    pub(crate) fn new(client: WeakClient, initial_delay: Duration, period: Duration) -> Self {
+        let join_handle = spawn(async move {
+            Self::run(client, initial_delay, period).await;
+        });
+
+        Self { join_handle }
+    }
+
+    async fn run(client: WeakClient, initial_delay: Duration, period: Duration) {
+        let mut delay = initial_delay;
+
+        loop {
+            if !delay.is_zero() {
+                crate::sleep::sleep(delay).await;
+            }
+            delay = period;
+
+            let Some(client) = client.get() else {
+                trace!("Client got dropped, shutting down the task");
+                break;
+            };
+
+            if let Err(e) = client.encryption().dehydrated_device().rotate().await {
+                warn!("Error rotating the dehydrated device {e:?}");
+            }
+        }
+    }
+}
+
+/// A task which runs the pending history sharing jobs, retrying the failed
+/// ones after their backoff delay.
+pub(crate) struct HistorySharingTask {
+    sender: mpsc::UnboundedSender<()>,
+    #[allow(dead_code)]
+    join_handle: JoinHandle<()>,
+}
+
+impl Drop for HistorySharingTask {
+    fn drop(&mut self) {
+        #[cfg(not(target_family = "wasm"))]
+        self.join_handle.abort();
+    }
+}
+
+impl HistorySharingTask {
+    pub(crate) fn new(client: WeakClient) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let join_handle = spawn(async move {
+            Self::run(client, receiver).await;
+        });
+
+        Self { sender, join_handle }
+    }
+
+    /// Wake up the task, so it takes a change to the jobs into account.
+    pub(crate) fn wake_up(&self) {
+        let _ = self.sender.send(());
+    }
+
+    async fn run(client: WeakClient, mut receiver: mpsc::UnboundedReceiver<()>) {
+        loop {
+            let next_delay = {
+                let Some(client) = client.get() else {
+                    trace!("Client got dropped, shutting down the task");
+                    break;
+                };
+
+                match client.encryption().history_sharing().run_due_jobs().await {
+                    Ok(next_delay) => next_delay,
+                    Err(e) => {
+                        warn!("Error running the history sharing jobs {e:?}");
+                        Some(HistorySharing::INITIAL_RETRY_DELAY)
+                    }
+                }
+            };
+
+            let sleep = async move {
+                match next_delay {
+                    Some(delay) => crate::sleep::sleep(delay).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                woken_up = receiver.recv() => {
+                    if woken_up.is_none() {
+                        break;
+                    }
+                }
+
+                _ = sleep => {}
+            }
+        }
+    }
+}
+
 pub(crate) struct BackupDownloadTask {
     sender: mpsc::UnboundedSender<RoomKeyDownloadRequest>,
     #[allow(dead_code)]
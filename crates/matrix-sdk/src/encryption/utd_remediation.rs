@@ -0,0 +1,709 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Automatic remediation of unable-to-decrypt (UTD) events.
+//!
+//! When enabled with [`UtdRemediation::set_settings()`], every event which
+//! couldn't be decrypted is classified by its [`UtdCause`], and the fixes
+//! which may apply to this cause are attempted in turn:
+//!
+//! - downloading the room key from the key backup,
+//! - requesting the room key from our other devices, which may have received
+//!   it,
+//! - waiting for the room key bundle of the inviter, if we joined the room
+//!   after an invite.
+//!
+//! As soon as one of them makes the event decryptable, the decrypted event is
+//! saved in the event cache. The timelines of the room retry to decrypt their
+//! events by themselves when a room key is received, be it from the key backup
+//! or from another device.
+//!
+//! The final outcome of the remediation of every event is recorded, see
+//! [`UtdRemediation::outcome()`] and [`UtdRemediation::subscribe()`].
+
+use std::{collections::BTreeSet, num::NonZeroUsize, time::Duration};
+
+use futures_core::Stream;
+use futures_util::{pin_mut, StreamExt};
+use matrix_sdk_base::{
+    crypto::{
+        store::types::RoomKeyInfo,
+        types::events::{CryptoContextInfo, UtdCause},
+        RoomEventDecryptionResult,
+    },
+    deserialized_responses::{TimelineEvent, UnableToDecryptInfo},
+    timeout::timeout,
+};
+use matrix_sdk_common::{
+    executor::spawn,
+    locks::{Mutex as StdMutex, RwLock as StdRwLock},
+    ring_buffer::RingBuffer,
+};
+use ruma::{
+    events::room::encrypted::{EncryptedEventScheme, OriginalSyncRoomEncryptedEvent},
+    serde::Raw,
+    EventId, OwnedEventId, RoomId,
+};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tracing::{debug, info, instrument, warn};
+
+use crate::{
+    event_handler::EventHandlerHandle, room::shared_room_history, Client, Error, Result, Room,
+};
+
+/// Settings for the automatic remediation of unable-to-decrypt events.
+#[derive(Clone, Copy, Debug)]
+pub struct UtdRemediationSettings {
+    /// Whether unable-to-decrypt events are remediated automatically.
+    ///
+    /// Disabled by default.
+    pub enabled: bool,
+
+    /// How long to wait before remediating an unable-to-decrypt event, since
+    /// the room key often arrives shortly after the event.
+    pub grace_period: Duration,
+
+    /// How long to wait for a room key after requesting it, or while waiting
+    /// for a room key bundle.
+    pub key_wait_timeout: Duration,
+}
+
+impl Default for UtdRemediationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            grace_period: Duration::from_secs(2),
+            key_wait_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A fix which may make an unable-to-decrypt event decryptable.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum UtdRemediationAction {
+    /// Download the room key from the key backup.
+    DownloadFromBackup,
+
+    /// Request the room key from our other devices, and wait for it.
+    ///
+    /// The room key isn't requested from the sender of the event: other users
+    /// only share room keys with the devices they sent them to initially.
+    RequestKey,
+
+    /// Wait for the room key bundle sent by the user who invited us to the
+    /// room, as per [MSC4268].
+    ///
+    /// [MSC4268]: https://github.com/matrix-org/matrix-spec-proposals/pull/4268
+    WaitForKeyBundle,
+}
+
+impl UtdRemediationAction {
+    /// Get the fixes which may apply to a UTD of the given cause, in the order
+    /// in which they should be attempted.
+    ///
+    /// An empty list means that the UTD can't be remediated automatically,
+    /// e.g. because the room key has been withheld on purpose, or because our
+    /// device must be verified first.
+    pub fn for_cause(cause: UtdCause, context: &CryptoContextInfo) -> Vec<Self> {
+        match cause {
+            UtdCause::Unknown => {
+                if context.is_backup_configured {
+                    vec![Self::DownloadFromBackup, Self::RequestKey]
+                } else {
+                    vec![Self::RequestKey]
+                }
+            }
+
+            UtdCause::SentBeforeWeJoined => vec![Self::WaitForKeyBundle],
+
+            // Our other devices may still have the room key.
+            UtdCause::HistoricalMessageAndBackupIsDisabled => vec![Self::RequestKey],
+
+            // The user must verify this device to access the backup, the sender must be
+            // trusted, or the room key has been withheld on purpose: nothing can be done
+            // automatically.
+            UtdCause::HistoricalMessageAndDeviceIsUnverified
+            | UtdCause::VerificationViolation
+            | UtdCause::UnsignedDevice
+            | UtdCause::UnknownDevice
+            | UtdCause::WithheldForUnverifiedOrInsecureDevice
+            | UtdCause::WithheldBySender => Vec::new(),
+        }
+    }
+}
+
+/// The final outcome of the remediation of an unable-to-decrypt event.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum UtdRemediationOutcome {
+    /// The event could be decrypted before any fix was attempted, e.g. because
+    /// the room key arrived during the grace period.
+    DecryptedWithoutRemediation,
+
+    /// The event could be decrypted thanks to the given fix.
+    Decrypted {
+        /// The fix which made the event decryptable.
+        action: UtdRemediationAction,
+    },
+
+    /// No fix applies to the cause of the UTD.
+    NotRemediable {
+        /// The cause of the UTD.
+        cause: UtdCause,
+    },
+
+    /// All the fixes which apply to the cause of the UTD have been attempted,
+    /// but the event still can't be decrypted.
+    Failed {
+        /// The cause of the UTD.
+        cause: UtdCause,
+
+        /// The attempted fixes.
+        attempted: Vec<UtdRemediationAction>,
+    },
+}
+
+impl UtdRemediationOutcome {
+    /// Whether the event could eventually be decrypted.
+    pub fn is_decrypted(&self) -> bool {
+        matches!(self, Self::DecryptedWithoutRemediation | Self::Decrypted { .. })
+    }
+}
+
+/// The state of the remediation of unable-to-decrypt events, shared by all
+/// the rooms of a client.
+pub(crate) struct UtdRemediationState {
+    settings: StdRwLock<UtdRemediationSettings>,
+
+    /// The handle of the event handler observing the UTDs received via sync,
+    /// if remediation is enabled.
+    event_handler: StdMutex<Option<EventHandlerHandle>>,
+
+    /// The events being remediated.
+    in_flight: StdMutex<BTreeSet<OwnedEventId>>,
+
+    /// The outcomes of the most recent remediations.
+    outcomes: StdMutex<RingBuffer<(OwnedEventId, UtdRemediationOutcome)>>,
+
+    /// A sender for the outcomes of the remediations.
+    outcomes_sender: broadcast::Sender<(OwnedEventId, UtdRemediationOutcome)>,
+}
+
+impl UtdRemediationState {
+    /// The maximum number of outcomes kept in memory.
+    const MAX_OUTCOMES: NonZeroUsize = NonZeroUsize::new(1000).unwrap();
+}
+
+impl Default for UtdRemediationState {
+    fn default() -> Self {
+        Self {
+            settings: Default::default(),
+            event_handler: Default::default(),
+            in_flight: Default::default(),
+            outcomes: StdMutex::new(RingBuffer::new(Self::MAX_OUTCOMES)),
+            outcomes_sender: broadcast::channel(100).0,
+        }
+    }
+}
+
+/// The manager of the automatic remediation of unable-to-decrypt events, for
+/// the [`Client`].
+#[derive(Debug, Clone)]
+pub struct UtdRemediation {
+    pub(super) client: Client,
+}
+
+impl UtdRemediation {
+    /// Get the settings of the automatic remediation.
+    pub fn settings(&self) -> UtdRemediationSettings {
+        *self.state().settings.read()
+    }
+
+    /// Set the settings of the automatic remediation, e.g. to enable it.
+    pub fn set_settings(&self, settings: UtdRemediationSettings) {
+        *self.state().settings.write() = settings;
+
+        let mut event_handler = self.state().event_handler.lock();
+
+        if settings.enabled {
+            if event_handler.is_none() {
+                *event_handler = Some(self.client.add_event_handler(Self::utd_event_handler));
+            }
+        } else if let Some(handle) = event_handler.take() {
+            self.client.remove_event_handler(handle);
+        }
+    }
+
+    /// Get the outcome of the remediation of the given event, if it has been
+    /// remediated recently.
+    pub fn outcome(&self, event_id: &EventId) -> Option<UtdRemediationOutcome> {
+        self.state()
+            .outcomes
+            .lock()
+            .iter()
+            .rev()
+            .find(|(id, _)| id == event_id)
+            .map(|(_, outcome)| outcome.clone())
+    }
+
+    /// Subscribe to the outcomes of the remediations, as soon as they are
+    /// known.
+    pub fn subscribe(
+        &self,
+    ) -> impl Stream<Item = Result<(OwnedEventId, UtdRemediationOutcome), BroadcastStreamRecvError>>
+    {
+        BroadcastStream::new(self.state().outcomes_sender.subscribe())
+    }
+
+    /// Remediate an unable-to-decrypt event now, regardless of the settings.
+    ///
+    /// The fixes which apply to the cause of the UTD are attempted in turn,
+    /// until the event can be decrypted. In that case, the decrypted event is
+    /// saved in the event cache.
+    ///
+    /// The outcome is recorded, and returned.
+    #[instrument(skip_all, fields(room_id = ?room.room_id()))]
+    pub async fn remediate(
+        &self,
+        room: &Room,
+        event: &Raw<OriginalSyncRoomEncryptedEvent>,
+    ) -> Result<UtdRemediationOutcome> {
+        let deserialized = event.deserialize()?;
+        let event_id = deserialized.event_id;
+
+        let EncryptedEventScheme::MegolmV1AesSha2(content) = deserialized.content.scheme else {
+            // Not a room key issue.
+            let outcome = UtdRemediationOutcome::NotRemediable { cause: UtdCause::Unknown };
+            self.record_outcome(event_id, outcome.clone());
+            return Ok(outcome);
+        };
+        let session_id = content.session_id;
+
+        let utd_info = match self.try_decrypt(room, event).await? {
+            Ok(()) => {
+                let outcome = UtdRemediationOutcome::DecryptedWithoutRemediation;
+                self.record_outcome(event_id, outcome.clone());
+                return Ok(outcome);
+            }
+            Err(utd_info) => utd_info,
+        };
+
+        let context = room.crypto_context_info().await;
+        let cause = UtdCause::determine(event.cast_ref(), context, &utd_info);
+        let actions = UtdRemediationAction::for_cause(cause, &context);
+
+        debug!(%event_id, ?cause, ?actions, "Remediating an unable-to-decrypt event");
+
+        let mut outcome = if actions.is_empty() {
+            UtdRemediationOutcome::NotRemediable { cause }
+        } else {
+            UtdRemediationOutcome::Failed { cause, attempted: actions.clone() }
+        };
+
+        for action in actions {
+            match self.apply(room, event, &session_id, action).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(error) => {
+                    warn!(%event_id, ?action, "Couldn't apply a UTD remediation: {error}");
+                    continue;
+                }
+            }
+
+            if self.try_decrypt(room, event).await?.is_ok() {
+                info!(%event_id, ?action, "Remediated an unable-to-decrypt event");
+                outcome = UtdRemediationOutcome::Decrypted { action };
+                break;
+            }
+        }
+
+        self.record_outcome(event_id, outcome.clone());
+
+        Ok(outcome)
+    }
+
+    /// Remediate an unable-to-decrypt event in the background, after the
+    /// grace period, if the automatic remediation is enabled and the event
+    /// hasn't been remediated already.
+    pub(crate) fn maybe_remediate(&self, room: Room, event: Raw<OriginalSyncRoomEncryptedEvent>) {
+        let settings = self.settings();
+
+        if !settings.enabled {
+            return;
+        }
+
+        let Ok(Some(event_id)) = event.get_field::<OwnedEventId>("event_id") else {
+            return;
+        };
+
+        // Don't remediate the same event again every time a timeline retries to decrypt
+        // it.
+        if self.outcome(&event_id).is_some() {
+            return;
+        }
+
+        if !self.state().in_flight.lock().insert(event_id.clone()) {
+            return;
+        }
+
+        let this = self.clone();
+
+        spawn(async move {
+            crate::sleep::sleep(settings.grace_period).await;
+
+            if let Err(error) = this.remediate(&room, &event).await {
+                warn!(%event_id, "Couldn't remediate an unable-to-decrypt event: {error}");
+            }
+
+            this.state().in_flight.lock().remove(&event_id);
+        });
+    }
+
+    /// Apply a fix.
+    ///
+    /// Returns `true` if the fix has been applied, and it's worth trying to
+    /// decrypt the event again.
+    async fn apply(
+        &self,
+        room: &Room,
+        event: &Raw<OriginalSyncRoomEncryptedEvent>,
+        session_id: &str,
+        action: UtdRemediationAction,
+    ) -> Result<bool> {
+        let client = &self.client;
+        let room_id = room.room_id();
+
+        match action {
+            UtdRemediationAction::DownloadFromBackup => {
+                client.encryption().backups().download_room_key(room_id, session_id).await
+            }
+
+            UtdRemediationAction::RequestKey => {
+                // Subscribe before sending the request, not to miss the room key.
+                let Some(room_keys) = client.encryption().room_keys_received_stream().await else {
+                    return Ok(false);
+                };
+
+                let (cancel, request) = {
+                    let olm = client.olm_machine().await;
+                    let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+                    olm.request_room_key(event.cast_ref(), room_id).await?
+                };
+
+                // The cancellation of a previous request must be sent first, otherwise the
+                // other devices ignore the new request.
+                if let Some(cancel) = cancel {
+                    client.send_outgoing_request(cancel).await?;
+                }
+                client.send_outgoing_request(request).await?;
+
+                Ok(self.wait_for_room_key(room_keys, room_id, session_id).await)
+            }
+
+            UtdRemediationAction::WaitForKeyBundle => {
+                let Some(details) = room.invite_acceptance_details() else {
+                    // We weren't invited, there is no bundle to wait for.
+                    return Ok(false);
+                };
+
+                let Some(room_keys) = client.encryption().room_keys_received_stream().await else {
+                    return Ok(false);
+                };
+
+                // The bundle may have been received already, otherwise it's imported as soon
+                // as it's received.
+                shared_room_history::maybe_accept_key_bundle(room, &details.inviter).await?;
+
+                if self.try_decrypt(room, event).await?.is_ok() {
+                    return Ok(true);
+                }
+
+                Ok(self.wait_for_room_key(room_keys, room_id, session_id).await)
+            }
+        }
+    }
+
+    /// Wait for the room key with the given session ID to be received, until
+    /// the timeout of the settings.
+    async fn wait_for_room_key(
+        &self,
+        room_keys: impl Stream<Item = Result<Vec<RoomKeyInfo>, BroadcastStreamRecvError>>,
+        room_id: &RoomId,
+        session_id: &str,
+    ) -> bool {
+        let wait = async {
+            pin_mut!(room_keys);
+
+            while let Some(update) = room_keys.next().await {
+                match update {
+                    Ok(infos) => {
+                        if infos
+                            .iter()
+                            .any(|info| info.room_id == room_id && info.session_id == session_id)
+                        {
+                            return true;
+                        }
+                    }
+                    // We lagged, the room key may have been received: let the caller try again.
+                    Err(_) => return true,
+                }
+            }
+
+            false
+        };
+
+        timeout(wait, self.settings().key_wait_timeout).await.unwrap_or(false)
+    }
+
+    /// Try to decrypt the event, and save it in the event cache if it worked.
+    ///
+    /// Returns the information about the decryption failure otherwise.
+    async fn try_decrypt(
+        &self,
+        room: &Room,
+        event: &Raw<OriginalSyncRoomEncryptedEvent>,
+    ) -> Result<Result<(), UnableToDecryptInfo>> {
+        let result = {
+            let olm = self.client.olm_machine().await;
+            let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+            olm.try_decrypt_room_event(
+                event.cast_ref(),
+                room.room_id(),
+                self.client.decryption_settings(),
+            )
+            .await?
+        };
+
+        let decrypted = match result {
+            RoomEventDecryptionResult::Decrypted(decrypted) => decrypted,
+            RoomEventDecryptionResult::UnableToDecrypt(utd_info) => return Ok(Err(utd_info)),
+        };
+
+        let push_actions = match room.push_context().await? {
+            Some(push_context) => Some(push_context.for_event(&decrypted.event).await),
+            None => None,
+        };
+
+        // Replace the UTD in the event cache, if it's enabled.
+        if let Ok((event_cache, _drop_handles)) = room.event_cache().await {
+            event_cache.save_events([TimelineEvent::from_decrypted(decrypted, push_actions)]).await;
+        }
+
+        Ok(Ok(()))
+    }
+
+    fn record_outcome(&self, event_id: OwnedEventId, outcome: UtdRemediationOutcome) {
+        self.state().outcomes.lock().push((event_id.clone(), outcome.clone()));
+        let _ = self.state().outcomes_sender.send((event_id, outcome));
+    }
+
+    fn state(&self) -> &UtdRemediationState {
+        &self.client.inner.e2ee.utd_remediation
+    }
+
+    /// Remediate the UTD events received via sync.
+    #[allow(clippy::unused_async)] // Because it's used as an event handler, which must be async.
+    async fn utd_event_handler(
+        event: Raw<OriginalSyncRoomEncryptedEvent>,
+        room: Room,
+        client: Client,
+    ) {
+        client.encryption().utd_remediation().maybe_remediate(room, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{iter, time::Duration};
+
+    use assert_matches2::assert_let;
+    use futures_util::StreamExt;
+    use matrix_sdk_base::crypto::{
+        types::events::{CryptoContextInfo, UtdCause},
+        EncryptionSettings,
+    };
+    use matrix_sdk_test::async_test;
+    use ruma::{
+        event_id, events::room::encrypted::OriginalSyncRoomEncryptedEvent, room_id, serde::Raw,
+        user_id, MilliSecondsSinceUnixEpoch, UserId,
+    };
+    use serde_json::json;
+
+    use super::{UtdRemediationAction, UtdRemediationOutcome, UtdRemediationSettings};
+    use crate::test_utils::mocks::MatrixMockServer;
+
+    fn context(is_backup_configured: bool) -> CryptoContextInfo {
+        CryptoContextInfo {
+            device_creation_ts: MilliSecondsSinceUnixEpoch::now(),
+            this_device_is_verified: true,
+            backup_exists_on_server: is_backup_configured,
+            is_backup_configured,
+        }
+    }
+
+    /// Create an encrypted event sent now, with the given content.
+    fn encrypted_event(
+        sender: &UserId,
+        content: serde_json::Value,
+    ) -> Raw<OriginalSyncRoomEncryptedEvent> {
+        Raw::new(&json!({
+            "type": "m.room.encrypted",
+            "event_id": "$encrypted:b.c",
+            "sender": sender,
+            "origin_server_ts": MilliSecondsSinceUnixEpoch::now(),
+            "content": content,
+        }))
+        .unwrap()
+        .cast_unchecked()
+    }
+
+    #[test]
+    fn test_actions_for_cause() {
+        assert_eq!(
+            UtdRemediationAction::for_cause(UtdCause::Unknown, &context(true)),
+            [UtdRemediationAction::DownloadFromBackup, UtdRemediationAction::RequestKey]
+        );
+        assert_eq!(
+            UtdRemediationAction::for_cause(UtdCause::Unknown, &context(false)),
+            [UtdRemediationAction::RequestKey]
+        );
+        assert_eq!(
+            UtdRemediationAction::for_cause(UtdCause::SentBeforeWeJoined, &context(true)),
+            [UtdRemediationAction::WaitForKeyBundle]
+        );
+        assert_eq!(
+            UtdRemediationAction::for_cause(
+                UtdCause::HistoricalMessageAndBackupIsDisabled,
+                &context(false)
+            ),
+            [UtdRemediationAction::RequestKey]
+        );
+
+        for cause in [
+            UtdCause::HistoricalMessageAndDeviceIsUnverified,
+            UtdCause::VerificationViolation,
+            UtdCause::UnsignedDevice,
+            UtdCause::UnknownDevice,
+            UtdCause::WithheldForUnverifiedOrInsecureDevice,
+            UtdCause::WithheldBySender,
+        ] {
+            assert!(UtdRemediationAction::for_cause(cause, &context(true)).is_empty());
+        }
+    }
+
+    #[async_test]
+    async fn test_remediate_not_a_room_key_issue() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room = server.sync_joined_room(&client, room_id!("!a:b.c")).await;
+        let utd_remediation = client.encryption().utd_remediation();
+
+        let event = encrypted_event(
+            user_id!("@alice:b.c"),
+            json!({
+                "algorithm": "m.olm.v1.curve25519-aes-sha2",
+                "sender_key": "WJ6Ce7U67a6jqkHYHd8o0+5H4bqdi9hInZdk0+swuXs",
+                "ciphertext": {},
+            }),
+        );
+
+        let outcome = utd_remediation.remediate(&room, &event).await.unwrap();
+
+        assert_eq!(outcome, UtdRemediationOutcome::NotRemediable { cause: UtdCause::Unknown });
+        assert_eq!(utd_remediation.outcome(event_id!("$encrypted:b.c")), Some(outcome));
+    }
+
+    #[async_test]
+    async fn test_remediate_decryptable_event() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!a:b.c");
+        let room = server.sync_joined_room(&client, room_id).await;
+        let utd_remediation = client.encryption().utd_remediation();
+
+        // Encrypt an event with a room key we have, as if we sent it from this device.
+        let content = {
+            let olm = client.olm_machine().await;
+            let olm = olm.as_ref().unwrap();
+
+            olm.share_room_key(room_id, iter::empty(), EncryptionSettings::default())
+                .await
+                .unwrap();
+            olm.encrypt_room_event_raw(
+                room_id,
+                "m.room.message",
+                &Raw::new(&json!({ "msgtype": "m.text", "body": "Hello" }))
+                    .unwrap()
+                    .cast_unchecked(),
+            )
+            .await
+            .unwrap()
+        };
+        let event =
+            encrypted_event(client.user_id().unwrap(), content.deserialize_as_unchecked().unwrap());
+
+        let mut outcomes = utd_remediation.subscribe();
+        let outcome = utd_remediation.remediate(&room, &event).await.unwrap();
+
+        assert_eq!(outcome, UtdRemediationOutcome::DecryptedWithoutRemediation);
+        assert!(outcome.is_decrypted());
+
+        assert_let!(Some(Ok((event_id, received_outcome))) = outcomes.next().await);
+        assert_eq!(event_id, "$encrypted:b.c");
+        assert_eq!(received_outcome, outcome);
+    }
+
+    #[async_test]
+    async fn test_remediate_missing_room_key() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!a:b.c");
+        let room = server.sync_joined_room(&client, room_id).await;
+        let utd_remediation = client.encryption().utd_remediation();
+
+        utd_remediation.set_settings(UtdRemediationSettings {
+            key_wait_timeout: Duration::from_millis(100),
+            ..Default::default()
+        });
+
+        // The room key is requested from our other devices, but nobody answers.
+        server.mock_send_to_device().ok().expect(1..).mount().await;
+
+        let event = encrypted_event(
+            user_id!("@alice:b.c"),
+            json!({
+                "algorithm": "m.megolm.v1.aes-sha2",
+                "sender_key": "WJ6Ce7U67a6jqkHYHd8o0+5H4bqdi9hInZdk0+swuXs",
+                "device_id": "ALICEDEVICE",
+                "session_id": "ZFD6+OmV7fVCsJ7Gap8UnORH8EnmiAkes8FAvQuCw/I",
+                "ciphertext": "AwgAEiBQs2LgBD2CcB+RLH2bsgp9VadFUJhBXOtCmcJuttBDOeDNjL21d9z0AcVSfQFAh9huh4or7sWuNrHcvu9/sMbweTgc0UtdA5xFLheubHouXy4aewze+ShndWAaTbjWJMLsPSQDUMQHBA",
+            }),
+        );
+
+        let outcome = utd_remediation.remediate(&room, &event).await.unwrap();
+
+        assert_eq!(
+            outcome,
+            UtdRemediationOutcome::Failed {
+                cause: UtdCause::Unknown,
+                attempted: vec![UtdRemediationAction::RequestKey],
+            }
+        );
+        assert!(!outcome.is_decrypted());
+        assert_eq!(utd_remediation.outcome(event_id!("$encrypted:b.c")), Some(outcome));
+
+        // The remediation is recorded for this event only.
+        assert!(utd_remediation.outcome(event_id!("$other:b.c")).is_none());
+    }
+}
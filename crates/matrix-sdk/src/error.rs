@@ -460,6 +460,20 @@ impl Error {
     pub fn as_uiaa_response(&self) -> Option<&UiaaInfo> {
         self.as_ruma_api_error().and_then(as_variant!(RumaApiError::Uiaa))
     }
+
+    /// Whether this error happened because the database of one of the stores
+    /// is busy, e.g. because another process is using it.
+    ///
+    /// The operation can be retried later.
+    pub fn is_store_busy(&self) -> bool {
+        match self {
+            Self::StateStore(error) => matches!(**error, StoreError::Busy),
+            Self::EventCacheStore(error) => matches!(**error, EventCacheStoreError::Busy),
+            #[cfg(feature = "e2e-encryption")]
+            Self::CryptoStoreError(error) => matches!(**error, CryptoStoreError::Busy),
+            _ => false,
+        }
+    }
 }
 
 impl From<HttpError> for Error {
@@ -0,0 +1,330 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fmt::Debug, future::Future, sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use http::{Method, StatusCode};
+use matrix_sdk_base::{SendOutsideWasm, SyncOutsideWasm};
+use tracing::debug;
+
+use super::HttpClient;
+use crate::error::HttpError;
+
+/// A hook called around every request sent to the homeserver by the
+/// [`Client`](crate::Client).
+///
+/// Middlewares are added with
+/// [`ClientBuilder::add_http_middleware()`](crate::ClientBuilder::add_http_middleware),
+/// and are called in the order in which they were added.
+///
+/// They are called for every attempt of a request: if a request is retried,
+/// the callbacks are called again, with an incremented
+/// [`HttpRequestInfo::attempt`].
+///
+/// # Examples
+///
+/// ```
+/// use matrix_sdk::{
+///     bytes::Bytes, HttpMiddleware, HttpRequestInfo, HttpResponseInfo,
+/// };
+///
+/// #[derive(Debug)]
+/// struct ProxyAuth(http::HeaderValue);
+///
+/// impl HttpMiddleware for ProxyAuth {
+///     fn before_request(
+///         &self,
+///         _info: &HttpRequestInfo,
+///         request: &mut http::Request<Bytes>,
+///     ) -> Option<http::Response<Bytes>> {
+///         request.headers_mut().insert("x-proxy-auth", self.0.clone());
+///         None
+///     }
+///
+///     fn after_response(
+///         &self,
+///         request: &HttpRequestInfo,
+///         response: &HttpResponseInfo,
+///     ) {
+///         println!(
+///             "{} {} {:?} in {:?}",
+///             request.method,
+///             request.path,
+///             response.status,
+///             response.duration
+///         );
+///     }
+/// }
+/// ```
+pub trait HttpMiddleware: Debug + SendOutsideWasm + SyncOutsideWasm {
+    /// Called before a request is sent.
+    ///
+    /// The request can be modified, for example to add headers. Returning a
+    /// response short-circuits the request: it isn't sent to the homeserver,
+    /// the `before_request` callbacks of the following middlewares aren't
+    /// called, and the returned response is used instead.
+    ///
+    /// For media uploads and downloads that are streamed, the body of the
+    /// request is empty and changing it has no effect.
+    fn before_request(
+        &self,
+        info: &HttpRequestInfo,
+        request: &mut http::Request<Bytes>,
+    ) -> Option<http::Response<Bytes>> {
+        let _ = (info, request);
+        None
+    }
+
+    /// Called with the request as it was sent and the response that was
+    /// received, including when it was returned by a middleware.
+    ///
+    /// It is called before [`HttpMiddleware::after_response()`], but not when
+    /// the request failed before a response was received, nor for the media
+    /// uploads and downloads that are streamed. Like
+    /// [`HttpMiddleware::after_response()`], it is only called if
+    /// [`HttpMiddleware::before_request()`] was called for this attempt.
+    fn inspect_response(
+        &self,
+        info: &HttpRequestInfo,
+        request: &http::Request<Bytes>,
+        response: &http::Response<Bytes>,
+    ) {
+        let _ = (info, request, response);
+    }
+
+    /// Called after a response was received, or the request failed.
+    ///
+    /// It is only called if [`HttpMiddleware::before_request()`] was called
+    /// for this attempt: when a middleware short-circuits a request, the
+    /// following middlewares aren't called at all.
+    fn after_response(&self, request: &HttpRequestInfo, response: &HttpResponseInfo) {
+        let _ = (request, response);
+    }
+}
+
+/// Information about a request, given to the [`HttpMiddleware`] callbacks.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct HttpRequestInfo {
+    /// The name of the Rust type of the request, for example
+    /// `ruma_client_api::account::whoami::v3::Request`.
+    pub request_type: &'static str,
+    /// The HTTP method of the request.
+    pub method: Method,
+    /// The path of the request URI, without the query string.
+    pub path: String,
+    /// The number of the attempt to send this request, starting at 1.
+    ///
+    /// It is greater than 1 when the request is retried.
+    pub attempt: u64,
+}
+
+impl HttpRequestInfo {
+    pub(super) fn new<R>(request: &http::Request<Bytes>, attempt: u64) -> Self {
+        Self {
+            request_type: std::any::type_name::<R>(),
+            method: request.method().clone(),
+            path: request.uri().path().to_owned(),
+            attempt,
+        }
+    }
+}
+
+/// Information about the outcome of a request, given to
+/// [`HttpMiddleware::after_response()`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct HttpResponseInfo {
+    /// The status code of the response.
+    ///
+    /// This is `None` if the request failed before a response was received,
+    /// in which case [`HttpResponseInfo::failure`] is set.
+    pub status: Option<StatusCode>,
+    /// Why the request failed before a response was received.
+    ///
+    /// This is `None` if a response was received.
+    pub failure: Option<HttpFailureKind>,
+    /// How long it took to receive the response.
+    pub duration: Duration,
+    /// Whether the response was returned by a middleware, instead of being
+    /// received from the homeserver.
+    pub short_circuited: bool,
+}
+
+/// The reason why a request failed before a response was received, in an
+/// [`HttpResponseInfo`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HttpFailureKind {
+    /// The request timed out.
+    Timeout,
+    /// The connection to the homeserver couldn't be established.
+    Connect,
+    /// The body of the request or of the response couldn't be transferred.
+    Body,
+    /// Another error occurred, for example the request couldn't be built.
+    Other,
+}
+
+impl HttpFailureKind {
+    fn from_error(error: &HttpError) -> Self {
+        let HttpError::Reqwest(error) = error else {
+            return Self::Other;
+        };
+
+        if error.is_timeout() {
+            return Self::Timeout;
+        }
+
+        #[cfg(not(target_family = "wasm"))]
+        if error.is_connect() {
+            return Self::Connect;
+        }
+
+        if error.is_body() || error.is_decode() {
+            Self::Body
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// The list of middlewares used by an [`HttpClient`].
+#[derive(Clone, Debug, Default)]
+pub(super) struct HttpMiddlewares(Arc<Vec<Arc<dyn HttpMiddleware>>>);
+
+impl From<Vec<Arc<dyn HttpMiddleware>>> for HttpMiddlewares {
+    fn from(middlewares: Vec<Arc<dyn HttpMiddleware>>) -> Self {
+        Self(Arc::new(middlewares))
+    }
+}
+
+impl HttpMiddlewares {
+    pub(super) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Call [`HttpMiddleware::before_request()`] on the middlewares, until one
+    /// of them short-circuits the request.
+    ///
+    /// Returns the middlewares that were called, and the response of the one
+    /// that short-circuited the request, if any.
+    pub(super) fn before_request(
+        &self,
+        info: &HttpRequestInfo,
+        request: &mut http::Request<Bytes>,
+    ) -> (&[Arc<dyn HttpMiddleware>], Option<http::Response<Bytes>>) {
+        for (index, middleware) in self.0.iter().enumerate() {
+            if let Some(response) = middleware.before_request(info, request) {
+                debug!("The request was short-circuited by a middleware");
+                return (&self.0[..=index], Some(response));
+            }
+        }
+
+        (&self.0, None)
+    }
+}
+
+/// A response that can be given to the [`HttpMiddleware`] callbacks.
+pub(super) trait MiddlewareResponse: From<http::Response<Bytes>> {
+    fn status(&self) -> StatusCode;
+
+    /// The response with its whole body, if it isn't streamed.
+    fn as_http_response(&self) -> Option<&http::Response<Bytes>>;
+}
+
+impl MiddlewareResponse for http::Response<Bytes> {
+    fn status(&self) -> StatusCode {
+        self.status()
+    }
+
+    fn as_http_response(&self) -> Option<&http::Response<Bytes>> {
+        Some(self)
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl MiddlewareResponse for reqwest::Response {
+    fn status(&self) -> StatusCode {
+        self.status()
+    }
+
+    fn as_http_response(&self) -> Option<&http::Response<Bytes>> {
+        None
+    }
+}
+
+impl HttpClient {
+    /// Set the middlewares called around the requests sent with this client.
+    pub(crate) fn with_middlewares(mut self, middlewares: Vec<Arc<dyn HttpMiddleware>>) -> Self {
+        self.middlewares = middlewares.into();
+        self
+    }
+
+    /// Send a single attempt of the given request with `send`, calling the
+    /// middlewares around it.
+    ///
+    /// `R` is the type of the Matrix request that was serialized into
+    /// `request`.
+    pub(super) async fn send_with_middlewares<R, T, F, Fut>(
+        &self,
+        request: &http::Request<Bytes>,
+        attempt: u64,
+        send: F,
+    ) -> Result<T, HttpError>
+    where
+        T: MiddlewareResponse,
+        F: FnOnce(http::Request<Bytes>) -> Fut,
+        Fut: Future<Output = Result<T, HttpError>>,
+    {
+        let mut request = request.clone();
+
+        if self.middlewares.is_empty() {
+            return send(request).await;
+        }
+
+        let info = HttpRequestInfo::new::<R>(&request, attempt);
+        let before = ruma::time::Instant::now();
+
+        // Only the middlewares whose `before_request` was called are called
+        // afterwards.
+        let (called, response) = self.middlewares.before_request(&info, &mut request);
+
+        let (result, short_circuited) = match response {
+            Some(response) => (Ok(response.into()), true),
+            None => (send(request.clone()).await, false),
+        };
+
+        if let Some(response) = result.as_ref().ok().and_then(MiddlewareResponse::as_http_response)
+        {
+            for middleware in called {
+                middleware.inspect_response(&info, &request, response);
+            }
+        }
+
+        let response_info = HttpResponseInfo {
+            status: result.as_ref().ok().map(MiddlewareResponse::status),
+            failure: result.as_ref().err().map(HttpFailureKind::from_error),
+            duration: ruma::time::Instant::now().saturating_duration_since(before),
+            short_circuited,
+        };
+
+        for middleware in called {
+            middleware.after_response(&info, &response_info);
+        }
+
+        result
+    }
+}
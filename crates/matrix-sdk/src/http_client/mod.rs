@@ -36,13 +36,18 @@ use tracing::{debug, field::debug, instrument, trace};
 
 use crate::{config::RequestConfig, error::HttpError};
 
+mod middleware;
 #[cfg(not(target_family = "wasm"))]
 mod native;
+mod recording;
 #[cfg(target_family = "wasm")]
 mod wasm;
 
+use middleware::HttpMiddlewares;
+pub use middleware::{HttpFailureKind, HttpMiddleware, HttpRequestInfo, HttpResponseInfo};
 #[cfg(not(target_family = "wasm"))]
-pub(crate) use native::HttpSettings;
+pub(crate) use native::{HttpSettings, StreamingDownload};
+pub use recording::{HttpRecorder, HttpRecording, HttpReplayer, RecordedBody, RecordedExchange};
 
 pub(crate) const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 
@@ -76,6 +81,7 @@ pub(crate) struct HttpClient {
     pub(crate) request_config: RequestConfig,
     concurrent_request_semaphore: MaybeSemaphore,
     next_request_id: Arc<AtomicU64>,
+    middlewares: HttpMiddlewares,
 }
 
 impl HttpClient {
@@ -87,6 +93,7 @@ impl HttpClient {
                 request_config.max_concurrent_requests,
             ),
             next_request_id: AtomicU64::new(0).into(),
+            middlewares: Default::default(),
         }
     }
 
@@ -265,18 +272,61 @@ mod tests {
         time::Duration,
     };
 
-    use matrix_sdk_common::executor::spawn;
+    use bytes::Bytes;
+    use http::{HeaderValue, Method, StatusCode};
+    use matrix_sdk_common::{executor::spawn, locks::Mutex};
     use matrix_sdk_test::{async_test, test_json};
     use wiremock::{
-        matchers::{method, path},
+        matchers::{header, method, path},
         Mock, Request, ResponseTemplate,
     };
 
+    use super::{HttpFailureKind, HttpMiddleware, HttpRequestInfo, HttpResponseInfo};
     use crate::{
         http_client::RequestConfig,
         test_utils::{set_client_session, test_client_builder_with_server},
     };
 
+    /// A middleware that adds a header to the requests and records what it
+    /// observes.
+    #[derive(Debug, Default)]
+    struct RecordingMiddleware {
+        short_circuit_whoami: bool,
+        responses: Mutex<Vec<(HttpRequestInfo, HttpResponseInfo)>>,
+    }
+
+    impl RecordingMiddleware {
+        fn whoami_responses(&self) -> Vec<(HttpRequestInfo, HttpResponseInfo)> {
+            self.responses
+                .lock()
+                .iter()
+                .filter(|(request, _)| request.path.ends_with("/account/whoami"))
+                .cloned()
+                .collect()
+        }
+    }
+
+    impl HttpMiddleware for RecordingMiddleware {
+        fn before_request(
+            &self,
+            info: &HttpRequestInfo,
+            request: &mut http::Request<Bytes>,
+        ) -> Option<http::Response<Bytes>> {
+            request.headers_mut().insert("x-proxy-auth", HeaderValue::from_static("secret"));
+
+            if self.short_circuit_whoami && info.path.ends_with("/account/whoami") {
+                let body = serde_json::to_vec(&*test_json::WHOAMI).unwrap();
+                return Some(http::Response::new(body.into()));
+            }
+
+            None
+        }
+
+        fn after_response(&self, request: &HttpRequestInfo, response: &HttpResponseInfo) {
+            self.responses.lock().push((request.clone(), response.clone()));
+        }
+    }
+
     #[async_test]
     async fn test_ensure_concurrent_request_limit_is_observed() {
         let (client_builder, server) = test_client_builder_with_server().await;
@@ -361,4 +411,148 @@ mod tests {
         assert_eq!(counter.load(Ordering::SeqCst), 254, "Not all requests passed through");
         bg_task.abort();
     }
+
+    #[async_test]
+    async fn test_middleware_modifies_requests_and_observes_retries() {
+        let middleware = Arc::new(RecordingMiddleware::default());
+
+        let (client_builder, server) = test_client_builder_with_server().await;
+        let client = client_builder
+            .request_config(RequestConfig::new().retry_limit(3))
+            .add_http_middleware(middleware.clone())
+            .build()
+            .await
+            .unwrap();
+
+        set_client_session(&client).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::VERSIONS))
+            .mount(&server)
+            .await;
+
+        // The first attempt fails, the request should be retried.
+        Mock::given(method("GET"))
+            .and(path("_matrix/client/r0/account/whoami"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // The request only succeeds with the header added by the middleware.
+        Mock::given(method("GET"))
+            .and(path("_matrix/client/r0/account/whoami"))
+            .and(header("x-proxy-auth", "secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::WHOAMI))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        client.whoami().await.unwrap();
+
+        let responses = middleware.whoami_responses();
+        assert_eq!(responses.len(), 2);
+
+        let (request, response) = &responses[0];
+        assert!(request.request_type.contains("whoami"));
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(request.attempt, 1);
+        assert_eq!(response.status, Some(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!response.short_circuited);
+
+        let (request, response) = &responses[1];
+        assert_eq!(request.attempt, 2);
+        assert_eq!(response.status, Some(StatusCode::OK));
+        assert_eq!(response.failure, None);
+        assert!(!response.short_circuited);
+    }
+
+    #[async_test]
+    async fn test_middleware_observes_failures() {
+        let middleware = Arc::new(RecordingMiddleware::default());
+
+        let (client_builder, server) = test_client_builder_with_server().await;
+        let client = client_builder
+            .request_config(
+                RequestConfig::new().disable_retry().timeout(Duration::from_millis(100)),
+            )
+            .add_http_middleware(middleware.clone())
+            .build()
+            .await
+            .unwrap();
+
+        set_client_session(&client).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::VERSIONS))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("_matrix/client/r0/account/whoami"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(&*test_json::WHOAMI)
+                    .set_delay(Duration::from_secs(5)),
+            )
+            .mount(&server)
+            .await;
+
+        client.whoami().await.unwrap_err();
+
+        let responses = middleware.whoami_responses();
+        assert_eq!(responses.len(), 1);
+
+        let (_, response) = &responses[0];
+        assert_eq!(response.status, None);
+        assert_eq!(response.failure, Some(HttpFailureKind::Timeout));
+    }
+
+    #[async_test]
+    async fn test_middleware_short_circuits_requests() {
+        let middleware =
+            Arc::new(RecordingMiddleware { short_circuit_whoami: true, ..Default::default() });
+        let next_middleware = Arc::new(RecordingMiddleware::default());
+
+        let (client_builder, server) = test_client_builder_with_server().await;
+        let client = client_builder
+            .add_http_middleware(middleware.clone())
+            .add_http_middleware(next_middleware.clone())
+            .build()
+            .await
+            .unwrap();
+
+        set_client_session(&client).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::VERSIONS))
+            .mount(&server)
+            .await;
+
+        // The request never reaches the server.
+        Mock::given(method("GET"))
+            .and(path("_matrix/client/r0/account/whoami"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let response = client.whoami().await.unwrap();
+        assert_eq!(response.user_id.as_str(), "@joe:example.org");
+
+        let responses = middleware.whoami_responses();
+        assert_eq!(responses.len(), 1);
+
+        let (request, response) = &responses[0];
+        assert_eq!(request.attempt, 1);
+        assert_eq!(response.status, Some(StatusCode::OK));
+        assert!(response.short_circuited);
+
+        // The middlewares after the one that short-circuited the request aren't called.
+        assert!(next_middleware.whoami_responses().is_empty());
+    }
 }
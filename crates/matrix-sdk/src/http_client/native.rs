@@ -14,7 +14,7 @@
 
 use std::{
     fmt::Debug,
-    mem,
+    io, mem,
     sync::atomic::{AtomicU64, Ordering},
     time::Duration,
 };
@@ -23,10 +23,20 @@ use backon::{ExponentialBuilder, Retryable};
 use bytes::Bytes;
 use bytesize::ByteSize;
 use eyeball::SharedObservable;
-use http::header::CONTENT_LENGTH;
+use futures_util::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use http::{
+    header::{CONTENT_LENGTH, CONTENT_RANGE, RANGE},
+    HeaderMap, HeaderValue, StatusCode,
+};
 use reqwest::{tls, Certificate};
-use ruma::api::{error::FromHttpResponseError, IncomingResponse, OutgoingRequest};
-use tracing::{debug, info, warn};
+use ruma::api::{
+    error::FromHttpResponseError, EndpointError, IncomingResponse, OutgoingRequest,
+    SupportedVersions,
+};
+use tracing::{debug, info, instrument, warn};
 
 use super::{response_to_http_response, HttpClient, TransmissionProgress, DEFAULT_REQUEST_TIMEOUT};
 use crate::{
@@ -78,8 +88,15 @@ impl HttpClient {
                 debug!(num_attempt, "Sending request");
                 let before = ruma::time::Instant::now();
 
-                let response =
-                    send_request(&self.inner, &request, config.timeout, send_progress).await?;
+                let response = self
+                    .send_with_middlewares::<R, _, _, _>(
+                        &request,
+                        num_attempt,
+                        |request| async move {
+                            send_request(&self.inner, &request, config.timeout, send_progress).await
+                        },
+                    )
+                    .await?;
 
                 let request_duration = ruma::time::Instant::now().saturating_duration_since(before);
 
@@ -141,6 +158,200 @@ impl HttpClient {
             })
             .await
     }
+
+    /// Send a request whose body is pulled from the given stream, instead of
+    /// the serialized body of the request.
+    ///
+    /// The given `headers` are added to those of the serialized request.
+    ///
+    /// The body can't be replayed, so the request is only attempted once:
+    /// retrying is left to the caller.
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(
+        skip(
+            self,
+            request,
+            body,
+            headers,
+            config,
+            homeserver,
+            access_token,
+            supported_versions,
+            send_progress
+        ),
+        fields(request_id)
+    )]
+    pub(crate) async fn send_streaming<R>(
+        &self,
+        request: R,
+        body: BoxStream<'static, io::Result<Bytes>>,
+        content_length: u64,
+        headers: HeaderMap,
+        config: Option<RequestConfig>,
+        homeserver: String,
+        access_token: Option<&str>,
+        supported_versions: &SupportedVersions,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Result<R::IncomingResponse, HttpError>
+    where
+        R: OutgoingRequest + Debug,
+        HttpError: From<FromHttpResponseError<R::EndpointError>>,
+    {
+        let config = config.unwrap_or(self.request_config);
+        tracing::Span::current().record("request_id", self.get_request_id());
+
+        let request = self
+            .serialize_request(request, config, homeserver, access_token, supported_versions)
+            .map_err(HttpError::IntoHttp)?;
+
+        send_progress.update(|p| p.total += content_length as usize);
+        let body = body.inspect_ok(move |chunk| send_progress.update(|p| p.current += chunk.len()));
+
+        // The body is replaced by the stream, so don't give the serialized one to the
+        // middlewares.
+        let request = request.map(|_| Bytes::new());
+
+        // will be automatically dropped at the end of this function
+        let _handle = self.concurrent_request_semaphore.acquire().await;
+
+        debug!(content_length, "Sending streaming request");
+        let response: reqwest::Response = self
+            .send_with_middlewares::<R, _, _, _>(&request, 1, |request| async move {
+                let mut request =
+                    reqwest::Request::try_from(request.map(|_| reqwest::Body::wrap_stream(body)))?;
+
+                // The length of a streamed body is unknown to reqwest / hyper, so it won't set
+                // the content-length header (required by some servers). Set it manually.
+                request.headers_mut().insert(CONTENT_LENGTH, content_length.into());
+                request.headers_mut().extend(headers);
+                *request.timeout_mut() = config.timeout;
+
+                Ok(self.inner.execute(request).await?)
+            })
+            .await?;
+        let response = response_to_http_response(response).await?;
+
+        R::IncomingResponse::try_from_http_response(response).map_err(HttpError::from)
+    }
+
+    /// Send a media download request, and return the response body as a
+    /// stream instead of buffering it.
+    ///
+    /// If `offset` is not zero, the content is requested from this offset with
+    /// a `Range` header. The server is free to ignore it, so the returned
+    /// [`StreamingDownload::offset`] must be checked by the caller.
+    ///
+    /// The request is only attempted once: resuming is left to the caller.
+    #[instrument(
+        skip(self, request, config, homeserver, access_token, supported_versions),
+        fields(request_id)
+    )]
+    pub(crate) async fn download_streaming<R>(
+        &self,
+        request: R,
+        offset: u64,
+        config: Option<RequestConfig>,
+        homeserver: String,
+        access_token: Option<&str>,
+        supported_versions: &SupportedVersions,
+    ) -> Result<StreamingDownload, HttpError>
+    where
+        R: OutgoingRequest + Debug,
+        HttpError: From<FromHttpResponseError<R::EndpointError>>,
+    {
+        let config = config.unwrap_or(self.request_config);
+        tracing::Span::current().record("request_id", self.get_request_id());
+
+        let mut request = self
+            .serialize_request(request, config, homeserver, access_token, supported_versions)
+            .map_err(HttpError::IntoHttp)?;
+
+        if offset > 0 {
+            let range = HeaderValue::from_str(&format!("bytes={offset}-"))
+                .expect("a byte range is a valid header value");
+            request.headers_mut().insert(RANGE, range);
+        }
+
+        // will be automatically dropped at the end of this function
+        let _handle = self.concurrent_request_semaphore.acquire().await;
+
+        debug!(offset, "Sending streaming download request");
+        let response: reqwest::Response = self
+            .send_with_middlewares::<R, _, _, _>(&request, 1, |request| async move {
+                let mut request = reqwest::Request::try_from(request)?;
+                *request.timeout_mut() = config.timeout;
+                Ok(self.inner.execute(request).await?)
+            })
+            .await?;
+        let status = response.status();
+
+        let content_range =
+            response.headers().get(CONTENT_RANGE).and_then(|value| value.to_str().ok());
+
+        let (offset, total_length) = match status {
+            StatusCode::OK => (Some(0), response.content_length()),
+
+            StatusCode::PARTIAL_CONTENT => match content_range.and_then(parse_content_range) {
+                Some((start, total_length)) => (Some(start), total_length),
+                None => (None, None),
+            },
+
+            // The requested range starts at the end of the content: there is nothing
+            // left to download.
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+                let total_length = content_range
+                    .and_then(|value| value.strip_prefix("bytes */"))
+                    .and_then(|total| total.parse::<u64>().ok());
+
+                if total_length == Some(offset) {
+                    return Ok(StreamingDownload {
+                        offset: Some(offset),
+                        total_length,
+                        body: stream::empty().boxed(),
+                    });
+                }
+
+                (None, total_length)
+            }
+
+            status if status.is_success() => (None, None),
+
+            _ => {
+                let response = response_to_http_response(response).await?;
+                let error = R::EndpointError::from_http_response(response);
+                return Err(FromHttpResponseError::Server(error).into());
+            }
+        };
+
+        Ok(StreamingDownload { offset, total_length, body: response.bytes_stream().boxed() })
+    }
+}
+
+/// The response to a streaming media download, as returned by
+/// [`HttpClient::download_streaming()`].
+pub(crate) struct StreamingDownload {
+    /// The offset of the first byte of the body in the whole content.
+    ///
+    /// This is `None` if the response doesn't describe which part of the
+    /// content it contains.
+    pub offset: Option<u64>,
+    /// The length of the whole content, if it is known.
+    pub total_length: Option<u64>,
+    /// The body of the response.
+    pub body: BoxStream<'static, reqwest::Result<Bytes>>,
+}
+
+/// Parse the value of a `Content-Range` header, in the form
+/// `bytes start-end/total`.
+///
+/// Returns the start of the range and the total length, if it is known.
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _end) = range.split_once('-')?;
+
+    let total = if total == "*" { None } else { Some(total.parse().ok()?) };
+
+    Some((start.parse().ok()?, total))
 }
 
 #[cfg(not(target_family = "wasm"))]
@@ -298,7 +509,7 @@ impl Iterator for BytesChunks {
 mod tests {
     use bytes::Bytes;
 
-    use super::BytesChunks;
+    use super::{parse_content_range, BytesChunks};
 
     #[test]
     fn test_bytes_chunks() {
@@ -329,4 +540,16 @@ mod tests {
             [Bytes::from_iter([1, 2]), Bytes::from_iter([3, 4])]
         );
     }
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(parse_content_range("bytes 0-9/10"), Some((0, Some(10))));
+        assert_eq!(parse_content_range("bytes 42-99/100"), Some((42, Some(100))));
+        assert_eq!(parse_content_range("bytes 42-99/*"), Some((42, None)));
+
+        assert_eq!(parse_content_range("bytes */100"), None);
+        assert_eq!(parse_content_range("items 0-9/10"), None);
+        assert_eq!(parse_content_range("bytes 0-9"), None);
+        assert_eq!(parse_content_range("bytes a-9/10"), None);
+    }
 }
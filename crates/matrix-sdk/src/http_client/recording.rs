@@ -0,0 +1,779 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recording of the HTTP traffic of a [`Client`], and replay of such a
+//! recording.
+//!
+//! The [`HttpRecorder`] captures the requests sent to the homeserver and the
+//! responses to them into an [`HttpRecording`], that can be saved to a file.
+//! The [`HttpReplayer`] serves the responses of a recording back to a fresh
+//! [`Client`], without any network access, which allows to reproduce bugs
+//! observed in the wild in tests.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
+#[cfg(not(target_family = "wasm"))]
+use std::{fs, io, path::Path};
+
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use matrix_sdk_base::SessionMeta;
+use matrix_sdk_common::locks::Mutex as SyncMutex;
+use ruma::serde::Base64;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use tracing::warn;
+
+use super::{HttpMiddleware, HttpRequestInfo};
+use crate::{
+    authentication::{matrix::MatrixSession, SessionTokens},
+    Client, ClientBuilder,
+};
+
+/// The value that replaces the scrubbed data in a recording.
+const REDACTED: &str = "<redacted>";
+
+/// The keys of the JSON objects whose values are scrubbed with the access
+/// tokens.
+const SECRET_KEYS: &[&str] = &["access_token", "refresh_token", "password", "token"];
+
+/// The keys of the message contents whose values are scrubbed with the
+/// message content.
+const MESSAGE_CONTENT_KEYS: &[&str] = &["body", "formatted_body"];
+
+/// The response headers that describe the body as it was transferred, and
+/// that don't apply to the recorded body.
+const TRANSFER_HEADERS: &[HeaderName] = &[
+    http::header::CONTENT_LENGTH,
+    http::header::CONTENT_ENCODING,
+    http::header::TRANSFER_ENCODING,
+];
+
+/// The path segments that are followed by a transaction ID, and the offset of
+/// the transaction ID from them.
+///
+/// For example, the transaction ID is 2 segments after `send` in
+/// `/rooms/{roomId}/send/{eventType}/{txnId}`.
+const TRANSACTION_ID_SEGMENTS: &[(&str, usize)] =
+    &[("send", 2), ("sendToDevice", 2), ("redact", 2)];
+
+/// The default maximum number of exchanges kept by an [`HttpRecorder`].
+const DEFAULT_MAX_EXCHANGES: usize = 1000;
+
+/// The homeserver URL used by the clients built by an [`HttpReplayer`].
+const REPLAY_HOMESERVER_URL: &str = "http://replay.invalid";
+
+/// A recording of the HTTP traffic between a [`Client`] and its homeserver.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HttpRecording {
+    /// The session of the user whose traffic was recorded, if known.
+    ///
+    /// It is used by [`HttpReplayer::matrix_session()`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionMeta>,
+
+    /// The recorded exchanges, in the order in which the responses were
+    /// received.
+    pub exchanges: Vec<RecordedExchange>,
+}
+
+impl HttpRecording {
+    /// Serialize this recording to JSON.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Deserialize a recording from JSON.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Save this recording as JSON to the file at the given path.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json()?)
+    }
+
+    /// Load a recording from the JSON file at the given path.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::from_json(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// A request sent to the homeserver and the response to it, in an
+/// [`HttpRecording`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedExchange {
+    /// The name of the Rust type of the request.
+    pub request_type: String,
+    /// The HTTP method of the request.
+    pub method: String,
+    /// The path of the request URI.
+    pub path: String,
+    /// The query string of the request URI, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// The headers of the request.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub request_headers: BTreeMap<String, String>,
+    /// The body of the request, if it isn't empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<RecordedBody>,
+    /// The status code of the response.
+    pub status: u16,
+    /// The headers of the response.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub response_headers: BTreeMap<String, String>,
+    /// The body of the response, if it isn't empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_body: Option<RecordedBody>,
+}
+
+impl RecordedExchange {
+    /// Build the recorded response.
+    fn to_response(&self) -> http::Response<Bytes> {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = self.response_body.as_ref().map(RecordedBody::to_bytes).unwrap_or_default();
+
+        let mut response = http::Response::new(body);
+        *response.status_mut() = status;
+
+        for (name, value) in &self.response_headers {
+            match (HeaderName::try_from(name.as_str()), HeaderValue::try_from(value.as_str())) {
+                (Ok(name), Ok(value)) => {
+                    response.headers_mut().insert(name, value);
+                }
+                _ => warn!(%name, "Ignoring invalid recorded header"),
+            }
+        }
+
+        response
+    }
+}
+
+/// The body of a request or a response, in an [`HttpRecording`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedBody {
+    /// A JSON body.
+    Json(JsonValue),
+    /// Any other body, encoded as base64.
+    Bytes(Base64),
+}
+
+impl RecordedBody {
+    fn from_bytes(bytes: &Bytes) -> Option<Self> {
+        if bytes.is_empty() {
+            return None;
+        }
+
+        Some(match serde_json::from_slice(bytes) {
+            Ok(json) => Self::Json(json),
+            Err(_) => Self::Bytes(Base64::new(bytes.to_vec())),
+        })
+    }
+
+    fn to_bytes(&self) -> Bytes {
+        match self {
+            Self::Json(json) => {
+                serde_json::to_vec(json).expect("a JSON value can always be serialized").into()
+            }
+            Self::Bytes(bytes) => bytes.as_bytes().to_vec().into(),
+        }
+    }
+}
+
+/// What is scrubbed from a recording.
+#[derive(Clone, Copy, Debug)]
+struct Scrubbing {
+    access_tokens: bool,
+    message_content: bool,
+}
+
+impl Scrubbing {
+    fn scrub_exchange(&self, exchange: &mut RecordedExchange) {
+        if self.access_tokens {
+            if let Some(authorization) =
+                exchange.request_headers.get_mut(http::header::AUTHORIZATION.as_str())
+            {
+                *authorization = format!("Bearer {REDACTED}");
+            }
+
+            if let Some(query) = &mut exchange.query {
+                *query = url::form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(url::form_urlencoded::parse(query.as_bytes()).map(
+                        |(key, value)| {
+                            let value = if key == "access_token" { REDACTED.into() } else { value };
+                            (key, value)
+                        },
+                    ))
+                    .finish();
+            }
+        }
+
+        for body in [&mut exchange.request_body, &mut exchange.response_body].into_iter().flatten()
+        {
+            if let RecordedBody::Json(json) = body {
+                self.scrub_json(json);
+            }
+        }
+    }
+
+    fn scrub_json(&self, value: &mut JsonValue) {
+        match value {
+            JsonValue::Object(object) => {
+                let is_message_content = self.message_content && object.contains_key("msgtype");
+
+                for (key, value) in object.iter_mut() {
+                    let scrub = (self.access_tokens && SECRET_KEYS.contains(&key.as_str()))
+                        || (is_message_content && MESSAGE_CONTENT_KEYS.contains(&key.as_str()));
+
+                    if scrub && value.is_string() {
+                        *value = REDACTED.into();
+                    } else {
+                        self.scrub_json(value);
+                    }
+                }
+            }
+            JsonValue::Array(array) => {
+                for value in array {
+                    self.scrub_json(value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// An [`HttpMiddleware`] that records the requests sent to the homeserver and
+/// the responses to them.
+///
+/// By default, the access tokens and passwords are scrubbed from the
+/// recording, but not the content of the messages.
+///
+/// Streamed media uploads and downloads are not recorded, nor the requests
+/// that failed before a response was received.
+///
+/// Only the most recent exchanges are kept, up to
+/// [`HttpRecorder::max_exchanges()`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// use matrix_sdk::{Client, HttpRecorder};
+/// # async {
+///
+/// let recorder = HttpRecorder::new().scrub_message_content(true);
+///
+/// let client = Client::builder()
+///     .server_name_or_homeserver_url("matrix.org")
+///     .add_http_middleware(Arc::new(recorder.clone()))
+///     .build()
+///     .await?;
+///
+/// // Reproduce the bug…
+///
+/// let mut recording = recorder.recording();
+/// recording.session = client.session_meta().cloned();
+/// recording.save("recording.json")?;
+/// # anyhow::Ok(()) };
+/// ```
+#[derive(Clone, Debug)]
+pub struct HttpRecorder {
+    exchanges: Arc<SyncMutex<VecDeque<RecordedExchange>>>,
+    max_exchanges: usize,
+    scrubbing: Scrubbing,
+}
+
+impl HttpRecorder {
+    /// Create a new `HttpRecorder`.
+    pub fn new() -> Self {
+        Self {
+            exchanges: Default::default(),
+            max_exchanges: DEFAULT_MAX_EXCHANGES,
+            scrubbing: Scrubbing { access_tokens: true, message_content: false },
+        }
+    }
+
+    /// The maximum number of exchanges to keep.
+    ///
+    /// When it is reached, the oldest exchanges are dropped to record the new
+    /// ones. Defaults to 1000.
+    pub fn max_exchanges(mut self, max: usize) -> Self {
+        self.max_exchanges = max;
+        self
+    }
+
+    /// Whether to scrub the access tokens and passwords from the recording.
+    ///
+    /// Defaults to `true`.
+    pub fn scrub_access_tokens(mut self, scrub: bool) -> Self {
+        self.scrubbing.access_tokens = scrub;
+        self
+    }
+
+    /// Whether to scrub the text of the messages from the recording.
+    ///
+    /// Only the unencrypted messages can be scrubbed. Defaults to `false`.
+    pub fn scrub_message_content(mut self, scrub: bool) -> Self {
+        self.scrubbing.message_content = scrub;
+        self
+    }
+
+    /// Get the exchanges recorded so far.
+    pub fn recording(&self) -> HttpRecording {
+        HttpRecording { session: None, exchanges: self.exchanges.lock().iter().cloned().collect() }
+    }
+
+    /// Forget the exchanges recorded so far.
+    pub fn clear(&self) {
+        self.exchanges.lock().clear();
+    }
+}
+
+impl Default for HttpRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpMiddleware for HttpRecorder {
+    fn inspect_response(
+        &self,
+        info: &HttpRequestInfo,
+        request: &http::Request<Bytes>,
+        response: &http::Response<Bytes>,
+    ) {
+        if self.max_exchanges == 0 {
+            return;
+        }
+
+        let mut exchange = RecordedExchange {
+            request_type: info.request_type.to_owned(),
+            method: info.method.to_string(),
+            path: info.path.clone(),
+            query: request.uri().query().map(ToOwned::to_owned),
+            request_headers: headers_to_map(request.headers(), &[]),
+            request_body: RecordedBody::from_bytes(request.body()),
+            status: response.status().as_u16(),
+            response_headers: headers_to_map(response.headers(), TRANSFER_HEADERS),
+            response_body: RecordedBody::from_bytes(response.body()),
+        };
+
+        self.scrubbing.scrub_exchange(&mut exchange);
+
+        let mut exchanges = self.exchanges.lock();
+        while exchanges.len() >= self.max_exchanges {
+            exchanges.pop_front();
+        }
+        exchanges.push_back(exchange);
+    }
+}
+
+fn headers_to_map(headers: &HeaderMap, ignored: &[HeaderName]) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| !ignored.contains(name))
+        .filter_map(|(name, value)| {
+            Some((name.as_str().to_owned(), value.to_str().ok()?.to_owned()))
+        })
+        .collect()
+}
+
+/// An [`HttpMiddleware`] that answers the requests of a [`Client`] with the
+/// responses of an [`HttpRecording`], instead of sending them.
+///
+/// A request is answered with the first exchange of the recording that wasn't
+/// replayed yet, and that has the same method and path, ignoring the
+/// transaction IDs. If several exchanges match, the first one with the same
+/// request type is preferred. Requests that don't match any exchange are
+/// answered with a `404 M_UNRECOGNIZED` error.
+///
+/// # Examples
+///
+/// ```no_run
+/// use matrix_sdk::{HttpRecording, HttpReplayer};
+/// # async {
+///
+/// let replayer = HttpReplayer::new(HttpRecording::load("recording.json")?);
+///
+/// let client = replayer.client_builder().build().await?;
+/// if let Some(session) = replayer.matrix_session() {
+///     client.restore_session(session).await?;
+/// }
+///
+/// client.sync_once(Default::default()).await?;
+/// // Check the state of the client…
+///
+/// replayer.verify();
+/// # anyhow::Ok(()) };
+/// ```
+#[derive(Clone, Debug)]
+pub struct HttpReplayer {
+    inner: Arc<ReplayerInner>,
+}
+
+#[derive(Debug)]
+struct ReplayerInner {
+    session: Option<SessionMeta>,
+    state: SyncMutex<ReplayState>,
+}
+
+#[derive(Debug)]
+struct ReplayState {
+    /// The exchanges that weren't replayed yet.
+    pending: Vec<RecordedExchange>,
+    /// The requests that didn't match any exchange.
+    unmatched: Vec<HttpRequestInfo>,
+}
+
+impl HttpReplayer {
+    /// Create a new `HttpReplayer` for the given recording.
+    pub fn new(recording: HttpRecording) -> Self {
+        let state = ReplayState { pending: recording.exchanges, unmatched: Vec::new() };
+        Self { inner: Arc::new(ReplayerInner { session: recording.session, state: state.into() }) }
+    }
+
+    /// Get a [`ClientBuilder`] for a client whose requests are answered by
+    /// this replayer.
+    pub fn client_builder(&self) -> ClientBuilder {
+        Client::builder()
+            .homeserver_url(REPLAY_HOMESERVER_URL)
+            .add_http_middleware(Arc::new(self.clone()))
+    }
+
+    /// Get a session to restore in the client, for the user whose traffic was
+    /// recorded.
+    ///
+    /// Returns `None` if the recording doesn't contain the session. The
+    /// tokens of the session are placeholders, since they are not checked
+    /// during the replay.
+    pub fn matrix_session(&self) -> Option<MatrixSession> {
+        let meta = self.inner.session.clone()?;
+        let tokens = SessionTokens { access_token: REDACTED.to_owned(), refresh_token: None };
+        Some(MatrixSession { meta, tokens })
+    }
+
+    /// The number of exchanges of the recording that weren't replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.inner.state.lock().pending.len()
+    }
+
+    /// The requests that didn't match any exchange of the recording.
+    pub fn unmatched_requests(&self) -> Vec<HttpRequestInfo> {
+        self.inner.state.lock().unmatched.clone()
+    }
+
+    /// Check that all the exchanges of the recording were replayed, and that
+    /// all the requests matched an exchange.
+    ///
+    /// # Panics
+    ///
+    /// Panics if some exchanges weren't replayed, or some requests didn't
+    /// match an exchange.
+    #[track_caller]
+    pub fn verify(&self) {
+        let state = self.inner.state.lock();
+
+        assert!(
+            state.unmatched.is_empty(),
+            "{} requests didn't match any recorded exchange: {:#?}",
+            state.unmatched.len(),
+            state.unmatched
+        );
+        assert!(
+            state.pending.is_empty(),
+            "{} recorded exchanges weren't replayed: {:#?}",
+            state.pending.len(),
+            state.pending.iter().map(|e| format!("{} {}", e.method, e.path)).collect::<Vec<_>>()
+        );
+    }
+}
+
+impl HttpMiddleware for HttpReplayer {
+    fn before_request(
+        &self,
+        info: &HttpRequestInfo,
+        _request: &mut http::Request<Bytes>,
+    ) -> Option<http::Response<Bytes>> {
+        let mut state = self.inner.state.lock();
+        let path = normalize_path(&info.path);
+
+        let candidates: Vec<_> = state
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.method == info.method.as_str() && normalize_path(&e.path) == path)
+            .collect();
+
+        // The type name of the request is only used to choose between the candidates,
+        // since it isn't stable across compiler versions.
+        let position = candidates
+            .iter()
+            .find(|(_, e)| e.request_type == info.request_type)
+            .or(candidates.first())
+            .map(|(position, _)| *position);
+
+        let Some(position) = position else {
+            warn!(method = %info.method, path = %info.path, "No recorded exchange for the request");
+            state.unmatched.push(info.clone());
+
+            let body = json!({
+                "errcode": "M_UNRECOGNIZED",
+                "error": "No recorded exchange for this request",
+            });
+            let mut response = http::Response::new(body.to_string().into());
+            *response.status_mut() = StatusCode::NOT_FOUND;
+
+            return Some(response);
+        };
+
+        Some(state.pending.remove(position).to_response())
+    }
+}
+
+/// Replace the transaction IDs in the given path with a placeholder, since
+/// they change every time a request is sent.
+fn normalize_path(path: &str) -> String {
+    let mut segments: Vec<&str> = path.split('/').collect();
+
+    for index in 0..segments.len() {
+        for (segment, offset) in TRANSACTION_ID_SEGMENTS {
+            if segments[index] == *segment && index + offset < segments.len() {
+                segments[index + offset] = "{txnId}";
+            }
+        }
+    }
+
+    segments.join("/")
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use std::{any::type_name, sync::Arc};
+
+    use bytes::Bytes;
+    use http::{Method, StatusCode};
+    use matrix_sdk_test::{async_test, test_json};
+    use ruma::{
+        api::client::{account::whoami, message::send_message_event},
+        event_id,
+        events::room::message::RoomMessageEventContent,
+        room_id,
+    };
+    use serde_json::{json, Value as JsonValue};
+
+    use super::{
+        HttpRecorder, HttpRecording, HttpReplayer, RecordedBody, RecordedExchange, REDACTED,
+    };
+    use crate::{
+        config::SyncSettings,
+        http_client::{HttpMiddleware, HttpRequestInfo},
+        test_utils::mocks::MatrixMockServer,
+    };
+
+    fn exchange(request_type: &str, method: &str, path: &str, body: JsonValue) -> RecordedExchange {
+        RecordedExchange {
+            request_type: request_type.to_owned(),
+            method: method.to_owned(),
+            path: path.to_owned(),
+            query: None,
+            request_headers: Default::default(),
+            request_body: None,
+            status: 200,
+            response_headers: Default::default(),
+            response_body: Some(RecordedBody::Json(body)),
+        }
+    }
+
+    /// Send a request of type `R` to the given replayer, and return the body
+    /// of the response it short-circuited the request with.
+    fn replay<R>(replayer: &HttpReplayer, method: Method, path: &str) -> JsonValue {
+        let mut request =
+            http::Request::builder().method(method).uri(path).body(Bytes::new()).unwrap();
+        let info = HttpRequestInfo::new::<R>(&request, 1);
+
+        let response = replayer.before_request(&info, &mut request).unwrap();
+        serde_json::from_slice(response.body()).unwrap()
+    }
+
+    #[async_test]
+    async fn test_record_and_replay() {
+        let room_id = room_id!("!test:localhost");
+        let recorder = HttpRecorder::new().scrub_message_content(true);
+
+        let server = MatrixMockServer::new().await;
+        let client = server
+            .client_builder()
+            .no_server_versions()
+            .on_builder(|builder| builder.add_http_middleware(Arc::new(recorder.clone())))
+            .build()
+            .await;
+
+        server.mock_versions().ok().mount().await;
+        server.mock_room_state_encryption().plain().mount().await;
+        server.mock_room_send().ok(event_id!("$event")).mount().await;
+
+        let room = server.sync_joined_room(&client, room_id).await;
+        room.send(RoomMessageEventContent::text_plain("secret message")).await.unwrap();
+
+        let mut recording = recorder.recording();
+        recording.session = client.session_meta().cloned();
+
+        // The access token and the message content are scrubbed.
+        let json = recording.to_json().unwrap();
+        assert!(!json.contains("Bearer 1234"));
+        assert!(!json.contains("secret message"));
+
+        let send = recording.exchanges.iter().find(|e| e.method == "PUT").unwrap();
+        assert_eq!(send.request_headers["authorization"], format!("Bearer {REDACTED}"));
+        let Some(RecordedBody::Json(content)) = &send.request_body else {
+            panic!("the request body should be JSON");
+        };
+        assert_eq!(content["body"], REDACTED);
+        assert_eq!(content["msgtype"], "m.text");
+
+        // Replay the recording with a fresh client, without the server.
+        drop(server);
+        let replayer = HttpReplayer::new(HttpRecording::from_json(&json).unwrap());
+
+        let client = replayer.client_builder().build().await.unwrap();
+        client.restore_session(replayer.matrix_session().unwrap()).await.unwrap();
+
+        client.sync_once(SyncSettings::default()).await.unwrap();
+        let room = client.get_room(room_id).unwrap();
+        let response = room.send(RoomMessageEventContent::text_plain("message")).await.unwrap();
+        assert_eq!(response.event_id, event_id!("$event"));
+
+        replayer.verify();
+    }
+
+    #[test]
+    fn test_replay_matches_path_ignoring_transaction_ids() {
+        let replayer = HttpReplayer::new(HttpRecording {
+            session: None,
+            exchanges: vec![
+                exchange(
+                    "Request",
+                    "PUT",
+                    "/_matrix/client/v3/rooms/!a:localhost/send/m.room.message/txn1",
+                    json!({ "event_id": "$a" }),
+                ),
+                exchange(
+                    "Request",
+                    "PUT",
+                    "/_matrix/client/v3/rooms/!b:localhost/send/m.room.message/txn2",
+                    json!({ "event_id": "$b" }),
+                ),
+            ],
+        });
+
+        // The requests are answered by the exchange for the same room, even if they're
+        // sent in a different order, and with other transaction IDs.
+        let response = replay::<send_message_event::v3::Request>(
+            &replayer,
+            Method::PUT,
+            "/_matrix/client/v3/rooms/!b:localhost/send/m.room.message/other",
+        );
+        assert_eq!(response["event_id"], "$b");
+
+        let response = replay::<send_message_event::v3::Request>(
+            &replayer,
+            Method::PUT,
+            "/_matrix/client/v3/rooms/!a:localhost/send/m.room.message/another",
+        );
+        assert_eq!(response["event_id"], "$a");
+
+        replayer.verify();
+    }
+
+    #[test]
+    fn test_replay_prefers_the_same_request_type() {
+        let path = "/_matrix/client/v3/account/whoami";
+        let replayer = HttpReplayer::new(HttpRecording {
+            session: None,
+            exchanges: vec![
+                exchange("Other", "GET", path, json!({ "exchange": 1 })),
+                exchange(type_name::<whoami::v3::Request>(), "GET", path, json!({ "exchange": 2 })),
+            ],
+        });
+
+        let response = replay::<whoami::v3::Request>(&replayer, Method::GET, path);
+        assert_eq!(response["exchange"], 2);
+
+        // The request type is only used to choose between the matching exchanges.
+        let response = replay::<whoami::v3::Request>(&replayer, Method::GET, path);
+        assert_eq!(response["exchange"], 1);
+
+        replayer.verify();
+    }
+
+    #[test]
+    fn test_recorder_keeps_the_most_recent_exchanges() {
+        let recorder = HttpRecorder::new().max_exchanges(2);
+
+        for path in ["/1", "/2", "/3"] {
+            let request = http::Request::builder().uri(path).body(Bytes::new()).unwrap();
+            let info = HttpRequestInfo::new::<whoami::v3::Request>(&request, 1);
+            recorder.inspect_response(&info, &request, &http::Response::new(Bytes::new()));
+        }
+
+        let paths: Vec<_> = recorder.recording().exchanges.into_iter().map(|e| e.path).collect();
+        assert_eq!(paths, ["/2", "/3"]);
+    }
+
+    #[async_test]
+    async fn test_replay_unmatched_request() {
+        let replayer = HttpReplayer::new(HttpRecording::default());
+        let client = replayer.client_builder().build().await.unwrap();
+
+        let error = client.fetch_server_versions(None).await.unwrap_err();
+        assert_eq!(error.as_client_api_error().unwrap().status_code, StatusCode::NOT_FOUND);
+
+        assert_eq!(replayer.remaining(), 0);
+        let unmatched = replayer.unmatched_requests();
+        assert_eq!(unmatched.len(), 1);
+        assert!(unmatched[0].path.ends_with("/versions"));
+    }
+
+    #[test]
+    fn test_unscrubbed_recording() {
+        let recorder = HttpRecorder::new().scrub_access_tokens(false);
+        let mut exchange = RecordedExchange {
+            request_type: "Request".to_owned(),
+            method: "POST".to_owned(),
+            path: "/login".to_owned(),
+            query: Some("access_token=abc&foo=bar".to_owned()),
+            request_headers: Default::default(),
+            request_body: Some(RecordedBody::Json(json!({ "password": "hunter2" }))),
+            status: 200,
+            response_headers: Default::default(),
+            response_body: Some(RecordedBody::Json(test_json::LOGIN.clone())),
+        };
+        recorder.scrubbing.scrub_exchange(&mut exchange);
+        assert_eq!(exchange.query.as_deref(), Some("access_token=abc&foo=bar"));
+
+        let recorder = HttpRecorder::new();
+        recorder.scrubbing.scrub_exchange(&mut exchange);
+        assert_eq!(exchange.query.as_deref(), Some("access_token=%3Credacted%3E&foo=bar"));
+        let Some(RecordedBody::Json(request)) = &exchange.request_body else { unreachable!() };
+        assert_eq!(request["password"], REDACTED);
+        let Some(RecordedBody::Json(response)) = &exchange.response_body else { unreachable!() };
+        assert_eq!(response["access_token"], REDACTED);
+    }
+}
@@ -35,11 +35,14 @@ impl HttpClient {
     {
         tracing::debug!("Sending request");
 
-        let request = reqwest::Request::try_from(request)?;
-
         let before = ruma::time::Instant::now();
 
-        let response = response_to_http_response(self.inner.execute(request).await?).await?;
+        let response = self
+            .send_with_middlewares::<R, _, _, _>(&request, 1, |request| async move {
+                let request = reqwest::Request::try_from(request)?;
+                Ok(response_to_http_response(self.inner.execute(request).await?).await?)
+            })
+            .await?;
 
         let request_duration = ruma::time::Instant::now().saturating_duration_since(before);
         let status_code = response.status();
@@ -75,7 +75,10 @@ pub use error::{
     Error, HttpError, HttpResult, NotificationSettingsError, RefreshTokenError, Result,
     RumaApiError,
 };
-pub use http_client::TransmissionProgress;
+pub use http_client::{
+    HttpFailureKind, HttpMiddleware, HttpRecorder, HttpRecording, HttpReplayer, HttpRequestInfo,
+    HttpResponseInfo, RecordedBody, RecordedExchange, TransmissionProgress,
+};
 #[cfg(all(feature = "e2e-encryption", feature = "sqlite"))]
 pub use matrix_sdk_sqlite::SqliteCryptoStore;
 #[cfg(feature = "sqlite")]
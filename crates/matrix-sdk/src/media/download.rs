@@ -0,0 +1,703 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Streaming media downloads, which can be resumed after an interruption.
+
+#[cfg(feature = "e2e-encryption")]
+use std::io::{self, Read, Write};
+use std::{
+    future::IntoFuture,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use eyeball::{SharedObservable, Subscriber};
+use futures_util::{future::try_join, StreamExt};
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_base::crypto::{AttachmentDecryptor, MediaEncryptionInfo};
+use matrix_sdk_common::boxed_into_future;
+use ruma::{
+    api::client::{authenticated_media, media},
+    events::room::{EncryptedFile, MediaSource},
+    MxcUri, OwnedMxcUri,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use tokio::{
+    fs::{File as TokioFile, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+#[cfg(feature = "e2e-encryption")]
+use tokio_stream::wrappers::ReceiverStream;
+#[cfg(feature = "e2e-encryption")]
+use tokio_util::io::{StreamReader, SyncIoBridge};
+use tracing::{debug, warn};
+
+use super::{Media, MediaError};
+use crate::{
+    error::RetryKind, http_client::StreamingDownload, Client, Error, HttpError, Result,
+    TransmissionProgress,
+};
+
+/// The extension added to the destination path of
+/// [`Media::download_to_file()`] while the download is in progress.
+const PARTIAL_DOWNLOAD_EXTENSION: &str = "part";
+/// The extension added to the path of the `.part` file, for the file storing
+/// its [`PartialDownloadMetadata`].
+const PARTIAL_DOWNLOAD_METADATA_EXTENSION: &str = "meta";
+/// The default number of times an interrupted download is resumed before
+/// giving up.
+const DEFAULT_MAX_RESUME_ATTEMPTS: u32 = 5;
+/// The delay before the first attempt to resume an interrupted download.
+const MIN_RESUME_DELAY: Duration = Duration::from_millis(500);
+/// The maximum delay between two attempts to resume an interrupted download.
+const MAX_RESUME_DELAY: Duration = Duration::from_secs(30);
+/// The size of the chunks written to the destination of an encrypted
+/// download.
+#[cfg(feature = "e2e-encryption")]
+const DECRYPTION_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Future returned by [`Media::download_to_file()`].
+#[allow(missing_debug_implementations)]
+pub struct DownloadMediaToFile {
+    client: Client,
+    source: MediaSource,
+    path: PathBuf,
+    progress: SharedObservable<TransmissionProgress>,
+    max_resume_attempts: u32,
+}
+
+impl DownloadMediaToFile {
+    pub(super) fn new(client: Client, source: MediaSource, path: PathBuf) -> Self {
+        Self {
+            client,
+            source,
+            path,
+            progress: Default::default(),
+            max_resume_attempts: DEFAULT_MAX_RESUME_ATTEMPTS,
+        }
+    }
+
+    /// Replace the default `SharedObservable` used for tracking the download
+    /// progress.
+    ///
+    /// Note that any subscribers obtained from
+    /// [`subscribe_to_progress`][Self::subscribe_to_progress] will be
+    /// invalidated by this.
+    pub fn with_progress_observable(
+        mut self,
+        progress: SharedObservable<TransmissionProgress>,
+    ) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Set how many times the download is resumed after a network failure,
+    /// before giving up.
+    ///
+    /// Defaults to 5.
+    pub fn with_max_resume_attempts(mut self, max_resume_attempts: u32) -> Self {
+        self.max_resume_attempts = max_resume_attempts;
+        self
+    }
+
+    /// Get a subscriber to observe the progress of the download.
+    pub fn subscribe_to_progress(&self) -> Subscriber<TransmissionProgress> {
+        self.progress.subscribe()
+    }
+}
+
+impl IntoFuture for DownloadMediaToFile {
+    type Output = Result<()>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { client, source, path, progress, max_resume_attempts } = self;
+
+        Box::pin(async move {
+            // Local medias, notably those pending in the send queue, are already in the
+            // cache.
+            if let Some(uri) = Media::as_local_uri(&source) {
+                let content = client.media().get_local_media_content(uri).await?;
+                tokio::fs::write(&path, content).await?;
+                return Ok(());
+            }
+
+            let part_path = partial_download_path(&path);
+            let metadata_path = partial_download_metadata_path(&part_path);
+            let mut part = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&part_path)
+                .await?;
+
+            let encrypted_file = encrypted_file(&source);
+            let mut hasher = encrypted_file.map(|_| Sha256::new());
+
+            let mut metadata =
+                PartialDownloadMetadata { uri: source_uri(&source).to_owned(), total_length: None };
+            let mut offset = part.metadata().await?.len();
+
+            if offset > 0 {
+                // Only resume from the content of the same media.
+                match PartialDownloadMetadata::load(&metadata_path).await {
+                    Some(previous) if previous.uri == metadata.uri => {
+                        metadata.total_length = previous.total_length;
+                    }
+                    _ => {
+                        warn!("The partial media download is for another media, starting over");
+                        part.set_len(0).await?;
+                        offset = 0;
+                    }
+                }
+            }
+
+            // Record which media is in the `.part` file before writing to it.
+            metadata.save(&metadata_path).await?;
+
+            if offset > 0 {
+                debug!(offset, "Resuming a partial media download");
+
+                if let Some(hasher) = &mut hasher {
+                    hash_prefix(&mut part, offset, hasher).await?;
+                }
+
+                part.seek(SeekFrom::Start(offset)).await?;
+            }
+
+            let result = download_to_part_file(
+                &client,
+                &mut metadata,
+                &metadata_path,
+                offset,
+                &mut part,
+                hasher.as_mut(),
+                &progress,
+                max_resume_attempts,
+            )
+            .await;
+
+            match result {
+                Err(Error::Media(
+                    error @ (MediaError::RangeNotSupported
+                    | MediaError::UnexpectedRange
+                    | MediaError::ContentLengthChanged),
+                )) => {
+                    // The download can't be resumed from the content we have, start over.
+                    warn!("Couldn't resume the media download, starting over: {error}");
+
+                    part.set_len(0).await?;
+                    part.seek(SeekFrom::Start(0)).await?;
+                    hasher = encrypted_file.map(|_| Sha256::new());
+                    metadata.total_length = None;
+                    metadata.save(&metadata_path).await?;
+
+                    download_to_part_file(
+                        &client,
+                        &mut metadata,
+                        &metadata_path,
+                        0,
+                        &mut part,
+                        hasher.as_mut(),
+                        &progress,
+                        max_resume_attempts,
+                    )
+                    .await?;
+                }
+                result => result?,
+            }
+
+            part.flush().await?;
+            drop(part);
+
+            #[cfg(feature = "e2e-encryption")]
+            if let (Some(file), Some(hasher)) = (encrypted_file, hasher) {
+                // Don't keep corrupted content around, otherwise the next attempt would
+                // resume from it.
+                if let Err(error) = verify_hash(file, hasher) {
+                    tokio::fs::remove_file(&part_path).await?;
+                    tokio::fs::remove_file(&metadata_path).await?;
+                    return Err(error);
+                }
+
+                decrypt_part_file(file, &part_path, &path).await?;
+                tokio::fs::remove_file(&part_path).await?;
+                tokio::fs::remove_file(&metadata_path).await?;
+
+                return Ok(());
+            }
+
+            tokio::fs::rename(&part_path, &path).await?;
+            tokio::fs::remove_file(&metadata_path).await?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Future returned by [`Media::download_to_writer()`].
+#[allow(missing_debug_implementations)]
+pub struct DownloadMediaToWriter<W> {
+    client: Client,
+    source: MediaSource,
+    writer: W,
+    progress: SharedObservable<TransmissionProgress>,
+    max_resume_attempts: u32,
+}
+
+impl<W> DownloadMediaToWriter<W> {
+    pub(super) fn new(client: Client, source: MediaSource, writer: W) -> Self {
+        Self {
+            client,
+            source,
+            writer,
+            progress: Default::default(),
+            max_resume_attempts: DEFAULT_MAX_RESUME_ATTEMPTS,
+        }
+    }
+
+    /// Replace the default `SharedObservable` used for tracking the download
+    /// progress.
+    ///
+    /// Note that any subscribers obtained from
+    /// [`subscribe_to_progress`][Self::subscribe_to_progress] will be
+    /// invalidated by this.
+    pub fn with_progress_observable(
+        mut self,
+        progress: SharedObservable<TransmissionProgress>,
+    ) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Set how many times the download is resumed after a network failure,
+    /// before giving up.
+    ///
+    /// Defaults to 5.
+    pub fn with_max_resume_attempts(mut self, max_resume_attempts: u32) -> Self {
+        self.max_resume_attempts = max_resume_attempts;
+        self
+    }
+
+    /// Get a subscriber to observe the progress of the download.
+    pub fn subscribe_to_progress(&self) -> Subscriber<TransmissionProgress> {
+        self.progress.subscribe()
+    }
+}
+
+impl<W> IntoFuture for DownloadMediaToWriter<W>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = Result<W>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { client, source, mut writer, progress, max_resume_attempts } = self;
+
+        Box::pin(async move {
+            if let Some(uri) = Media::as_local_uri(&source) {
+                let content = client.media().get_local_media_content(uri).await?;
+                writer.write_all(&content).await?;
+                writer.flush().await?;
+                return Ok(writer);
+            }
+
+            let uri = source_uri(&source);
+            let (sender, mut receiver) = mpsc::channel::<Bytes>(4);
+            let download =
+                download_raw(&client, uri, 0, None, sender, &progress, max_resume_attempts);
+
+            #[cfg(feature = "e2e-encryption")]
+            if let Some(file) = encrypted_file(&source) {
+                // The decryptor only works over a blocking reader, so run it on a dedicated
+                // thread, fed with the downloaded chunks, and forward the decrypted chunks to
+                // the writer as they come.
+                let reader = SyncIoBridge::new(StreamReader::new(
+                    ReceiverStream::new(receiver).map(Ok::<_, io::Error>),
+                ));
+                let (decrypted_sender, mut decrypted_receiver) = mpsc::channel::<Bytes>(4);
+                let info = MediaEncryptionInfo::from(file.clone());
+                let decryption = tokio::task::spawn_blocking(move || {
+                    decrypt_into(reader, info, decrypted_sender)
+                });
+
+                let write = async {
+                    while let Some(chunk) = decrypted_receiver.recv().await {
+                        writer.write_all(&chunk).await?;
+                    }
+                    writer.flush().await?;
+                    Ok::<_, Error>(())
+                };
+
+                try_join(download, write).await?;
+                decryption.await.map_err(io::Error::other)??;
+
+                return Ok(writer);
+            }
+
+            let write = async {
+                while let Some(chunk) = receiver.recv().await {
+                    writer.write_all(&chunk).await?;
+                }
+                writer.flush().await?;
+                Ok::<_, Error>(())
+            };
+
+            try_join(download, write).await?;
+
+            Ok(writer)
+        })
+    }
+}
+
+/// Get the path where the raw content of a media is downloaded before being
+/// moved to, or decrypted into, `path`.
+fn partial_download_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().map(ToOwned::to_owned).unwrap_or_default();
+    file_name.push(".");
+    file_name.push(PARTIAL_DOWNLOAD_EXTENSION);
+    path.with_file_name(file_name)
+}
+
+/// Get the path of the file storing the [`PartialDownloadMetadata`] of the
+/// `.part` file at `part_path`.
+fn partial_download_metadata_path(part_path: &Path) -> PathBuf {
+    let mut file_name = part_path.file_name().map(ToOwned::to_owned).unwrap_or_default();
+    file_name.push(".");
+    file_name.push(PARTIAL_DOWNLOAD_METADATA_EXTENSION);
+    part_path.with_file_name(file_name)
+}
+
+/// What is known about the content of a `.part` file, to check that a download
+/// can be resumed from it.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct PartialDownloadMetadata {
+    /// The MXC URI of the content.
+    uri: OwnedMxcUri,
+
+    /// The length of the whole content, once it is known.
+    total_length: Option<u64>,
+}
+
+impl PartialDownloadMetadata {
+    /// Load the metadata at `path`, if it exists and can be read.
+    async fn load(path: &Path) -> Option<Self> {
+        let bytes = tokio::fs::read(path).await.ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// Save the metadata to `path`.
+    async fn save(&self, path: &Path) -> Result<()> {
+        tokio::fs::write(path, serde_json::to_vec(self)?).await?;
+        Ok(())
+    }
+}
+
+/// Get the MXC URI of the content of the given media source.
+fn source_uri(source: &MediaSource) -> &MxcUri {
+    match source {
+        MediaSource::Plain(uri) => uri,
+        MediaSource::Encrypted(file) => &file.url,
+    }
+}
+
+/// Get the encryption info of the given media source, if it is encrypted and
+/// can be decrypted.
+///
+/// Without the `e2e-encryption` feature, encrypted media is downloaded as-is.
+fn encrypted_file(source: &MediaSource) -> Option<&EncryptedFile> {
+    match source {
+        #[cfg(feature = "e2e-encryption")]
+        MediaSource::Encrypted(file) => Some(file),
+        _ => None,
+    }
+}
+
+/// Feed the first `length` bytes of `file` to `hasher`.
+async fn hash_prefix(file: &mut TokioFile, length: u64, hasher: &mut Sha256) -> Result<()> {
+    file.seek(SeekFrom::Start(0)).await?;
+
+    let mut reader = (&mut *file).take(length);
+    let mut buf = vec![0; 64 * 1024];
+
+    loop {
+        let read = reader.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(())
+}
+
+/// Check that the downloaded content of an encrypted file matches the SHA-256
+/// hash of its encryption info.
+#[cfg(feature = "e2e-encryption")]
+fn verify_hash(file: &EncryptedFile, hasher: Sha256) -> Result<()> {
+    let expected = file.hashes.get("sha256").ok_or(MediaError::MissingHash)?;
+
+    if expected.as_bytes() != hasher.finalize().as_slice() {
+        return Err(MediaError::HashMismatch.into());
+    }
+
+    Ok(())
+}
+
+/// Decrypt the encrypted content at `part_path` into `path`.
+#[cfg(feature = "e2e-encryption")]
+async fn decrypt_part_file(file: &EncryptedFile, part_path: &Path, path: &Path) -> Result<()> {
+    let info = MediaEncryptionInfo::from(file.clone());
+    let part_path = part_path.to_owned();
+    let path = path.to_owned();
+
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let mut input = std::fs::File::open(part_path)?;
+        let mut decryptor = AttachmentDecryptor::new(&mut input, info)?;
+        let mut output = std::fs::File::create(path)?;
+
+        io::copy(&mut decryptor, &mut output)?;
+        output.flush()?;
+
+        Ok(())
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+/// Decrypt everything that can be read from `reader`, sending the decrypted
+/// chunks to `sender`.
+///
+/// The hash of the content is verified once everything has been read.
+#[cfg(feature = "e2e-encryption")]
+fn decrypt_into(
+    mut reader: impl Read,
+    info: MediaEncryptionInfo,
+    sender: mpsc::Sender<Bytes>,
+) -> Result<()> {
+    let mut decryptor = AttachmentDecryptor::new(&mut reader, info)?;
+    let mut buf = vec![0; DECRYPTION_CHUNK_SIZE];
+
+    loop {
+        let read = match decryptor.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            // The underlying reader only yields the downloaded chunks, so the only way the
+            // decryptor can fail is if the hash of the content doesn't match.
+            Err(_) => return Err(MediaError::HashMismatch.into()),
+        };
+
+        if sender.blocking_send(Bytes::copy_from_slice(&buf[..read])).is_err() {
+            return Err(
+                io::Error::new(io::ErrorKind::BrokenPipe, "the download has been aborted").into()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Download the raw content described by `metadata` from `offset` into
+/// `part`, feeding it to `hasher` too if there is one.
+///
+/// The length of the whole content is saved to `metadata_path` as soon as it
+/// is known.
+#[allow(clippy::too_many_arguments)]
+async fn download_to_part_file(
+    client: &Client,
+    metadata: &mut PartialDownloadMetadata,
+    metadata_path: &Path,
+    offset: u64,
+    part: &mut TokioFile,
+    mut hasher: Option<&mut Sha256>,
+    progress: &SharedObservable<TransmissionProgress>,
+    max_resume_attempts: u32,
+) -> Result<()> {
+    let (sender, mut receiver) = mpsc::channel::<Bytes>(4);
+    let uri = metadata.uri.clone();
+    let download = download_raw(
+        client,
+        &uri,
+        offset,
+        metadata.total_length,
+        sender,
+        progress,
+        max_resume_attempts,
+    );
+
+    let write = async {
+        while let Some(chunk) = receiver.recv().await {
+            // The total length, if any, is known before the first chunk is received.
+            if metadata.total_length.is_none() {
+                let total_length = progress.get().total;
+
+                if total_length > 0 {
+                    metadata.total_length = Some(total_length as u64);
+                    metadata.save(metadata_path).await?;
+                }
+            }
+
+            part.write_all(&chunk).await?;
+
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&chunk);
+            }
+        }
+        Ok::<_, Error>(())
+    };
+
+    try_join(download, write).await?;
+
+    Ok(())
+}
+
+/// Download the raw content at `uri` from `offset`, sending the chunks to
+/// `sender` as they come.
+///
+/// If the download is interrupted by a network failure, it is resumed from
+/// where it stopped, at most `max_resume_attempts` times in a row.
+///
+/// Returns [`MediaError::RangeNotSupported`] if the server doesn't allow to
+/// resume the download, and [`MediaError::ContentLengthChanged`] if the length
+/// of the whole content isn't `expected_length`, or changes between two
+/// attempts.
+async fn download_raw(
+    client: &Client,
+    uri: &MxcUri,
+    mut offset: u64,
+    mut expected_length: Option<u64>,
+    sender: mpsc::Sender<Bytes>,
+    progress: &SharedObservable<TransmissionProgress>,
+    max_resume_attempts: u32,
+) -> Result<()> {
+    // Downloading a file should have no timeout as we don't know the network
+    // connectivity available for the user or the file size.
+    let request_config = client.request_config().timeout(None);
+
+    // Use the authenticated endpoints when the server supports it.
+    let supported_versions = client.supported_versions().await?;
+    let use_auth = authenticated_media::get_content::v1::Request::is_supported(&supported_versions);
+
+    progress.update(|p| p.current = offset as usize);
+
+    let mut resume_attempts = 0;
+    let mut delay = MIN_RESUME_DELAY;
+
+    loop {
+        let previous_offset = offset;
+
+        let result: Result<bool> = async {
+            let download = if use_auth {
+                let request = authenticated_media::get_content::v1::Request::from_uri(uri)?;
+                client.download_streaming_inner(request, offset, Some(request_config)).await?
+            } else {
+                #[allow(deprecated)]
+                let request = media::get_content::v3::Request::from_url(uri)?;
+                client.download_streaming_inner(request, offset, Some(request_config)).await?
+            };
+
+            let StreamingDownload { offset: start, total_length, mut body } = download;
+
+            match start {
+                Some(start) if start == offset => {}
+                Some(0) => return Err(MediaError::RangeNotSupported.into()),
+                _ => return Err(MediaError::UnexpectedRange.into()),
+            }
+
+            if let Some(total_length) = total_length {
+                if expected_length.is_some_and(|expected| expected != total_length) {
+                    return Err(MediaError::ContentLengthChanged.into());
+                }
+
+                expected_length = Some(total_length);
+                progress.update(|p| p.total = total_length as usize);
+            }
+
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk.map_err(HttpError::from)?;
+                offset += chunk.len() as u64;
+                progress.update(|p| p.current = offset as usize);
+
+                if sender.send(chunk).await.is_err() {
+                    // The receiving end failed, it will report its own error.
+                    return Ok(true);
+                }
+            }
+
+            // The connection might have been closed before the whole content was sent.
+            Ok(total_length.is_none_or(|total_length| offset >= total_length))
+        }
+        .await;
+
+        let error = match result {
+            Ok(true) => return Ok(()),
+            Ok(false) => None,
+            Err(Error::Http(error))
+                if matches!(
+                    error.retry_kind(),
+                    RetryKind::NetworkFailure | RetryKind::Transient { .. }
+                ) =>
+            {
+                Some(error)
+            }
+            Err(error) => return Err(error),
+        };
+
+        // Only count the attempts that didn't make any progress.
+        if offset > previous_offset {
+            resume_attempts = 0;
+            delay = MIN_RESUME_DELAY;
+        }
+
+        if resume_attempts >= max_resume_attempts {
+            return match error {
+                Some(error) => Err(Error::Http(error)),
+                None => Err(MediaError::IncompleteDownload.into()),
+            };
+        }
+
+        resume_attempts += 1;
+        warn!(offset, resume_attempts, "Media download interrupted, resuming after {delay:?}");
+
+        crate::sleep::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RESUME_DELAY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{partial_download_metadata_path, partial_download_path};
+
+    #[test]
+    fn test_partial_download_path() {
+        assert_eq!(
+            partial_download_path(Path::new("/tmp/media/image.png")),
+            Path::new("/tmp/media/image.png.part")
+        );
+        assert_eq!(partial_download_path(Path::new("video")), Path::new("video.part"));
+        assert_eq!(
+            partial_download_metadata_path(Path::new("/tmp/media/image.png.part")),
+            Path::new("/tmp/media/image.png.part.meta")
+        );
+    }
+}
@@ -19,10 +19,21 @@
 use std::io::Read;
 use std::time::Duration;
 #[cfg(not(target_family = "wasm"))]
-use std::{fmt, fs::File, path::Path};
+use std::{
+    fmt,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
 
+#[cfg(not(target_family = "wasm"))]
+use bytes::Bytes;
 use eyeball::SharedObservable;
 use futures_util::future::try_join;
+#[cfg(not(target_family = "wasm"))]
+use futures_util::{stream::BoxStream, StreamExt};
 use matrix_sdk_base::event_cache::store::media::IgnoreMediaRetentionPolicy;
 pub use matrix_sdk_base::{event_cache::store::media::MediaRetentionPolicy, media::*};
 use mime::Mime;
@@ -38,13 +49,27 @@ use ruma::{
 #[cfg(not(target_family = "wasm"))]
 use tempfile::{Builder as TempFileBuilder, NamedTempFile, TempDir};
 #[cfg(not(target_family = "wasm"))]
-use tokio::{fs::File as TokioFile, io::AsyncWriteExt};
+use tokio::{
+    fs::File as TokioFile,
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, ReadBuf},
+};
+#[cfg(not(target_family = "wasm"))]
+use tokio_util::io::ReaderStream;
 
+#[cfg(not(target_family = "wasm"))]
+pub use self::download::{DownloadMediaToFile, DownloadMediaToWriter};
+pub use self::scheduler::{MediaDownloadPriority, MediaScheduler, MediaSchedulerConfig};
+#[cfg(not(target_family = "wasm"))]
+use crate::client::futures::SendMediaUploadStream;
 use crate::{
     attachment::Thumbnail, client::futures::SendMediaUploadRequest, config::RequestConfig, Client,
-    Error, Result, TransmissionProgress,
+    Error, HttpError, Result, TransmissionProgress,
 };
 
+#[cfg(not(target_family = "wasm"))]
+mod download;
+mod scheduler;
+
 /// A conservative upload speed of 1Mbps
 const DEFAULT_UPLOAD_SPEED: u64 = 125_000;
 /// 5 min minimal upload request timeout, used to clamp the request timeout.
@@ -58,6 +83,9 @@ const MIN_UPLOAD_REQUEST_TIMEOUT: Duration = Duration::from_secs(60 * 5);
 // possible would be coming from the user themselves, which we consider a
 // non-threat.
 const LOCAL_MXC_SERVER_NAME: &str = "send-queue.localhost";
+/// The size of the chunks read from an [`UploadStream`] while it's uploaded.
+#[cfg(not(target_family = "wasm"))]
+pub(crate) const UPLOAD_STREAM_CHUNK_SIZE: usize = 64 * 1024;
 
 /// A high-level API to interact with the media API.
 #[derive(Debug, Clone)]
@@ -118,6 +146,129 @@ impl fmt::Display for PersistError {
     }
 }
 
+/// The content of a media to upload, read from a file or any async reader
+/// while it's being uploaded, instead of being held in memory.
+#[cfg(not(target_family = "wasm"))]
+pub struct UploadStream {
+    /// The reader for the content, limited to `length` bytes.
+    reader: Pin<Box<dyn AsyncRead + Send>>,
+    /// The number of bytes to upload.
+    length: u64,
+    /// The path of the file the content is read from, if any, allowing to
+    /// read it again to retry the upload.
+    path: Option<PathBuf>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl UploadStream {
+    /// Create an `UploadStream` that will upload the first `length` bytes read
+    /// from `reader`.
+    ///
+    /// The reader must be able to provide at least `length` bytes, otherwise
+    /// the upload will fail.
+    pub fn new(reader: impl AsyncRead + Send + 'static, length: u64) -> Self {
+        Self { reader: Box::pin(reader.take(length)), length, path: None }
+    }
+
+    /// Create an `UploadStream` that will upload the content of the file at
+    /// the given path.
+    ///
+    /// The length of the file is measured when it's opened. If the file is
+    /// modified so that its length doesn't match anymore while it's
+    /// uploaded, the upload will fail.
+    pub async fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = TokioFile::open(path).await?;
+        let length = file.metadata().await?.len();
+
+        Ok(Self {
+            reader: Box::pin(ExactLengthReader { inner: file, remaining: length }),
+            length,
+            path: Some(path.to_owned()),
+        })
+    }
+
+    /// The number of bytes that will be uploaded.
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    /// Whether there's nothing to upload.
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// The path of the file the content is read from, if it was created with
+    /// [`UploadStream::from_path()`].
+    pub(crate) fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Get the underlying reader.
+    pub(crate) fn into_reader(self) -> Pin<Box<dyn AsyncRead + Send>> {
+        self.reader
+    }
+
+    /// Turn this into a stream of chunks, to be used as a request body.
+    pub(crate) fn into_body(self) -> BoxStream<'static, io::Result<Bytes>> {
+        ReaderStream::with_capacity(self.reader, UPLOAD_STREAM_CHUNK_SIZE).boxed()
+    }
+}
+
+#[cfg(not(any(target_family = "wasm", tarpaulin_include)))]
+impl fmt::Debug for UploadStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UploadStream")
+            .field("length", &self.length)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A reader that fails if the inner reader doesn't provide exactly `remaining`
+/// bytes before reaching EOF.
+#[cfg(not(target_family = "wasm"))]
+struct ExactLengthReader<R> {
+    inner: R,
+    remaining: u64,
+}
+
+#[cfg(not(target_family = "wasm"))]
+impl<R: AsyncRead + Unpin> AsyncRead for ExactLengthReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let this = &mut *self;
+        let filled_before = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        let read = (buf.filled().len() - filled_before) as u64;
+
+        if read == 0 {
+            if this.remaining > 0 {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the file was shortened while it was uploaded",
+                )));
+            }
+        } else if read > this.remaining {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the file was extended while it was uploaded",
+            )));
+        } else {
+            this.remaining -= read;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
 /// A preallocated MXC URI created by [`Media::create_content_uri()`], and
 /// to be used with [`Media::upload_preallocated()`].
 #[derive(Debug)]
@@ -158,6 +309,35 @@ pub enum MediaError {
     /// Fetching the `max_upload_size` value from the homeserver failed.
     #[error("Fetching the `max_upload_size` value from the homeserver failed: {0}")]
     FetchMaxUploadSizeFailed(String),
+
+    /// The server didn't send the requested range of the media content, so
+    /// the download can't be resumed.
+    #[error("the server doesn't support resuming media downloads")]
+    RangeNotSupported,
+
+    /// The server sent a range of the media content that doesn't match the
+    /// requested one.
+    #[error("the server sent an unexpected range of the media content")]
+    UnexpectedRange,
+
+    /// The length of the media content changed while it was downloaded, so
+    /// the download can't be resumed.
+    #[error("the length of the media content changed during the download")]
+    ContentLengthChanged,
+
+    /// The connection was closed repeatedly before the whole media content
+    /// was downloaded.
+    #[error("the media download couldn't be completed")]
+    IncompleteDownload,
+
+    /// The encryption info of the media doesn't contain a SHA-256 hash.
+    #[error("the encryption info of the media doesn't contain a SHA-256 hash")]
+    MissingHash,
+
+    /// The SHA-256 hash of the downloaded media doesn't match the one of its
+    /// encryption info.
+    #[error("the hash of the downloaded media doesn't match")]
+    HashMismatch,
 }
 
 impl Media {
@@ -214,11 +394,74 @@ impl Media {
         SendMediaUploadRequest::new(request)
     }
 
+    /// Upload some media to the server, reading its content from the given
+    /// [`UploadStream`] while it's sent.
+    ///
+    /// Contrary to [`Media::upload()`], the content is never entirely loaded in
+    /// memory, which makes this suitable for large files. Since the content
+    /// can't be replayed, the request isn't retried if it fails, except if
+    /// the stream was created with [`UploadStream::from_path()`] and the
+    /// access token needed to be refreshed, in which case the file is read
+    /// again from the start.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_type` - The type of the media, this will be used as the
+    ///   content-type header.
+    ///
+    /// * `stream` - The content to be uploaded to the server.
+    ///
+    /// * `request_config` - Optional request configuration for the HTTP client,
+    ///   overriding the default. If not provided, a reasonable timeout value is
+    ///   inferred.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, media::UploadStream};
+    /// # use url::Url;
+    /// # use mime;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let mut client = Client::new(homeserver).await?;
+    /// let video = UploadStream::from_path("/home/example/my-cat.mp4").await?;
+    /// let content_type: mime::Mime = "video/mp4".parse()?;
+    ///
+    /// let response =
+    ///     client.media().upload_stream(&content_type, video, None).await?;
+    ///
+    /// println!("Cat URI: {}", response.content_uri);
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[cfg(not(target_family = "wasm"))]
+    pub fn upload_stream(
+        &self,
+        content_type: &Mime,
+        stream: UploadStream,
+        request_config: Option<RequestConfig>,
+    ) -> SendMediaUploadStream {
+        let length = stream.len();
+        let path = stream.path().map(ToOwned::to_owned);
+        SendMediaUploadStream::new(
+            self.client.clone(),
+            content_type.clone(),
+            stream.into_body(),
+            length,
+            request_config,
+        )
+        .with_reopen_path(path)
+    }
+
     /// Returns a reasonable upload timeout for an upload, based on the size of
     /// the data to be uploaded.
     pub(crate) fn reasonable_upload_timeout(data: &[u8]) -> Duration {
+        Self::reasonable_upload_timeout_for_length(data.len() as u64)
+    }
+
+    /// Returns a reasonable upload timeout for an upload of `length` bytes.
+    pub(crate) fn reasonable_upload_timeout_for_length(length: u64) -> Duration {
         std::cmp::max(
-            Duration::from_secs(data.len() as u64 / DEFAULT_UPLOAD_SPEED),
+            Duration::from_secs(length / DEFAULT_UPLOAD_SPEED),
             MIN_UPLOAD_REQUEST_TIMEOUT,
         )
     }
@@ -293,26 +536,177 @@ impl Media {
 
         let request_config = self.client.request_config().timeout(timeout);
 
-        if let Err(err) = self.client.send(request).with_request_config(request_config).await {
-            match err.client_api_error_kind() {
-                Some(ErrorKind::CannotOverwriteMedia) => {
-                    Err(Error::Media(MediaError::CannotOverwriteMedia))
-                }
+        self.client
+            .send(request)
+            .with_request_config(request_config)
+            .await
+            .map_err(Self::map_preallocated_upload_error)?;
 
-                // Unfortunately, the spec says a server will return 404 for either an expired MXC
-                // ID or a non-existing MXC ID. Do a best-effort guess to recognize an expired MXC
-                // ID based on the error string, which will work with Synapse (as of 2024-10-23).
-                Some(ErrorKind::Unknown) if err.to_string().contains("expired") => {
-                    Err(Error::Media(MediaError::ExpiredPreallocatedMxcUri))
-                }
+        Ok(())
+    }
+
+    /// Upload a chunk of the content of a preallocated MXC URI, starting at
+    /// `offset`, out of a content of `total_length` bytes.
+    ///
+    /// The chunk is sent with a `Content-Range` header, which the homeserver
+    /// must accept, see [`SendQueue::enable_resumable_uploads()`].
+    ///
+    /// [`SendQueue::enable_resumable_uploads()`]: crate::send_queue::SendQueue::enable_resumable_uploads
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) async fn upload_preallocated_chunk(
+        &self,
+        uri: &MxcUri,
+        content_type: &Mime,
+        chunk: Bytes,
+        offset: u64,
+        total_length: u64,
+    ) -> Result<()> {
+        let length = chunk.len() as u64;
+        debug_assert!(length > 0, "chunks of a resumable upload can't be empty");
+
+        let request = assign!(media::create_content_async::v3::Request::from_url(uri, Vec::new())?, {
+            content_type: Some(content_type.as_ref().to_owned()),
+        });
+
+        let content_range = format!("bytes {offset}-{}/{total_length}", offset + length - 1);
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::CONTENT_RANGE,
+            http::HeaderValue::from_str(&content_range)
+                .expect("a content range is a valid header value"),
+        );
+
+        let request_config = self
+            .client
+            .request_config()
+            .timeout(Self::reasonable_upload_timeout_for_length(length));
+
+        self.client
+            .send_streaming_inner(
+                request,
+                futures_util::stream::once(async { Ok(chunk) }).boxed(),
+                length,
+                headers,
+                Some(request_config),
+                Default::default(),
+            )
+            .await
+            .map_err(Self::map_preallocated_upload_error)?;
 
-                _ => Err(err.into()),
+        Ok(())
+    }
+
+    /// Convert an error returned when uploading the content of a preallocated
+    /// MXC URI.
+    fn map_preallocated_upload_error(err: HttpError) -> Error {
+        match err.client_api_error_kind() {
+            Some(ErrorKind::CannotOverwriteMedia) => Error::Media(MediaError::CannotOverwriteMedia),
+
+            // Unfortunately, the spec says a server will return 404 for either an expired MXC
+            // ID or a non-existing MXC ID. Do a best-effort guess to recognize an expired MXC
+            // ID based on the error string, which will work with Synapse (as of 2024-10-23).
+            Some(ErrorKind::Unknown) if err.to_string().contains("expired") => {
+                Error::Media(MediaError::ExpiredPreallocatedMxcUri)
             }
-        } else {
-            Ok(())
+
+            _ => err.into(),
         }
     }
 
+    /// Download the content of a media to the file at `path`, without
+    /// buffering it in memory.
+    ///
+    /// The content is downloaded next to `path`, in a file with the additional
+    /// `.part` extension, which is moved to, or decrypted into, `path` once
+    /// the download is complete. If the download is interrupted by a network
+    /// failure, it is resumed from where it stopped, with a `Range` request.
+    /// If the returned future fails or is dropped, calling this method again
+    /// with the same source and path resumes the download from the content
+    /// already in the `.part` file. The MXC URI and the length of the media
+    /// are stored next to it, in a `.part.meta` file, so that a `.part` file
+    /// with the content of another media is discarded instead of being
+    /// resumed. The download also starts over if the server can't send the
+    /// missing range of the content.
+    ///
+    /// If the media is encrypted, its SHA-256 hash is verified before it is
+    /// decrypted, and the `.part` file is deleted if it doesn't match.
+    ///
+    /// Contrary to [`Media::get_media_content()`], the media cache isn't used.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source of the media content.
+    ///
+    /// * `path` - The path of the destination file. It is overwritten if it
+    ///   already exists.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use futures_util::StreamExt;
+    /// # use matrix_sdk::{Client, ruma::events::room::MediaSource};
+    /// # async {
+    /// # let client: Client = unimplemented!();
+    /// # let source: MediaSource = unimplemented!();
+    /// let download = client.media().download_to_file(&source, "/tmp/video.mp4");
+    /// let mut progress = download.subscribe_to_progress();
+    ///
+    /// tokio::spawn(async move {
+    ///     while let Some(progress) = progress.next().await {
+    ///         println!(
+    ///             "Downloaded {} of {} bytes",
+    ///             progress.current, progress.total
+    ///         );
+    ///     }
+    /// });
+    ///
+    /// download.await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[cfg(not(target_family = "wasm"))]
+    pub fn download_to_file(
+        &self,
+        source: &MediaSource,
+        path: impl Into<std::path::PathBuf>,
+    ) -> DownloadMediaToFile {
+        DownloadMediaToFile::new(self.client.clone(), source.clone(), path.into())
+    }
+
+    /// Download the content of a media to the given writer, without buffering
+    /// it in memory.
+    ///
+    /// If the download is interrupted by a network failure, it is resumed from
+    /// where it stopped, with a `Range` request. Since the content that was
+    /// already written can't be taken back, the download fails if the server
+    /// doesn't support that.
+    ///
+    /// If the media is encrypted, it is decrypted on the fly and its SHA-256
+    /// hash is verified at the end.
+    ///
+    /// **Warning**: the decrypted content is written to the writer before the
+    /// hash of the encrypted content has been verified. It must not be used
+    /// before the returned future succeeds, and must be discarded if the
+    /// future fails. Use [`Media::download_to_file()`] to only get content
+    /// whose hash has been verified.
+    ///
+    /// Contrary to [`Media::get_media_content()`], the media cache isn't used.
+    ///
+    /// The returned future resolves to the writer, once all the content has
+    /// been written and flushed.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source of the media content.
+    ///
+    /// * `writer` - The destination of the media content.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn download_to_writer<W>(&self, source: &MediaSource, writer: W) -> DownloadMediaToWriter<W>
+    where
+        W: tokio::io::AsyncWrite + Unpin + Send + 'static,
+    {
+        DownloadMediaToWriter::new(self.client.clone(), source.clone(), writer)
+    }
+
     /// Gets a media file by copying it to a temporary location on disk.
     ///
     /// The file won't be encrypted even if it is encrypted on the server.
@@ -733,6 +1127,29 @@ impl Media {
         Ok((MediaSource::Plain(response.content_uri), thumbnail))
     }
 
+    /// Upload the file streamed from `stream` and the thumbnail, and return the
+    /// source information.
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) async fn upload_plain_stream_and_thumbnail(
+        &self,
+        content_type: &Mime,
+        stream: UploadStream,
+        thumbnail: Option<Thumbnail>,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Result<(MediaSource, Option<(MediaSource, Box<ThumbnailInfo>)>)> {
+        let upload_thumbnail = self.upload_thumbnail(thumbnail, send_progress.clone());
+
+        let upload_attachment = async move {
+            self.upload_stream(content_type, stream, None)
+                .with_send_progress_observable(send_progress)
+                .await
+        };
+
+        let (thumbnail, response) = try_join(upload_thumbnail, upload_attachment).await?;
+
+        Ok((MediaSource::Plain(response.content_uri), thumbnail))
+    }
+
     /// Uploads an unencrypted thumbnail to the media repository, and returns
     /// its source and extra information.
     async fn upload_thumbnail(
@@ -874,4 +1291,30 @@ mod tests {
         let source = MediaSource::Plain("https://server.local/nbvcxw".into());
         assert_matches!(Media::as_local_uri(&source), None);
     }
+
+    #[cfg(not(target_family = "wasm"))]
+    #[matrix_sdk_test::async_test]
+    async fn test_exact_length_reader() {
+        use std::io::Cursor;
+
+        use tokio::io::AsyncReadExt;
+
+        use super::ExactLengthReader;
+
+        // The expected length is read.
+        let mut reader = ExactLengthReader { inner: Cursor::new(b"hello"), remaining: 5 };
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello");
+
+        // The content is shorter than expected.
+        let mut reader = ExactLengthReader { inner: Cursor::new(b"hello"), remaining: 6 };
+        let err = reader.read_to_end(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        // The content is longer than expected.
+        let mut reader = ExactLengthReader { inner: Cursor::new(b"hello"), remaining: 4 };
+        let err = reader.read_to_end(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
 }
@@ -0,0 +1,624 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A scheduler coordinating the downloads of media contents.
+//!
+//! Downloads go through a queue ordered by [`MediaDownloadPriority`], so that
+//! the media the user is looking at is fetched before the media that might be
+//! shown soon, which is itself fetched before the media that is only
+//! prefetched. The number of concurrent downloads and the overall bandwidth
+//! can be capped with a [`MediaSchedulerConfig`], and identical requests are
+//! only sent once.
+
+use std::{cmp::Reverse, collections::HashMap, sync::Arc, time::Duration};
+
+use futures_util::{pin_mut, StreamExt};
+use matrix_sdk_base::{
+    media::{MediaEventContent, MediaFormat, MediaRequestParameters, UniqueKey},
+    sleep::sleep,
+};
+use matrix_sdk_common::{
+    executor::{spawn, AbortOnDrop, JoinHandleExt},
+    locks::Mutex as SyncMutex,
+};
+use ruma::{
+    events::{
+        media_preview_config::MediaPreviews,
+        room::{message::MessageType, MediaSource},
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent, SyncMessageLikeEvent,
+    },
+    time::Instant,
+};
+use tokio::sync::{oneshot, OnceCell};
+use tracing::{debug, trace, warn};
+
+use crate::{Client, Error, Result, Room};
+
+/// The priority of a media download in a [`MediaScheduler`].
+///
+/// Downloads with a higher priority are started first. Downloads with the same
+/// priority are started in the order they were requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MediaDownloadPriority {
+    /// The media isn't displayed and is only fetched ahead of time, e.g. the
+    /// thumbnails of the latest events in the room list.
+    Background,
+
+    /// The media isn't displayed yet but will likely be soon, e.g. because it
+    /// is right outside of the viewport.
+    LikelyVisible,
+
+    /// The media is currently displayed to the user.
+    Visible,
+}
+
+/// The configuration of a [`MediaScheduler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MediaSchedulerConfig {
+    /// The maximum number of media contents downloaded at the same time.
+    ///
+    /// When it's more than 1, one of the slots is kept for the downloads with
+    /// a priority higher than [`MediaDownloadPriority::Background`], so that
+    /// prefetching never makes them wait for a slot.
+    ///
+    /// Defaults to 4. A value of 0 is treated as 1.
+    pub max_concurrent_downloads: usize,
+
+    /// The maximum average bandwidth used by the downloads, in bytes per
+    /// second.
+    ///
+    /// The limit is enforced between downloads: once a media content was
+    /// downloaded, the next download is delayed for as long as it would have
+    /// taken to download it at this rate.
+    ///
+    /// Defaults to `None`, meaning that the bandwidth isn't limited.
+    pub max_bandwidth: Option<u64>,
+}
+
+impl MediaSchedulerConfig {
+    /// Create a [`MediaSchedulerConfig`] with the default values.
+    pub fn new() -> Self {
+        Self { max_concurrent_downloads: 4, max_bandwidth: None }
+    }
+
+    /// Set the maximum number of media contents downloaded at the same time.
+    pub fn with_max_concurrent_downloads(self, max_concurrent_downloads: usize) -> Self {
+        Self { max_concurrent_downloads, ..self }
+    }
+
+    /// Set the maximum average bandwidth used by the downloads, in bytes per
+    /// second.
+    pub fn with_max_bandwidth(self, max_bandwidth: Option<u64>) -> Self {
+        Self { max_bandwidth, ..self }
+    }
+}
+
+impl Default for MediaSchedulerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A scheduler coordinating the downloads of media contents.
+///
+/// All the downloads requested through the same scheduler (or its clones)
+/// share the same queue, concurrency and bandwidth limits. The downloaded
+/// contents are stored in the media cache, like with
+/// [`Media::get_media_content()`] when the cache is used.
+///
+/// [`Media::get_media_content()`]: super::Media::get_media_content
+#[derive(Debug, Clone)]
+pub struct MediaScheduler {
+    inner: Arc<MediaSchedulerInner>,
+}
+
+#[derive(Debug)]
+struct MediaSchedulerInner {
+    client: Client,
+
+    /// The state of the queue.
+    state: SyncMutex<SchedulerState>,
+
+    /// The latest known media previews policy of the user, shared with the
+    /// task keeping it up to date.
+    media_previews: Arc<SyncMutex<Option<MediaPreviews>>>,
+
+    /// The task observing the media previews policy, started on the first
+    /// prefetch.
+    media_previews_task: OnceCell<AbortOnDrop<()>>,
+}
+
+#[derive(Debug)]
+struct SchedulerState {
+    config: MediaSchedulerConfig,
+
+    /// The number of downloads currently holding a slot.
+    running: usize,
+
+    /// The number of downloads that got their slot with the
+    /// [`MediaDownloadPriority::Background`] priority.
+    running_background: usize,
+
+    /// The sequence number given to the next download, to keep the order of
+    /// downloads with the same priority.
+    next_sequence: u64,
+
+    /// The time before which no download should start, to respect the
+    /// bandwidth limit.
+    next_start: Option<Instant>,
+
+    /// The queued and running downloads, by unique key of their request.
+    downloads: HashMap<String, PendingDownload>,
+}
+
+/// The result sent to the callers waiting for a download.
+type DownloadResult = Result<Vec<u8>>;
+
+#[derive(Debug)]
+struct PendingDownload {
+    priority: MediaDownloadPriority,
+    sequence: u64,
+
+    /// The sender used to give a slot to the download, or `None` if it
+    /// already has one.
+    start: Option<oneshot::Sender<()>>,
+
+    /// Whether the download got its slot with the
+    /// [`MediaDownloadPriority::Background`] priority.
+    started_in_background: bool,
+
+    /// The sender used to cancel the download once it has started, if nobody
+    /// wants its result anymore.
+    cancel: Option<oneshot::Sender<()>>,
+
+    /// The callers waiting for the result of the download.
+    waiters: Vec<oneshot::Sender<DownloadResult>>,
+
+    /// Whether the download was prefetched, in which case it should happen
+    /// even if nobody is waiting for it.
+    prefetch: bool,
+}
+
+impl PendingDownload {
+    /// Whether the result of this download is still wanted.
+    fn is_wanted(&self) -> bool {
+        self.prefetch || self.waiters.iter().any(|waiter| !waiter.is_closed())
+    }
+}
+
+impl SchedulerState {
+    /// Give the free slots to the queued downloads with the highest priority.
+    fn start_next(&mut self) {
+        let max_concurrent_downloads = self.config.max_concurrent_downloads.max(1);
+
+        // Keep a slot for the media that is displayed, or about to be, so that
+        // prefetching doesn't make it wait.
+        let max_background_downloads = if max_concurrent_downloads > 1 {
+            max_concurrent_downloads - 1
+        } else {
+            max_concurrent_downloads
+        };
+
+        while self.running < max_concurrent_downloads {
+            let can_start_background = self.running_background < max_background_downloads;
+
+            let Some((key, start, in_background)) = self
+                .downloads
+                .iter_mut()
+                .filter(|(_, download)| {
+                    download.start.is_some()
+                        && (can_start_background
+                            || download.priority > MediaDownloadPriority::Background)
+                })
+                .max_by_key(|(_, download)| (download.priority, Reverse(download.sequence)))
+                .and_then(|(key, download)| {
+                    let in_background = download.priority == MediaDownloadPriority::Background;
+                    download.started_in_background = in_background;
+                    Some((key.clone(), download.start.take()?, in_background))
+                })
+            else {
+                break;
+            };
+
+            if start.send(()).is_ok() {
+                self.running += 1;
+                if in_background {
+                    self.running_background += 1;
+                }
+            } else {
+                // The task of the download is gone, it will never run.
+                self.downloads.remove(&key);
+            }
+        }
+    }
+
+    /// The delay to wait before starting a download, to respect the bandwidth
+    /// limit.
+    fn bandwidth_delay(&self) -> Option<Duration> {
+        self.config.max_bandwidth?;
+        let next_start = self.next_start?;
+        let now = Instant::now();
+        (next_start > now).then(|| next_start - now)
+    }
+
+    /// Account for a download of the given size in the bandwidth limit.
+    fn record_transfer(&mut self, bytes: usize) {
+        let Some(max_bandwidth) = self.config.max_bandwidth.filter(|bandwidth| *bandwidth > 0)
+        else {
+            return;
+        };
+
+        let now = Instant::now();
+        let base = self.next_start.filter(|next_start| *next_start > now).unwrap_or(now);
+        self.next_start = Some(base + Duration::from_secs_f64(bytes as f64 / max_bandwidth as f64));
+    }
+}
+
+impl MediaScheduler {
+    /// Create a new [`MediaScheduler`] for the given client.
+    pub fn new(client: Client, config: MediaSchedulerConfig) -> Self {
+        Self {
+            inner: Arc::new(MediaSchedulerInner {
+                client,
+                state: SyncMutex::new(SchedulerState {
+                    config,
+                    running: 0,
+                    running_background: 0,
+                    next_sequence: 0,
+                    next_start: None,
+                    downloads: HashMap::new(),
+                }),
+                media_previews: Default::default(),
+                media_previews_task: OnceCell::new(),
+            }),
+        }
+    }
+
+    /// Get the current configuration of this scheduler.
+    pub fn config(&self) -> MediaSchedulerConfig {
+        self.inner.state.lock().config
+    }
+
+    /// Change the configuration of this scheduler.
+    ///
+    /// The downloads that were already started are not interrupted.
+    pub fn set_config(&self, config: MediaSchedulerConfig) {
+        let mut state = self.inner.state.lock();
+        state.config = config;
+        state.start_next();
+    }
+
+    /// Get a media file's content, downloading it with the given priority if
+    /// it isn't in the media cache.
+    ///
+    /// If the same media content is already queued or being downloaded, no
+    /// new request is sent and the result of that download is returned. If
+    /// it is still queued with a lower priority, its priority is raised.
+    ///
+    /// Dropping the returned future before it resolves cancels the download,
+    /// whether it has started or not, unless another caller still wants it,
+    /// or it was prefetched.
+    pub async fn get_media_content(
+        &self,
+        request: &MediaRequestParameters,
+        priority: MediaDownloadPriority,
+    ) -> Result<Vec<u8>> {
+        if let Some(content) = self.cached_content(request).await? {
+            return Ok(content);
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        self.enqueue(request, priority, Some(sender), false);
+
+        let mut waiter =
+            DownloadWaiter { inner: self.inner.clone(), key: request.unique_key(), receiver };
+
+        // The sender is only dropped without a result if the download task was
+        // aborted.
+        (&mut waiter.receiver).await.unwrap_or(Err(Error::ConcurrentRequestFailed))
+    }
+
+    /// Download a media file's content in the background, so that it is in
+    /// the media cache when it is needed.
+    ///
+    /// The download has the [`MediaDownloadPriority::Background`] priority, and
+    /// only happens if the media previews policy of the user allows it for the
+    /// given room:
+    ///
+    /// - With [`MediaPreviews::Off`], nothing is prefetched.
+    /// - With [`MediaPreviews::Private`], nothing is prefetched in public
+    ///   rooms.
+    /// - With [`MediaPreviews::On`], or when the user has no policy, the media
+    ///   is always prefetched.
+    ///
+    /// Returns `true` if the download was queued.
+    pub async fn prefetch(&self, request: &MediaRequestParameters, room: &Room) -> Result<bool> {
+        if !self.allows_prefetch(room).await? {
+            trace!(room_id = ?room.room_id(), "media previews policy forbids prefetching");
+            return Ok(false);
+        }
+
+        if self.cached_content(request).await?.is_some() {
+            return Ok(false);
+        }
+
+        self.enqueue(request, MediaDownloadPriority::Background, None, true);
+        Ok(true)
+    }
+
+    /// Prefetch the thumbnail of the latest event of the given room, if it is
+    /// a media message with a thumbnail.
+    ///
+    /// See [`MediaScheduler::prefetch()`] for the conditions under which the
+    /// thumbnail is downloaded.
+    ///
+    /// Returns `true` if the download was queued.
+    pub async fn prefetch_latest_event_thumbnail(&self, room: &Room) -> Result<bool> {
+        let Some(source) = room.latest_event().and_then(|latest_event| {
+            let event = latest_event.event().raw().deserialize().ok()?;
+            latest_event_thumbnail_source(event)
+        }) else {
+            return Ok(false);
+        };
+
+        self.prefetch(&MediaRequestParameters { source, format: MediaFormat::File }, room).await
+    }
+
+    /// Get the content of the given request from the media cache, if any.
+    async fn cached_content(&self, request: &MediaRequestParameters) -> Result<Option<Vec<u8>>> {
+        Ok(self.inner.client.event_cache_store().lock().await?.get_media_content(request).await?)
+    }
+
+    /// Queue the download of the given request, or join the download of the
+    /// same request if there is one.
+    fn enqueue(
+        &self,
+        request: &MediaRequestParameters,
+        priority: MediaDownloadPriority,
+        waiter: Option<oneshot::Sender<DownloadResult>>,
+        prefetch: bool,
+    ) {
+        let key = request.unique_key();
+        let mut state = self.inner.state.lock();
+
+        if let Some(download) = state.downloads.get_mut(&key) {
+            trace!(?key, ?priority, "joining an existing media download");
+
+            download.waiters.extend(waiter);
+            download.prefetch |= prefetch;
+
+            if priority > download.priority {
+                download.priority = priority;
+            }
+        } else {
+            debug!(?key, ?priority, "queuing a media download");
+
+            let (start_sender, start_receiver) = oneshot::channel();
+            let (cancel_sender, cancel_receiver) = oneshot::channel();
+            let sequence = state.next_sequence;
+            state.next_sequence += 1;
+
+            state.downloads.insert(
+                key.clone(),
+                PendingDownload {
+                    priority,
+                    sequence,
+                    start: Some(start_sender),
+                    started_in_background: false,
+                    cancel: Some(cancel_sender),
+                    waiters: waiter.into_iter().collect(),
+                    prefetch,
+                },
+            );
+
+            let inner = self.inner.clone();
+            let request = request.clone();
+            spawn(async move {
+                inner.run_download(key, request, start_receiver, cancel_receiver).await;
+            });
+        }
+
+        state.start_next();
+    }
+
+    /// Whether the media previews policy of the user allows prefetching media
+    /// in the given room.
+    async fn allows_prefetch(&self, room: &Room) -> Result<bool> {
+        self.inner
+            .media_previews_task
+            .get_or_try_init(|| async {
+                let (initial, stream) =
+                    self.inner.client.account().observe_media_preview_config().await?;
+
+                let media_previews = self.inner.media_previews.clone();
+                *media_previews.lock() = initial.and_then(|content| content.media_previews);
+
+                Ok::<_, Error>(
+                    spawn(async move {
+                        pin_mut!(stream);
+
+                        while let Some(content) = stream.next().await {
+                            *media_previews.lock() = content.media_previews;
+                        }
+                    })
+                    .abort_on_drop(),
+                )
+            })
+            .await?;
+
+        let media_previews = self.inner.media_previews.lock().clone();
+
+        Ok(match media_previews {
+            Some(MediaPreviews::Off) => false,
+            Some(MediaPreviews::Private) => !room.is_public().unwrap_or(false),
+            _ => true,
+        })
+    }
+}
+
+impl MediaSchedulerInner {
+    /// Wait for a slot and download the given request, then share the result
+    /// with everyone waiting for it.
+    ///
+    /// The download is interrupted if `cancel` resolves first.
+    async fn run_download(
+        &self,
+        key: String,
+        request: MediaRequestParameters,
+        start: oneshot::Receiver<()>,
+        cancel: oneshot::Receiver<()>,
+    ) {
+        if start.await.is_err() {
+            // The download was removed from the queue before getting a slot.
+            return;
+        }
+
+        let is_wanted = self.state.lock().downloads.get(&key).is_some_and(|d| d.is_wanted());
+
+        let result = if is_wanted {
+            let download = async {
+                let delay = self.state.lock().bandwidth_delay();
+                if let Some(delay) = delay {
+                    trace!(
+                        ?key,
+                        ?delay,
+                        "delaying a media download to respect the bandwidth limit"
+                    );
+                    sleep(delay).await;
+                }
+
+                let result = self.client.media().get_media_content(&request, true).await;
+
+                if let Ok(content) = &result {
+                    self.state.lock().record_transfer(content.len());
+                }
+
+                result
+            };
+
+            tokio::select! {
+                result = download => Some(result),
+                _ = cancel => {
+                    trace!(?key, "nobody is waiting for the media download anymore, cancelling it");
+                    None
+                }
+            }
+        } else {
+            trace!(?key, "nobody is waiting for the media download anymore, skipping it");
+            None
+        };
+
+        let download = {
+            let mut state = self.state.lock();
+            let download = state.downloads.remove(&key);
+            state.running -= 1;
+            if download.as_ref().is_some_and(|download| download.started_in_background) {
+                state.running_background -= 1;
+            }
+            state.start_next();
+            download
+        };
+
+        let (Some(download), Some(result)) = (download, result) else {
+            return;
+        };
+
+        match result {
+            Ok(content) => {
+                for waiter in download.waiters {
+                    let _ = waiter.send(Ok(content.clone()));
+                }
+            }
+
+            Err(error) => {
+                warn!(?key, "couldn't download media content: {error}");
+
+                // The actual error goes to the first caller still waiting, the other ones
+                // only learn that the download failed.
+                let mut error = Some(error);
+
+                for waiter in download.waiters {
+                    let result = Err(error.take().unwrap_or(Error::ConcurrentRequestFailed));
+
+                    if let Err(Err(unsent)) = waiter.send(result) {
+                        if !matches!(unsent, Error::ConcurrentRequestFailed) {
+                            error = Some(unsent);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl MediaSchedulerInner {
+    /// Cancel the download of the given key if nobody wants its result
+    /// anymore.
+    fn cancel_if_unwanted(&self, key: &str) {
+        let mut state = self.state.lock();
+
+        let Some(download) = state.downloads.get_mut(key) else {
+            return;
+        };
+
+        if download.is_wanted() {
+            return;
+        }
+
+        if download.start.is_some() {
+            // The download hasn't got a slot yet, its task stops when it's removed from the
+            // queue.
+            trace!(?key, "nobody is waiting for the media download anymore, unqueuing it");
+            state.downloads.remove(key);
+        } else if let Some(cancel) = download.cancel.take() {
+            let _ = cancel.send(());
+        }
+    }
+}
+
+/// A caller waiting for the result of a download, which cancels the download
+/// when it's dropped if nobody else wants its result.
+struct DownloadWaiter {
+    inner: Arc<MediaSchedulerInner>,
+    key: String,
+    receiver: oneshot::Receiver<DownloadResult>,
+}
+
+impl Drop for DownloadWaiter {
+    fn drop(&mut self) {
+        // Close the receiver first, so this caller doesn't count as waiting for the
+        // download anymore.
+        self.receiver.close();
+        self.inner.cancel_if_unwanted(&self.key);
+    }
+}
+
+/// Get the source of the thumbnail of the given latest event, if it is a media
+/// message with a thumbnail.
+fn latest_event_thumbnail_source(event: AnySyncTimelineEvent) -> Option<MediaSource> {
+    let AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+        SyncMessageLikeEvent::Original(event),
+    )) = event
+    else {
+        return None;
+    };
+
+    match event.content.msgtype {
+        MessageType::Image(content) => content.thumbnail_source(),
+        MessageType::Video(content) => content.thumbnail_source(),
+        MessageType::File(content) => content.thumbnail_source(),
+        _ => None,
+    }
+}
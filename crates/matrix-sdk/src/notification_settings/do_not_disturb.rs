@@ -0,0 +1,592 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Time-based notification suppression, aka “do not disturb” or “quiet hours”.
+//!
+//! The settings are stored in the global account data of the user, so that
+//! they are shared by all the clients of the user. They are evaluated locally
+//! by each client, see
+//! [`NotificationSettings::should_show_notification`][should_show].
+//!
+//! [should_show]: super::NotificationSettings::should_show_notification
+
+use std::{collections::BTreeSet, time::Duration};
+
+use chrono::{DateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use ruma::{
+    events::macros::EventContent, push::Action, MilliSecondsSinceUnixEpoch, OwnedUserId, UInt,
+    UserId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::Room;
+
+/// The number of minutes in a day.
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// A day of the week.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Weekday {
+    /// Monday.
+    Monday,
+    /// Tuesday.
+    Tuesday,
+    /// Wednesday.
+    Wednesday,
+    /// Thursday.
+    Thursday,
+    /// Friday.
+    Friday,
+    /// Saturday.
+    Saturday,
+    /// Sunday.
+    Sunday,
+}
+
+impl Weekday {
+    /// All the days of the week, starting on Monday.
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    /// Get the day of the week of a day, counted in days since the Unix epoch.
+    fn from_days_since_epoch(days: i64) -> Self {
+        // The Unix epoch, 1970-01-01, was a Thursday.
+        Self::ALL[(days + 3).rem_euclid(7) as usize]
+    }
+}
+
+/// An error when creating a [`DoNotDisturbSchedule`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DoNotDisturbScheduleError {
+    /// The time of day isn't valid, i.e. the hours aren't lower than 24 or the
+    /// minutes aren't lower than 60.
+    #[error("invalid time of day: {hours:02}:{minutes:02}")]
+    InvalidTime {
+        /// The hours of the time.
+        hours: u8,
+        /// The minutes of the time.
+        minutes: u8,
+    },
+
+    /// The minute of the day isn't lower than 1440.
+    #[error("invalid minute of the day: {0}")]
+    InvalidMinute(u16),
+}
+
+/// A weekly time range during which notifications are suppressed.
+///
+/// The range starts at [`start_minute`](Self::start_minute) and ends at
+/// [`end_minute`](Self::end_minute), both counted in minutes since midnight,
+/// in the time zone of the [`DoNotDisturbEventContent`]. If the end is lower
+/// than or equal to the start, the range ends on the next day, e.g. from 22:00
+/// to 07:00.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "RawDoNotDisturbSchedule")]
+pub struct DoNotDisturbSchedule {
+    /// The days on which the range starts.
+    pub days: BTreeSet<Weekday>,
+
+    /// The start of the range, in minutes since midnight.
+    start_minute: u16,
+
+    /// The end of the range, in minutes since midnight.
+    end_minute: u16,
+}
+
+impl DoNotDisturbSchedule {
+    /// Create a new schedule, from `start` to `end`, expressed as
+    /// `(hours, minutes)`.
+    ///
+    /// Returns an error if one of the times isn't a valid time of day.
+    pub fn new(
+        days: impl IntoIterator<Item = Weekday>,
+        start: (u8, u8),
+        end: (u8, u8),
+    ) -> Result<Self, DoNotDisturbScheduleError> {
+        Self::from_minutes(days, Self::minute_of_day(start)?, Self::minute_of_day(end)?)
+    }
+
+    /// Create a new schedule, from `start_minute` to `end_minute`, counted in
+    /// minutes since midnight.
+    ///
+    /// Returns an error if one of the minutes isn't lower than 1440, the number
+    /// of minutes in a day.
+    pub fn from_minutes(
+        days: impl IntoIterator<Item = Weekday>,
+        start_minute: u16,
+        end_minute: u16,
+    ) -> Result<Self, DoNotDisturbScheduleError> {
+        for minute in [start_minute, end_minute] {
+            if i64::from(minute) >= MINUTES_PER_DAY {
+                return Err(DoNotDisturbScheduleError::InvalidMinute(minute));
+            }
+        }
+
+        Ok(Self { days: days.into_iter().collect(), start_minute, end_minute })
+    }
+
+    /// Convert a time of day, expressed as `(hours, minutes)`, to minutes
+    /// since midnight.
+    fn minute_of_day((hours, minutes): (u8, u8)) -> Result<u16, DoNotDisturbScheduleError> {
+        if hours >= 24 || minutes >= 60 {
+            return Err(DoNotDisturbScheduleError::InvalidTime { hours, minutes });
+        }
+
+        Ok(u16::from(hours) * 60 + u16::from(minutes))
+    }
+
+    /// The start of the range, in minutes since midnight.
+    pub fn start_minute(&self) -> u16 {
+        self.start_minute
+    }
+
+    /// The end of the range, in minutes since midnight.
+    pub fn end_minute(&self) -> u16 {
+        self.end_minute
+    }
+
+    /// The duration of the range, in minutes.
+    fn duration_minutes(&self) -> i64 {
+        let start = i64::from(self.start_minute);
+        let end = i64::from(self.end_minute);
+
+        if end > start {
+            end - start
+        } else {
+            end + MINUTES_PER_DAY - start
+        }
+    }
+
+    /// Iterate over the `(start, end)` ranges, in local minutes since the Unix
+    /// epoch, of the occurrences of this schedule starting between `first_day`
+    /// and `last_day` included.
+    fn occurrences(&self, first_day: i64, last_day: i64) -> impl Iterator<Item = (i64, i64)> + '_ {
+        (first_day..=last_day)
+            .filter(|day| self.days.contains(&Weekday::from_days_since_epoch(*day)))
+            .map(|day| {
+                let start = day * MINUTES_PER_DAY + i64::from(self.start_minute);
+                (start, start + self.duration_minutes())
+            })
+    }
+}
+
+/// The serialized form of a [`DoNotDisturbSchedule`], which is validated when
+/// it is deserialized.
+#[derive(Deserialize)]
+struct RawDoNotDisturbSchedule {
+    days: BTreeSet<Weekday>,
+    start_minute: u16,
+    end_minute: u16,
+}
+
+impl TryFrom<RawDoNotDisturbSchedule> for DoNotDisturbSchedule {
+    type Error = DoNotDisturbScheduleError;
+
+    fn try_from(raw: RawDoNotDisturbSchedule) -> Result<Self, Self::Error> {
+        Self::from_minutes(raw.days, raw.start_minute, raw.end_minute)
+    }
+}
+
+/// The exceptions to the “do not disturb” mode, i.e. the notifications that
+/// are shown anyway.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DoNotDisturbExceptions {
+    /// Show the notifications from direct messages.
+    #[serde(default)]
+    pub direct_messages: bool,
+
+    /// Show the notifications for mentions, i.e. highlights.
+    #[serde(default)]
+    pub mentions: bool,
+
+    /// Show the notifications from these users.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub users: BTreeSet<OwnedUserId>,
+}
+
+/// The content of the global account data event storing the “do not disturb”
+/// settings.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, EventContent)]
+#[ruma_event(type = "org.matrix.rust_sdk.do_not_disturb", kind = GlobalAccountData)]
+pub struct DoNotDisturbEventContent {
+    /// Whether the schedules are enabled.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// The weekly time ranges during which notifications are suppressed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<DoNotDisturbSchedule>,
+
+    /// Suppress notifications until this time, whatever the schedules are,
+    /// e.g. to enable the “do not disturb” mode manually for an hour.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<MilliSecondsSinceUnixEpoch>,
+
+    /// The time zone of the schedules, as an IANA time zone name, e.g.
+    /// `Europe/Paris`.
+    ///
+    /// The offset of the time zone from UTC is computed for each evaluation,
+    /// so the schedules follow the daylight saving time changes. If it is
+    /// unset or unknown, the schedules are evaluated in UTC.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+
+    /// The notifications that are shown anyway.
+    #[serde(default)]
+    pub exceptions: DoNotDisturbExceptions,
+}
+
+impl DoNotDisturbEventContent {
+    /// The time zone of the schedules, if it is set and known.
+    fn time_zone(&self) -> Option<Tz> {
+        let name = self.timezone.as_deref()?;
+        let time_zone = name.parse().ok();
+
+        if time_zone.is_none() {
+            warn!("Unknown time zone `{name}` in the do not disturb settings, using UTC");
+        }
+
+        time_zone
+    }
+
+    /// Get the offset of the time zone from UTC at the given UTC minute since
+    /// the Unix epoch, in minutes.
+    fn utc_offset_minutes_at(time_zone: Option<Tz>, utc_minutes: i64) -> i64 {
+        let Some(time_zone) = time_zone else {
+            return 0;
+        };
+        let Some(datetime) = DateTime::from_timestamp(utc_minutes * 60, 0) else {
+            return 0;
+        };
+
+        let offset_seconds =
+            time_zone.offset_from_utc_datetime(&datetime.naive_utc()).fix().local_minus_utc();
+        i64::from(offset_seconds) / 60
+    }
+
+    /// Convert a timestamp to local minutes since the Unix epoch.
+    fn local_minutes(time_zone: Option<Tz>, ts: MilliSecondsSinceUnixEpoch) -> i64 {
+        let utc_minutes = i64::from(ts.get()) / 60_000;
+        utc_minutes + Self::utc_offset_minutes_at(time_zone, utc_minutes)
+    }
+
+    /// Convert local minutes since the Unix epoch to a timestamp.
+    ///
+    /// Local times that are skipped or repeated by a change of offset, e.g. for
+    /// daylight saving time, are resolved with one of the offsets around the
+    /// change.
+    fn timestamp(time_zone: Option<Tz>, local_minutes: i64) -> MilliSecondsSinceUnixEpoch {
+        // The offset depends on the UTC time we are looking for, so start from an
+        // approximation and correct it once.
+        let approximation = local_minutes - Self::utc_offset_minutes_at(time_zone, local_minutes);
+        let utc_minutes = local_minutes - Self::utc_offset_minutes_at(time_zone, approximation);
+
+        let ms = utc_minutes.max(0) * 60_000;
+        MilliSecondsSinceUnixEpoch(UInt::try_from(ms).unwrap_or(UInt::MAX))
+    }
+
+    /// Whether the “do not disturb” mode is active at the given time.
+    pub fn is_active_at(&self, now: MilliSecondsSinceUnixEpoch) -> bool {
+        if self.until.is_some_and(|until| now < until) {
+            return true;
+        }
+
+        if !self.enabled {
+            return false;
+        }
+
+        let now = Self::local_minutes(self.time_zone(), now);
+        let today = now.div_euclid(MINUTES_PER_DAY);
+
+        // A range starting yesterday may still be running.
+        self.schedules.iter().any(|schedule| {
+            schedule.occurrences(today - 1, today).any(|(start, end)| start <= now && now < end)
+        })
+    }
+
+    /// Get the next time after `now` at which the “do not disturb” mode may
+    /// change, if any.
+    pub fn next_change_after(
+        &self,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Option<MilliSecondsSinceUnixEpoch> {
+        let until = self.until.filter(|until| *until > now);
+
+        let next_schedule_change = if self.enabled {
+            let time_zone = self.time_zone();
+            let local_now = Self::local_minutes(time_zone, now);
+            let today = local_now.div_euclid(MINUTES_PER_DAY);
+
+            self.schedules
+                .iter()
+                .flat_map(|schedule| schedule.occurrences(today - 1, today + 7))
+                .flat_map(|(start, end)| [start, end])
+                .filter(|minute| *minute > local_now)
+                .min()
+                .map(|minute| Self::timestamp(time_zone, minute))
+        } else {
+            None
+        };
+
+        match (until, next_schedule_change) {
+            (Some(until), Some(next)) => Some(until.min(next)),
+            (until, next) => until.or(next),
+        }
+    }
+
+    /// Whether a notification must be shown at the given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    /// * `sender` - The sender of the notifying event.
+    /// * `is_direct` - Whether the event was sent in a direct message.
+    /// * `is_mention` - Whether the event is a mention, i.e. a highlight.
+    pub fn should_show_notification_at(
+        &self,
+        now: MilliSecondsSinceUnixEpoch,
+        sender: &UserId,
+        is_direct: bool,
+        is_mention: bool,
+    ) -> bool {
+        if !self.is_active_at(now) {
+            return true;
+        }
+
+        (self.exceptions.direct_messages && is_direct)
+            || (self.exceptions.mentions && is_mention)
+            || self.exceptions.users.contains(sender)
+    }
+
+    /// Whether a notification for an event in the given room must be shown
+    /// now.
+    ///
+    /// This doesn't evaluate the push rules: `actions` are the push actions
+    /// of the event, and are only used to know whether the event is a mention.
+    ///
+    /// If the room can't be checked, the notification is shown: it is better
+    /// to show an unwanted notification than to lose a wanted one.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - The room the event was sent in.
+    /// * `sender` - The sender of the event.
+    /// * `actions` - The push actions of the event.
+    pub async fn should_show_notification(
+        &self,
+        room: &Room,
+        sender: &UserId,
+        actions: &[Action],
+    ) -> bool {
+        let now = MilliSecondsSinceUnixEpoch::now();
+
+        if !self.is_active_at(now) {
+            return true;
+        }
+
+        let is_mention = actions.iter().any(Action::is_highlight);
+        let is_direct = match room.is_direct().await {
+            Ok(is_direct) => is_direct,
+            Err(error) => {
+                warn!(
+                    room_id = ?room.room_id(),
+                    "Couldn't check whether the room is direct, showing the notification: {error}"
+                );
+                return true;
+            }
+        };
+
+        self.should_show_notification_at(now, sender, is_direct, is_mention)
+    }
+
+    /// Get the duration from `now` until the next change of the “do not
+    /// disturb” mode, if any.
+    pub(crate) fn duration_until_next_change(
+        &self,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Option<Duration> {
+        self.next_change_after(now).map(|next| {
+            Duration::from_millis(u64::from(next.get()).saturating_sub(u64::from(now.get())))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_matches;
+    use ruma::{owned_user_id, user_id, MilliSecondsSinceUnixEpoch};
+    use serde_json::json;
+
+    use super::{
+        DoNotDisturbEventContent, DoNotDisturbSchedule, DoNotDisturbScheduleError, Weekday,
+    };
+
+    /// 2024-01-01 was a Monday, at 00:00 UTC.
+    const MONDAY: u64 = 1_704_067_200_000;
+    const HOUR: u64 = 60 * 60 * 1000;
+
+    fn ts(ms: u64) -> MilliSecondsSinceUnixEpoch {
+        MilliSecondsSinceUnixEpoch(ms.try_into().unwrap())
+    }
+
+    fn nights() -> DoNotDisturbEventContent {
+        DoNotDisturbEventContent {
+            enabled: true,
+            schedules: vec![DoNotDisturbSchedule::new([Weekday::Monday], (22, 0), (7, 0)).unwrap()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_weekday() {
+        assert_eq!(Weekday::from_days_since_epoch(0), Weekday::Thursday);
+        assert_eq!(Weekday::from_days_since_epoch((MONDAY / (24 * HOUR)) as i64), Weekday::Monday);
+        assert_eq!(Weekday::from_days_since_epoch(-1), Weekday::Wednesday);
+    }
+
+    #[test]
+    fn test_invalid_schedule() {
+        assert_matches!(
+            DoNotDisturbSchedule::new([Weekday::Monday], (24, 0), (7, 0)),
+            Err(DoNotDisturbScheduleError::InvalidTime { hours: 24, minutes: 0 })
+        );
+        assert_matches!(
+            DoNotDisturbSchedule::new([Weekday::Monday], (22, 0), (7, 60)),
+            Err(DoNotDisturbScheduleError::InvalidTime { hours: 7, minutes: 60 })
+        );
+        assert_matches!(
+            DoNotDisturbSchedule::from_minutes([Weekday::Monday], 1320, 1440),
+            Err(DoNotDisturbScheduleError::InvalidMinute(1440))
+        );
+
+        let schedule = DoNotDisturbSchedule::from_minutes([Weekday::Monday], 1320, 1439).unwrap();
+        assert_eq!(schedule.start_minute(), 1320);
+        assert_eq!(schedule.end_minute(), 1439);
+    }
+
+    #[test]
+    fn test_schedule_deserialization() {
+        let schedule = DoNotDisturbSchedule::new([Weekday::Monday], (22, 0), (7, 0)).unwrap();
+        let json = serde_json::to_value(&schedule).unwrap();
+        assert_eq!(json, json!({ "days": ["monday"], "start_minute": 1320, "end_minute": 420 }));
+        assert_eq!(serde_json::from_value::<DoNotDisturbSchedule>(json).unwrap(), schedule);
+
+        // Minutes beyond the end of the day are rejected.
+        let res = serde_json::from_value::<DoNotDisturbSchedule>(
+            json!({ "days": ["monday"], "start_minute": 1320, "end_minute": 1440 }),
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_schedule_across_midnight() {
+        let content = nights();
+
+        assert!(!content.is_active_at(ts(MONDAY + 21 * HOUR)));
+        assert!(content.is_active_at(ts(MONDAY + 22 * HOUR)));
+        assert!(content.is_active_at(ts(MONDAY + 30 * HOUR)));
+        assert!(!content.is_active_at(ts(MONDAY + 31 * HOUR)));
+
+        // The schedule only starts on Mondays.
+        assert!(!content.is_active_at(ts(MONDAY + 46 * HOUR)));
+
+        // Disabled schedules are ignored.
+        let disabled = DoNotDisturbEventContent { enabled: false, ..nights() };
+        assert!(!disabled.is_active_at(ts(MONDAY + 22 * HOUR)));
+    }
+
+    #[test]
+    fn test_time_zone() {
+        let content =
+            DoNotDisturbEventContent { timezone: Some("Europe/Paris".to_owned()), ..nights() };
+
+        // In winter, Paris is UTC+1: 22:00 local time is 21:00 UTC.
+        assert!(!content.is_active_at(ts(MONDAY + 20 * HOUR)));
+        assert!(content.is_active_at(ts(MONDAY + 21 * HOUR)));
+        assert!(!content.is_active_at(ts(MONDAY + 30 * HOUR)));
+        assert_eq!(content.next_change_after(ts(MONDAY)), Some(ts(MONDAY + 21 * HOUR)));
+
+        // In summer, Paris is UTC+2: 22:00 local time is 20:00 UTC.
+        let summer_monday = MONDAY + 26 * 7 * 24 * HOUR;
+        assert!(!content.is_active_at(ts(summer_monday + 19 * HOUR)));
+        assert!(content.is_active_at(ts(summer_monday + 20 * HOUR)));
+        assert!(!content.is_active_at(ts(summer_monday + 29 * HOUR)));
+        assert_eq!(
+            content.next_change_after(ts(summer_monday)),
+            Some(ts(summer_monday + 20 * HOUR))
+        );
+
+        // An unknown time zone falls back to UTC.
+        let unknown =
+            DoNotDisturbEventContent { timezone: Some("Mars/Olympus_Mons".to_owned()), ..nights() };
+        assert!(unknown.is_active_at(ts(MONDAY + 22 * HOUR)));
+        assert!(!unknown.is_active_at(ts(MONDAY + 21 * HOUR)));
+    }
+
+    #[test]
+    fn test_until() {
+        let content =
+            DoNotDisturbEventContent { until: Some(ts(MONDAY + HOUR)), ..Default::default() };
+
+        assert!(content.is_active_at(ts(MONDAY)));
+        assert!(!content.is_active_at(ts(MONDAY + HOUR)));
+        assert_eq!(content.next_change_after(ts(MONDAY)), Some(ts(MONDAY + HOUR)));
+        assert_eq!(content.next_change_after(ts(MONDAY + HOUR)), None);
+    }
+
+    #[test]
+    fn test_next_change() {
+        let content = nights();
+
+        assert_eq!(content.next_change_after(ts(MONDAY)), Some(ts(MONDAY + 22 * HOUR)));
+        assert_eq!(content.next_change_after(ts(MONDAY + 22 * HOUR)), Some(ts(MONDAY + 31 * HOUR)));
+        // The next occurrence is next Monday.
+        assert_eq!(
+            content.next_change_after(ts(MONDAY + 31 * HOUR)),
+            Some(ts(MONDAY + (7 * 24 + 22) * HOUR))
+        );
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let mut content = nights();
+        content.exceptions.mentions = true;
+        content.exceptions.users.insert(owned_user_id!("@boss:example.org"));
+
+        let night = ts(MONDAY + 23 * HOUR);
+        let day = ts(MONDAY + 12 * HOUR);
+        let bob = user_id!("@bob:example.org");
+
+        assert!(content.should_show_notification_at(day, bob, false, false));
+        assert!(!content.should_show_notification_at(night, bob, false, false));
+        assert!(!content.should_show_notification_at(night, bob, true, false));
+        assert!(content.should_show_notification_at(night, bob, false, true));
+        assert!(content.should_show_notification_at(
+            night,
+            user_id!("@boss:example.org"),
+            false,
+            false
+        ));
+    }
+}
@@ -16,32 +16,42 @@
 
 use std::sync::Arc;
 
+use futures_core::Stream;
+use futures_util::StreamExt;
 use indexmap::IndexSet;
 use ruma::{
     api::client::push::{
         delete_pushrule, set_pushrule, set_pushrule_actions, set_pushrule_enabled,
     },
-    events::push_rules::PushRulesEvent,
+    events::{push_rules::PushRulesEvent, GlobalAccountDataEvent},
     push::{Action, NewPushRule, PredefinedUnderrideRuleId, RuleKind, Ruleset, Tweak},
-    RoomId,
+    MilliSecondsSinceUnixEpoch, RoomId, UserId,
 };
 use tokio::sync::{
     broadcast::{self, Receiver},
     RwLock,
 };
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 use self::{command::Command, rule_commands::RuleCommands, rules::Rules};
 
 mod command;
+mod do_not_disturb;
 mod rule_commands;
 mod rules;
+mod trace;
 
+pub use do_not_disturb::{
+    DoNotDisturbEventContent, DoNotDisturbExceptions, DoNotDisturbSchedule,
+    DoNotDisturbScheduleError, Weekday,
+};
 pub use matrix_sdk_base::notification_settings::RoomNotificationMode;
+pub(crate) use trace::trace_event;
+pub use trace::{PushConditionTrace, PushRuleTrace, PushRulesSimulation, PushRulesTrace};
 
 use crate::{
     config::RequestConfig, error::NotificationSettingsError, event_handler::EventHandlerDropGuard,
-    Client, Result,
+    sleep::sleep, Client, Result, Room,
 };
 
 /// Whether or not a room is encrypted
@@ -481,6 +491,114 @@ impl NotificationSettings {
         Ok(())
     }
 
+    /// Get the “do not disturb” settings of the user, from the global account
+    /// data.
+    ///
+    /// Returns the default, disabled, settings if none have been set yet.
+    pub async fn do_not_disturb_settings(&self) -> Result<DoNotDisturbEventContent> {
+        Ok(self
+            .client
+            .account()
+            .account_data::<DoNotDisturbEventContent>()
+            .await?
+            .and_then(|raw| raw.deserialize().ok())
+            .unwrap_or_default())
+    }
+
+    /// Set the “do not disturb” settings of the user, in the global account
+    /// data.
+    ///
+    /// The settings are shared by all the clients of the user.
+    pub async fn set_do_not_disturb_settings(
+        &self,
+        settings: DoNotDisturbEventContent,
+    ) -> Result<()> {
+        self.client.account().set_account_data(settings).await?;
+        Ok(())
+    }
+
+    /// Whether a notification for an event must be shown now, according to the
+    /// “do not disturb” settings of the user.
+    ///
+    /// This loads the settings every time it is called: when several
+    /// notifications are handled at once, prefer loading the settings once
+    /// with [`NotificationSettings::do_not_disturb_settings()`], and calling
+    /// [`DoNotDisturbEventContent::should_show_notification()`] for each
+    /// notification.
+    ///
+    /// If the settings can't be loaded, the notification is shown.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - The room the event was sent in.
+    /// * `sender` - The sender of the event.
+    /// * `actions` - The push actions of the event.
+    pub async fn should_show_notification(
+        &self,
+        room: &Room,
+        sender: &UserId,
+        actions: &[Action],
+    ) -> bool {
+        match self.do_not_disturb_settings().await {
+            Ok(settings) => settings.should_show_notification(room, sender, actions).await,
+            Err(error) => {
+                warn!(
+                    "Couldn't load the do not disturb settings, showing the notification: {error}"
+                );
+                true
+            }
+        }
+    }
+
+    /// Observe whether the “do not disturb” mode is active.
+    ///
+    /// The stream yields the current state first, then each time the state
+    /// changes, either because a schedule starts or ends, or because the
+    /// settings have been updated, possibly by another client.
+    pub async fn observe_do_not_disturb(&self) -> Result<impl Stream<Item = bool>> {
+        let observer =
+            self.client.observe_events::<GlobalAccountDataEvent<DoNotDisturbEventContent>, ()>();
+        let mut updates = observer.subscribe();
+
+        // Get the initial value after creating the observer, so no update is missed.
+        let mut settings = self.do_not_disturb_settings().await?;
+
+        Ok(async_stream::stream! {
+            // The observer needs to be alive for the stream to be alive.
+            let _observer = observer;
+
+            let mut is_active = settings.is_active_at(MilliSecondsSinceUnixEpoch::now());
+            yield is_active;
+
+            loop {
+                let next_change =
+                    settings.duration_until_next_change(MilliSecondsSinceUnixEpoch::now());
+
+                let wait_for_next_change = async move {
+                    match next_change {
+                        Some(duration) => sleep(duration).await,
+                        None => std::future::pending().await,
+                    }
+                };
+
+                tokio::select! {
+                    update = updates.next() => match update {
+                        Some((event, ())) => settings = event.content,
+                        None => break,
+                    },
+                    _ = wait_for_next_change => {}
+                }
+
+                let is_now_active = settings.is_active_at(MilliSecondsSinceUnixEpoch::now());
+
+                if is_now_active != is_active {
+                    is_active = is_now_active;
+                    yield is_active;
+                }
+            }
+        })
+    }
+
     /// Convert commands into requests to the server, and run them.
     async fn run_server_commands(
         &self,
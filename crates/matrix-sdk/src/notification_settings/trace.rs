@@ -0,0 +1,316 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Explain how the push rules are evaluated for an event.
+//!
+//! This is useful to answer the question “why didn't I get notified?”: see
+//! [`Room::trace_event_push_actions`] and [`Room::simulate_push_rules`].
+//!
+//! [`Room::trace_event_push_actions`]: crate::Room::trace_event_push_actions
+//! [`Room::simulate_push_rules`]: crate::Room::simulate_push_rules
+
+use ruma::{
+    push::{
+        Action, AnyPushRuleRef, FlattenedJson, PushCondition, PushConditionRoomCtx, RuleKind,
+        Ruleset,
+    },
+    serde::Raw,
+    OwnedEventId, OwnedUserId,
+};
+
+/// The evaluation of a single condition of a push rule.
+#[derive(Clone, Debug)]
+pub struct PushConditionTrace {
+    /// The evaluated condition.
+    ///
+    /// The implicit conditions of the content, room and sender rules are
+    /// represented as [`PushCondition::EventMatch`] conditions on the
+    /// `content.body`, `room_id` and `sender` keys respectively.
+    pub condition: PushCondition,
+
+    /// Whether the condition matched the event.
+    pub matched: bool,
+}
+
+/// The evaluation of a single push rule.
+#[derive(Clone, Debug)]
+pub struct PushRuleTrace {
+    /// The kind of the rule.
+    pub kind: RuleKind,
+
+    /// The ID of the rule.
+    pub rule_id: String,
+
+    /// Whether the rule is enabled. A disabled rule never matches.
+    pub enabled: bool,
+
+    /// Whether the rule is a server-default rule.
+    pub is_server_default: bool,
+
+    /// The evaluation of each condition of the rule.
+    pub conditions: Vec<PushConditionTrace>,
+
+    /// Whether the rule matched the event.
+    ///
+    /// This is the result of the actual evaluation of the rule, which may take
+    /// more than the conditions into account, e.g. whether the rule is
+    /// enabled.
+    pub matched: bool,
+
+    /// The actions of the rule.
+    pub actions: Vec<Action>,
+}
+
+/// The full evaluation trace of the push rules for an event.
+#[derive(Clone, Debug)]
+pub struct PushRulesTrace {
+    /// The evaluation of each rule, in the order of evaluation.
+    ///
+    /// All the rules are evaluated, even the ones after the first matching
+    /// rule, so that it's possible to know which other rules would have
+    /// matched.
+    pub rules: Vec<PushRuleTrace>,
+
+    /// The index, in [`Self::rules`], of the first matching rule, i.e. the one
+    /// whose actions apply.
+    pub matched_rule_index: Option<usize>,
+
+    /// Whether the event has been sent by the current user. Such events never
+    /// trigger any action.
+    pub is_own_event: bool,
+
+    /// The final actions for the event.
+    pub actions: Vec<Action>,
+}
+
+impl PushRulesTrace {
+    /// Get the first matching rule, i.e. the one whose actions apply.
+    pub fn matched_rule(&self) -> Option<&PushRuleTrace> {
+        self.matched_rule_index.and_then(|index| self.rules.get(index))
+    }
+
+    /// Whether the event notifies.
+    pub fn notifies(&self) -> bool {
+        self.actions.iter().any(Action::should_notify)
+    }
+
+    /// Whether the event is highlighted.
+    pub fn highlights(&self) -> bool {
+        self.actions.iter().any(Action::is_highlight)
+    }
+}
+
+/// The result of the evaluation of a proposed push rules change for an event.
+///
+/// See [`Room::simulate_push_rules`](crate::Room::simulate_push_rules).
+#[derive(Clone, Debug)]
+pub struct PushRulesSimulation {
+    /// The ID of the event, if any.
+    pub event_id: Option<OwnedEventId>,
+
+    /// The sender of the event, if any.
+    pub sender: Option<OwnedUserId>,
+
+    /// The actions for the event with the current push rules.
+    pub current_actions: Vec<Action>,
+
+    /// The actions for the event with the proposed push rules.
+    pub proposed_actions: Vec<Action>,
+}
+
+impl PushRulesSimulation {
+    /// Whether the proposed push rules change whether the event notifies, or
+    /// whether it is highlighted.
+    pub fn changes_notification(&self) -> bool {
+        let notifies = |actions: &[Action]| actions.iter().any(Action::should_notify);
+        let highlights = |actions: &[Action]| actions.iter().any(Action::is_highlight);
+
+        notifies(&self.current_actions) != notifies(&self.proposed_actions)
+            || highlights(&self.current_actions) != highlights(&self.proposed_actions)
+    }
+}
+
+/// Get the conditions of a rule, including the implicit ones.
+fn rule_conditions(rule: &AnyPushRuleRef<'_>) -> Vec<PushCondition> {
+    match rule {
+        AnyPushRuleRef::Override(r) | AnyPushRuleRef::Underride(r) => r.conditions.clone(),
+        AnyPushRuleRef::Content(r) => vec![PushCondition::EventMatch {
+            key: "content.body".to_owned(),
+            pattern: r.pattern.clone(),
+        }],
+        AnyPushRuleRef::Room(r) => {
+            vec![PushCondition::EventMatch {
+                key: "room_id".to_owned(),
+                pattern: r.rule_id.to_string(),
+            }]
+        }
+        AnyPushRuleRef::Sender(r) => {
+            vec![PushCondition::EventMatch {
+                key: "sender".to_owned(),
+                pattern: r.rule_id.to_string(),
+            }]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Evaluate all the push rules of a ruleset for an event, and trace the
+/// result.
+pub(crate) async fn trace_event<T>(
+    ruleset: &Ruleset,
+    context: &PushConditionRoomCtx,
+    event: &Raw<T>,
+) -> PushRulesTrace {
+    let is_own_event = event
+        .get_field::<OwnedUserId>("sender")
+        .ok()
+        .flatten()
+        .is_some_and(|sender| sender == context.user_id);
+
+    let flattened_event = FlattenedJson::from_raw(event);
+
+    let mut rules = Vec::new();
+    let mut matched_rule_index = None;
+
+    for rule in ruleset {
+        let mut conditions = Vec::new();
+
+        for condition in rule_conditions(&rule) {
+            let matched = condition.applies(&flattened_event, context).await;
+            conditions.push(PushConditionTrace { condition, matched });
+        }
+
+        let matched = !is_own_event && rule.applies(&flattened_event, context).await;
+
+        if matched && matched_rule_index.is_none() {
+            matched_rule_index = Some(rules.len());
+        }
+
+        rules.push(PushRuleTrace {
+            kind: rule.kind(),
+            rule_id: rule.rule_id().to_owned(),
+            enabled: rule.enabled(),
+            is_server_default: rule.is_server_default(),
+            conditions,
+            matched,
+            actions: rule.actions().to_owned(),
+        });
+    }
+
+    let actions = ruleset.get_actions(event, context).await.to_owned();
+
+    PushRulesTrace { rules, matched_rule_index, is_own_event, actions }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_test::async_test;
+    use ruma::{
+        owned_room_id, owned_user_id,
+        push::{Action, PushConditionRoomCtx, RuleKind, Ruleset},
+        serde::Raw,
+        uint, user_id,
+    };
+    use serde_json::json;
+
+    use super::{trace_event, PushRulesSimulation};
+
+    fn context() -> PushConditionRoomCtx {
+        PushConditionRoomCtx::new(
+            owned_room_id!("!room:example.org"),
+            uint!(2),
+            owned_user_id!("@alice:example.org"),
+            "Alice".to_owned(),
+        )
+    }
+
+    #[async_test]
+    async fn test_trace_mention() {
+        let ruleset = Ruleset::server_default(user_id!("@alice:example.org"));
+        let event = Raw::new(&json!({
+            "type": "m.room.message",
+            "event_id": "$ev",
+            "sender": "@bob:example.org",
+            "origin_server_ts": 1,
+            "room_id": "!room:example.org",
+            "content": {
+                "msgtype": "m.text",
+                "body": "Hello Alice",
+                "m.mentions": { "user_ids": ["@alice:example.org"] },
+            },
+        }))
+        .unwrap();
+
+        let trace = trace_event(&ruleset, &context(), &event).await;
+
+        assert!(!trace.is_own_event);
+        assert!(trace.notifies());
+        assert!(trace.highlights());
+        assert_eq!(trace.rules.len(), ruleset.iter().count());
+
+        let matched_rule = trace.matched_rule().unwrap();
+        assert_eq!(matched_rule.kind, RuleKind::Override);
+        assert_eq!(matched_rule.rule_id, ".m.rule.is_user_mention");
+        assert!(matched_rule.conditions.iter().all(|condition| condition.matched));
+
+        // The master rule is evaluated but doesn't match.
+        let master = &trace.rules[0];
+        assert_eq!(master.rule_id, ".m.rule.master");
+        assert!(!master.enabled);
+        assert!(!master.matched);
+    }
+
+    #[async_test]
+    async fn test_trace_own_event() {
+        let ruleset = Ruleset::server_default(user_id!("@alice:example.org"));
+        let event = Raw::new(&json!({
+            "type": "m.room.message",
+            "event_id": "$ev",
+            "sender": "@alice:example.org",
+            "origin_server_ts": 1,
+            "room_id": "!room:example.org",
+            "content": {
+                "msgtype": "m.text",
+                "body": "Hello",
+            },
+        }))
+        .unwrap();
+
+        let trace = trace_event(&ruleset, &context(), &event).await;
+
+        assert!(trace.is_own_event);
+        assert!(trace.matched_rule().is_none());
+        assert!(trace.rules.iter().all(|rule| !rule.matched));
+        assert!(!trace.notifies());
+    }
+
+    #[test]
+    fn test_simulation_changes_notification() {
+        let simulation = PushRulesSimulation {
+            event_id: None,
+            sender: None,
+            current_actions: vec![Action::Notify],
+            proposed_actions: vec![],
+        };
+        assert!(simulation.changes_notification());
+
+        let simulation = PushRulesSimulation {
+            event_id: None,
+            sender: None,
+            current_actions: vec![Action::Notify],
+            proposed_actions: vec![Action::Notify],
+        };
+        assert!(!simulation.changes_notification());
+    }
+}
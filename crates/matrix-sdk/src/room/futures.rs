@@ -34,7 +34,9 @@ use tracing::{info, trace, Instrument, Span};
 
 use super::Room;
 use crate::{
-    attachment::AttachmentConfig, config::RequestConfig, utils::IntoRawMessageLikeEventContent,
+    attachment::{AttachmentConfig, AttachmentSource},
+    config::RequestConfig,
+    utils::IntoRawMessageLikeEventContent,
     Result, TransmissionProgress,
 };
 
@@ -201,7 +203,12 @@ impl<'a> IntoFuture for SendRawMessageLikeEvent<'a> {
                     // could have not query their keys ever.
                     room.query_keys_for_untracked_or_dirty_users().await?;
 
-                    room.preshare_room_key().await?;
+                    let room_key_shared = room.ensure_room_key_shared().await?;
+                    room.client
+                        .inner
+                        .e2ee
+                        .room_key_preshare
+                        .record_send(room.room_id(), room_key_shared);
 
                     let olm = room.client.olm_machine().await;
                     let olm = olm.as_ref().expect("Olm machine wasn't started");
@@ -242,7 +249,7 @@ pub struct SendAttachment<'a> {
     room: &'a Room,
     filename: String,
     content_type: &'a Mime,
-    data: Vec<u8>,
+    source: AttachmentSource,
     config: AttachmentConfig,
     tracing_span: Span,
     send_progress: SharedObservable<TransmissionProgress>,
@@ -254,14 +261,14 @@ impl<'a> SendAttachment<'a> {
         room: &'a Room,
         filename: String,
         content_type: &'a Mime,
-        data: Vec<u8>,
+        source: AttachmentSource,
         config: AttachmentConfig,
     ) -> Self {
         Self {
             room,
             filename,
             content_type,
-            data,
+            source,
             config,
             tracing_span: Span::current(),
             send_progress: Default::default(),
@@ -283,6 +290,9 @@ impl<'a> SendAttachment<'a> {
     ///
     /// If set to true, then retrieving the data for the attachment will result
     /// in a cache hit immediately after upload.
+    ///
+    /// This has no effect on the attachment itself when it's streamed (see
+    /// [`Room::send_attachment_stream()`]), only on its thumbnail.
     pub fn store_in_cache(mut self) -> Self {
         self.store_in_cache = true;
         self
@@ -298,7 +308,7 @@ impl<'a> IntoFuture for SendAttachment<'a> {
             room,
             filename,
             content_type,
-            data,
+            source,
             config,
             tracing_span,
             send_progress,
@@ -308,7 +318,7 @@ impl<'a> IntoFuture for SendAttachment<'a> {
             room.prepare_and_send_attachment(
                 filename,
                 content_type,
-                data,
+                source,
                 config,
                 send_progress,
                 store_in_cache,
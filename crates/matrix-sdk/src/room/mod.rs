@@ -33,7 +33,9 @@ use http::StatusCode;
 #[cfg(feature = "e2e-encryption")]
 pub use identity_status_changes::IdentityStatusChanges;
 #[cfg(feature = "e2e-encryption")]
-use matrix_sdk_base::crypto::{IdentityStatusChange, RoomIdentityProvider, UserIdentity};
+use matrix_sdk_base::crypto::{
+    CollectStrategy, IdentityStatusChange, RoomIdentityProvider, UserIdentity,
+};
 pub use matrix_sdk_base::store::ThreadSubscription;
 #[cfg(feature = "e2e-encryption")]
 use matrix_sdk_base::{crypto::RoomEventDecryptionResult, deserialized_responses::EncryptionInfo};
@@ -148,8 +150,10 @@ pub use self::{
 };
 #[cfg(doc)]
 use crate::event_cache::EventCache;
+#[cfg(not(target_family = "wasm"))]
+use crate::media::UploadStream;
 use crate::{
-    attachment::{AttachmentConfig, AttachmentInfo},
+    attachment::{AttachmentConfig, AttachmentInfo, AttachmentSource},
     client::WeakClient,
     config::RequestConfig,
     error::{BeaconError, WrongRoomState},
@@ -157,7 +161,10 @@ use crate::{
     event_handler::{EventHandler, EventHandlerDropGuard, EventHandlerHandle, SyncEvent},
     live_location_share::ObservableLiveLocation,
     media::{MediaFormat, MediaRequestParameters},
-    notification_settings::{IsEncrypted, IsOneToOne, RoomNotificationMode},
+    notification_settings::{
+        trace_event, IsEncrypted, IsOneToOne, PushRulesSimulation, PushRulesTrace,
+        RoomNotificationMode,
+    },
     room::{
         knock_requests::{KnockRequest, KnockRequestMemberInfo},
         power_levels::{RoomPowerLevelChanges, RoomPowerLevelsExt},
@@ -168,7 +175,13 @@ use crate::{
     BaseRoom, Client, Error, HttpResult, Result, RoomState, TransmissionProgress,
 };
 #[cfg(feature = "e2e-encryption")]
-use crate::{crypto::types::events::CryptoContextInfo, encryption::backups::BackupState};
+use crate::{
+    crypto::types::events::CryptoContextInfo,
+    encryption::{
+        backups::BackupState, history_sharing::HistorySharingJob,
+        room_key_preshare::PreshareOutcome,
+    },
+};
 
 pub mod edit;
 pub mod futures;
@@ -226,6 +239,12 @@ impl PushContext {
     pub async fn for_event<T>(&self, event: &Raw<T>) -> Vec<Action> {
         self.push_rules.get_actions(event, &self.push_condition_room_ctx).await.to_owned()
     }
+
+    /// Compute the push rules for a given event, and trace how each rule has
+    /// been evaluated.
+    pub async fn trace_event<T>(&self, event: &Raw<T>) -> PushRulesTrace {
+        trace_event(&self.push_rules, &self.push_condition_room_ctx, event).await
+    }
 }
 
 macro_rules! make_media_type {
@@ -1571,6 +1590,10 @@ impl Room {
                     .encryption()
                     .backups()
                     .maybe_download_room_key(self.room_id().to_owned(), event.clone());
+                self.client
+                    .encryption()
+                    .utd_remediation()
+                    .maybe_remediate(self.clone(), event.clone());
                 Ok(TimelineEvent::from_utd(event.clone().cast(), utd_info))
             }
         }
@@ -1681,9 +1704,14 @@ impl Room {
     /// * `user_id` - The `UserId` of the user to invite to the room.
     #[instrument(skip_all)]
     pub async fn invite_user_by_id(&self, user_id: &UserId) -> Result<()> {
+        // Share the room history before the invite, so that the invitee has the room
+        // keys when joining. If it fails, the job is retried in the background.
         #[cfg(feature = "e2e-encryption")]
         if self.client.inner.enable_share_history_on_invite {
-            shared_room_history::share_room_history(self, user_id.to_owned()).await?;
+            let history_sharing = self.client.encryption().history_sharing();
+            history_sharing.enqueue(self.room_id(), user_id).await?;
+            history_sharing.run_job(self.room_id(), user_id).await?;
+            history_sharing.wake_up_task();
         }
 
         let recipient = InvitationRecipient::UserId { user_id: user_id.to_owned() };
@@ -1698,6 +1726,20 @@ impl Room {
         Ok(())
     }
 
+    /// Get the jobs sharing the history of this room with the users invited
+    /// to it, e.g. to know which invitees have received the room history.
+    ///
+    /// History is shared on invite only if
+    /// [`ClientBuilder::with_enable_share_history_on_invite()`] is enabled.
+    /// See [`HistorySharing`] for more details.
+    ///
+    /// [`ClientBuilder::with_enable_share_history_on_invite()`]: crate::ClientBuilder::with_enable_share_history_on_invite
+    /// [`HistorySharing`]: crate::encryption::history_sharing::HistorySharing
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn history_sharing_jobs(&self) -> Result<Vec<HistorySharingJob>> {
+        self.client.encryption().history_sharing().jobs_for_room(self.room_id()).await
+    }
+
     /// Invite the specified user by third party id to this room.
     ///
     /// # Arguments
@@ -1754,6 +1796,11 @@ impl Room {
     pub async fn typing_notice(&self, typing: bool) -> Result<()> {
         self.ensure_room_joined()?;
 
+        #[cfg(feature = "e2e-encryption")]
+        if typing {
+            self.maybe_schedule_room_key_preshare();
+        }
+
         // Only send a request to the homeserver if the old timeout has elapsed
         // or the typing notice changed state within the `TYPING_NOTICE_TIMEOUT`
         let send = if let Some(typing_time) =
@@ -1960,23 +2007,130 @@ impl Room {
         Ok(())
     }
 
+    /// Get the strategy used to collect the devices which receive the room
+    /// key of this room, if it has been overridden with
+    /// [`Room::set_room_key_recipient_strategy()`].
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn room_key_recipient_strategy(&self) -> Result<Option<CollectStrategy>> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.room_key_recipient_strategy(self.room_id()).await?)
+    }
+
+    /// Override the strategy used to collect the devices which receive the
+    /// room key of this room.
+    ///
+    /// By default, the strategy set with
+    /// [`ClientBuilder::with_room_key_recipient_strategy()`] is used for all
+    /// the rooms. The override is persisted in the crypto store; pass `None`
+    /// to remove it.
+    ///
+    /// [`ClientBuilder::with_room_key_recipient_strategy()`]: crate::ClientBuilder::with_room_key_recipient_strategy
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn set_room_key_recipient_strategy(
+        &self,
+        strategy: Option<CollectStrategy>,
+    ) -> Result<()> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.set_room_key_recipient_strategy(self.room_id(), strategy).await?)
+    }
+
+    /// Pre-share the room key of this room with all the devices of its
+    /// members.
+    ///
+    /// This does the work which is otherwise done when the next event is sent
+    /// in an encrypted room: fetch the members if needed, query their device
+    /// keys, create Olm sessions with their devices, and share the room key
+    /// with them. Calling this, e.g. when the user starts typing a message,
+    /// reduces the time it takes to send it.
+    ///
+    /// Does nothing if the room isn't encrypted, or if no room key needs to
+    /// be shared.
+    ///
+    /// See also [`Room::schedule_room_key_preshare()`] and
+    /// [`Encryption::set_room_key_preshare_settings()`] to do this
+    /// automatically.
+    ///
+    /// [`Encryption::set_room_key_preshare_settings()`]: crate::encryption::Encryption::set_room_key_preshare_settings
+    #[cfg(feature = "e2e-encryption")]
+    #[instrument(skip_all, fields(room_id = ?self.room_id()))]
+    pub async fn preshare_room_key(&self) -> Result<()> {
+        self.ensure_room_joined()?;
+
+        if !self.latest_encryption_state().await?.is_encrypted() {
+            return Ok(());
+        }
+
+        let result = async {
+            if !self.are_members_synced() {
+                self.sync_members().await?;
+            }
+
+            self.query_keys_for_untracked_or_dirty_users().await?;
+            self.ensure_room_key_shared().await
+        }
+        .await;
+
+        self.client
+            .inner
+            .e2ee
+            .room_key_preshare
+            .record_preshare(self.room_id(), PreshareOutcome::from_result(&result));
+
+        result.map(|_| ())
+    }
+
+    /// Schedule a pre-share of the room key of this room, see
+    /// [`Room::preshare_room_key()`].
+    ///
+    /// The pre-share happens in the background, after the debounce delay of
+    /// the [`RoomKeyPreshareSettings`]; calling this method again in the
+    /// meantime restarts the delay. This is what happens when the user
+    /// starts typing if automatic pre-sharing is enabled.
+    ///
+    /// [`RoomKeyPreshareSettings`]: crate::encryption::room_key_preshare::RoomKeyPreshareSettings
+    #[cfg(feature = "e2e-encryption")]
+    pub fn schedule_room_key_preshare(&self) {
+        self.client.inner.e2ee.room_key_preshare.schedule(self);
+    }
+
+    /// Cancel the pending pre-share of the room key of this room scheduled
+    /// with [`Room::schedule_room_key_preshare()`], if any.
+    #[cfg(feature = "e2e-encryption")]
+    pub fn cancel_room_key_preshare(&self) {
+        self.client.inner.e2ee.room_key_preshare.cancel(self.room_id());
+    }
+
+    /// Schedule a pre-share of the room key of this room, if automatic
+    /// pre-sharing is enabled.
+    #[cfg(feature = "e2e-encryption")]
+    fn maybe_schedule_room_key_preshare(&self) {
+        if self.client.inner.e2ee.room_key_preshare.settings().automatic {
+            self.schedule_room_key_preshare();
+        }
+    }
+
     /// Share a room key with users in the given room.
     ///
     /// This will create Olm sessions with all the users/device pairs in the
     /// room if necessary and share a room key that can be shared with them.
     ///
     /// Does nothing if no room key needs to be shared.
-    // TODO: expose this publicly so people can pre-share a group session if
-    // e.g. a user starts to type a message for a room.
+    ///
+    /// Returns whether the room key had to be shared.
     #[cfg(feature = "e2e-encryption")]
     #[instrument(skip_all, fields(room_id = ?self.room_id(), store_generation))]
-    async fn preshare_room_key(&self) -> Result<()> {
+    pub(crate) async fn ensure_room_key_shared(&self) -> Result<bool> {
         self.ensure_room_joined()?;
 
         // Take and release the lock on the store, if needs be.
         let guard = self.client.encryption().spin_lock_store(Some(60000)).await?;
         tracing::Span::current().record("store_generation", guard.map(|guard| guard.generation()));
 
+        // A concurrent call sharing the room key forwards its result.
         self.client
             .locks()
             .group_session_deduplicated_handler
@@ -1995,37 +2149,41 @@ impl Room {
                 // If one of the responses failed invalidate the group
                 // session as using it would end up in undecryptable
                 // messages.
-                if let Err(r) = response {
-                    let machine = self.client.olm_machine().await;
-                    if let Some(machine) = machine.as_ref() {
-                        machine.discard_room_key(self.room_id()).await?;
+                match response {
+                    Ok(num_requests) => Ok(num_requests > 0),
+                    Err(r) => {
+                        let machine = self.client.olm_machine().await;
+                        if let Some(machine) = machine.as_ref() {
+                            machine.discard_room_key(self.room_id()).await?;
+                        }
+                        Err(r)
                     }
-                    return Err(r);
                 }
-
-                Ok(())
             })
             .await
     }
 
     /// Share a group session for a room.
     ///
+    /// Returns the number of to-device requests which have been sent.
+    ///
     /// # Panics
     ///
     /// Panics if the client isn't logged in.
     #[cfg(feature = "e2e-encryption")]
     #[instrument(skip_all)]
-    async fn share_room_key(&self) -> Result<()> {
+    async fn share_room_key(&self) -> Result<usize> {
         self.ensure_room_joined()?;
 
         let requests = self.client.base_client().share_room_key(self.room_id()).await?;
+        let num_requests = requests.len();
 
         for request in requests {
             let response = self.client.send_to_device(&request).await?;
             self.client.mark_request_as_sent(&request.txn_id, &response).await?;
         }
 
-        Ok(())
+        Ok(num_requests)
     }
 
     /// Wait for the room to be fully synced.
@@ -2260,7 +2418,63 @@ impl Room {
         data: Vec<u8>,
         config: AttachmentConfig,
     ) -> SendAttachment<'a> {
-        SendAttachment::new(self, filename.into(), content_type, data, config)
+        SendAttachment::new(
+            self,
+            filename.into(),
+            content_type,
+            AttachmentSource::Data(data),
+            config,
+        )
+    }
+
+    /// Send an attachment to this room, reading its content from an
+    /// [`UploadStream`].
+    ///
+    /// This behaves like [`Room::send_attachment()`], except that the content
+    /// is read, encrypted if needs be, and uploaded chunk by chunk, so it's
+    /// never entirely held in memory. This should be preferred for large
+    /// files.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{
+    /// #     Client, ruma::room_id, attachment::AttachmentConfig, media::UploadStream,
+    /// # };
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let mut client = Client::new(homeserver).await?;
+    /// # let room_id = room_id!("!test:localhost");
+    /// let video = UploadStream::from_path("/home/example/my-cat.mp4").await?;
+    /// let content_type: mime::Mime = "video/mp4".parse()?;
+    ///
+    /// if let Some(room) = client.get_room(&room_id) {
+    ///     room.send_attachment_stream(
+    ///         "my_favorite_cat.mp4",
+    ///         &content_type,
+    ///         video,
+    ///         AttachmentConfig::new(),
+    ///     ).await?;
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[cfg(not(target_family = "wasm"))]
+    #[instrument(skip_all)]
+    pub fn send_attachment_stream<'a>(
+        &'a self,
+        filename: impl Into<String>,
+        content_type: &'a Mime,
+        stream: UploadStream,
+        config: AttachmentConfig,
+    ) -> SendAttachment<'a> {
+        SendAttachment::new(
+            self,
+            filename.into(),
+            content_type,
+            AttachmentSource::Stream(stream),
+            config,
+        )
     }
 
     /// Prepare and send an attachment to this room.
@@ -2295,7 +2509,7 @@ impl Room {
         &'a self,
         filename: String,
         content_type: &'a Mime,
-        data: Vec<u8>,
+        source: AttachmentSource,
         mut config: AttachmentConfig,
         send_progress: SharedObservable<TransmissionProgress>,
         store_in_cache: bool,
@@ -2317,30 +2531,83 @@ impl Room {
         };
 
         #[cfg(feature = "e2e-encryption")]
-        let (media_source, thumbnail) = if self.latest_encryption_state().await?.is_encrypted() {
-            self.client
-                .upload_encrypted_media_and_thumbnail(&data, thumbnail, send_progress)
-                .await?
-        } else {
-            self.client
-                .media()
-                .upload_plain_media_and_thumbnail(
-                    content_type,
-                    // TODO: get rid of this clone; wait for Ruma to use `Bytes` or something
-                    // similar.
-                    data.clone(),
-                    thumbnail,
-                    send_progress,
-                )
-                .await?
-        };
+        let is_encrypted = self.latest_encryption_state().await?.is_encrypted();
 
-        #[cfg(not(feature = "e2e-encryption"))]
-        let (media_source, thumbnail) = self
-            .client
-            .media()
-            .upload_plain_media_and_thumbnail(content_type, data.clone(), thumbnail, send_progress)
-            .await?;
+        let (media_source, thumbnail, data) = match source {
+            AttachmentSource::Data(data) => {
+                if let Some(info) = &mut config.info {
+                    info.fill_voice_metadata(content_type, &data);
+                }
+
+                #[cfg(feature = "e2e-encryption")]
+                let (media_source, thumbnail) = if is_encrypted {
+                    self.client
+                        .upload_encrypted_media_and_thumbnail(&data, thumbnail, send_progress)
+                        .await?
+                } else {
+                    self.client
+                        .media()
+                        .upload_plain_media_and_thumbnail(
+                            content_type,
+                            // TODO: get rid of this clone; wait for Ruma to use `Bytes` or
+                            // something similar.
+                            data.clone(),
+                            thumbnail,
+                            send_progress,
+                        )
+                        .await?
+                };
+
+                #[cfg(not(feature = "e2e-encryption"))]
+                let (media_source, thumbnail) = self
+                    .client
+                    .media()
+                    .upload_plain_media_and_thumbnail(
+                        content_type,
+                        data.clone(),
+                        thumbnail,
+                        send_progress,
+                    )
+                    .await?;
+
+                (media_source, thumbnail, Some(data))
+            }
+
+            #[cfg(not(target_family = "wasm"))]
+            AttachmentSource::Stream(stream) => {
+                #[cfg(feature = "e2e-encryption")]
+                let (media_source, thumbnail) = if is_encrypted {
+                    self.client
+                        .upload_encrypted_stream_and_thumbnail(stream, thumbnail, send_progress)
+                        .await?
+                } else {
+                    self.client
+                        .media()
+                        .upload_plain_stream_and_thumbnail(
+                            content_type,
+                            stream,
+                            thumbnail,
+                            send_progress,
+                        )
+                        .await?
+                };
+
+                #[cfg(not(feature = "e2e-encryption"))]
+                let (media_source, thumbnail) = self
+                    .client
+                    .media()
+                    .upload_plain_stream_and_thumbnail(
+                        content_type,
+                        stream,
+                        thumbnail,
+                        send_progress,
+                    )
+                    .await?;
+
+                // The content has been streamed, there's nothing to cache.
+                (media_source, thumbnail, None)
+            }
+        };
 
         if store_in_cache {
             let cache_store_lock_guard = self.client.event_cache_store().lock().await?;
@@ -2348,15 +2615,19 @@ impl Room {
             // A failure to cache shouldn't prevent the whole upload from finishing
             // properly, so only log errors during caching.
 
-            debug!("caching the media");
-            let request =
-                MediaRequestParameters { source: media_source.clone(), format: MediaFormat::File };
+            if let Some(data) = data {
+                debug!("caching the media");
+                let request = MediaRequestParameters {
+                    source: media_source.clone(),
+                    format: MediaFormat::File,
+                };
 
-            if let Err(err) = cache_store_lock_guard
-                .add_media_content(&request, data, IgnoreMediaRetentionPolicy::No)
-                .await
-            {
-                warn!("unable to cache the media after uploading it: {err}");
+                if let Err(err) = cache_store_lock_guard
+                    .add_media_content(&request, data, IgnoreMediaRetentionPolicy::No)
+                    .await
+                {
+                    warn!("unable to cache the media after uploading it: {err}");
+                }
             }
 
             if let Some(((data, height, width), source)) =
@@ -3104,6 +3375,66 @@ impl Room {
         }
     }
 
+    /// Get the full evaluation trace of the push rules for the given event
+    /// with the current room state, i.e. each rule in order, which of their
+    /// conditions matched, and the final actions.
+    ///
+    /// The event doesn't need to exist: a synthetic event can be used to know
+    /// whether such an event would notify.
+    ///
+    /// Returns `None` in the same cases as [`Room::event_push_actions`].
+    pub async fn trace_event_push_actions<T>(
+        &self,
+        event: &Raw<T>,
+    ) -> Result<Option<PushRulesTrace>> {
+        if let Some(ctx) = self.push_context().await? {
+            Ok(Some(ctx.trace_event(event).await))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Evaluate a proposed change of the push rules against the most recent
+    /// events of this room in the event cache, without applying it.
+    ///
+    /// At most `max_events` events are evaluated, from the most recent to the
+    /// oldest one. Each result contains the actions with the current push
+    /// rules and the actions with `proposed_push_rules`, see
+    /// [`PushRulesSimulation::changes_notification`].
+    ///
+    /// Returns `None` in the same cases as [`Room::event_push_actions`].
+    pub async fn simulate_push_rules(
+        &self,
+        proposed_push_rules: Ruleset,
+        max_events: usize,
+    ) -> Result<Option<Vec<PushRulesSimulation>>> {
+        let Some(current_ctx) = self.push_context().await? else {
+            return Ok(None);
+        };
+        let Some(push_condition_room_ctx) = self.push_condition_room_ctx().await? else {
+            return Ok(None);
+        };
+        let proposed_ctx = PushContext::new(push_condition_room_ctx, proposed_push_rules);
+
+        let (event_cache, _drop_handles) = self.event_cache().await?;
+        let events = event_cache.events().await;
+
+        let mut simulations = Vec::new();
+
+        for event in events.iter().rev().take(max_events) {
+            let raw = event.raw();
+
+            simulations.push(PushRulesSimulation {
+                event_id: event.event_id(),
+                sender: raw.get_field("sender").ok().flatten(),
+                current_actions: current_ctx.for_event(raw).await,
+                proposed_actions: proposed_ctx.for_event(raw).await,
+            });
+        }
+
+        Ok(Some(simulations))
+    }
+
     /// The membership details of the (latest) invite for the logged-in user in
     /// this room.
     pub async fn invite_details(&self) -> Result<Invite> {
@@ -3441,6 +3772,9 @@ impl Room {
         draft: ComposerDraft,
         thread_root: Option<&EventId>,
     ) -> Result<()> {
+        #[cfg(feature = "e2e-encryption")]
+        self.maybe_schedule_room_key_preshare();
+
         self.client
             .state_store()
             .set_kv_data(
@@ -4304,7 +4638,7 @@ mod tests {
         let room = client.get_room(&DEFAULT_TEST_ROOM_ID).expect("Room should exist");
 
         // Step 1, preshare the room keys.
-        room.preshare_room_key().await.unwrap();
+        room.ensure_room_key_shared().await.unwrap();
 
         // Step 2, force lock invalidation by pretending another client obtained the
         // lock.
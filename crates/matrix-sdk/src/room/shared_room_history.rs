@@ -18,14 +18,40 @@ use matrix_sdk_base::media::{MediaFormat, MediaRequestParameters};
 use ruma::{events::room::MediaSource, OwnedUserId, UserId};
 use tracing::{info, instrument, warn};
 
-use crate::{crypto::types::events::room_key_bundle::RoomKeyBundleContent, Error, Result, Room};
+use crate::{
+    crypto::types::events::room_key_bundle::RoomKeyBundleContent,
+    encryption::history_sharing::HistorySharingStatus, Error, Result, Room,
+};
+
+/// The outcome of an attempt to share the room history.
+#[derive(Debug)]
+pub(crate) enum ShareRoomHistoryOutcome {
+    /// The key bundle has been sent to the recipient.
+    Sent {
+        /// The number of room keys in the bundle.
+        room_keys: usize,
+    },
+
+    /// There are no room keys to share.
+    NothingToShare,
+
+    /// Our user hasn't set up cross-signing yet, so the room history can't be
+    /// shared.
+    CrossSigningNotSetUp,
+}
 
 /// Share any shareable E2EE history in the given room with the given recipient,
 /// as per [MSC4268].
 ///
+/// `on_progress` is called with the current step of the sharing.
+///
 /// [MSC4268]: https://github.com/matrix-org/matrix-spec-proposals/pull/4268
-#[instrument(skip(room), fields(room_id = ?room.room_id()))]
-pub(super) async fn share_room_history(room: &Room, user_id: OwnedUserId) -> Result<()> {
+#[instrument(skip(room, on_progress), fields(room_id = ?room.room_id()))]
+pub(crate) async fn share_room_history(
+    room: &Room,
+    user_id: OwnedUserId,
+    on_progress: impl Fn(HistorySharingStatus),
+) -> Result<ShareRoomHistoryOutcome> {
     let client = &room.client;
 
     // 0. We can only share room history if our user has set up cross signing
@@ -36,10 +62,11 @@ pub(super) async fn share_room_history(room: &Room, user_id: OwnedUserId) -> Res
 
     if own_identity.is_none() {
         warn!("Not sharing message history as cross-signing is not set up");
-        return Ok(());
+        return Ok(ShareRoomHistoryOutcome::CrossSigningNotSetUp);
     }
 
     info!("Sharing message history");
+    on_progress(HistorySharingStatus::BuildingBundle);
 
     let olm_machine = client.olm_machine().await;
     let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
@@ -49,10 +76,11 @@ pub(super) async fn share_room_history(room: &Room, user_id: OwnedUserId) -> Res
 
     if bundle.is_empty() {
         info!("No keys to share");
-        return Ok(());
+        return Ok(ShareRoomHistoryOutcome::NothingToShare);
     }
 
     // 2. Upload to the server as an encrypted file
+    on_progress(HistorySharingStatus::UploadingBundle);
     let json = serde_json::to_vec(&bundle)?;
     let upload = client.upload_encrypted_file(&mut (json.as_slice())).await?;
 
@@ -64,6 +92,7 @@ pub(super) async fn share_room_history(room: &Room, user_id: OwnedUserId) -> Res
     );
 
     // 3. Ensure that we get a fresh list of devices for the invited user.
+    on_progress(HistorySharingStatus::SendingBundle);
     let (req_id, request) = olm_machine.query_keys_for_users(iter::once(user_id.as_ref()));
 
     if !request.device_keys.is_empty() {
@@ -92,7 +121,7 @@ pub(super) async fn share_room_history(room: &Room, user_id: OwnedUserId) -> Res
         client.mark_request_as_sent(&request.txn_id, &response).await?;
     }
 
-    Ok(())
+    Ok(ShareRoomHistoryOutcome::Sent { room_keys: bundle.room_keys.len() })
 }
 
 /// Having accepted an invite for the given room from the given user, attempt to
@@ -129,7 +129,8 @@
 //! remembered and fixed up into the media event, just before sending it.
 
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
     str::FromStr as _,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -255,6 +256,7 @@ impl SendQueue {
             &self.client,
             owned_room_id.clone(),
             data.report_media_upload_progress.clone(),
+            data.resumable_uploads.clone(),
         );
 
         map.insert(owned_room_id, room_q.clone());
@@ -297,6 +299,19 @@ impl SendQueue {
         self.data().report_media_upload_progress.store(enabled, Ordering::SeqCst);
     }
 
+    /// Enable or disable resumable media uploads.
+    ///
+    /// When enabled, media are uploaded in chunks to a preallocated MXC URI,
+    /// each with a `Content-Range` header, and an interrupted upload resumes
+    /// from the last uploaded chunk instead of starting over. No homeserver
+    /// feature advertises support for this, so it must only be enabled when
+    /// the homeserver is known to accept such chunks.
+    ///
+    /// This is disabled by default.
+    pub fn enable_resumable_uploads(&self, enabled: bool) {
+        self.data().resumable_uploads.store(enabled, Ordering::SeqCst);
+    }
+
     /// Subscribe to all updates for all rooms.
     ///
     /// Use [`RoomSendQueue::subscribe`] to subscribe to update for a _specific
@@ -377,6 +392,9 @@ pub(super) struct SendQueueData {
 
     /// Will media upload progress be reported via send queue updates?
     report_media_upload_progress: Arc<AtomicBool>,
+
+    /// Are media uploaded in chunks that can be resumed?
+    resumable_uploads: Arc<AtomicBool>,
 }
 
 impl SendQueueData {
@@ -392,6 +410,7 @@ impl SendQueueData {
             error_sender,
             is_dropping: Arc::new(false.into()),
             report_media_upload_progress: Arc::new(false.into()),
+            resumable_uploads: Arc::new(false.into()),
         }
     }
 }
@@ -433,6 +452,7 @@ impl std::fmt::Debug for RoomSendQueue {
 }
 
 impl RoomSendQueue {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         globally_enabled: bool,
         global_update_sender: broadcast::Sender<SendQueueUpdate>,
@@ -441,10 +461,12 @@ impl RoomSendQueue {
         client: &Client,
         room_id: OwnedRoomId,
         report_media_upload_progress: Arc<AtomicBool>,
+        resumable_uploads: Arc<AtomicBool>,
     ) -> Self {
         let (update_sender, _) = broadcast::channel(32);
 
-        let queue = QueueStorage::new(WeakClient::from_client(client), room_id.clone());
+        let queue =
+            QueueStorage::new(WeakClient::from_client(client), room_id.clone(), resumable_uploads);
         let notifier = Arc::new(Notify::new());
 
         let weak_room = WeakRoom::new(WeakClient::from_client(client), room_id);
@@ -648,6 +670,7 @@ impl RoomSendQueue {
             let (related_txn_id, media_upload_progress_info, http_progress) =
                 if let QueuedRequestKind::MediaUpload {
                     cache_key,
+                    local_file,
                     thumbnail_source,
                     #[cfg(feature = "unstable-msc4274")]
                     accumulated,
@@ -664,6 +687,7 @@ impl RoomSendQueue {
                                     &queued_request.transaction_id,
                                     related_to,
                                     cache_key,
+                                    local_file.as_deref(),
                                     thumbnail_source.as_ref(),
                                     #[cfg(feature = "unstable-msc4274")]
                                     accumulated,
@@ -688,7 +712,14 @@ impl RoomSendQueue {
                     Default::default()
                 };
 
-            match Self::handle_request(&room, queued_request, cancel_upload_rx, http_progress).await
+            match Self::handle_request(
+                &room,
+                &queue,
+                queued_request,
+                cancel_upload_rx,
+                http_progress,
+            )
+            .await
             {
                 Ok(Some(parent_key)) => match queue.mark_as_sent(&txn_id, parent_key.clone()).await
                 {
@@ -811,6 +842,7 @@ impl RoomSendQueue {
     /// `None`).
     async fn handle_request(
         room: &Room,
+        queue: &QueueStorage,
         request: QueuedRequest,
         cancel_upload_rx: Option<oneshot::Receiver<()>>,
         progress: Option<SharedObservable<TransmissionProgress>>,
@@ -829,17 +861,65 @@ impl RoomSendQueue {
                 Ok(Some(SentRequestKey::Event(res.event_id)))
             }
 
-            QueuedRequestKind::MediaUpload {
-                content_type,
-                cache_key,
-                thumbnail_source,
-                related_to: relates_to,
-                #[cfg(feature = "unstable-msc4274")]
-                accumulated,
-            } => {
+            kind @ QueuedRequestKind::MediaUpload { .. } => {
+                // Keep the whole request around, so the progress of a resumable upload can be
+                // saved without loading it again.
+                let QueuedRequestKind::MediaUpload {
+                    content_type,
+                    cache_key,
+                    local_file,
+                    thumbnail_source,
+                    related_to: relates_to,
+                    #[cfg(feature = "unstable-msc4274")]
+                    accumulated,
+                    ..
+                } = kind.clone()
+                else {
+                    unreachable!("the request kind has been matched above");
+                };
+
                 trace!(%relates_to, "uploading media related to event");
 
+                let txn_id = request.transaction_id;
+
                 let fut = async move {
+                    let mime = Mime::from_str(&content_type).map_err(|_| {
+                        crate::Error::SendQueueWedgeError(Box::new(
+                            QueueWedgeError::InvalidMimeType { mime_type: content_type.clone() },
+                        ))
+                    })?;
+
+                    // Prefer uploading the media in chunks, if resumable uploads are enabled, so
+                    // the upload can be resumed after an interruption.
+                    if let Some(media_source) =
+                        queue.upload_resumable(room, &txn_id, &mime, kind, progress.clone()).await?
+                    {
+                        trace!(%relates_to, "media successfully uploaded in chunks");
+
+                        return Ok(SentRequestKey::Media(SentMediaInfo {
+                            file: media_source,
+                            thumbnail: thumbnail_source,
+                            #[cfg(feature = "unstable-msc4274")]
+                            accumulated,
+                        }));
+                    }
+
+                    // The media's bytes weren't copied into the media cache, stream them from
+                    // the local file instead.
+                    if let Some(local_file) = local_file {
+                        let media_source =
+                            Self::upload_local_file(room, &local_file, &mime, progress).await?;
+
+                        trace!(%relates_to, "media successfully uploaded from a local file");
+
+                        return Ok(SentRequestKey::Media(SentMediaInfo {
+                            file: media_source,
+                            thumbnail: thumbnail_source,
+                            #[cfg(feature = "unstable-msc4274")]
+                            accumulated,
+                        }));
+                    }
+
                     let data = room
                         .client()
                         .event_cache_store()
@@ -851,12 +931,6 @@ impl RoomSendQueue {
                             QueueWedgeError::MissingMediaContent,
                         )))?;
 
-                    let mime = Mime::from_str(&content_type).map_err(|_| {
-                        crate::Error::SendQueueWedgeError(Box::new(
-                            QueueWedgeError::InvalidMimeType { mime_type: content_type.clone() },
-                        ))
-                    })?;
-
                     #[cfg(feature = "e2e-encryption")]
                     let media_source = if room.latest_encryption_state().await?.is_encrypted() {
                         trace!("upload will be encrypted (encrypted room)");
@@ -1131,6 +1205,16 @@ struct QueueStorage {
     /// others do. Since we access the thumbnails by their index within the
     /// gallery, the vector needs to hold optional usize's.
     thumbnail_file_sizes: Arc<SyncMutex<HashMap<OwnedTransactionId, Vec<Option<usize>>>>>,
+
+    /// In-memory set of the transaction IDs of the media uploads that have
+    /// been paused with [`SendHandle::pause_upload`], and that must not be
+    /// picked by the sending task until they're resumed.
+    paused_uploads: Arc<SyncMutex<HashSet<OwnedTransactionId>>>,
+
+    /// Are media uploaded in chunks that can be resumed?
+    ///
+    /// See [`SendQueue::enable_resumable_uploads`].
+    resumable_uploads: Arc<AtomicBool>,
 }
 
 impl QueueStorage {
@@ -1141,11 +1225,13 @@ impl QueueStorage {
     const HIGH_PRIORITY: usize = 10;
 
     /// Create a new queue for queuing requests to be sent later.
-    fn new(client: WeakClient, room: OwnedRoomId) -> Self {
+    fn new(client: WeakClient, room: OwnedRoomId, resumable_uploads: Arc<AtomicBool>) -> Self {
         Self {
             room_id: room,
             store: StoreLock { client, being_sent: Default::default() },
             thumbnail_file_sizes: Default::default(),
+            paused_uploads: Default::default(),
+            resumable_uploads,
         }
     }
 
@@ -1188,7 +1274,14 @@ impl QueueStorage {
         let queued_requests =
             guard.client()?.state_store().load_send_queue_requests(&self.room_id).await?;
 
-        if let Some(request) = queued_requests.iter().find(|queued| !queued.is_wedged()) {
+        let next_request = {
+            let paused_uploads = self.paused_uploads.lock();
+            queued_requests.iter().find(|queued| {
+                !queued.is_wedged() && !paused_uploads.contains(&queued.transaction_id)
+            })
+        };
+
+        if let Some(request) = next_request {
             let (cancel_upload_tx, cancel_upload_rx) =
                 if matches!(request.kind, QueuedRequestKind::MediaUpload { .. }) {
                     let (tx, rx) = oneshot::channel();
@@ -1304,6 +1397,7 @@ impl QueueStorage {
         }
 
         self.thumbnail_file_sizes.lock().remove(transaction_id);
+        self.paused_uploads.lock().remove(transaction_id);
 
         Ok(())
     }
@@ -1403,6 +1497,7 @@ impl QueueStorage {
         created_at: MilliSecondsSinceUnixEpoch,
         upload_file_txn: OwnedTransactionId,
         file_media_request: MediaRequestParameters,
+        local_file: Option<PathBuf>,
         thumbnail: Option<QueueThumbnailInfo>,
     ) -> Result<(), RoomSendQueueStorageError> {
         let guard = self.store.lock().await;
@@ -1420,6 +1515,7 @@ impl QueueStorage {
                 created_at,
                 upload_file_txn.clone(),
                 file_media_request,
+                local_file,
                 thumbnail,
             )
             .await?;
@@ -1478,6 +1574,7 @@ impl QueueStorage {
                 created_at,
                 upload_file_txn.clone(),
                 file_media_request.clone(),
+                None,
                 thumbnail.clone(),
             )
             .await?;
@@ -1516,6 +1613,7 @@ impl QueueStorage {
                         DependentQueuedRequestKind::UploadFileOrThumbnail {
                             content_type: thumbnail_content_type.to_string(),
                             cache_key: thumbnail_media_request.clone(),
+                            local_file: None,
                             related_to: send_event_txn.clone(),
                             parent_is_thumbnail_upload: false,
                         },
@@ -1539,6 +1637,7 @@ impl QueueStorage {
                     DependentQueuedRequestKind::UploadFileOrThumbnail {
                         content_type: content_type.to_string(),
                         cache_key: file_media_request.clone(),
+                        local_file: None,
                         related_to: send_event_txn.clone(),
                         parent_is_thumbnail_upload: thumbnail.is_some(),
                     },
@@ -1588,6 +1687,7 @@ impl QueueStorage {
         created_at: MilliSecondsSinceUnixEpoch,
         upload_file_txn: OwnedTransactionId,
         file_media_request: MediaRequestParameters,
+        local_file: Option<PathBuf>,
         thumbnail: Option<QueueThumbnailInfo>,
     ) -> Result<Option<FinishUploadThumbnailInfo>, RoomSendQueueStorageError> {
         if let Some(QueueThumbnailInfo {
@@ -1608,6 +1708,9 @@ impl QueueStorage {
                     QueuedRequestKind::MediaUpload {
                         content_type: thumbnail_content_type.to_string(),
                         cache_key: thumbnail_media_request,
+                        // Thumbnails are always stored in the media cache.
+                        local_file: None,
+                        resumable_upload: None,
                         thumbnail_source: None, // the thumbnail has no thumbnails :)
                         related_to: send_event_txn.clone(),
                         #[cfg(feature = "unstable-msc4274")]
@@ -1627,6 +1730,7 @@ impl QueueStorage {
                     DependentQueuedRequestKind::UploadFileOrThumbnail {
                         content_type: content_type.to_string(),
                         cache_key: file_media_request,
+                        local_file,
                         related_to: send_event_txn,
                         parent_is_thumbnail_upload: true,
                     },
@@ -1644,6 +1748,8 @@ impl QueueStorage {
                     QueuedRequestKind::MediaUpload {
                         content_type: content_type.to_string(),
                         cache_key: file_media_request,
+                        local_file,
+                        resumable_upload: None,
                         thumbnail_source: None,
                         related_to: send_event_txn,
                         #[cfg(feature = "unstable-msc4274")]
@@ -2018,6 +2124,7 @@ impl QueueStorage {
             DependentQueuedRequestKind::UploadFileOrThumbnail {
                 content_type,
                 cache_key,
+                local_file,
                 related_to,
                 parent_is_thumbnail_upload,
             } => {
@@ -2031,6 +2138,7 @@ impl QueueStorage {
                     parent_key,
                     content_type,
                     cache_key,
+                    local_file,
                     related_to,
                     parent_is_thumbnail_upload,
                 )
@@ -2326,6 +2434,11 @@ pub enum RoomSendQueueError {
     #[error("the attachment event could not be created")]
     FailedToCreateAttachment,
 
+    /// The local file holding an attachment couldn't be accessed.
+    #[cfg(not(target_family = "wasm"))]
+    #[error("the attachment file could not be accessed: {0}")]
+    AttachmentFileError(#[source] std::io::Error),
+
     /// The gallery contains no items.
     #[cfg(feature = "unstable-msc4274")]
     #[error("the gallery contains no items")]
@@ -2588,6 +2701,61 @@ impl SendHandle {
         Ok(())
     }
 
+    /// Pauses the upload(s) of the media attached to this local echo, if they
+    /// haven't completed yet.
+    ///
+    /// An upload that is in flight is interrupted; if resumable uploads are
+    /// enabled with [`SendQueue::enable_resumable_uploads`], it will carry on
+    /// from the last uploaded chunk once resumed with [`Self::resume_upload`].
+    /// The other requests of the room's send queue keep on being sent in the
+    /// meanwhile.
+    ///
+    /// The pause isn't persisted: a paused upload will be resumed after the
+    /// client is restarted.
+    ///
+    /// Returns true if an upload has been paused, false if this handle isn't
+    /// about a media, or if all its uploads have completed already.
+    #[instrument(skip(self), fields(room_id = %self.room.inner.room.room_id(), txn_id = %self.transaction_id))]
+    pub async fn pause_upload(&self) -> Result<bool, RoomSendQueueStorageError> {
+        trace!("received a pause request");
+
+        let mut paused = false;
+
+        for handles in &self.media_handles {
+            paused |= self.room.inner.queue.pause_upload(handles).await?;
+        }
+
+        Ok(paused)
+    }
+
+    /// Resumes the upload(s) previously paused with [`Self::pause_upload`].
+    ///
+    /// Returns true if an upload has been resumed, false if no upload was
+    /// paused.
+    #[instrument(skip(self), fields(room_id = %self.room.inner.room.room_id(), txn_id = %self.transaction_id))]
+    pub fn resume_upload(&self) -> bool {
+        trace!("received a resume request");
+
+        let mut resumed = false;
+
+        for handles in &self.media_handles {
+            resumed |= self.room.inner.queue.resume_upload(handles);
+        }
+
+        if resumed {
+            // Wake up the queue, in case the room was asleep while the upload was paused.
+            self.room.inner.notifier.notify_one();
+        }
+
+        resumed
+    }
+
+    /// Whether the upload(s) of the media attached to this local echo have been
+    /// paused with [`Self::pause_upload`].
+    pub fn is_upload_paused(&self) -> bool {
+        self.media_handles.iter().any(|handles| self.room.inner.queue.is_upload_paused(handles))
+    }
+
     /// Send a reaction to the event as soon as it's sent.
     ///
     /// If returning `Ok(None)`; this means the reaction couldn't be sent
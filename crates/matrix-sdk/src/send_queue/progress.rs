@@ -14,7 +14,7 @@
 
 //! Progress facilities for the media upload system.
 
-use std::ops::Add;
+use std::{ops::Add, path::Path};
 
 use eyeball::SharedObservable;
 #[cfg(feature = "unstable-msc4274")]
@@ -77,6 +77,7 @@ impl RoomSendQueue {
         own_txn_id: &TransactionId,
         related_to: &TransactionId,
         cache_key: &MediaRequestParameters,
+        local_file: Option<&Path>,
         thumbnail_source: Option<&MediaSource>,
         #[cfg(feature = "unstable-msc4274")] accumulated: &[AccumulatedSentMediaInfo],
         room: &Room,
@@ -93,18 +94,12 @@ impl RoomSendQueue {
             }
         };
 
-        // Get the size of the file being uploaded from the event cache.
-        let bytes = match room.client().event_cache_store().lock().await {
-            Ok(cache) => match cache.get_media_content(cache_key).await {
-                Ok(Some(content)) => content.len(),
-                Ok(None) => 0,
-                Err(err) => {
-                    warn!("error when reading media content from cache store: {err}");
-                    0
-                }
-            },
+        // Get the size of the file being uploaded from the local file or the event
+        // cache.
+        let bytes = match media_size(room, cache_key, local_file).await {
+            Ok(size) => size.unwrap_or(0),
             Err(err) => {
-                warn!("couldn't acquire cache store lock: {err}");
+                warn!("error when getting the size of the media: {err}");
                 0
             }
         };
@@ -167,7 +162,7 @@ impl RoomSendQueue {
 
         // Try to find a depending request which depends on the target one, and that's a
         // media upload.
-        let Some((cache_key, parent_is_thumbnail_upload)) =
+        let Some((cache_key, local_file, parent_is_thumbnail_upload)) =
             dependent_requests.into_iter().find_map(|r| {
                 if r.parent_transaction_id != txn_id {
                     return None;
@@ -175,11 +170,12 @@ impl RoomSendQueue {
 
                 if let DependentQueuedRequestKind::UploadFileOrThumbnail {
                     cache_key,
+                    local_file,
                     parent_is_thumbnail_upload,
                     ..
                 } = r.kind
                 {
-                    Some((cache_key, parent_is_thumbnail_upload))
+                    Some((cache_key, local_file, parent_is_thumbnail_upload))
                 } else {
                     None
                 }
@@ -195,11 +191,7 @@ impl RoomSendQueue {
             return Ok(None);
         }
 
-        let cache_store_guard = client.event_cache_store().lock().await?;
-
-        let maybe_content = cache_store_guard.get_media_content(&cache_key).await?;
-
-        Ok(maybe_content.map(|c| c.len()))
+        media_size(room, &cache_key, local_file.as_deref()).await
     }
 
     /// Create an observable to watch a media's upload progress.
@@ -236,6 +228,33 @@ impl RoomSendQueue {
     }
 }
 
+/// Get the size of a media to upload, from its local file if it has one, or
+/// from the event cache otherwise.
+async fn media_size(
+    room: &Room,
+    cache_key: &MediaRequestParameters,
+    local_file: Option<&Path>,
+) -> Result<Option<usize>, RoomSendQueueStorageError> {
+    if let Some(local_file) = local_file {
+        cfg_if::cfg_if! {
+            if #[cfg(not(target_family = "wasm"))] {
+                return Ok(tokio::fs::metadata(local_file)
+                    .await
+                    .ok()
+                    .map(|metadata| metadata.len() as usize));
+            } else {
+                let _ = local_file;
+                return Ok(None);
+            }
+        }
+    }
+
+    let cache_store_guard = room.client().event_cache_store().lock().await?;
+    let maybe_content = cache_store_guard.get_media_content(cache_key).await?;
+
+    Ok(maybe_content.map(|c| c.len()))
+}
+
 /// Estimates the upload progress for a single media file (either a thumbnail or
 /// a file).
 ///
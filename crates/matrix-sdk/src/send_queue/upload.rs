@@ -14,14 +14,31 @@
 
 //! Private implementations of the media upload mechanism.
 
+#[cfg(not(target_family = "wasm"))]
+use std::{
+    collections::BTreeMap,
+    io::{self, Read},
+    sync::atomic::Ordering,
+};
 #[cfg(feature = "unstable-msc4274")]
 use std::{collections::HashMap, iter::zip};
+use std::{
+    iter,
+    path::{Path, PathBuf},
+};
 
+#[cfg(not(target_family = "wasm"))]
+use bytes::Bytes;
+use eyeball::SharedObservable;
+#[cfg(all(feature = "e2e-encryption", not(target_family = "wasm")))]
+use matrix_sdk_base::crypto::{AttachmentEncryptor, MediaEncryptionInfo};
+#[cfg(not(target_family = "wasm"))]
+use matrix_sdk_base::store::{ResumableUploadEncryption, ResumableUploadState};
 use matrix_sdk_base::{
     event_cache::store::media::IgnoreMediaRetentionPolicy,
     media::{MediaFormat, MediaRequestParameters},
     store::{
-        ChildTransactionId, DependentQueuedRequestKind, FinishUploadThumbnailInfo,
+        ChildTransactionId, DependentQueuedRequestKind, FinishUploadThumbnailInfo, QueueWedgeError,
         QueuedRequestKind, SentMediaInfo, SentRequestKey, SerializableEventContent,
     },
     RoomState,
@@ -34,6 +51,11 @@ use matrix_sdk_base::{
 use mime::Mime;
 #[cfg(feature = "unstable-msc4274")]
 use ruma::events::room::message::{GalleryItemType, GalleryMessageEventContent};
+#[cfg(not(target_family = "wasm"))]
+use ruma::{
+    events::room::{EncryptedFile, EncryptedFileInit},
+    serde::Base64,
+};
 use ruma::{
     events::{
         room::{
@@ -44,6 +66,10 @@ use ruma::{
     },
     MilliSecondsSinceUnixEpoch, OwnedTransactionId, TransactionId,
 };
+#[cfg(not(target_family = "wasm"))]
+use sha2::{Digest as _, Sha256};
+#[cfg(not(target_family = "wasm"))]
+use tokio::sync::mpsc;
 use tracing::{debug, error, instrument, trace, warn, Span};
 
 use super::{QueueStorage, QueueThumbnailInfo, RoomSendQueue, RoomSendQueueError};
@@ -54,13 +80,142 @@ use crate::{
         LocalEcho, LocalEchoContent, MediaHandles, RoomSendQueueStorageError, RoomSendQueueUpdate,
         SendHandle,
     },
-    Client, Media, Room,
+    Client, Media, Room, TransmissionProgress,
 };
 #[cfg(feature = "unstable-msc4274")]
 use crate::{
     attachment::{GalleryConfig, GalleryItemInfo},
     send_queue::GalleryItemQueueInfo,
 };
+#[cfg(not(target_family = "wasm"))]
+use crate::{
+    config::RequestConfig,
+    media::{MediaError, UploadStream},
+};
+
+/// The size of the chunks of a resumable upload.
+#[cfg(not(target_family = "wasm"))]
+const RESUMABLE_UPLOAD_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// The outcome of reading the chunks of a resumable upload.
+#[cfg(not(target_family = "wasm"))]
+enum ReadChunksOutcome {
+    /// All the chunks have been read, with the hashes of the whole encrypted
+    /// content, if it's encrypted.
+    Done(Option<BTreeMap<String, Base64>>),
+
+    /// The bytes that have been uploaded already don't match the media
+    /// anymore, the upload must be started over.
+    ContentChanged,
+}
+
+/// Read the content of a media from `reader`, encrypting it if needed, and
+/// send the chunks following the first `offset` bytes to `sender`, along with
+/// the SHA-256 hash of all the bytes read up to the end of each chunk.
+///
+/// The first `offset` bytes must match `uploaded_hash`, otherwise nothing is
+/// sent.
+#[cfg(not(target_family = "wasm"))]
+fn read_chunks_into(
+    mut reader: Box<dyn Read + Send>,
+    encryption: Option<ResumableUploadEncryption>,
+    offset: u64,
+    uploaded_hash: Option<Base64>,
+    sender: mpsc::Sender<(Bytes, Base64)>,
+) -> io::Result<ReadChunksOutcome> {
+    #[cfg(feature = "e2e-encryption")]
+    if let Some(encryption) = encryption {
+        let info = MediaEncryptionInfo {
+            version: "v2".to_owned(),
+            key: encryption.key,
+            iv: encryption.iv,
+            hashes: Default::default(),
+        };
+        let mut encryptor = AttachmentEncryptor::with_encryption_info(&mut reader, &info)
+            .map_err(io::Error::other)?;
+
+        // The key and initialization vector must never be used for a different content:
+        // the encrypted bytes that have been uploaded already are checked before
+        // sending anything new.
+        if !send_chunks(&mut encryptor, offset, uploaded_hash.as_ref(), &sender)? {
+            return Ok(ReadChunksOutcome::ContentChanged);
+        }
+
+        return Ok(ReadChunksOutcome::Done(Some(encryptor.finish().hashes)));
+    }
+
+    #[cfg(not(feature = "e2e-encryption"))]
+    if encryption.is_some() {
+        return Err(io::Error::other(
+            "can't resume an encrypted upload without encryption support",
+        ));
+    }
+
+    if !send_chunks(&mut reader, offset, uploaded_hash.as_ref(), &sender)? {
+        return Ok(ReadChunksOutcome::ContentChanged);
+    }
+
+    Ok(ReadChunksOutcome::Done(None))
+}
+
+/// Skip the first `offset` bytes of `reader`, checking that they match
+/// `uploaded_hash`, and send the rest to `sender` in chunks of
+/// [`RESUMABLE_UPLOAD_CHUNK_SIZE`] bytes.
+///
+/// Returns `false` if the skipped bytes don't match `uploaded_hash`.
+#[cfg(not(target_family = "wasm"))]
+fn send_chunks(
+    reader: &mut impl Read,
+    offset: u64,
+    uploaded_hash: Option<&Base64>,
+    sender: &mpsc::Sender<(Bytes, Base64)>,
+) -> io::Result<bool> {
+    let mut hasher = Sha256::new();
+
+    // The bytes that have been uploaded already must still be read, so the
+    // encryption (if any) carries on with the same state.
+    if offset > 0 {
+        let mut uploaded = reader.by_ref().take(offset);
+        let mut buf = vec![0; 64 * 1024];
+        let mut skipped = 0;
+
+        loop {
+            let read = match uploaded.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+
+            hasher.update(&buf[..read]);
+            skipped += read as u64;
+        }
+
+        let matches_uploaded = skipped == offset
+            && uploaded_hash
+                .is_some_and(|hash| hash.as_bytes() == hasher.clone().finalize().as_slice());
+
+        if !matches_uploaded {
+            return Ok(false);
+        }
+    }
+
+    loop {
+        let mut chunk = Vec::new();
+        reader.by_ref().take(RESUMABLE_UPLOAD_CHUNK_SIZE).read_to_end(&mut chunk)?;
+
+        if chunk.is_empty() {
+            return Ok(true);
+        }
+
+        hasher.update(&chunk);
+        let hash = Base64::new(hasher.clone().finalize().to_vec());
+
+        if sender.blocking_send((chunk.into(), hash)).is_err() {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "the upload has been aborted"));
+        }
+    }
+}
 
 /// Replace the source by the final ones in all the media types handled by
 /// [`Room::make_attachment_type()`].
@@ -166,6 +321,16 @@ fn update_gallery_event_after_upload(
     }
 }
 
+/// Where the send queue can find the content of an attachment.
+enum QueuedAttachment {
+    /// The content is copied into the media cache.
+    Data(Vec<u8>),
+
+    /// The content is read from a local file when it's uploaded.
+    #[cfg(not(target_family = "wasm"))]
+    File(PathBuf),
+}
+
 #[derive(Default)]
 struct MediaCacheResult {
     upload_thumbnail_txn: Option<OwnedTransactionId>,
@@ -199,6 +364,65 @@ impl RoomSendQueue {
         filename: impl Into<String>,
         content_type: Mime,
         data: Vec<u8>,
+        config: AttachmentConfig,
+    ) -> Result<SendHandle, RoomSendQueueError> {
+        self.queue_attachment(filename.into(), content_type, QueuedAttachment::Data(data), config)
+            .await
+    }
+
+    /// Queues an attachment stored in a local file to be sent to the room,
+    /// using the send queue.
+    ///
+    /// This behaves like [`Self::send_attachment()`], except that the content
+    /// of the file isn't copied into the media cache: only its path is
+    /// persisted, and the file is encrypted if needs be and uploaded chunk by
+    /// chunk, without ever being entirely loaded in memory. This should be
+    /// preferred for large files.
+    ///
+    /// The file must not be modified, moved or removed until the upload is
+    /// done; if it has disappeared by then, the request will be wedged with
+    /// [`QueueWedgeError::MissingMediaContent`].
+    ///
+    /// Since the attachment isn't stored in the media cache, it can't be
+    /// retrieved with [`Media::get_media_content()`] until it's been uploaded;
+    /// its optional thumbnail can, though.
+    #[cfg(not(target_family = "wasm"))]
+    #[instrument(skip_all, fields(event_txn))]
+    pub async fn send_attachment_file(
+        &self,
+        filename: impl Into<String>,
+        content_type: Mime,
+        path: impl Into<PathBuf>,
+        mut config: AttachmentConfig,
+    ) -> Result<SendHandle, RoomSendQueueError> {
+        // The path is persisted, so make sure it doesn't depend on the current
+        // directory. This also makes sure the file exists, rather than wedging
+        // the request later.
+        let path = tokio::fs::canonicalize(path.into())
+            .await
+            .map_err(RoomSendQueueError::AttachmentFileError)?;
+        let metadata =
+            tokio::fs::metadata(&path).await.map_err(RoomSendQueueError::AttachmentFileError)?;
+        if !metadata.is_file() {
+            return Err(RoomSendQueueError::AttachmentFileError(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the attachment path doesn't point to a file",
+            )));
+        }
+
+        if let Some(info) = &mut config.info {
+            info.fill_voice_metadata_from_file(&content_type, &path, metadata.len()).await;
+        }
+
+        self.queue_attachment(filename.into(), content_type, QueuedAttachment::File(path), config)
+            .await
+    }
+
+    async fn queue_attachment(
+        &self,
+        filename: String,
+        content_type: Mime,
+        attachment: QueuedAttachment,
         mut config: AttachmentConfig,
     ) -> Result<SendHandle, RoomSendQueueError> {
         let Some(room) = self.inner.room.get() else {
@@ -209,7 +433,6 @@ impl RoomSendQueue {
             return Err(RoomSendQueueError::RoomNotJoined);
         }
 
-        let filename = filename.into();
         let upload_file_txn = TransactionId::new();
         let send_event_txn = config.txn_id.map_or_else(ChildTransactionId::new, Into::into);
 
@@ -218,6 +441,18 @@ impl RoomSendQueue {
 
         let file_media_request = Media::make_local_file_media_request(&upload_file_txn);
 
+        let (data, local_file) = match attachment {
+            QueuedAttachment::Data(data) => {
+                if let Some(info) = &mut config.info {
+                    info.fill_voice_metadata(&content_type, &data);
+                }
+
+                (Some(data), None)
+            }
+            #[cfg(not(target_family = "wasm"))]
+            QueuedAttachment::File(path) => (None, Some(path)),
+        };
+
         let MediaCacheResult { upload_thumbnail_txn, event_thumbnail_info, queue_thumbnail_info } =
             RoomSendQueue::cache_media(&room, data, config.thumbnail.take(), &file_media_request)
                 .await?;
@@ -252,6 +487,7 @@ impl RoomSendQueue {
                 created_at,
                 upload_file_txn.clone(),
                 file_media_request,
+                local_file,
                 queue_thumbnail_info,
             )
             .await?;
@@ -339,8 +575,13 @@ impl RoomSendQueue {
                 upload_thumbnail_txn,
                 event_thumbnail_info,
                 queue_thumbnail_info,
-            } = RoomSendQueue::cache_media(&room, data, item_info.thumbnail, &file_media_request)
-                .await?;
+            } = RoomSendQueue::cache_media(
+                &room,
+                Some(data),
+                item_info.thumbnail,
+                &file_media_request,
+            )
+            .await?;
 
             item_types.push(Room::make_gallery_item_type(
                 &content_type,
@@ -413,9 +654,13 @@ impl RoomSendQueue {
         Ok(send_handle)
     }
 
+    /// Caches the file and its thumbnail in the cache store.
+    ///
+    /// If `data` is `None`, the file is read from a local file when uploading
+    /// it, and only the thumbnail is cached.
     async fn cache_media(
         room: &Room,
-        data: Vec<u8>,
+        data: Option<Vec<u8>>,
         thumbnail: Option<Thumbnail>,
         file_media_request: &MediaRequestParameters,
     ) -> Result<MediaCacheResult, RoomSendQueueError> {
@@ -427,15 +672,17 @@ impl RoomSendQueue {
             .map_err(RoomSendQueueStorageError::LockError)?;
 
         // Cache the file itself in the cache store.
-        cache_store
-            .add_media_content(
-                file_media_request,
-                data,
-                // Make sure that the file is stored until it has been uploaded.
-                IgnoreMediaRetentionPolicy::Yes,
-            )
-            .await
-            .map_err(RoomSendQueueStorageError::EventCacheStoreError)?;
+        if let Some(data) = data {
+            cache_store
+                .add_media_content(
+                    file_media_request,
+                    data,
+                    // Make sure that the file is stored until it has been uploaded.
+                    IgnoreMediaRetentionPolicy::Yes,
+                )
+                .await
+                .map_err(RoomSendQueueStorageError::EventCacheStoreError)?;
+        }
 
         // Process the thumbnail, if it's been provided.
         if let Some(thumbnail) = thumbnail {
@@ -603,6 +850,65 @@ impl QueueStorage {
         Ok(())
     }
 
+    /// Uploads the content of a local file, streaming it from disk and
+    /// encrypting it on the fly if the room is encrypted.
+    #[cfg(not(target_family = "wasm"))]
+    pub(super) async fn upload_local_file(
+        room: &Room,
+        path: &Path,
+        content_type: &Mime,
+        progress: Option<SharedObservable<TransmissionProgress>>,
+    ) -> Result<MediaSource, crate::Error> {
+        let stream = UploadStream::from_path(path).await.map_err(|err| {
+            if err.kind() == std::io::ErrorKind::NotFound {
+                crate::Error::SendQueueWedgeError(Box::new(QueueWedgeError::MissingMediaContent))
+            } else {
+                err.into()
+            }
+        })?;
+
+        #[cfg(feature = "e2e-encryption")]
+        if room.latest_encryption_state().await?.is_encrypted() {
+            trace!("upload will be encrypted (encrypted room)");
+
+            let mut req = room
+                .client()
+                .upload_encrypted_stream(stream)
+                .with_request_config(RequestConfig::short_retry());
+            if let Some(progress) = progress {
+                req = req.with_send_progress_observable(progress);
+            }
+            let encrypted_file = req.await?;
+
+            return Ok(MediaSource::Encrypted(Box::new(encrypted_file)));
+        }
+
+        trace!("upload will be in clear text (room without encryption)");
+
+        let request_config = RequestConfig::short_retry()
+            .timeout(Media::reasonable_upload_timeout_for_length(stream.len()));
+        let mut req =
+            room.client().media().upload_stream(content_type, stream, Some(request_config));
+        if let Some(progress) = progress {
+            req = req.with_send_progress_observable(progress);
+        }
+        let res = req.await?;
+
+        Ok(MediaSource::Plain(res.content_uri))
+    }
+
+    /// Local files can't be streamed on this platform, so this always fails.
+    #[cfg(target_family = "wasm")]
+    pub(super) async fn upload_local_file(
+        _room: &Room,
+        path: &Path,
+        _content_type: &Mime,
+        _progress: Option<SharedObservable<TransmissionProgress>>,
+    ) -> Result<MediaSource, crate::Error> {
+        warn!(?path, "can't upload a local file on this platform");
+        Err(crate::Error::SendQueueWedgeError(Box::new(QueueWedgeError::MissingMediaContent)))
+    }
+
     /// Consumes a finished file or thumbnail upload and queues the dependent
     /// file or thumbnail upload.
     #[allow(clippy::too_many_arguments)]
@@ -613,6 +919,7 @@ impl QueueStorage {
         parent_key: SentRequestKey,
         content_type: String,
         cache_key: MediaRequestParameters,
+        local_file: Option<PathBuf>,
         event_txn: OwnedTransactionId,
         parent_is_thumbnail_upload: bool,
     ) -> Result<(), RoomSendQueueError> {
@@ -657,6 +964,8 @@ impl QueueStorage {
         let request = QueuedRequestKind::MediaUpload {
             content_type,
             cache_key,
+            local_file,
+            resumable_upload: None,
             // If the previous upload was a thumbnail, it becomes the thumbnail source for the next
             // upload.
             thumbnail_source: parent_is_thumbnail_upload.then_some(sent_media.file),
@@ -802,10 +1111,338 @@ impl QueueStorage {
             }
         }
 
+        {
+            let mut paused_uploads = self.paused_uploads.lock();
+            paused_uploads.remove(&handles.upload_file_txn);
+            if let Some(txn) = &handles.upload_thumbnail_txn {
+                paused_uploads.remove(txn);
+            }
+        }
+
         debug!("successfully aborted!");
         Ok(true)
     }
 
+    /// Pauses the uploads related to a media, interrupting the one being sent,
+    /// if any.
+    ///
+    /// Returns whether one of the uploads was still pending.
+    pub(super) async fn pause_upload(
+        &self,
+        handles: &MediaHandles,
+    ) -> Result<bool, RoomSendQueueStorageError> {
+        // Keep the lock until we're done touching the storage and the request being
+        // sent.
+        let mut guard = self.store.lock().await;
+        let client = guard.client()?;
+        let store = client.state_store();
+
+        let upload_txns = handles
+            .upload_thumbnail_txn
+            .iter()
+            .chain(iter::once(&handles.upload_file_txn))
+            .collect::<Vec<_>>();
+
+        // The uploads are pending as long as one of them is a queued request, or the
+        // file upload is still waiting on the thumbnail upload.
+        let upload_file_as_dependent = ChildTransactionId::from(handles.upload_file_txn.clone());
+        let is_pending = store
+            .load_send_queue_requests(&self.room_id)
+            .await?
+            .iter()
+            .any(|request| upload_txns.contains(&&request.transaction_id))
+            || store
+                .load_dependent_queued_requests(&self.room_id)
+                .await?
+                .iter()
+                .any(|request| request.own_transaction_id == upload_file_as_dependent);
+
+        if !is_pending {
+            debug!("uploads have completed already, nothing to pause");
+            return Ok(false);
+        }
+
+        self.paused_uploads.lock().extend(upload_txns.iter().map(|txn| (*txn).clone()));
+
+        if let Some(info) = guard.being_sent.as_ref() {
+            if upload_txns.contains(&&info.transaction_id) {
+                // SAFETY: we knew it was Some(), two lines above.
+                let info = guard.being_sent.take().unwrap();
+                if info.cancel_upload() {
+                    trace!("interrupted ongoing upload");
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Resumes the uploads related to a media, paused with
+    /// [`Self::pause_upload`].
+    ///
+    /// Returns whether one of the uploads was paused.
+    pub(super) fn resume_upload(&self, handles: &MediaHandles) -> bool {
+        let mut paused_uploads = self.paused_uploads.lock();
+
+        let mut resumed = paused_uploads.remove(&handles.upload_file_txn);
+        if let Some(txn) = &handles.upload_thumbnail_txn {
+            resumed |= paused_uploads.remove(txn);
+        }
+
+        resumed
+    }
+
+    /// Whether the uploads related to a media have been paused.
+    pub(super) fn is_upload_paused(&self, handles: &MediaHandles) -> bool {
+        self.paused_uploads.lock().contains(&handles.upload_file_txn)
+    }
+
+    /// Persists how far the resumable upload of the given media upload request
+    /// went, or forgets about it if `state` is `None`.
+    ///
+    /// `kind` is the media upload request, which is updated in place and saved
+    /// as a whole.
+    #[cfg(not(target_family = "wasm"))]
+    async fn save_resumable_upload_state(
+        &self,
+        transaction_id: &TransactionId,
+        kind: &mut QueuedRequestKind,
+        state: Option<ResumableUploadState>,
+    ) -> Result<(), crate::Error> {
+        if let QueuedRequestKind::MediaUpload { resumable_upload, .. } = kind {
+            *resumable_upload = state;
+        }
+
+        let guard = self.store.lock().await;
+        let client = guard.client().map_err(|err| crate::Error::UnknownError(Box::new(err)))?;
+
+        let updated = client
+            .state_store()
+            .update_send_queue_request(&self.room_id, transaction_id, kind.clone())
+            .await?;
+
+        if !updated {
+            warn!(txn_id = %transaction_id, "missing upload request when saving its progress");
+        }
+
+        Ok(())
+    }
+
+    /// Uploads the content of a media in chunks, if resumable uploads are
+    /// enabled, or if this upload was started in chunks already.
+    ///
+    /// The byte offset reached by the upload is persisted after each chunk, so
+    /// that an interrupted upload restarts from there, and not from scratch.
+    /// If the media has changed in the meantime, the upload is started over
+    /// with a regular upload.
+    ///
+    /// `kind` is the media upload request.
+    ///
+    /// Returns `None` if the media must be uploaded in a single request
+    /// instead.
+    #[cfg(not(target_family = "wasm"))]
+    pub(super) async fn upload_resumable(
+        &self,
+        room: &Room,
+        transaction_id: &TransactionId,
+        content_type: &Mime,
+        mut kind: QueuedRequestKind,
+        progress: Option<SharedObservable<TransmissionProgress>>,
+    ) -> Result<Option<MediaSource>, crate::Error> {
+        let QueuedRequestKind::MediaUpload { cache_key, local_file, resumable_upload, .. } = &kind
+        else {
+            return Ok(None);
+        };
+        let (cache_key, local_file, state) =
+            (cache_key.clone(), local_file.clone(), resumable_upload.clone());
+
+        if state.is_none() && !self.resumable_uploads.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+
+        let client = room.client();
+        let media = client.media();
+
+        let (reader, total_length): (Box<dyn Read + Send>, u64) = if let Some(path) = local_file {
+            let file = tokio::fs::File::open(path).await.map_err(|err| {
+                if err.kind() == io::ErrorKind::NotFound {
+                    crate::Error::SendQueueWedgeError(Box::new(
+                        QueueWedgeError::MissingMediaContent,
+                    ))
+                } else {
+                    err.into()
+                }
+            })?;
+            let length = file.metadata().await?.len();
+
+            (Box::new(file.into_std().await), length)
+        } else {
+            let data = client
+                .event_cache_store()
+                .lock()
+                .await?
+                .get_media_content(&cache_key)
+                .await?
+                .ok_or(crate::Error::SendQueueWedgeError(Box::new(
+                    QueueWedgeError::MissingMediaContent,
+                )))?;
+            let length = data.len() as u64;
+
+            (Box::new(io::Cursor::new(data)), length)
+        };
+
+        // An empty content can't be described with a byte range.
+        if total_length == 0 {
+            return Ok(None);
+        }
+
+        let mut state = match state {
+            Some(state) if state.total_length != total_length => {
+                // The media has changed, it can't be encrypted with the same key again, nor
+                // uploaded to the same MXC URI: start over with a regular upload.
+                warn!(
+                    mxc_uri = %state.mxc_uri,
+                    previous_length = state.total_length,
+                    total_length,
+                    "the media's length has changed, restarting upload"
+                );
+                self.save_resumable_upload_state(transaction_id, &mut kind, None).await?;
+                return Ok(None);
+            }
+
+            Some(state) => {
+                trace!(offset = state.offset, total_length, "resuming upload");
+                state
+            }
+
+            None => {
+                let mxc_uri = media.create_content_uri().await?.uri;
+
+                #[cfg(feature = "e2e-encryption")]
+                let encryption = if room.latest_encryption_state().await?.is_encrypted() {
+                    // Only the key and initialization vector are needed, so that the content can
+                    // be encrypted again in the same way when resuming the upload.
+                    let info = AttachmentEncryptor::new(&mut io::empty()).finish();
+                    Some(ResumableUploadEncryption { key: info.key, iv: info.iv })
+                } else {
+                    None
+                };
+
+                #[cfg(not(feature = "e2e-encryption"))]
+                let encryption = None;
+
+                let state = ResumableUploadState {
+                    mxc_uri,
+                    offset: 0,
+                    total_length,
+                    uploaded_hash: None,
+                    encryption,
+                };
+                self.save_resumable_upload_state(transaction_id, &mut kind, Some(state.clone()))
+                    .await?;
+
+                trace!(mxc_uri = %state.mxc_uri, total_length, "starting upload in chunks");
+                state
+            }
+        };
+
+        // The encryptor only works over a blocking reader, so read (and encrypt) the
+        // chunks on a dedicated thread, and upload them as they come.
+        let (sender, mut receiver) = mpsc::channel(1);
+        let offset = state.offset;
+        let encryption = state.encryption.clone();
+        let uploaded_hash = state.uploaded_hash.clone();
+        let producer = tokio::task::spawn_blocking(move || {
+            read_chunks_into(reader, encryption, offset, uploaded_hash, sender)
+        });
+
+        let upload_content_type = if state.encryption.is_some() {
+            mime::APPLICATION_OCTET_STREAM
+        } else {
+            content_type.clone()
+        };
+
+        while let Some((chunk, uploaded_hash)) = receiver.recv().await {
+            let length = chunk.len() as u64;
+
+            if let Err(err) = media
+                .upload_preallocated_chunk(
+                    &state.mxc_uri,
+                    &upload_content_type,
+                    chunk,
+                    state.offset,
+                    total_length,
+                )
+                .await
+            {
+                if matches!(err, crate::Error::Media(MediaError::ExpiredPreallocatedMxcUri)) {
+                    // The content can't be uploaded to this MXC URI anymore: forget about it and
+                    // start over with a regular upload.
+                    warn!(mxc_uri = %state.mxc_uri, "preallocated MXC URI expired, restarting upload");
+                    self.save_resumable_upload_state(transaction_id, &mut kind, None).await?;
+                    return Ok(None);
+                }
+
+                return Err(err);
+            }
+
+            state.offset += length;
+            state.uploaded_hash = Some(uploaded_hash);
+            self.save_resumable_upload_state(transaction_id, &mut kind, Some(state.clone()))
+                .await?;
+
+            if let Some(progress) = &progress {
+                progress.set(TransmissionProgress {
+                    current: state.offset as usize,
+                    total: total_length as usize,
+                });
+            }
+        }
+
+        let hashes = match producer.await.map_err(io::Error::other)?? {
+            ReadChunksOutcome::Done(hashes) => hashes,
+
+            ReadChunksOutcome::ContentChanged => {
+                // The media has changed, it can't be encrypted with the same key again, nor
+                // uploaded to the same MXC URI: start over with a regular upload.
+                warn!(mxc_uri = %state.mxc_uri, "the media has changed, restarting upload");
+                self.save_resumable_upload_state(transaction_id, &mut kind, None).await?;
+                return Ok(None);
+            }
+        };
+
+        let media_source = if let Some(encryption) = state.encryption {
+            let file: EncryptedFile = EncryptedFileInit {
+                url: state.mxc_uri,
+                key: encryption.key,
+                iv: encryption.iv,
+                hashes: hashes.unwrap_or_default(),
+                v: "v2".to_owned(),
+            }
+            .into();
+
+            MediaSource::Encrypted(Box::new(file))
+        } else {
+            MediaSource::Plain(state.mxc_uri)
+        };
+
+        Ok(Some(media_source))
+    }
+
+    /// Resumable uploads aren't supported on this platform, so this always
+    /// falls back to a regular upload.
+    #[cfg(target_family = "wasm")]
+    pub(super) async fn upload_resumable(
+        &self,
+        _room: &Room,
+        _transaction_id: &TransactionId,
+        _content_type: &Mime,
+        _kind: QueuedRequestKind,
+        _progress: Option<SharedObservable<TransmissionProgress>>,
+    ) -> Result<Option<MediaSource>, crate::Error> {
+        Ok(None)
+    }
+
     #[instrument(skip(self, caption, formatted_caption))]
     pub(super) async fn edit_media_caption(
         &self,
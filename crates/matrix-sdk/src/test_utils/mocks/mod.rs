@@ -1263,6 +1263,13 @@ impl MatrixMockServer {
         self.mock_endpoint(mock, UserDirectoryEndpoint).expect_default_access_token()
     }
 
+    /// Create a prebuilt mock for the endpoint used to get the TURN servers of
+    /// the homeserver.
+    pub fn mock_turn_servers(&self) -> MockEndpoint<'_, TurnServersEndpoint> {
+        let mock = Mock::given(method("GET")).and(path("/_matrix/client/v3/voip/turnServer"));
+        self.mock_endpoint(mock, TurnServersEndpoint).expect_default_access_token()
+    }
+
     /// Create a prebuilt mock for the endpoint used to create a new room.
     pub fn mock_create_room(&self) -> MockEndpoint<'_, CreateRoomEndpoint> {
         let mock = Mock::given(method("POST")).and(path("/_matrix/client/v3/createRoom"));
@@ -3721,6 +3728,28 @@ impl<'a> MockEndpoint<'a, UserDirectoryEndpoint> {
     }
 }
 
+/// A prebuilt mock for `GET /voip/turnServer` requests.
+pub struct TurnServersEndpoint;
+
+impl<'a> MockEndpoint<'a, TurnServersEndpoint> {
+    /// Returns a successful response with the given TURN server URIs and
+    /// credentials, valid for the given duration.
+    pub fn ok(
+        self,
+        uris: &[&str],
+        username: &str,
+        password: &str,
+        ttl: Duration,
+    ) -> MatrixMock<'a> {
+        self.respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "uris": uris,
+            "username": username,
+            "password": password,
+            "ttl": ttl.as_secs(),
+        })))
+    }
+}
+
 /// A prebuilt mock for `POST /createRoom` requests.
 pub struct CreateRoomEndpoint;
 
@@ -3763,6 +3792,18 @@ impl<'a> MockEndpoint<'a, MediaAllocateEndpoint> {
 pub struct MediaAllocatedUploadEndpoint;
 
 impl<'a> MockEndpoint<'a, MediaAllocatedUploadEndpoint> {
+    /// Expect that the request uploads the bytes from `start` to `end`
+    /// (inclusive) of a content of `total_length` bytes, with a
+    /// `Content-Range` header.
+    pub fn expect_content_range(self, start: u64, end: u64, total_length: u64) -> Self {
+        Self {
+            mock: self
+                .mock
+                .and(header("content-range", format!("bytes {start}-{end}/{total_length}"))),
+            ..self
+        }
+    }
+
     /// Returns a successful response.
     pub fn ok(self) -> MatrixMock<'a> {
         self.respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
@@ -3802,11 +3843,33 @@ impl<'a> MockEndpoint<'a, MediaThumbnailEndpoint> {
 pub struct AuthedMediaDownloadEndpoint;
 
 impl<'a> MockEndpoint<'a, AuthedMediaDownloadEndpoint> {
+    /// Expect that the content is requested from the given offset, with a
+    /// `Range` header.
+    pub fn expect_range_from(self, offset: u64) -> Self {
+        Self { mock: self.mock.and(header("range", format!("bytes={offset}-"))), ..self }
+    }
+
     /// Returns a successful response with a plain text content.
     pub fn ok_plain_text(self) -> MatrixMock<'a> {
         self.respond_with(ResponseTemplate::new(200).set_body_string("Hello, World!"))
     }
 
+    /// Returns a partial content response with the given bytes, starting at
+    /// `offset`, out of a content of `total_length` bytes.
+    pub fn ok_partial_bytes(
+        self,
+        bytes: Vec<u8>,
+        offset: u64,
+        total_length: u64,
+    ) -> MatrixMock<'a> {
+        let end = offset + bytes.len() as u64 - 1;
+        self.respond_with(
+            ResponseTemplate::new(206)
+                .insert_header("content-range", format!("bytes {offset}-{end}/{total_length}"))
+                .set_body_raw(bytes, "application/octet-stream"),
+        )
+    }
+
     /// Returns a successful response with the given bytes.
     pub fn ok_bytes(self, bytes: Vec<u8>) -> MatrixMock<'a> {
         self.respond_with(
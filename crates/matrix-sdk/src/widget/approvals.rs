@@ -0,0 +1,654 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistence of the capabilities that the user granted to, or denied,
+//! widgets.
+//!
+//! The [`CapabilityApprovals`] remember the decisions of the user per widget
+//! and room, either in the state store or for the current session only. The
+//! [`PersistedCapabilitiesProvider`] uses them to only prompt the user for the
+//! capabilities they haven't decided on yet.
+
+use std::{collections::BTreeSet, future::Future, sync::Arc};
+
+use matrix_sdk_common::{locks::Mutex as SyncMutex, SendOutsideWasm, SyncOutsideWasm};
+use ruma::{OwnedRoomId, RoomId};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex as AsyncMutex;
+use tracing::warn;
+
+use super::{Capabilities, CapabilitiesProvider};
+use crate::{Client, Result};
+
+/// The key under which the remembered capability approvals are persisted in
+/// the state store.
+const WIDGET_CAPABILITY_APPROVALS_STORE_KEY: &[u8] = b"widget_capability_approvals";
+
+/// How long a decision of the user about some capabilities is valid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApprovalLifetime {
+    /// The decision is kept in memory, and valid as long as the
+    /// [`CapabilityApprovals`] that recorded it, or one of its clones, is
+    /// alive.
+    ThisSession,
+    /// The decision is persisted, and valid until it is revoked.
+    Remember,
+}
+
+/// The answer of the user when prompted for some capabilities.
+#[derive(Clone, Debug)]
+pub struct CapabilitiesDecision {
+    /// The capabilities that the user approved. The other prompted
+    /// capabilities are considered denied.
+    pub approved: Capabilities,
+    /// How long this decision is valid.
+    pub lifetime: ApprovalLifetime,
+}
+
+/// Must be implemented by a component that asks the user whether a widget is
+/// allowed to use certain capabilities, to be used by a
+/// [`PersistedCapabilitiesProvider`].
+pub trait CapabilitiesPrompt: SendOutsideWasm + SyncOutsideWasm + 'static {
+    /// Prompts the user for the given capabilities, which they haven't decided
+    /// on yet for this widget, and returns their decision.
+    fn prompt(
+        &self,
+        capabilities: Capabilities,
+    ) -> impl Future<Output = CapabilitiesDecision> + SendOutsideWasm;
+}
+
+/// The decisions of the user about the capabilities of a widget in a room.
+#[derive(Clone, Debug)]
+pub struct WidgetCapabilityApprovals {
+    /// The room in which the widget runs.
+    pub room_id: OwnedRoomId,
+    /// The ID of the widget.
+    pub widget_id: String,
+    /// The capabilities that the user granted to the widget.
+    pub granted: Capabilities,
+    /// The capabilities that the user denied to the widget.
+    pub denied: Capabilities,
+    /// How long these decisions are valid.
+    pub lifetime: ApprovalLifetime,
+}
+
+/// The decisions about the capabilities of a widget in a room, as stored.
+///
+/// Capabilities are stored in their serialized form, so they can be compared
+/// and combined as sets.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct StoredApprovals {
+    room_id: OwnedRoomId,
+    widget_id: String,
+    granted: BTreeSet<String>,
+    denied: BTreeSet<String>,
+}
+
+impl StoredApprovals {
+    fn is_for(&self, room_id: &RoomId, widget_id: &str) -> bool {
+        self.room_id == room_id && self.widget_id == widget_id
+    }
+
+    fn is_empty(&self) -> bool {
+        self.granted.is_empty() && self.denied.is_empty()
+    }
+
+    /// Records new decisions, which override the previous ones.
+    fn record(&mut self, granted: &BTreeSet<String>, denied: &BTreeSet<String>) {
+        self.granted.retain(|c| !denied.contains(c));
+        self.granted.extend(granted.iter().cloned());
+        self.denied.retain(|c| !granted.contains(c));
+        self.denied.extend(denied.iter().cloned());
+    }
+
+    /// Forgets the decisions about the given capabilities.
+    fn forget(&mut self, capabilities: &BTreeSet<String>) {
+        self.granted.retain(|c| !capabilities.contains(c));
+        self.denied.retain(|c| !capabilities.contains(c));
+    }
+
+    fn to_approvals(&self, lifetime: ApprovalLifetime) -> WidgetCapabilityApprovals {
+        WidgetCapabilityApprovals {
+            room_id: self.room_id.clone(),
+            widget_id: self.widget_id.clone(),
+            granted: capabilities_from_set(&self.granted),
+            denied: capabilities_from_set(&self.denied),
+            lifetime,
+        }
+    }
+}
+
+/// Finds the entry for the given widget in the list, or adds an empty one.
+fn entry<'a>(
+    entries: &'a mut Vec<StoredApprovals>,
+    room_id: &RoomId,
+    widget_id: &str,
+) -> &'a mut StoredApprovals {
+    match entries.iter().position(|e| e.is_for(room_id, widget_id)) {
+        Some(index) => &mut entries[index],
+        None => {
+            entries.push(StoredApprovals {
+                room_id: room_id.to_owned(),
+                widget_id: widget_id.to_owned(),
+                granted: BTreeSet::new(),
+                denied: BTreeSet::new(),
+            });
+            entries.last_mut().expect("we just pushed an entry")
+        }
+    }
+}
+
+/// Converts capabilities to the set of their serialized forms.
+fn capabilities_to_set(capabilities: &Capabilities) -> BTreeSet<String> {
+    serde_json::to_value(capabilities)
+        .and_then(serde_json::from_value)
+        .expect("capabilities always serialize to a list of strings")
+}
+
+/// Converts a set of serialized capabilities back to capabilities.
+fn capabilities_from_set(set: &BTreeSet<String>) -> Capabilities {
+    // Unknown capabilities are ignored, so this can't fail.
+    serde_json::to_value(set)
+        .and_then(serde_json::from_value)
+        .expect("a list of strings always deserializes to capabilities")
+}
+
+/// The capabilities that the user granted to, or denied, widgets.
+///
+/// Remembered decisions are persisted in the state store, while the decisions
+/// for this session only are kept in memory, as long as this object (or one of
+/// its clones) is alive.
+#[derive(Clone, Debug)]
+pub struct CapabilityApprovals {
+    inner: Arc<CapabilityApprovalsInner>,
+}
+
+#[derive(Debug)]
+struct CapabilityApprovalsInner {
+    client: Client,
+
+    /// The decisions that are only valid for this session.
+    session: SyncMutex<Vec<StoredApprovals>>,
+
+    /// A lock to make sure the persisted decisions are not updated
+    /// concurrently, which would lose some of the updates.
+    store_lock: AsyncMutex<()>,
+}
+
+impl CapabilityApprovals {
+    /// Creates a new `CapabilityApprovals`, which persists the remembered
+    /// decisions in the state store of the given client.
+    pub fn new(client: Client) -> Self {
+        Self {
+            inner: Arc::new(CapabilityApprovalsInner {
+                client,
+                session: SyncMutex::new(Vec::new()),
+                store_lock: AsyncMutex::new(()),
+            }),
+        }
+    }
+
+    /// Creates a [`CapabilitiesProvider`] for the widget with the given ID in
+    /// the given room, which uses `prompt` to ask the user for the
+    /// capabilities they haven't decided on yet.
+    pub fn provider<P: CapabilitiesPrompt>(
+        &self,
+        room_id: &RoomId,
+        widget_id: &str,
+        prompt: P,
+    ) -> PersistedCapabilitiesProvider<P> {
+        PersistedCapabilitiesProvider {
+            approvals: self.clone(),
+            room_id: room_id.to_owned(),
+            widget_id: widget_id.to_owned(),
+            prompt,
+        }
+    }
+
+    /// Returns all the decisions of the user, both the remembered ones and the
+    /// ones for this session only.
+    pub async fn approvals(&self) -> Result<Vec<WidgetCapabilityApprovals>> {
+        let mut approvals: Vec<_> =
+            self.load().await?.iter().map(|e| e.to_approvals(ApprovalLifetime::Remember)).collect();
+        approvals.extend(
+            self.inner.session.lock().iter().map(|e| e.to_approvals(ApprovalLifetime::ThisSession)),
+        );
+
+        Ok(approvals)
+    }
+
+    /// Returns the decisions of the user about the capabilities of the widget
+    /// with the given ID in the given room.
+    pub async fn approvals_for_widget(
+        &self,
+        room_id: &RoomId,
+        widget_id: &str,
+    ) -> Result<Vec<WidgetCapabilityApprovals>> {
+        Ok(self
+            .approvals()
+            .await?
+            .into_iter()
+            .filter(|a| a.room_id == room_id && a.widget_id == widget_id)
+            .collect())
+    }
+
+    /// Revokes the decisions about the given capabilities of the widget with
+    /// the given ID in the given room, so the user will be prompted for them
+    /// again the next time the widget requests them.
+    pub async fn revoke(
+        &self,
+        room_id: &RoomId,
+        widget_id: &str,
+        capabilities: &Capabilities,
+    ) -> Result<()> {
+        let capabilities = capabilities_to_set(capabilities);
+        self.update(room_id, widget_id, |entry| entry.forget(&capabilities)).await
+    }
+
+    /// Revokes all the decisions about the capabilities of the widget with the
+    /// given ID in the given room.
+    pub async fn revoke_all(&self, room_id: &RoomId, widget_id: &str) -> Result<()> {
+        self.update(room_id, widget_id, |entry| {
+            entry.granted.clear();
+            entry.denied.clear();
+        })
+        .await
+    }
+
+    /// Returns the capabilities that the user already granted to and denied
+    /// the given widget, combining the remembered decisions and the ones for
+    /// this session only.
+    ///
+    /// If the remembered decisions can't be loaded, only the ones for this
+    /// session are returned.
+    async fn decisions(
+        &self,
+        room_id: &RoomId,
+        widget_id: &str,
+    ) -> (BTreeSet<String>, BTreeSet<String>) {
+        let mut granted = BTreeSet::new();
+        let mut denied = BTreeSet::new();
+
+        let persisted = self.load().await.unwrap_or_else(|error| {
+            warn!("Failed to load the widget capability approvals: {error}");
+            Vec::new()
+        });
+        let session = self.inner.session.lock().clone();
+
+        for entry in persisted.iter().chain(&session).filter(|e| e.is_for(room_id, widget_id)) {
+            granted.extend(entry.granted.iter().cloned());
+            denied.extend(entry.denied.iter().cloned());
+        }
+
+        (granted, denied)
+    }
+
+    /// Records new decisions of the user about the capabilities of the given
+    /// widget.
+    async fn record(
+        &self,
+        room_id: &RoomId,
+        widget_id: &str,
+        granted: &BTreeSet<String>,
+        denied: &BTreeSet<String>,
+        lifetime: ApprovalLifetime,
+    ) -> Result<()> {
+        match lifetime {
+            ApprovalLifetime::ThisSession => {
+                entry(&mut self.inner.session.lock(), room_id, widget_id).record(granted, denied);
+                Ok(())
+            }
+            ApprovalLifetime::Remember => {
+                let _guard = self.inner.store_lock.lock().await;
+
+                let mut entries = self.load().await?;
+                entry(&mut entries, room_id, widget_id).record(granted, denied);
+                self.persist(&entries).await
+            }
+        }
+    }
+
+    /// Updates the decisions about the capabilities of the given widget, both
+    /// the remembered ones and the ones for this session only.
+    async fn update(
+        &self,
+        room_id: &RoomId,
+        widget_id: &str,
+        f: impl Fn(&mut StoredApprovals),
+    ) -> Result<()> {
+        {
+            let mut session = self.inner.session.lock();
+            session.iter_mut().filter(|e| e.is_for(room_id, widget_id)).for_each(&f);
+            session.retain(|e| !e.is_empty());
+        }
+
+        let _guard = self.inner.store_lock.lock().await;
+
+        let mut entries = self.load().await?;
+        entries.iter_mut().filter(|e| e.is_for(room_id, widget_id)).for_each(&f);
+        entries.retain(|e| !e.is_empty());
+        self.persist(&entries).await
+    }
+
+    /// Loads the remembered decisions from the state store.
+    ///
+    /// Fails if the stored decisions can't be deserialized, so they're never
+    /// overwritten by an update based on an empty list.
+    async fn load(&self) -> Result<Vec<StoredApprovals>> {
+        let Some(bytes) = self
+            .inner
+            .client
+            .state_store()
+            .get_custom_value(WIDGET_CAPABILITY_APPROVALS_STORE_KEY)
+            .await?
+        else {
+            return Ok(Vec::new());
+        };
+
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Saves the remembered decisions in the state store.
+    async fn persist(&self, entries: &[StoredApprovals]) -> Result<()> {
+        let bytes = serde_json::to_vec(entries)?;
+        self.inner
+            .client
+            .state_store()
+            .set_custom_value(WIDGET_CAPABILITY_APPROVALS_STORE_KEY, bytes)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// A [`CapabilitiesProvider`] that remembers the decisions of the user about
+/// the capabilities of a widget in a room, and only prompts them for the
+/// capabilities they haven't decided on yet.
+///
+/// It is created with [`CapabilityApprovals::provider`].
+#[derive(Debug)]
+pub struct PersistedCapabilitiesProvider<P> {
+    approvals: CapabilityApprovals,
+    room_id: OwnedRoomId,
+    widget_id: String,
+    prompt: P,
+}
+
+impl<P: CapabilitiesPrompt> CapabilitiesProvider for PersistedCapabilitiesProvider<P> {
+    async fn acquire_capabilities(&self, capabilities: Capabilities) -> Capabilities {
+        let requested = capabilities_to_set(&capabilities);
+
+        let (known_granted, known_denied) =
+            self.approvals.decisions(&self.room_id, &self.widget_id).await;
+
+        let mut granted: BTreeSet<_> = requested.intersection(&known_granted).cloned().collect();
+        let undecided: BTreeSet<_> = requested
+            .iter()
+            .filter(|c| !known_granted.contains(*c) && !known_denied.contains(*c))
+            .cloned()
+            .collect();
+
+        if undecided.is_empty() {
+            return capabilities_from_set(&granted);
+        }
+
+        let decision = self.prompt.prompt(capabilities_from_set(&undecided)).await;
+
+        // Don't let the prompt grant capabilities that weren't asked for.
+        let approved = capabilities_to_set(&decision.approved);
+        let (newly_granted, newly_denied): (BTreeSet<_>, BTreeSet<_>) =
+            undecided.into_iter().partition(|c| approved.contains(c));
+
+        if let Err(error) = self
+            .approvals
+            .record(
+                &self.room_id,
+                &self.widget_id,
+                &newly_granted,
+                &newly_denied,
+                decision.lifetime,
+            )
+            .await
+        {
+            warn!("Failed to save the widget capability approvals, keeping them for this session only: {error}");
+
+            // Recording the decisions in memory can't fail.
+            let _ = self
+                .approvals
+                .record(
+                    &self.room_id,
+                    &self.widget_id,
+                    &newly_granted,
+                    &newly_denied,
+                    ApprovalLifetime::ThisSession,
+                )
+                .await;
+        }
+
+        granted.extend(newly_granted);
+        capabilities_from_set(&granted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use matrix_sdk_test::async_test;
+    use ruma::room_id;
+    use serde_json::json;
+
+    use super::{
+        ApprovalLifetime, CapabilitiesDecision, CapabilitiesPrompt, CapabilityApprovals,
+        WIDGET_CAPABILITY_APPROVALS_STORE_KEY,
+    };
+    use crate::{
+        test_utils::mocks::MatrixMockServer,
+        widget::{Capabilities, CapabilitiesProvider},
+    };
+
+    /// A prompt which approves the capabilities in `approve`, and counts how
+    /// many times it was called.
+    struct TestPrompt {
+        approve: Vec<&'static str>,
+        lifetime: ApprovalLifetime,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl CapabilitiesPrompt for TestPrompt {
+        async fn prompt(&self, _capabilities: Capabilities) -> CapabilitiesDecision {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            CapabilitiesDecision { approved: caps(&self.approve), lifetime: self.lifetime }
+        }
+    }
+
+    fn caps(capabilities: &[&str]) -> Capabilities {
+        serde_json::from_value(json!(capabilities)).unwrap()
+    }
+
+    fn caps_json(capabilities: &Capabilities) -> serde_json::Value {
+        let mut list: Vec<String> =
+            serde_json::from_value(serde_json::to_value(capabilities).unwrap()).unwrap();
+        list.sort();
+        json!(list)
+    }
+
+    const READ_MESSAGES: &str = "org.matrix.msc2762.receive.event:m.room.message";
+    const SEND_MESSAGES: &str = "org.matrix.msc2762.send.event:m.room.message";
+    const UPLOAD_FILE: &str = "org.matrix.msc4039.upload_file";
+
+    #[async_test]
+    async fn test_remembered_approvals_are_not_prompted_again() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!a:b.c");
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let prompt = TestPrompt {
+            approve: vec![READ_MESSAGES],
+            lifetime: ApprovalLifetime::Remember,
+            calls: calls.clone(),
+        };
+
+        let approvals = CapabilityApprovals::new(client.clone());
+        let provider = approvals.provider(room_id, "widget", prompt);
+
+        let granted = provider.acquire_capabilities(caps(&[READ_MESSAGES, SEND_MESSAGES])).await;
+        assert_eq!(caps_json(&granted), json!([READ_MESSAGES]));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // A new session remembers both the granted and the denied capabilities, and
+        // only prompts for the new capability.
+        let calls = Arc::new(AtomicUsize::new(0));
+        let prompt = TestPrompt {
+            approve: vec![UPLOAD_FILE],
+            lifetime: ApprovalLifetime::Remember,
+            calls: calls.clone(),
+        };
+        let approvals = CapabilityApprovals::new(client);
+        let provider = approvals.provider(room_id, "widget", prompt);
+
+        let granted = provider.acquire_capabilities(caps(&[READ_MESSAGES, SEND_MESSAGES])).await;
+        assert_eq!(caps_json(&granted), json!([READ_MESSAGES]));
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let granted =
+            provider.acquire_capabilities(caps(&[READ_MESSAGES, SEND_MESSAGES, UPLOAD_FILE])).await;
+        assert_eq!(caps_json(&granted), json!([READ_MESSAGES, UPLOAD_FILE]));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let [remembered]: [_; 1] = approvals.approvals().await.unwrap().try_into().unwrap();
+        assert_eq!(remembered.room_id, room_id);
+        assert_eq!(remembered.widget_id, "widget");
+        assert_eq!(remembered.lifetime, ApprovalLifetime::Remember);
+        assert_eq!(caps_json(&remembered.granted), json!([READ_MESSAGES, UPLOAD_FILE]));
+        assert_eq!(caps_json(&remembered.denied), json!([SEND_MESSAGES]));
+
+        // Other widgets and rooms are prompted separately.
+        assert!(approvals.approvals_for_widget(room_id, "other").await.unwrap().is_empty());
+        assert!(approvals
+            .approvals_for_widget(room_id!("!other:b.c"), "widget")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[async_test]
+    async fn test_session_approvals_are_not_persisted() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!a:b.c");
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let approvals = CapabilityApprovals::new(client.clone());
+        let provider = approvals.provider(
+            room_id,
+            "widget",
+            TestPrompt {
+                approve: vec![READ_MESSAGES],
+                lifetime: ApprovalLifetime::ThisSession,
+                calls: calls.clone(),
+            },
+        );
+
+        provider.acquire_capabilities(caps(&[READ_MESSAGES])).await;
+        provider.acquire_capabilities(caps(&[READ_MESSAGES])).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let [approval]: [_; 1] = approvals.approvals().await.unwrap().try_into().unwrap();
+        assert_eq!(approval.lifetime, ApprovalLifetime::ThisSession);
+
+        // A new session prompts again.
+        let approvals = CapabilityApprovals::new(client);
+        assert!(approvals.approvals().await.unwrap().is_empty());
+    }
+
+    #[async_test]
+    async fn test_unreadable_approvals_are_not_overwritten() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!a:b.c");
+
+        client
+            .state_store()
+            .set_custom_value(WIDGET_CAPABILITY_APPROVALS_STORE_KEY, b"not json".to_vec())
+            .await
+            .unwrap();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let approvals = CapabilityApprovals::new(client.clone());
+        let provider = approvals.provider(
+            room_id,
+            "widget",
+            TestPrompt {
+                approve: vec![READ_MESSAGES],
+                lifetime: ApprovalLifetime::Remember,
+                calls: calls.clone(),
+            },
+        );
+
+        approvals.approvals().await.unwrap_err();
+        approvals.revoke_all(room_id, "widget").await.unwrap_err();
+
+        // The decision is kept for this session only, instead of replacing the stored
+        // decisions.
+        let granted = provider.acquire_capabilities(caps(&[READ_MESSAGES])).await;
+        assert_eq!(caps_json(&granted), json!([READ_MESSAGES]));
+        provider.acquire_capabilities(caps(&[READ_MESSAGES])).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let stored = client
+            .state_store()
+            .get_custom_value(WIDGET_CAPABILITY_APPROVALS_STORE_KEY)
+            .await
+            .unwrap();
+        assert_eq!(stored.as_deref(), Some(b"not json".as_slice()));
+    }
+
+    #[async_test]
+    async fn test_revoked_approvals_are_prompted_again() {
+        let server = MatrixMockServer::new().await;
+        let client = server.client_builder().build().await;
+        let room_id = room_id!("!a:b.c");
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let approvals = CapabilityApprovals::new(client);
+        let provider = approvals.provider(
+            room_id,
+            "widget",
+            TestPrompt {
+                approve: vec![READ_MESSAGES, SEND_MESSAGES],
+                lifetime: ApprovalLifetime::Remember,
+                calls: calls.clone(),
+            },
+        );
+
+        provider.acquire_capabilities(caps(&[READ_MESSAGES, SEND_MESSAGES])).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        approvals.revoke(room_id, "widget", &caps(&[SEND_MESSAGES])).await.unwrap();
+        let [approval]: [_; 1] =
+            approvals.approvals_for_widget(room_id, "widget").await.unwrap().try_into().unwrap();
+        assert_eq!(caps_json(&approval.granted), json!([READ_MESSAGES]));
+
+        provider.acquire_capabilities(caps(&[READ_MESSAGES, SEND_MESSAGES])).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        approvals.revoke_all(room_id, "widget").await.unwrap();
+        assert!(approvals.approvals().await.unwrap().is_empty());
+    }
+}
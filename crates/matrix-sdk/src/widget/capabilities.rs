@@ -57,6 +57,16 @@ pub struct Capabilities {
     pub update_delayed_event: bool,
     /// This allows the widget to send events with a delay.
     pub send_delayed_event: bool,
+    /// This allows the widget to upload files to the media repository, and to
+    /// get its configuration.
+    pub upload_file: bool,
+    /// This allows the widget to download files from the media repository.
+    pub download_file: bool,
+    /// This allows the widget to search the user directory of the homeserver.
+    pub user_directory_search: bool,
+    /// This allows the widget to get the TURN servers of the homeserver, and
+    /// to be notified when their credentials change.
+    pub turn_servers: bool,
 }
 
 impl Capabilities {
@@ -113,6 +123,10 @@ pub(super) const READ_TODEVICE: &str = "org.matrix.msc3819.receive.to_device";
 pub(super) const REQUIRES_CLIENT: &str = "io.element.requires_client";
 pub(super) const SEND_DELAYED_EVENT: &str = "org.matrix.msc4157.send.delayed_event";
 pub(super) const UPDATE_DELAYED_EVENT: &str = "org.matrix.msc4157.update_delayed_event";
+pub(super) const UPLOAD_FILE: &str = "org.matrix.msc4039.upload_file";
+pub(super) const DOWNLOAD_FILE: &str = "org.matrix.msc4039.download_file";
+pub(super) const USER_DIRECTORY_SEARCH: &str = "org.matrix.msc3973.user_directory_search";
+pub(super) const TURN_SERVERS: &str = "town.robin.msc3846.turn_servers";
 
 impl Serialize for Capabilities {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -174,6 +188,18 @@ impl Serialize for Capabilities {
         if self.send_delayed_event {
             seq.serialize_element(SEND_DELAYED_EVENT)?;
         }
+        if self.upload_file {
+            seq.serialize_element(UPLOAD_FILE)?;
+        }
+        if self.download_file {
+            seq.serialize_element(DOWNLOAD_FILE)?;
+        }
+        if self.user_directory_search {
+            seq.serialize_element(USER_DIRECTORY_SEARCH)?;
+        }
+        if self.turn_servers {
+            seq.serialize_element(TURN_SERVERS)?;
+        }
         for filter in &self.read {
             let name = match filter {
                 Filter::MessageLike(_) => READ_EVENT,
@@ -204,6 +230,10 @@ impl<'de> Deserialize<'de> for Capabilities {
             RequiresClient,
             UpdateDelayedEvent,
             SendDelayedEvent,
+            UploadFile,
+            DownloadFile,
+            UserDirectorySearch,
+            TurnServers,
             Read(Filter),
             Send(Filter),
             Unknown,
@@ -224,6 +254,18 @@ impl<'de> Deserialize<'de> for Capabilities {
                 if s == SEND_DELAYED_EVENT {
                     return Ok(Self::SendDelayedEvent);
                 }
+                if s == UPLOAD_FILE {
+                    return Ok(Self::UploadFile);
+                }
+                if s == DOWNLOAD_FILE {
+                    return Ok(Self::DownloadFile);
+                }
+                if s == USER_DIRECTORY_SEARCH {
+                    return Ok(Self::UserDirectorySearch);
+                }
+                if s == TURN_SERVERS {
+                    return Ok(Self::TurnServers);
+                }
 
                 match s.split_once(':') {
                     Some((READ_EVENT, filter_s)) => Ok(Permission::Read(Filter::MessageLike(
@@ -284,6 +326,10 @@ impl<'de> Deserialize<'de> for Capabilities {
                 Permission::Unknown => {}
                 Permission::UpdateDelayedEvent => capabilities.update_delayed_event = true,
                 Permission::SendDelayedEvent => capabilities.send_delayed_event = true,
+                Permission::UploadFile => capabilities.upload_file = true,
+                Permission::DownloadFile => capabilities.download_file = true,
+                Permission::UserDirectorySearch => capabilities.user_directory_search = true,
+                Permission::TurnServers => capabilities.turn_servers = true,
             }
         }
 
@@ -321,7 +367,11 @@ mod tests {
             "org.matrix.msc2762.send.state_event:org.matrix.msc3401.call.member#@user:matrix.server",
             "org.matrix.msc3819.send.to_device:io.element.call.encryption_keys",
             "org.matrix.msc4157.send.delayed_event",
-            "org.matrix.msc4157.update_delayed_event"
+            "org.matrix.msc4157.update_delayed_event",
+            "org.matrix.msc4039.upload_file",
+            "org.matrix.msc4039.download_file",
+            "org.matrix.msc3973.user_directory_search",
+            "town.robin.msc3846.turn_servers"
         ]"#;
 
         let parsed = serde_json::from_str::<Capabilities>(capabilities_str).unwrap();
@@ -351,6 +401,10 @@ mod tests {
             requires_client: true,
             update_delayed_event: true,
             send_delayed_event: true,
+            upload_file: true,
+            download_file: true,
+            user_directory_search: true,
+            turn_servers: true,
         };
 
         assert_eq!(parsed, expected);
@@ -381,6 +435,10 @@ mod tests {
             requires_client: true,
             update_delayed_event: false,
             send_delayed_event: false,
+            upload_file: true,
+            download_file: false,
+            user_directory_search: true,
+            turn_servers: false,
         };
 
         let capabilities_str = serde_json::to_string(&capabilities).unwrap();
@@ -17,19 +17,29 @@
 use std::{collections::BTreeMap, marker::PhantomData};
 
 use ruma::{
-    api::client::{account::request_openid_token, delayed_events::update_delayed_event},
-    events::{AnyStateEvent, AnyTimelineEvent, AnyToDeviceEventContent},
-    serde::Raw,
+    api::{
+        client::{account::request_openid_token, delayed_events::update_delayed_event},
+        Direction,
+    },
+    events::{
+        relation::RelationType, room::EncryptedFile, AnyStateEvent, AnyTimelineEvent,
+        AnyToDeviceEventContent, TimelineEventType,
+    },
+    serde::{Base64, Raw},
     to_device::DeviceIdOrAllDevices,
-    OwnedUserId,
+    OwnedEventId, OwnedMxcUri, OwnedUserId, UInt,
 };
 use serde::Deserialize;
 use serde_json::value::RawValue as RawJsonValue;
 use tracing::error;
 
 use super::{
-    from_widget::SendEventResponse, incoming::MatrixDriverResponse, Action,
-    MatrixDriverRequestMeta, SendToDeviceEventResponse, WidgetMachine,
+    from_widget::{
+        DownloadFileResponse, GetMediaConfigResponse, ReadRelationsResponse, SendEventResponse,
+        UploadFileResponse, UserDirectorySearchResponse,
+    },
+    incoming::MatrixDriverResponse,
+    Action, MatrixDriverRequestMeta, SendToDeviceEventResponse, WidgetMachine,
 };
 use crate::widget::{Capabilities, StateKeySelector};
 
@@ -59,6 +69,21 @@ pub(crate) enum MatrixDriverRequestData {
 
     /// Data for sending a UpdateDelayedEvent client server api request.
     UpdateDelayedEvent(UpdateDelayedEventRequest),
+
+    /// Get the configuration of the media repository.
+    GetMediaConfig,
+
+    /// Upload a file to the media repository.
+    UploadFile(UploadFileRequest),
+
+    /// Download a file from the media repository.
+    DownloadFile(DownloadFileRequest),
+
+    /// Read the events related to a given event.
+    ReadRelations(ReadRelationsRequest),
+
+    /// Search the user directory of the homeserver.
+    SearchUserDirectory(UserDirectorySearchRequest),
 }
 
 /// A handle to a pending `toWidget` request.
@@ -339,3 +364,166 @@ impl FromMatrixDriverResponse for update_delayed_event::unstable::Response {
         }
     }
 }
+
+/// Ask the client for the configuration of the media repository of the
+/// homeserver. Defined by [MSC4039](https://github.com/matrix-org/matrix-spec-proposals/pull/4039)
+#[derive(Debug)]
+pub(crate) struct GetMediaConfigRequest;
+
+impl From<GetMediaConfigRequest> for MatrixDriverRequestData {
+    fn from(_: GetMediaConfigRequest) -> Self {
+        MatrixDriverRequestData::GetMediaConfig
+    }
+}
+
+impl MatrixDriverRequest for GetMediaConfigRequest {
+    type Response = GetMediaConfigResponse;
+}
+
+impl FromMatrixDriverResponse for GetMediaConfigResponse {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::MediaConfigReceived(response) => Some(response),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
+
+/// Ask the client to upload a file to the media repository. The file is
+/// encrypted by the client if the room is encrypted. Defined by [MSC4039](https://github.com/matrix-org/matrix-spec-proposals/pull/4039)
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct UploadFileRequest {
+    /// The content of the file, encoded in base64.
+    pub(crate) file: Base64,
+}
+
+impl From<UploadFileRequest> for MatrixDriverRequestData {
+    fn from(value: UploadFileRequest) -> Self {
+        MatrixDriverRequestData::UploadFile(value)
+    }
+}
+
+impl MatrixDriverRequest for UploadFileRequest {
+    type Response = UploadFileResponse;
+}
+
+impl FromMatrixDriverResponse for UploadFileResponse {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::FileUploaded(response) => Some(response),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
+
+/// Ask the client to download a file from the media repository, and to
+/// decrypt it if the widget provides its encryption info. Defined by [MSC4039](https://github.com/matrix-org/matrix-spec-proposals/pull/4039)
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct DownloadFileRequest {
+    /// The MXC URI of the file.
+    pub(crate) content_uri: OwnedMxcUri,
+    /// The encryption info of the file, as returned when it was uploaded in
+    /// an encrypted room.
+    #[serde(default)]
+    pub(crate) encrypted_file: Option<Box<EncryptedFile>>,
+}
+
+impl From<DownloadFileRequest> for MatrixDriverRequestData {
+    fn from(value: DownloadFileRequest) -> Self {
+        MatrixDriverRequestData::DownloadFile(value)
+    }
+}
+
+impl MatrixDriverRequest for DownloadFileRequest {
+    type Response = DownloadFileResponse;
+}
+
+impl FromMatrixDriverResponse for DownloadFileResponse {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::FileDownloaded(response) => Some(response),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
+
+/// Ask the client to read the events related to a given event in the room.
+/// Defined by [MSC3869](https://github.com/matrix-org/matrix-spec-proposals/pull/3869)
+#[derive(Clone, Debug)]
+pub(crate) struct ReadRelationsRequest {
+    /// The event to read the relations of.
+    pub(crate) event_id: OwnedEventId,
+    /// Only read the relations of this type.
+    pub(crate) rel_type: Option<RelationType>,
+    /// Only read the relations with this event type. Only used along with
+    /// `rel_type`.
+    pub(crate) event_type: Option<TimelineEventType>,
+    /// The maximum number of events to return.
+    pub(crate) limit: Option<UInt>,
+    /// The pagination token to start returning events from.
+    pub(crate) from: Option<String>,
+    /// The direction to return events in.
+    pub(crate) direction: Direction,
+}
+
+impl From<ReadRelationsRequest> for MatrixDriverRequestData {
+    fn from(value: ReadRelationsRequest) -> Self {
+        MatrixDriverRequestData::ReadRelations(value)
+    }
+}
+
+impl MatrixDriverRequest for ReadRelationsRequest {
+    type Response = ReadRelationsResponse;
+}
+
+impl FromMatrixDriverResponse for ReadRelationsResponse {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::RelationsRead(response) => Some(response),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
+
+/// Ask the client to search the user directory of the homeserver. Defined by [MSC3973](https://github.com/matrix-org/matrix-spec-proposals/pull/3973)
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct UserDirectorySearchRequest {
+    /// The term to search for.
+    pub(crate) search_term: String,
+    /// The maximum number of results to return.
+    pub(crate) limit: Option<u64>,
+}
+
+impl From<UserDirectorySearchRequest> for MatrixDriverRequestData {
+    fn from(value: UserDirectorySearchRequest) -> Self {
+        MatrixDriverRequestData::SearchUserDirectory(value)
+    }
+}
+
+impl MatrixDriverRequest for UserDirectorySearchRequest {
+    type Response = UserDirectorySearchResponse;
+}
+
+impl FromMatrixDriverResponse for UserDirectorySearchResponse {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::UserDirectorySearched(response) => Some(response),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
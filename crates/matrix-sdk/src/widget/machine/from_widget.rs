@@ -16,20 +16,25 @@ use std::collections::BTreeMap;
 
 use as_variant::as_variant;
 use ruma::{
-    api::client::{
-        delayed_events::{delayed_message_event, delayed_state_event, update_delayed_event},
-        error::{ErrorBody, StandardErrorBody},
+    api::{
+        client::{
+            delayed_events::{delayed_message_event, delayed_state_event, update_delayed_event},
+            error::{ErrorBody, StandardErrorBody},
+        },
+        Direction,
     },
-    events::AnyTimelineEvent,
-    serde::Raw,
-    OwnedEventId, OwnedRoomId,
+    events::{relation::RelationType, room::EncryptedFile, AnyTimelineEvent, TimelineEventType},
+    serde::{Base64, Raw},
+    OwnedEventId, OwnedMxcUri, OwnedRoomId, OwnedUserId, UInt,
 };
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
 use super::{
-    driver_req::SendToDeviceRequest, MatrixDriverResponse, SendEventRequest,
-    UpdateDelayedEventRequest,
+    driver_req::{
+        DownloadFileRequest, SendToDeviceRequest, UploadFileRequest, UserDirectorySearchRequest,
+    },
+    MatrixDriverResponse, SendEventRequest, UpdateDelayedEventRequest,
 };
 use crate::{
     widget::{machine::driver_req::FromMatrixDriverResponse, StateKeySelector},
@@ -49,6 +54,18 @@ pub(super) enum FromWidgetRequest {
     SendToDevice(SendToDeviceRequest),
     #[serde(rename = "org.matrix.msc4157.update_delayed_event")]
     DelayedEventUpdate(UpdateDelayedEventRequest),
+    #[serde(rename = "org.matrix.msc4039.get_media_config")]
+    GetMediaConfig {},
+    #[serde(rename = "org.matrix.msc4039.upload_file")]
+    UploadFile(UploadFileRequest),
+    #[serde(rename = "org.matrix.msc4039.download_file")]
+    DownloadFile(DownloadFileRequest),
+    #[serde(rename = "org.matrix.msc3869.read_relations")]
+    ReadRelations(ReadRelationsRequest),
+    #[serde(rename = "org.matrix.msc3973.user_directory_search")]
+    UserDirectorySearch(UserDirectorySearchRequest),
+    WatchTurnServers {},
+    UnwatchTurnServers {},
 }
 
 /// The full response a client sends to a [`FromWidgetRequest`] in case of an
@@ -145,6 +162,10 @@ impl SupportedApiVersionsResponse {
                 ApiVersion::MSC2762UpdateState,
                 ApiVersion::MSC2871,
                 ApiVersion::MSC3819,
+                ApiVersion::MSC3846,
+                ApiVersion::MSC3869,
+                ApiVersion::MSC3973,
+                ApiVersion::MSC4039,
             ],
         }
     }
@@ -192,6 +213,18 @@ pub(super) enum ApiVersion {
     /// Supports access to the TURN servers.
     #[serde(rename = "town.robin.msc3846")]
     MSC3846,
+
+    /// Supports reading the relations of an event.
+    #[serde(rename = "org.matrix.msc3869")]
+    MSC3869,
+
+    /// Supports searching the user directory.
+    #[serde(rename = "org.matrix.msc3973")]
+    MSC3973,
+
+    /// Supports uploading and downloading files.
+    #[serde(rename = "org.matrix.msc4039")]
+    MSC4039,
 }
 
 #[derive(Deserialize, Debug)]
@@ -207,6 +240,40 @@ pub(super) struct ReadEventsResponse {
     pub(super) events: Vec<Raw<AnyTimelineEvent>>,
 }
 
+/// A request to read the events related to a given event. Defined by [MSC3869](https://github.com/matrix-org/matrix-spec-proposals/pull/3869)
+#[derive(Deserialize, Debug)]
+pub(super) struct ReadRelationsRequest {
+    /// The event to read the relations of.
+    pub(super) event_id: OwnedEventId,
+    /// The room of the event. Only the room of the widget is supported.
+    pub(super) room_id: Option<OwnedRoomId>,
+    /// Only read the relations of this type.
+    pub(super) rel_type: Option<RelationType>,
+    /// Only read the relations with this event type. Requires `rel_type`.
+    pub(super) event_type: Option<TimelineEventType>,
+    /// The maximum number of events to return.
+    pub(super) limit: Option<UInt>,
+    /// The pagination token to start returning events from.
+    pub(super) from: Option<String>,
+    /// The direction to return events in. Defaults to backwards.
+    #[serde(default)]
+    pub(super) direction: Direction,
+}
+
+/// Response to a `read_relations` request.
+#[derive(Serialize, Debug)]
+pub(crate) struct ReadRelationsResponse {
+    /// The events related to the requested event, that the widget is allowed
+    /// to read.
+    pub(crate) chunk: Vec<Raw<AnyTimelineEvent>>,
+    /// The token to get the next batch of events, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) next_batch: Option<String>,
+    /// The token to get the previous batch of events, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) prev_batch: Option<String>,
+}
+
 #[derive(Serialize, Debug)]
 pub(crate) struct SendEventResponse {
     /// The room id for the send event.
@@ -276,3 +343,52 @@ impl FromMatrixDriverResponse for SendToDeviceEventResponse {
         }
     }
 }
+
+/// Response to a `get_media_config` request, with the configuration of the
+/// media repository of the homeserver.
+#[derive(Serialize, Debug)]
+pub(crate) struct GetMediaConfigResponse {
+    /// The maximum size of an upload, in bytes.
+    #[serde(rename = "m.upload.size")]
+    pub(crate) upload_size: UInt,
+}
+
+/// Response to an `upload_file` request.
+#[derive(Serialize, Debug)]
+pub(crate) struct UploadFileResponse {
+    /// The MXC URI of the uploaded file.
+    pub(crate) content_uri: OwnedMxcUri,
+    /// The encryption info of the file, if it was encrypted because the room
+    /// is encrypted. The widget must keep it to be able to download the file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) encrypted_file: Option<Box<EncryptedFile>>,
+}
+
+/// Response to a `download_file` request.
+#[derive(Serialize, Debug)]
+pub(crate) struct DownloadFileResponse {
+    /// The content of the file, encoded in base64.
+    pub(crate) file: Base64,
+}
+
+/// Response to a `user_directory_search` request.
+#[derive(Serialize, Debug)]
+pub(crate) struct UserDirectorySearchResponse {
+    /// Whether the results were truncated by the limit of the request.
+    pub(crate) limited: bool,
+    /// The users matching the search term.
+    pub(crate) results: Vec<UserDirectorySearchResult>,
+}
+
+/// A user found by a `user_directory_search` request.
+#[derive(Serialize, Debug)]
+pub(crate) struct UserDirectorySearchResult {
+    /// The ID of the user.
+    pub(crate) user_id: OwnedUserId,
+    /// The display name of the user, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) display_name: Option<String>,
+    /// The avatar URL of the user, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) avatar_url: Option<OwnedMxcUri>,
+}
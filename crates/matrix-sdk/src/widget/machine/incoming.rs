@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use ruma::{
-    api::client::{account::request_openid_token, delayed_events},
+    api::client::{account::request_openid_token, delayed_events, voip::get_turn_server_info},
     events::{AnyStateEvent, AnyTimelineEvent, AnyToDeviceEvent},
     serde::Raw,
 };
@@ -24,7 +24,10 @@ use uuid::Uuid;
 #[cfg(doc)]
 use super::MatrixDriverRequestData;
 use super::{
-    from_widget::{FromWidgetRequest, SendEventResponse},
+    from_widget::{
+        DownloadFileResponse, FromWidgetRequest, GetMediaConfigResponse, ReadRelationsResponse,
+        SendEventResponse, UploadFileResponse, UserDirectorySearchResponse,
+    },
     to_widget::ToWidgetResponse,
     SendToDeviceEventResponse,
 };
@@ -63,6 +66,13 @@ pub(crate) enum IncomingMessage {
     /// The `MatrixDriver` notified the `WidgetMachine` of a new to-device
     /// event.
     ToDeviceReceived(Raw<AnyToDeviceEvent>),
+
+    /// The `MatrixDriver` notified the `WidgetMachine` of new TURN servers
+    /// credentials.
+    ///
+    /// This means that the machine previously started watching the TURN
+    /// servers ([`crate::widget::Action::WatchTurnServers`] request).
+    TurnServersReceived(get_turn_server_info::v3::Response),
 }
 
 pub(crate) enum MatrixDriverResponse {
@@ -87,6 +97,22 @@ pub(crate) enum MatrixDriverResponse {
     /// Client updated a delayed event.
     /// A response to a [`MatrixDriverRequestData::UpdateDelayedEvent`] command.
     DelayedEventUpdated(delayed_events::update_delayed_event::unstable::Response),
+    /// Client got the configuration of the media repository.
+    /// A response to a [`MatrixDriverRequestData::GetMediaConfig`] command.
+    MediaConfigReceived(GetMediaConfigResponse),
+    /// Client uploaded a file.
+    /// A response to a [`MatrixDriverRequestData::UploadFile`] command.
+    FileUploaded(UploadFileResponse),
+    /// Client downloaded a file.
+    /// A response to a [`MatrixDriverRequestData::DownloadFile`] command.
+    FileDownloaded(DownloadFileResponse),
+    /// Client read the relations of an event.
+    /// A response to a [`MatrixDriverRequestData::ReadRelations`] command.
+    RelationsRead(ReadRelationsResponse),
+    /// Client searched the user directory.
+    /// A response to a [`MatrixDriverRequestData::SearchUserDirectory`]
+    /// command.
+    UserDirectorySearched(UserDirectorySearchResponse),
 }
 
 pub(super) struct IncomingWidgetMessage {
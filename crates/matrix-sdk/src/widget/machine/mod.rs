@@ -16,8 +16,11 @@
 
 use std::time::Duration;
 
-use driver_req::{ReadStateRequest, UpdateDelayedEventRequest};
-use from_widget::UpdateDelayedEventResponse;
+use driver_req::{
+    DownloadFileRequest, GetMediaConfigRequest, ReadStateRequest, UpdateDelayedEventRequest,
+    UploadFileRequest,
+};
+use from_widget::{ReadRelationsRequest, UpdateDelayedEventResponse};
 use indexmap::IndexMap;
 use ruma::{
     events::{AnyStateEvent, AnyTimelineEvent},
@@ -43,13 +46,17 @@ use self::{
     pending::{PendingRequests, RequestLimits},
     to_widget::{
         NotifyCapabilitiesChanged, NotifyNewMatrixEvent, NotifyOpenIdChanged, NotifyStateUpdate,
-        RequestCapabilities, ToWidgetRequest, ToWidgetRequestHandle, ToWidgetResponse,
+        NotifyTurnServersChanged, RequestCapabilities, ToWidgetRequest, ToWidgetRequestHandle,
+        ToWidgetResponse,
     },
 };
 #[cfg(doc)]
 use super::WidgetDriver;
 use super::{
-    capabilities::{SEND_DELAYED_EVENT, UPDATE_DELAYED_EVENT},
+    capabilities::{
+        DOWNLOAD_FILE, SEND_DELAYED_EVENT, TURN_SERVERS, UPDATE_DELAYED_EVENT, UPLOAD_FILE,
+        USER_DIRECTORY_SEARCH,
+    },
     filter::FilterInput,
     Capabilities, StateEventFilter, StateKeySelector,
 };
@@ -66,7 +73,11 @@ mod to_widget;
 
 pub(crate) use self::{
     driver_req::{MatrixDriverRequestData, SendEventRequest, SendToDeviceRequest},
-    from_widget::{SendEventResponse, SendToDeviceEventResponse},
+    from_widget::{
+        DownloadFileResponse, GetMediaConfigResponse, ReadRelationsResponse, SendEventResponse,
+        SendToDeviceEventResponse, UploadFileResponse, UserDirectorySearchResponse,
+        UserDirectorySearchResult,
+    },
     incoming::{IncomingMessage, MatrixDriverResponse},
 };
 
@@ -102,6 +113,14 @@ pub(crate) enum Action {
     /// Unsubscribe from the events that the widget capabilities allow,
     /// in the _current_ room. Symmetrical to `Subscribe`.
     Unsubscribe,
+
+    /// Start fetching the TURN servers of the homeserver, and keep fetching
+    /// them before their credentials expire.
+    WatchTurnServers,
+
+    /// Stop fetching the TURN servers of the homeserver. Symmetrical to
+    /// `WatchTurnServers`.
+    UnwatchTurnServers,
 }
 
 /// An initial state update which is in the process of being computed.
@@ -153,6 +172,9 @@ pub(crate) struct WidgetMachine {
 
     /// Current negotiation state for capabilities.
     capabilities: CapabilitiesState,
+
+    /// Whether the widget asked to be notified of the TURN servers.
+    watching_turn_servers: bool,
 }
 
 impl WidgetMachine {
@@ -174,6 +196,7 @@ impl WidgetMachine {
             pending_matrix_driver_requests: PendingRequests::new(limits),
             pending_state_updates: None,
             capabilities: CapabilitiesState::Unset,
+            watching_turn_servers: false,
         };
 
         let initial_actions =
@@ -241,6 +264,25 @@ impl WidgetMachine {
                     None => self.send_state_update(state).into_iter().collect(),
                 }
             }
+            IncomingMessage::TurnServersReceived(turn_servers) => {
+                let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+                    error!("Received TURN servers before capabilities negotiation");
+                    return Vec::new();
+                };
+
+                if !capabilities.turn_servers || !self.watching_turn_servers {
+                    return Vec::new();
+                }
+
+                let notify_turn_servers = NotifyTurnServersChanged {
+                    uris: turn_servers.uris,
+                    username: turn_servers.username,
+                    password: turn_servers.password,
+                };
+                self.send_to_widget_request(notify_turn_servers)
+                    .map(|(_request, action)| vec![action])
+                    .unwrap_or_default()
+            }
         }
     }
 
@@ -381,6 +423,93 @@ impl WidgetMachine {
                 })
                 .unwrap_or_default()
             }
+
+            FromWidgetRequest::GetMediaConfig {} => self
+                .process_capability_request(
+                    GetMediaConfigRequest,
+                    UPLOAD_FILE,
+                    |c| c.upload_file,
+                    raw_request,
+                )
+                .map(|a| vec![a])
+                .unwrap_or_default(),
+
+            FromWidgetRequest::UploadFile(req) => self
+                .process_capability_request(req, UPLOAD_FILE, |c| c.upload_file, raw_request)
+                .map(|a| vec![a])
+                .unwrap_or_default(),
+
+            FromWidgetRequest::DownloadFile(req) => {
+                if req.encrypted_file.as_ref().is_some_and(|file| file.url != req.content_uri) {
+                    return vec![Self::send_from_widget_error_string_response(
+                        raw_request,
+                        "The URI of the encrypted file doesn't match the content URI",
+                    )];
+                }
+
+                self.process_capability_request(
+                    req,
+                    DOWNLOAD_FILE,
+                    |c| c.download_file,
+                    raw_request,
+                )
+                .map(|a| vec![a])
+                .unwrap_or_default()
+            }
+
+            FromWidgetRequest::ReadRelations(req) => self
+                .process_read_relations_request(req, raw_request)
+                .map(|a| vec![a])
+                .unwrap_or_default(),
+
+            FromWidgetRequest::UserDirectorySearch(req) => self
+                .process_capability_request(
+                    req,
+                    USER_DIRECTORY_SEARCH,
+                    |c| c.user_directory_search,
+                    raw_request,
+                )
+                .map(|a| vec![a])
+                .unwrap_or_default(),
+
+            FromWidgetRequest::WatchTurnServers {} => {
+                let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+                    return vec![Self::send_from_widget_error_string_response(
+                        raw_request,
+                        "Received watch TURN servers request before capabilities were negotiated",
+                    )];
+                };
+
+                if !capabilities.turn_servers {
+                    return vec![Self::send_from_widget_error_string_response(
+                        raw_request,
+                        format!("Not allowed: missing the {TURN_SERVERS} capability."),
+                    )];
+                }
+
+                let mut actions =
+                    vec![Self::send_from_widget_response(raw_request, Ok(JsonObject::new()))];
+                if !self.watching_turn_servers {
+                    self.watching_turn_servers = true;
+                    actions.push(Action::WatchTurnServers);
+                }
+                actions
+            }
+
+            FromWidgetRequest::UnwatchTurnServers {} => {
+                if !self.watching_turn_servers {
+                    return vec![Self::send_from_widget_error_string_response(
+                        raw_request,
+                        "The TURN servers are not being watched",
+                    )];
+                }
+
+                self.watching_turn_servers = false;
+                vec![
+                    Self::send_from_widget_response(raw_request, Ok(JsonObject::new())),
+                    Action::UnwatchTurnServers,
+                ]
+            }
         }
     }
 
@@ -473,6 +602,67 @@ impl WidgetMachine {
         })
     }
 
+    fn process_read_relations_request(
+        &mut self,
+        request: ReadRelationsRequest,
+        raw_request: Raw<FromWidgetRequest>,
+    ) -> Option<Action> {
+        let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+            return Some(Self::send_from_widget_error_string_response(
+                raw_request,
+                "Received read relations request before capabilities were negotiated",
+            ));
+        };
+
+        if capabilities.read.is_empty() {
+            return Some(Self::send_from_widget_error_string_response(
+                raw_request,
+                "Not allowed to read events",
+            ));
+        }
+
+        if request.room_id.as_ref().is_some_and(|room_id| *room_id != self.room_id) {
+            return Some(Self::send_from_widget_error_string_response(
+                raw_request,
+                "Reading relations in another room is not supported",
+            ));
+        }
+
+        if request.event_type.is_some() && request.rel_type.is_none() {
+            return Some(Self::send_from_widget_error_string_response(
+                raw_request,
+                "Filtering relations by event type requires a relation type",
+            ));
+        }
+
+        let request = driver_req::ReadRelationsRequest {
+            event_id: request.event_id,
+            rel_type: request.rel_type,
+            event_type: request.event_type,
+            limit: request.limit,
+            from: request.from,
+            direction: request.direction,
+        };
+
+        let (request, action) = self.send_matrix_driver_request(request)?;
+        request.add_response_handler(|result, machine| {
+            let response = match &machine.capabilities {
+                CapabilitiesState::Negotiated(capabilities) => result
+                    .map(|mut response| {
+                        // Only forward the related events that the widget is allowed to read.
+                        response.chunk.retain(|e| capabilities.allow_reading(e));
+                        response
+                    })
+                    .map_err(FromWidgetErrorResponse::from_error),
+                _ => Err(FromWidgetErrorResponse::from_string(
+                    "Received read relations response while capabilities were renegotiated",
+                )),
+            };
+            vec![Self::send_from_widget_response(raw_request, response)]
+        });
+        Some(action)
+    }
+
     fn process_send_event_request(
         &mut self,
         request: SendEventRequest,
@@ -538,6 +728,43 @@ impl WidgetMachine {
         Some(action)
     }
 
+    /// Forward a request to the Matrix driver, if the widget was granted the
+    /// given capability.
+    fn process_capability_request<T>(
+        &mut self,
+        request: T,
+        capability: &str,
+        is_granted: fn(&Capabilities) -> bool,
+        raw_request: Raw<FromWidgetRequest>,
+    ) -> Option<Action>
+    where
+        T: MatrixDriverRequest,
+        T::Response: Serialize,
+    {
+        let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+            return Some(Self::send_from_widget_error_string_response(
+                raw_request,
+                "Received request before capabilities were negotiated",
+            ));
+        };
+
+        if !is_granted(capabilities) {
+            return Some(Self::send_from_widget_error_string_response(
+                raw_request,
+                format!("Not allowed: missing the {capability} capability."),
+            ));
+        }
+
+        let (request, action) = self.send_matrix_driver_request(request)?;
+        request.add_response_handler(|result, _| {
+            vec![Self::send_from_widget_response(
+                raw_request,
+                result.map_err(FromWidgetErrorResponse::from_error),
+            )]
+        });
+        Some(action)
+    }
+
     #[instrument(skip_all, fields(?request_id))]
     fn process_to_widget_response(
         &mut self,
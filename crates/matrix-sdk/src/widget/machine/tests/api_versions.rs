@@ -51,6 +51,10 @@ fn test_get_supported_api_versions() {
                     "org.matrix.msc2762_update_state",
                     "org.matrix.msc2871",
                     "org.matrix.msc3819",
+                    "town.robin.msc3846",
+                    "org.matrix.msc3869",
+                    "org.matrix.msc3973",
+                    "org.matrix.msc4039",
                 ]
             },
         }),
@@ -150,3 +150,17 @@ impl ToWidgetRequest for NotifyNewToDeviceMessage {
     const ACTION: &'static str = "send_to_device";
     type ResponseData = Empty;
 }
+
+/// Notify the widget that the TURN servers, or their credentials, changed.
+/// This is a "response" to the widget watching the TURN servers.
+#[derive(Serialize)]
+pub(crate) struct NotifyTurnServersChanged {
+    pub(super) uris: Vec<String>,
+    pub(super) username: String,
+    pub(super) password: String,
+}
+
+impl ToWidgetRequest for NotifyTurnServersChanged {
+    const ACTION: &'static str = "update_turn_servers";
+    type ResponseData = Empty;
+}
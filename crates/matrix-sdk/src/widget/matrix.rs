@@ -15,30 +15,46 @@
 //! Matrix driver implementation that exposes Matrix functionality
 //! that is relevant for the widget API.
 
-use std::collections::{BTreeMap, BTreeSet};
+#[cfg(feature = "e2e-encryption")]
+use std::io::Cursor;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
 
 use as_variant::as_variant;
 use matrix_sdk_base::{
     crypto::CollectStrategy,
     deserialized_responses::{EncryptionInfo, RawAnySyncOrStrippedState},
+    media::{MediaFormat, MediaRequestParameters},
+    sleep::sleep,
     sync::State,
 };
+use matrix_sdk_common::executor::{spawn, AbortOnDrop, JoinHandleExt};
 use ruma::{
-    api::client::{
-        account::request_openid_token::v3::{Request as OpenIdRequest, Response as OpenIdResponse},
-        delayed_events::{self, update_delayed_event::unstable::UpdateAction},
-        filter::RoomEventFilter,
-        to_device::send_event_to_device::v3::Request as RumaToDeviceRequest,
+    api::{
+        client::{
+            account::request_openid_token::v3::{
+                Request as OpenIdRequest, Response as OpenIdResponse,
+            },
+            delayed_events::{self, update_delayed_event::unstable::UpdateAction},
+            filter::RoomEventFilter,
+            to_device::send_event_to_device::v3::Request as RumaToDeviceRequest,
+            voip::get_turn_server_info,
+        },
+        Direction,
     },
     assign,
     events::{
+        relation::RelationType,
+        room::{EncryptedFile, MediaSource},
         AnyMessageLikeEventContent, AnyStateEvent, AnyStateEventContent, AnySyncStateEvent,
         AnySyncTimelineEvent, AnyTimelineEvent, AnyToDeviceEvent, AnyToDeviceEventContent,
         MessageLikeEventType, StateEventType, TimelineEventType, ToDeviceEventType,
     },
-    serde::{from_raw_json_value, Raw},
+    serde::{from_raw_json_value, Base64, Raw},
     to_device::DeviceIdOrAllDevices,
-    EventId, OwnedDeviceId, OwnedUserId, RoomId, TransactionId,
+    EventId, OwnedDeviceId, OwnedEventId, OwnedMxcUri, OwnedUserId, RoomId, TransactionId, UInt,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{value::RawValue as RawJsonValue, Value};
@@ -48,10 +64,19 @@ use tokio::sync::{
 };
 use tracing::{error, trace, warn};
 
-use super::{machine::SendEventResponse, StateKeySelector};
+use super::{
+    machine::{
+        DownloadFileResponse, GetMediaConfigResponse, ReadRelationsResponse, SendEventResponse,
+        UploadFileResponse, UserDirectorySearchResponse, UserDirectorySearchResult,
+    },
+    StateKeySelector,
+};
 use crate::{
-    event_handler::EventHandlerDropGuard, room::MessagesOptions, sync::RoomUpdate,
-    widget::machine::SendToDeviceEventResponse, Client, Error, Result, Room,
+    event_handler::EventHandlerDropGuard,
+    room::{IncludeRelations, MessagesOptions, RelationsOptions},
+    sync::RoomUpdate,
+    widget::machine::SendToDeviceEventResponse,
+    Client, Error, Result, Room,
 };
 
 /// Thin wrapper around a [`Room`] that provides functionality relevant for
@@ -213,6 +238,168 @@ impl MatrixDriver {
         self.room.client.send(r).await.map_err(|error| Error::Http(Box::new(error)))
     }
 
+    /// Gets the configuration of the media repository of the homeserver.
+    pub(crate) async fn get_media_config(&self) -> Result<GetMediaConfigResponse> {
+        let upload_size = self.room.client.load_or_fetch_max_upload_size().await?;
+        Ok(GetMediaConfigResponse { upload_size })
+    }
+
+    /// Uploads the given file to the media repository.
+    ///
+    /// If the room is encrypted, the file is encrypted before being uploaded
+    /// and the response contains its encryption info.
+    pub(crate) async fn upload_file(&self, data: Vec<u8>) -> Result<UploadFileResponse> {
+        if self.room.latest_encryption_state().await?.is_encrypted() {
+            #[cfg(feature = "e2e-encryption")]
+            {
+                let encrypted_file =
+                    self.room.client.upload_encrypted_file(&mut Cursor::new(data)).await?;
+
+                return Ok(UploadFileResponse {
+                    content_uri: encrypted_file.url.clone(),
+                    encrypted_file: Some(Box::new(encrypted_file)),
+                });
+            }
+
+            #[cfg(not(feature = "e2e-encryption"))]
+            return Err(Error::UnknownError(
+                "Uploading files in encrypted rooms requires end-to-end encryption support".into(),
+            ));
+        }
+
+        let response =
+            self.room.client.media().upload(&mime::APPLICATION_OCTET_STREAM, data, None).await?;
+
+        Ok(UploadFileResponse { content_uri: response.content_uri, encrypted_file: None })
+    }
+
+    /// Downloads the file with the given MXC URI from the media repository.
+    ///
+    /// If the encryption info of the file is provided, the file is decrypted.
+    pub(crate) async fn download_file(
+        &self,
+        content_uri: OwnedMxcUri,
+        encrypted_file: Option<Box<EncryptedFile>>,
+    ) -> Result<DownloadFileResponse> {
+        let source = match encrypted_file {
+            Some(file) => MediaSource::Encrypted(file),
+            None => MediaSource::Plain(content_uri),
+        };
+
+        let data = self
+            .room
+            .client
+            .media()
+            .get_media_content(&MediaRequestParameters { source, format: MediaFormat::File }, true)
+            .await?;
+
+        Ok(DownloadFileResponse { file: Base64::new(data) })
+    }
+
+    /// Reads the events related to the given event in the room.
+    pub(crate) async fn read_relations(
+        &self,
+        event_id: OwnedEventId,
+        rel_type: Option<RelationType>,
+        event_type: Option<TimelineEventType>,
+        limit: Option<UInt>,
+        from: Option<String>,
+        direction: Direction,
+    ) -> Result<ReadRelationsResponse> {
+        let include_relations = match (rel_type, event_type) {
+            (None, _) => IncludeRelations::AllRelations,
+            (Some(rel_type), None) => IncludeRelations::RelationsOfType(rel_type),
+            (Some(rel_type), Some(event_type)) => {
+                IncludeRelations::RelationsOfTypeAndEventType(rel_type, event_type)
+            }
+        };
+        let options = assign!(RelationsOptions::default(), {
+            from,
+            dir: direction,
+            limit,
+            include_relations,
+        });
+
+        let relations = self.room.relations(event_id, options).await?;
+        let room_id = self.room.room_id();
+
+        Ok(ReadRelationsResponse {
+            // Decrypted events don't contain the room ID, so make sure it's always there.
+            chunk: relations
+                .chunk
+                .into_iter()
+                .map(|ev| attach_room_id(&ev.into_raw(), room_id))
+                .collect(),
+            next_batch: relations.next_batch_token,
+            prev_batch: relations.prev_batch_token,
+        })
+    }
+
+    /// Searches the user directory of the homeserver.
+    pub(crate) async fn search_user_directory(
+        &self,
+        search_term: &str,
+        limit: Option<u64>,
+    ) -> Result<UserDirectorySearchResponse> {
+        const DEFAULT_LIMIT: u64 = 10;
+
+        let response =
+            self.room.client.search_users(search_term, limit.unwrap_or(DEFAULT_LIMIT)).await?;
+
+        Ok(UserDirectorySearchResponse {
+            limited: response.limited,
+            results: response
+                .results
+                .into_iter()
+                .map(|user| UserDirectorySearchResult {
+                    user_id: user.user_id,
+                    display_name: user.display_name,
+                    avatar_url: user.avatar_url,
+                })
+                .collect(),
+        })
+    }
+
+    /// Starts fetching the TURN servers of the homeserver, and fetches them
+    /// again before their credentials expire. Once the returned
+    /// `TurnServersReceiver` is dropped, fetching will be stopped.
+    pub(crate) fn turn_servers(&self) -> TurnServersReceiver {
+        /// The delay before trying again when the TURN servers couldn't be
+        /// fetched.
+        const RETRY_DELAY: Duration = Duration::from_secs(60);
+        /// The minimum delay between two fetches of the TURN servers.
+        const MIN_REFRESH_DELAY: Duration = Duration::from_secs(30);
+
+        let (tx, rx) = unbounded_channel();
+        let client = self.room.client.clone();
+
+        let task = spawn(async move {
+            loop {
+                let delay = match client.send(get_turn_server_info::v3::Request::new()).await {
+                    Ok(response) => {
+                        // Refresh the credentials a bit before they expire.
+                        let delay = (response.ttl * 9 / 10).max(MIN_REFRESH_DELAY);
+
+                        if tx.send(response).is_err() {
+                            return;
+                        }
+
+                        delay
+                    }
+                    Err(error) => {
+                        warn!("Failed to fetch the TURN servers: {error}");
+                        RETRY_DELAY
+                    }
+                };
+
+                sleep(delay).await;
+            }
+        })
+        .abort_on_drop();
+
+        TurnServersReceiver { rx, _task: task }
+    }
+
     /// Starts forwarding new room events. Once the returned `EventReceiver`
     /// is dropped, forwarding will be stopped.
     pub(crate) fn events(&self) -> EventReceiver<Raw<AnyTimelineEvent>> {
@@ -524,6 +711,19 @@ impl<T> EventReceiver<T> {
     }
 }
 
+/// A simple entity that wraps an `UnboundedReceiver` along with the task
+/// fetching the TURN servers.
+pub(crate) struct TurnServersReceiver {
+    rx: UnboundedReceiver<get_turn_server_info::v3::Response>,
+    _task: AbortOnDrop<()>,
+}
+
+impl TurnServersReceiver {
+    pub(crate) async fn recv(&mut self) -> Option<get_turn_server_info::v3::Response> {
+        self.rx.recv().await
+    }
+}
+
 /// A simple entity that wraps an `UnboundedReceiver` for the room state update
 /// handler.
 pub(crate) struct StateUpdateReceiver {
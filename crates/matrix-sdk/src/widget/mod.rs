@@ -35,6 +35,7 @@ use self::{
 };
 use crate::{room::Room, Result};
 
+mod approvals;
 mod capabilities;
 mod filter;
 mod machine;
@@ -42,6 +43,10 @@ mod matrix;
 mod settings;
 
 pub use self::{
+    approvals::{
+        ApprovalLifetime, CapabilitiesDecision, CapabilitiesPrompt, CapabilityApprovals,
+        PersistedCapabilitiesProvider, WidgetCapabilityApprovals,
+    },
     capabilities::{Capabilities, CapabilitiesProvider},
     filter::{Filter, MessageLikeEventFilter, StateEventFilter, ToDeviceEventFilter},
     settings::{
@@ -71,6 +76,12 @@ pub struct WidgetDriver {
     ///
     /// Only set if a subscription happened ([`Action::Subscribe`]).
     event_forwarding_guard: Option<DropGuard>,
+
+    /// Drop guard for a task forwarding the TURN servers of the homeserver to
+    /// the widget.
+    ///
+    /// Only set if the widget watches them ([`Action::WatchTurnServers`]).
+    turn_servers_forwarding_guard: Option<DropGuard>,
 }
 
 /// A handle that encapsulates the communication between a widget driver and the
@@ -119,7 +130,13 @@ impl WidgetDriver {
         let (from_widget_tx, from_widget_rx) = async_channel::unbounded();
         let (to_widget_tx, to_widget_rx) = async_channel::unbounded();
 
-        let driver = Self { settings, from_widget_rx, to_widget_tx, event_forwarding_guard: None };
+        let driver = Self {
+            settings,
+            from_widget_rx,
+            to_widget_tx,
+            event_forwarding_guard: None,
+            turn_servers_forwarding_guard: None,
+        };
         let channels = WidgetDriverHandle { from_widget_tx, to_widget_rx };
 
         (driver, channels)
@@ -242,6 +259,38 @@ impl WidgetDriver {
                         .await
                         .map(MatrixDriverResponse::DelayedEventUpdated),
 
+                    MatrixDriverRequestData::GetMediaConfig => matrix_driver
+                        .get_media_config()
+                        .await
+                        .map(MatrixDriverResponse::MediaConfigReceived),
+
+                    MatrixDriverRequestData::UploadFile(req) => matrix_driver
+                        .upload_file(req.file.into_inner())
+                        .await
+                        .map(MatrixDriverResponse::FileUploaded),
+
+                    MatrixDriverRequestData::DownloadFile(req) => matrix_driver
+                        .download_file(req.content_uri, req.encrypted_file)
+                        .await
+                        .map(MatrixDriverResponse::FileDownloaded),
+
+                    MatrixDriverRequestData::ReadRelations(req) => matrix_driver
+                        .read_relations(
+                            req.event_id,
+                            req.rel_type,
+                            req.event_type,
+                            req.limit,
+                            req.from,
+                            req.direction,
+                        )
+                        .await
+                        .map(MatrixDriverResponse::RelationsRead),
+
+                    MatrixDriverRequestData::SearchUserDirectory(req) => matrix_driver
+                        .search_user_directory(&req.search_term, req.limit)
+                        .await
+                        .map(MatrixDriverResponse::UserDirectorySearched),
+
                     MatrixDriverRequestData::SendToDeviceEvent(send_to_device_request) => {
                         matrix_driver
                             .send_to_device(
@@ -307,6 +356,43 @@ impl WidgetDriver {
             Action::Unsubscribe => {
                 self.event_forwarding_guard = None;
             }
+
+            Action::WatchTurnServers => {
+                // Only watch if we are not already watching.
+                if self.turn_servers_forwarding_guard.is_some() {
+                    return Ok(());
+                }
+
+                let (stop_forwarding, guard) = {
+                    let token = CancellationToken::new();
+                    (token.child_token(), token.drop_guard())
+                };
+
+                self.turn_servers_forwarding_guard = Some(guard);
+
+                let mut turn_servers = matrix_driver.turn_servers();
+                let incoming_msg_tx = incoming_msg_tx.clone();
+
+                spawn(async move {
+                    loop {
+                        tokio::select! {
+                            _ = stop_forwarding.cancelled() => {
+                                // Upon cancellation, stop this task.
+                                return;
+                            }
+
+                            Some(response) = turn_servers.recv() => {
+                                // Forward the TURN servers to the incoming messages stream.
+                                let _ = incoming_msg_tx.send(IncomingMessage::TurnServersReceived(response));
+                            }
+                        }
+                    }
+                });
+            }
+
+            Action::UnwatchTurnServers => {
+                self.turn_servers_forwarding_guard = None;
+            }
         }
 
         Ok(())
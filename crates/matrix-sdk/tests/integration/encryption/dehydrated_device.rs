@@ -0,0 +1,407 @@
+// Copyright 2025 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use assert_matches2::assert_let;
+use futures_util::StreamExt;
+use matrix_sdk::{
+    authentication::matrix::MatrixSession,
+    config::RequestConfig,
+    encryption::{
+        dehydrated_device::{DehydratedDevice, RehydrationProgress},
+        EncryptionSettings,
+    },
+    test_utils::{client::mock_session_tokens, test_client_builder},
+    Client,
+};
+use matrix_sdk_base::SessionMeta;
+use matrix_sdk_test::async_test;
+use ruma::device_id;
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{body_partial_json, header, method, path, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use super::secret_storage::{mock_secret_store_key, SECRET_STORE_KEY};
+use crate::logged_in_client_with_server;
+
+const DEHYDRATED_DEVICE_PATH: &str =
+    "_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device";
+
+/// Mock the endpoint to upload a dehydrated device.
+///
+/// The body of the uploaded dehydrated devices are pushed to the returned
+/// list. If `events_received` is set, it is checked to be `true` when a
+/// dehydrated device is uploaded.
+async fn mock_put_dehydrated_device(
+    server: &MockServer,
+    events_received: Option<Arc<AtomicBool>>,
+) -> Arc<Mutex<Vec<Value>>> {
+    let uploaded = Arc::new(Mutex::new(Vec::new()));
+
+    Mock::given(method("PUT"))
+        .and(path(DEHYDRATED_DEVICE_PATH))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with({
+            let uploaded = uploaded.clone();
+
+            move |request: &wiremock::Request| {
+                if let Some(events_received) = &events_received {
+                    assert!(
+                        events_received.load(Ordering::SeqCst),
+                        "The events of the previous dehydrated device should be received \
+                         before it is replaced"
+                    );
+                }
+
+                let body: Value = request.body_json().expect("The body should be a JSON body");
+                let device_id = body["device_id"].clone();
+                uploaded.lock().unwrap().push(body);
+
+                ResponseTemplate::new(200).set_body_json(json!({ "device_id": device_id }))
+            }
+        })
+        .named("dehydrated device PUT")
+        .mount(server)
+        .await;
+
+    uploaded
+}
+
+/// Enable the dehydrated device of the client, creating a new pickle key.
+///
+/// Returns the body of the request which uploaded the dehydrated device, and
+/// the content of the secret containing the pickle key.
+async fn enable_dehydrated_device(client: &Client, server: &MockServer) -> (Value, Value) {
+    let user_id = client.user_id().unwrap();
+
+    mock_secret_store_key(
+        server,
+        user_id,
+        "bmur2d9ypPUH1msSwCxQOJkuKRmJI55e",
+        "xv5b6/p3ExEw++wTyfSHEg==",
+        "ujBBbXahnTAMkmPUX2/0+VTfUh63pGyVRuBcDMgmJC8=",
+    )
+    .await;
+
+    let secret_path =
+        format!("_matrix/client/r0/user/{user_id}/account_data/{}", DehydratedDevice::SECRET_NAME);
+
+    let get_guard = Mock::given(method("GET"))
+        .and(path(&secret_path))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Account data not found"
+        })))
+        .expect(1..)
+        .named("dehydrated device pickle key GET")
+        .mount_as_scoped(server)
+        .await;
+
+    let pickle_key_secret = Arc::new(Mutex::new(None));
+
+    let put_guard = Mock::given(method("PUT"))
+        .and(path(&secret_path))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with({
+            let pickle_key_secret = pickle_key_secret.clone();
+
+            move |request: &wiremock::Request| {
+                let content: Value = request.body_json().expect("The body should be a JSON body");
+                *pickle_key_secret.lock().unwrap() = Some(content);
+
+                ResponseTemplate::new(200).set_body_json(json!({}))
+            }
+        })
+        .expect(1)
+        .named("dehydrated device pickle key PUT")
+        .mount_as_scoped(server)
+        .await;
+
+    let uploaded = mock_put_dehydrated_device(server, None).await;
+
+    let secret_store = client
+        .encryption()
+        .secret_storage()
+        .open_secret_store(SECRET_STORE_KEY)
+        .await
+        .expect("We should be able to open our secret store");
+
+    let dehydrated_device = client.encryption().dehydrated_device();
+    assert!(!dehydrated_device.is_enabled().await.unwrap());
+
+    let device_id = dehydrated_device
+        .enable(&secret_store)
+        .await
+        .expect("We should be able to enable the dehydrated device");
+
+    assert!(dehydrated_device.is_enabled().await.unwrap());
+
+    let mut uploaded = std::mem::take(&mut *uploaded.lock().unwrap());
+    assert_eq!(uploaded.len(), 1, "A single dehydrated device should have been uploaded");
+    let body = uploaded.pop().unwrap();
+    assert_eq!(body["device_id"], device_id.as_str());
+
+    // Check the expectations of the scoped mocks before removing all the mocks.
+    drop((get_guard, put_guard));
+    server.reset().await;
+
+    let pickle_key_secret =
+        pickle_key_secret.lock().unwrap().take().expect("The pickle key should have been stored");
+
+    (body, pickle_key_secret)
+}
+
+/// Mock the endpoints to download the dehydrated device uploaded with `body`,
+/// and its to-device events.
+///
+/// The dehydrated device receives a single page of events, containing a single
+/// event. `events_received` is set to `true` once all the events were
+/// received.
+async fn mock_dehydrated_device_with_events(
+    server: &MockServer,
+    body: &Value,
+    events_received: Arc<AtomicBool>,
+) {
+    let device_id = body["device_id"].as_str().unwrap();
+
+    Mock::given(method("GET"))
+        .and(path(DEHYDRATED_DEVICE_PATH))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_id": device_id,
+            "device_data": body["device_data"],
+        })))
+        .expect(1)
+        .named("dehydrated device GET")
+        .mount(server)
+        .await;
+
+    let events_path = format!("{DEHYDRATED_DEVICE_PATH}/{device_id}/events");
+
+    Mock::given(method("POST"))
+        .and(path(&events_path))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "events": [{
+                "type": "org.example.custom",
+                "sender": "@example:localhost",
+                "content": {},
+            }],
+            "next_batch": "page2",
+        })))
+        .up_to_n_times(1)
+        .expect(1)
+        .named("dehydrated device events POST, first page")
+        .mount(server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path(&events_path))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({ "next_batch": "page2" })))
+        .respond_with(move |_: &wiremock::Request| {
+            events_received.store(true, Ordering::SeqCst);
+            ResponseTemplate::new(200).set_body_json(json!({ "events": [] }))
+        })
+        .expect(1)
+        .named("dehydrated device events POST, last page")
+        .mount(server)
+        .await;
+}
+
+#[async_test]
+async fn test_enable_dehydrated_device() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let (body, _) = enable_dehydrated_device(&client, &server).await;
+
+    assert_eq!(body["initial_device_display_name"], DehydratedDevice::DISPLAY_NAME);
+    assert!(body["device_data"].is_object(), "The device data should have been uploaded");
+    assert!(
+        body["one_time_keys"].as_object().is_some_and(|keys| !keys.is_empty()),
+        "The dehydrated device should have been uploaded with one-time keys"
+    );
+}
+
+#[async_test]
+async fn test_rotate_dehydrated_device_receives_pending_events() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let (previous, _) = enable_dehydrated_device(&client, &server).await;
+
+    let events_received = Arc::new(AtomicBool::new(false));
+    mock_dehydrated_device_with_events(&server, &previous, events_received.clone()).await;
+    let uploaded = mock_put_dehydrated_device(&server, Some(events_received.clone())).await;
+
+    let device_id = client
+        .encryption()
+        .dehydrated_device()
+        .rotate()
+        .await
+        .expect("We should be able to rotate the dehydrated device");
+
+    assert!(events_received.load(Ordering::SeqCst));
+
+    let uploaded = std::mem::take(&mut *uploaded.lock().unwrap());
+    assert_eq!(uploaded.len(), 1, "A new dehydrated device should have been uploaded");
+    assert_eq!(uploaded[0]["device_id"], device_id.as_str());
+    assert_ne!(uploaded[0]["device_id"], previous["device_id"]);
+
+    server.verify().await;
+}
+
+#[async_test]
+async fn test_rehydrate_receives_events() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let (previous, _) = enable_dehydrated_device(&client, &server).await;
+    let previous_device_id = previous["device_id"].as_str().unwrap().to_owned();
+
+    let events_received = Arc::new(AtomicBool::new(false));
+    mock_dehydrated_device_with_events(&server, &previous, events_received.clone()).await;
+    let uploaded = mock_put_dehydrated_device(&server, Some(events_received.clone())).await;
+
+    let rehydrate = client.encryption().dehydrated_device().rehydrate();
+    let mut progress = rehydrate.subscribe_to_progress();
+
+    let room_keys = rehydrate.await.expect("We should be able to rehydrate the dehydrated device");
+
+    // The event doesn't contain any room key.
+    assert_eq!(room_keys, 0);
+    assert!(events_received.load(Ordering::SeqCst));
+
+    assert_let!(Some(Ok(RehydrationProgress::Rehydrating)) = progress.next().await);
+    assert_let!(
+        Some(Ok(RehydrationProgress::ReceivingEvents { device_id, events, room_keys })) =
+            progress.next().await
+    );
+    assert_eq!(device_id, previous_device_id);
+    assert_eq!(events, 1);
+    assert_eq!(room_keys, 0);
+    assert_let!(Some(Ok(RehydrationProgress::Rotating)) = progress.next().await);
+    assert_let!(Some(Ok(RehydrationProgress::Done { room_keys: 0 })) = progress.next().await);
+
+    // The rehydrated device was replaced, without receiving its events again.
+    assert_eq!(uploaded.lock().unwrap().len(), 1);
+
+    server.verify().await;
+}
+
+#[async_test]
+async fn test_import_secrets_rehydrates_dehydrated_device_automatically() {
+    let (client, server) = logged_in_client_with_server().await;
+    let user_id = client.user_id().unwrap().to_owned();
+
+    let (previous, pickle_key_secret) = enable_dehydrated_device(&client, &server).await;
+
+    // Log in on a new device, which manages the dehydrated device automatically.
+    let new_client = test_client_builder(Some(server.uri()))
+        .request_config(RequestConfig::new().disable_retry())
+        .with_encryption_settings(EncryptionSettings {
+            auto_manage_dehydrated_device: true,
+            ..Default::default()
+        })
+        .build()
+        .await
+        .unwrap();
+    new_client
+        .restore_session(MatrixSession {
+            meta: SessionMeta {
+                user_id: user_id.clone(),
+                device_id: device_id!("NEWDEVICE").to_owned(),
+            },
+            tokens: mock_session_tokens(),
+        })
+        .await
+        .unwrap();
+
+    mock_secret_store_key(
+        &server,
+        &user_id,
+        "bmur2d9ypPUH1msSwCxQOJkuKRmJI55e",
+        "xv5b6/p3ExEw++wTyfSHEg==",
+        "ujBBbXahnTAMkmPUX2/0+VTfUh63pGyVRuBcDMgmJC8=",
+    )
+    .await;
+
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "_matrix/client/r0/user/{user_id}/account_data/{}",
+            DehydratedDevice::SECRET_NAME
+        )))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(pickle_key_secret))
+        .expect(1)
+        .named("dehydrated device pickle key GET")
+        .mount(&server)
+        .await;
+
+    // None of the other secrets are stored.
+    Mock::given(method("GET"))
+        .and(path_regex(format!("^/_matrix/client/r0/user/{user_id}/account_data/")))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Account data not found"
+        })))
+        .named("account data GET")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("_matrix/client/r0/keys/query"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .named("/keys/query POST")
+        .mount(&server)
+        .await;
+
+    let events_received = Arc::new(AtomicBool::new(false));
+    mock_dehydrated_device_with_events(&server, &previous, events_received.clone()).await;
+    let uploaded = mock_put_dehydrated_device(&server, Some(events_received.clone())).await;
+
+    let secret_store = new_client
+        .encryption()
+        .secret_storage()
+        .open_secret_store(SECRET_STORE_KEY)
+        .await
+        .expect("We should be able to open our secret store");
+    secret_store.import_secrets().await.expect("We should be able to import our secrets");
+
+    assert!(new_client.encryption().dehydrated_device().is_enabled().await.unwrap());
+
+    // The dehydrated device is rehydrated, and rotated, in the background.
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while uploaded.lock().unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The dehydrated device should have been rotated in the background");
+
+    assert!(events_received.load(Ordering::SeqCst));
+    assert_ne!(uploaded.lock().unwrap()[0]["device_id"], previous["device_id"]);
+
+    server.verify().await;
+}
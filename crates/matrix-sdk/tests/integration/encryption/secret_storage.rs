@@ -26,9 +26,10 @@ use wiremock::{
 
 use crate::logged_in_client_with_server;
 
-const SECRET_STORE_KEY: &str = "EsTj 3yST y93F SLpB jJsz eAXc 2XzA ygD3 w69H fGaN TKBj jXEd";
+pub(super) const SECRET_STORE_KEY: &str =
+    "EsTj 3yST y93F SLpB jJsz eAXc 2XzA ygD3 w69H fGaN TKBj jXEd";
 
-async fn mock_secret_store_key(
+pub(super) async fn mock_secret_store_key(
     server: &MockServer,
     user_id: &UserId,
     key_id: &str,
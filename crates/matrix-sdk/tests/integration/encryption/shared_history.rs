@@ -1,8 +1,9 @@
+use assert_matches2::assert_matches;
 use futures_util::{FutureExt, StreamExt};
 use matrix_sdk::{
     assert_decrypted_message_eq, assert_next_matches_with_timeout,
     deserialized_responses::{TimelineEvent, UnableToDecryptInfo, UnableToDecryptReason},
-    encryption::EncryptionSettings,
+    encryption::{history_sharing::HistorySharingStatus, EncryptionSettings},
     test_utils::mocks::MatrixMockServer,
 };
 use matrix_sdk_test::{
@@ -103,6 +104,11 @@ async fn test_shared_history_out_of_order() {
     room.invite_user_by_id(bob_user_id).await.expect("We should be able to invite Bob");
     let bundle = receiver.await.expect("We should have received a bundle now.");
 
+    let jobs = room.history_sharing_jobs().await.expect("We should be able to get the jobs");
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].user_id, bob_user_id);
+    assert_matches!(jobs[0].status, HistorySharingStatus::Sent { .. });
+
     let mut bundle_stream = bob
         .encryption()
         .historic_room_key_stream()
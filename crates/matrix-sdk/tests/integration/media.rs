@@ -1,14 +1,37 @@
+#[cfg(feature = "e2e-encryption")]
+use std::io::Read as _;
+use std::{
+    io::Cursor,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+#[cfg(feature = "e2e-encryption")]
+use assert_matches2::assert_matches;
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk::{
+    crypto::{AttachmentDecryptor, AttachmentEncryptor},
+    media::MediaError,
+    Error,
+};
 use matrix_sdk::{
-    media::{MediaFormat, MediaRequestParameters, MediaThumbnailSettings},
+    media::{
+        MediaDownloadPriority, MediaFormat, MediaRequestParameters, MediaScheduler,
+        MediaSchedulerConfig, MediaThumbnailSettings, UploadStream,
+    },
     test_utils::mocks::MatrixMockServer,
 };
-use matrix_sdk_test::async_test;
+use matrix_sdk_test::{async_test, GlobalAccountDataTestEvent};
 use ruma::{
     api::client::media::get_content_thumbnail::v3::Method,
     assign,
     events::room::{message::ImageMessageEventContent, ImageInfo, MediaSource},
-    mxc_uri, owned_mxc_uri, uint,
+    mxc_uri, owned_mxc_uri, room_id, uint, MxcUri,
 };
+use serde_json::json;
+use tokio::{spawn, time::sleep};
+use wiremock::{Request, ResponseTemplate};
 
 #[async_test]
 async fn test_get_media_content_no_auth() {
@@ -321,3 +344,630 @@ async fn test_async_media_upload() {
         .await
         .unwrap();
 }
+
+#[async_test]
+async fn test_upload_stream() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    server.mock_authenticated_media_config().ok_default().mount().await;
+
+    let mxc = mxc_uri!("mxc://localhost/12345");
+    let (receiver, upload_mock) =
+        server.mock_upload().expect_mime_type("image/jpeg").ok_with_capture(mxc);
+    upload_mock.mock_once().mount().await;
+
+    // Use more data than a single chunk of the request body.
+    let data = b"hello world".repeat(10_000);
+    let stream = UploadStream::new(Cursor::new(data.clone()), data.len() as u64);
+    assert_eq!(stream.len(), data.len() as u64);
+
+    let request = client.media().upload_stream(&mime::IMAGE_JPEG, stream, None);
+    let progress = request.subscribe_to_send_progress();
+    let response = request.await.unwrap();
+
+    assert_eq!(response.content_uri, mxc);
+    assert_eq!(receiver.await.unwrap(), data);
+
+    let progress = progress.get();
+    assert_eq!(progress.current, data.len());
+    assert_eq!(progress.total, data.len());
+}
+
+#[async_test]
+async fn test_upload_stream_stops_at_length() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    server.mock_authenticated_media_config().ok_default().mount().await;
+
+    let mxc = mxc_uri!("mxc://localhost/12345");
+    let (receiver, upload_mock) = server.mock_upload().ok_with_capture(mxc);
+    upload_mock.mock_once().mount().await;
+
+    // Only the first 5 bytes are uploaded.
+    let stream = UploadStream::new(Cursor::new(b"hello world".to_vec()), 5);
+    client.media().upload_stream(&mime::TEXT_PLAIN, stream, None).await.unwrap();
+
+    assert_eq!(receiver.await.unwrap(), b"hello");
+}
+
+#[cfg(feature = "e2e-encryption")]
+#[async_test]
+async fn test_upload_encrypted_stream() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    server.mock_authenticated_media_config().ok_default().mount().await;
+
+    let mxc = mxc_uri!("mxc://localhost/12345");
+    let (receiver, upload_mock) =
+        server.mock_upload().expect_mime_type("application/octet-stream").ok_with_capture(mxc);
+    upload_mock.mock_once().mount().await;
+
+    let data = b"hello world".repeat(10_000);
+    let stream = UploadStream::new(Cursor::new(data.clone()), data.len() as u64);
+
+    let encrypted_file = client.upload_encrypted_stream(stream).await.unwrap();
+    assert_eq!(encrypted_file.url, mxc);
+
+    // The encrypted content has the same length as the plain one, and can be
+    // decrypted with the returned keys.
+    let uploaded = receiver.await.unwrap();
+    assert_eq!(uploaded.len(), data.len());
+    assert_ne!(uploaded, data);
+
+    let mut cursor = Cursor::new(uploaded);
+    let mut decryptor = AttachmentDecryptor::new(&mut cursor, encrypted_file.into()).unwrap();
+    let mut decrypted = Vec::new();
+    decryptor.read_to_end(&mut decrypted).unwrap();
+
+    assert_eq!(decrypted, data);
+}
+
+/// Encrypt the given data like an attachment, and return the encrypted data
+/// with its encryption info.
+#[cfg(feature = "e2e-encryption")]
+fn encrypt_attachment(data: &[u8]) -> (Vec<u8>, ruma::events::room::EncryptedFile) {
+    let mut cursor = Cursor::new(data);
+    let mut encryptor = AttachmentEncryptor::new(&mut cursor);
+    let mut encrypted = Vec::new();
+    encryptor.read_to_end(&mut encrypted).unwrap();
+    let keys = encryptor.finish();
+
+    let file = ruma::events::room::EncryptedFileInit {
+        url: owned_mxc_uri!("mxc://localhost/encrypted"),
+        key: keys.key,
+        iv: keys.iv,
+        hashes: keys.hashes,
+        v: keys.version,
+    }
+    .into();
+
+    (encrypted, file)
+}
+
+/// Write the content of an interrupted download of the media at `uri` to
+/// `hello.txt.part` in `dir`, with its metadata.
+fn write_partial_download(dir: &Path, uri: &MxcUri, content: &[u8], total_length: usize) {
+    std::fs::write(dir.join("hello.txt.part"), content).unwrap();
+    std::fs::write(
+        dir.join("hello.txt.part.meta"),
+        json!({ "uri": uri, "total_length": total_length }).to_string(),
+    )
+    .unwrap();
+}
+
+#[async_test]
+async fn test_download_to_file() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let data = b"hello world".repeat(10_000);
+    server.mock_authed_media_download().ok_bytes(data.clone()).mock_once().mount().await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hello.txt");
+    let source = MediaSource::Plain(owned_mxc_uri!("mxc://localhost/hello"));
+
+    let download = client.media().download_to_file(&source, &path);
+    let progress = download.subscribe_to_progress();
+    download.await.unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+    // The partial file has been moved to the destination.
+    assert!(!dir.path().join("hello.txt.part").exists());
+
+    let progress = progress.get();
+    assert_eq!(progress.current, data.len());
+    assert_eq!(progress.total, data.len());
+}
+
+#[async_test]
+async fn test_download_to_file_resumes_partial_download() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let data = b"hello world".repeat(10_000);
+    let offset = 12_345;
+
+    server
+        .mock_authed_media_download()
+        .expect_range_from(offset as u64)
+        .ok_partial_bytes(data[offset..].to_vec(), offset as u64, data.len() as u64)
+        .mock_once()
+        .mount()
+        .await;
+
+    // A previous download was interrupted.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hello.txt");
+    let uri = owned_mxc_uri!("mxc://localhost/hello");
+    write_partial_download(dir.path(), &uri, &data[..offset], data.len());
+
+    let source = MediaSource::Plain(uri);
+    client.media().download_to_file(&source, &path).await.unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+    assert!(!dir.path().join("hello.txt.part").exists());
+    assert!(!dir.path().join("hello.txt.part.meta").exists());
+}
+
+#[async_test]
+async fn test_download_to_file_discards_partial_download_of_another_media() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let data = b"hello world".repeat(10_000);
+    server.mock_authed_media_download().ok_bytes(data.clone()).mock_once().mount().await;
+
+    // The download of another media to the same path was interrupted.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hello.txt");
+    write_partial_download(dir.path(), mxc_uri!("mxc://localhost/other"), b"other", 10);
+
+    let source = MediaSource::Plain(owned_mxc_uri!("mxc://localhost/hello"));
+    client.media().download_to_file(&source, &path).await.unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+    assert!(!dir.path().join("hello.txt.part").exists());
+    assert!(!dir.path().join("hello.txt.part.meta").exists());
+}
+
+#[async_test]
+async fn test_download_to_file_restarts_when_content_length_changed() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let data = b"hello world".repeat(10_000);
+    let offset = 12_345;
+
+    // The media is now shorter than when the download started.
+    server
+        .mock_authed_media_download()
+        .expect_range_from(offset as u64)
+        .ok_partial_bytes(data[offset..].to_vec(), offset as u64, data.len() as u64)
+        .mock_once()
+        .mount()
+        .await;
+    server.mock_authed_media_download().ok_bytes(data.clone()).mock_once().mount().await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hello.txt");
+    let uri = owned_mxc_uri!("mxc://localhost/hello");
+    write_partial_download(dir.path(), &uri, &data[..offset], data.len() + 1);
+
+    let source = MediaSource::Plain(uri);
+    client.media().download_to_file(&source, &path).await.unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+}
+
+#[async_test]
+async fn test_download_to_file_restarts_on_unexpected_range() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let data = b"hello world".repeat(10_000);
+
+    // The server sends another range than the requested one.
+    server
+        .mock_authed_media_download()
+        .expect_range_from(12_345)
+        .ok_partial_bytes(data[100..].to_vec(), 100, data.len() as u64)
+        .mock_once()
+        .mount()
+        .await;
+    server.mock_authed_media_download().ok_bytes(data.clone()).mock_once().mount().await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hello.txt");
+    let uri = owned_mxc_uri!("mxc://localhost/hello");
+    write_partial_download(dir.path(), &uri, &data[..12_345], data.len());
+
+    let source = MediaSource::Plain(uri);
+    client.media().download_to_file(&source, &path).await.unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+}
+
+#[async_test]
+async fn test_download_to_file_restarts_when_range_is_ignored() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let data = b"hello world".repeat(10_000);
+
+    // The server sends the whole content, even though a range was requested.
+    server
+        .mock_authed_media_download()
+        .expect_range_from(12_345)
+        .ok_bytes(data.clone())
+        .mock_once()
+        .mount()
+        .await;
+    server.mock_authed_media_download().ok_bytes(data.clone()).mock_once().mount().await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hello.txt");
+    let uri = owned_mxc_uri!("mxc://localhost/hello");
+    write_partial_download(dir.path(), &uri, &data[..12_345], data.len());
+
+    let source = MediaSource::Plain(uri);
+    client.media().download_to_file(&source, &path).await.unwrap();
+
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+}
+
+#[async_test]
+async fn test_download_to_writer() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let data = b"hello world".repeat(10_000);
+    server.mock_authed_media_download().ok_bytes(data.clone()).mock_once().mount().await;
+
+    let source = MediaSource::Plain(owned_mxc_uri!("mxc://localhost/hello"));
+    let written = client.media().download_to_writer(&source, Vec::new()).await.unwrap();
+
+    assert_eq!(written, data);
+}
+
+#[cfg(feature = "e2e-encryption")]
+#[async_test]
+async fn test_download_encrypted_to_file_resumes_partial_download() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let data = b"hello world".repeat(10_000);
+    let (encrypted, file) = encrypt_attachment(&data);
+    let offset = 12_345;
+
+    server
+        .mock_authed_media_download()
+        .expect_range_from(offset as u64)
+        .ok_partial_bytes(encrypted[offset..].to_vec(), offset as u64, encrypted.len() as u64)
+        .mock_once()
+        .mount()
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hello.txt");
+    write_partial_download(dir.path(), &file.url, &encrypted[..offset], encrypted.len());
+
+    let source = MediaSource::Encrypted(Box::new(file));
+    client.media().download_to_file(&source, &path).await.unwrap();
+
+    // The content has been decrypted.
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+    assert!(!dir.path().join("hello.txt.part").exists());
+}
+
+#[cfg(feature = "e2e-encryption")]
+#[async_test]
+async fn test_download_encrypted_to_file_hash_mismatch() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let data = b"hello world".repeat(10_000);
+    let (mut encrypted, file) = encrypt_attachment(&data);
+    encrypted[42] ^= 0xff;
+
+    server.mock_authed_media_download().ok_bytes(encrypted).mock_once().mount().await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hello.txt");
+
+    let source = MediaSource::Encrypted(Box::new(file));
+    let error = client.media().download_to_file(&source, &path).await.unwrap_err();
+    assert_matches!(error, Error::Media(MediaError::HashMismatch));
+
+    // Nothing is kept around, so the next attempt starts over.
+    assert!(!path.exists());
+    assert!(!dir.path().join("hello.txt.part").exists());
+}
+
+#[cfg(feature = "e2e-encryption")]
+#[async_test]
+async fn test_download_encrypted_to_writer() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let data = b"hello world".repeat(10_000);
+    let (encrypted, file) = encrypt_attachment(&data);
+    let source = MediaSource::Encrypted(Box::new(file));
+
+    {
+        let _guard = server
+            .mock_authed_media_download()
+            .ok_bytes(encrypted.clone())
+            .mock_once()
+            .mount_as_scoped()
+            .await;
+
+        let written = client.media().download_to_writer(&source, Vec::new()).await.unwrap();
+        assert_eq!(written, data);
+    }
+
+    // A tampered content is rejected once everything has been read.
+    let mut tampered = encrypted;
+    tampered[42] ^= 0xff;
+    server.mock_authed_media_download().ok_bytes(tampered).mock_once().mount().await;
+
+    let error = client.media().download_to_writer(&source, Vec::new()).await.unwrap_err();
+    assert_matches!(error, Error::Media(MediaError::HashMismatch));
+}
+
+#[async_test]
+async fn test_scheduler_deduplicates_downloads() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    server
+        .mock_authed_media_download()
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(b"hello world".to_vec())
+                .set_delay(Duration::from_millis(100)),
+        )
+        .mock_once()
+        .mount()
+        .await;
+
+    let scheduler = MediaScheduler::new(client.clone(), MediaSchedulerConfig::new());
+    let request = MediaRequestParameters {
+        source: MediaSource::Plain(owned_mxc_uri!("mxc://localhost/hello")),
+        format: MediaFormat::File,
+    };
+
+    // Both requests are served by a single download.
+    let (first, second) = tokio::join!(
+        scheduler.get_media_content(&request, MediaDownloadPriority::Visible),
+        scheduler.get_media_content(&request, MediaDownloadPriority::Background),
+    );
+    assert_eq!(first.unwrap(), b"hello world");
+    assert_eq!(second.unwrap(), b"hello world");
+
+    // The content is now served from the cache.
+    let content =
+        scheduler.get_media_content(&request, MediaDownloadPriority::Visible).await.unwrap();
+    assert_eq!(content, b"hello world");
+}
+
+#[async_test]
+async fn test_scheduler_downloads_by_priority() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let downloaded = Arc::new(Mutex::new(Vec::new()));
+    server
+        .mock_authed_media_download()
+        .respond_with({
+            let downloaded = downloaded.clone();
+            move |request: &Request| {
+                let media_id = request.url.path().rsplit('/').next().unwrap().to_owned();
+                downloaded.lock().unwrap().push(media_id.clone());
+
+                ResponseTemplate::new(200)
+                    .set_body_bytes(media_id.into_bytes())
+                    .set_delay(Duration::from_millis(100))
+            }
+        })
+        .expect(3)
+        .mount()
+        .await;
+
+    let scheduler = MediaScheduler::new(
+        client.clone(),
+        MediaSchedulerConfig::new().with_max_concurrent_downloads(1),
+    );
+    let request = |media_id: &str| MediaRequestParameters {
+        source: MediaSource::Plain(format!("mxc://localhost/{media_id}").into()),
+        format: MediaFormat::File,
+    };
+
+    let first = spawn({
+        let scheduler = scheduler.clone();
+        async move {
+            scheduler.get_media_content(&request("first"), MediaDownloadPriority::Visible).await
+        }
+    });
+
+    // Wait for the first download to take the only slot.
+    sleep(Duration::from_millis(30)).await;
+
+    let background = spawn({
+        let scheduler = scheduler.clone();
+        async move {
+            scheduler
+                .get_media_content(&request("background"), MediaDownloadPriority::Background)
+                .await
+        }
+    });
+    let visible = spawn({
+        let scheduler = scheduler.clone();
+        async move {
+            scheduler.get_media_content(&request("visible"), MediaDownloadPriority::Visible).await
+        }
+    });
+
+    assert_eq!(first.await.unwrap().unwrap(), b"first");
+    assert_eq!(background.await.unwrap().unwrap(), b"background");
+    assert_eq!(visible.await.unwrap().unwrap(), b"visible");
+
+    // The visible media was downloaded before the background one, even though it
+    // was requested later.
+    assert_eq!(*downloaded.lock().unwrap(), ["first", "visible", "background"]);
+}
+
+#[async_test]
+async fn test_scheduler_cancels_dropped_downloads() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    server
+        .mock_authed_media_download()
+        .respond_with(|request: &Request| {
+            let media_id = request.url.path().rsplit('/').next().unwrap().to_owned();
+            let delay = if media_id == "slow" { Duration::from_secs(60) } else { Duration::ZERO };
+
+            ResponseTemplate::new(200).set_body_bytes(media_id.into_bytes()).set_delay(delay)
+        })
+        .mount()
+        .await;
+
+    let scheduler = MediaScheduler::new(
+        client.clone(),
+        MediaSchedulerConfig::new().with_max_concurrent_downloads(1),
+    );
+    let request = |media_id: &str| MediaRequestParameters {
+        source: MediaSource::Plain(format!("mxc://localhost/{media_id}").into()),
+        format: MediaFormat::File,
+    };
+
+    // The slow download takes the only slot, then its caller gives up on it.
+    let slow = tokio::time::timeout(
+        Duration::from_millis(100),
+        scheduler.get_media_content(&request("slow"), MediaDownloadPriority::Visible),
+    )
+    .await;
+    assert!(slow.is_err());
+
+    // The slow download was cancelled, so the next one gets the slot right away.
+    let fast = tokio::time::timeout(
+        Duration::from_secs(5),
+        scheduler.get_media_content(&request("fast"), MediaDownloadPriority::Visible),
+    )
+    .await
+    .expect("the slow download should have been cancelled");
+    assert_eq!(fast.unwrap(), b"fast");
+}
+
+#[async_test]
+async fn test_scheduler_keeps_a_slot_for_visible_downloads() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    server
+        .mock_authed_media_download()
+        .respond_with(|request: &Request| {
+            let media_id = request.url.path().rsplit('/').next().unwrap().to_owned();
+            let delay = if media_id.starts_with("background") {
+                Duration::from_secs(60)
+            } else {
+                Duration::ZERO
+            };
+
+            ResponseTemplate::new(200).set_body_bytes(media_id.into_bytes()).set_delay(delay)
+        })
+        .mount()
+        .await;
+
+    let scheduler = MediaScheduler::new(
+        client.clone(),
+        MediaSchedulerConfig::new().with_max_concurrent_downloads(2),
+    );
+    let request = |media_id: &str| MediaRequestParameters {
+        source: MediaSource::Plain(format!("mxc://localhost/{media_id}").into()),
+        format: MediaFormat::File,
+    };
+
+    // Queue more slow background downloads than there are slots.
+    let background = (0..3)
+        .map(|index| {
+            let scheduler = scheduler.clone();
+            let request = request(&format!("background{index}"));
+            spawn(async move {
+                scheduler.get_media_content(&request, MediaDownloadPriority::Background).await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    sleep(Duration::from_millis(30)).await;
+
+    // The visible media is downloaded right away, since a slot was kept for it.
+    let visible = tokio::time::timeout(
+        Duration::from_secs(5),
+        scheduler.get_media_content(&request("visible"), MediaDownloadPriority::Visible),
+    )
+    .await
+    .expect("the visible download shouldn't wait for the background ones");
+    assert_eq!(visible.unwrap(), b"visible");
+
+    for task in background {
+        task.abort();
+    }
+}
+
+#[async_test]
+async fn test_scheduler_prefetch_respects_media_previews() {
+    let server = MatrixMockServer::new().await;
+    let client = server.client_builder().build().await;
+
+    let room = server.sync_joined_room(&client, room_id!("!room:localhost")).await;
+    let request = MediaRequestParameters {
+        source: MediaSource::Plain(owned_mxc_uri!("mxc://localhost/prefetched")),
+        format: MediaFormat::File,
+    };
+
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder.add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+                "content": {
+                    "media_previews": "off",
+                },
+                "type": "m.media_preview_config",
+            })));
+        })
+        .await;
+
+    let scheduler = MediaScheduler::new(client.clone(), MediaSchedulerConfig::new());
+
+    // Media previews are disabled, nothing is downloaded.
+    assert!(!scheduler.prefetch(&request, &room).await.unwrap());
+
+    // Media previews are allowed in private rooms, the media is downloaded.
+    server
+        .mock_sync()
+        .ok_and_run(&client, |builder| {
+            builder.add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+                "content": {
+                    "media_previews": "private",
+                },
+                "type": "m.media_preview_config",
+            })));
+        })
+        .await;
+
+    server.mock_authed_media_download().ok_bytes(b"prefetched".to_vec()).mock_once().mount().await;
+
+    // Let the scheduler observe the new policy.
+    sleep(Duration::from_millis(30)).await;
+    assert!(scheduler.prefetch(&request, &room).await.unwrap());
+
+    // The prefetched media isn't downloaded a second time.
+    let content =
+        scheduler.get_media_content(&request, MediaDownloadPriority::Visible).await.unwrap();
+    assert_eq!(content, b"prefetched");
+}
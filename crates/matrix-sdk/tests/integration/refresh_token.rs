@@ -9,6 +9,7 @@ use matrix_sdk::{
     authentication::matrix::MatrixSession,
     config::RequestConfig,
     executor::spawn,
+    media::UploadStream,
     store::RoomLoadSettings,
     test_utils::{
         client::mock_session_meta,
@@ -24,12 +25,12 @@ use ruma::{
         client::{account::register, error::ErrorKind},
         MatrixVersion,
     },
-    assign, owned_device_id, owned_user_id,
+    assign, owned_device_id, owned_mxc_uri, owned_user_id,
 };
 use serde_json::json;
 use tokio::sync::{broadcast::error::TryRecvError, mpsc};
 use wiremock::{
-    matchers::{body_partial_json, header, method, path},
+    matchers::{body_partial_json, body_string, header, method, path},
     Mock, ResponseTemplate,
 };
 
@@ -561,6 +562,70 @@ async fn test_refresh_token_handled_other_error() {
     client.whoami().await.unwrap_err();
 }
 
+#[async_test]
+async fn test_refresh_token_handled_streaming_upload_from_path() {
+    let (builder, server) = test_client_builder_with_server().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .server_versions([MatrixVersion::V1_3])
+        .handle_refresh_tokens()
+        .build()
+        .await
+        .unwrap();
+    let auth = client.matrix_auth();
+
+    let session = session();
+    auth.restore_session(session, RoomLoadSettings::default()).await.unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("video.mp4");
+    std::fs::write(&file_path, b"hello world").unwrap();
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/v3/config"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "m.upload.size": 1000 })))
+        .mount(&server)
+        .await;
+
+    // The token expires during the upload.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/media/v3/upload"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 1234"))
+        .respond_with(
+            ResponseTemplate::new(401).set_body_json(&*test_json::UNKNOWN_TOKEN_SOFT_LOGOUT),
+        )
+        .expect(1)
+        .named("`POST /upload` wrong token")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/refresh"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::REFRESH_TOKEN))
+        .expect(1)
+        .named("`POST /refresh`")
+        .mount(&server)
+        .await;
+
+    // The file is read again for the retry.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/media/v3/upload"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 5678"))
+        .and(body_string("hello world"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "content_uri": "mxc://localhost/AQwafuaFswefuhsfAFAgsw" })),
+        )
+        .expect(1)
+        .named("`POST /upload` good token")
+        .mount(&server)
+        .await;
+
+    let stream = UploadStream::from_path(&file_path).await.unwrap();
+    let response = client.media().upload_stream(&mime::TEXT_PLAIN, stream, None).await.unwrap();
+    assert_eq!(response.content_uri, owned_mxc_uri!("mxc://localhost/AQwafuaFswefuhsfAFAgsw"));
+}
+
 #[async_test]
 async fn test_oauth_refresh_token_handled_success() {
     use matrix_sdk::test_utils::{
@@ -1,7 +1,9 @@
 use std::time::Duration;
 
 use matrix_sdk::{
-    attachment::{AttachmentConfig, AttachmentInfo, BaseImageInfo, BaseVideoInfo, Thumbnail},
+    attachment::{
+        AttachmentConfig, AttachmentInfo, BaseAudioInfo, BaseImageInfo, BaseVideoInfo, Thumbnail,
+    },
     media::{MediaFormat, MediaRequestParameters, MediaThumbnailSettings},
     room::reply::{EnforceThread, Reply},
     test_utils::mocks::MatrixMockServer,
@@ -661,3 +663,71 @@ async fn test_room_attachment_send_is_animated() {
 
     assert_eq!(expected_event_id, response.event_id)
 }
+
+#[async_test]
+async fn test_room_attachment_send_voice_computes_metadata() {
+    let mock = MatrixMockServer::new().await;
+
+    mock.mock_authenticated_media_config().ok_default().mount().await;
+
+    // A 16-bit mono WAV content at 8 kHz: half a second of silence, then half a
+    // second of sound.
+    let mut samples = vec![0i16; 4000];
+    samples.extend((0..4000).map(|index| if index % 2 == 0 { 8192i16 } else { -8192 }));
+    let mut data = b"RIFF".to_vec();
+    data.extend_from_slice(&(36u32 + 16_000).to_le_bytes());
+    data.extend_from_slice(b"WAVEfmt ");
+    data.extend_from_slice(&16u32.to_le_bytes());
+    data.extend_from_slice(&[1, 0, 1, 0]); // PCM, mono.
+    data.extend_from_slice(&8000u32.to_le_bytes());
+    data.extend_from_slice(&16_000u32.to_le_bytes());
+    data.extend_from_slice(&[2, 0, 16, 0]); // Block align, bits per sample.
+    data.extend_from_slice(b"data");
+    data.extend_from_slice(&16_000u32.to_le_bytes());
+    for sample in samples {
+        data.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    let mut waveform = vec![0; 50];
+    waveform.extend([1024; 50]);
+
+    let expected_event_id = event_id!("$h29iv0s8:example.com");
+    mock.mock_room_send()
+        .body_matches_partial_json(json!({
+            "info": {
+                "mimetype": "audio/wav",
+                "duration": 1000,
+            },
+            "org.matrix.msc1767.audio": {
+                "duration": 1000,
+                "waveform": waveform,
+            },
+            "org.matrix.msc3245.voice": {},
+        }))
+        .ok(expected_event_id)
+        .mock_once()
+        .mount()
+        .await;
+
+    mock.mock_upload()
+        .expect_mime_type("audio/wav")
+        .ok(mxc_uri!("mxc://example.com/AQwafuaFswefuhsfAFAgsw"))
+        .mock_once()
+        .mount()
+        .await;
+
+    let client = mock.client_builder().build().await;
+    let room = mock.sync_joined_room(&client, &DEFAULT_TEST_ROOM_ID).await;
+    mock.mock_room_state_encryption().plain().mount().await;
+
+    // The voice message is sent without a duration or a waveform.
+    let config = AttachmentConfig::new()
+        .info(AttachmentInfo::Voice { audio_info: BaseAudioInfo::default(), waveform: None });
+
+    let response = room
+        .send_attachment("voice.wav", &"audio/wav".parse().unwrap(), data, config)
+        .await
+        .unwrap();
+
+    assert_eq!(expected_event_id, response.event_id);
+}
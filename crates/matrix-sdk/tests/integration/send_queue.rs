@@ -1,4 +1,4 @@
-use std::{ops::Not as _, sync::Arc, time::Duration};
+use std::{io::Write as _, ops::Not as _, sync::Arc, time::Duration};
 
 use as_variant::as_variant;
 use assert_matches2::{assert_let, assert_matches};
@@ -6,7 +6,7 @@ use assert_matches2::{assert_let, assert_matches};
 use matrix_sdk::attachment::{GalleryConfig, GalleryItemInfo};
 use matrix_sdk::{
     assert_let_timeout,
-    attachment::{AttachmentConfig, AttachmentInfo, BaseImageInfo, Thumbnail},
+    attachment::{AttachmentConfig, AttachmentInfo, BaseAudioInfo, BaseImageInfo, Thumbnail},
     config::StoreConfig,
     media::{MediaFormat, MediaRequestParameters, MediaThumbnailSettings},
     room::reply::Reply,
@@ -2680,6 +2680,522 @@ async fn test_media_upload_retry() {
     assert!(watch.is_empty());
 }
 
+#[async_test]
+async fn test_media_upload_from_local_file() {
+    let mock = MatrixMockServer::new().await;
+
+    // Mark the room as joined.
+    let room_id = room_id!("!a:b.c");
+    let client = mock.client_builder().build().await;
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    let q = room.send_queue();
+    let (local_echoes, mut watch) = q.subscribe().await.unwrap();
+    assert!(local_echoes.is_empty());
+
+    let mut global_watch = client.send_queue().subscribe();
+
+    // Prepare endpoints.
+    mock.mock_authenticated_media_config().ok_default().mount().await;
+    mock.mock_room_state_encryption().plain().mount().await;
+    let (upload_receiver, upload_mock) = mock
+        .mock_upload()
+        .expect_mime_type("image/jpeg")
+        .ok_with_capture(mxc_uri!("mxc://sdk.rs/media"));
+    upload_mock.mock_once().mount().await;
+    mock.mock_room_send().ok(event_id!("$1")).mock_once().mount().await;
+
+    // Write the media to a local file.
+    let data = b"hello world".repeat(10_000);
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(&data).unwrap();
+
+    // Send the media.
+    let filename = "surprise.jpeg.exe";
+    q.send_attachment_file(filename, mime::IMAGE_JPEG, file.path(), AttachmentConfig::new())
+        .await
+        .expect("queuing the attachment works");
+
+    // Observe the local echo.
+    let (event_txn, _send_handle, content) =
+        assert_update!((global_watch, watch) => local echo event);
+    assert_let!(MessageType::Image(img_content) = content.msgtype);
+    assert_eq!(img_content.body, filename);
+
+    // The content of the file hasn't been copied into the media cache.
+    let local_request =
+        MediaRequestParameters { source: img_content.source, format: MediaFormat::File };
+    assert!(client
+        .event_cache_store()
+        .lock()
+        .await
+        .unwrap()
+        .get_media_content(&local_request)
+        .await
+        .unwrap()
+        .is_none());
+
+    assert_update!((global_watch, watch) => uploaded {
+        related_to = event_txn,
+        mxc = mxc_uri!("mxc://sdk.rs/media")
+    });
+
+    let edit_msg = assert_update!((global_watch, watch) => edit local echo {
+        txn = event_txn
+    });
+    assert_let!(MessageType::Image(new_content) = edit_msg.msgtype);
+    assert_let!(MediaSource::Plain(new_uri) = &new_content.source);
+    assert_eq!(new_uri, mxc_uri!("mxc://sdk.rs/media"));
+
+    assert_update!((global_watch, watch) => sent {
+        txn = event_txn,
+        event_id = event_id!("$1")
+    });
+
+    // The whole file has been uploaded.
+    assert_eq!(upload_receiver.await.unwrap(), data);
+
+    // That's all, folks!
+    assert!(watch.is_empty());
+}
+
+#[async_test]
+async fn test_voice_message_upload_from_local_file_computes_metadata() {
+    let mock = MatrixMockServer::new().await;
+
+    // Mark the room as joined.
+    let room_id = room_id!("!a:b.c");
+    let client = mock.client_builder().build().await;
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    let q = room.send_queue();
+    let (local_echoes, mut watch) = q.subscribe().await.unwrap();
+    assert!(local_echoes.is_empty());
+
+    let mut global_watch = client.send_queue().subscribe();
+
+    // Prepare endpoints.
+    mock.mock_authenticated_media_config().ok_default().mount().await;
+    mock.mock_room_state_encryption().plain().mount().await;
+    mock.mock_upload()
+        .expect_mime_type("audio/wav")
+        .ok(mxc_uri!("mxc://sdk.rs/voice"))
+        .mock_once()
+        .mount()
+        .await;
+    mock.mock_room_send().ok(event_id!("$1")).mock_once().mount().await;
+
+    // Write a 16-bit mono WAV content at 8 kHz to a local file: half a second of
+    // silence, then half a second of sound.
+    let mut data = b"RIFF".to_vec();
+    data.extend_from_slice(&(36u32 + 16_000).to_le_bytes());
+    data.extend_from_slice(b"WAVEfmt ");
+    data.extend_from_slice(&16u32.to_le_bytes());
+    data.extend_from_slice(&[1, 0, 1, 0]); // PCM, mono.
+    data.extend_from_slice(&8000u32.to_le_bytes());
+    data.extend_from_slice(&16_000u32.to_le_bytes());
+    data.extend_from_slice(&[2, 0, 16, 0]); // Block align, bits per sample.
+    data.extend_from_slice(b"data");
+    data.extend_from_slice(&16_000u32.to_le_bytes());
+    data.extend(std::iter::repeat_n(0u8, 8000));
+    for index in 0..4000 {
+        let sample: i16 = if index % 2 == 0 { 8192 } else { -8192 };
+        data.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(&data).unwrap();
+
+    // The voice message is sent without a duration or a waveform.
+    let config = AttachmentConfig::new()
+        .info(AttachmentInfo::Voice { audio_info: BaseAudioInfo::default(), waveform: None });
+    q.send_attachment_file("voice.wav", "audio/wav".parse().unwrap(), file.path(), config)
+        .await
+        .expect("queuing the attachment works");
+
+    // They have been computed from the content of the file.
+    let (event_txn, _send_handle, content) =
+        assert_update!((global_watch, watch) => local echo event);
+    assert_let!(MessageType::Audio(audio_content) = content.msgtype);
+    assert_eq!(audio_content.info.unwrap().duration, Some(Duration::from_secs(1)));
+
+    let audio_details = audio_content.audio.unwrap();
+    assert_eq!(audio_details.duration, Duration::from_secs(1));
+    let waveform: Vec<u16> = audio_details
+        .waveform
+        .iter()
+        .map(|amplitude| amplitude.get().try_into().unwrap())
+        .collect();
+    let mut expected_waveform = vec![0; 50];
+    expected_waveform.extend([1024; 50]);
+    assert_eq!(waveform, expected_waveform);
+
+    assert_update!((global_watch, watch) => uploaded {
+        related_to = event_txn,
+        mxc = mxc_uri!("mxc://sdk.rs/voice")
+    });
+    assert_update!((global_watch, watch) => edit local echo { txn = event_txn });
+    assert_update!((global_watch, watch) => sent { txn = event_txn, event_id = event_id!("$1") });
+
+    // That's all, folks!
+    assert!(watch.is_empty());
+}
+
+#[async_test]
+async fn test_media_upload_from_missing_local_file() {
+    let mock = MatrixMockServer::new().await;
+
+    // Mark the room as joined.
+    let room_id = room_id!("!a:b.c");
+    let client = mock.client_builder().build().await;
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    let q = room.send_queue();
+    let (local_echoes, watch) = q.subscribe().await.unwrap();
+    assert!(local_echoes.is_empty());
+
+    let dir = tempfile::tempdir().unwrap();
+
+    // Queuing a file that doesn't exist fails early.
+    let res = q
+        .send_attachment_file(
+            "surprise.jpeg.exe",
+            mime::IMAGE_JPEG,
+            dir.path().join("missing.jpeg"),
+            AttachmentConfig::new(),
+        )
+        .await;
+    assert_matches!(res, Err(RoomSendQueueError::AttachmentFileError(_)));
+
+    // So does queuing a directory.
+    let res = q
+        .send_attachment_file(
+            "surprise.jpeg.exe",
+            mime::IMAGE_JPEG,
+            dir.path(),
+            AttachmentConfig::new(),
+        )
+        .await;
+    assert_matches!(res, Err(RoomSendQueueError::AttachmentFileError(_)));
+
+    // Nothing has been queued.
+    assert!(watch.is_empty());
+}
+
+/// The size of the chunks of a resumable upload.
+const RESUMABLE_UPLOAD_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Create a client with resumable uploads enabled, and mock the endpoints that
+/// are used before uploading a media.
+async fn set_up_resumable_uploads(mock: &MatrixMockServer) -> Client {
+    let client = mock.client_builder().build().await;
+    client.send_queue().enable_resumable_uploads(true);
+
+    mock.mock_authenticated_media_config().ok_default().mount().await;
+    mock.mock_room_state_encryption().plain().mount().await;
+
+    client
+}
+
+#[async_test]
+async fn test_media_upload_resumable() {
+    let mock = MatrixMockServer::new().await;
+    let client = set_up_resumable_uploads(&mock).await;
+
+    // Mark the room as joined.
+    let room_id = room_id!("!a:b.c");
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    let q = room.send_queue();
+    let (local_echoes, mut watch) = q.subscribe().await.unwrap();
+    assert!(local_echoes.is_empty());
+
+    let mut global_watch = client.send_queue().subscribe();
+
+    // The media is uploaded in two chunks, to a preallocated MXC URI.
+    let total_length = RESUMABLE_UPLOAD_CHUNK_SIZE + 1024;
+    mock.mock_media_allocate().ok().mock_once().mount().await;
+    mock.mock_media_allocated_upload("example.com", "AQwafuaFswefuhsfAFAgsw")
+        .expect_content_range(0, RESUMABLE_UPLOAD_CHUNK_SIZE - 1, total_length)
+        .ok()
+        .mock_once()
+        .mount()
+        .await;
+    mock.mock_media_allocated_upload("example.com", "AQwafuaFswefuhsfAFAgsw")
+        .expect_content_range(RESUMABLE_UPLOAD_CHUNK_SIZE, total_length - 1, total_length)
+        .ok()
+        .mock_once()
+        .mount()
+        .await;
+    mock.mock_room_send().ok(event_id!("$1")).mock_once().mount().await;
+
+    // The regular upload endpoint isn't used.
+    mock.mock_upload().ok(mxc_uri!("mxc://sdk.rs/media")).never().mount().await;
+
+    // Send the media.
+    let data = vec![42; total_length as usize];
+    q.send_attachment("surprise.jpeg.exe", mime::IMAGE_JPEG, data, AttachmentConfig::new())
+        .await
+        .expect("queuing the attachment works");
+
+    let (event_txn, _send_handle, _content) =
+        assert_update!((global_watch, watch) => local echo event);
+
+    assert_update!((global_watch, watch) => uploaded {
+        related_to = event_txn,
+        mxc = mxc_uri!("mxc://example.com/AQwafuaFswefuhsfAFAgsw")
+    });
+
+    let edit_msg = assert_update!((global_watch, watch) => edit local echo {
+        txn = event_txn
+    });
+    assert_let!(MessageType::Image(new_content) = edit_msg.msgtype);
+    assert_let!(MediaSource::Plain(new_uri) = &new_content.source);
+    assert_eq!(new_uri, mxc_uri!("mxc://example.com/AQwafuaFswefuhsfAFAgsw"));
+
+    assert_update!((global_watch, watch) => sent {
+        txn = event_txn,
+        event_id = event_id!("$1")
+    });
+
+    // That's all, folks!
+    assert!(watch.is_empty());
+}
+
+#[async_test]
+async fn test_media_upload_resumable_after_error() {
+    let mock = MatrixMockServer::new().await;
+    let client = set_up_resumable_uploads(&mock).await;
+
+    // Mark the room as joined.
+    let room_id = room_id!("!a:b.c");
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    let q = room.send_queue();
+    let (local_echoes, mut watch) = q.subscribe().await.unwrap();
+    assert!(local_echoes.is_empty());
+
+    let mut global_watch = client.send_queue().subscribe();
+
+    // The MXC URI is allocated and the first chunk is uploaded only once, even
+    // though the upload of the second chunk fails at first.
+    let total_length = RESUMABLE_UPLOAD_CHUNK_SIZE + 1024;
+    mock.mock_media_allocate().ok().expect(1).mount().await;
+    mock.mock_media_allocated_upload("example.com", "AQwafuaFswefuhsfAFAgsw")
+        .expect_content_range(0, RESUMABLE_UPLOAD_CHUNK_SIZE - 1, total_length)
+        .ok()
+        .expect(1)
+        .mount()
+        .await;
+    mock.mock_media_allocated_upload("example.com", "AQwafuaFswefuhsfAFAgsw")
+        .expect_content_range(RESUMABLE_UPLOAD_CHUNK_SIZE, total_length - 1, total_length)
+        .error500()
+        .mock_once()
+        .mount()
+        .await;
+
+    // Send the media.
+    let data = vec![42; total_length as usize];
+    q.send_attachment("surprise.jpeg.exe", mime::IMAGE_JPEG, data, AttachmentConfig::new())
+        .await
+        .expect("queuing the attachment works");
+
+    let (event_txn, _send_handle, _content) =
+        assert_update!((global_watch, watch) => local echo event);
+
+    // Let the upload stumble and the queue disable itself.
+    let error = assert_update!((global_watch, watch) => error { recoverable=true, txn=event_txn });
+    let error = error.as_client_api_error().unwrap();
+    assert_eq!(error.status_code, 500);
+    assert!(q.is_enabled().not());
+
+    // Restart the send queue: only the second chunk is uploaded again.
+    mock.mock_media_allocated_upload("example.com", "AQwafuaFswefuhsfAFAgsw")
+        .expect_content_range(RESUMABLE_UPLOAD_CHUNK_SIZE, total_length - 1, total_length)
+        .ok()
+        .mock_once()
+        .mount()
+        .await;
+    mock.mock_room_send().ok(event_id!("$1")).mock_once().mount().await;
+
+    q.set_enabled(true);
+
+    assert_update!((global_watch, watch) => uploaded {
+        related_to = event_txn,
+        mxc = mxc_uri!("mxc://example.com/AQwafuaFswefuhsfAFAgsw")
+    });
+    assert_update!((global_watch, watch) => edit local echo { txn = event_txn });
+    assert_update!((global_watch, watch) => sent {
+        txn = event_txn,
+        event_id = event_id!("$1")
+    });
+
+    // That's all, folks!
+    assert!(watch.is_empty());
+}
+
+#[async_test]
+async fn test_media_upload_resumable_restarts_when_file_changed() {
+    let mock = MatrixMockServer::new().await;
+    let client = set_up_resumable_uploads(&mock).await;
+
+    // Mark the room as joined.
+    let room_id = room_id!("!a:b.c");
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    let q = room.send_queue();
+    let (local_echoes, mut watch) = q.subscribe().await.unwrap();
+    assert!(local_echoes.is_empty());
+
+    let mut global_watch = client.send_queue().subscribe();
+
+    // The first chunk is uploaded, but the upload of the second chunk fails.
+    let total_length = RESUMABLE_UPLOAD_CHUNK_SIZE + 1024;
+    mock.mock_media_allocate().ok().expect(1).mount().await;
+    mock.mock_media_allocated_upload("example.com", "AQwafuaFswefuhsfAFAgsw")
+        .expect_content_range(0, RESUMABLE_UPLOAD_CHUNK_SIZE - 1, total_length)
+        .ok()
+        .expect(1)
+        .mount()
+        .await;
+    mock.mock_media_allocated_upload("example.com", "AQwafuaFswefuhsfAFAgsw")
+        .expect_content_range(RESUMABLE_UPLOAD_CHUNK_SIZE, total_length - 1, total_length)
+        .error500()
+        .expect(1)
+        .mount()
+        .await;
+
+    // Write the media to a local file.
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(&vec![42; total_length as usize]).unwrap();
+
+    q.send_attachment_file(
+        "surprise.jpeg.exe",
+        mime::IMAGE_JPEG,
+        file.path(),
+        AttachmentConfig::new(),
+    )
+    .await
+    .expect("queuing the attachment works");
+
+    let (event_txn, _send_handle, _content) =
+        assert_update!((global_watch, watch) => local echo event);
+
+    // Let the upload stumble and the queue disable itself.
+    assert_update!((global_watch, watch) => error { recoverable=true, txn=event_txn });
+    assert!(q.is_enabled().not());
+
+    // Change the content of the file, without changing its length.
+    let data = vec![43; total_length as usize];
+    std::fs::write(file.path(), &data).unwrap();
+
+    // Restart the send queue: the upload starts over with a regular upload, since
+    // the content that was uploaded already doesn't match the file anymore.
+    let (upload_receiver, upload_mock) = mock
+        .mock_upload()
+        .expect_mime_type("image/jpeg")
+        .ok_with_capture(mxc_uri!("mxc://sdk.rs/media"));
+    upload_mock.mock_once().mount().await;
+    mock.mock_room_send().ok(event_id!("$1")).mock_once().mount().await;
+
+    q.set_enabled(true);
+
+    assert_update!((global_watch, watch) => uploaded {
+        related_to = event_txn,
+        mxc = mxc_uri!("mxc://sdk.rs/media")
+    });
+    assert_eq!(upload_receiver.await.unwrap(), data);
+
+    assert_update!((global_watch, watch) => edit local echo { txn = event_txn });
+    assert_update!((global_watch, watch) => sent {
+        txn = event_txn,
+        event_id = event_id!("$1")
+    });
+
+    // That's all, folks!
+    assert!(watch.is_empty());
+}
+
+#[async_test]
+async fn test_pause_and_resume_media_upload() {
+    let mock = MatrixMockServer::new().await;
+    let client = set_up_resumable_uploads(&mock).await;
+
+    // Mark the room as joined.
+    let room_id = room_id!("!a:b.c");
+    let room = mock.sync_joined_room(&client, room_id).await;
+
+    let q = room.send_queue();
+    let (local_echoes, mut watch) = q.subscribe().await.unwrap();
+    assert!(local_echoes.is_empty());
+
+    let mut global_watch = client.send_queue().subscribe();
+
+    mock.mock_media_allocate().ok().expect(1).mount().await;
+
+    // Have the upload of the first chunk take forever. This will be interrupted
+    // when pausing, so this will never have to complete.
+    let total_length = 1024;
+    mock.mock_media_allocated_upload("example.com", "AQwafuaFswefuhsfAFAgsw")
+        .expect_content_range(0, total_length - 1, total_length)
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(60)))
+        .mock_once()
+        .named("slow chunk upload")
+        .mount()
+        .await;
+
+    // Send the media.
+    let data = vec![42; total_length as usize];
+    let upload_handle = q
+        .send_attachment("surprise.jpeg.exe", mime::IMAGE_JPEG, data, AttachmentConfig::new())
+        .await
+        .expect("queuing the attachment works");
+
+    let (event_txn, _send_handle, _content) =
+        assert_update!((global_watch, watch) => local echo event);
+
+    // Let the upload request start.
+    sleep(Duration::from_millis(500)).await;
+
+    // Pause the upload.
+    assert!(upload_handle.pause_upload().await.unwrap(), "upload must have been paused");
+    assert!(upload_handle.is_upload_paused());
+
+    // Other requests aren't blocked by the paused upload.
+    mock.mock_room_send().ok(event_id!("$msg")).mock_once().mount().await;
+    q.send(RoomMessageEventContent::text_plain("hi").into()).await.unwrap();
+    let (msg_txn, _handle) = assert_update!((global_watch, watch) => local echo { body = "hi" });
+    assert_update!((global_watch, watch) => sent { txn = msg_txn, });
+
+    // Resume the upload, which completes this time.
+    mock.mock_media_allocated_upload("example.com", "AQwafuaFswefuhsfAFAgsw")
+        .expect_content_range(0, total_length - 1, total_length)
+        .ok()
+        .mock_once()
+        .mount()
+        .await;
+    mock.mock_room_send().ok(event_id!("$1")).mock_once().mount().await;
+
+    assert!(upload_handle.resume_upload());
+    assert!(upload_handle.is_upload_paused().not());
+
+    assert_update!((global_watch, watch) => uploaded {
+        related_to = event_txn,
+        mxc = mxc_uri!("mxc://example.com/AQwafuaFswefuhsfAFAgsw")
+    });
+    assert_update!((global_watch, watch) => edit local echo { txn = event_txn });
+    assert_update!((global_watch, watch) => sent {
+        txn = event_txn,
+        event_id = event_id!("$1")
+    });
+
+    // Once the upload has completed, it can't be paused anymore.
+    assert!(upload_handle.pause_upload().await.unwrap().not());
+
+    // That's all, folks!
+    assert!(watch.is_empty());
+}
+
 #[async_test]
 async fn test_media_upload_retry_with_520_http_status_code() {
     let mock = MatrixMockServer::new().await;
@@ -20,6 +20,7 @@ use futures_util::FutureExt;
 use matrix_sdk::{
     test_utils::mocks::{
         encryption::PendingToDeviceMessages, MatrixMockServer, RoomMessagesResponseTemplate,
+        RoomRelationsResponseTemplate,
     },
     widget::{
         Capabilities, CapabilitiesProvider, WidgetDriver, WidgetDriverHandle, WidgetSettings,
@@ -41,10 +42,10 @@ use ruma::{
         room::{member::MembershipState, message::RoomMessageEventContent},
         AnySyncStateEvent, AnyToDeviceEvent, MessageLikeEventType, StateEventType,
     },
-    owned_room_id, room_id,
+    mxc_uri, owned_event_id, owned_room_id, room_id,
     serde::{JsonObject, Raw},
     to_device::DeviceIdOrAllDevices,
-    user_id, OwnedRoomId,
+    uint, user_id, OwnedRoomId,
 };
 use serde::Serialize;
 use serde_json::{json, Value as JsonValue, Value};
@@ -1225,6 +1226,290 @@ async fn test_send_redaction() {
     assert_eq!(redact_room_id, "!a98sd12bjh:example.org");
 }
 
+#[async_test]
+async fn test_get_media_config() {
+    let (_, mock_server, driver_handle) = run_test_driver(false, false).await;
+
+    negotiate_capabilities(&driver_handle, json!(["org.matrix.msc4039.upload_file"])).await;
+
+    mock_server.mock_authenticated_media_config().ok(uint!(1024)).mock_once().mount().await;
+
+    send_request(
+        &driver_handle,
+        "get-media-config",
+        "org.matrix.msc4039.get_media_config",
+        json!({}),
+    )
+    .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "fromWidget");
+    assert_eq!(msg["action"], "org.matrix.msc4039.get_media_config");
+    assert_eq!(msg["response"], json!({ "m.upload.size": 1024 }));
+}
+
+#[async_test]
+async fn test_upload_and_download_file() {
+    let (_, mock_server, driver_handle) = run_test_driver(false, false).await;
+
+    negotiate_capabilities(
+        &driver_handle,
+        json!(["org.matrix.msc4039.upload_file", "org.matrix.msc4039.download_file"]),
+    )
+    .await;
+
+    let (uploaded, upload_mock) =
+        mock_server.mock_upload().ok_with_capture(mxc_uri!("mxc://localhost/widget-file"));
+    upload_mock.mock_once().mount().await;
+
+    // "hello world", encoded in base64.
+    send_request(
+        &driver_handle,
+        "upload-file",
+        "org.matrix.msc4039.upload_file",
+        json!({ "file": "aGVsbG8gd29ybGQ" }),
+    )
+    .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["action"], "org.matrix.msc4039.upload_file");
+    assert_eq!(msg["response"], json!({ "content_uri": "mxc://localhost/widget-file" }));
+    assert_eq!(uploaded.await.unwrap(), b"hello world");
+
+    mock_server
+        .mock_authed_media_download()
+        .ok_bytes(b"hello world".to_vec())
+        .mock_once()
+        .mount()
+        .await;
+
+    send_request(
+        &driver_handle,
+        "download-file",
+        "org.matrix.msc4039.download_file",
+        json!({ "content_uri": "mxc://localhost/widget-file" }),
+    )
+    .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["action"], "org.matrix.msc4039.download_file");
+    assert_eq!(msg["response"], json!({ "file": "aGVsbG8gd29ybGQ" }));
+}
+
+#[cfg(feature = "e2e-encryption")]
+#[async_test]
+async fn test_upload_and_download_file_in_encrypted_room() {
+    let (_, mock_server, driver_handle) = run_test_driver(false, true).await;
+
+    negotiate_capabilities(
+        &driver_handle,
+        json!(["org.matrix.msc4039.upload_file", "org.matrix.msc4039.download_file"]),
+    )
+    .await;
+
+    let (uploaded, upload_mock) =
+        mock_server.mock_upload().ok_with_capture(mxc_uri!("mxc://localhost/widget-file"));
+    upload_mock.mock_once().mount().await;
+
+    send_request(
+        &driver_handle,
+        "upload-file",
+        "org.matrix.msc4039.upload_file",
+        json!({ "file": "aGVsbG8gd29ybGQ" }),
+    )
+    .await;
+
+    // The client encrypted the file and gives its encryption info to the widget.
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["action"], "org.matrix.msc4039.upload_file");
+    let response = &msg["response"];
+    assert_eq!(response["content_uri"], "mxc://localhost/widget-file");
+    assert_eq!(response["encrypted_file"]["url"], "mxc://localhost/widget-file");
+
+    let uploaded = uploaded.await.unwrap();
+    assert_ne!(uploaded, b"hello world");
+
+    mock_server.mock_authed_media_download().ok_bytes(uploaded).mock_once().mount().await;
+
+    // The client decrypts the file with the encryption info.
+    send_request(
+        &driver_handle,
+        "download-file",
+        "org.matrix.msc4039.download_file",
+        json!({
+            "content_uri": "mxc://localhost/widget-file",
+            "encrypted_file": response["encrypted_file"],
+        }),
+    )
+    .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["action"], "org.matrix.msc4039.download_file");
+    assert_eq!(msg["response"], json!({ "file": "aGVsbG8gd29ybGQ" }));
+}
+
+#[async_test]
+async fn test_try_download_file_without_permission() {
+    let (_, _mock_server, driver_handle) = run_test_driver(false, false).await;
+
+    negotiate_capabilities(&driver_handle, json!(["org.matrix.msc4039.upload_file"])).await;
+
+    send_request(
+        &driver_handle,
+        "download-file",
+        "org.matrix.msc4039.download_file",
+        json!({ "content_uri": "mxc://localhost/widget-file" }),
+    )
+    .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["action"], "org.matrix.msc4039.download_file");
+    assert_eq!(
+        msg["response"]["error"]["message"],
+        "Not allowed: missing the org.matrix.msc4039.download_file capability."
+    );
+}
+
+#[async_test]
+async fn test_read_relations() {
+    let (_, mock_server, driver_handle) = run_test_driver(false, false).await;
+
+    negotiate_capabilities(&driver_handle, json!(["org.matrix.msc2762.receive.event:m.reaction"]))
+        .await;
+
+    let f = EventFactory::new().room(&ROOM_ID).sender(user_id!("@example:localhost"));
+    mock_server
+        .mock_room_relations()
+        .match_target_event(owned_event_id!("$target"))
+        .ok(RoomRelationsResponseTemplate::default()
+            .events(vec![
+                f.reaction(event_id!("$target"), "👍").event_id(event_id!("$reaction")).into_raw(),
+                f.text_msg("hello").event_id(event_id!("$message")).into_raw(),
+            ])
+            .next_batch("next_batch"))
+        .mock_once()
+        .mount()
+        .await;
+
+    send_request(
+        &driver_handle,
+        "read-relations",
+        "org.matrix.msc3869.read_relations",
+        json!({ "event_id": "$target" }),
+    )
+    .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "fromWidget");
+    assert_eq!(msg["action"], "org.matrix.msc3869.read_relations");
+    assert_eq!(msg["response"]["next_batch"], "next_batch");
+
+    // The widget isn't allowed to read the message, so only the reaction is
+    // returned.
+    let chunk = msg["response"]["chunk"].as_array().unwrap();
+    assert_eq!(chunk.len(), 1);
+    assert_eq!(chunk[0]["event_id"], "$reaction");
+    assert_eq!(chunk[0]["room_id"], ROOM_ID.as_str());
+}
+
+#[async_test]
+async fn test_user_directory_search() {
+    let (_, mock_server, driver_handle) = run_test_driver(false, false).await;
+
+    negotiate_capabilities(&driver_handle, json!(["org.matrix.msc3973.user_directory_search"]))
+        .await;
+
+    mock_server.mock_user_directory().ok().mock_once().mount().await;
+
+    send_request(
+        &driver_handle,
+        "user-directory-search",
+        "org.matrix.msc3973.user_directory_search",
+        json!({ "search_term": "test", "limit": 50 }),
+    )
+    .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "fromWidget");
+    assert_eq!(msg["action"], "org.matrix.msc3973.user_directory_search");
+    assert_eq!(
+        msg["response"],
+        json!({
+            "limited": false,
+            "results": [{
+                "user_id": "@test:example.me",
+                "display_name": "Test",
+                "avatar_url": "mxc://example.me/someid",
+            }],
+        })
+    );
+}
+
+#[async_test]
+async fn test_watch_turn_servers() {
+    let (_, mock_server, driver_handle) = run_test_driver(false, false).await;
+
+    negotiate_capabilities(&driver_handle, json!(["town.robin.msc3846.turn_servers"])).await;
+
+    mock_server
+        .mock_turn_servers()
+        .ok(
+            &["turn:turn.example.org:3478?transport=udp"],
+            "1443779631:@user:example.org",
+            "JlKfBy1QwLrO20385QyAtEyIv0=",
+            Duration::from_secs(86400),
+        )
+        .mock_once()
+        .mount()
+        .await;
+
+    send_request(&driver_handle, "watch-turn-servers", "watch_turn_servers", json!({})).await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "fromWidget");
+    assert_eq!(msg["action"], "watch_turn_servers");
+    assert_eq!(msg["response"], json!({}));
+
+    // The TURN servers are then sent to the widget.
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "toWidget");
+    assert_eq!(msg["action"], "update_turn_servers");
+    assert_eq!(
+        msg["data"],
+        json!({
+            "uris": ["turn:turn.example.org:3478?transport=udp"],
+            "username": "1443779631:@user:example.org",
+            "password": "JlKfBy1QwLrO20385QyAtEyIv0=",
+        })
+    );
+    let request_id = msg["requestId"].as_str().unwrap();
+    send_response(&driver_handle, request_id, "update_turn_servers", &msg["data"], json!({})).await;
+
+    send_request(&driver_handle, "unwatch-turn-servers", "unwatch_turn_servers", json!({})).await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "fromWidget");
+    assert_eq!(msg["action"], "unwatch_turn_servers");
+    assert_eq!(msg["response"], json!({}));
+}
+
+#[async_test]
+async fn test_try_watch_turn_servers_without_permission() {
+    let (_, _mock_server, driver_handle) = run_test_driver(false, false).await;
+
+    negotiate_capabilities(&driver_handle, json!(["org.matrix.msc3973.user_directory_search"]))
+        .await;
+
+    send_request(&driver_handle, "watch-turn-servers", "watch_turn_servers", json!({})).await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["action"], "watch_turn_servers");
+    assert_eq!(
+        msg["response"]["error"]["message"],
+        "Not allowed: missing the town.robin.msc3846.turn_servers capability."
+    );
+}
+
 async fn send_to_device_test_helper(
     request_id: &str,
     data: JsonValue,